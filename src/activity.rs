@@ -0,0 +1,226 @@
+//! Module for attributing changes in Google Drive to whoever made them, using the Drive Activity API. Changes to the GSync folders
+//! which GSync didn't make itself were made in the web UI, on another machine or by another app, and are worth knowing about
+//! before deciding how to resolve a conflict
+
+use crate::env::Env;
+use crate::api::activity::{self, Actor};
+use crate::{Result, Error, unwrap_db_err};
+use rusqlite::named_params;
+
+/// How many seconds an activity may be apart from an operation in the audit log on the same file, for the activity to be attributed to GSync.
+/// The Drive Activity API doesn't report which app performed an activity, so this is the best GSync can do
+const ATTRIBUTION_WINDOW: i64 = 120;
+
+/// Struct describing a change in Google Drive which GSync didn't make
+#[derive(Debug)]
+pub struct RemoteChange {
+    /// The epoch time in seconds at which the change was made
+    pub time:       i64,
+
+    /// The epoch time in seconds at which the change started, if it was spread over a period of time
+    pub started:    Option<i64>,
+
+    /// The type of the change, e.g. `edit`, `move` or `delete`
+    pub action:     String,
+
+    /// Who made the change
+    pub actor:      String,
+
+    /// The local path of the file if it is tracked, its name in Drive otherwise
+    pub path:       String,
+
+    /// The ID of the file in Google Drive
+    pub file_id:    String
+}
+
+/// Get the changes made in the GSync folders in Google Drive which GSync didn't make itself, newest first
+///
+/// ## Params
+/// - `env` Env instance
+/// - `since` Only return changes made at or after this epoch time in seconds
+///
+/// ## Errors
+/// - When a database operation fails
+/// - Request failure
+/// - Google API error, e.g. when GSync was logged in before it could read the activity in Drive. Log in again with `gsync login`
+pub fn remote_changes(env: &Env, since: i64) -> Result<Vec<RemoteChange>> {
+    let since = chrono::NaiveDateTime::from_timestamp(since, 0).format("%Y-%m-%dT%H:%M:%SZ").to_string();
+
+    let mut changes = Vec::new();
+    for root in root_folders(env)? {
+        let activities = match activity::query(env, &root, Some(&since)) {
+            Ok(activities) => activities,
+            Err((Error::GoogleError(e), line, file)) if e.code == 403 => {
+                eprintln!("Hint: GSync may have been logged in before it could read the activity in Google Drive. Run `gsync login` again to grant it access");
+                return Err((Error::GoogleError(e), line, file));
+            },
+            Err(e) => return Err(e)
+        };
+
+        for activity in activities {
+            let time = match activity.time().and_then(|time| chrono::DateTime::parse_from_rfc3339(time).ok()) {
+                Some(time) => time.timestamp(),
+                None => continue
+            };
+
+            let started = activity.start_time().and_then(|time| chrono::DateTime::parse_from_rfc3339(time).ok()).map(|time| time.timestamp());
+            for item in activity.targets.iter().filter_map(|target| target.drive_item.as_ref()) {
+                let by_gsync = is_current_user(&activity.actors) && has_operation_near(env, item.id(), time)?;
+                if by_gsync {
+                    continue;
+                }
+
+                let path = match crate::files::get_by_id(env, item.id())? {
+                    Some(file) => file.path,
+                    None => item.title.clone()
+                };
+
+                changes.push(RemoteChange {
+                    time,
+                    started,
+                    action:     activity.action().to_string(),
+                    actor:      describe_actors(&activity.actors),
+                    path,
+                    file_id:    item.id().to_string()
+                });
+            }
+        }
+    }
+
+    changes.sort_by_key(|change| std::cmp::Reverse(change.time));
+    Ok(changes)
+}
+
+/// Format a change as a tab separated line: time, action, who made it, path and file ID.
+/// A change spread over a period of time is given as an interval, e.g. `2021-07-01T10:00:00Z/2021-07-01T10:05:00Z`
+pub fn format_line(change: &RemoteChange) -> String {
+    let format = |time: i64| chrono::NaiveDateTime::from_timestamp(time, 0).format("%Y-%m-%dT%H:%M:%SZ").to_string();
+    let time = match change.started {
+        Some(started) if started != change.time => format!("{}/{}", format(started), format(change.time)),
+        _ => format(change.time)
+    };
+
+    format!("{}\t{}\t{}\t{}\t{}",
+        time,
+        change.action,
+        change.actor,
+        change.path,
+        change.file_id)
+}
+
+/// Get the IDs of the folders in Google Drive the inputs are synced into. These are the parents of tracked files which are not tracked themselves
+///
+/// ## Errors
+/// - When a database operation fails
+fn root_folders(env: &Env) -> Result<Vec<String>> {
+    let conn = unwrap_db_err!(env.get_conn());
+    let mut stmt = unwrap_db_err!(conn.prepare("SELECT DISTINCT parent_id FROM files WHERE parent_id NOT IN (SELECT id FROM files)"));
+    let mut result = unwrap_db_err!(stmt.query(named_params! {}));
+
+    let mut roots = Vec::new();
+    while let Some(row) = unwrap_db_err!(result.next()) {
+        roots.push(unwrap_db_err!(row.get::<&str, String>("parent_id")));
+    }
+
+    Ok(roots)
+}
+
+/// Check if GSync recorded an operation on a file in the audit log around the given time
+///
+/// ## Errors
+/// - When a database operation fails
+fn has_operation_near(env: &Env, file_id: &str, time: i64) -> Result<bool> {
+    let conn = unwrap_db_err!(env.get_conn());
+    let count = unwrap_db_err!(conn.query_row("SELECT COUNT(*) AS count FROM operations WHERE file_id = :file_id AND time BETWEEN :from AND :to", named_params! {
+        ":file_id": file_id,
+        ":from": time - ATTRIBUTION_WINDOW,
+        ":to": time + ATTRIBUTION_WINDOW
+    }, |row| row.get::<&str, i64>("count")));
+
+    Ok(count > 0)
+}
+
+/// Check if an activity was performed by the user GSync is logged in as, and no one else
+fn is_current_user(actors: &[Actor]) -> bool {
+    !actors.is_empty() && actors.iter().all(|actor| matches!(&actor.user, Some(user) if user.known_user.as_ref().map(|known| known.is_current_user).unwrap_or(false)))
+}
+
+/// Describe who performed an activity
+fn describe_actors(actors: &[Actor]) -> String {
+    if actors.is_empty() {
+        return "unknown".to_string();
+    }
+
+    actors.iter()
+        .map(describe_actor)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Describe a single actor of an activity
+fn describe_actor(actor: &Actor) -> String {
+    if let Some(user) = &actor.user {
+        return match (&user.known_user, &user.deleted_user, &user.unknown_user) {
+            (Some(known), _, _) if known.is_current_user => "you, outside of GSync".to_string(),
+            (Some(known), _, _) => known.person_name.clone().unwrap_or_else(|| "another user".to_string()),
+            (None, Some(_), _) => "a deleted user".to_string(),
+            (None, None, Some(_)) => "an unknown user".to_string(),
+            (None, None, None) => "unknown".to_string()
+        };
+    }
+
+    let description = if actor.anonymous.is_some() {
+        "an anonymous user"
+    } else if actor.impersonation.is_some() {
+        "an administrator impersonating a user"
+    } else if actor.administrator.is_some() {
+        "an administrator"
+    } else if actor.system.is_some() {
+        "Google Drive"
+    } else {
+        "unknown"
+    };
+
+    description.to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::activity::{describe_actors, format_line, is_current_user, RemoteChange};
+    use crate::api::activity::Actor;
+
+    /// Parse actors as returned by the Drive Activity API
+    fn actors(json: &str) -> Vec<Actor> {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn current_user() {
+        let actors = actors(r#"[{"user": {"knownUser": {"personName": "people/1", "isCurrentUser": true}}}]"#);
+        assert!(is_current_user(&actors));
+        assert_eq!("you, outside of GSync", describe_actors(&actors));
+    }
+
+    #[test]
+    fn other_actors() {
+        let actors = actors(r#"[{"user": {"knownUser": {"personName": "people/2"}}}, {"administrator": {}}, {"system": {}}]"#);
+        assert!(!is_current_user(&actors));
+        assert_eq!("people/2, an administrator, Google Drive", describe_actors(&actors));
+        assert!(!is_current_user(&[]));
+    }
+
+    #[test]
+    fn unknown_actors() {
+        let actors = actors(r#"[{"user": {"unknownUser": {}}}, {"user": {"deletedUser": {}}}]"#);
+        assert_eq!("an unknown user, a deleted user", describe_actors(&actors));
+    }
+
+    #[test]
+    fn format_period() {
+        let mut change = RemoteChange { time: 300, started: Some(0), action: "edit".to_string(), actor: "people/2".to_string(), path: "/home/me/notes.txt".to_string(), file_id: "abc".to_string() };
+        assert_eq!("1970-01-01T00:00:00Z/1970-01-01T00:05:00Z\tedit\tpeople/2\t/home/me/notes.txt\tabc", format_line(&change));
+
+        change.started = None;
+        assert_eq!("1970-01-01T00:05:00Z\tedit\tpeople/2\t/home/me/notes.txt\tabc", format_line(&change));
+    }
+}
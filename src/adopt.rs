@@ -0,0 +1,154 @@
+//! Module for taking over a folder in Google Drive which already holds the content of an input, e.g. uploaded by hand or by another tool.
+//! Remote files matching the local files by name, size and checksum are tracked as if GSync had synced them, so they aren't uploaded again
+
+use crate::api::drive::{self, File};
+use crate::config::{Configuration, InputConfiguration};
+use crate::env::Env;
+use crate::files::{self, TrackedFile};
+use crate::hash::{self, HashAlgorithm};
+use crate::{Result, Error};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Struct describing the outcome of adopting a folder
+#[derive(Debug, Default)]
+pub struct Adopted {
+    /// The number of folders which are now tracked
+    pub folders:    usize,
+
+    /// The number of files which are now tracked
+    pub files:      usize,
+
+    /// The total size of the files which are now tracked, in bytes
+    pub bytes:      u64,
+
+    /// Local files whose copy in Drive has a different size or checksum. They are compared again on the next sync
+    pub differs:    Vec<PathBuf>,
+
+    /// Local files and folders without a copy in Drive. They are uploaded on the next sync
+    pub missing:    Vec<PathBuf>
+}
+
+/// Adopt a folder in Google Drive as the copy of an input. The input is synced into the parent of the folder from then on
+///
+/// ## Params
+/// - `input` The normalized path of the input
+/// - `folder_id` The ID of the folder in Drive holding the content of the input
+///
+/// ## Errors
+/// - When the folder doesn't exist or isn't a folder
+/// - When traversing the input fails
+/// - When the Google API returns an error
+/// - When a database operation fails
+pub fn adopt(config: &Configuration, env: &Env, input: &Path, folder_id: &str) -> Result<Adopted> {
+    let folder = drive::blocking::get_file(env, folder_id)?;
+    if !folder.is_folder() {
+        return Err((Error::Other(format!("'{}' is not a folder", folder.name)), line!(), file!()));
+    }
+
+    let parent_id = match folder.parents.first() {
+        Some(parent_id) => parent_id.clone(),
+        None => return Err((Error::Other(format!("The parent of folder '{}' is not accessible", folder.name)), line!(), file!()))
+    };
+
+    let mut env = env.clone();
+    env.drive_id = folder.drive_id.clone();
+
+    let mut entries = crate::sync::local_entries(config, &env, true)?.into_iter()
+        .filter(|(entry_input, _)| entry_input.eq(input))
+        .map(|(_, entry)| entry)
+        .collect::<Vec<_>>();
+    // A folder comes before what is in it
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let algorithm = HashAlgorithm::resolve(config.hash_algorithm.as_deref());
+    let mut adopted = Adopted::default();
+    let mut folder_ids: HashMap<PathBuf, String> = HashMap::new();
+    let mut listings: HashMap<String, Vec<File>> = HashMap::new();
+
+    for entry in entries {
+        let (remote, remote_parent) = if entry.path.eq(input) {
+            (Some(folder.clone()), parent_id.clone())
+        } else {
+            let remote_parent = match entry.path.parent().and_then(|parent| folder_ids.get(parent)) {
+                Some(remote_parent) => remote_parent.clone(),
+                None => {
+                    adopted.missing.push(entry.path);
+                    continue;
+                }
+            };
+
+            if !listings.contains_key(&remote_parent) {
+                let query = format!("'{}' in parents and trashed = false", &remote_parent);
+                listings.insert(remote_parent.clone(), drive::blocking::list_files(&env, Some(&query), env.drive_id.as_deref())?);
+            }
+
+            let name = entry.path.file_name().unwrap().to_string_lossy();
+            let remote = listings[&remote_parent].iter()
+                .find(|file| file.name == name && file.is_folder() == entry.is_folder && !file.is_shortcut())
+                .cloned();
+
+            (remote, remote_parent)
+        };
+
+        let remote = match remote {
+            Some(remote) => remote,
+            None => {
+                adopted.missing.push(entry.path);
+                continue;
+            }
+        };
+
+        let path = entry.path.to_string_lossy().to_string();
+        if entry.is_folder {
+            files::insert(&env, &TrackedFile {
+                path,
+                id:         remote.id.clone(),
+                parent_id:  remote_parent,
+                is_folder:  true,
+                link_target: None,
+                size:       None,
+                md5:        None,
+                synced_at:  Some(chrono::Utc::now().timestamp())
+            })?;
+
+            folder_ids.insert(entry.path, remote.id);
+            adopted.folders += 1;
+            continue;
+        }
+
+        // Comparing the size first avoids reading files which can't match
+        if remote.size() != entry.size || remote.md5_checksum.is_none() {
+            adopted.differs.push(entry.path);
+            continue;
+        }
+
+        let (md5, checksum) = hash::checksums(&entry.path, algorithm)?;
+        if !remote.md5_checksum.as_deref().unwrap_or_default().eq_ignore_ascii_case(&md5) {
+            adopted.differs.push(entry.path);
+            continue;
+        }
+
+        files::insert(&env, &TrackedFile {
+            path:       path.clone(),
+            id:         remote.id,
+            parent_id:  remote_parent,
+            is_folder:  false,
+            link_target: None,
+            size:       Some(entry.size as i64),
+            md5:        Some(md5),
+            synced_at:  Some(chrono::Utc::now().timestamp())
+        })?;
+        hash::store(&env, &path, algorithm, &checksum)?;
+
+        adopted.files += 1;
+        adopted.bytes += entry.size;
+    }
+
+    // The input is synced into the folder the adopted folder is in, where GSync finds the folder as tracked
+    let mut input_config = InputConfiguration::get(&env, &input.to_string_lossy())?;
+    input_config.remote_root_id = Some(parent_id);
+    input_config.write(&env)?;
+
+    Ok(adopted)
+}
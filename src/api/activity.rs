@@ -0,0 +1,202 @@
+//! Google Drive Activity API
+
+use crate::env::Env;
+use crate::api::GoogleResponse;
+use crate::api::oauth::get_access_token;
+use serde::{Deserialize, Serialize};
+
+use crate::{Result, unwrap_req_err, unwrap_google_err};
+
+/// Struct describing the request to query the activity within a folder
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct QueryDriveActivityRequest<'a> {
+    /// The folder whose descendants' activity is returned, in the form `items/<ID>`
+    ancestor_name:  String,
+
+    /// Filter on the activity, e.g. on the time it happened
+    #[serde(skip_serializing_if = "Option::is_none")]
+    filter:         Option<String>,
+
+    /// The maximum amount of activities to return per page
+    page_size:      u32,
+
+    /// The token of the page to fetch, if not the first page
+    #[serde(skip_serializing_if = "Option::is_none")]
+    page_token:     Option<&'a str>
+}
+
+/// Struct describing the response to a query of the activity API
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct QueryDriveActivityResponse {
+    /// The activities returned
+    #[serde(default)]
+    activities:         Vec<DriveActivity>,
+
+    /// The token for the next page, if there are more results
+    next_page_token:    Option<String>
+}
+
+/// Struct describing a single activity in Google Drive. One activity may consist of several actions on several files
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DriveActivity {
+    /// The most important action of the activity. Contains a single key, the type of the action, e.g. `edit`, `move` or `delete`
+    pub primary_action_detail:  serde_json::Map<String, serde_json::Value>,
+
+    /// Who performed the activity
+    #[serde(default)]
+    pub actors:                 Vec<Actor>,
+
+    /// The files the activity was performed on
+    #[serde(default)]
+    pub targets:                Vec<Target>,
+
+    /// The time of the activity, if it happened at a single point in time
+    pub timestamp:              Option<String>,
+
+    /// The time range of the activity, if it was spread over a period of time
+    pub time_range:             Option<TimeRange>
+}
+
+/// Struct describing who performed an activity. Exactly one of the options is present
+#[derive(Deserialize, Debug)]
+pub struct Actor {
+    /// Present if the activity was performed by a user
+    pub user:           Option<User>,
+
+    /// Present if the activity was performed by an anonymous user
+    pub anonymous:      Option<serde_json::Value>,
+
+    /// Present if the activity was performed by a user impersonating another user
+    pub impersonation:  Option<serde_json::Value>,
+
+    /// Present if the activity was performed by Google Drive itself, e.g. when emptying the trash
+    pub system:         Option<serde_json::Value>,
+
+    /// Present if the activity was performed by a domain administrator
+    pub administrator:  Option<serde_json::Value>
+}
+
+/// Struct describing the user who performed an activity. Exactly one of the options is present
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct User {
+    /// Present if the user is known
+    pub known_user:     Option<KnownUser>,
+
+    /// Present if the user has been deleted
+    pub deleted_user:   Option<serde_json::Value>,
+
+    /// Present if the user is unknown
+    pub unknown_user:   Option<serde_json::Value>
+}
+
+/// Struct describing a known user
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct KnownUser {
+    /// The user's resource name, in the form `people/<ID>`
+    pub person_name:        Option<String>,
+
+    /// Whether the user is the user GSync is logged in as
+    #[serde(default)]
+    pub is_current_user:    bool
+}
+
+/// Struct describing the target of an activity
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Target {
+    /// Present if the target is a file or folder
+    pub drive_item: Option<DriveItem>
+}
+
+/// Struct describing a file or folder targeted by an activity
+#[derive(Deserialize, Debug)]
+pub struct DriveItem {
+    /// The resource name of the file, in the form `items/<ID>`
+    pub name:   String,
+
+    /// The name of the file as shown in Drive
+    #[serde(default)]
+    pub title:  String
+}
+
+/// Struct describing the time range of an activity
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TimeRange {
+    /// The start of the range, in RFC 3339 format
+    pub start_time: String,
+
+    /// The end of the range, in RFC 3339 format
+    pub end_time:   String
+}
+
+impl DriveActivity {
+    /// The type of the most important action of the activity, e.g. `edit`, `move` or `delete`
+    pub fn action(&self) -> &str {
+        self.primary_action_detail.keys().next().map(String::as_str).unwrap_or("unknown")
+    }
+
+    /// The time of the activity, in RFC 3339 format. For activities spread over a period of time, this is the end of the period
+    pub fn time(&self) -> Option<&str> {
+        self.timestamp.as_deref().or_else(|| self.time_range.as_ref().map(|range| range.end_time.as_str()))
+    }
+
+    /// The start of the period of time an activity is spread over, in RFC 3339 format. None for activities at a single point in time
+    pub fn start_time(&self) -> Option<&str> {
+        self.time_range.as_ref().map(|range| range.start_time.as_str())
+    }
+}
+
+impl DriveItem {
+    /// The ID of the file in Google Drive
+    pub fn id(&self) -> &str {
+        self.name.strip_prefix("items/").unwrap_or(&self.name)
+    }
+}
+
+/// Query the activity within a folder in Google Drive, including the activity on all of its descendants, newest first
+///
+/// ## Params
+/// - `env` Env instance
+/// - `ancestor_id` The ID of the folder
+/// - `since` Only return activity at or after this time, in RFC 3339 format
+///
+/// ## Errors
+/// - Request failure
+/// - Google API error, e.g. when GSync was logged in before it could read the activity in Drive
+pub fn query(env: &Env, ancestor_id: &str, since: Option<&str>) -> Result<Vec<DriveActivity>> {
+    let mut activities = Vec::new();
+    let mut page_token: Option<String> = None;
+
+    loop {
+        let body = QueryDriveActivityRequest {
+            ancestor_name:  format!("items/{}", ancestor_id),
+            filter:         since.map(|since| format!("time >= \"{}\"", since)),
+            page_size:      100,
+            page_token:     page_token.as_deref()
+        };
+
+        let access_token = get_access_token(env)?;
+        let response = unwrap_req_err!(crate::api::client().post("https://driveactivity.googleapis.com/v2/activity:query")
+            .header("Content-Type","application/json")
+            .header("Authorization", &format!("Bearer {}", &access_token))
+            .body(serde_json::to_string(&body).unwrap())
+            .send());
+
+        let request_payload: GoogleResponse<QueryDriveActivityResponse> = unwrap_req_err!(response.json());
+        let mut payload = unwrap_google_err!(request_payload);
+        activities.append(&mut payload.activities);
+
+        match payload.next_page_token {
+            Some(token) => page_token = Some(token),
+            None => break
+        }
+    }
+
+    Ok(activities)
+}
@@ -0,0 +1,156 @@
+//! Module for sending many requests to Google Drive in a single HTTP round trip, using the batch endpoint.
+//! Every request in a batch gets its own response, so one failing request doesn't fail the others
+
+use crate::env::Env;
+use crate::{Result, Error, unwrap_req_err};
+use crate::api::GoogleResponse;
+use crate::api::retry;
+use rand::Rng;
+
+/// The maximum number of requests Google accepts in a single batch
+pub const MAX_BATCH_SIZE: usize = 100;
+
+/// The batch endpoint of the Drive API
+const BATCH_URI: &str = "https://www.googleapis.com/batch/drive/v3";
+
+/// Struct describing a single request in a batch
+pub struct BatchRequest {
+    /// The HTTP method, e.g. `DELETE`
+    pub method: &'static str,
+    /// The path and query of the request, e.g. `/drive/v3/files/<ID>`
+    pub path:   String,
+    /// The JSON body of the request, if any
+    pub body:   Option<String>
+}
+
+/// Send the requests as a single batch. At most [MAX_BATCH_SIZE] requests can be sent at once.
+/// A request with a JSON body which Google answered successfully has `Ok` as result
+///
+/// ## Params
+/// - `env` Env instance
+/// - `access_token` The access token to authorize the batch with, it applies to every request in it
+/// - `requests` The requests to send
+///
+/// ## Returns
+/// The result of every request, in the same order as the requests
+///
+/// ## Errors
+/// - When the batch request itself fails
+/// - When Google's response can't be read
+pub async fn send(env: &Env, access_token: &str, requests: &[BatchRequest]) -> Result<Vec<Result<()>>> {
+    let boundary = format!("gsync_batch_{}", rand::thread_rng().gen::<u64>());
+    let response = retry::send(env, crate::api::async_client().post(BATCH_URI)
+        .header("Authorization", &format!("Bearer {}", access_token))
+        .header("Content-Type", &format!("multipart/mixed; boundary={}", boundary))
+        .body(build_body(&boundary, requests))).await?;
+
+    let content_type = response.headers().get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+
+    let response_boundary = match content_type.split(';').find_map(|param| param.trim().strip_prefix("boundary=")) {
+        Some(boundary) => boundary.trim_matches('"').to_string(),
+        None => return Err((Error::Other(format!("Google's response to a batch has no boundary: '{}'", content_type)), line!(), file!()))
+    };
+
+    let body = unwrap_req_err!(response.text().await);
+    Ok(parse_response(&response_boundary, &body, requests.len()))
+}
+
+/// Build the multipart/mixed body of a batch. Every request is identified by its index through its `Content-ID`
+fn build_body(boundary: &str, requests: &[BatchRequest]) -> String {
+    let mut body = String::new();
+    for (index, request) in requests.iter().enumerate() {
+        body.push_str(&format!("--{}\r\nContent-Type: application/http\r\nContent-ID: <item{}>\r\n\r\n", boundary, index));
+        body.push_str(&format!("{} {} HTTP/1.1\r\n", request.method, request.path));
+        match &request.body {
+            Some(content) => body.push_str(&format!("Content-Type: application/json; charset=UTF-8\r\n\r\n{}\r\n", content)),
+            None => body.push_str("\r\n")
+        }
+    }
+
+    body.push_str(&format!("--{}--\r\n", boundary));
+    body
+}
+
+/// Split Google's response to a batch into the results of the individual requests.
+/// A request without a response in the batch is considered failed
+fn parse_response(boundary: &str, body: &str, count: usize) -> Vec<Result<()>> {
+    let mut results: Vec<Option<Result<()>>> = (0..count).map(|_| None).collect();
+    for part in body.split(&format!("--{}", boundary)) {
+        match part.lines().find_map(content_id) {
+            Some(index) if index < count => results[index] = Some(parse_part(part)),
+            _ => {}
+        }
+    }
+
+    results.into_iter()
+        .map(|result| result.unwrap_or_else(|| Err((Error::Other("Google did not respond to a request in the batch".to_string()), line!(), file!()))))
+        .collect()
+}
+
+/// The index of the request a response belongs to, from its `Content-ID: <response-itemN>` header
+fn content_id(line: &str) -> Option<usize> {
+    let (name, value) = line.split_once(':')?;
+    if !name.trim().eq_ignore_ascii_case("content-id") {
+        return None;
+    }
+
+    value.trim().trim_start_matches('<').trim_end_matches('>').strip_prefix("response-item")?.parse().ok()
+}
+
+/// The result of a single response in a batch, from its status line and, if it failed, its body
+fn parse_part(part: &str) -> Result<()> {
+    let mut lines = part.lines().skip_while(|line| !line.starts_with("HTTP/"));
+    let status = match lines.next().and_then(|line| line.split_whitespace().nth(1)).and_then(|code| code.parse::<u16>().ok()) {
+        Some(status) => status,
+        None => return Err((Error::Other("Google's response to a request in the batch has no status".to_string()), line!(), file!()))
+    };
+
+    if (200..300).contains(&status) {
+        return Ok(());
+    }
+
+    let content = lines.skip_while(|line| !line.trim().is_empty()).collect::<Vec<_>>().join("\n");
+    match serde_json::from_str::<GoogleResponse<()>>(content.trim()) {
+        Ok(GoogleResponse { error: Some(error), .. }) => Err((Error::GoogleError(error), line!(), file!())),
+        _ => Err((Error::Other(format!("Google responded with {} to a request in the batch", status)), line!(), file!()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::api::batch::{BatchRequest, build_body, parse_response};
+    use crate::Error;
+
+    #[test]
+    fn build_batch_body() {
+        let requests = vec![
+            BatchRequest { method: "DELETE", path: "/drive/v3/files/a".to_string(), body: None },
+            BatchRequest { method: "PATCH", path: "/drive/v3/files/b".to_string(), body: Some("{\"trashed\":true}".to_string()) }
+        ];
+
+        let body = build_body("xyz", &requests);
+        assert_eq!("--xyz\r\nContent-Type: application/http\r\nContent-ID: <item0>\r\n\r\nDELETE /drive/v3/files/a HTTP/1.1\r\n\r\n\
+            --xyz\r\nContent-Type: application/http\r\nContent-ID: <item1>\r\n\r\nPATCH /drive/v3/files/b HTTP/1.1\r\nContent-Type: application/json; charset=UTF-8\r\n\r\n{\"trashed\":true}\r\n\
+            --xyz--\r\n", body);
+    }
+
+    #[test]
+    fn parse_batch_response() {
+        // Google answers out of order, and didn't answer the third request
+        let body = "--batch_abc\r\nContent-Type: application/http\r\nContent-ID: <response-item1>\r\n\r\n\
+            HTTP/1.1 404 Not Found\r\nContent-Type: application/json; charset=UTF-8\r\n\r\n\
+            {\"error\": {\"code\": 404, \"message\": \"File not found: b.\", \"errors\": [{\"domain\": \"global\", \"reason\": \"notFound\", \"message\": \"File not found: b.\"}]}}\r\n\
+            --batch_abc\r\nContent-Type: application/http\r\nContent-ID: <response-item0>\r\n\r\n\
+            HTTP/1.1 204 No Content\r\nContent-Length: 0\r\n\r\n\r\n\
+            --batch_abc--\r\n";
+
+        let results = parse_response("batch_abc", body, 3);
+        assert_eq!(3, results.len());
+        assert!(results[0].is_ok());
+        assert!(matches!(&results[1], Err((Error::GoogleError(e), _, _)) if e.code == 404));
+        assert!(matches!(results[2], Err((Error::Other(_), _, _))));
+    }
+}
@@ -1,20 +1,34 @@
 //! Google Drive API
 
+pub mod blocking;
+
 use serde::{Serialize, Deserialize};
 use lazy_static::lazy_static;
-use std::sync::{Arc, Mutex};
-use std::cell::Cell;
+use std::io::{Read, Write};
 use std::path::Path;
-use reqwest::blocking::multipart::{Form, Part};
+use std::collections::HashMap;
+use futures::future::LocalBoxFuture;
+use futures::stream::Stream;
+use reqwest::Body;
+use reqwest::multipart::{Form, Part};
 use crate::api::GoogleResponse;
-use crate::api::oauth::get_access_token;
+use crate::api::{oauth, retry};
+use crate::api::batch::{self, BatchRequest};
 
 use crate::{Result, unwrap_req_err, unwrap_google_err, unwrap_other_err, Error};
 use crate::env::Env;
+use crate::audit::Operation;
+use crate::sparse::Layout;
+
+/// The alias of the application data folder, a hidden folder in the user's Drive which only GSync can access
+pub const APP_DATA_FOLDER: &str = "appDataFolder";
+
+/// The size of the chunks in which the content of a file is read while uploading it
+const UPLOAD_CHUNK_SIZE: usize = 64 * 1024;
 
 lazy_static! {
-    /// Vector of IDs that can be used for creating files and folders
-    static ref IDS: Arc<Mutex<Cell<Vec<String>>>> = Arc::new(Mutex::new(Cell::new(Vec::new())));
+    /// Vector of IDs that can be used for creating files and folders. An async lock, since it is held while new IDs are requested
+    static ref IDS: tokio::sync::Mutex<Vec<String>> = tokio::sync::Mutex::new(Vec::new());
 }
 
 /// Struct describing the metadata supplied when creating a file
@@ -28,7 +42,9 @@ struct CreateFileRequestMetadata<'a> {
     /// The file's ID
     id:         &'a str,
     /// The file's parents
-    parents:    Vec<&'a str>
+    parents:    Vec<&'a str>,
+    /// Private properties of the file, only visible to GSync
+    app_properties: &'a HashMap<String, String>
 }
 
 /// Create a folder in Google Drive, and return it's ID
@@ -37,85 +53,260 @@ struct CreateFileRequestMetadata<'a> {
 /// - `env` Env instance
 /// - `folder_name` The name of the folder to create
 /// - `parent` ID of parent folder
+/// - `app_properties` Private properties of the folder, only visible to GSync
 ///
 /// ## Errors
 /// - Request failure
 /// - Google API error
-pub fn create_folder(env: &Env, folder_name: &str, parent: &str) -> Result<String> {
-    let access_token = get_access_token(env)?;
-    let id = get_id(env)?;
+pub async fn create_folder(env: &Env, folder_name: &str, parent: &str, app_properties: &HashMap<String, String>) -> Result<String> {
+    let result = create_folder_request(env, folder_name, parent, app_properties).await;
+    crate::audit::record(env, Operation::Create, Some(folder_name), result.as_ref().ok().map(String::as_str), &result);
+    result
+}
+
+/// Send the request for [create_folder], without recording it in the audit log
+async fn create_folder_request(env: &Env, folder_name: &str, parent: &str, app_properties: &HashMap<String, String>) -> Result<String> {
+    let access_token = oauth::access_token(env).await?;
+    let id = get_id(env).await?;
 
     let body = CreateFileRequestMetadata {
         name:       folder_name,
         mime_type:  "application/vnd.google-apps.folder",
         id:         &id,
-        parents:    vec![parent]
+        parents:    vec![parent],
+        app_properties
     };
 
-    let response = unwrap_req_err!(reqwest::blocking::Client::new().post("https://www.googleapis.com/drive/v3/files?supportsAllDrives=true")
+    let response = retry::send(env, crate::api::async_client().post("https://www.googleapis.com/drive/v3/files?supportsAllDrives=true")
         .header("Content-Type","application/json")
         .header("Authorization", &format!("Bearer {}", &access_token))
-        .body(serde_json::to_string(&body).unwrap())
-        .send());
+        .body(serde_json::to_string(&body).unwrap())).await?;
 
-    let payload: GoogleResponse<()> = unwrap_req_err!(response.json());
+    let payload: GoogleResponse<()> = unwrap_req_err!(response.json().await);
     unwrap_google_err!(payload);
 
     Ok(id)
 }
 
+/// Struct describing the metadata supplied when creating a shortcut
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CreateShortcutRequestMetadata<'a> {
+    /// The shortcut's name
+    name:               &'a str,
+    /// The shortcut's MIME type
+    mime_type:          &'static str,
+    /// The shortcut's ID
+    id:                 &'a str,
+    /// The shortcut's parents
+    parents:            Vec<&'a str>,
+    /// The file the shortcut points to
+    shortcut_details:   ShortcutTarget<'a>,
+    /// Private properties of the shortcut, only visible to GSync
+    app_properties:     &'a HashMap<String, String>
+}
+
+/// Struct describing the target of a shortcut when creating one
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ShortcutTarget<'a> {
+    /// The ID of the file the shortcut points to
+    target_id:  &'a str
+}
+
+/// Create a shortcut in Google Drive, and return it's ID
+///
+/// ## Params
+/// - `env` Env instance
+/// - `name` The name of the shortcut
+/// - `parent` ID of the parent folder
+/// - `target_id` ID of the file the shortcut points to
+/// - `app_properties` Private properties of the shortcut, only visible to GSync
+///
+/// ## Errors
+/// - Request failure
+/// - Google API error
+pub async fn create_shortcut(env: &Env, name: &str, parent: &str, target_id: &str, app_properties: &HashMap<String, String>) -> Result<String> {
+    let result = create_shortcut_request(env, name, parent, target_id, app_properties).await;
+    crate::audit::record(env, Operation::Create, Some(name), result.as_ref().ok().map(String::as_str), &result);
+    result
+}
+
+/// Send the request for [create_shortcut], without recording it in the audit log
+async fn create_shortcut_request(env: &Env, name: &str, parent: &str, target_id: &str, app_properties: &HashMap<String, String>) -> Result<String> {
+    let access_token = oauth::access_token(env).await?;
+    let id = get_id(env).await?;
+
+    let body = CreateShortcutRequestMetadata {
+        name,
+        mime_type:          "application/vnd.google-apps.shortcut",
+        id:                 &id,
+        parents:            vec![parent],
+        shortcut_details:   ShortcutTarget { target_id },
+        app_properties
+    };
+
+    let response = retry::send(env, crate::api::async_client().post("https://www.googleapis.com/drive/v3/files?supportsAllDrives=true")
+        .header("Content-Type","application/json")
+        .header("Authorization", &format!("Bearer {}", &access_token))
+        .body(unwrap_other_err!(serde_json::to_string(&body)))).await?;
+
+    let payload: GoogleResponse<()> = unwrap_req_err!(response.json().await);
+    unwrap_google_err!(payload);
+
+    Ok(id)
+}
+/// Upload a file to Google Drive, and return its ID and the checksum of the content Google received
 /// Upload a file to Google Drive and return it's ID
 ///
 /// ## Params
 /// - `env` Env instance
 /// - `path` Path to the file to be uploaded
 /// - `parent` ID of the parent folder
+/// - `app_properties` Private properties of the file, only visible to GSync
 ///
 /// ## Errors
 /// - Request failure
 /// - Error from Google API
 /// - Upon failing to identify MIME type
 /// - Upon failing to identify file name
-pub fn upload_file<P>(env: &Env, path: P, parent: &str) -> Result<String>
+pub async fn upload_file<P>(env: &Env, path: P, parent: &str, app_properties: &HashMap<String, String>) -> Result<UploadedFile>
+where P: AsRef<Path> {
+    let result = upload_file_request(env, path.as_ref(), parent, app_properties).await;
+    crate::audit::record(env, Operation::Create, Some(&path.as_ref().to_string_lossy()), result.as_ref().ok().map(|file| file.id.as_str()), &result);
+    result
+}
+
+/// Send the request for [upload_file], without recording it in the audit log
+async fn upload_file_request<P>(env: &Env, path: P, parent: &str, app_properties: &HashMap<String, String>) -> Result<UploadedFile>
 where P: AsRef<Path> {
-    let access_token = get_access_token(env)?;
-    let id = get_id(env)?;
+    let access_token = oauth::access_token(env).await?;
+    let id = get_id(env).await?;
     let file_name = match path.as_ref().file_name() {
-        Some(f) => f.to_str().unwrap(),
+        Some(f) => f.to_string_lossy(),
         None => return Err((Error::Other("Missing file name".to_string()), line!(), file!()))
     };
 
-    let mime = match mime_guess::from_path(&path).first() {
-        Some(g) => {
-            g.essence_str().to_string()
-        },
-        None => "application/octet-stream".to_string()
+    let mime = crate::mime::mime_type(path.as_ref(), &env.mime_types);
+    let layout = packed_layout(env, path.as_ref())?;
+    let mut app_properties = app_properties.clone();
+    if layout.is_some() {
+        app_properties.insert(crate::metadata::PACKED.to_string(), "true".to_string());
+    }
+
+    // With conversion enabled, Google Drive imports the file as the Google document type given in the metadata
+    let target_mime = match crate::mime::google_type(&mime) {
+        Some(google_type) if env.convert => google_type,
+        _ => mime.as_str()
     };
 
     let body = CreateFileRequestMetadata {
-        name:       file_name,
+        name:       &file_name,
         parents:    vec![parent],
         id:         &id,
-        mime_type:  &mime
+        mime_type:  target_mime,
+        app_properties: &app_properties
     };
 
-    let metadata_part = unwrap_req_err!(Part::text(serde_json::to_string(&body).unwrap()).mime_str("application/json"));
-    let file_part = unwrap_req_err!(unwrap_other_err!(Part::file(path)).mime_str(&mime));
+    let metadata = unwrap_other_err!(serde_json::to_string(&body));
+    let uri = format!("https://www.googleapis.com/upload/drive/v3/files?uploadType=multipart&supportsAllDrives=true&fields={}", UPLOADED_FILE_FIELDS);
+    let response = retry::send_with(env, || Ok(crate::api::async_client().post(&uri)
+        .multipart(upload_form(env, &metadata, path.as_ref(), &mime, layout.as_ref())?)
+        .header("Content-Type", "multipart/related")
+        .header("Authorization", &format!("Bearer {}", &access_token)))).await?;
+
+    let payload: GoogleResponse<UploadedFile> = unwrap_req_err!(response.json().await);
+    let uploaded = unwrap_google_err!(payload);
+
+    Ok(uploaded)
+}
+
+/// The fields of a file requested in the response to an upload
+const UPLOADED_FILE_FIELDS: &str = "id,md5Checksum,headRevisionId";
+
+/// Struct describing a file whose content was uploaded, as returned by Google
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadedFile {
+    /// The ID of the file
+    pub id:             String,
+    /// The MD5 checksum of the content Google received. Absent for files converted to Google documents
+    pub md5_checksum:   Option<String>,
+    /// The ID of the revision holding the uploaded content. Absent for files converted to Google documents
+    pub head_revision_id: Option<String>
+}
 
-    let form = Form::new()
+/// Create the multipart form of an upload, holding the metadata of a file and its content.
+/// The file is opened again for every form, so an upload can be retried, see [retry::send_with].
+/// With a layout, the content is packed with it
+///
+/// ## Errors
+/// - When the file can't be opened
+/// - When the MIME type is invalid
+fn upload_form(env: &Env, metadata: &str, path: &Path, mime: &str, layout: Option<&Layout>) -> Result<Form> {
+    let metadata_part = unwrap_req_err!(Part::text(metadata.to_string()).mime_str("application/json"));
+    Ok(Form::new()
         .part("Metadata", metadata_part)
-        .part("Media", file_part);
+        .part("Media", file_part(env, path, mime, layout)?))
+}
 
-    let response = unwrap_req_err!(reqwest::blocking::Client::new().post("https://www.googleapis.com/upload/drive/v3/files?uploadType=multipart&supportsAllDrives=true")
-        .multipart(form)
-        .header("Content-Type", "multipart/related")
-        .header("Authorization", &format!("Bearer {}", &access_token))
-        .send());
+/// Get the layout a file is uploaded packed with. Only sparse files with enough holes are packed, and only when packing is enabled
+///
+/// ## Errors
+/// - When the file can't be opened
+fn packed_layout(env: &Env, path: &Path) -> Result<Option<Layout>> {
+    if !env.pack_sparse {
+        return Ok(None);
+    }
 
-    let payload: GoogleResponse<()> = unwrap_req_err!(response.json());
-    unwrap_google_err!(payload);
+    let file = unwrap_other_err!(std::fs::File::open(path));
+    Ok(crate::sparse::layout(&file))
+}
 
-    Ok(id)
+/// Create the multipart part holding the content of a file. The upload is registered while the content is read, so its progress can be followed.
+/// The content is read no faster than the bandwidth limit allows
+///
+/// ## Errors
+/// - When the file can't be opened
+/// - When the MIME type is invalid
+fn file_part<P>(env: &Env, path: P, mime: &str, layout: Option<&Layout>) -> Result<Part>
+where P: AsRef<Path> {
+    let file = unwrap_other_err!(std::fs::File::open(&path));
+    let (content, len): (Box<dyn Read + Send + Sync>, u64) = match layout {
+        Some(layout) => (Box::new(crate::sparse::PackedReader::new(file, layout)), layout.packed_len()),
+        None => {
+            let len = unwrap_other_err!(file.metadata()).len();
+            (Box::new(file), len)
+        }
+    };
+
+    let reader = crate::progress::track(&path.as_ref().to_string_lossy(), len, crate::throttle::throttle(env.bwlimit.as_ref(), content));
+    let part = unwrap_req_err!(Part::stream_with_length(Body::wrap_stream(read_chunks(reader)), len).mime_str(mime));
+
+    match path.as_ref().file_name() {
+        Some(name) => Ok(part.file_name(name.to_string_lossy().to_string())),
+        None => Ok(part)
+    }
+}
+
+/// Read the content of a reader as a stream of chunks. The chunks are read on the blocking thread pool, since reading may wait for the bandwidth limit
+fn read_chunks<R>(reader: R) -> impl Stream<Item = std::io::Result<Vec<u8>>> + Send + Sync + 'static
+where R: Read + Send + Sync + 'static {
+    futures::stream::try_unfold(reader, |mut reader| async move {
+        let read = tokio::task::spawn_blocking(move || {
+            let mut chunk = vec![0; UPLOAD_CHUNK_SIZE];
+            let len = reader.read(&mut chunk)?;
+            chunk.truncate(len);
+            Ok::<_, std::io::Error>((chunk, reader))
+        }).await;
+
+        match read {
+            Ok(Ok((chunk, _))) if chunk.is_empty() => Ok(None),
+            Ok(Ok((chunk, reader))) => Ok(Some((chunk, reader))),
+            Ok(Err(e)) => Err(e),
+            Err(e) => Err(std::io::Error::other(e))
+        }
+    })
 }
 
 /// Struct describing the request the the file list API
@@ -140,18 +331,29 @@ struct FileListRequest<'a> {
     include_items_from_all_drives:  bool,
 
     /// The fields to get
-    fields:                         &'static str
+    fields:                         &'static str,
+
+    /// The maximum amount of files to return per page
+    page_size:                      u32,
+
+    /// The token of the page to fetch, if not the first page
+    #[serde(skip_serializing_if = "Option::is_none")]
+    page_token:                     Option<&'a str>
 }
 
 /// Struct describing the response to a call to the list API
 #[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
 struct FileListResponse {
     /// The files returned
-    files:  Vec<File>
+    files:              Vec<File>,
+
+    /// The token for the next page, if there are more results
+    next_page_token:    Option<String>
 }
 
 /// Struct describing an individual file returned by the list API
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct File {
     /// The ID of the file
@@ -160,6 +362,78 @@ pub struct File {
     pub name:           String,
     /// The time the file was last modified
     pub modified_time:  String,
+    /// The MIME type of the file
+    pub mime_type:      String,
+    /// The IDs of the file's parent folders
+    #[serde(default)]
+    pub parents:        Vec<String>,
+    /// Whether the file was trashed explicitly, as opposed to recursively from a trashed parent folder
+    #[serde(default)]
+    pub explicitly_trashed: bool,
+    /// Whether the file is in the trash, explicitly or because a parent folder was trashed. Only requested by `get_file`
+    #[serde(default)]
+    pub trashed:        bool,
+    /// If the file is a shortcut, details about the shortcut's target
+    pub shortcut_details:   Option<ShortcutDetails>,
+    /// The size of the file's content in bytes. Absent for folders and Google-native documents
+    pub size:           Option<String>,
+    /// The MD5 checksum of the file's content. Absent for folders and Google-native documents
+    pub md5_checksum:   Option<String>,
+    /// Private properties of the file, only visible to GSync
+    #[serde(default)]
+    pub app_properties: HashMap<String, String>,
+    /// Whether the user owns the file. Always false for files in shared drives
+    #[serde(default)]
+    pub owned_by_me:    bool,
+    /// What the user may do with the file. Only requested by `get_file`
+    pub capabilities:   Option<Capabilities>,
+    /// The ID of the shared drive the file is in. Only requested by `get_file`, absent for files outside of shared drives
+    pub drive_id:       Option<String>,
+    /// The ID of the current revision of the file's content. Only requested by `get_file`, absent for folders and Google-native documents
+    pub head_revision_id: Option<String>,
+}
+
+/// Struct describing what the user may do with a file
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Capabilities {
+    /// Whether the user can add files to the folder. Always false for files which are not a folder
+    #[serde(default)]
+    pub can_add_children:   bool
+}
+
+/// Struct describing the target of a shortcut
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ShortcutDetails {
+    /// The ID of the file the shortcut points to
+    pub target_id:          String,
+    /// The MIME type of the file the shortcut points to
+    pub target_mime_type:   Option<String>
+}
+
+impl ShortcutDetails {
+    /// Whether the shortcut points to another shortcut. False if the type of the file it points to is unknown
+    pub fn points_to_shortcut(&self) -> bool {
+        self.target_mime_type.as_deref().eq(&Some("application/vnd.google-apps.shortcut"))
+    }
+}
+
+impl File {
+    /// Check if this file is a folder
+    pub fn is_folder(&self) -> bool {
+        self.mime_type.eq("application/vnd.google-apps.folder")
+    }
+
+    /// Check if this file is a shortcut to another file
+    pub fn is_shortcut(&self) -> bool {
+        self.mime_type.eq("application/vnd.google-apps.shortcut")
+    }
+
+    /// The size of the file's content in bytes, 0 if unknown
+    pub fn size(&self) -> u64 {
+        self.size.as_ref().and_then(|size| size.parse().ok()).unwrap_or(0)
+    }
 }
 
 /// List the files in Google Drive
@@ -172,194 +446,1229 @@ pub struct File {
 /// ## Error
 /// - Request failure
 /// - Error from Google API
-pub fn list_files(env: &Env, q: Option<&str>, drive_id: Option<&str>) -> Result<Vec<File>> {
-    let query_params = FileListRequest {
-        q,
-        drive_id,
-        corpora:                        if drive_id.is_some() { "drive" } else { "user" },
-        supports_all_drives:            true,
-        include_items_from_all_drives:  true,
-        fields:                         "kind,incompleteSearch,files/kind,files/modifiedTime,files/id,files/name"
-    };
+pub async fn list_files(env: &Env, q: Option<&str>, drive_id: Option<&str>) -> Result<Vec<File>> {
+    let mut files = Vec::new();
+    let mut page_token: Option<String> = None;
 
-    let access_token = get_access_token(env)?;
-    let req = unwrap_req_err!(reqwest::blocking::Client::new().get(format!("https://www.googleapis.com/drive/v3/files?{}", serde_qs::to_string(&query_params).unwrap()))
-        .header("Authorization", &format!("Bearer {}", &access_token))
-        .send());
+    loop {
+        let query_params = FileListRequest {
+            q,
+            drive_id,
+            corpora:                        if drive_id.is_some() { "drive" } else { "user" },
+            supports_all_drives:            true,
+            include_items_from_all_drives:  true,
+            fields:                         "kind,incompleteSearch,nextPageToken,files/kind,files/modifiedTime,files/id,files/name,files/mimeType,files/parents,files/explicitlyTrashed,files/shortcutDetails,files/size,files/md5Checksum,files/appProperties,files/ownedByMe",
+            page_size:                      1000,
+            page_token:                     page_token.as_deref()
+        };
 
-    let request_payload: GoogleResponse<FileListResponse> = unwrap_req_err!(req.json());
-    let payload = unwrap_google_err!(request_payload);
+        let access_token = oauth::access_token(env).await?;
+        let req = retry::send(env, crate::api::async_client().get(format!("https://www.googleapis.com/drive/v3/files?{}", serde_qs::to_string(&query_params).unwrap()))
+            .header("Authorization", &format!("Bearer {}", &access_token))).await?;
 
-    Ok(payload.files)
-}
+        let request_payload: GoogleResponse<FileListResponse> = unwrap_req_err!(req.json().await);
+        let mut payload = unwrap_google_err!(request_payload);
+        files.append(&mut payload.files);
 
-/// Struct describing the response to the shared drives API
-#[derive(Deserialize, Debug)]
-struct SharedDriveResponse {
-    /// The returned drives
-    drives: Vec<SharedDrive>,
+        match payload.next_page_token {
+            Some(token) => page_token = Some(token),
+            None => break
+        }
+    }
+
+    Ok(files)
 }
 
-/// Struct describing the individual drives returned by the shared shared drives API
-#[derive(Deserialize, Debug)]
-pub struct SharedDrive {
-    /// The drive's ID
-    pub id:     String,
-    /// The drive's name
-    pub name:   String
+/// Struct describing the request to the file list API for the application data folder
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AppDataListRequest<'a> {
+    /// The space to list, always the application data folder
+    spaces:     &'static str,
+
+    /// Search query parameter
+    q:          &'a str,
+
+    /// The fields to get
+    fields:     &'static str
 }
 
-/// Get all shared drives the user has access too
+/// List the files with the provided name in the application data folder, a hidden folder only GSync can access. Newest first
 ///
-/// # Error
-/// - Google API error
-/// - Reqwest error
-pub fn get_shared_drives(env: &Env) -> Result<Vec<SharedDrive>> {
-    let access_token = get_access_token(env)?;
+/// ## Params
+/// - `env` Env instance
+/// - `name` The name of the files
+///
+/// ## Errors
+/// - Request failure
+/// - Error from Google API
+pub async fn list_app_data(env: &Env, name: &str) -> Result<Vec<File>> {
+    let query = format!("name = '{}' and trashed = false", escape_query_value(name));
+    let query_params = AppDataListRequest {
+        spaces: APP_DATA_FOLDER,
+        q:      &query,
+        fields: "files/modifiedTime,files/id,files/name,files/mimeType,files/size,files/md5Checksum,files/appProperties"
+    };
 
-    let request = unwrap_req_err!(reqwest::blocking::Client::new().get("https://www.googleapis.com/drive/v3/drives?pageSize=100")
-        .header("Authorization", &format!("Bearer {}", &access_token))
-        .send());
+    let access_token = oauth::access_token(env).await?;
+    let req = retry::send(env, crate::api::async_client().get(format!("https://www.googleapis.com/drive/v3/files?orderBy=modifiedTime%20desc&{}", unwrap_other_err!(serde_qs::to_string(&query_params))))
+        .header("Authorization", &format!("Bearer {}", &access_token))).await?;
 
-    let response: GoogleResponse<SharedDriveResponse> = unwrap_req_err!(request.json());
-    let payload = unwrap_google_err!(response);
+    let payload: GoogleResponse<FileListResponse> = unwrap_req_err!(req.json().await);
+    let list = unwrap_google_err!(payload);
+    Ok(list.files)
+}
 
-    Ok(payload.drives)
+/// Struct describing the metadata supplied when creating a file in the application data folder.
+/// IDs from the generateIds API can't be used there, so Google assigns the ID
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CreateAppDataRequestMetadata<'a> {
+    /// The file's name
+    name:       &'a str,
+    /// The file's parents, always the application data folder
+    parents:    Vec<&'a str>,
+    /// Private properties of the file, only visible to GSync
+    app_properties: &'a HashMap<String, String>
 }
 
-/// Struct describing the response to a call to the generateIds API
+/// Struct describing the response to creating a file
 #[derive(Deserialize)]
-struct GetIdsResponse {
-    /// The returned IDs
-    ids:    Vec<String>
+struct CreatedFile {
+    /// The ID of the created file
+    id:     String
 }
 
-/// Get a File ID from the IDS Vec. If this Vec contains no more IDs, a new set will be requested from Google.
+/// Upload a file to the application data folder, a hidden folder only GSync can access, and return its ID
 ///
 /// ## Params
 /// - `env` Env instance
+/// - `path` The path to the file to upload. Its file name is used as name in Drive
+/// - `app_properties` Private properties of the file, only visible to GSync
 ///
 /// ## Errors
 /// - Request failure
-/// - Error from Google API
-fn get_id(env: &Env) -> Result<String> {
-    let mut lock = unwrap_other_err!(IDS.lock());
-    let vec = lock.get_mut();
+/// - Google API error
+/// - Failure to construct multipart parts
+pub async fn upload_app_data<P>(env: &Env, path: P, app_properties: &HashMap<String, String>) -> Result<String>
+where P: AsRef<Path> {
+    let result = upload_app_data_request(env, path.as_ref(), app_properties).await;
+    crate::audit::record(env, Operation::Create, Some(&path.as_ref().to_string_lossy()), result.as_ref().ok().map(String::as_str), &result);
+    result
+}
 
-    let access_token = get_access_token(env)?;
+/// Send the request for [upload_app_data], without recording it in the audit log
+async fn upload_app_data_request(env: &Env, path: &Path, app_properties: &HashMap<String, String>) -> Result<String> {
+    let access_token = oauth::access_token(env).await?;
+    let file_name = match path.file_name() {
+        Some(f) => f.to_string_lossy(),
+        None => return Err((Error::Other("Missing file name".to_string()), line!(), file!()))
+    };
 
-    if vec.is_empty() {
-        let mut new_ids = get_ids_from_google(&access_token)?;
-        let id = new_ids.pop().unwrap();
-        lock.set(new_ids);
+    let body = CreateAppDataRequestMetadata {
+        name:       &file_name,
+        parents:    vec![APP_DATA_FOLDER],
+        app_properties
+    };
 
-        return Ok(id);
-    }
+    let metadata = unwrap_other_err!(serde_json::to_string(&body));
+    let response = retry::send_with(env, || Ok(crate::api::async_client().post("https://www.googleapis.com/upload/drive/v3/files?uploadType=multipart")
+        .multipart(upload_form(env, &metadata, path, "application/octet-stream", packed_layout(env, path)?.as_ref())?)
+        .header("Content-Type", "multipart/related")
+        .header("Authorization", &format!("Bearer {}", &access_token)))).await?;
 
-    Ok(vec.pop().unwrap())
+    let payload: GoogleResponse<CreatedFile> = unwrap_req_err!(response.json().await);
+    let created = unwrap_google_err!(payload);
+    Ok(created.id)
 }
 
-/// Request 100 new File IDs from Google. Do not call this function directly, instead use `get_id()`
+/// Get a single file from Google Drive by its ID
+///
+/// ## Params
+/// - `env` Env instance
+/// - `id` The ID of the file
 ///
 /// ## Errors
 /// - Request failure
 /// - Error from Google API
-fn get_ids_from_google(access_token: &str) -> Result<Vec<String>> {
-    let request = unwrap_req_err!(reqwest::blocking::Client::new().get("https://www.googleapis.com/drive/v3/files/generateIds?count=100")
-        .header("Authorization", &format!("Bearer {}", access_token))
-        .send());
+pub async fn get_file(env: &Env, id: &str) -> Result<File> {
+    let access_token = oauth::access_token(env).await?;
+    let uri = format!("https://www.googleapis.com/drive/v3/files/{}?supportsAllDrives=true&fields=modifiedTime,id,name,mimeType,parents,explicitlyTrashed,trashed,shortcutDetails,size,md5Checksum,appProperties,ownedByMe,capabilities/canAddChildren,driveId,headRevisionId", id);
+    let request = retry::send(env, crate::api::async_client().get(&uri)
+        .header("Authorization", &format!("Bearer {}", access_token))).await?;
 
-    let payload: GoogleResponse<GetIdsResponse> = unwrap_req_err!(request.json());
-    let ids = unwrap_google_err!(payload);
-    Ok(ids.ids)
+    let response: GoogleResponse<File> = unwrap_req_err!(request.json().await);
+    let file = unwrap_google_err!(response);
+
+    Ok(file)
 }
 
-/// Struct describing the query parameters used when updating a file
-#[derive(Serialize)]
-#[serde(rename_all = "camelCase")]
-struct UpdateFileRequestQuery {
-    /// The upload type
-    upload_type:            &'static str,
-    /// If we support all drives, we do
-    supports_all_drives:    bool
+/// Escape a value so it can be safely used as a string literal in a search query
+pub fn escape_query_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('\'', "\\'")
 }
 
-/// Struct describing the metadata used when updating a file
-#[derive(Serialize)]
-#[serde(rename_all = "camelCase")]
-struct UpdateFileRequest<'a> {
-    /// The MIME type of the file
-    mime_type: &'a str
+/// Download the content of a file from Google Drive to the provided path
+///
+/// ## Params
+/// - `env` Env instance
+/// - `id` The ID of the file in Google Drive
+/// - `path` The path to write the file's content to
+///
+/// ## Errors
+/// - Request failure
+/// - Google API error
+/// - IO error when writing the file
+pub async fn download_file<P>(env: &Env, id: &str, path: P) -> Result<()>
+where P: AsRef<Path> {
+    let uri = format!("https://www.googleapis.com/drive/v3/files/{}?alt=media&supportsAllDrives=true", id);
+    download(env, &uri, path).await
 }
 
-/// Update a file in Google Drive. The caller should make sure the file exists.
+/// Download the content of a specific revision of a file from Google Drive to the provided path
 ///
 /// ## Params
 /// - `env` Env instance
-/// - `path` Path to the file to be updated
-/// - `id` The ID of the existing file in Google Drive to be updated
+/// - `id` The ID of the file in Google Drive
+/// - `revision_id` The ID of the revision to download
+/// - `path` The path to write the revision's content to
 ///
 /// ## Errors
 /// - Request failure
 /// - Google API error
-/// - Failure to construct multipart parts
-pub fn update_file<P>(env: &Env, path: P, id: &str) -> Result<()>
+/// - IO error when writing the file
+pub async fn download_revision<P>(env: &Env, id: &str, revision_id: &str, path: P) -> Result<()>
 where P: AsRef<Path> {
-    let access_token = get_access_token(env)?;
-    let query = UpdateFileRequestQuery {
-        supports_all_drives:    true,
-        upload_type:            "multipart"
-    };
+    let uri = format!("https://www.googleapis.com/drive/v3/files/{}/revisions/{}?alt=media&supportsAllDrives=true", id, revision_id);
+    download(env, &uri, path).await
+}
 
-    let mime = match mime_guess::from_path(&path).first() {
-        Some(g) => {
-            g.essence_str().to_string()
-        },
-        None => "application/octet-stream".to_string()
-    };
+/// Export a Google-native document (Docs, Sheets, Slides, Drawings) to the provided MIME type and write it to the provided path
+///
+/// ## Params
+/// - `env` Env instance
+/// - `id` The ID of the document in Google Drive
+/// - `mime_type` The MIME type to export to
+/// - `path` The path to write the exported document to
+///
+/// ## Errors
+/// - Request failure
+/// - Google API error
+/// - IO error when writing the file
+pub async fn export_file<P>(env: &Env, id: &str, mime_type: &str, path: P) -> Result<()>
+where P: AsRef<Path> {
+    let uri = format!("https://www.googleapis.com/drive/v3/files/{}/export?mimeType={}", id, mime_type.replace("+", "%2B"));
+    download(env, &uri, path).await
+}
 
-    let payload = UpdateFileRequest {
-        mime_type: &mime
-    };
+/// Start downloading the content of a file from Google Drive. The content can be read from the returned response
+///
+/// ## Params
+/// - `env` Env instance
+/// - `id` The ID of the file in Google Drive
+///
+/// ## Errors
+/// - Request failure
+/// - Google API error
+pub async fn download_stream(env: &Env, id: &str) -> Result<reqwest::Response> {
+    let access_token = oauth::access_token(env).await?;
+    let uri = format!("https://www.googleapis.com/drive/v3/files/{}?alt=media&supportsAllDrives=true", id);
+    let response = retry::send(env, crate::api::async_client().get(&uri)
+        .header("Authorization", &format!("Bearer {}", access_token))).await?;
 
-    let metadata_part = unwrap_req_err!(Part::text(unwrap_other_err!(serde_json::to_string(&payload))).mime_str("application/json"));
-    let file_part = unwrap_req_err!(unwrap_other_err!(Part::file(&path)).mime_str(&mime));
+    if !response.status().is_success() {
+        let payload: GoogleResponse<()> = unwrap_req_err!(response.json().await);
+        unwrap_google_err!(payload);
+        return Err((Error::Other(format!("Failed to download '{}'", id)), line!(), file!()));
+    }
 
-    let form = Form::new()
-        .part("Metadata", metadata_part)
-        .part("Media", file_part);
+    Ok(response)
+}
 
-    let uri = format!("https://www.googleapis.com/upload/drive/v3/files/{}?{}", id, unwrap_other_err!(serde_qs::to_string(&query)));
-    let response = unwrap_req_err!(reqwest::blocking::Client::new().patch(&uri)
-        .multipart(form)
-        .header("Content-Type", "multipart/related")
+/// Download part of the content of a file from Google Drive
+///
+/// ## Params
+/// - `env` Env instance
+/// - `id` The ID of the file in Google Drive
+/// - `offset` The offset in bytes of the first byte to download
+/// - `length` The number of bytes to download
+///
+/// ## Errors
+/// - Request failure
+/// - Google API error
+#[cfg(all(unix, feature = "fuse"))]
+pub async fn download_range(env: &Env, id: &str, offset: u64, length: u64) -> Result<Vec<u8>> {
+    let access_token = oauth::access_token(env).await?;
+    let uri = format!("https://www.googleapis.com/drive/v3/files/{}?alt=media&supportsAllDrives=true", id);
+    let response = retry::send(env, crate::api::async_client().get(&uri)
         .header("Authorization", &format!("Bearer {}", access_token))
-        .send());
+        .header("Range", &format!("bytes={}-{}", offset, offset + length.max(1) - 1))).await?;
 
-    let payload: GoogleResponse<()> = unwrap_req_err!(response.json());
-    unwrap_google_err!(payload);
+    if !response.status().is_success() {
+        let payload: GoogleResponse<()> = unwrap_req_err!(response.json().await);
+        unwrap_google_err!(payload);
+        return Err((Error::Other(format!("Failed to download '{}'", id)), line!(), file!()));
+    }
+
+    Ok(unwrap_req_err!(response.bytes().await).to_vec())
+}
+
+/// Download the content returned by the provided URI to the provided path
+///
+/// ## Errors
+/// - Request failure
+/// - Google API error
+/// - IO error when writing the file
+async fn download<P>(env: &Env, uri: &str, path: P) -> Result<()>
+where P: AsRef<Path> {
+    let access_token = oauth::access_token(env).await?;
+    let mut response = retry::send(env, crate::api::async_client().get(uri)
+        .header("Authorization", &format!("Bearer {}", access_token))).await?;
+
+    if !response.status().is_success() {
+        let payload: GoogleResponse<()> = unwrap_req_err!(response.json().await);
+        unwrap_google_err!(payload);
+        return Err((Error::Other(format!("Failed to download '{}'", uri)), line!(), file!()));
+    }
+
+    let mut file = unwrap_other_err!(std::fs::File::create(path));
+    while let Some(chunk) = unwrap_req_err!(response.chunk().await) {
+        unwrap_other_err!(file.write_all(&chunk));
+    }
 
     Ok(())
 }
 
-/// Permanently delete a file
+/// Struct describing the response to a call to the revisions list API
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct RevisionListResponse {
+    /// The revisions returned
+    revisions:          Vec<Revision>,
+
+    /// The token for the next page, if there are more results
+    next_page_token:    Option<String>
+}
+
+/// Struct describing an individual revision of a file
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Revision {
+    /// The ID of the revision
+    pub id:             String,
+    /// The time the revision was created
+    pub modified_time:  String,
+    /// The size of the revision's content in bytes
+    pub size:           Option<String>,
+    /// The MD5 checksum of the revision's content
+    pub md5_checksum:   Option<String>
+}
+
+impl Revision {
+    /// The size of the revision's content in bytes, 0 if unknown
+    pub fn size(&self) -> u64 {
+        self.size.as_ref().and_then(|size| size.parse().ok()).unwrap_or(0)
+    }
+}
+
+/// List all revisions of a file, oldest first
 ///
 /// ## Params
 /// - `env` Env instance
-/// - `id` The ID of the existing file in Google Drive to be updated
+/// - `id` The ID of the file in Google Drive
 ///
 /// ## Errors
 /// - Request failure
 /// - Google API error
-pub fn delete_file(env: &Env, id: &str) -> Result<()> {
-    let access_token = get_access_token(env)?;
-    let uri = format!("https://www.googleapis.com/drive/v3/files/{}?supportsAllDrives=true", id);
-    let response = unwrap_req_err!(reqwest::blocking::Client::new().delete(&uri)
-        .header("Authorization", &format!("Bearer {}", access_token))
-        .send());
+pub async fn list_revisions(env: &Env, id: &str) -> Result<Vec<Revision>> {
+    let mut revisions = Vec::new();
+    let mut page_token: Option<String> = None;
 
-    let payload: GoogleResponse<()> = unwrap_req_err!(response.json());
-    unwrap_google_err!(payload);
+    loop {
+        let mut uri = format!("https://www.googleapis.com/drive/v3/files/{}/revisions?pageSize=1000&fields=nextPageToken,revisions/id,revisions/modifiedTime,revisions/size,revisions/md5Checksum", id);
+        if let Some(token) = &page_token {
+            uri.push_str(&format!("&pageToken={}", token));
+        }
 
-    Ok(())
-}
\ No newline at end of file
+        let access_token = oauth::access_token(env).await?;
+        let request = retry::send(env, crate::api::async_client().get(&uri)
+            .header("Authorization", &format!("Bearer {}", access_token))).await?;
+
+        let response: GoogleResponse<RevisionListResponse> = unwrap_req_err!(request.json().await);
+        let mut payload = unwrap_google_err!(response);
+        revisions.append(&mut payload.revisions);
+
+        match payload.next_page_token {
+            Some(token) => page_token = Some(token),
+            None => break
+        }
+    }
+
+    Ok(revisions)
+}
+
+/// Struct describing the response to a call to the startPageToken API
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct StartPageTokenResponse {
+    /// The token to list the changes made from now on with
+    start_page_token:   String
+}
+
+/// Struct describing the response to a call to the changes list API
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ChangeListResponse {
+    /// The changes returned
+    changes:                Vec<Change>,
+
+    /// The token for the next page, if there are more changes
+    next_page_token:        Option<String>,
+
+    /// The token to list future changes with, only present on the last page
+    new_start_page_token:   Option<String>
+}
+
+/// Struct describing a change to a file in Google Drive
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Change {
+    /// The ID of the file which changed
+    pub file_id:    String,
+    /// Whether the file was removed, i.e. deleted permanently or no longer accessible to the user
+    #[serde(default)]
+    pub removed:    bool,
+    /// The file as it is after the change. Absent if the file was removed
+    pub file:       Option<File>
+}
+
+/// Get the token to list the changes made in Google Drive from now on with
+///
+/// ## Params
+/// - `env` Env instance
+/// - `drive_id` The ID of the shared drive to follow, None for My Drive
+///
+/// ## Errors
+/// - Request failure
+/// - Google API error
+pub async fn get_start_page_token(env: &Env, drive_id: Option<&str>) -> Result<String> {
+    let mut uri = "https://www.googleapis.com/drive/v3/changes/startPageToken?supportsAllDrives=true".to_string();
+    if let Some(drive_id) = drive_id {
+        uri.push_str(&format!("&driveId={}", drive_id));
+    }
+
+    let access_token = oauth::access_token(env).await?;
+    let request = retry::send(env, crate::api::async_client().get(&uri)
+        .header("Authorization", &format!("Bearer {}", access_token))).await?;
+
+    let response: GoogleResponse<StartPageTokenResponse> = unwrap_req_err!(request.json().await);
+    let payload = unwrap_google_err!(response);
+
+    Ok(payload.start_page_token)
+}
+
+/// List the changes made in Google Drive since a page token was obtained, oldest first.
+/// Returns the changes, and the token to list the changes made after them with
+///
+/// ## Params
+/// - `env` Env instance
+/// - `page_token` The token from `get_start_page_token`, or from a previous call
+/// - `drive_id` The ID of the shared drive to follow, None for My Drive
+///
+/// ## Errors
+/// - Request failure
+/// - Google API error
+pub async fn list_changes(env: &Env, page_token: &str, drive_id: Option<&str>) -> Result<(Vec<Change>, String)> {
+    let mut changes = Vec::new();
+    let mut page_token = page_token.to_string();
+
+    loop {
+        let mut uri = format!("https://www.googleapis.com/drive/v3/changes?pageToken={}&pageSize=1000&supportsAllDrives=true&includeItemsFromAllDrives=true&fields=nextPageToken,newStartPageToken,changes/fileId,changes/removed,changes/file/id,changes/file/name,changes/file/modifiedTime,changes/file/mimeType,changes/file/parents,changes/file/trashed,changes/file/md5Checksum", page_token);
+        if let Some(drive_id) = drive_id {
+            uri.push_str(&format!("&driveId={}", drive_id));
+        }
+
+        let access_token = oauth::access_token(env).await?;
+        let request = retry::send(env, crate::api::async_client().get(&uri)
+            .header("Authorization", &format!("Bearer {}", access_token))).await?;
+
+        let response: GoogleResponse<ChangeListResponse> = unwrap_req_err!(request.json().await);
+        let mut payload = unwrap_google_err!(response);
+        changes.append(&mut payload.changes);
+
+        match (payload.next_page_token, payload.new_start_page_token) {
+            (Some(token), _) => page_token = token,
+            (None, Some(token)) => return Ok((changes, token)),
+            (None, None) => return Err((Error::Other("Google returned neither a next page nor a new start page token".to_string()), line!(), file!()))
+        }
+    }
+}
+
+/// Struct describing the response to the shared drives API
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct SharedDriveResponse {
+    /// The returned drives
+    #[serde(default)]
+    drives:             Vec<SharedDrive>,
+    /// The token for the next page, absent on the last page
+    next_page_token:    Option<String>
+}
+
+/// Struct describing the individual drives returned by the shared shared drives API
+#[derive(Deserialize, Debug)]
+pub struct SharedDrive {
+    /// The drive's ID
+    pub id:             String,
+    /// The drive's name
+    pub name:           String,
+    /// What the user may do in the drive
+    #[serde(default)]
+    pub capabilities:   DriveCapabilities
+}
+
+/// Struct describing what the user may do in a shared drive
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct DriveCapabilities {
+    /// Whether the user can add files and folders to the drive
+    #[serde(default)]
+    pub can_add_children:       bool,
+    /// Whether the user can edit the files in the drive
+    #[serde(default)]
+    pub can_edit:               bool,
+    /// Whether the user can move files in the drive to the trash
+    #[serde(default)]
+    pub can_trash_children:     bool,
+    /// Whether the user can permanently delete files in the drive
+    #[serde(default)]
+    pub can_delete_children:    bool,
+    /// Whether the user can manage the members of the drive
+    #[serde(default)]
+    pub can_manage_members:     bool
+}
+
+impl SharedDrive {
+    /// Whether GSync can sync into this drive, i.e. the user can add and edit files in it
+    pub fn is_writable(&self) -> bool {
+        self.capabilities.can_add_children && self.capabilities.can_edit
+    }
+
+    /// Whether the drive's name contains `filter`, ignoring case
+    pub fn matches(&self, filter: &str) -> bool {
+        self.name.to_lowercase().contains(&filter.to_lowercase())
+    }
+}
+
+impl DriveCapabilities {
+    /// The names of the capabilities the user has, in a human readable form
+    pub fn names(&self) -> Vec<&'static str> {
+        [
+            (self.can_add_children, "add files"),
+            (self.can_edit, "edit files"),
+            (self.can_trash_children, "trash files"),
+            (self.can_delete_children, "delete files"),
+            (self.can_manage_members, "manage members")
+        ].iter()
+            .filter(|(has, _)| *has)
+            .map(|(_, name)| *name)
+            .collect()
+    }
+}
+
+/// Get all shared drives the user has access too
+///
+/// # Error
+/// - Google API error
+/// - Reqwest error
+pub async fn get_shared_drives(env: &Env) -> Result<Vec<SharedDrive>> {
+    let mut drives = Vec::new();
+    let mut page_token: Option<String> = None;
+
+    loop {
+        let access_token = oauth::access_token(env).await?;
+        let mut uri = "https://www.googleapis.com/drive/v3/drives?pageSize=100&fields=nextPageToken,drives(id,name,capabilities)".to_string();
+        if let Some(token) = &page_token {
+            uri.push_str(&format!("&pageToken={}", token));
+        }
+
+        let request = retry::send(env, crate::api::async_client().get(&uri)
+            .header("Authorization", &format!("Bearer {}", &access_token))).await?;
+
+        let response: GoogleResponse<SharedDriveResponse> = unwrap_req_err!(request.json().await);
+        let mut payload = unwrap_google_err!(response);
+        drives.append(&mut payload.drives);
+
+        match payload.next_page_token {
+            Some(token) => page_token = Some(token),
+            None => break
+        }
+    }
+
+    Ok(drives)
+}
+
+/// Struct describing the response to a call to the about API
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct AboutResponse {
+    /// The user's storage quota
+    storage_quota:  StorageQuota
+}
+
+/// Struct describing the storage quota of a user. All values are in bytes
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageQuota {
+    /// The usage limit, absent if the user has unlimited storage
+    limit:  Option<String>,
+    /// The total usage across all services
+    usage:  Option<String>
+}
+
+impl StorageQuota {
+    /// The usage limit in bytes, None if the user has unlimited storage
+    pub fn limit(&self) -> Option<u64> {
+        self.limit.as_ref().and_then(|limit| limit.parse().ok())
+    }
+
+    /// The total usage across all services in bytes
+    pub fn usage(&self) -> u64 {
+        self.usage.as_ref().and_then(|usage| usage.parse().ok()).unwrap_or(0)
+    }
+}
+
+/// Get the storage quota of the user
+///
+/// ## Errors
+/// - Request failure
+/// - Google API error
+pub async fn get_storage_quota(env: &Env) -> Result<StorageQuota> {
+    let access_token = oauth::access_token(env).await?;
+    let request = retry::send(env, crate::api::async_client().get("https://www.googleapis.com/drive/v3/about?fields=storageQuota")
+        .header("Authorization", &format!("Bearer {}", access_token))).await?;
+
+    let response: GoogleResponse<AboutResponse> = unwrap_req_err!(request.json().await);
+    let payload = unwrap_google_err!(response);
+
+    Ok(payload.storage_quota)
+}
+
+/// Struct describing the response to a call to the generateIds API
+#[derive(Deserialize)]
+struct GetIdsResponse {
+    /// The returned IDs
+    ids:    Vec<String>
+}
+
+/// Get a File ID from the IDS Vec. If this Vec contains no more IDs, a new set will be requested from Google.
+///
+/// ## Params
+/// - `env` Env instance
+///
+/// ## Errors
+/// - Request failure
+/// - Error from Google API
+async fn get_id(env: &Env) -> Result<String> {
+    let mut ids = IDS.lock().await;
+    if ids.is_empty() {
+        let access_token = oauth::access_token(env).await?;
+        *ids = get_ids_from_google(env, &access_token).await?;
+    }
+
+    match ids.pop() {
+        Some(id) => Ok(id),
+        None => Err((Error::Other("Google returned no file IDs".to_string()), line!(), file!()))
+    }
+}
+
+/// Request 100 new File IDs from Google. Do not call this function directly, instead use `get_id()`
+///
+/// ## Errors
+/// - Request failure
+/// - Error from Google API
+async fn get_ids_from_google(env: &Env, access_token: &str) -> Result<Vec<String>> {
+    let request = retry::send(env, crate::api::async_client().get("https://www.googleapis.com/drive/v3/files/generateIds?count=100")
+        .header("Authorization", &format!("Bearer {}", access_token))).await?;
+
+    let payload: GoogleResponse<GetIdsResponse> = unwrap_req_err!(request.json().await);
+    let ids = unwrap_google_err!(payload);
+    Ok(ids.ids)
+}
+
+/// Struct describing the query parameters used when updating a file
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct UpdateFileRequestQuery {
+    /// The upload type
+    upload_type:            &'static str,
+    /// If we support all drives, we do
+    supports_all_drives:    bool,
+    /// The fields of the updated file to return
+    fields:                 &'static str
+}
+
+/// Struct describing the metadata used when updating a file
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct UpdateFileRequest<'a> {
+    /// The MIME type of the file. If not set, the file keeps its type
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mime_type: Option<&'a str>,
+    /// Private properties of the file, only visible to GSync. A property without a value is removed
+    app_properties: HashMap<&'a str, Option<&'a str>>
+}
+
+/// Update a file in Google Drive, and return the checksum of the content Google received. The caller should make sure the file exists.
+///
+/// ## Params
+/// - `env` Env instance
+/// - `path` Path to the file to be updated
+/// - `id` The ID of the existing file in Google Drive to be updated
+/// - `app_properties` Private properties of the file, only visible to GSync
+///
+/// ## Errors
+/// - Request failure
+/// - Google API error
+/// - Failure to construct multipart parts
+pub async fn update_file<P>(env: &Env, path: P, id: &str, app_properties: &HashMap<String, String>) -> Result<UploadedFile>
+where P: AsRef<Path> {
+    let result = update_file_request(env, path.as_ref(), id, app_properties).await;
+    let revision = result.as_ref().ok().and_then(|file| file.head_revision_id.as_deref());
+    crate::audit::record_revision(env, Operation::Update, Some(&path.as_ref().to_string_lossy()), Some(id), revision, &result);
+    result
+}
+
+/// Send the request for [update_file], without recording it in the audit log
+async fn update_file_request<P>(env: &Env, path: P, id: &str, app_properties: &HashMap<String, String>) -> Result<UploadedFile>
+where P: AsRef<Path> {
+    let access_token = oauth::access_token(env).await?;
+    let query = UpdateFileRequestQuery {
+        supports_all_drives:    true,
+        upload_type:            "multipart",
+        fields:                 UPLOADED_FILE_FIELDS
+    };
+
+    let mime = crate::mime::mime_type(path.as_ref(), &env.mime_types);
+
+    // The marker of packed content is removed when the new content isn't packed
+    let layout = packed_layout(env, path.as_ref())?;
+    let mut properties = app_properties.iter().map(|(key, value)| (key.as_str(), Some(value.as_str()))).collect::<HashMap<_, _>>();
+    properties.insert(crate::metadata::PACKED, layout.as_ref().map(|_| "true"));
+
+    // A file converted to a Google document keeps its type, the new content is converted to it
+    let payload = UpdateFileRequest {
+        mime_type: match crate::mime::google_type(&mime) {
+            Some(_) if env.convert => None,
+            _ => Some(mime.as_str())
+        },
+        app_properties: properties
+    };
+
+    let metadata = unwrap_other_err!(serde_json::to_string(&payload));
+    let uri = format!("https://www.googleapis.com/upload/drive/v3/files/{}?{}", id, unwrap_other_err!(serde_qs::to_string(&query)));
+    let response = retry::send_with(env, || Ok(crate::api::async_client().patch(&uri)
+        .multipart(upload_form(env, &metadata, path.as_ref(), &mime, layout.as_ref())?)
+        .header("Content-Type", "multipart/related")
+        .header("Authorization", &format!("Bearer {}", access_token)))).await?;
+
+    let payload: GoogleResponse<UploadedFile> = unwrap_req_err!(response.json().await);
+    let uploaded = unwrap_google_err!(payload);
+
+    Ok(uploaded)
+}
+
+/// Struct describing the metadata used when marking a file stale
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct MarkStaleRequest<'a> {
+    /// The private properties to set on the file, a property without a value is removed
+    app_properties: HashMap<&'a str, Option<String>>
+}
+
+/// Send the request to mark a file or folder as stale, i.e. removed locally but kept in Drive, by setting a private property with the current time
+async fn mark_stale_request(env: &Env, id: &str) -> Result<()> {
+    set_stale_request(env, id, Some(chrono::Utc::now().timestamp().to_string())).await
+}
+
+/// Mark a file or folder which was marked stale as current again, as if it was never removed locally
+///
+/// ## Params
+/// - `env` Env instance
+/// - `id` The ID of the existing file in Google Drive
+///
+/// ## Errors
+/// - Request failure
+/// - Google API error
+pub async fn unmark_stale(env: &Env, id: &str) -> Result<()> {
+    let result = set_stale_request(env, id, None).await;
+    crate::audit::record(env, Operation::Unarchive, None, Some(id), &result);
+    result
+}
+
+/// Send the request to set or, without a time, remove the private property marking a file or folder as stale
+async fn set_stale_request(env: &Env, id: &str, stale_since: Option<String>) -> Result<()> {
+    let mut app_properties = HashMap::new();
+    app_properties.insert(crate::metadata::STALE, stale_since);
+
+    let access_token = oauth::access_token(env).await?;
+    let uri = format!("https://www.googleapis.com/drive/v3/files/{}?supportsAllDrives=true", id);
+    let response = retry::send(env, crate::api::async_client().patch(&uri)
+        .header("Content-Type", "application/json")
+        .header("Authorization", &format!("Bearer {}", access_token))
+        .body(unwrap_other_err!(serde_json::to_string(&MarkStaleRequest { app_properties })))).await?;
+
+    let payload: GoogleResponse<()> = unwrap_req_err!(response.json().await);
+    unwrap_google_err!(payload);
+
+    Ok(())
+}
+
+/// Struct describing the metadata used when moving a file to or from the trash
+#[derive(Serialize)]
+struct TrashFileRequest {
+    /// Whether the file should be trashed
+    trashed:    bool
+}
+
+/// Move a file to, or restore a file from, the trash
+///
+/// ## Params
+/// - `env` Env instance
+/// - `id` The ID of the existing file in Google Drive
+/// - `trashed` `true` to move the file to the trash, `false` to restore it
+///
+/// ## Errors
+/// - Request failure
+/// - Google API error
+pub async fn set_trashed(env: &Env, id: &str, trashed: bool) -> Result<()> {
+    let result = set_trashed_request(env, id, trashed).await;
+    let operation = if trashed { Operation::Trash } else { Operation::Untrash };
+    crate::audit::record(env, operation, None, Some(id), &result);
+    result
+}
+
+/// Send the request for [set_trashed], without recording it in the audit log
+async fn set_trashed_request(env: &Env, id: &str, trashed: bool) -> Result<()> {
+    let access_token = oauth::access_token(env).await?;
+    let uri = format!("https://www.googleapis.com/drive/v3/files/{}?supportsAllDrives=true", id);
+    let response = retry::send(env, crate::api::async_client().patch(&uri)
+        .header("Content-Type", "application/json")
+        .header("Authorization", &format!("Bearer {}", access_token))
+        .body(unwrap_other_err!(serde_json::to_string(&TrashFileRequest { trashed })))).await?;
+
+    let payload: GoogleResponse<()> = unwrap_req_err!(response.json().await);
+    unwrap_google_err!(payload);
+
+    Ok(())
+}
+
+/// Struct describing a permission on a file in Google Drive
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Permission {
+    /// The ID of the permission
+    pub id:                 String,
+
+    /// The type of the grantee: user, group, domain or anyone
+    #[serde(rename = "type")]
+    pub permission_type:    String,
+
+    /// The role granted: owner, organizer, fileOrganizer, writer, commenter or reader
+    pub role:               String,
+
+    /// The email address of the user or group the permission is granted to
+    pub email_address:      Option<String>,
+
+    /// The domain the permission is granted to
+    pub domain:             Option<String>,
+
+    /// The display name of the grantee
+    pub display_name:       Option<String>,
+
+    /// Details on whether the permission is inherited. Only present for files in shared drives
+    #[serde(default)]
+    pub permission_details: Vec<PermissionDetails>
+}
+
+/// Struct describing details of a permission
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PermissionDetails {
+    /// Whether the permission is inherited from a parent folder
+    #[serde(default)]
+    pub inherited:  bool
+}
+
+impl Permission {
+    /// Check if this permission is inherited from a parent folder, rather than set on the file itself
+    pub fn is_inherited(&self) -> bool {
+        !self.permission_details.is_empty() && self.permission_details.iter().all(|details| details.inherited)
+    }
+
+    /// A human readable description of who the permission is granted to
+    pub fn grantee(&self) -> String {
+        match self.permission_type.as_str() {
+            "anyone" => "anyone with the link".to_string(),
+            "domain" => format!("everyone in {}", self.domain.as_deref().unwrap_or("the domain")),
+            _ => self.email_address.clone().or_else(|| self.display_name.clone()).unwrap_or_else(|| self.id.clone())
+        }
+    }
+}
+
+/// Struct describing the response to a permissions list request
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PermissionListResponse {
+    /// The permissions on the file
+    permissions:        Vec<Permission>,
+
+    /// The token of the next page, if there is one
+    next_page_token:    Option<String>
+}
+
+/// List all permissions on a file
+///
+/// ## Params
+/// - `env` Env instance
+/// - `id` The ID of the file in Google Drive
+///
+/// ## Errors
+/// - Request failure
+/// - Google API error
+pub async fn list_permissions(env: &Env, id: &str) -> Result<Vec<Permission>> {
+    let access_token = oauth::access_token(env).await?;
+    let mut permissions = Vec::new();
+    let mut page_token: Option<String> = None;
+
+    loop {
+        let mut uri = format!("https://www.googleapis.com/drive/v3/files/{}/permissions?supportsAllDrives=true&fields=nextPageToken,permissions(id,type,role,emailAddress,domain,displayName,permissionDetails)", id);
+        if let Some(token) = &page_token {
+            uri.push_str(&format!("&pageToken={}", token));
+        }
+
+        let response = retry::send(env, crate::api::async_client().get(&uri)
+            .header("Authorization", &format!("Bearer {}", access_token))).await?;
+
+        let payload: GoogleResponse<PermissionListResponse> = unwrap_req_err!(response.json().await);
+        let list = unwrap_google_err!(payload);
+        permissions.extend(list.permissions);
+
+        match list.next_page_token {
+            Some(token) => page_token = Some(token),
+            None => break
+        }
+    }
+
+    Ok(permissions)
+}
+
+/// Struct describing the request to create a permission
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CreatePermissionRequest<'a> {
+    /// The role to grant
+    role:           &'static str,
+
+    /// The type of the grantee
+    #[serde(rename = "type")]
+    grantee_type:   &'static str,
+
+    /// The email address of the grantee
+    email_address:  &'a str,
+
+    /// Whether the grantee is asked to accept ownership of the file
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pending_owner:  bool
+}
+
+/// Transfer the ownership of a file to another user. The current owner keeps write access to the file
+///
+/// ## Params
+/// - `env` Env instance
+/// - `id` The ID of the file in Google Drive
+/// - `email` The email address of the new owner
+/// - `pending` Instead of transferring the ownership right away, ask the new owner to accept it. This is required when transferring ownership to a user outside of your domain, e.g. between personal accounts
+///
+/// ## Errors
+/// - Request failure
+/// - Google API error
+pub async fn transfer_ownership(env: &Env, id: &str, email: &str, pending: bool) -> Result<()> {
+    let result = transfer_ownership_request(env, id, email, pending).await;
+    crate::audit::record(env, Operation::Permission, None, Some(id), &result);
+    result
+}
+
+/// Send the request for [transfer_ownership], without recording it in the audit log
+async fn transfer_ownership_request(env: &Env, id: &str, email: &str, pending: bool) -> Result<()> {
+    let access_token = oauth::access_token(env).await?;
+    let (uri, body) = if pending {
+        (format!("https://www.googleapis.com/drive/v3/files/{}/permissions?supportsAllDrives=true", id), CreatePermissionRequest { role: "writer", grantee_type: "user", email_address: email, pending_owner: true })
+    } else {
+        (format!("https://www.googleapis.com/drive/v3/files/{}/permissions?supportsAllDrives=true&transferOwnership=true", id), CreatePermissionRequest { role: "owner", grantee_type: "user", email_address: email, pending_owner: false })
+    };
+
+    let response = retry::send(env, crate::api::async_client().post(&uri)
+        .header("Content-Type", "application/json")
+        .header("Authorization", &format!("Bearer {}", access_token))
+        .body(unwrap_other_err!(serde_json::to_string(&body)))).await?;
+
+    let payload: GoogleResponse<Permission> = unwrap_req_err!(response.json().await);
+    unwrap_google_err!(payload);
+
+    Ok(())
+}
+
+/// Struct describing a modification of the labels on a file
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LabelModification {
+    /// The ID of the label to apply
+    pub label_id:               String,
+
+    /// The values to set on the label's fields
+    pub field_modifications:    Vec<FieldModification>
+}
+
+/// Struct describing a modification of a field of a label. Only the value matching the field's type should be set
+#[derive(Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldModification {
+    /// The ID of the field
+    pub field_id:               String,
+
+    /// The value of a text field
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub set_text_values:        Vec<String>,
+
+    /// The value of an integer field
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub set_integer_values:     Vec<String>,
+
+    /// The value of a date field
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub set_date_values:        Vec<LabelDate>,
+
+    /// The email address of a user field
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub set_user_values:        Vec<String>,
+
+    /// The ID of the chosen choice of a selection field
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub set_selection_values:   Vec<String>
+}
+
+/// Struct describing the value of a date field
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct LabelDate {
+    /// The year
+    pub year:   i32,
+    /// The month, 1 to 12
+    pub month:  u32,
+    /// The day of the month
+    pub day:    u32
+}
+
+/// Struct describing a request to modify the labels on a file
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ModifyLabelsRequest<'a> {
+    /// The modifications to apply
+    label_modifications:    &'a [LabelModification]
+}
+
+/// Apply labels to a file
+///
+/// ## Params
+/// - `env` Env instance
+/// - `id` The ID of the file in Google Drive
+/// - `modifications` The labels, and the values of their fields, to apply
+///
+/// ## Errors
+/// - Request failure
+/// - Google API error
+pub async fn modify_labels(env: &Env, id: &str, modifications: &[LabelModification]) -> Result<()> {
+    let result = modify_labels_request(env, id, modifications).await;
+    crate::audit::record(env, Operation::Label, None, Some(id), &result);
+    result
+}
+
+/// Send the request for [modify_labels], without recording it in the audit log
+async fn modify_labels_request(env: &Env, id: &str, modifications: &[LabelModification]) -> Result<()> {
+    let access_token = oauth::access_token(env).await?;
+    let uri = format!("https://www.googleapis.com/drive/v3/files/{}/modifyLabels", id);
+    let response = retry::send(env, crate::api::async_client().post(&uri)
+        .header("Content-Type", "application/json")
+        .header("Authorization", &format!("Bearer {}", access_token))
+        .body(unwrap_other_err!(serde_json::to_string(&ModifyLabelsRequest { label_modifications: modifications })))).await?;
+
+    let payload: GoogleResponse<serde_json::Value> = unwrap_req_err!(response.json().await);
+    unwrap_google_err!(payload);
+
+    Ok(())
+}
+
+/// Remove a permission from a file
+///
+/// ## Params
+/// - `env` Env instance
+/// - `id` The ID of the file in Google Drive
+/// - `permission_id` The ID of the permission to remove
+///
+/// ## Errors
+/// - Request failure
+/// - Google API error
+pub async fn delete_permission(env: &Env, id: &str, permission_id: &str) -> Result<()> {
+    let result = delete_permission_request(env, id, permission_id).await;
+    crate::audit::record(env, Operation::Permission, None, Some(id), &result);
+    result
+}
+
+/// Send the request for [delete_permission], without recording it in the audit log
+async fn delete_permission_request(env: &Env, id: &str, permission_id: &str) -> Result<()> {
+    let access_token = oauth::access_token(env).await?;
+    let uri = format!("https://www.googleapis.com/drive/v3/files/{}/permissions/{}?supportsAllDrives=true", id, permission_id);
+    let response = retry::send(env, crate::api::async_client().delete(&uri)
+        .header("Authorization", &format!("Bearer {}", access_token))).await?;
+
+    // A successful delete has an empty body
+    if response.status().is_success() {
+        return Ok(());
+    }
+
+    let payload: GoogleResponse<()> = unwrap_req_err!(response.json().await);
+    unwrap_google_err!(payload);
+
+    Ok(())
+}
+
+/// Send the request to permanently delete a file, without recording it in the audit log
+async fn delete_file_request(env: &Env, id: &str) -> Result<()> {
+    let access_token = oauth::access_token(env).await?;
+    let uri = format!("https://www.googleapis.com/drive/v3/files/{}?supportsAllDrives=true", id);
+    let response = retry::send(env, crate::api::async_client().delete(&uri)
+        .header("Authorization", &format!("Bearer {}", access_token))).await?;
+
+    // A successful delete has an empty body
+    if response.status().is_success() {
+        return Ok(());
+    }
+
+    let payload: GoogleResponse<()> = unwrap_req_err!(response.json().await);
+    unwrap_google_err!(payload);
+
+    Ok(())
+}
+
+/// Permanently delete many files, sending up to [batch::MAX_BATCH_SIZE] deletions in a single request
+///
+/// ## Params
+/// - `env` Env instance
+/// - `ids` The IDs of the existing files in Google Drive
+///
+/// ## Returns
+/// The result of every deletion, in the same order as `ids`
+///
+/// ## Errors
+/// - When a batch request fails as a whole
+pub async fn delete_files(env: &Env, ids: &[&str]) -> Result<Vec<Result<()>>> {
+    send_batched(env, ids, Operation::Delete, "DELETE", None, |id| Box::pin(delete_file_request(env, id))).await
+}
+
+/// Move many files to the trash, sending up to [batch::MAX_BATCH_SIZE] of them in a single request
+///
+/// ## Params
+/// - `env` Env instance
+/// - `ids` The IDs of the existing files in Google Drive
+///
+/// ## Returns
+/// The result for every file, in the same order as `ids`
+///
+/// ## Errors
+/// - When a batch request fails as a whole
+pub async fn trash_files(env: &Env, ids: &[&str]) -> Result<Vec<Result<()>>> {
+    let body = unwrap_other_err!(serde_json::to_string(&TrashFileRequest { trashed: true }));
+    send_batched(env, ids, Operation::Trash, "PATCH", Some(body), |id| Box::pin(set_trashed_request(env, id, true))).await
+}
+
+/// Mark many files as stale, i.e. removed locally but kept in Drive, sending up to [batch::MAX_BATCH_SIZE] of them in a single request
+///
+/// ## Params
+/// - `env` Env instance
+/// - `ids` The IDs of the existing files in Google Drive
+///
+/// ## Returns
+/// The result for every file, in the same order as `ids`
+///
+/// ## Errors
+/// - When a batch request fails as a whole
+pub async fn mark_stale_files(env: &Env, ids: &[&str]) -> Result<Vec<Result<()>>> {
+    let mut app_properties = HashMap::new();
+    app_properties.insert(crate::metadata::STALE, Some(chrono::Utc::now().timestamp().to_string()));
+
+    let body = unwrap_other_err!(serde_json::to_string(&MarkStaleRequest { app_properties }));
+    send_batched(env, ids, Operation::Archive, "PATCH", Some(body), |id| Box::pin(mark_stale_request(env, id))).await
+}
+
+/// Send the same request for every file in batches, and record every result in the audit log.
+/// Requests which Google throttled within a batch are sent again on their own with `single`, so they are retried with a backoff
+async fn send_batched<'a, F: Fn(&'a str) -> LocalBoxFuture<'a, Result<()>>>(env: &Env, ids: &[&'a str], operation: Operation, method: &'static str, body: Option<String>, single: F) -> Result<Vec<Result<()>>> {
+    let mut results = Vec::with_capacity(ids.len());
+    for chunk in ids.chunks(batch::MAX_BATCH_SIZE) {
+        let requests = chunk.iter()
+            .map(|id| BatchRequest {
+                method,
+                path: format!("/drive/v3/files/{}?supportsAllDrives=true", id),
+                body: body.clone()
+            })
+            .collect::<Vec<_>>();
+
+        let access_token = oauth::access_token(env).await?;
+        let responses = batch::send(env, &access_token, &requests).await?;
+        for (id, result) in chunk.iter().zip(responses) {
+            let result = match result {
+                Err((ref e, _, _)) if crate::concurrency::is_throttled(e) => single(id).await,
+                result => result
+            };
+
+            crate::audit::record(env, operation, None, Some(id), &result);
+            results.push(result);
+        }
+    }
+
+    Ok(results)
+}
+
+/// Delete a revision of a file. The last remaining revision of a file can't be deleted
+///
+/// ## Params
+/// - `env` Env instance
+/// - `id` The ID of the file in Google Drive
+/// - `revision_id` The ID of the revision to delete
+///
+/// ## Errors
+/// - Request failure
+/// - Google API error
+pub async fn delete_revision(env: &Env, id: &str, revision_id: &str) -> Result<()> {
+    let result = delete_revision_request(env, id, revision_id).await;
+    crate::audit::record(env, Operation::Revision, None, Some(id), &result);
+    result
+}
+
+/// Send the request for [delete_revision], without recording it in the audit log
+async fn delete_revision_request(env: &Env, id: &str, revision_id: &str) -> Result<()> {
+    let access_token = oauth::access_token(env).await?;
+    let uri = format!("https://www.googleapis.com/drive/v3/files/{}/revisions/{}", id, revision_id);
+    let response = retry::send(env, crate::api::async_client().delete(&uri)
+        .header("Authorization", &format!("Bearer {}", access_token))).await?;
+
+    // A successful delete has an empty body
+    if response.status().is_success() {
+        return Ok(());
+    }
+
+    let payload: GoogleResponse<()> = unwrap_req_err!(response.json().await);
+    unwrap_google_err!(payload);
+
+    Ok(())
+}
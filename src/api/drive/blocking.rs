@@ -0,0 +1,146 @@
+//! Blocking counterparts of the Google Drive API requests, for code which doesn't run on the async runtime.
+//! Every function runs the request of the same name in the parent module to completion on the shared runtime
+
+use std::collections::HashMap;
+use std::path::Path;
+use crate::api::block_on;
+use crate::api::drive::{self, File, Change, Revision, SharedDrive, StorageQuota, Permission, UploadedFile};
+use crate::env::Env;
+use crate::Result;
+
+/// Create a folder in Google Drive, and return it's ID. See [drive::create_folder]
+pub fn create_folder(env: &Env, folder_name: &str, parent: &str, app_properties: &HashMap<String, String>) -> Result<String> {
+    block_on(drive::create_folder(env, folder_name, parent, app_properties))
+}
+
+/// Create a shortcut in Google Drive, and return it's ID. See [drive::create_shortcut]
+pub fn create_shortcut(env: &Env, name: &str, parent: &str, target_id: &str, app_properties: &HashMap<String, String>) -> Result<String> {
+    block_on(drive::create_shortcut(env, name, parent, target_id, app_properties))
+}
+
+/// Upload a file to Google Drive. See [drive::upload_file]
+pub fn upload_file<P>(env: &Env, path: P, parent: &str, app_properties: &HashMap<String, String>) -> Result<UploadedFile>
+where P: AsRef<Path> {
+    block_on(drive::upload_file(env, path, parent, app_properties))
+}
+
+/// List the files in Google Drive. See [drive::list_files]
+pub fn list_files(env: &Env, q: Option<&str>, drive_id: Option<&str>) -> Result<Vec<File>> {
+    block_on(drive::list_files(env, q, drive_id))
+}
+
+/// List the files with the provided name in the application data folder. See [drive::list_app_data]
+pub fn list_app_data(env: &Env, name: &str) -> Result<Vec<File>> {
+    block_on(drive::list_app_data(env, name))
+}
+
+/// Upload a file to the application data folder and return its ID. See [drive::upload_app_data]
+pub fn upload_app_data<P>(env: &Env, path: P, app_properties: &HashMap<String, String>) -> Result<String>
+where P: AsRef<Path> {
+    block_on(drive::upload_app_data(env, path, app_properties))
+}
+
+/// Get a single file from Google Drive by its ID. See [drive::get_file]
+pub fn get_file(env: &Env, id: &str) -> Result<File> {
+    block_on(drive::get_file(env, id))
+}
+
+/// Download the content of a file to the provided path. See [drive::download_file]
+pub fn download_file<P>(env: &Env, id: &str, path: P) -> Result<()>
+where P: AsRef<Path> {
+    block_on(drive::download_file(env, id, path))
+}
+
+/// Download the content of a revision of a file to the provided path. See [drive::download_revision]
+pub fn download_revision<P>(env: &Env, id: &str, revision_id: &str, path: P) -> Result<()>
+where P: AsRef<Path> {
+    block_on(drive::download_revision(env, id, revision_id, path))
+}
+
+/// Export a Google-native document to the provided path. See [drive::export_file]
+pub fn export_file<P>(env: &Env, id: &str, mime_type: &str, path: P) -> Result<()>
+where P: AsRef<Path> {
+    block_on(drive::export_file(env, id, mime_type, path))
+}
+
+/// Download part of the content of a file. See [drive::download_range]
+#[cfg(all(unix, feature = "fuse"))]
+pub fn download_range(env: &Env, id: &str, offset: u64, length: u64) -> Result<Vec<u8>> {
+    block_on(drive::download_range(env, id, offset, length))
+}
+
+/// List all revisions of a file, oldest first. See [drive::list_revisions]
+pub fn list_revisions(env: &Env, id: &str) -> Result<Vec<Revision>> {
+    block_on(drive::list_revisions(env, id))
+}
+
+/// Get the token to list the changes made in Google Drive from now on with. See [drive::get_start_page_token]
+pub fn get_start_page_token(env: &Env, drive_id: Option<&str>) -> Result<String> {
+    block_on(drive::get_start_page_token(env, drive_id))
+}
+
+/// List the changes made in Google Drive since a page token was obtained. See [drive::list_changes]
+pub fn list_changes(env: &Env, page_token: &str, drive_id: Option<&str>) -> Result<(Vec<Change>, String)> {
+    block_on(drive::list_changes(env, page_token, drive_id))
+}
+
+/// Get all shared drives the user has access too. See [drive::get_shared_drives]
+pub fn get_shared_drives(env: &Env) -> Result<Vec<SharedDrive>> {
+    block_on(drive::get_shared_drives(env))
+}
+
+/// Get the storage quota of the user. See [drive::get_storage_quota]
+pub fn get_storage_quota(env: &Env) -> Result<StorageQuota> {
+    block_on(drive::get_storage_quota(env))
+}
+
+/// Update a file in Google Drive. See [drive::update_file]
+pub fn update_file<P>(env: &Env, path: P, id: &str, app_properties: &HashMap<String, String>) -> Result<UploadedFile>
+where P: AsRef<Path> {
+    block_on(drive::update_file(env, path, id, app_properties))
+}
+
+/// Mark a file or folder which was marked stale as current again. See [drive::unmark_stale]
+pub fn unmark_stale(env: &Env, id: &str) -> Result<()> {
+    block_on(drive::unmark_stale(env, id))
+}
+
+/// Move a file to, or restore a file from, the trash. See [drive::set_trashed]
+pub fn set_trashed(env: &Env, id: &str, trashed: bool) -> Result<()> {
+    block_on(drive::set_trashed(env, id, trashed))
+}
+
+/// List all permissions on a file. See [drive::list_permissions]
+pub fn list_permissions(env: &Env, id: &str) -> Result<Vec<Permission>> {
+    block_on(drive::list_permissions(env, id))
+}
+
+/// Transfer the ownership of a file to another user. See [drive::transfer_ownership]
+pub fn transfer_ownership(env: &Env, id: &str, email: &str, pending: bool) -> Result<()> {
+    block_on(drive::transfer_ownership(env, id, email, pending))
+}
+
+/// Remove a permission from a file. See [drive::delete_permission]
+pub fn delete_permission(env: &Env, id: &str, permission_id: &str) -> Result<()> {
+    block_on(drive::delete_permission(env, id, permission_id))
+}
+
+/// Permanently delete many files in batches. See [drive::delete_files]
+pub fn delete_files(env: &Env, ids: &[&str]) -> Result<Vec<Result<()>>> {
+    block_on(drive::delete_files(env, ids))
+}
+
+/// Move many files to the trash in batches. See [drive::trash_files]
+pub fn trash_files(env: &Env, ids: &[&str]) -> Result<Vec<Result<()>>> {
+    block_on(drive::trash_files(env, ids))
+}
+
+/// Mark many files as stale in batches. See [drive::mark_stale_files]
+pub fn mark_stale_files(env: &Env, ids: &[&str]) -> Result<Vec<Result<()>>> {
+    block_on(drive::mark_stale_files(env, ids))
+}
+
+/// Delete a revision of a file. See [drive::delete_revision]
+pub fn delete_revision(env: &Env, id: &str, revision_id: &str) -> Result<()> {
+    block_on(drive::delete_revision(env, id, revision_id))
+}
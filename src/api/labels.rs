@@ -0,0 +1,123 @@
+//! Google Drive Labels API
+
+use crate::env::Env;
+use crate::api::GoogleResponse;
+use crate::api::oauth::get_access_token;
+use serde::Deserialize;
+
+use crate::{Result, unwrap_req_err, unwrap_google_err};
+
+/// Struct describing a Drive label
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Label {
+    /// The ID of the label
+    pub id:         String,
+
+    /// The basic properties of the label
+    pub properties: LabelProperties,
+
+    /// The fields of the label
+    #[serde(default)]
+    pub fields:     Vec<Field>
+}
+
+/// Struct describing the basic properties of a label
+#[derive(Deserialize, Debug)]
+pub struct LabelProperties {
+    /// The title of the label
+    pub title:  String
+}
+
+/// Struct describing a field of a label. Exactly one of the options is present, which determines the type of the field
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Field {
+    /// The ID of the field
+    pub id:                 String,
+
+    /// The basic properties of the field
+    pub properties:         FieldProperties,
+
+    /// Present if the field is a text field
+    pub text_options:       Option<TextOptions>,
+
+    /// Present if the field is an integer field
+    pub integer_options:    Option<serde_json::Value>,
+
+    /// Present if the field is a date field
+    pub date_options:       Option<serde_json::Value>,
+
+    /// Present if the field is a user field
+    pub user_options:       Option<serde_json::Value>,
+
+    /// Present if the field is a selection field
+    pub selection_options:  Option<SelectionOptions>
+}
+
+/// Struct describing the basic properties of a field
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldProperties {
+    /// The name of the field shown to users
+    pub display_name:   String
+}
+
+/// Struct describing the options of a text field
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TextOptions {
+    /// The minimum number of characters of a value, if limited
+    pub min_length: Option<usize>,
+
+    /// The maximum number of characters of a value, if limited
+    pub max_length: Option<usize>
+}
+
+/// Struct describing the options of a selection field
+#[derive(Deserialize, Debug)]
+pub struct SelectionOptions {
+    /// The choices of the field
+    #[serde(default)]
+    pub choices:    Vec<Choice>
+}
+
+/// Struct describing a choice of a selection field
+#[derive(Deserialize, Debug)]
+pub struct Choice {
+    /// The ID of the choice
+    pub id:         String,
+
+    /// The basic properties of the choice
+    pub properties: ChoiceProperties
+}
+
+/// Struct describing the basic properties of a choice
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ChoiceProperties {
+    /// The name of the choice shown to users
+    pub display_name:   String
+}
+
+/// Get the definition of a label, including its fields
+///
+/// ## Params
+/// - `env` Env instance
+/// - `id` The ID of the label
+///
+/// ## Errors
+/// - Request failure
+/// - Google API error, e.g. when GSync was logged in before it could read labels
+pub fn get_label(env: &Env, id: &str) -> Result<Label> {
+    let access_token = get_access_token(env)?;
+    let uri = format!("https://drivelabels.googleapis.com/v2/labels/{}?view=LABEL_VIEW_FULL", id);
+    let response = unwrap_req_err!(crate::api::client().get(&uri)
+        .header("Authorization", &format!("Bearer {}", access_token))
+        .send());
+
+    let payload: GoogleResponse<Label> = unwrap_req_err!(response.json());
+    let label = unwrap_google_err!(payload);
+
+    Ok(label)
+}
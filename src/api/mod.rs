@@ -1,9 +1,63 @@
 //! Common Google API types
 
+pub mod activity;
+pub mod batch;
 pub mod drive;
+pub mod labels;
 pub mod oauth;
+pub mod retry;
 
+use crate::{Result, Error};
 use serde::Deserialize;
+use lazy_static::lazy_static;
+use std::future::Future;
+
+lazy_static! {
+    /// The blocking HTTP client shared by the requests to the OAuth2, labels and activity APIs, so connections and TLS sessions are reused across requests
+    static ref CLIENT: reqwest::blocking::Client = reqwest::blocking::Client::new();
+
+    /// The HTTP client shared by all requests to the Drive API
+    static ref ASYNC_CLIENT: reqwest::Client = reqwest::Client::new();
+
+    /// The runtime the requests to the Drive API run on. There is a single runtime, since the connections of the client belong to the runtime they were opened on
+    static ref RUNTIME: tokio::runtime::Runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .thread_name("gsync-runtime")
+        .build()
+        .expect("Failed to start the async runtime");
+}
+
+/// The blocking HTTP client to send requests to Google with
+pub fn client() -> &'static reqwest::blocking::Client {
+    &CLIENT
+}
+
+/// The HTTP client to send requests to the Drive API with
+pub fn async_client() -> &'static reqwest::Client {
+    &ASYNC_CLIENT
+}
+
+/// Run a future on the shared runtime, and block until it is done.
+/// When called from within the runtime, the worker thread hands its other tasks off while it blocks
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    match tokio::runtime::Handle::try_current() {
+        Ok(_) => tokio::task::block_in_place(|| RUNTIME.block_on(future)),
+        Err(_) => RUNTIME.block_on(future)
+    }
+}
+
+/// Run blocking work, e.g. a database operation or reading a file, on the blocking thread pool of the runtime, so it doesn't hold up requests in progress
+///
+/// ## Errors
+/// - When the work fails
+/// - When the work panics
+pub async fn unblock<T, F>(work: F) -> Result<T>
+where T: Send + 'static, F: FnOnce() -> Result<T> + Send + 'static {
+    match tokio::task::spawn_blocking(work).await {
+        Ok(result) => result,
+        Err(e) => Err((Error::Other(format!("Blocking work failed: {}", e)), line!(), file!()))
+    }
+}
 
 /// Struct describing a generic response from a Google API
 #[derive(Deserialize, Debug)]
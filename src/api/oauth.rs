@@ -1,11 +1,26 @@
 //! Google OAuth2 API
 
-use crate::env::Env;
+use crate::env::{Env, CachedToken, ServiceAccount};
 use serde::{Deserialize, Serialize};
+use rusqlite::{named_params, Connection, OptionalExtension, TransactionBehavior};
+use std::time::Duration;
 
-use crate::{Result, unwrap_req_err, unwrap_db_err, unwrap_google_err};
+use crate::{Result, Error, unwrap_req_err, unwrap_db_err, unwrap_google_err, unwrap_other_err};
 use crate::api::GoogleResponse;
 
+/// How long to wait for another process to finish refreshing the access token
+const REFRESH_LOCK_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The OAuth2 scopes GSync requests, separated by spaces. Reading the activity in Drive is needed to report who changed files remotely,
+/// and the application data folder holds the backup of GSync's own state
+const SCOPE: &str = "https://www.googleapis.com/auth/drive https://www.googleapis.com/auth/drive.activity.readonly https://www.googleapis.com/auth/drive.appdata";
+
+/// The OAuth2 scope needed to apply Drive labels to files. It is only requested when labels are configured
+const LABELS_SCOPE: &str = "https://www.googleapis.com/auth/drive.labels.readonly";
+
+/// The lifetime of an assertion used to get an access token for a service account, in seconds. Google allows at most one hour
+const ASSERTION_LIFETIME: i64 = 3600;
+
 /// Login Data
 pub struct LoginData {
     /// Refresh token
@@ -66,7 +81,10 @@ struct AuthenticationRequest<'a> {
     response_type:          &'static str,
 
     /// The scopes requested
-    scope:                  &'static str,
+    scope:                  &'a str,
+
+    /// Whether the scopes granted earlier are kept, so a scope can be added without losing the others
+    include_granted_scopes: bool,
 
     /// The challenge halve of the code challenge
     code_challenge:         &'a str,
@@ -105,16 +123,26 @@ struct RefreshTokenResponse {
     expires_in:     i64,
 }
 
+/// The scopes to request, separated by spaces
+fn scopes(env: &Env) -> String {
+    if env.labels {
+        format!("{} {}", SCOPE, LABELS_SCOPE)
+    } else {
+        SCOPE.to_string()
+    }
+}
+
 /// Create an authentication URL used for step 1 in the OAuth2 flow
 pub fn create_authentication_uri(env: &Env, code_challenge: &str, state: &str, redirect_uri: &str) -> String {
     let auth_request = AuthenticationRequest {
         client_id:              &env.client_id,
         redirect_uri,
         response_type:          "code",
-        scope:                  "https://www.googleapis.com/auth/drive",
-        code_challenge:         &code_challenge,
+        scope:                  &scopes(env),
+        include_granted_scopes: true,
+        code_challenge,
         code_challenge_method:  "S256",
-        state:                  &state
+        state
     };
 
     let qstring = serde_qs::to_string(&auth_request).unwrap();
@@ -140,13 +168,15 @@ pub fn exchange_access_token(env: &Env, access_token: &str, code_verifier: &str,
     };
 
     // Send a request to Google to exchange the code for the necessary codes
-    let response = unwrap_req_err!(reqwest::blocking::Client::new().post("https://oauth2.googleapis.com/token")
+    let response = unwrap_req_err!(crate::api::client().post("https://oauth2.googleapis.com/token")
         .body(serde_json::to_string(&exchange_request).unwrap())
         .send());
 
     // Deserialize from JSON
     let exchange_response: GoogleResponse<ExchangeAccessTokenResponse> = unwrap_req_err!(response.json());
     let token_response = unwrap_google_err!(exchange_response);
+    crate::redact::register(&token_response.access_token);
+    crate::redact::register(&token_response.refresh_token);
 
     Ok(LoginData {
         access_token: token_response.access_token,
@@ -155,39 +185,204 @@ pub fn exchange_access_token(env: &Env, access_token: &str, code_verifier: &str,
     })
 }
 
-/// Get an access token
+/// Get an access token. The token is cached in the Env, the database is only consulted when the cached token is about to expire.
+/// When the token has to be refreshed, the database is locked while doing so, so concurrent GSync processes don't refresh the token at the same time
 ///
 /// ## Errors
 /// - When a database error occurs
 /// - When the Google API returns an error
 /// - When reqwest returns an error
 pub fn get_access_token(env: &Env) -> Result<String> {
-    let conn = unwrap_db_err!(env.get_conn());
-    let mut stmt = unwrap_db_err!(conn.prepare("SELECT access_token, refresh_token, expiry FROM user"));
-    let mut result = unwrap_db_err!(stmt.query(rusqlite::named_params! {}));
-
-    if let Ok(Some(row)) = result.next() {
-        let access_token = unwrap_db_err!(row.get::<&str, String>("access_token"));
-        let refresh_token = unwrap_db_err!(row.get::<&str, String>("refresh_token"));
-        let expiry = unwrap_db_err!(row.get::<&str, i64>("expiry"));
-
-        if chrono::Utc::now().timestamp() > (expiry - 60) {
-            // We need to manually drop these to avoid having two open connections at the same time
-            // Since sqlite won't allow that
-            drop(result);
-            drop(stmt);
-            drop(conn);
-            let new_token = refresh_access_token(env, &refresh_token)?;
-            crate::login::db::save_to_database(&new_token, env)?;
-
-            return Ok(new_token.access_token);
+    // Holding the lock for the rest of this function makes sure the token is only refreshed once when it is requested concurrently
+    let mut cache = unwrap_other_err!(env.token_cache.lock());
+    if let Some(cached) = cache.as_ref() {
+        if !is_expiring(cached.expiry) {
+            return Ok(cached.access_token.clone());
         }
+    }
 
-        return Ok(access_token)
+    if let Some(service_account) = &env.service_account {
+        let token = get_service_account_token(env, service_account)?;
+        let expiry = chrono::Utc::now().timestamp() + token.expires_in;
+        *cache = Some(CachedToken { access_token: token.access_token.clone(), expiry });
+        return Ok(token.access_token);
     }
 
-    Ok(String::default())
+    let mut conn = unwrap_db_err!(env.get_conn());
+    unwrap_db_err!(conn.busy_timeout(REFRESH_LOCK_TIMEOUT));
+
+    let (access_token, _, expiry) = match get_stored_token(&conn)? {
+        Some(token) => token,
+        None => return Ok(String::default())
+    };
+
+    if !is_expiring(expiry) {
+        *cache = Some(CachedToken { access_token: access_token.clone(), expiry });
+        return Ok(access_token);
+    }
+
+    // An immediate transaction takes the database's write lock, so only one process can refresh the token at a time.
+    // Other processes wait here, and will find the token refreshed when they get the lock
+    let tx = unwrap_db_err!(conn.transaction_with_behavior(TransactionBehavior::Immediate));
+    let (access_token, refresh_token, expiry) = match get_stored_token(&tx)? {
+        Some(token) => token,
+        None => return Ok(String::default())
+    };
+
+    if !is_expiring(expiry) {
+        unwrap_db_err!(tx.commit());
+        *cache = Some(CachedToken { access_token: access_token.clone(), expiry });
+        return Ok(access_token);
+    }
+
+    let new_token = refresh_access_token(env, &refresh_token)?;
+    let expiry = chrono::Utc::now().timestamp() + new_token.expires_in;
+
+    // Only update the token belonging to the refresh token we used, in case the user logged in again in the meantime
+    unwrap_db_err!(tx.execute("UPDATE user SET access_token = :access_token, expiry = :expiry WHERE refresh_token = :refresh_token", named_params! {
+        ":access_token": &new_token.access_token,
+        ":expiry": expiry,
+        ":refresh_token": &refresh_token
+    }));
+    unwrap_db_err!(tx.commit());
 
+    *cache = Some(CachedToken { access_token: new_token.access_token.clone(), expiry });
+    Ok(new_token.access_token)
+}
+
+/// Get an access token from async code, like [get_access_token] does.
+/// Only when the cached token is about to expire, the token is refreshed on the blocking thread pool
+///
+/// ## Errors
+/// - When a database error occurs
+/// - When the Google API returns an error
+/// - When reqwest returns an error
+pub async fn access_token(env: &Env) -> Result<String> {
+    if let Some(cached) = unwrap_other_err!(env.token_cache.lock()).as_ref() {
+        if !is_expiring(cached.expiry) {
+            return Ok(cached.access_token.clone());
+        }
+    }
+
+    let env = env.clone();
+    crate::api::unblock(move || get_access_token(&env)).await
+}
+
+/// Check if an access token with the provided expiry is about to expire
+fn is_expiring(expiry: i64) -> bool {
+    chrono::Utc::now().timestamp() > (expiry - 60)
+}
+
+/// Get the access token, refresh token and expiry stored in the database, if the user is logged in
+///
+/// ## Errors
+/// - When a database error occurs
+fn get_stored_token(conn: &Connection) -> Result<Option<(String, String, i64)>> {
+    let token = unwrap_db_err!(conn.query_row("SELECT access_token, refresh_token, expiry FROM user", named_params! {}, |row| {
+        Ok((row.get::<&str, String>("access_token")?, row.get::<&str, String>("refresh_token")?, row.get::<&str, i64>("expiry")?))
+    }).optional());
+
+    if let Some((access_token, refresh_token, _)) = &token {
+        crate::redact::register(access_token);
+        crate::redact::register(refresh_token);
+    }
+
+    Ok(token)
+}
+
+/// Struct describing the JSON key of a service account
+#[derive(Deserialize)]
+struct ServiceAccountKey {
+    /// The email address of the service account
+    client_email:   String,
+
+    /// The PEM encoded RSA private key of the service account
+    private_key:    String,
+
+    /// The URI to request access tokens from
+    token_uri:      String
+}
+
+/// Struct describing the claims of the assertion used to get an access token for a service account
+#[derive(Serialize)]
+struct AssertionClaims<'a> {
+    /// The email address of the service account
+    iss:    &'a str,
+
+    /// The email address of the user to impersonate
+    sub:    &'a str,
+
+    /// The scopes requested
+    scope:  &'a str,
+
+    /// The URI the assertion is intended for
+    aud:    &'a str,
+
+    /// The epoch time in seconds at which the assertion was issued
+    iat:    i64,
+
+    /// The epoch time in seconds at which the assertion expires
+    exp:    i64
+}
+
+/// Struct describing the request to exchange an assertion for an access token
+#[derive(Serialize)]
+struct AssertionTokenRequest<'a> {
+    /// The type of grant
+    grant_type: &'static str,
+
+    /// The signed assertion
+    assertion:  &'a str
+}
+
+/// Get an access token for a service account with domain-wide delegation, impersonating a user.
+/// This does not require any interaction from the user, nor does it store anything in the database
+///
+/// ## Errors
+/// - When the key of the service account cannot be read or is invalid
+/// - When signing the assertion fails
+/// - When Google refuses to issue an access token, e.g. because domain-wide delegation is not set up
+/// - When reqwest returns an error
+fn get_service_account_token(env: &Env, service_account: &ServiceAccount) -> Result<LoginData> {
+    let key_contents = unwrap_other_err!(std::fs::read_to_string(&service_account.key_file));
+    let key: ServiceAccountKey = unwrap_other_err!(serde_json::from_str(&key_contents));
+
+    let now = chrono::Utc::now().timestamp();
+    let claims = AssertionClaims {
+        iss:    &key.client_email,
+        sub:    &service_account.subject,
+        scope:  &scopes(env),
+        aud:    &key.token_uri,
+        iat:    now,
+        exp:    now + ASSERTION_LIFETIME
+    };
+
+    let encoding_key = unwrap_other_err!(jsonwebtoken::EncodingKey::from_rsa_pem(key.private_key.as_bytes()));
+    let assertion = unwrap_other_err!(jsonwebtoken::encode(&jsonwebtoken::Header::new(jsonwebtoken::Algorithm::RS256), &claims, &encoding_key));
+
+    let request_body = AssertionTokenRequest {
+        grant_type: "urn:ietf:params:oauth:grant-type:jwt-bearer",
+        assertion:  &assertion
+    };
+
+    let response = unwrap_req_err!(crate::api::client().post(&key.token_uri)
+        .form(&request_body)
+        .send());
+
+    // The token endpoint returns errors in the OAuth2 format, rather than the format used by the other Google APIs
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = unwrap_req_err!(response.text());
+        return Err((Error::Other(format!("Failed to get an access token impersonating '{}' ({}): {}", &service_account.subject, status, crate::redact::redact(body))), line!(), file!()));
+    }
+
+    let payload: RefreshTokenResponse = unwrap_req_err!(response.json());
+    crate::redact::register(&payload.access_token);
+    Ok(LoginData {
+        access_token:   payload.access_token,
+        expires_in:     payload.expires_in,
+        refresh_token:  None
+    })
 }
 
 /// Refresh an OAuth2 access token using a refresh token
@@ -205,12 +400,13 @@ fn refresh_access_token(env: &Env, refresh_token: &str) -> Result<LoginData> {
 
     //Safe to unwrap() because we know the struct can be translated to valid json
     let body = serde_json::to_string(&request_body).unwrap();
-    let request = unwrap_req_err!(reqwest::blocking::Client::new().post("https://oauth2.googleapis.com/token")
+    let request = unwrap_req_err!(crate::api::client().post("https://oauth2.googleapis.com/token")
         .body(body)
         .send());
 
     let response_payload: GoogleResponse<RefreshTokenResponse> = unwrap_req_err!(request.json());
     let payload = unwrap_google_err!(response_payload);
+    crate::redact::register(&payload.access_token);
 
     Ok(LoginData {
         access_token: payload.access_token,
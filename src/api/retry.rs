@@ -0,0 +1,200 @@
+//! Module for retrying requests to Google which are rate limited or fail temporarily. Requests are retried with an exponential backoff with jitter,
+//! or after the time Google asks for with a `Retry-After` header
+
+use crate::env::Env;
+use crate::{Result, Error, unwrap_req_err, unwrap_other_err};
+use crate::api::GoogleResponse;
+use rand::Rng;
+use reqwest::StatusCode;
+use reqwest::{RequestBuilder, Response};
+use std::time::Duration;
+
+/// The number of times a request is retried when none is configured
+pub const DEFAULT_MAX_RETRIES: u32 = 5;
+
+/// The delay before the first retry, doubled for every following retry
+const BASE_DELAY: Duration = Duration::from_secs(1);
+
+/// The maximum delay between two attempts of the exponential backoff
+const MAX_DELAY: Duration = Duration::from_secs(64);
+
+/// The maximum delay honored when Google asks for one with `Retry-After`, e.g. when a quota is exhausted.
+/// When Google asks to wait longer the request fails right away, rather than holding up the run
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(15 * 60);
+
+/// Send a request, retrying it when Google rate limits it or is temporarily unavailable, up to the configured number of retries.
+/// Requests with a streaming body can't be sent again and are not retried, send them with [send_with] instead.
+/// A response with an error status is turned into an error, so the caller only receives successful responses
+///
+/// ## Errors
+/// - When the request fails
+/// - When Google returns an error, and the request can't or may no longer be retried
+pub async fn send(env: &Env, request: RequestBuilder) -> Result<Response> {
+    match request.try_clone() {
+        Some(_) => send_attempts(env.max_retries, || match request.try_clone() {
+            Some(request) => Ok(request),
+            None => Err((Error::Other("The request can't be sent again".to_string()), line!(), file!()))
+        }).await,
+        None => {
+            let mut request = Some(request);
+            send_attempts(0, || match request.take() {
+                Some(request) => Ok(request),
+                None => Err((Error::Other("The request can't be sent again".to_string()), line!(), file!()))
+            }).await
+        }
+    }
+}
+
+/// Send a request built by `build`, retrying it like [send]. The request is built again for every attempt,
+/// so requests with a streaming body, e.g. uploads reading a file, are retried as well
+///
+/// ## Errors
+/// - When building the request fails
+/// - When the request fails
+/// - When Google returns an error, and the request may no longer be retried
+pub async fn send_with<F>(env: &Env, build: F) -> Result<Response>
+where F: FnMut() -> Result<RequestBuilder> {
+    send_attempts(env.max_retries, build).await
+}
+
+/// Send a request built by `build` up to `retries` times again, see [send]
+///
+/// ## Errors
+/// - When building the request fails
+/// - When the request fails
+/// - When Google returns an error, and the request may no longer be retried
+async fn send_attempts<F>(retries: u32, mut build: F) -> Result<Response>
+where F: FnMut() -> Result<RequestBuilder> {
+    let mut attempt = 0;
+    loop {
+        let response = match build()?.send().await {
+            Ok(response) => response,
+            Err(e) if attempt < retries && (e.is_timeout() || e.is_connect()) => {
+                attempt += 1;
+                println!("Warning: Request to Google failed, retrying ({}/{}): {}", attempt, retries, e);
+                tokio::time::sleep(delay(attempt, None, jitter())).await;
+                continue;
+            },
+            Err(e) => return Err((Error::RequestError(e), line!(), file!()))
+        };
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+
+        let retry_after = response.headers().get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<u64>().ok())
+            .map(Duration::from_secs);
+
+        let text = unwrap_req_err!(response.text().await);
+        let error = match serde_json::from_str::<GoogleResponse<()>>(&text) {
+            Ok(GoogleResponse { error: Some(error), .. }) => Error::GoogleError(error),
+            _ => Error::Other(format!("Google responded with {}: {}", status, text))
+        };
+
+        let retryable = status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error() || crate::concurrency::is_throttled(&error);
+        if let Some(retry_after) = retry_after.filter(|retry_after| retryable && *retry_after > MAX_RETRY_AFTER) {
+            let retry_at = chrono::Utc::now() + unwrap_other_err!(chrono::Duration::from_std(retry_after));
+            println!("Warning: Google responded with {} and asks to wait {} seconds, not retrying. Try again after {}", status, retry_after.as_secs(), retry_at.format("%Y-%m-%d %H:%M:%S UTC"));
+            return Err((error, line!(), file!()));
+        }
+
+        if !retryable || attempt >= retries {
+            return Err((error, line!(), file!()));
+        }
+
+        attempt += 1;
+        let delay = delay(attempt, retry_after, jitter());
+        println!("Warning: Google responded with {}, retrying in {} seconds ({}/{})", status, delay.as_secs(), attempt, retries);
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// The delay before a retry. Google's `Retry-After` is honored if present, up to [MAX_RETRY_AFTER], otherwise the delay doubles with every attempt.
+/// The jitter is added so concurrent requests which were throttled together are not retried together
+///
+/// ## Params
+/// - `attempt` The number of the retry, starting at 1
+/// - `retry_after` The delay Google asked for, if any
+/// - `jitter` A random delay to add
+fn delay(attempt: u32, retry_after: Option<Duration>, jitter: Duration) -> Duration {
+    match retry_after {
+        Some(retry_after) => retry_after.min(MAX_RETRY_AFTER) + jitter,
+        None => (BASE_DELAY * 2u32.saturating_pow(attempt.saturating_sub(1)).min(MAX_DELAY.as_secs() as u32)).min(MAX_DELAY) + jitter
+    }
+}
+
+/// A random delay of up to a second
+fn jitter() -> Duration {
+    Duration::from_millis(rand::thread_rng().gen_range(0..1000))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::api::retry::{delay, send_with};
+    use crate::env::Env;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[test]
+    fn exponential_backoff() {
+        let jitter = Duration::from_millis(250);
+        assert_eq!(Duration::from_millis(1250), delay(1, None, jitter));
+        assert_eq!(Duration::from_millis(4250), delay(3, None, jitter));
+        assert_eq!(Duration::from_millis(64250), delay(12, None, jitter));
+        assert_eq!(Duration::from_millis(64250), delay(40, None, jitter));
+    }
+
+    #[test]
+    fn honors_retry_after() {
+        assert_eq!(Duration::from_secs(30), delay(1, Some(Duration::from_secs(30)), Duration::from_secs(0)));
+        assert_eq!(Duration::from_secs(600), delay(1, Some(Duration::from_secs(600)), Duration::from_secs(0)));
+        assert_eq!(Duration::from_secs(900), delay(1, Some(Duration::from_secs(3600)), Duration::from_secs(0)));
+    }
+
+    #[test]
+    fn retries_throttled_uploads() {
+        let env = Env::empty();
+        let builds = AtomicUsize::new(0);
+        let status = crate::api::block_on(async {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let uri = format!("http://{}/upload", listener.local_addr().unwrap());
+
+            // Google throttles the first attempt, and accepts the second
+            let server = tokio::spawn(async move {
+                for response in ["429 Too Many Requests\r\nRetry-After: 0", "200 OK"] {
+                    let (mut stream, _) = listener.accept().await.unwrap();
+                    // The body is sent chunked, the request is read up to the last chunk so the connection isn't reset
+                    let mut request = Vec::new();
+                    let mut buffer = vec![0; 64 * 1024];
+                    while !request.ends_with(b"0\r\n\r\n") {
+                        let read = stream.read(&mut buffer).await.unwrap();
+                        if read == 0 {
+                            break;
+                        }
+
+                        request.extend_from_slice(&buffer[..read]);
+                    }
+
+                    stream.write_all(format!("HTTP/1.1 {}\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{{}}", response).as_bytes()).await.unwrap();
+                }
+            });
+
+            // A streaming body, like the content of an upload, can't be cloned, so it is built again
+            let response = send_with(&env, || {
+                builds.fetch_add(1, Ordering::SeqCst);
+                let body = reqwest::Body::wrap_stream(futures::stream::iter(vec![Ok::<_, std::io::Error>(b"content".to_vec())]));
+                Ok(crate::api::async_client().post(&uri).body(body))
+            }).await;
+
+            server.await.unwrap();
+            response.map(|response| response.status())
+        });
+
+        assert_eq!(reqwest::StatusCode::OK, status.unwrap());
+        assert_eq!(2, builds.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,304 @@
+//! Module for the audit log of remote operations. Every operation which changes something in Google Drive is recorded in the append-only `operations` table,
+//! and optionally in a log file, so unexpected changes in Drive can be traced back to the run that made them
+
+use crate::env::Env;
+use crate::{Result, unwrap_db_err, unwrap_other_err};
+use chrono::Utc;
+use rusqlite::named_params;
+use std::fmt;
+use std::io::Write;
+use std::sync::PoisonError;
+
+/// Enum describing an operation which changes something in Google Drive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    /// A file, folder or shortcut was created
+    Create,
+
+    /// The content of a file was updated
+    Update,
+
+    /// A file or folder was moved to the trash
+    Trash,
+
+    /// A file or folder was restored from the trash
+    Untrash,
+
+    /// A file or folder was permanently deleted
+    Delete,
+
+    /// A permission of a file was added or removed
+    Permission,
+
+    /// The labels of a file were changed
+    Label,
+
+    /// A revision of a file was deleted
+    Revision,
+
+    /// A file or folder removed locally was marked stale instead of being deleted
+    Archive,
+
+    /// A file or folder marked stale was marked current again
+    Unarchive
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Create => "create",
+            Self::Update => "update",
+            Self::Trash => "trash",
+            Self::Untrash => "untrash",
+            Self::Delete => "delete",
+            Self::Permission => "permission",
+            Self::Label => "label",
+            Self::Revision => "revision",
+            Self::Archive => "archive",
+            Self::Unarchive => "unarchive"
+        };
+
+        f.pad(name)
+    }
+}
+
+/// Struct describing an operation in the audit log
+#[derive(Debug)]
+pub struct OperationRecord {
+    /// The epoch time in seconds at which the operation was performed
+    pub time:       i64,
+
+    /// The epoch time in seconds at which the run performing the operation started, if it was performed by a sync
+    pub run:        Option<i64>,
+
+    /// The operation
+    pub operation:  String,
+
+    /// The local path, or the name in Drive, of the file, if known
+    pub path:       Option<String>,
+
+    /// The ID of the file in Drive, if known
+    pub file_id:    Option<String>,
+
+    /// The ID of the revision the operation created, for updates
+    pub revision_id: Option<String>,
+
+    /// 'ok', or the error if the operation failed
+    pub result:     String
+}
+
+/// Record an operation in the audit log. Failing to record it is reported as a warning, it doesn't fail the operation
+///
+/// ## Params
+/// - `env` Env instance
+/// - `operation` The operation performed
+/// - `path` The local path, or the name in Drive, of the file, if known
+/// - `file_id` The ID of the file in Drive, if known
+/// - `result` The result of the operation
+pub fn record<T>(env: &Env, operation: Operation, path: Option<&str>, file_id: Option<&str>, result: &Result<T>) {
+    record_revision(env, operation, path, file_id, None, result)
+}
+
+/// Record an operation which created a revision of a file in the audit log, so undoing it deletes only that revision. See [record]
+///
+/// ## Params
+/// - `revision_id` The ID of the revision the operation created, if known
+pub fn record_revision<T>(env: &Env, operation: Operation, path: Option<&str>, file_id: Option<&str>, revision_id: Option<&str>, result: &Result<T>) {
+    let record = OperationRecord {
+        time:       Utc::now().timestamp(),
+        run:        env.run,
+        operation:  operation.to_string(),
+        path:       path.map(String::from),
+        file_id:    file_id.map(String::from),
+        revision_id: revision_id.map(String::from),
+        result:     match result {
+            Ok(_) => "ok".to_string(),
+            Err((e, _, _)) => crate::redact::redact(format!("{:?}", e))
+        }
+    };
+
+    if let Err((e, _, _)) = insert(env, &record) {
+        println!("Warning: Failed to record a remote operation in the audit log: {}", crate::redact::redact(format!("{:?}", e)));
+    }
+
+    if let Some(log) = &env.audit_log {
+        if let Err((e, _, _)) = append_to_log(log, &record) {
+            println!("Warning: Failed to write to the audit log '{}': {}", log, crate::redact::redact(format!("{:?}", e)));
+        }
+    }
+}
+
+/// Insert an operation into the `operations` table. Concurrent uploads record their operations through the same connection,
+/// so they wait for each other instead of failing on a locked database
+///
+/// ## Errors
+/// - When a database operation fails
+fn insert(env: &Env, record: &OperationRecord) -> Result<()> {
+    let mut shared = env.audit_conn.lock().unwrap_or_else(PoisonError::into_inner);
+    if shared.is_none() {
+        *shared = Some(unwrap_db_err!(env.get_conn()));
+    }
+
+    // Unwrap is safe because the connection was opened above
+    let conn = shared.as_ref().unwrap();
+
+    unwrap_db_err!(conn.execute("INSERT INTO operations (time, run, operation, path, file_id, revision_id, result) VALUES (:time, :run, :operation, :path, :file_id, :revision_id, :result)", named_params! {
+        ":time": record.time,
+        ":run": record.run,
+        ":operation": &record.operation,
+        ":path": &record.path,
+        ":file_id": &record.file_id,
+        ":revision_id": &record.revision_id,
+        ":result": &record.result
+    }));
+
+    Ok(())
+}
+
+/// Append an operation to the audit log file as a tab separated line. The file is created accessible by its owner only
+///
+/// ## Errors
+/// - When the log file can't be opened or written to
+fn append_to_log(log: &str, record: &OperationRecord) -> Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.create(true).append(true);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = unwrap_other_err!(options.open(log));
+    unwrap_other_err!(writeln!(file, "{}", format_line(record)));
+    Ok(())
+}
+
+/// Format an operation as a tab separated line: time, run, operation, path, file ID and result
+pub fn format_line(record: &OperationRecord) -> String {
+    let time = |time: i64| chrono::NaiveDateTime::from_timestamp(time, 0).format("%Y-%m-%dT%H:%M:%SZ").to_string();
+    format!("{}\t{}\t{}\t{}\t{}\t{}",
+        time(record.time),
+        record.run.map(time).unwrap_or_else(|| "-".to_string()),
+        record.operation,
+        record.path.as_deref().unwrap_or("-"),
+        record.file_id.as_deref().unwrap_or("-"),
+        record.result)
+}
+
+/// Get the most recent operations from the audit log, newest first
+///
+/// ## Params
+/// - `limit` The maximum number of operations to return
+///
+/// ## Errors
+/// - When a database operation fails
+pub fn recent_operations(env: &Env, limit: u32) -> Result<Vec<OperationRecord>> {
+    let conn = unwrap_db_err!(env.get_conn());
+    let mut stmt = unwrap_db_err!(conn.prepare("SELECT time, run, operation, path, file_id, revision_id, result FROM operations ORDER BY id DESC LIMIT :limit"));
+    let mut result = unwrap_db_err!(stmt.query(named_params! {
+        ":limit": limit
+    }));
+
+    let mut operations = Vec::new();
+    while let Some(row) = unwrap_db_err!(result.next()) {
+        operations.push(read_row(row)?);
+    }
+
+    Ok(operations)
+}
+
+/// Get the most recent run which performed remote operations
+///
+/// ## Errors
+/// - When a database operation fails
+pub fn last_run(env: &Env) -> Result<Option<i64>> {
+    let conn = unwrap_db_err!(env.get_conn());
+    let run = unwrap_db_err!(conn.query_row("SELECT MAX(run) AS run FROM operations", named_params! {}, |row| row.get::<&str, Option<i64>>("run")));
+    Ok(run)
+}
+
+/// Get the operations a run performed, oldest first
+///
+/// ## Params
+/// - `run` The epoch time in seconds at which the run started
+///
+/// ## Errors
+/// - When a database operation fails
+pub fn operations_of_run(env: &Env, run: i64) -> Result<Vec<OperationRecord>> {
+    let conn = unwrap_db_err!(env.get_conn());
+    let mut stmt = unwrap_db_err!(conn.prepare("SELECT time, run, operation, path, file_id, revision_id, result FROM operations WHERE run = :run ORDER BY id ASC"));
+    let mut result = unwrap_db_err!(stmt.query(named_params! {
+        ":run": run
+    }));
+
+    let mut operations = Vec::new();
+    while let Some(row) = unwrap_db_err!(result.next()) {
+        operations.push(read_row(row)?);
+    }
+
+    Ok(operations)
+}
+
+/// Read an OperationRecord from a row selected from the operations table
+///
+/// ## Errors
+/// - When a column can't be read
+fn read_row(row: &rusqlite::Row<'_>) -> Result<OperationRecord> {
+    Ok(OperationRecord {
+        time:       unwrap_db_err!(row.get::<&str, i64>("time")),
+        run:        unwrap_db_err!(row.get::<&str, Option<i64>>("run")),
+        operation:  unwrap_db_err!(row.get::<&str, String>("operation")),
+        path:       unwrap_db_err!(row.get::<&str, Option<String>>("path")),
+        file_id:    unwrap_db_err!(row.get::<&str, Option<String>>("file_id")),
+        revision_id: unwrap_db_err!(row.get::<&str, Option<String>>("revision_id")),
+        result:     unwrap_db_err!(row.get::<&str, String>("result"))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::audit::{append_to_log, format_line, OperationRecord, Operation};
+
+    #[test]
+    fn format_line_without_run() {
+        let record = OperationRecord {
+            time:       0,
+            run:        None,
+            operation:  Operation::Delete.to_string(),
+            path:       None,
+            file_id:    Some("abc".to_string()),
+            revision_id: None,
+            result:     "ok".to_string()
+        };
+
+        assert_eq!("1970-01-01T00:00:00Z\t-\tdelete\t-\tabc\tok", format_line(&record));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn creates_private_log() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("gsync-audit-log-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let log = dir.join("audit.log");
+        let record = OperationRecord {
+            time:       0,
+            run:        None,
+            operation:  Operation::Create.to_string(),
+            path:       None,
+            file_id:    None,
+            revision_id: None,
+            result:     "ok".to_string()
+        };
+
+        append_to_log(log.to_str().unwrap(), &record).unwrap();
+        let mode = std::fs::metadata(&log).unwrap().permissions().mode() & 0o777;
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert_eq!(0o600, mode);
+    }
+}
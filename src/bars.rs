@@ -0,0 +1,143 @@
+//! Module for showing the progress of a sync as progress bars: one over the whole plan, with the bytes uploaded, the speed and the ETA,
+//! and one per upload in progress. The bars are drawn on standard error, so the log on standard output can be redirected to a file
+
+use crate::progress::{self, Transfer};
+use crate::report::{Progress, RunReport};
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// How often the bars are updated
+const TICK: Duration = Duration::from_millis(200);
+
+/// The template of the bar over the whole plan
+const OVERALL_TEMPLATE: &str = "{prefix} [{bar:40}] {bytes}/{total_bytes} {bytes_per_sec} eta {eta}";
+
+/// The template of the bar of an upload in progress
+const TRANSFER_TEMPLATE: &str = "  {wide_msg} [{bar:30}] {bytes}/{total_bytes} {bytes_per_sec} eta {eta}";
+
+/// Run `work` while showing the progress of the sync recorded in `report` as progress bars. The bars are removed once `work` returns.
+/// The bar over the whole plan is shown once the totals of the plan are recorded in the report
+///
+/// ## Params
+/// - `report` The report of the sync
+/// - `work` The sync
+pub fn show<T, F: FnOnce() -> T>(report: &RunReport, work: F) -> T {
+    let done = AtomicBool::new(false);
+    std::thread::scope(|scope| {
+        scope.spawn(|| draw(report, &done));
+        let result = work();
+        done.store(true, Ordering::Relaxed);
+        result
+    })
+}
+
+/// Update the bars every tick, until `done` is set
+///
+/// ## Params
+/// - `report` The report of the sync
+/// - `done` Set once the sync is finished
+fn draw(report: &RunReport, done: &AtomicBool) {
+    let multi = MultiProgress::new();
+    let mut total: Option<ProgressBar> = None;
+    let mut transfers: HashMap<u64, ProgressBar> = HashMap::new();
+
+    while !done.load(Ordering::Relaxed) {
+        let snapshot = report.snapshot();
+        let active = progress::active();
+
+        if total.is_none() && snapshot.planned_files > 0 {
+            // Inserted at the top, so it stays above the uploads which may already be in progress
+            let bar = multi.insert(0, ProgressBar::new(snapshot.planned_bytes));
+            bar.set_style(ProgressStyle::with_template(OVERALL_TEMPLATE).unwrap().progress_chars("=> "));
+            total = Some(bar);
+        }
+
+        if let Some(bar) = &total {
+            let (position, length, prefix) = overall(&snapshot, &active);
+            bar.set_length(length);
+            bar.set_position(position);
+            bar.set_prefix(prefix);
+        }
+
+        // Bars of finished uploads are removed, bars of new uploads are added at the bottom
+        transfers.retain(|id, bar| {
+            let running = active.iter().any(|transfer| transfer.id == *id);
+            if !running {
+                bar.finish_and_clear();
+                multi.remove(bar);
+            }
+
+            running
+        });
+
+        for transfer in active.iter() {
+            let bar = transfers.entry(transfer.id).or_insert_with(|| {
+                let bar = multi.add(ProgressBar::new(transfer.total));
+                bar.set_style(ProgressStyle::with_template(TRANSFER_TEMPLATE).unwrap().progress_chars("=> "));
+                bar.set_message(file_name(&transfer.path));
+                bar
+            });
+
+            bar.set_position(transfer.done);
+        }
+
+        std::thread::sleep(TICK);
+    }
+
+    for bar in transfers.values().chain(total.iter()) {
+        bar.finish_and_clear();
+    }
+}
+
+/// The position and length in bytes of the bar over the whole plan, and the number of files processed to show before it.
+/// The bytes include the uploads in progress. The plan is an estimate, so the length grows when more is uploaded than planned
+///
+/// ## Params
+/// - `progress` The progress of the sync
+/// - `active` The uploads in progress
+fn overall(progress: &Progress, active: &[Transfer]) -> (u64, u64, String) {
+    let position = progress.bytes + active.iter().map(|transfer| transfer.done).sum::<u64>();
+    let length = progress.planned_bytes.max(position);
+    let prefix = format!("{}/{} files", progress.processed(), progress.planned_files);
+    (position, length, prefix)
+}
+
+/// The file name of a path, to label the bar of its upload
+fn file_name(path: &str) -> String {
+    Path::new(path).file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| path.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::bars::overall;
+    use crate::progress::Transfer;
+    use crate::report::Progress;
+
+    #[test]
+    fn overall_counts_uploads_in_progress() {
+        let progress = Progress {
+            started:    0,
+            uploaded:   2,
+            updated:    1,
+            unchanged:  5,
+            deleted:    0,
+            bytes:      300,
+            failures:   1,
+            skipped:    1,
+            planned_files: 20,
+            planned_bytes: 1000
+        };
+
+        let active = vec![Transfer { id: 0, path: "/tmp/a".to_string(), total: 500, done: 200 }];
+        assert_eq!((500, 1000, "10/20 files".to_string()), overall(&progress, &active));
+
+        // More was uploaded than planned
+        let active = vec![Transfer { id: 0, path: "/tmp/a".to_string(), total: 1500, done: 1200 }];
+        assert_eq!((1500, 1500, "10/20 files".to_string()), overall(&progress, &active));
+    }
+}
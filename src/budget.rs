@@ -0,0 +1,180 @@
+//! Module for the budgets of a sync run. A run can be limited in the number of bytes it uploads and in how long it runs, e.g. on a metered
+//! connection or in a nightly maintenance window. Once a budget is used up the run stops starting new work, and the work it didn't get to is
+//! journaled in the database, to be picked up by the next run
+
+use crate::env::Env;
+use crate::{Result, unwrap_db_err};
+use rusqlite::named_params;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Struct describing the budget of a sync run, shared between the files synced concurrently
+#[derive(Debug)]
+pub struct Budget {
+    /// The maximum number of bytes the run may upload
+    max_bytes:  Option<u64>,
+
+    /// The moment after which the run may not start new work
+    deadline:   Option<Instant>,
+
+    /// The number of bytes reserved by the uploads started so far
+    reserved:   AtomicU64,
+
+    /// Whether the budget is used up. Once set, it stays set for the rest of the run
+    exhausted:  AtomicBool
+}
+
+impl Budget {
+    /// Create the budget of a run starting now
+    ///
+    /// ## Params
+    /// - `max_bytes` The maximum number of bytes the run may upload, if limited
+    /// - `max_duration` How long the run may take, if limited
+    pub fn new(max_bytes: Option<u64>, max_duration: Option<Duration>) -> Self {
+        Self {
+            max_bytes,
+            deadline:   max_duration.map(|duration| Instant::now() + duration),
+            reserved:   AtomicU64::new(0),
+            exhausted:  AtomicBool::new(false)
+        }
+    }
+
+    /// Whether the budget is used up, either because the bytes ran out or because the time did
+    pub fn is_exhausted(&self) -> bool {
+        if self.deadline.map(|deadline| Instant::now() >= deadline).unwrap_or(false) {
+            self.exhausted.store(true, Ordering::Relaxed);
+        }
+
+        self.exhausted.load(Ordering::Relaxed)
+    }
+
+    /// Whether a file is larger than the whole transfer budget, so it can't be uploaded in any run with this budget
+    pub fn exceeds(&self, bytes: u64) -> bool {
+        self.max_bytes.map(|max_bytes| bytes > max_bytes).unwrap_or(false)
+    }
+
+    /// Reserve room for uploading a file. Returns false if the file doesn't fit in what is left of the budget,
+    /// in which case the budget is used up and no further files are uploaded in this run
+    pub fn reserve(&self, bytes: u64) -> bool {
+        if self.is_exhausted() {
+            return false;
+        }
+
+        let max_bytes = match self.max_bytes {
+            Some(max_bytes) => max_bytes,
+            None => return true
+        };
+
+        let fits = self.reserved.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |reserved| {
+            reserved.checked_add(bytes).filter(|total| *total <= max_bytes)
+        }).is_ok();
+
+        if !fits {
+            self.exhausted.store(true, Ordering::Relaxed);
+        }
+
+        fits
+    }
+}
+
+/// Parse a human readable duration, e.g. `90m`, `2h` or `1h30m`, into a Duration. Supported units are `s`, `m`, `h` and `d`, a number without a unit is in seconds
+pub fn parse_duration(duration: &str) -> Option<Duration> {
+    let duration = duration.trim();
+    if duration.is_empty() {
+        return None;
+    }
+
+    let mut seconds = 0u64;
+    let mut rest = duration;
+    while !rest.is_empty() {
+        let split = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        let number = rest[..split].parse::<u64>().ok()?;
+        rest = &rest[split..];
+
+        let unit_len = rest.find(|c: char| c.is_ascii_digit()).unwrap_or(rest.len());
+        let multiplier = match rest[..unit_len].trim().to_ascii_lowercase().as_str() {
+            "" | "s" => 1,
+            "m" => 60,
+            "h" => 60 * 60,
+            "d" => 24 * 60 * 60,
+            _ => return None
+        };
+        rest = &rest[unit_len..];
+
+        seconds = seconds.checked_add(number.checked_mul(multiplier)?)?;
+    }
+
+    Some(Duration::from_secs(seconds))
+}
+
+/// Replace the journal of deferred work with the files and folders a run didn't get to. An empty list clears the journal
+///
+/// ## Errors
+/// - When a database operation fails
+pub fn journal(env: &Env, deferred: &[String]) -> Result<()> {
+    let conn = unwrap_db_err!(env.get_conn());
+    unwrap_db_err!(conn.execute("DELETE FROM deferred", named_params! {}));
+
+    let now = chrono::Utc::now().timestamp();
+    for path in deferred {
+        unwrap_db_err!(conn.execute("INSERT OR REPLACE INTO deferred (path, deferred_at) VALUES (:path, :deferred_at)", named_params! {
+            ":path":        path,
+            ":deferred_at": now
+        }));
+    }
+
+    Ok(())
+}
+
+/// Get the files and folders the previous run didn't get to because its budget was used up
+///
+/// ## Errors
+/// - When a database operation fails
+pub fn deferred(env: &Env) -> Result<Vec<String>> {
+    let conn = unwrap_db_err!(env.get_conn());
+    let mut stmt = unwrap_db_err!(conn.prepare("SELECT path FROM deferred ORDER BY path"));
+    let mut result = unwrap_db_err!(stmt.query(named_params! {}));
+
+    let mut paths = Vec::new();
+    while let Some(row) = unwrap_db_err!(result.next()) {
+        paths.push(unwrap_db_err!(row.get::<&str, String>("path")));
+    }
+
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::budget::{parse_duration, Budget};
+    use std::time::Duration;
+
+    #[test]
+    fn durations() {
+        assert_eq!(Some(Duration::from_secs(90 * 60)), parse_duration("90m"));
+        assert_eq!(Some(Duration::from_secs(5400)), parse_duration("1h30m"));
+        assert_eq!(Some(Duration::from_secs(45)), parse_duration("45"));
+        assert_eq!(Some(Duration::from_secs(2 * 86400)), parse_duration("2d"));
+        assert_eq!(None, parse_duration("soon"));
+        assert_eq!(None, parse_duration("5w"));
+        assert_eq!(None, parse_duration(""));
+    }
+
+    #[test]
+    fn transfer_budget() {
+        let budget = Budget::new(Some(100), None);
+        assert!(budget.reserve(60));
+        assert!(budget.reserve(40));
+        assert!(!budget.reserve(1));
+        assert!(budget.is_exhausted());
+
+        // Once used up, even files which would fit are deferred
+        assert!(!budget.reserve(0));
+
+        let unlimited = Budget::new(None, None);
+        assert!(unlimited.reserve(u64::MAX));
+        assert!(!unlimited.is_exhausted());
+
+        let expired = Budget::new(None, Some(Duration::from_secs(0)));
+        assert!(expired.is_exhausted());
+    }
+}
@@ -0,0 +1,255 @@
+//! Module for detecting changes made in Google Drive outside of GSync, e.g. in the web UI, using the Drive Changes API.
+//! Every sync lists the changes made since the previous one, so records of files deleted or moved in Drive are repaired,
+//! and files edited in Drive are not overwritten by the next change to the local file
+
+use crate::env::Env;
+use crate::{Result, unwrap_db_err};
+use crate::api::drive::{self, Change};
+use crate::files::{self, TrackedFile};
+use rusqlite::named_params;
+use std::collections::{HashMap, HashSet};
+
+/// Enum describing a change made in Google Drive to a tracked file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RemoteChange {
+    /// The file was deleted or trashed
+    Deleted,
+
+    /// The file was moved to another folder
+    Moved,
+
+    /// The content of the file was changed
+    Edited
+}
+
+/// Struct describing the changes made in Google Drive to tracked files since the previous sync
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Summary {
+    /// The number of tracked files and folders deleted or trashed in Drive
+    pub deleted:    usize,
+
+    /// The number of tracked files and folders moved in Drive
+    pub moved:      usize,
+
+    /// The number of tracked files edited in Drive
+    pub edited:     usize
+}
+
+/// List the changes made in a drive since the previous check, and apply those made to tracked files. Records of files deleted or moved in Drive
+/// are removed, so the files are uploaded again. Files edited in Drive are recorded, so they are not overwritten by the sync.
+/// The first check only records where to list changes from
+///
+/// ## Params
+/// - `drive_id` The ID of the shared drive to check, None for My Drive
+///
+/// ## Errors
+/// - When a Drive API call fails
+/// - When a database operation fails
+pub fn check(env: &Env, drive_id: Option<&str>) -> Result<Summary> {
+    let key = drive_id.unwrap_or_default();
+    let token = match get_token(env, key)? {
+        Some(token) => token,
+        None => {
+            let token = drive::blocking::get_start_page_token(env, drive_id)?;
+            set_token(env, key, &token)?;
+            return Ok(Summary::default());
+        }
+    };
+
+    let (changes, next_token) = drive::blocking::list_changes(env, &token, drive_id)?;
+    let tracked = files::get_all(env)?;
+    let by_id = tracked.iter().map(|file| (file.id.as_str(), file)).collect::<HashMap<_, _>>();
+
+    let mut summary = Summary::default();
+    for change in changes.iter() {
+        let file = match by_id.get(change.file_id.as_str()) {
+            Some(file) => *file,
+            None => continue
+        };
+
+        match classify(file, change) {
+            Some(RemoteChange::Deleted) => {
+                println!("Info: '{}' was deleted in Drive, it will be uploaded again", file.path);
+                forget(env, file)?;
+                summary.deleted += 1;
+            },
+            Some(RemoteChange::Moved) => {
+                println!("Warning: '{}' was moved in Drive, it is no longer tracked and will be uploaded again to its original place", file.path);
+                forget(env, file)?;
+                summary.moved += 1;
+            },
+            Some(RemoteChange::Edited) => {
+                println!("Warning: '{}' was edited in Drive, it won't be overwritten by the sync. Restore it, or sync with --overwrite-remote to keep the local version", file.path);
+                record_edit(env, file)?;
+                summary.edited += 1;
+            },
+            None => {}
+        }
+    }
+
+    // Only stored once the changes are applied, so they are listed again if applying them fails
+    set_token(env, key, &next_token)?;
+    Ok(summary)
+}
+
+/// Decide how a change made in Google Drive affects a tracked file. Changes made by GSync itself, e.g. uploading a new version,
+/// match the record of the file and are ignored
+fn classify(file: &TrackedFile, change: &Change) -> Option<RemoteChange> {
+    let remote = match &change.file {
+        Some(remote) if !change.removed => remote,
+        _ => return Some(RemoteChange::Deleted)
+    };
+
+    if remote.trashed {
+        return Some(RemoteChange::Deleted);
+    }
+
+    if !remote.parents.is_empty() && !remote.parents.contains(&file.parent_id) {
+        return Some(RemoteChange::Moved);
+    }
+
+    // Google-native documents, e.g. converted files, have no checksum, so edits to them can't be detected
+    match (&file.md5, &remote.md5_checksum) {
+        (Some(recorded), Some(current)) if !file.is_folder && recorded != current => Some(RemoteChange::Edited),
+        _ => None
+    }
+}
+
+/// Stop tracking a file or folder which was deleted or moved in Drive
+///
+/// ## Errors
+/// - When a database operation fails
+fn forget(env: &Env, file: &TrackedFile) -> Result<()> {
+    if file.is_folder {
+        files::delete_tree(env, &file.path)?;
+    } else {
+        files::delete(env, &file.path)?;
+    }
+
+    resolve(env, &file.id)
+}
+
+/// Record that a tracked file was edited in Drive
+///
+/// ## Errors
+/// - When a database operation fails
+fn record_edit(env: &Env, file: &TrackedFile) -> Result<()> {
+    let conn = unwrap_db_err!(env.get_conn());
+    unwrap_db_err!(conn.execute("INSERT OR REPLACE INTO remote_edits (id, path, detected) VALUES (:id, :path, :detected)", named_params! {
+        ":id":          &file.id,
+        ":path":        &file.path,
+        ":detected":    chrono::Utc::now().timestamp()
+    }));
+
+    Ok(())
+}
+
+/// Get the IDs of the tracked files which were edited in Drive, and have not been overwritten since
+///
+/// ## Errors
+/// - When a database operation fails
+pub fn edited(env: &Env) -> Result<HashSet<String>> {
+    let conn = unwrap_db_err!(env.get_conn());
+    let mut stmt = unwrap_db_err!(conn.prepare("SELECT id FROM remote_edits"));
+    let mut result = unwrap_db_err!(stmt.query(named_params! {}));
+
+    let mut ids = HashSet::new();
+    while let Some(row) = unwrap_db_err!(result.next()) {
+        ids.insert(unwrap_db_err!(row.get::<&str, String>("id")));
+    }
+
+    Ok(ids)
+}
+
+/// Forget that a file was edited in Drive, e.g. because it was overwritten with the local version
+///
+/// ## Errors
+/// - When a database operation fails
+pub fn resolve(env: &Env, id: &str) -> Result<()> {
+    let conn = unwrap_db_err!(env.get_conn());
+    unwrap_db_err!(conn.execute("DELETE FROM remote_edits WHERE id = :id", named_params! {
+        ":id": id
+    }));
+
+    Ok(())
+}
+
+/// Get the stored token to list the changes in a drive from
+///
+/// ## Errors
+/// - When a database operation fails
+fn get_token(env: &Env, drive: &str) -> Result<Option<String>> {
+    let conn = unwrap_db_err!(env.get_conn());
+    let mut stmt = unwrap_db_err!(conn.prepare("SELECT token FROM change_tokens WHERE drive = :drive"));
+    let mut result = unwrap_db_err!(stmt.query(named_params! {
+        ":drive": drive
+    }));
+
+    match unwrap_db_err!(result.next()) {
+        Some(row) => Ok(Some(unwrap_db_err!(row.get::<&str, String>("token")))),
+        None => Ok(None)
+    }
+}
+
+/// Store the token to list the changes in a drive from
+///
+/// ## Errors
+/// - When a database operation fails
+fn set_token(env: &Env, drive: &str, token: &str) -> Result<()> {
+    let conn = unwrap_db_err!(env.get_conn());
+    unwrap_db_err!(conn.execute("INSERT OR REPLACE INTO change_tokens (drive, token) VALUES (:drive, :token)", named_params! {
+        ":drive": drive,
+        ":token": token
+    }));
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::changes::{classify, RemoteChange};
+    use crate::api::drive::{Change, File};
+    use crate::files::TrackedFile;
+    use std::collections::HashMap;
+
+    fn remote(parent: &str, md5: &str, trashed: bool) -> File {
+        File {
+            id:             "file".to_string(),
+            name:           "notes.txt".to_string(),
+            modified_time:  String::new(),
+            mime_type:      "text/plain".to_string(),
+            parents:        vec![parent.to_string()],
+            explicitly_trashed: trashed,
+            trashed,
+            shortcut_details:   None,
+            size:           None,
+            md5_checksum:   Some(md5.to_string()),
+            head_revision_id: None,
+            app_properties: HashMap::new(),
+            owned_by_me:    true,
+            capabilities:   None,
+            drive_id:       None
+        }
+    }
+
+    #[test]
+    fn classify_changes() {
+        let file = TrackedFile {
+            path:       "/home/me/notes.txt".to_string(),
+            id:         "file".to_string(),
+            parent_id:  "folder".to_string(),
+            is_folder:  false,
+            link_target: None,
+            size:       Some(5),
+            md5:        Some("abc".to_string()),
+            synced_at:  None
+        };
+
+        let change = |file: Option<File>, removed: bool| Change { file_id: "file".to_string(), removed, file };
+        assert_eq!(None, classify(&file, &change(Some(remote("folder", "abc", false)), false)));
+        assert_eq!(Some(RemoteChange::Edited), classify(&file, &change(Some(remote("folder", "def", false)), false)));
+        assert_eq!(Some(RemoteChange::Moved), classify(&file, &change(Some(remote("other", "abc", false)), false)));
+        assert_eq!(Some(RemoteChange::Deleted), classify(&file, &change(Some(remote("folder", "abc", true)), false)));
+        assert_eq!(Some(RemoteChange::Deleted), classify(&file, &change(None, true)));
+    }
+}
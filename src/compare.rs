@@ -0,0 +1,226 @@
+//! Module for deciding whether a local file changed since it was synced. Checking the modification time is fast, hashing the content is accurate,
+//! so which is used can be configured globally and per input
+
+use crate::Result;
+use crate::files::TrackedFile;
+use crate::hash::HashAlgorithm;
+use std::fmt;
+
+/// How GSync decides whether a file changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComparePolicy {
+    /// The file changed if it was modified after its copy in Drive. Fast, but misses changes which keep the modification time
+    Mtime,
+
+    /// The file changed if its size differs from its copy in Drive, or it was modified after it
+    SizeMtime,
+
+    /// The file changed if the checksum of its content differs from its copy in Drive. Accurate, but reads every file
+    Hash
+}
+
+/// The policy used when neither the input nor the configuration sets one
+pub const DEFAULT_POLICY: ComparePolicy = ComparePolicy::Mtime;
+
+/// The number of seconds modification times may differ while still being considered the same, when none is configured.
+/// FAT and exFAT store modification times with a granularity of 2 seconds
+pub const DEFAULT_TOLERANCE: i64 = 2;
+
+/// The names of the policies, as accepted on the command line
+pub const POLICY_NAMES: [&str; 3] = ["mtime", "size-mtime", "hash"];
+
+impl ComparePolicy {
+    /// Parse a policy from its name
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "mtime" => Some(Self::Mtime),
+            "size-mtime" => Some(Self::SizeMtime),
+            "hash" => Some(Self::Hash),
+            _ => None
+        }
+    }
+
+    /// The policy of an input: its own if it has one, the configured one otherwise, and the default if neither is set
+    ///
+    /// ## Params
+    /// - `input` The policy of the input, if set
+    /// - `global` The configured policy, if set
+    pub fn resolve(input: Option<&str>, global: Option<&str>) -> Self {
+        input.and_then(Self::parse)
+            .or_else(|| global.and_then(Self::parse))
+            .unwrap_or(DEFAULT_POLICY)
+    }
+}
+
+impl fmt::Display for ComparePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Mtime => "mtime",
+            Self::SizeMtime => "size-mtime",
+            Self::Hash => "hash"
+        };
+
+        f.pad(name)
+    }
+}
+
+/// Struct describing what is known about the copy of a file in Google Drive
+#[derive(Debug)]
+pub struct SyncedCopy {
+    /// The epoch time in seconds at which the copy was last modified or synced
+    pub modified:   i64,
+
+    /// The modification time of the local file when the copy was synced, as epoch seconds, if it was recorded
+    pub mtime:      Option<i64>,
+
+    /// The size of the copy in bytes, if known
+    pub size:       Option<u64>,
+
+    /// The MD5 checksum of the copy, if known. Google-native documents have none
+    pub md5:        Option<String>,
+
+    /// The checksum stored when the copy was synced, together with the algorithm it was computed with
+    pub hash:       Option<(HashAlgorithm, String)>
+}
+
+impl SyncedCopy {
+    /// What the files table knows about the copy of a tracked file, for when Google Drive isn't asked, e.g. to plan a sync.
+    /// The time the file was synced stands in for the modification time of the copy. None if that time is unknown
+    ///
+    /// ## Params
+    /// - `file` The tracked file
+    /// - `hash` The checksum stored when the file was synced, see [crate::hash::stored_all]
+    pub fn tracked(file: &TrackedFile, hash: Option<(HashAlgorithm, String)>) -> Option<Self> {
+        Some(Self {
+            modified:   file.synced_at?,
+            mtime:      None,
+            size:       file.size.map(|size| size as u64),
+            md5:        file.md5.clone(),
+            hash
+        })
+    }
+}
+
+/// How the modification time of a local file compares to the one of its copy in Google Drive
+#[derive(Debug, PartialEq, Eq)]
+enum Timestamp {
+    /// The file was modified after it was synced
+    Newer,
+
+    /// The times are the same, within the tolerance
+    Same,
+
+    /// The modification time moved backwards, e.g. because an older version of the file was restored
+    Older
+}
+
+/// Decide whether a local file changed compared to its copy in Google Drive.
+/// Modification times within `tolerance` seconds of each other are considered the same. If the modification time moved backwards,
+/// the modification time can't tell, so the size and, if Drive has one, the checksum are compared instead
+///
+/// ## Params
+/// - `policy` How to decide
+/// - `tolerance` The number of seconds modification times may differ while still being considered the same
+/// - `modified` The modification time of the local file, as epoch seconds
+/// - `size` The size of the local file in bytes
+/// - `copy` What is known about the copy in Drive
+/// - `hash` Computes the checksum of the local file with the provided algorithm. Only called when the checksums are compared.
+///   The stored checksum is preferred, with the algorithm it was computed with. The MD5 checksum of Drive is used otherwise
+///
+/// ## Errors
+/// - When computing the checksum fails
+pub fn changed<F>(policy: ComparePolicy, tolerance: i64, modified: i64, size: u64, copy: &SyncedCopy, hash: F) -> Result<bool>
+where F: FnOnce(HashAlgorithm) -> Result<String> {
+    // The modification time the file had when it was synced isn't affected by the clocks of Drive and this machine.
+    // Without it, the file can only have changed if it was modified after the copy
+    let timestamp = match copy.mtime {
+        Some(mtime) if (modified - mtime).abs() <= tolerance => Timestamp::Same,
+        Some(mtime) if modified > mtime => Timestamp::Newer,
+        Some(_) => Timestamp::Older,
+        None if modified > copy.modified + tolerance => Timestamp::Newer,
+        None => Timestamp::Same
+    };
+
+    let size_differs = copy.size.map(|copy_size| copy_size != size).unwrap_or(false);
+    let has_checksum = copy.hash.is_some() || copy.md5.is_some();
+    let hash_differs = |hash: F| -> Result<bool> {
+        match (&copy.hash, &copy.md5) {
+            (Some((algorithm, copy_hash)), _) => Ok(!hash(*algorithm)?.eq_ignore_ascii_case(copy_hash)),
+            (None, Some(copy_md5)) => Ok(!hash(HashAlgorithm::Md5)?.eq_ignore_ascii_case(copy_md5)),
+            // Without a checksum to compare with, the file is synced again rather than risking a missed change
+            (None, None) => Ok(true)
+        }
+    };
+
+    match (policy, timestamp) {
+        (ComparePolicy::Hash, _) if has_checksum => hash_differs(hash),
+        (_, Timestamp::Newer) => Ok(true),
+        (ComparePolicy::Mtime, Timestamp::Same) => Ok(false),
+        (_, Timestamp::Same) => Ok(size_differs),
+        (_, Timestamp::Older) => Ok(size_differs || hash_differs(hash)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::compare::{changed, ComparePolicy, SyncedCopy};
+    use crate::hash::HashAlgorithm;
+
+    #[test]
+    fn policies() {
+        let copy = SyncedCopy { modified: 100, mtime: None, size: Some(10), md5: Some("abc".to_string()), hash: None };
+        let md5 = |_| Ok("ABC".to_string());
+
+        assert!(!changed(ComparePolicy::Mtime, 0, 100, 20, &copy, md5).unwrap());
+        assert!(changed(ComparePolicy::SizeMtime, 0, 100, 20, &copy, md5).unwrap());
+        assert!(!changed(ComparePolicy::SizeMtime, 0, 50, 10, &copy, md5).unwrap());
+        assert!(!changed(ComparePolicy::Hash, 0, 200, 20, &copy, md5).unwrap());
+        assert!(changed(ComparePolicy::Hash, 0, 50, 10, &copy, |_| Ok("def".to_string())).unwrap());
+    }
+
+    #[test]
+    fn tolerance() {
+        let copy = SyncedCopy { modified: 500, mtime: Some(100), size: Some(10), md5: Some("abc".to_string()), hash: None };
+        let md5 = |_| Ok("abc".to_string());
+
+        // Coarse timestamps, e.g. on FAT
+        assert!(!changed(ComparePolicy::Mtime, 2, 102, 10, &copy, md5).unwrap());
+        assert!(changed(ComparePolicy::Mtime, 2, 103, 10, &copy, md5).unwrap());
+
+        // Moved backwards, the checksum decides
+        assert!(!changed(ComparePolicy::Mtime, 2, 50, 10, &copy, md5).unwrap());
+        assert!(changed(ComparePolicy::Mtime, 2, 50, 10, &copy, |_| Ok("def".to_string())).unwrap());
+        assert!(changed(ComparePolicy::Mtime, 2, 50, 20, &copy, md5).unwrap());
+
+        // Without the recorded modification time, the clock of Drive may be slightly behind
+        let copy = SyncedCopy { mtime: None, ..copy };
+        assert!(!changed(ComparePolicy::Mtime, 2, 502, 10, &copy, md5).unwrap());
+    }
+
+    #[test]
+    fn stored_hash() {
+        let copy = SyncedCopy {
+            modified:   100,
+            mtime:      None,
+            size:       Some(10),
+            md5:        Some("abc".to_string()),
+            hash:       Some((HashAlgorithm::Blake3, "b3".to_string()))
+        };
+
+        // The stored checksum is compared with a checksum computed with the same algorithm
+        let hash = |algorithm| Ok(match algorithm {
+            HashAlgorithm::Blake3 => "b3".to_string(),
+            _ => "other".to_string()
+        });
+
+        assert!(!changed(ComparePolicy::Hash, 0, 200, 10, &copy, hash).unwrap());
+        assert!(changed(ComparePolicy::Hash, 0, 200, 10, &copy, |_| Ok("def".to_string())).unwrap());
+    }
+
+    #[test]
+    fn resolve_policy() {
+        assert_eq!(ComparePolicy::Hash, ComparePolicy::resolve(Some("hash"), Some("size-mtime")));
+        assert_eq!(ComparePolicy::SizeMtime, ComparePolicy::resolve(None, Some("size-mtime")));
+        assert_eq!(ComparePolicy::Mtime, ComparePolicy::resolve(None, None));
+    }
+}
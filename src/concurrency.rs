@@ -0,0 +1,331 @@
+//! Module for adaptively limiting the number of concurrent uploads
+//!
+//! The limit is tuned AIMD-style: it is increased by one after every window of successful uploads in which the
+//! aggregate throughput held up, and it is halved when Google starts rate limiting us or when the latency of an upload
+//! rises far above the best latency observed during the sync for uploads of about the same size.
+
+use crate::Error;
+use std::collections::HashMap;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+/// The default maximum number of concurrent uploads
+pub const DEFAULT_MAX_JOBS: usize = 8;
+
+/// The fraction of the previous window's throughput a window must reach for the limit to be increased
+const THROUGHPUT_TOLERANCE: f64 = 0.9;
+
+/// How many times slower than the best observed latency an upload may be before the limit is decreased
+const LATENCY_TOLERANCE: f64 = 4.0;
+
+/// Limits the number of concurrent uploads, tuning the limit based on the outcome of finished uploads
+#[derive(Debug)]
+pub struct AdaptiveLimiter {
+    /// The state of the limiter
+    state:      Mutex<LimiterState>,
+
+    /// The permits to start an upload. There are as many as the limit allows, minus the uploads in progress
+    permits:    Semaphore
+}
+
+/// Struct describing the mutable state of an AdaptiveLimiter
+#[derive(Debug)]
+struct LimiterState {
+    /// The current limit on the number of concurrent uploads
+    limit:              usize,
+
+    /// The maximum limit
+    max:                usize,
+
+    /// The number of permits still to be taken out of circulation after the limit was decreased
+    debt:               usize,
+
+    /// The moment the current window started
+    window_start:       Instant,
+
+    /// The number of successful uploads in the current window
+    window_uploads:     usize,
+
+    /// The number of bytes uploaded in the current window
+    window_bytes:       u64,
+
+    /// The aggregate throughput of the previous window, in bytes per second
+    last_throughput:    Option<f64>,
+
+    /// The best latency observed in seconds, per size range of uploads, see [size_class].
+    /// The fixed cost of a request dominates the latency of small uploads, so only uploads of about the same size are compared
+    best_latency:       HashMap<u32, f64>
+}
+
+/// The outcome of an upload, reported to the limiter
+#[derive(Debug, Clone, Copy)]
+pub enum Outcome {
+    /// The upload succeeded
+    Success {
+        /// The number of bytes uploaded
+        bytes:      u64,
+        /// How long the upload took
+        elapsed:    Duration
+    },
+
+    /// Google rate limited the upload, or was temporarily unavailable
+    Throttled,
+
+    /// The upload failed for another reason, this does not influence the limit
+    Failed
+}
+
+/// A permit to perform one upload. When dropped without reporting an outcome, the permit is released without influencing the limit
+#[derive(Debug)]
+pub struct Permit<'a> {
+    /// The limiter which handed out this permit
+    limiter:    &'a AdaptiveLimiter,
+
+    /// The permit from the semaphore, None once an outcome has been reported
+    permit:     Option<SemaphorePermit<'a>>
+}
+
+impl AdaptiveLimiter {
+    /// Create a new limiter, starting with a single concurrent upload
+    ///
+    /// ## Params
+    /// - `max` The maximum number of concurrent uploads
+    pub fn new(max: usize) -> Self {
+        Self {
+            state:      Mutex::new(LimiterState::new(max)),
+            permits:    Semaphore::new(1)
+        }
+    }
+
+    /// The maximum number of concurrent uploads this limiter will allow
+    pub fn max(&self) -> usize {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner).max
+    }
+
+    /// Wait until an upload may be started
+    pub async fn acquire(&self) -> Permit<'_> {
+        // The semaphore is never closed, so acquiring only fails in theory
+        Permit { limiter: self, permit: self.permits.acquire().await.ok() }
+    }
+
+    /// Release a permit, reporting the outcome of the upload.
+    /// When the limit was decreased, permits are taken out of circulation until the number of permits matches the limit again
+    fn release(&self, permit: SemaphorePermit<'_>, outcome: Outcome) {
+        // This runs when a Permit is dropped, also while unwinding, where panicking again would abort the process
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let previous = state.limit;
+        state.record(outcome);
+
+        if state.limit > previous {
+            let increase = state.limit - previous;
+            let paid = increase.min(state.debt);
+            state.debt -= paid;
+            self.permits.add_permits(increase - paid);
+        } else if state.limit < previous {
+            state.debt += previous - state.limit;
+            while state.debt > 0 {
+                match self.permits.try_acquire() {
+                    Ok(idle) => idle.forget(),
+                    Err(_) => break
+                }
+
+                state.debt -= 1;
+            }
+        }
+
+        if state.limit != previous {
+            println!("Info: Adjusted the number of concurrent uploads from {} to {}", previous, state.limit);
+        }
+
+        if state.debt > 0 {
+            permit.forget();
+            state.debt -= 1;
+        }
+    }
+}
+
+impl Permit<'_> {
+    /// Report the outcome of the upload and release the permit
+    pub fn finish(mut self, outcome: Outcome) {
+        if let Some(permit) = self.permit.take() {
+            self.limiter.release(permit, outcome);
+        }
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        if let Some(permit) = self.permit.take() {
+            self.limiter.release(permit, Outcome::Failed);
+        }
+    }
+}
+
+impl LimiterState {
+    /// Create the initial state
+    fn new(max: usize) -> Self {
+        Self {
+            limit:              1,
+            max:                max.max(1),
+            debt:               0,
+            window_start:       Instant::now(),
+            window_uploads:     0,
+            window_bytes:       0,
+            last_throughput:    None,
+            best_latency:       HashMap::new()
+        }
+    }
+
+    /// Update the limit based on the outcome of an upload
+    fn record(&mut self, outcome: Outcome) {
+        match outcome {
+            Outcome::Success { bytes, elapsed } => {
+                // Uploads which transferred nothing, e.g. because the file was up-to-date, say nothing about the connection
+                if bytes == 0 {
+                    return;
+                }
+
+                let latency = elapsed.as_secs_f64();
+                let class = size_class(bytes);
+                match self.best_latency.get(&class) {
+                    Some(best) if latency > best * LATENCY_TOLERANCE => {
+                        self.decrease();
+                        return;
+                    },
+                    Some(best) if latency >= *best => {},
+                    _ => {
+                        self.best_latency.insert(class, latency);
+                    }
+                }
+
+                self.window_uploads += 1;
+                self.window_bytes += bytes;
+                if self.window_uploads >= self.limit {
+                    let elapsed = self.window_start.elapsed().as_secs_f64().max(f64::EPSILON);
+                    let throughput = self.window_bytes as f64 / elapsed;
+                    let held_up = self.last_throughput.map(|last| throughput >= last * THROUGHPUT_TOLERANCE).unwrap_or(true);
+
+                    if held_up && self.limit < self.max {
+                        self.limit += 1;
+                    }
+
+                    self.last_throughput = Some(throughput);
+                    self.reset_window();
+                }
+            },
+            Outcome::Throttled => self.decrease(),
+            Outcome::Failed => {}
+        }
+    }
+
+    /// Halve the limit, and start a new window
+    fn decrease(&mut self) {
+        self.limit = (self.limit / 2).max(1);
+        self.last_throughput = None;
+        self.reset_window();
+    }
+
+    /// Start a new measurement window
+    fn reset_window(&mut self) {
+        self.window_start = Instant::now();
+        self.window_uploads = 0;
+        self.window_bytes = 0;
+    }
+}
+
+/// The size range of an upload of this many bytes. Uploads in the same range are at most twice as large as one another
+fn size_class(bytes: u64) -> u32 {
+    u64::BITS - bytes.leading_zeros()
+}
+
+/// Check if an error indicates that Google is rate limiting us or is temporarily unavailable
+pub fn is_throttled(error: &Error) -> bool {
+    match error {
+        Error::GoogleError(e) => {
+            e.code == 429
+                || e.code >= 500
+                || (e.code == 403 && e.errors.iter().any(|data| data.reason.eq("rateLimitExceeded") || data.reason.eq("userRateLimitExceeded")))
+        },
+        Error::RequestError(e) => e.is_timeout(),
+        _ => false
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::concurrency::{size_class, AdaptiveLimiter, LimiterState, Outcome};
+    use std::time::Duration;
+
+    /// A successful upload of one MiB
+    const SUCCESS: Outcome = Outcome::Success { bytes: 1 << 20, elapsed: Duration::from_secs(1) };
+
+    #[test]
+    fn increases_after_successful_window() {
+        let mut state = LimiterState::new(4);
+        state.record(SUCCESS);
+        assert_eq!(state.limit, 2);
+    }
+
+    #[test]
+    fn does_not_exceed_max() {
+        let mut state = LimiterState::new(2);
+        for _ in 0..10 {
+            state.record(SUCCESS);
+        }
+
+        assert_eq!(state.limit, 2);
+    }
+
+    #[test]
+    fn halves_when_throttled() {
+        let mut state = LimiterState::new(8);
+        state.limit = 6;
+        state.record(Outcome::Throttled);
+        assert_eq!(state.limit, 3);
+
+        state.limit = 1;
+        state.record(Outcome::Throttled);
+        assert_eq!(state.limit, 1);
+    }
+
+    #[test]
+    fn halves_on_high_latency() {
+        let mut state = LimiterState::new(8);
+        state.best_latency.insert(size_class(1 << 20), 1.0);
+        state.limit = 4;
+        state.record(Outcome::Success { bytes: 1 << 20, elapsed: Duration::from_secs(10) });
+        assert_eq!(state.limit, 2);
+    }
+
+    #[test]
+    fn compares_latency_of_similar_sizes() {
+        let mut state = LimiterState::new(8);
+        state.limit = 4;
+        state.record(Outcome::Success { bytes: 64 << 20, elapsed: Duration::from_secs(8) });
+
+        // Far slower per MiB than the large upload, but that is the fixed cost of a request
+        state.record(Outcome::Success { bytes: 1 << 10, elapsed: Duration::from_millis(200) });
+        assert_eq!(state.limit, 4);
+
+        state.record(Outcome::Success { bytes: 96 << 20, elapsed: Duration::from_secs(40) });
+        assert_eq!(state.limit, 2);
+    }
+
+    #[test]
+    fn takes_permits_out_of_circulation_when_decreased() {
+        crate::api::block_on(async {
+            let limiter = AdaptiveLimiter::new(4);
+            limiter.acquire().await.finish(SUCCESS);
+            assert_eq!(limiter.permits.available_permits(), 2);
+
+            let first = limiter.acquire().await;
+            let second = limiter.acquire().await;
+            first.finish(Outcome::Throttled);
+            assert_eq!(limiter.permits.available_permits(), 0);
+
+            second.finish(Outcome::Failed);
+            assert_eq!(limiter.permits.available_permits(), 1);
+        });
+    }
+}
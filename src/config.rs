@@ -3,6 +3,8 @@
 use crate::env::Env;
 use rusqlite::named_params;
 use crate::{Result, unwrap_db_err, Error};
+use std::fs;
+use std::path::Path;
 
 /// Struct describing a configuration for GSync
 #[derive(Debug)]
@@ -17,14 +19,156 @@ pub struct Configuration {
     pub input_files:    Option<String>,
 
     /// If using a Team Drive/Shared Drive, the ID of that drive
-    pub drive_id:       Option<String>
+    pub drive_id:       Option<String>,
+
+    /// The percentage of the storage quota that may be in use after a sync
+    pub quota_threshold: Option<i64>,
+
+    /// What to do when a sync would exceed the quota threshold, `warn` or `abort`
+    pub quota_action:   Option<String>,
+
+    /// Whether extended attributes of files should be stored in Drive
+    pub preserve_xattrs: Option<bool>,
+
+    /// The maximum number of concurrent uploads. The actual number is tuned automatically up to this maximum
+    pub max_jobs: Option<i64>,
+
+    /// Path to the JSON key of a service account with domain-wide delegation, used together with `impersonate`
+    pub service_account_key: Option<String>,
+
+    /// The user to impersonate with the service account
+    pub impersonate: Option<String>,
+
+    /// The Drive labels to apply to files created by GSync
+    pub labels: Option<String>,
+
+    /// Whether the report of every sync should be uploaded to the reports folder in Drive
+    pub upload_reports: Option<bool>,
+
+    /// The number of days runs are kept in the run history
+    pub history_retention: Option<i64>,
+
+    /// The maximum number of tracked files a sync may delete from Drive, either as a number or as a percentage, e.g. `10%`
+    pub delete_limit: Option<String>,
+
+    /// The ID of an existing folder in Google Drive to sync into, instead of the 'GSync' folder
+    pub target_folder: Option<String>,
+
+    /// If set, every remote operation is also appended to this file
+    pub audit_log: Option<String>,
+
+    /// The number of incremental snapshots after which the next snapshot is a full one
+    pub snapshot_full_every: Option<i64>,
+
+    /// The number of days within which the daemon verifies every synced file once. 0 disables scrubbing
+    pub scrub_window: Option<i64>,
+
+    /// How GSync decides whether a file changed: `mtime`, `size-mtime` or `hash`. Inputs can override it. Defaults to `mtime`
+    pub compare: Option<String>,
+
+    /// How symbolic links are synced: follow or shortcut
+    pub symlinks: Option<String>,
+
+    /// The number of seconds modification times may differ while still being considered the same
+    pub mtime_tolerance: Option<i64>,
+
+    /// The algorithm checksums of synced files are stored with
+    pub hash_algorithm: Option<String>,
+
+    /// The passphrase the backup of the state in the application data folder is encrypted with. Without one, the state isn't backed up
+    pub state_passphrase: Option<String>,
+
+    /// Comma separated patterns of files and folders which are not synced in any input
+    pub excludes: Option<String>,
+
+    /// Comma separated MIME types per file extension, used instead of the MIME type guessed from the extension
+    pub mime_types: Option<String>,
+
+    /// Whether eligible uploads are converted to Google documents
+    pub convert: Option<bool>,
+
+    /// What happens in Drive to files and folders removed locally: `mirror`, `archive` or `trash`
+    pub delete_policy: Option<String>,
+
+    /// The number of times a request to Google Drive is retried when it is rate limited or fails temporarily
+    pub max_retries: Option<i64>,
+
+    /// The maximum upload bandwidth per second, e.g. `2M`. `0` means the bandwidth is not limited
+    pub bwlimit: Option<String>,
+
+    /// If set, files larger than this many bytes are not synced, unless an input or folder sets its own maximum size
+    pub max_size: Option<i64>,
+
+    /// Whether sparse files with enough holes are uploaded packed, leaving out their holes
+    pub pack_sparse: Option<bool>
 }
 
+/// The names of the fields of the configuration, as accepted by `gsync config --unset`
+pub const FIELD_NAMES: [&str; 31] = [
+    "client_id",
+    "client_secret",
+    "input_files",
+    "drive_id",
+    "quota_threshold",
+    "quota_action",
+    "preserve_xattrs",
+    "max_jobs",
+    "service_account_key",
+    "impersonate",
+    "labels",
+    "upload_reports",
+    "history_retention",
+    "delete_limit",
+    "target_folder",
+    "audit_log",
+    "snapshot_full_every",
+    "scrub_window",
+    "compare",
+    "symlinks",
+    "mtime_tolerance",
+    "hash_algorithm",
+    "state_passphrase",
+    "excludes",
+    "mime_types",
+    "convert",
+    "delete_policy",
+    "max_retries",
+    "bwlimit",
+    "max_size",
+    "pack_sparse"
+];
+
 impl Configuration {
 
     /// Check if all fields in the current configuration are empty
     pub fn is_empty(&self) -> bool {
         self.input_files.is_none() && self.client_id.is_none() && self.client_secret.is_none() && self.drive_id.is_none()
+            && self.quota_threshold.is_none() && self.quota_action.is_none()
+            && self.preserve_xattrs.is_none()
+            && self.max_jobs.is_none()
+            && self.service_account_key.is_none()
+            && self.impersonate.is_none()
+            && self.labels.is_none()
+            && self.upload_reports.is_none()
+            && self.history_retention.is_none()
+            && self.delete_limit.is_none()
+            && self.target_folder.is_none()
+            && self.audit_log.is_none()
+            && self.snapshot_full_every.is_none()
+            && self.scrub_window.is_none()
+            && self.compare.is_none()
+            && self.symlinks.is_none()
+            && self.mtime_tolerance.is_none()
+            && self.hash_algorithm.is_none()
+            && self.state_passphrase.is_none()
+            && self.excludes.is_none()
+            && self.mime_types.is_none()
+            && self.convert.is_none()
+            && self.delete_policy.is_none()
+            && self.max_retries.is_none()
+            && self.bwlimit.is_none()
+            && self.max_size.is_none()
+            && self.pack_sparse.is_none()
     }
 
     /// Create an empty configuration
@@ -33,17 +177,52 @@ impl Configuration {
             client_id:      None,
             client_secret:  None,
             input_files:    None,
-            drive_id:       None
+            drive_id:       None,
+            quota_threshold: None,
+            quota_action:   None,
+            preserve_xattrs: None,
+            max_jobs: None,
+            service_account_key: None,
+            impersonate: None,
+            labels: None,
+            upload_reports: None,
+            history_retention: None,
+            delete_limit: None,
+            target_folder: None,
+            audit_log: None,
+            snapshot_full_every: None,
+            scrub_window: None,
+            compare: None,
+            symlinks: None,
+            mtime_tolerance: None,
+            hash_algorithm: None,
+            state_passphrase: None,
+            excludes: None,
+            mime_types: None,
+            convert: None,
+            delete_policy: None,
+            max_retries: None,
+            bwlimit: None,
+            max_size: None,
+            pack_sparse: None
         }
     }
 
+    /// Get the patterns of files and folders which are not synced in any input
+    pub fn excludes(&self) -> Vec<String> {
+        split_patterns(self.excludes.as_deref())
+    }
+
     /// Check if the current configuration is complete, i.e. all required fields are set
     pub fn is_complete(&self) -> (bool, &str) {
         // Self::drive_id is allowed to be None
+        // The client ID and secret are not needed when impersonating a user with a service account
 
-        if self.client_id.is_none() {
+        if self.service_account_key.is_some() != self.impersonate.is_some() {
+            (false, "'service_account_key' and 'impersonate' must be set together")
+        } else if self.client_id.is_none() && !self.uses_service_account() {
             (false, "'client_id' is empty")
-        } else if self.client_secret.is_none() {
+        } else if self.client_secret.is_none() && !self.uses_service_account() {
             (false, "'client_secret' is empty")
         } else if self.input_files.is_none() {
             (false, "'input_files' is empty")
@@ -52,6 +231,136 @@ impl Configuration {
         }
     }
 
+    /// Check if GSync should authenticate with a service account impersonating a user, instead of with the OAuth2 login
+    pub fn uses_service_account(&self) -> bool {
+        self.service_account_key.is_some() && self.impersonate.is_some()
+    }
+
+    /// Check the configured inputs, so a typo is caught when it is configured instead of silently syncing nothing.
+    /// Returns a description of every input which doesn't exist, can't be read or is inside GSync's own state directory
+    ///
+    /// ## Params
+    /// - `state_dir` The directory GSync keeps its state in
+    pub fn input_problems(&self, state_dir: &Path) -> Vec<String> {
+        let state_dir = fs::canonicalize(state_dir).unwrap_or_else(|_| state_dir.to_path_buf());
+
+        let mut problems = Vec::new();
+        for input in self.input_files.as_deref().unwrap_or_default().split(',').filter(|input| !input.is_empty()) {
+            let path = match fs::canonicalize(input) {
+                Ok(path) => path,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    problems.push(format!("Input '{}' does not exist", input));
+                    continue;
+                },
+                Err(e) => {
+                    problems.push(format!("Input '{}' can't be accessed: {}", input, e));
+                    continue;
+                }
+            };
+
+            if path.starts_with(&state_dir) {
+                problems.push(format!("Input '{}' is inside GSync's state directory '{}'", input, state_dir.to_string_lossy()));
+                continue;
+            }
+
+            let readable = if path.is_dir() {
+                fs::read_dir(&path).map(|_| ())
+            } else {
+                fs::File::open(&path).map(|_| ())
+            };
+
+            if let Err(e) = readable {
+                problems.push(format!("Input '{}' can't be read: {}", input, e));
+            }
+        }
+
+        problems
+    }
+
+    /// Unset a field, so its default is used again. Returns false if there is no field with that name
+    pub fn unset(&mut self, field: &str) -> bool {
+        match field {
+            "client_id" => self.client_id = None,
+            "client_secret" => self.client_secret = None,
+            "input_files" => self.input_files = None,
+            "drive_id" => self.drive_id = None,
+            "quota_threshold" => self.quota_threshold = None,
+            "quota_action" => self.quota_action = None,
+            "preserve_xattrs" => self.preserve_xattrs = None,
+            "max_jobs" => self.max_jobs = None,
+            "service_account_key" => self.service_account_key = None,
+            "impersonate" => self.impersonate = None,
+            "labels" => self.labels = None,
+            "upload_reports" => self.upload_reports = None,
+            "history_retention" => self.history_retention = None,
+            "delete_limit" => self.delete_limit = None,
+            "target_folder" => self.target_folder = None,
+            "audit_log" => self.audit_log = None,
+            "snapshot_full_every" => self.snapshot_full_every = None,
+            "scrub_window" => self.scrub_window = None,
+            "compare" => self.compare = None,
+            "symlinks" => self.symlinks = None,
+            "mtime_tolerance" => self.mtime_tolerance = None,
+            "hash_algorithm" => self.hash_algorithm = None,
+            "state_passphrase" => self.state_passphrase = None,
+            "excludes" => self.excludes = None,
+            "mime_types" => self.mime_types = None,
+            "convert" => self.convert = None,
+            "delete_policy" => self.delete_policy = None,
+            "max_retries" => self.max_retries = None,
+            "bwlimit" => self.bwlimit = None,
+            "max_size" => self.max_size = None,
+            "pack_sparse" => self.pack_sparse = None,
+            _ => return false
+        }
+
+        true
+    }
+
+    /// Set a field from its textual value, e.g. as read from an environment variable. Lists are comma separated
+    ///
+    /// ## Errors
+    /// - When there is no field with that name
+    /// - When the value is not valid for the type of the field
+    pub fn set(&mut self, field: &str, value: &str) -> std::result::Result<(), String> {
+        match field {
+            "client_id" => self.client_id = Some(value.to_string()),
+            "client_secret" => self.client_secret = Some(value.to_string()),
+            "input_files" => self.input_files = Some(value.to_string()),
+            "drive_id" => self.drive_id = Some(value.to_string()),
+            "quota_threshold" => self.quota_threshold = Some(parse_number(field, value)?),
+            "quota_action" => self.quota_action = Some(value.to_string()),
+            "preserve_xattrs" => self.preserve_xattrs = Some(parse_bool(field, value)?),
+            "max_jobs" => self.max_jobs = Some(parse_number(field, value)?),
+            "service_account_key" => self.service_account_key = Some(value.to_string()),
+            "impersonate" => self.impersonate = Some(value.to_string()),
+            "labels" => self.labels = Some(value.to_string()),
+            "upload_reports" => self.upload_reports = Some(parse_bool(field, value)?),
+            "history_retention" => self.history_retention = Some(parse_number(field, value)?),
+            "delete_limit" => self.delete_limit = Some(value.to_string()),
+            "target_folder" => self.target_folder = Some(value.to_string()),
+            "audit_log" => self.audit_log = Some(value.to_string()),
+            "snapshot_full_every" => self.snapshot_full_every = Some(parse_number(field, value)?),
+            "scrub_window" => self.scrub_window = Some(parse_number(field, value)?),
+            "compare" => self.compare = Some(value.to_string()),
+            "symlinks" => self.symlinks = Some(value.to_string()),
+            "mtime_tolerance" => self.mtime_tolerance = Some(parse_number(field, value)?),
+            "hash_algorithm" => self.hash_algorithm = Some(value.to_string()),
+            "state_passphrase" => self.state_passphrase = Some(value.to_string()),
+            "excludes" => self.excludes = Some(value.to_string()),
+            "mime_types" => self.mime_types = Some(value.to_string()),
+            "convert" => self.convert = Some(parse_bool(field, value)?),
+            "delete_policy" => self.delete_policy = Some(value.to_string()),
+            "max_retries" => self.max_retries = Some(parse_number(field, value)?),
+            "bwlimit" => self.bwlimit = Some(value.to_string()),
+            "max_size" => self.max_size = Some(parse_number(field, value)?),
+            "pack_sparse" => self.pack_sparse = Some(parse_bool(field, value)?),
+            _ => return Err(format!("'{}' is not a configuration field", field))
+        }
+
+        Ok(())
+    }
+
     /// Merge two Configurations, where `a` is seen as more important than `b`
     pub fn merge(a: Self, b: Self) -> Self {
         let mut output = Self::empty();
@@ -75,6 +384,141 @@ impl Configuration {
             None => output.drive_id = b.drive_id
         }
 
+        match a.quota_threshold {
+            Some(s) => output.quota_threshold = Some(s),
+            None => output.quota_threshold = b.quota_threshold
+        }
+
+        match a.quota_action {
+            Some(s) => output.quota_action = Some(s),
+            None => output.quota_action = b.quota_action
+        }
+
+        match a.preserve_xattrs {
+            Some(s) => output.preserve_xattrs = Some(s),
+            None => output.preserve_xattrs = b.preserve_xattrs
+        }
+
+        match a.max_jobs {
+            Some(s) => output.max_jobs = Some(s),
+            None => output.max_jobs = b.max_jobs
+        }
+
+        match a.service_account_key {
+            Some(s) => output.service_account_key = Some(s),
+            None => output.service_account_key = b.service_account_key
+        }
+
+        match a.impersonate {
+            Some(s) => output.impersonate = Some(s),
+            None => output.impersonate = b.impersonate
+        }
+
+        match a.labels {
+            Some(s) => output.labels = Some(s),
+            None => output.labels = b.labels
+        }
+
+        match a.upload_reports {
+            Some(s) => output.upload_reports = Some(s),
+            None => output.upload_reports = b.upload_reports
+        }
+
+        match a.history_retention {
+            Some(s) => output.history_retention = Some(s),
+            None => output.history_retention = b.history_retention
+        }
+
+        match a.delete_limit {
+            Some(s) => output.delete_limit = Some(s),
+            None => output.delete_limit = b.delete_limit
+        }
+
+        match a.target_folder {
+            Some(s) => output.target_folder = Some(s),
+            None => output.target_folder = b.target_folder
+        }
+
+        match a.audit_log {
+            Some(s) => output.audit_log = Some(s),
+            None => output.audit_log = b.audit_log
+        }
+
+        match a.snapshot_full_every {
+            Some(s) => output.snapshot_full_every = Some(s),
+            None => output.snapshot_full_every = b.snapshot_full_every
+        }
+
+        match a.scrub_window {
+            Some(s) => output.scrub_window = Some(s),
+            None => output.scrub_window = b.scrub_window
+        }
+
+        match a.compare {
+            Some(s) => output.compare = Some(s),
+            None => output.compare = b.compare
+        }
+
+        match a.symlinks {
+            Some(s) => output.symlinks = Some(s),
+            None => output.symlinks = b.symlinks
+        }
+
+        match a.mtime_tolerance {
+            Some(s) => output.mtime_tolerance = Some(s),
+            None => output.mtime_tolerance = b.mtime_tolerance
+        }
+
+        match a.hash_algorithm {
+            Some(s) => output.hash_algorithm = Some(s),
+            None => output.hash_algorithm = b.hash_algorithm
+        }
+
+        match a.state_passphrase {
+            Some(s) => output.state_passphrase = Some(s),
+            None => output.state_passphrase = b.state_passphrase
+        }
+
+        match a.excludes {
+            Some(s) => output.excludes = Some(s),
+            None => output.excludes = b.excludes
+        }
+
+        match a.mime_types {
+            Some(s) => output.mime_types = Some(s),
+            None => output.mime_types = b.mime_types
+        }
+
+        match a.convert {
+            Some(s) => output.convert = Some(s),
+            None => output.convert = b.convert
+        }
+
+        match a.delete_policy {
+            Some(s) => output.delete_policy = Some(s),
+            None => output.delete_policy = b.delete_policy
+        }
+
+        match a.max_retries {
+            Some(s) => output.max_retries = Some(s),
+            None => output.max_retries = b.max_retries
+        }
+
+        match a.bwlimit {
+            Some(s) => output.bwlimit = Some(s),
+            None => output.bwlimit = b.bwlimit
+        }
+
+        match a.max_size {
+            Some(s) => output.max_size = Some(s),
+            None => output.max_size = b.max_size
+        }
+
+        match a.pack_sparse {
+            Some(s) => output.pack_sparse = Some(s),
+            None => output.pack_sparse = b.pack_sparse
+        }
+
         output
     }
 
@@ -94,8 +538,35 @@ impl Configuration {
                 let client_secret = unwrap_db_err!(row.get::<&str, Option<String>>("client_secret"));
                 let input_files = unwrap_db_err!(row.get::<&str, Option<String>>("input_files"));
                 let drive_id = unwrap_db_err!(row.get::<&str, Option<String>>("drive_id"));
+                let quota_threshold = unwrap_db_err!(row.get::<&str, Option<i64>>("quota_threshold"));
+                let quota_action = unwrap_db_err!(row.get::<&str, Option<String>>("quota_action"));
+                let preserve_xattrs = unwrap_db_err!(row.get::<&str, Option<bool>>("preserve_xattrs"));
+                let max_jobs = unwrap_db_err!(row.get::<&str, Option<i64>>("max_jobs"));
+                let service_account_key = unwrap_db_err!(row.get::<&str, Option<String>>("service_account_key"));
+                let impersonate = unwrap_db_err!(row.get::<&str, Option<String>>("impersonate"));
+                let labels = unwrap_db_err!(row.get::<&str, Option<String>>("labels"));
+                let upload_reports = unwrap_db_err!(row.get::<&str, Option<bool>>("upload_reports"));
+                let history_retention = unwrap_db_err!(row.get::<&str, Option<i64>>("history_retention"));
+                let delete_limit = unwrap_db_err!(row.get::<&str, Option<String>>("delete_limit"));
+                let target_folder = unwrap_db_err!(row.get::<&str, Option<String>>("target_folder"));
+                let audit_log = unwrap_db_err!(row.get::<&str, Option<String>>("audit_log"));
+                let snapshot_full_every = unwrap_db_err!(row.get::<&str, Option<i64>>("snapshot_full_every"));
+                let scrub_window = unwrap_db_err!(row.get::<&str, Option<i64>>("scrub_window"));
+                let compare = unwrap_db_err!(row.get::<&str, Option<String>>("compare"));
+                let symlinks = unwrap_db_err!(row.get::<&str, Option<String>>("symlinks"));
+                let mtime_tolerance = unwrap_db_err!(row.get::<&str, Option<i64>>("mtime_tolerance"));
+                let hash_algorithm = unwrap_db_err!(row.get::<&str, Option<String>>("hash_algorithm"));
+                let state_passphrase = unwrap_db_err!(row.get::<&str, Option<String>>("state_passphrase"));
+                let excludes = unwrap_db_err!(row.get::<&str, Option<String>>("excludes"));
+                let mime_types = unwrap_db_err!(row.get::<&str, Option<String>>("mime_types"));
+                let convert = unwrap_db_err!(row.get::<&str, Option<bool>>("convert"));
+                let delete_policy = unwrap_db_err!(row.get::<&str, Option<String>>("delete_policy"));
+                let max_retries = unwrap_db_err!(row.get::<&str, Option<i64>>("max_retries"));
+                let bwlimit = unwrap_db_err!(row.get::<&str, Option<String>>("bwlimit"));
+                let max_size = unwrap_db_err!(row.get::<&str, Option<i64>>("max_size"));
+                let pack_sparse = unwrap_db_err!(row.get::<&str, Option<bool>>("pack_sparse"));
 
-                Ok(Self { client_id, client_secret, input_files, drive_id })
+                Ok(Self { client_id, client_secret, input_files, drive_id, quota_threshold, quota_action, preserve_xattrs, max_jobs, service_account_key, impersonate, labels, upload_reports, history_retention, delete_limit, target_folder, audit_log, snapshot_full_every, scrub_window, compare, symlinks, mtime_tolerance, hash_algorithm, state_passphrase, excludes, mime_types, convert, delete_policy, max_retries, bwlimit, max_size, pack_sparse })
             },
             Ok(None) => Ok(Self::empty()),
             Err(e) => Err((Error::DatabaseError(e), line!(), file!()))
@@ -111,14 +582,271 @@ impl Configuration {
 
         unwrap_db_err!(conn.execute("DELETE FROM config", named_params! {}));
 
-        unwrap_db_err!(conn.execute("INSERT INTO config (client_id, client_secret, input_files, drive_id) VALUES (:client_id, :client_secret, :input_files, :drive_id)", named_params! {
+        unwrap_db_err!(conn.execute("INSERT INTO config (client_id, client_secret, input_files, drive_id, quota_threshold, quota_action, preserve_xattrs, max_jobs, service_account_key, impersonate, labels, upload_reports, history_retention, delete_limit, target_folder, audit_log, snapshot_full_every, scrub_window, compare, symlinks, mtime_tolerance, hash_algorithm, state_passphrase, excludes, mime_types, convert, delete_policy, max_retries, bwlimit, max_size, pack_sparse) VALUES (:client_id, :client_secret, :input_files, :drive_id, :quota_threshold, :quota_action, :preserve_xattrs, :max_jobs, :service_account_key, :impersonate, :labels, :upload_reports, :history_retention, :delete_limit, :target_folder, :audit_log, :snapshot_full_every, :scrub_window, :compare, :symlinks, :mtime_tolerance, :hash_algorithm, :state_passphrase, :excludes, :mime_types, :convert, :delete_policy, :max_retries, :bwlimit, :max_size, :pack_sparse)", named_params! {
             ":client_id":       &self.client_id,
             ":client_secret":   &self.client_secret,
             ":input_files":     &self.input_files,
-            ":drive_id":         &self.drive_id
+            ":drive_id":         &self.drive_id,
+            ":quota_threshold": &self.quota_threshold,
+            ":quota_action":    &self.quota_action,
+            ":preserve_xattrs": &self.preserve_xattrs,
+            ":max_jobs": &self.max_jobs,
+            ":service_account_key": &self.service_account_key,
+            ":impersonate": &self.impersonate,
+            ":labels": &self.labels,
+            ":upload_reports": &self.upload_reports,
+            ":history_retention": &self.history_retention,
+            ":delete_limit": &self.delete_limit,
+            ":target_folder": &self.target_folder,
+            ":audit_log": &self.audit_log,
+            ":snapshot_full_every": &self.snapshot_full_every,
+            ":scrub_window": &self.scrub_window,
+            ":compare": &self.compare,
+            ":symlinks": &self.symlinks,
+            ":mtime_tolerance": &self.mtime_tolerance,
+            ":hash_algorithm": &self.hash_algorithm,
+            ":state_passphrase": &self.state_passphrase,
+            ":excludes": &self.excludes,
+            ":mime_types": &self.mime_types,
+            ":convert": &self.convert,
+            ":delete_policy": &self.delete_policy,
+            ":max_retries": &self.max_retries,
+            ":bwlimit": &self.bwlimit,
+            ":max_size": &self.max_size,
+            ":pack_sparse": &self.pack_sparse
         }));
 
         Ok(())
     }
 }
 
+/// Struct describing the configuration of a single input, overriding the global configuration
+#[derive(Debug, Clone)]
+pub struct InputConfiguration {
+    /// The normalized path of the input
+    pub path:       String,
+
+    /// If set, the ID of the Team Drive/Shared Drive to sync this input to. `root` means the input is synced to My Drive
+    pub drive_id:   Option<String>,
+
+    /// If set, the path of the folder to sync this input into, relative to the root of the drive, e.g. `Backups/Docs`
+    pub remote_root: Option<String>,
+
+    /// If set, the ID of an existing folder to sync this input into. Takes precedence over `remote_root`
+    pub remote_root_id: Option<String>,
+
+    /// Comma separated patterns of files and folders in this input which are not synced
+    pub excludes:   Option<String>,
+
+    /// Whether .gitignore files in this input are respected. Defaults to true
+    pub gitignore:  Option<bool>,
+
+    /// If set, files in this input larger than this many bytes are not synced
+    pub max_size:   Option<i64>,
+
+    /// How GSync decides whether a file in this input changed, overriding the configured policy
+    pub compare:    Option<String>,
+
+    /// How symbolic links in this input are synced, overriding the configured policy
+    pub symlinks:   Option<String>,
+
+    /// The kind of filesystem snapshot this input is synced from, if any: btrfs, zfs, lvm or vss
+    pub fs_snapshot: Option<String>
+}
+
+/// Split comma separated patterns of files and folders
+fn split_patterns(patterns: Option<&str>) -> Vec<String> {
+    patterns.unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|pattern| !pattern.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Where in Google Drive an input is synced to
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum RemoteRoot {
+    /// The 'GSync' folder, or the configured target folder
+    Default,
+
+    /// A folder path relative to the root of the drive. Missing folders are created
+    Path(String),
+
+    /// An existing folder, by its ID
+    Id(String)
+}
+
+impl InputConfiguration {
+
+    /// Create an empty configuration for the input with the provided path
+    pub fn empty(path: &str) -> Self {
+        Self {
+            path:       path.to_string(),
+            drive_id:   None,
+            remote_root: None,
+            remote_root_id: None,
+            excludes:   None,
+            gitignore:  None,
+            max_size:   None,
+            compare:    None,
+            symlinks:   None,
+            fs_snapshot: None
+        }
+    }
+
+    /// Get the patterns of files and folders in this input which are not synced
+    pub fn excludes(&self) -> Vec<String> {
+        split_patterns(self.excludes.as_deref())
+    }
+
+    /// Get the folder this input should be synced into
+    pub fn remote_root(&self) -> RemoteRoot {
+        match (&self.remote_root_id, &self.remote_root) {
+            (Some(id), _) => RemoteRoot::Id(id.clone()),
+            (None, Some(path)) => RemoteRoot::Path(path.clone()),
+            (None, None) => RemoteRoot::Default
+        }
+    }
+
+    /// Get the ID of the drive this input should be synced to, where None means My Drive
+    pub fn target_drive(&self, global_drive_id: Option<&String>) -> Option<String> {
+        match self.drive_id.as_deref() {
+            Some("root") => None,
+            Some(drive_id) => Some(drive_id.to_string()),
+            None => global_drive_id.cloned()
+        }
+    }
+
+    /// Get the configuration of all inputs from the database
+    ///
+    /// ## Error
+    /// - When a database operation fails
+    pub fn get_all(env: &Env) -> Result<Vec<Self>> {
+        let conn = unwrap_db_err!(env.get_conn());
+
+        let mut stmt = unwrap_db_err!(conn.prepare("SELECT * FROM inputs"));
+        let mut result = unwrap_db_err!(stmt.query(named_params! {}));
+
+        let mut inputs = Vec::new();
+        while let Some(row) = unwrap_db_err!(result.next()) {
+            let path = unwrap_db_err!(row.get::<&str, String>("path"));
+            let drive_id = unwrap_db_err!(row.get::<&str, Option<String>>("drive_id"));
+            let remote_root = unwrap_db_err!(row.get::<&str, Option<String>>("remote_root"));
+            let remote_root_id = unwrap_db_err!(row.get::<&str, Option<String>>("remote_root_id"));
+            let excludes = unwrap_db_err!(row.get::<&str, Option<String>>("excludes"));
+            let gitignore = unwrap_db_err!(row.get::<&str, Option<bool>>("gitignore"));
+            let max_size = unwrap_db_err!(row.get::<&str, Option<i64>>("max_size"));
+            let compare = unwrap_db_err!(row.get::<&str, Option<String>>("compare"));
+            let symlinks = unwrap_db_err!(row.get::<&str, Option<String>>("symlinks"));
+            let fs_snapshot = unwrap_db_err!(row.get::<&str, Option<String>>("fs_snapshot"));
+
+            inputs.push(Self { path, drive_id, remote_root, remote_root_id, excludes, gitignore, max_size, compare, symlinks, fs_snapshot });
+        }
+
+        Ok(inputs)
+    }
+
+    /// Get the configuration of the input with the provided path from the database.
+    /// If the input has no configuration, an empty configuration is returned
+    ///
+    /// ## Error
+    /// - When a database operation fails
+    pub fn get(env: &Env, path: &str) -> Result<Self> {
+        let input = Self::get_all(env)?.into_iter()
+            .find(|input| input.path.eq(path))
+            .unwrap_or_else(|| Self::empty(path));
+
+        Ok(input)
+    }
+
+    /// Write the configuration of this input to the database
+    ///
+    /// ## Error
+    /// - When a database operation fails
+    pub fn write(&self, env: &Env) -> Result<()> {
+        let conn = unwrap_db_err!(env.get_conn());
+
+        unwrap_db_err!(conn.execute("INSERT OR REPLACE INTO inputs (path, drive_id, remote_root, remote_root_id, excludes, gitignore, max_size, compare, symlinks, fs_snapshot) VALUES (:path, :drive_id, :remote_root, :remote_root_id, :excludes, :gitignore, :max_size, :compare, :symlinks, :fs_snapshot)", named_params! {
+            ":path":        &self.path,
+            ":drive_id":    &self.drive_id,
+            ":remote_root": &self.remote_root,
+            ":remote_root_id": &self.remote_root_id,
+            ":excludes":    &self.excludes,
+            ":gitignore":   &self.gitignore,
+            ":max_size":    &self.max_size,
+            ":compare":     &self.compare,
+            ":symlinks":    &self.symlinks,
+            ":fs_snapshot": &self.fs_snapshot
+        }));
+
+        Ok(())
+    }
+}
+
+/// Parse the value of a numeric configuration field
+fn parse_number(field: &str, value: &str) -> std::result::Result<i64, String> {
+    value.trim().parse().map_err(|_| format!("'{}' is not a valid number for '{}'", value, field))
+}
+
+/// Parse the value of a boolean configuration field, `true` or `false`
+fn parse_bool(field: &str, value: &str) -> std::result::Result<bool, String> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(format!("'{}' is not true or false for '{}'", value, field))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::config::{Configuration, FIELD_NAMES};
+
+    #[test]
+    fn input_problems() {
+        let dir = std::env::temp_dir().join(format!("gsync-test-inputs-{}", std::process::id()));
+        let state_dir = dir.join(".gsync");
+        std::fs::create_dir_all(&state_dir).unwrap();
+
+        let mut config = Configuration::empty();
+        config.input_files = Some(format!("{},{},{}", dir.to_string_lossy(), dir.join("missing").to_string_lossy(), state_dir.to_string_lossy()));
+        let problems = config.input_problems(&state_dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(2, problems.len());
+        assert!(problems[0].contains("does not exist"));
+        assert!(problems[1].contains("state directory"));
+    }
+
+    #[test]
+    fn unset() {
+        let mut config = Configuration::empty();
+        config.drive_id = Some("shared".to_string());
+        config.max_jobs = Some(4);
+
+        assert!(config.unset("drive_id"));
+        assert_eq!(None, config.drive_id);
+        assert_eq!(Some(4), config.max_jobs);
+        assert!(!config.unset("unknown"));
+
+        for field in FIELD_NAMES.iter() {
+            assert!(config.unset(field));
+        }
+        assert!(config.is_empty());
+    }
+
+    #[test]
+    fn set() {
+        let mut config = Configuration::empty();
+        config.set("drive_id", "shared").unwrap();
+        config.set("max_jobs", "8").unwrap();
+        config.set("convert", "TRUE").unwrap();
+
+        assert_eq!(Some("shared".to_string()), config.drive_id);
+        assert_eq!(Some(8), config.max_jobs);
+        assert_eq!(Some(true), config.convert);
+        assert!(config.set("max_jobs", "many").is_err());
+        assert!(config.set("convert", "yes").is_err());
+        assert!(config.set("unknown", "value").is_err());
+    }
+}
@@ -0,0 +1,164 @@
+//! Module for running GSync in a container, e.g. as a Docker or Kubernetes backup job. The configuration comes from environment variables and
+//! an optional mounted configuration file instead of `gsync config`, nothing ever waits for input, and a single sync is run after which GSync exits
+//! with a code telling how it went. Errors are written to stderr as a single line of JSON, so the orchestrator can act on them
+
+use crate::config::{Configuration, FIELD_NAMES};
+use crate::env::Env;
+use crate::report::Progress;
+use serde::Serialize;
+use std::path::Path;
+
+/// The environment variable pointing to the mounted configuration file
+pub const CONFIG_FILE_VAR: &str = "GSYNC_CONFIG_FILE";
+
+/// The prefix of the environment variables configuring GSync. Every configuration field can be set as the prefix followed by its name in upper case, e.g. `GSYNC_DRIVE_ID`
+pub const ENV_PREFIX: &str = "GSYNC_";
+
+/// The outcomes of a run in a container, each with its own exit code
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Outcome {
+    /// The sync completed without failures
+    Success,
+
+    /// The sync completed, but some files failed to sync
+    CompletedWithFailures,
+
+    /// The configuration is missing, incomplete or invalid
+    ConfigurationError,
+
+    /// There are no credentials to authenticate with Google
+    AuthenticationError,
+
+    /// The sync was aborted
+    SyncFailed
+}
+
+impl Outcome {
+    /// The exit code of this outcome
+    pub fn code(self) -> i32 {
+        match self {
+            Self::Success => 0,
+            Self::CompletedWithFailures => 2,
+            Self::ConfigurationError => 3,
+            Self::AuthenticationError => 4,
+            Self::SyncFailed => 5
+        }
+    }
+}
+
+/// Struct describing the machine-readable result of a run in a container
+#[derive(Debug, Serialize)]
+struct RunResult<'a> {
+    /// How the run went
+    outcome:    Outcome,
+
+    /// The exit code GSync exits with
+    code:       i32,
+
+    /// What went wrong, if anything
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message:    Option<&'a str>,
+
+    /// The counters of the sync, if it ran
+    #[serde(skip_serializing_if = "Option::is_none")]
+    run:        Option<Progress>
+}
+
+/// Load the configuration of a run in a container. Fields set in environment variables take precedence over the configuration file,
+/// which takes precedence over the configuration stored in the state directory, if any
+///
+/// ## Params
+/// - `env` Env instance
+/// - `file` The mounted configuration file, a TOML file with the configuration fields as keys. Lists may be given as arrays
+///
+/// ## Errors
+/// - When reading the stored configuration or the configuration file fails
+/// - When the configuration file or an environment variable sets an unknown field, or a value of the wrong type
+pub fn load_config(env: &Env, file: Option<&Path>) -> std::result::Result<Configuration, String> {
+    let stored = Configuration::get_config(env).map_err(|(e, _, _)| format!("Failed to read the stored configuration: {}", crate::redact::redact(format!("{:?}", e))))?;
+    let mut overrides = Configuration::empty();
+
+    if let Some(file) = file {
+        let contents = std::fs::read_to_string(file).map_err(|e| format!("Failed to read the configuration file '{}': {}", file.to_string_lossy(), e))?;
+        apply_file(&mut overrides, &contents).map_err(|e| format!("Invalid configuration file '{}': {}", file.to_string_lossy(), e))?;
+    }
+
+    for field in FIELD_NAMES.iter() {
+        let variable = format!("{}{}", ENV_PREFIX, field.to_ascii_uppercase());
+        if let Ok(value) = std::env::var(&variable) {
+            overrides.set(field, &value).map_err(|e| format!("Invalid value in {}: {}", variable, e))?;
+        }
+    }
+
+    Ok(Configuration::merge(overrides, stored))
+}
+
+/// Set the fields in a configuration file on a configuration
+///
+/// ## Errors
+/// - When the file is not valid TOML
+/// - When the file sets an unknown field, or a value of the wrong type
+fn apply_file(config: &mut Configuration, contents: &str) -> std::result::Result<(), String> {
+    let table = match contents.parse::<toml::Value>().map_err(|e| e.to_string())? {
+        toml::Value::Table(table) => table,
+        _ => return Err("expected a table of configuration fields".to_string())
+    };
+
+    for (field, value) in table {
+        let value = match value {
+            toml::Value::String(value) => value,
+            toml::Value::Integer(value) => value.to_string(),
+            toml::Value::Boolean(value) => value.to_string(),
+            toml::Value::Array(values) => values.iter()
+                .map(|value| value.as_str().map(String::from).ok_or_else(|| format!("'{}' may only hold strings", field)))
+                .collect::<std::result::Result<Vec<_>, _>>()?
+                .join(","),
+            _ => return Err(format!("'{}' has a value of an unsupported type", field))
+        };
+
+        config.set(&field, &value)?;
+    }
+
+    Ok(())
+}
+
+/// Print the result of a run as a line of JSON, to stdout if it succeeded and to stderr otherwise, and exit with the code of its outcome
+pub fn exit(outcome: Outcome, message: Option<&str>, run: Option<Progress>) -> ! {
+    let message = message.map(crate::redact::redact);
+    let result = RunResult {
+        outcome,
+        code:       outcome.code(),
+        message:    message.as_deref(),
+        run
+    };
+
+    // Serializing can't fail, the result only holds strings and numbers
+    let json = serde_json::to_string(&result).unwrap_or_default();
+    match outcome {
+        Outcome::Success | Outcome::CompletedWithFailures => println!("{}", json),
+        _ => eprintln!("{}", json)
+    }
+
+    std::process::exit(outcome.code())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::config::Configuration;
+    use crate::container::apply_file;
+
+    #[test]
+    fn configuration_file() {
+        let mut config = Configuration::empty();
+        apply_file(&mut config, "input_files = [\"/data\", \"/etc\"]\nmax_jobs = 2\nupload_reports = true\nservice_account_key = \"/secrets/key.json\"").unwrap();
+
+        assert_eq!(Some("/data,/etc".to_string()), config.input_files);
+        assert_eq!(Some(2), config.max_jobs);
+        assert_eq!(Some(true), config.upload_reports);
+        assert_eq!(Some("/secrets/key.json".to_string()), config.service_account_key);
+
+        assert!(apply_file(&mut config, "unknown = 1").is_err());
+        assert!(apply_file(&mut config, "max_jobs = \"many\"").is_err());
+    }
+}
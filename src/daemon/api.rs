@@ -0,0 +1,204 @@
+//! HTTP API for triggering syncs and querying their results, e.g. from home automation or scripts on other hosts.
+//! All requests must carry the configured token as `Authorization: Bearer <token>`
+
+use crate::env::Env;
+use crate::daemon::{ApiOptions, DaemonState};
+use crate::report::{Progress, RunRecord};
+use crate::scrub::Finding;
+use crate::Result;
+use actix_web::{get, post, web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::Sender;
+
+/// The number of runs returned by `GET /runs` if no limit is given
+const DEFAULT_RUNS: u32 = 10;
+
+/// The maximum number of runs returned by `GET /runs`
+const MAX_RUNS: u32 = 1000;
+
+/// Struct describing the data passed to the API endpoints
+#[derive(Clone)]
+struct ApiData {
+    /// Env instance
+    env:        Env,
+
+    /// The state of the daemon
+    state:      Arc<Mutex<DaemonState>>,
+
+    /// Channel on which a sync can be requested
+    trigger:    Sender<()>,
+
+    /// The token clients must authenticate with
+    token:      String
+}
+
+/// Response of `GET /status`
+#[derive(Serialize)]
+struct StatusResponse {
+    /// Whether a sync is currently running
+    running:            bool,
+
+    /// The progress of the current sync, if one is running
+    progress:           Option<Progress>,
+
+    /// The time between two syncs in seconds
+    interval:           u64,
+
+    /// The epoch time in seconds at which the next sync starts, if no sync is running
+    next_run:           Option<i64>,
+
+    /// The most recent errors, newest first
+    errors:             Vec<ErrorResponse>,
+
+    /// The files which failed their last verification by the scrub after every sync, newest first
+    findings:           Vec<Finding>
+}
+
+/// An error which occurred during a sync
+#[derive(Serialize)]
+struct ErrorResponse {
+    /// The epoch time in seconds at which the error occurred
+    time:       i64,
+
+    /// The error
+    message:    String
+}
+
+/// Query parameters of `GET /runs`
+#[derive(Deserialize)]
+struct RunsQuery {
+    /// The maximum number of runs to return
+    limit:      Option<u32>,
+
+    /// In the multi-user mode, the user whose runs to return
+    user:       Option<String>
+}
+
+/// Response of `GET /runs`
+#[derive(Serialize)]
+struct RunsResponse {
+    /// The most recent runs, newest first
+    runs:       Vec<RunRecord>
+}
+
+/// Start the API on its own thread
+///
+/// ## Errors
+/// - When binding to the address fails
+pub fn start(env: Env, state: Arc<Mutex<DaemonState>>, trigger: Sender<()>, options: &ApiOptions) -> Result<()> {
+    let data = ApiData { env, state, trigger, token: options.token.clone() };
+    super::start_server("HTTP API", &options.bind, options.port, move |cfg| {
+        cfg.data(data.clone())
+            .service(sync)
+            .service(status)
+            .service(runs);
+    })
+}
+
+/// Check if the request carries the correct token
+fn is_authorized(req: &HttpRequest, token: &str) -> bool {
+    let header = match req.headers().get("Authorization").and_then(|header| header.to_str().ok()) {
+        Some(header) => header,
+        None => return false
+    };
+
+    match header.strip_prefix("Bearer ") {
+        Some(given) => constant_time_eq(given.as_bytes(), token.as_bytes()),
+        None => false
+    }
+}
+
+/// Compare two byte slices in time independent of where they differ, so the token can't be guessed byte by byte
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    a.iter().zip(b.iter()).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Response for requests without the correct token
+fn unauthorized() -> HttpResponse {
+    HttpResponse::Unauthorized()
+        .header("WWW-Authenticate", "Bearer")
+        .json(serde_json::json!({ "error": "Missing or invalid token" }))
+}
+
+/// Start a sync. Responds with 409 if a sync is already running
+#[post("/sync")]
+async fn sync(req: HttpRequest, data: web::Data<ApiData>) -> HttpResponse {
+    if !is_authorized(&req, &data.token) {
+        return unauthorized();
+    }
+
+    if data.state.lock().unwrap().current.is_some() {
+        return HttpResponse::Conflict().json(serde_json::json!({ "error": "A sync is already running" }));
+    }
+
+    match data.trigger.send(()) {
+        Ok(_) => HttpResponse::Accepted().json(serde_json::json!({ "started": true })),
+        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": "The daemon is not running" }))
+    }
+}
+
+/// The state of the daemon: whether a sync is running and its progress, the schedule and recent errors
+#[get("/status")]
+async fn status(req: HttpRequest, data: web::Data<ApiData>) -> HttpResponse {
+    if !is_authorized(&req, &data.token) {
+        return unauthorized();
+    }
+
+    let state = data.state.lock().unwrap();
+    HttpResponse::Ok().json(StatusResponse {
+        running:    state.current.is_some(),
+        progress:   state.current.as_ref().map(|report| report.snapshot()),
+        interval:   state.interval.as_secs(),
+        next_run:   state.next_run.map(|next_run| next_run.timestamp()),
+        errors:     state.errors.iter().rev().map(|(time, message)| ErrorResponse { time: time.timestamp(), message: message.clone() }).collect(),
+        findings:   state.findings.clone()
+    })
+}
+
+/// The most recent runs from the run history
+#[get("/runs")]
+async fn runs(req: HttpRequest, data: web::Data<ApiData>, query: web::Query<RunsQuery>) -> HttpResponse {
+    if !is_authorized(&req, &data.token) {
+        return unauthorized();
+    }
+
+    let limit = query.limit.unwrap_or(DEFAULT_RUNS).min(MAX_RUNS);
+    let user = {
+        let state = data.state.lock().unwrap();
+        if state.users.is_empty() {
+            None
+        } else {
+            match state.users.iter().find(|user| Some(&user.name) == query.user.as_ref()) {
+                Some(user) => Some(user.clone()),
+                None => return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Specify one of the synced users with ?user=<NAME>" }))
+            }
+        }
+    };
+
+    // The database of a user is only read by a child process running as that user
+    let env = data.env.clone();
+    match web::block(move || match user {
+        Some(user) => user.recent_runs(limit),
+        None => crate::report::recent_runs(&env, limit)
+    }).await {
+        Ok(runs) => HttpResponse::Ok().json(RunsResponse { runs }),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({ "error": crate::redact::redact(format!("Failed to read the run history: {:?}", e)) }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::daemon::api::constant_time_eq;
+
+    #[test]
+    fn constant_time_eq_compares() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret2"));
+    }
+}
@@ -0,0 +1,128 @@
+//! Local web page showing the status of the daemon
+
+use crate::env::Env;
+use crate::daemon::DaemonState;
+use crate::daemon::users::ServiceUser;
+use crate::Result;
+use crate::login::callback_endpoint::escape_html;
+use crate::size::format_size;
+use actix_web::{get, web, HttpResponse};
+use chrono::NaiveDateTime;
+use std::sync::{Arc, Mutex};
+
+/// The number of runs from the run history shown on the dashboard
+const RECENT_RUNS: u32 = 10;
+
+/// Struct describing the data passed to the dashboard endpoint
+#[derive(Clone)]
+struct DashboardData {
+    /// Env instance
+    env:    Env,
+
+    /// The state of the daemon
+    state:  Arc<Mutex<DaemonState>>
+}
+
+/// Start the dashboard on its own thread
+///
+/// ## Errors
+/// - When binding to the address fails
+pub fn start(env: Env, state: Arc<Mutex<DaemonState>>, bind: &str, port: u16) -> Result<()> {
+    let data = DashboardData { env, state };
+    super::start_server("dashboard", bind, port, move |cfg| {
+        cfg.data(data.clone()).service(dashboard);
+    })
+}
+
+/// The dashboard page
+#[get("/")]
+async fn dashboard(data: web::Data<DashboardData>) -> HttpResponse {
+    let mut body = String::new();
+
+    // In the multi-user mode every user has their own run history
+    let histories = {
+        let state = data.state.lock().unwrap();
+        body.push_str("<h2>Schedule</h2>\n");
+        body.push_str(&format!("<p>Every {} minutes. ", state.interval.as_secs() / 60));
+        match (&state.current, state.next_run) {
+            (Some(report), _) => body.push_str(&format!("Syncing now: {}</p>\n", escape_html(&report.progress()))),
+            (None, Some(next_run)) => body.push_str(&format!("Next sync at {}</p>\n", next_run.to_rfc3339())),
+            (None, None) => body.push_str("</p>\n")
+        }
+
+        body.push_str("<h2>Recent errors</h2>\n");
+        if state.errors.is_empty() {
+            body.push_str("<p>None</p>\n");
+        } else {
+            body.push_str("<ul>\n");
+            for (time, error) in state.errors.iter().rev() {
+                body.push_str(&format!("<li>{}: {}</li>\n", time.to_rfc3339(), escape_html(error)));
+            }
+            body.push_str("</ul>\n");
+        }
+
+        body.push_str("<h2>Files failing verification</h2>\n");
+        if state.findings.is_empty() {
+            body.push_str("<p>None</p>\n");
+        } else {
+            body.push_str("<ul>\n");
+            for finding in &state.findings {
+                body.push_str(&format!("<li>{}: '{}' {}</li>\n", NaiveDateTime::from_timestamp(finding.time, 0).format("%Y-%m-%d %H:%M:%S UTC"), escape_html(&finding.path), escape_html(&finding.problem)));
+            }
+            body.push_str("</ul>\n");
+        }
+
+        if state.users.is_empty() {
+            vec![("Last runs".to_string(), None)]
+        } else {
+            state.users.iter().map(|user| (format!("Last runs of {}", user.name), Some(user.clone()))).collect::<Vec<_>>()
+        }
+    };
+
+    for (heading, user) in histories {
+        body.push_str(&format!("<h2>{}</h2>\n", escape_html(&heading)));
+        body.push_str(&runs_table(data.env.clone(), user).await);
+    }
+
+    HttpResponse::Ok().content_type("text/html; charset=utf-8").body(format!(r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta http-equiv="refresh" content="10">
+<title>GSync - Status</title>
+<style>body {{ font-family: sans-serif; max-width: 60em; margin: 2em auto; }} td, th {{ padding: 0.2em 1em; text-align: left; }}</style>
+</head>
+<body>
+<h1>GSync</h1>
+{body}</body>
+</html>"#, body = body))
+}
+
+/// Render the most recent runs from the run history of `user`, or from the database of `env` when not syncing for other users, as an HTML table.
+/// The run history of a user is read by a child process running as that user
+async fn runs_table(env: Env, user: Option<ServiceUser>) -> String {
+    let mut body = String::new();
+    match web::block(move || match user {
+        Some(user) => user.recent_runs(RECENT_RUNS),
+        None => crate::report::recent_runs(&env, RECENT_RUNS)
+    }).await {
+        Ok(runs) => {
+            body.push_str("<table>\n<tr><th>Started</th><th>Duration</th><th>Result</th><th>New</th><th>Changed</th><th>Up-to-date</th><th>Uploaded</th><th>Failures</th></tr>\n");
+            for run in runs {
+                body.push_str(&format!("<tr><td>{}</td><td>{} s</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                    NaiveDateTime::from_timestamp(run.started, 0),
+                    run.finished - run.started,
+                    if run.success { "Success" } else { "Failed" },
+                    run.uploaded,
+                    run.updated,
+                    run.unchanged,
+                    format_size(run.bytes as u64),
+                    run.failures));
+            }
+            body.push_str("</table>\n");
+        },
+        Err(e) => body.push_str(&format!("<p>Failed to read the run history: {}</p>\n", escape_html(&crate::redact::redact(format!("{:?}", e)))))
+    }
+
+    body
+}
@@ -0,0 +1,379 @@
+//! Module for running GSync as a daemon, syncing on an interval
+
+mod api;
+mod dashboard;
+pub mod schedule;
+pub mod users;
+
+use crate::config::Configuration;
+use crate::env::Env;
+use crate::report::RunReport;
+use crate::sync::SyncOptions;
+use crate::daemon::schedule::Slots;
+use crate::daemon::users::ServiceUser;
+use crate::scrub::Finding;
+use crate::{Result, unwrap_other_err};
+use chrono::{DateTime, Utc};
+use std::collections::VecDeque;
+use actix_web::{web, App, HttpServer};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+/// The default time between two syncs
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// The number of recent errors kept to show on the dashboard
+const MAX_ERRORS: usize = 20;
+
+/// Struct describing the state of the daemon, shared with the dashboard
+#[derive(Debug)]
+pub struct DaemonState {
+    /// The time between two syncs
+    pub interval:   Duration,
+
+    /// When the next sync starts. None while a sync is running
+    pub next_run:   Option<DateTime<Utc>>,
+
+    /// The report of the sync which is currently running
+    pub current:    Option<Arc<RunReport>>,
+
+    /// The most recent errors, newest last
+    pub errors:     VecDeque<(DateTime<Utc>, String)>,
+
+    /// The users synced for in the multi-user mode. Empty when syncing for the user running the daemon
+    pub users:      Vec<ServiceUser>,
+
+    /// The files which failed their last verification by the scrub after every sync
+    pub findings:   Vec<Finding>
+}
+
+/// Options for the daemon, set on the command line
+#[derive(Debug, Clone)]
+pub struct DaemonOptions {
+    /// The time between two syncs
+    pub interval:   Duration,
+
+    /// If set, the address and port to serve the status dashboard on
+    pub dashboard:  Option<(String, u16)>,
+
+    /// If set, the address and port to serve the HTTP API on, and the token clients must authenticate with
+    pub api:        Option<ApiOptions>,
+
+    /// The users to sync for, each with their own configuration, state database and Drive credentials.
+    /// If empty, the daemon syncs the configuration of the user running it
+    pub users:      Vec<ServiceUser>,
+
+    /// The maximum random delay before every scheduled sync, and before the sync of every user in the multi-user mode
+    pub jitter:     Duration,
+
+    /// The maximum number of users synced at the same time in the multi-user mode
+    pub parallel:   usize,
+
+    /// The names of the users of which no two are synced at the same time in the multi-user mode, e.g. because they have a lot of data
+    pub heavy:      Vec<String>,
+
+    /// If set, the time between two restore drills. Only supported when syncing for the user running the daemon
+    pub drill:      Option<Duration>,
+
+    /// The number of files restored per drill
+    pub drill_sample: usize
+}
+
+/// Options for the HTTP API
+#[derive(Debug, Clone)]
+pub struct ApiOptions {
+    /// The address to bind to
+    pub bind:       String,
+
+    /// The port to listen on
+    pub port:       u16,
+
+    /// The token clients must send as `Authorization: Bearer <token>`
+    pub token:      String
+}
+
+/// Run the daemon, syncing the configured inputs every interval, or when a sync is requested through the HTTP API.
+/// This never returns unless the dashboard or the API fails to start.
+/// The configuration is read again before every sync, so changes are picked up without restarting the daemon
+///
+/// ## Errors
+/// - When the dashboard or the API fails to start
+pub fn run(env: &Env, options: &DaemonOptions) -> Result<()> {
+    let state = Arc::new(Mutex::new(DaemonState {
+        interval:   options.interval,
+        next_run:   None,
+        current:    None,
+        errors:     VecDeque::new(),
+        users:      options.users.clone(),
+        findings:   Vec::new()
+    }));
+
+    if let Some((bind, port)) = &options.dashboard {
+        dashboard::start(env.clone(), state.clone(), bind, *port)?;
+        println!("Info: Serving the status dashboard on http://{}:{}", bind, port);
+    }
+
+    // Requests for a sync from the API are sent over this channel
+    let (tx_trigger, rx_trigger) = channel();
+    if let Some(api_options) = &options.api {
+        api::start(env.clone(), state.clone(), tx_trigger, api_options)?;
+        println!("Info: Serving the HTTP API on http://{}:{}", api_options.bind, api_options.port);
+    }
+
+    // The first sync is delayed as well, so machines which start at the same moment don't sync at the same moment
+    let mut scheduled = true;
+    loop {
+        let findings = if options.users.is_empty() {
+            if scheduled {
+                delay(&state, options.jitter)?;
+            }
+
+            run_once(env, &state);
+            if let Some(every) = options.drill {
+                if let Err((e, _, _)) = drill_if_due(env, every, options.drill_sample) {
+                    let error = crate::redact::redact(format!("{:?}", e));
+                    eprintln!("Error: Restore drill failed: {}", error);
+                    record_error(&state, format!("Restore drill failed: {}", error));
+                }
+            }
+
+            scrub_or_record(scrub(env, options.interval), &state)
+        } else {
+            run_users(options, &state, scheduled)
+        };
+        state.lock().unwrap().findings = findings;
+
+        let next_run = Utc::now() + unwrap_other_err!(chrono::Duration::from_std(options.interval));
+        state.lock().unwrap().next_run = Some(next_run);
+        println!("Info: Next sync at {}", next_run.to_rfc3339());
+        scheduled = wait(&rx_trigger, options.interval);
+    }
+}
+
+/// Wait until the interval has passed, or until a sync is requested. Returns whether the interval passed
+fn wait(rx_trigger: &Receiver<()>, interval: Duration) -> bool {
+    // Requests made while the previous sync was running are covered by that sync
+    while rx_trigger.try_recv().is_ok() {}
+
+    match rx_trigger.recv_timeout(interval) {
+        Ok(_) => {
+            println!("Info: Sync requested through the API");
+            false
+        },
+        Err(RecvTimeoutError::Timeout) => true,
+        // The API is not running, so nothing can request a sync
+        Err(RecvTimeoutError::Disconnected) => {
+            std::thread::sleep(interval);
+            true
+        }
+    }
+}
+
+/// Wait a random time of at most `jitter` before a scheduled sync
+///
+/// ## Errors
+/// - When the delay is too long to represent as the time of the next sync
+fn delay(state: &Mutex<DaemonState>, jitter: Duration) -> Result<()> {
+    let delay = schedule::jitter(jitter);
+    if delay.as_secs() == 0 {
+        return Ok(());
+    }
+
+    let next_run = Utc::now() + unwrap_other_err!(chrono::Duration::from_std(delay));
+    state.lock().unwrap().next_run = Some(next_run);
+    println!("Info: Delaying the sync until {}", next_run.to_rfc3339());
+    std::thread::sleep(delay);
+    Ok(())
+}
+
+/// Sync and scrub for every user in the multi-user mode. Every user is synced on its own thread after a random delay,
+/// with at most `options.parallel` users and at most one heavy user syncing at the same time.
+/// Returns the files of all users which failed their last verification
+fn run_users(options: &DaemonOptions, state: &Arc<Mutex<DaemonState>>, scheduled: bool) -> Vec<Finding> {
+    // The syncs run in child processes, so only whether they are running is known
+    {
+        let mut state = state.lock().unwrap();
+        state.next_run = None;
+        state.current = Some(Arc::new(RunReport::new()));
+    }
+
+    let slots = Arc::new(Slots::new(options.parallel));
+    let handles = options.users.iter().cloned().map(|user| {
+        let state = state.clone();
+        let slots = slots.clone();
+        let heavy = options.heavy.contains(&user.name);
+        let delay = if scheduled { schedule::jitter(options.jitter) } else { Duration::from_secs(0) };
+        let interval = options.interval;
+
+        std::thread::spawn(move || {
+            std::thread::sleep(delay);
+            let _slot = slots.acquire(heavy);
+
+            println!("Info: Syncing for user '{}'", user.name);
+            run_as_user(&user, &state);
+            scrub_or_record(user.scrub(interval), &state)
+        })
+    }).collect::<Vec<_>>();
+
+    let findings = handles.into_iter()
+        .flat_map(|handle| handle.join().unwrap_or_default())
+        .collect();
+
+    state.lock().unwrap().current = None;
+    findings
+}
+
+/// Start an Actix Web Server on its own thread, returning once it is listening
+///
+/// ## Params
+/// - `name` What the server is for, used in the error message
+/// - `bind` The address to bind to
+/// - `port` The port to listen on
+/// - `configure` Function registering the data and services of the server
+///
+/// ## Errors
+/// - When binding to the address fails
+fn start_server<F>(name: &str, bind: &str, port: u16, configure: F) -> Result<()>
+where F: Fn(&mut web::ServiceConfig) + Clone + Send + 'static {
+    let address = format!("{}:{}", bind, port);
+
+    // The result of binding is sent back, so a failure to start is reported to the caller
+    let (tx, rx) = channel();
+    std::thread::spawn(move || {
+        let mut sys = actix_web::rt::System::new("GSync");
+        let server = match HttpServer::new(move || {
+            App::new().configure(configure.clone())
+        }).bind(&address) {
+            Ok(server) => server.run(),
+            Err(e) => {
+                let _ = tx.send(Err(e.to_string()));
+                return;
+            }
+        };
+
+        let _ = tx.send(Ok(()));
+        let _ = sys.block_on(server);
+    });
+
+    match unwrap_other_err!(rx.recv()) {
+        Ok(_) => Ok(()),
+        Err(e) => Err((crate::Error::Other(format!("Failed to start the {}: {}", name, e)), line!(), file!()))
+    }
+}
+
+/// Run a single sync, recording its progress and any error in the daemon state
+fn run_once(env: &Env, state: &Mutex<DaemonState>) {
+    let report = Arc::new(RunReport::new());
+    {
+        let mut state = state.lock().unwrap();
+        state.next_run = None;
+        state.current = Some(report.clone());
+    }
+
+    if let Err((e, _, _)) = sync(env, &report) {
+        let error = crate::redact::redact(format!("{:?}", e));
+        eprintln!("Error: Sync failed: {}", error);
+        record_error(state, error);
+    }
+
+    state.lock().unwrap().current = None;
+}
+
+/// Run a single sync for a user in the multi-user mode, recording any error in the daemon state.
+/// The sync runs in a child process, so its progress is only stored in the run history of the user
+fn run_as_user(user: &ServiceUser, state: &Mutex<DaemonState>) {
+    if let Err((e, _, _)) = user.sync() {
+        let error = crate::redact::redact(format!("{:?}", e));
+        eprintln!("Error: Sync for user '{}' failed: {}", user.name, error);
+        record_error(state, format!("{}: {}", user.name, error));
+    }
+}
+
+/// Verify the next batch of synced files, sized so every file is verified once within the configured scrub window.
+/// Returns the files which failed their last verification
+///
+/// ## Errors
+/// - When reading the configuration or the tracked files fails
+/// - When the scrub fails
+fn scrub(env: &Env, interval: Duration) -> Result<Vec<Finding>> {
+    let config = Configuration::get_config(env)?;
+    let scrub_env = Env::from_config(&config, config.drive_id.as_ref());
+    let count = match crate::scrub::due(&scrub_env, config.scrub_window, interval)? {
+        Some(count) => count,
+        None => return Ok(Vec::new())
+    };
+
+    let result = crate::scrub::scrub(&scrub_env, count)?;
+    for finding in &result.findings {
+        eprintln!("Warning: '{}' {} in Google Drive", finding.path, finding.problem);
+    }
+
+    println!("Info: Verified {} files in Google Drive, {} failed verification", result.checked, result.findings.len());
+    crate::scrub::findings(&scrub_env)
+}
+
+/// Run a restore drill if the last one was at least `every` ago. Files which fail the drill are reported as errors
+///
+/// ## Errors
+/// - When reading the configuration or the drill history fails
+/// - When the drill fails
+fn drill_if_due(env: &Env, every: Duration, sample: usize) -> Result<()> {
+    let config = Configuration::get_config(env)?;
+    let drill_env = Env::from_config(&config, config.drive_id.as_ref());
+    let due = match crate::drill::last_drill(&drill_env)? {
+        Some(last) => Utc::now().timestamp() - last >= every.as_secs() as i64,
+        None => true
+    };
+
+    if !due {
+        return Ok(());
+    }
+
+    println!("Info: Running a restore drill");
+    let result = crate::drill::drill(&drill_env, sample)?;
+    println!("Info: Restored and verified {} files, {} failed", result.checked, result.failures.len());
+    if result.failures.is_empty() {
+        return Ok(());
+    }
+
+    let failures = result.failures.iter().map(|(path, problem)| format!("'{}' {}", path, problem)).collect::<Vec<_>>();
+    Err((crate::Error::Other(failures.join("; ")), line!(), file!()))
+}
+
+/// Unwrap the findings of a scrub, recording the error in the daemon state if it failed
+fn scrub_or_record(result: Result<Vec<Finding>>, state: &Mutex<DaemonState>) -> Vec<Finding> {
+    match result {
+        Ok(findings) => findings,
+        Err((e, _, _)) => {
+            let error = crate::redact::redact(format!("{:?}", e));
+            eprintln!("Error: Scrub failed: {}", error);
+            record_error(state, format!("Scrub failed: {}", error));
+            Vec::new()
+        }
+    }
+}
+
+/// Record an error in the daemon state, dropping the oldest error if there are too many
+fn record_error(state: &Mutex<DaemonState>, error: String) {
+    let mut state = state.lock().unwrap();
+    state.errors.push_back((Utc::now(), error));
+    if state.errors.len() > MAX_ERRORS {
+        state.errors.pop_front();
+    }
+}
+
+/// Read the configuration and sync
+///
+/// ## Errors
+/// - When the configuration is incomplete
+/// - When the sync fails
+fn sync(env: &Env, report: &RunReport) -> Result<()> {
+    let config = Configuration::get_config(env)?;
+    if let (false, reason) = config.is_complete() {
+        return Err((crate::Error::Other(format!("Configuration is incomplete; {}", reason)), line!(), file!()));
+    }
+
+    let sync_env = Env::from_config(&config, config.drive_id.as_ref());
+    crate::sync::sync_with_report(&config, &sync_env, &SyncOptions::default(), report)
+}
@@ -0,0 +1,131 @@
+//! Scheduling of the syncs in the multi-user mode: how many users are synced at once, which users may not be synced at the same time,
+//! and the random delay before every scheduled sync, so that many users, or many machines, don't all start syncing at the same moment
+
+use rand::Rng;
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+/// The default number of users synced at the same time
+pub const DEFAULT_PARALLEL: usize = 1;
+
+/// Limits the number of users synced at the same time, and never syncs two heavy users at the same time
+#[derive(Debug)]
+pub struct Slots {
+    /// The users currently syncing
+    state:      Mutex<SlotState>,
+
+    /// Notified when a sync finishes
+    released:   Condvar
+}
+
+/// Struct describing the users currently syncing
+#[derive(Debug)]
+struct SlotState {
+    /// The maximum number of users synced at the same time
+    parallel:   usize,
+
+    /// The number of users currently syncing
+    running:    usize,
+
+    /// Whether a heavy user is currently syncing
+    heavy:      bool
+}
+
+/// A slot held by a syncing user, released when dropped
+#[derive(Debug)]
+pub struct Slot<'a> {
+    /// The slots this slot was taken from
+    slots:      &'a Slots,
+
+    /// Whether the user holding this slot is heavy
+    heavy:      bool
+}
+
+impl Slots {
+    /// Create new slots
+    ///
+    /// ## Params
+    /// - `parallel` The maximum number of users synced at the same time
+    pub fn new(parallel: usize) -> Self {
+        Self {
+            state:      Mutex::new(SlotState { parallel: parallel.max(1), running: 0, heavy: false }),
+            released:   Condvar::new()
+        }
+    }
+
+    /// Wait until a user may start syncing
+    ///
+    /// ## Params
+    /// - `heavy` Whether the user is heavy, and may not sync at the same time as another heavy user
+    pub fn acquire(&self, heavy: bool) -> Slot<'_> {
+        let mut state = self.state.lock().unwrap();
+        while !state.can_start(heavy) {
+            state = self.released.wait(state).unwrap();
+        }
+
+        state.running += 1;
+        state.heavy |= heavy;
+        Slot { slots: self, heavy }
+    }
+}
+
+impl SlotState {
+    /// Whether a user may start syncing now
+    fn can_start(&self, heavy: bool) -> bool {
+        self.running < self.parallel && !(heavy && self.heavy)
+    }
+}
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        let mut state = self.slots.state.lock().unwrap();
+        state.running -= 1;
+        if self.heavy {
+            state.heavy = false;
+        }
+
+        self.slots.released.notify_all();
+    }
+}
+
+/// A random delay of at most `jitter`
+pub fn jitter(jitter: Duration) -> Duration {
+    if jitter.as_secs() == 0 {
+        return Duration::from_secs(0);
+    }
+
+    Duration::from_secs(rand::thread_rng().gen_range(0..=jitter.as_secs()))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::daemon::schedule::{jitter, SlotState};
+    use std::time::Duration;
+
+    #[test]
+    fn limits_parallel_users() {
+        let state = SlotState { parallel: 2, running: 1, heavy: false };
+        assert!(state.can_start(false));
+
+        let state = SlotState { parallel: 2, running: 2, heavy: false };
+        assert!(!state.can_start(false));
+    }
+
+    #[test]
+    fn never_runs_two_heavy_users() {
+        let state = SlotState { parallel: 4, running: 1, heavy: true };
+        assert!(state.can_start(false));
+        assert!(!state.can_start(true));
+
+        let state = SlotState { parallel: 4, running: 1, heavy: false };
+        assert!(state.can_start(true));
+    }
+
+    #[test]
+    fn jitter_within_bounds() {
+        assert_eq!(jitter(Duration::from_secs(0)), Duration::from_secs(0));
+        for _ in 0..100 {
+            assert!(jitter(Duration::from_secs(60)) <= Duration::from_secs(60));
+        }
+    }
+}
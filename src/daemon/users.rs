@@ -0,0 +1,177 @@
+//! Multi-user service mode: a single privileged daemon syncing for several users.
+//! Each user configures GSync and logs in as usual, so their state database and Drive credentials stay in their own home folder.
+//! The sync for a user runs in a child process with the privileges of that user, so only files they can read are synced.
+//! Their database is only ever read by such a child process as well, which writes what the daemon needs to stdout as JSON
+
+use crate::report::RunRecord;
+use crate::scrub::Finding;
+use crate::{Result, Error, unwrap_other_err};
+use serde::de::DeserializeOwned;
+use std::process::Command;
+use std::time::Duration;
+
+/// Struct describing a user the daemon syncs for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceUser {
+    /// The name of the user
+    pub name:   String,
+
+    /// The user ID
+    pub uid:    u32,
+
+    /// The ID of the primary group of the user
+    pub gid:    u32,
+
+    /// The home folder of the user
+    pub home:   String
+}
+
+impl ServiceUser {
+    /// Look up a user by name
+    ///
+    /// ## Errors
+    /// - When the user does not exist
+    #[cfg(unix)]
+    pub fn lookup(name: &str) -> Result<Self> {
+        use ::users::os::unix::UserExt;
+
+        match ::users::get_user_by_name(name) {
+            Some(user) => Ok(Self {
+                name:   name.to_string(),
+                uid:    user.uid(),
+                gid:    user.primary_group_id(),
+                home:   user.home_dir().to_string_lossy().to_string()
+            }),
+            None => Err((Error::Other(format!("User '{}' does not exist", name)), line!(), file!()))
+        }
+    }
+
+    /// Look up a user by name
+    ///
+    /// ## Errors
+    /// - Always, the multi-user mode is only supported on Unix
+    #[cfg(not(unix))]
+    pub fn lookup(_name: &str) -> Result<Self> {
+        Err((Error::Other("Syncing for other users is only supported on Unix".to_string()), line!(), file!()))
+    }
+
+    /// Sync the configured inputs of this user, in a child process running with their privileges
+    ///
+    /// ## Errors
+    /// - When the child process can't be started, e.g. because the daemon isn't running as root
+    /// - When the sync fails
+    pub fn sync(&self) -> Result<()> {
+        self.run(&["sync"], "sync")
+    }
+
+    /// Verify the next batch of the files synced for this user when scrubbing every `interval`, in a child process running with their privileges.
+    /// Returns the files of this user which failed their last verification. See `gsync scrub --every`
+    ///
+    /// ## Errors
+    /// - When the child process can't be started, e.g. because the daemon isn't running as root
+    /// - When the scrub fails
+    pub fn scrub(&self, interval: Duration) -> Result<Vec<Finding>> {
+        self.output(&["scrub", "--every", &interval.as_secs().to_string(), "--json"], "scrub")
+    }
+
+    /// Get the most recent runs from the run history of this user, newest first, in a child process running with their privileges. See `gsync history`
+    ///
+    /// ## Errors
+    /// - When the child process can't be started, e.g. because the daemon isn't running as root
+    /// - When reading the run history fails
+    pub fn recent_runs(&self, limit: u32) -> Result<Vec<RunRecord>> {
+        self.output(&["history", "--limit", &limit.to_string(), "--json"], "run history")
+    }
+
+    /// Run GSync with the given arguments in a child process running with the privileges of this user
+    ///
+    /// ## Params
+    /// - `args` The arguments to run GSync with
+    /// - `what` What is being run, used in error messages
+    ///
+    /// ## Errors
+    /// - When the child process can't be started, e.g. because the daemon isn't running as root
+    /// - When the child process fails
+    fn run(&self, args: &[&str], what: &str) -> Result<()> {
+        let status = match self.command(args)?.status() {
+            Ok(status) => status,
+            Err(e) => return Err(self.start_failed(what, e))
+        };
+
+        if status.success() {
+            Ok(())
+        } else {
+            Err((Error::Other(format!("The {} for user '{}' failed with {}", what, self.name, status)), line!(), file!()))
+        }
+    }
+
+    /// Run GSync with the given arguments in a child process running with the privileges of this user, and parse the JSON it writes to stdout.
+    /// The JSON is the last line of the output, any lines before it, e.g. retry warnings, are passed on to the stdout of the daemon
+    ///
+    /// ## Params
+    /// - `args` The arguments to run GSync with
+    /// - `what` What is being run, used in error messages
+    ///
+    /// ## Errors
+    /// - When the child process can't be started, e.g. because the daemon isn't running as root
+    /// - When the child process fails
+    /// - When the last line of the output isn't the expected JSON
+    fn output<T: DeserializeOwned>(&self, args: &[&str], what: &str) -> Result<T> {
+        let output = match self.command(args)?.stderr(std::process::Stdio::inherit()).output() {
+            Ok(output) => output,
+            Err(e) => return Err(self.start_failed(what, e))
+        };
+
+        if !output.status.success() {
+            return Err((Error::Other(format!("The {} for user '{}' failed with {}", what, self.name, output.status)), line!(), file!()));
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let mut lines = stdout.lines().filter(|line| !line.trim().is_empty()).collect::<Vec<_>>();
+        let json = lines.pop().unwrap_or_default();
+        for line in lines {
+            println!("{}", line);
+        }
+
+        match serde_json::from_str(json) {
+            Ok(value) => Ok(value),
+            Err(e) => Err((Error::Other(format!("The {} for user '{}' returned unexpected output: {}", what, self.name, e)), line!(), file!()))
+        }
+    }
+
+    /// The error for a child process which couldn't be started
+    fn start_failed(&self, what: &str, e: std::io::Error) -> (Error, u32, &'static str) {
+        (Error::Other(format!("Failed to start the {} for user '{}': {}. Syncing for other users requires running as root", what, self.name, e)), line!(), file!())
+    }
+
+    /// A command running GSync with the given arguments with the privileges of this user, in their home folder and with only their basic environment
+    ///
+    /// ## Errors
+    /// - When the path of the GSync executable can't be determined
+    #[cfg(unix)]
+    fn command(&self, args: &[&str]) -> Result<Command> {
+        use std::os::unix::process::CommandExt;
+
+        let exe = unwrap_other_err!(std::env::current_exe());
+        let mut command = Command::new(exe);
+        command.args(args)
+            .env_clear()
+            .env("HOME", &self.home)
+            .env("USER", &self.name)
+            .env("LOGNAME", &self.name)
+            .env("PATH", std::env::var("PATH").unwrap_or_default())
+            .current_dir(&self.home)
+            .gid(self.gid)
+            .uid(self.uid);
+        Ok(command)
+    }
+
+    /// A command running GSync with the privileges of this user
+    ///
+    /// ## Errors
+    /// - Always, the multi-user mode is only supported on Unix
+    #[cfg(not(unix))]
+    fn command(&self, _args: &[&str]) -> Result<Command> {
+        Err((Error::Other("Syncing for other users is only supported on Unix".to_string()), line!(), file!()))
+    }
+}
@@ -0,0 +1,61 @@
+//! Module for inspecting the GSync database
+
+use crate::env::Env;
+use crate::{Result, unwrap_db_err, unwrap_other_err};
+use crate::size::format_size;
+use crate::report;
+use rusqlite::named_params;
+
+/// Print statistics about the database: the number of rows per table, what is being tracked, the last run and the size of the database file
+///
+/// ## Errors
+/// - When a database operation fails
+/// - When the size of the database file cannot be read
+pub fn print_stats(env: &Env) -> Result<()> {
+    let conn = unwrap_db_err!(env.get_conn());
+
+    let tables = {
+        let mut stmt = unwrap_db_err!(conn.prepare("SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' ORDER BY name"));
+        let mut result = unwrap_db_err!(stmt.query(named_params! {}));
+
+        let mut tables = Vec::new();
+        while let Some(row) = unwrap_db_err!(result.next()) {
+            tables.push(unwrap_db_err!(row.get::<&str, String>("name")));
+        }
+
+        tables
+    };
+
+    println!("Rows per table:");
+    for table in &tables {
+        // Table names can't be bound as parameters. They come from sqlite_master, not from the user
+        let count = unwrap_db_err!(conn.query_row(&format!("SELECT COUNT(*) FROM \"{}\"", table), named_params! {}, |row| row.get::<usize, i64>(0)));
+        println!("  {}: {}", table, count);
+    }
+
+    let (files, folders, bytes) = unwrap_db_err!(conn.query_row("SELECT COALESCE(SUM(is_folder = 0), 0), COALESCE(SUM(is_folder = 1), 0), COALESCE(SUM(size), 0) FROM files", named_params! {}, |row| {
+        Ok((row.get::<usize, i64>(0)?, row.get::<usize, i64>(1)?, row.get::<usize, i64>(2)?))
+    }));
+    println!("\nTracked: {} files and {} folders, totalling {}", files, folders, format_size(bytes as u64));
+
+    match report::last_run(env)? {
+        Some(run) => {
+            let checked = run.uploaded + run.updated + run.unchanged;
+            let up_to_date_rate = if checked > 0 { run.unchanged as f64 / checked as f64 * 100.0 } else { 0.0 };
+
+            println!("\nLast run: {} ({})", chrono::NaiveDateTime::from_timestamp(run.started, 0).format("%Y-%m-%d %H:%M:%S UTC"), if run.success { "completed" } else { "failed" });
+            println!("  Duration: {} seconds", run.finished - run.started);
+            println!("  Files checked: {}, of which {:.1}% were up-to-date and not uploaded again", checked, up_to_date_rate);
+            println!("  Uploaded: {} new and {} changed files, {}", run.uploaded, run.updated, format_size(run.bytes as u64));
+            println!("  Failures: {}", run.failures);
+        },
+        None => println!("\nLast run: never")
+    }
+
+    let mut path = std::path::PathBuf::from(&env.db);
+    path.push("data.db3");
+    let size = unwrap_other_err!(std::fs::metadata(&path)).len();
+    println!("\nDatabase file: {} ({})", path.to_string_lossy(), format_size(size));
+
+    Ok(())
+}
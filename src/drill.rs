@@ -0,0 +1,206 @@
+//! Module for restore drills: restoring a random sample of the synced files from Google Drive to a temporary folder and verifying their content.
+//! Where a scrub trusts the checksums Google reports, a drill downloads the files, so it is evidence the backup can actually be restored
+
+use crate::env::Env;
+use crate::api::drive;
+use crate::hash::{self, HashAlgorithm};
+use crate::{Result, Error, unwrap_db_err, unwrap_other_err};
+use chrono::Utc;
+use rusqlite::named_params;
+use std::fs;
+use std::path::Path;
+
+/// The default number of files restored per drill
+pub const DEFAULT_SAMPLE: usize = 10;
+
+/// Struct describing the result of a drill
+#[derive(Debug, Default)]
+pub struct DrillResult {
+    /// The number of files restored and verified
+    pub checked:    usize,
+
+    /// The number of bytes restored
+    pub bytes:      u64,
+
+    /// The number of sampled files which were skipped, e.g. because they were converted to Google documents
+    pub skipped:    usize,
+
+    /// The files which could not be restored or were restored with different content, with the reason why
+    pub failures:   Vec<(String, String)>
+}
+
+/// Struct describing a tracked file sampled for a drill
+#[derive(Debug)]
+struct Sampled {
+    /// The local path of the file
+    path:   String,
+
+    /// The ID of the file in Google Drive
+    id:     String,
+
+    /// The MD5 checksum of the file when it was synced
+    md5:    String
+}
+
+/// Restore a random sample of the synced files to a temporary folder, verify their checksums against the ones recorded when they were synced,
+/// and remove them again. The result is stored in the drill history
+///
+/// ## Params
+/// - `env` Env instance
+/// - `sample` The number of files to restore
+///
+/// ## Errors
+/// - When a database operation fails
+/// - When creating the temporary folder fails
+pub fn drill(env: &Env, sample: usize) -> Result<DrillResult> {
+    let sampled = sample_files(env, sample)?;
+    let dir = std::env::temp_dir().join(format!("gsync-drill-{}", std::process::id()));
+    unwrap_other_err!(fs::create_dir_all(&dir));
+
+    // The restored files are removed whatever the outcome
+    let result = restore_sample(env, &sampled, &dir);
+    let _ = fs::remove_dir_all(&dir);
+    let result = result?;
+
+    let conn = unwrap_db_err!(env.get_conn());
+    unwrap_db_err!(conn.execute("INSERT INTO drills (time, checked, failed, bytes) VALUES (:time, :checked, :failed, :bytes)", named_params! {
+        ":time":    Utc::now().timestamp(),
+        ":checked": result.checked as i64,
+        ":failed":  result.failures.len() as i64,
+        ":bytes":   result.bytes as i64
+    }));
+
+    Ok(result)
+}
+
+/// Get the epoch time in seconds of the last drill, if there was one
+///
+/// ## Errors
+/// - When a database operation fails
+pub fn last_drill(env: &Env) -> Result<Option<i64>> {
+    let conn = unwrap_db_err!(env.get_conn());
+    let last = unwrap_db_err!(conn.query_row("SELECT MAX(time) FROM drills", named_params! {}, |row| row.get::<usize, Option<i64>>(0)));
+    Ok(last)
+}
+
+/// Pick a random sample of the synced files. Folders and links have no content of their own, so they are never picked
+///
+/// ## Errors
+/// - When a database operation fails
+fn sample_files(env: &Env, sample: usize) -> Result<Vec<Sampled>> {
+    let conn = unwrap_db_err!(env.get_conn());
+    let mut stmt = unwrap_db_err!(conn.prepare("SELECT path, id, md5 FROM files WHERE is_folder = 0 AND md5 IS NOT NULL AND link_target IS NULL ORDER BY RANDOM() LIMIT :limit"));
+    let mut result = unwrap_db_err!(stmt.query(named_params! {
+        ":limit": sample as i64
+    }));
+
+    let mut sampled = Vec::new();
+    while let Some(row) = unwrap_db_err!(result.next()) {
+        sampled.push(Sampled {
+            path:   unwrap_db_err!(row.get::<&str, String>("path")),
+            id:     unwrap_db_err!(row.get::<&str, String>("id")),
+            md5:    unwrap_db_err!(row.get::<&str, String>("md5"))
+        });
+    }
+
+    Ok(sampled)
+}
+
+/// Restore the sampled files into a folder one by one, verifying and removing each after it is restored
+///
+/// ## Errors
+/// - When a database operation fails
+fn restore_sample(env: &Env, sampled: &[Sampled], dir: &Path) -> Result<DrillResult> {
+    let mut result = DrillResult::default();
+    for (index, file) in sampled.iter().enumerate() {
+        println!("Info: Restoring '{}'", file.path);
+
+        match drive::blocking::get_file(env, &file.id) {
+            // Converted files are restored as an export, which differs from what was uploaded
+            Ok(remote) if remote.mime_type.starts_with("application/vnd.google-apps.") => {
+                println!("Info: Skipping '{}', it was converted to a Google document", file.path);
+                result.skipped += 1;
+                continue;
+            },
+            Ok(remote) if remote.trashed => {
+                result.failures.push((file.path.clone(), "is in the trash".to_string()));
+                continue;
+            },
+            Ok(_) => {},
+            Err((Error::GoogleError(e), _, _)) if e.code == 404 => {
+                result.failures.push((file.path.clone(), "is missing from Google Drive".to_string()));
+                continue;
+            },
+            Err((e, _, _)) => {
+                result.failures.push((file.path.clone(), format!("could not be looked up: {}", crate::redact::redact(format!("{:?}", e)))));
+                continue;
+            }
+        }
+
+        let target = dir.join(index.to_string());
+        if let Err((e, _, _)) = drive::blocking::download_file(env, &file.id, &target) {
+            result.failures.push((file.path.clone(), format!("could not be downloaded: {}", crate::redact::redact(format!("{:?}", e)))));
+            let _ = fs::remove_file(&target);
+            continue;
+        }
+
+        let stored = hash::stored(env, &file.path, &file.id)?;
+        match verify(&target, &file.md5, stored.as_ref()) {
+            Ok(None) => {
+                result.checked += 1;
+                result.bytes += fs::metadata(&target).map(|metadata| metadata.len()).unwrap_or(0);
+            },
+            Ok(Some(problem)) => result.failures.push((file.path.clone(), problem)),
+            Err((e, _, _)) => result.failures.push((file.path.clone(), format!("could not be verified: {}", crate::redact::redact(format!("{:?}", e)))))
+        }
+
+        let _ = fs::remove_file(&target);
+    }
+
+    Ok(result)
+}
+
+/// Verify the content of a restored file. The checksum stored with the algorithm of the configuration is preferred, the MD5 checksum is used otherwise.
+/// The stored checksum is of the local file, so packed sparse files are unpacked first. The MD5 checksum is of the content as it was uploaded.
+/// Returns what is wrong with the file, if anything
+///
+/// ## Errors
+/// - When reading the restored file fails
+/// - When unpacking a sparse file fails
+fn verify(path: &Path, md5: &str, stored: Option<&(HashAlgorithm, String)>) -> Result<Option<String>> {
+    let (algorithm, expected) = match stored {
+        Some((algorithm, hash)) => {
+            crate::sparse::unpack(path)?;
+            (*algorithm, hash.as_str())
+        },
+        None => (HashAlgorithm::Md5, md5)
+    };
+
+    let actual = hash::hash_file(path, algorithm)?;
+    if actual == expected {
+        Ok(None)
+    } else {
+        Ok(Some(format!("was restored with {} checksum {} instead of {}", algorithm, actual, expected)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::drill::verify;
+    use crate::hash::{self, HashAlgorithm};
+
+    #[test]
+    fn verify_restored() {
+        let path = std::env::temp_dir().join(format!("gsync-test-drill-{}", std::process::id()));
+        std::fs::write(&path, b"abc").unwrap();
+        let md5 = hash::md5_file(&path).unwrap();
+        let blake3 = hash::hash_file(&path, HashAlgorithm::Blake3).unwrap();
+
+        assert_eq!(None, verify(&path, &md5, None).unwrap());
+        assert_eq!(None, verify(&path, "", Some(&(HashAlgorithm::Blake3, blake3))).unwrap());
+        assert!(verify(&path, "0cc175b9c0f1b6a831c399e269772661", None).unwrap().is_some());
+        assert!(verify(&path, &md5, Some(&(HashAlgorithm::Sha256, "00".to_string()))).unwrap().is_some());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,6 +1,9 @@
 //! Module describing user-configurable and program-fetched variables needed for proper operation of GSync
 
 use cfg_if::cfg_if;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use crate::config::Configuration;
 
 /// Struct describing the environment of GSync
 #[derive(Debug, Clone)]
@@ -18,79 +21,403 @@ pub struct Env {
     pub drive_id:       Option<String>,
 
     /// The ID of the root folder ('GSync')
-    pub root_folder:    String
+    pub root_folder:    String,
+
+    /// The ID of an existing folder configured as the sync destination. If set, it is used as the root folder instead of the 'GSync' folder
+    pub target_folder:  Option<String>,
+
+    /// If set, remote operations are also appended to this file
+    pub audit_log:      Option<String>,
+
+    /// Configured MIME types per lowercase file extension, used instead of the guessed MIME type
+    pub mime_types:     HashMap<String, String>,
+
+    /// Whether eligible uploads are converted to Google documents
+    pub convert:        bool,
+
+    /// Whether sparse files with enough holes are uploaded packed, see [crate::sparse]
+    pub pack_sparse:    bool,
+
+    /// The number of times a request to Google Drive is retried when it is rate limited or fails temporarily
+    pub max_retries:    u32,
+
+    /// If set, the upload bandwidth is limited, shared by all uploads
+    pub bwlimit:        Option<Arc<crate::throttle::RateLimiter>>,
+
+    /// The epoch time in seconds at which the current sync run started, recorded with every remote operation
+    pub run:            Option<i64>,
+
+    /// The access token cached in memory, shared between clones of this Env
+    pub token_cache:    Arc<Mutex<Option<CachedToken>>>,
+
+    /// The connection operations are recorded in the audit log with, opened on first use and shared between clones of this Env
+    pub audit_conn:     Arc<Mutex<Option<rusqlite::Connection>>>,
+
+    /// If set, GSync authenticates with a service account impersonating a user instead of with the OAuth2 login
+    pub service_account: Option<ServiceAccount>,
+
+    /// Whether Drive labels are configured, in which case the scope to read them is requested as well
+    pub labels:         bool
 }
 
+/// Struct describing a service account with domain-wide delegation, and the user it impersonates
+#[derive(Debug, Clone)]
+pub struct ServiceAccount {
+    /// Path to the JSON key of the service account
+    pub key_file:   String,
+
+    /// The email address of the user to impersonate
+    pub subject:    String
+}
+
+/// Struct describing an access token cached in memory
+#[derive(Debug, Clone)]
+pub struct CachedToken {
+    /// The access token
+    pub access_token:   String,
+
+    /// The epoch time in seconds at which the access token expires
+    pub expiry:         i64
+}
+
+/// How long a connection to the database waits for another connection to finish writing before it fails
+pub const BUSY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// The environment variable which, if set, overrides where GSync keeps its state, e.g. a volume mounted into a container
+pub const STATE_DIR_VAR: &str = "GSYNC_STATE_DIR";
+
 #[cfg(unix)]
 /// Unix path to the gsync home folder
 const DB_PATH: &str = "%home%/.gsync/";
 
+#[cfg(unix)]
+/// The name of the gsync home folder
+const DB_DIR_NAME: &str = ".gsync";
+
 #[cfg(windows)]
 /// Windows path to the gsync home folder
 const DB_PATH: &str = r#"%appdata%\gsync\"#;
 
+#[cfg(not(unix))]
+/// The name of the gsync home folder
+const DB_DIR_NAME: &str = "gsync";
+
+/// The name of the database file in the state directory
+pub const DB_FILE_NAME: &str = "data.db3";
+
 impl Env {
     /// Create a new instance of Env
     pub fn new<A, B, C, D>(id: A, secret: B, drive_id: Option<C>, root_folder: D) -> Self
     where A: AsRef<str>, B: AsRef<str>, C: AsRef<str>, D: AsRef<str> {
         let db = get_db_path();
-        if !std::path::Path::new(&db).exists() {
-            #[allow(clippy::panic)]
-            std::fs::create_dir_all(std::path::Path::new(&db)).unwrap_or_else(|f| panic!("Failed to create database folder at {}: {:?} ", &db, f));
-        }
+        create_db_dir(&db);
+        crate::redact::register(secret.as_ref());
 
         Self {
             db,
             client_secret:  secret.as_ref().to_string(),
             client_id:      id.as_ref().to_string(),
             drive_id:       drive_id.map(|id| id.as_ref().to_string()),
-            root_folder:    root_folder.as_ref().to_string()
+            root_folder:    root_folder.as_ref().to_string(),
+            target_folder:  None,
+            audit_log:      None,
+            mime_types:     HashMap::new(),
+            convert:        false,
+            pack_sparse:    false,
+            max_retries:    crate::api::retry::DEFAULT_MAX_RETRIES,
+            bwlimit:        None,
+            run:            None,
+            token_cache:    Arc::new(Mutex::new(None)),
+            audit_conn:     Arc::new(Mutex::new(None)),
+            service_account: None,
+            labels:         false
+        }
+    }
+
+    /// Create a new instance of Env from a Configuration. The caller should make sure the configuration is complete
+    pub fn from_config(config: &Configuration, drive_id: Option<&String>) -> Self {
+        let mut env = Self::new(config.client_id.as_deref().unwrap_or_default(), config.client_secret.as_deref().unwrap_or_default(), drive_id, String::new());
+        env.target_folder = config.target_folder.clone();
+        env.audit_log = config.audit_log.clone();
+        env.mime_types = config.mime_types.as_deref().and_then(|overrides| crate::mime::parse_overrides(overrides).ok()).unwrap_or_default();
+        env.convert = config.convert.unwrap_or(false);
+        env.pack_sparse = config.pack_sparse.unwrap_or(false);
+        env.max_retries = config.max_retries.map(|retries| retries as u32).unwrap_or(crate::api::retry::DEFAULT_MAX_RETRIES);
+        env.bwlimit = config.bwlimit.as_deref().and_then(crate::size::parse_size).and_then(crate::throttle::limiter);
+        env.labels = config.labels.is_some();
+        if let (Some(key_file), Some(subject)) = (&config.service_account_key, &config.impersonate) {
+            env.service_account = Some(ServiceAccount {
+                key_file:   key_file.clone(),
+                subject:    subject.clone()
+            });
         }
+
+        env
     }
 
     /// Create an empty instance of Env
     pub fn empty() -> Self {
 
         let db = get_db_path();
-        if !std::path::Path::new(&db).exists() {
-            #[allow(clippy::panic)]
-            std::fs::create_dir_all(std::path::Path::new(&db)).unwrap_or_else(|f| panic!("Failed to create database folder at {}: {:?} ", &db, f));
-        }
+        create_db_dir(&db);
 
         Self {
             db,
             client_id:      String::new(),
             client_secret:  String::new(),
             drive_id:       None,
-            root_folder:    String::new()
+            root_folder:    String::new(),
+            target_folder:  None,
+            audit_log:      None,
+            mime_types:     HashMap::new(),
+            convert:        false,
+            pack_sparse:    false,
+            max_retries:    crate::api::retry::DEFAULT_MAX_RETRIES,
+            bwlimit:        None,
+            run:            None,
+            token_cache:    Arc::new(Mutex::new(None)),
+            audit_conn:     Arc::new(Mutex::new(None)),
+            service_account: None,
+            labels:         false
         }
     }
 
-    /// Get a connection to the database
+    /// Get a connection to the database. Writes from concurrent uploads or another GSync process are waited for, up to [BUSY_TIMEOUT]
     pub fn get_conn(&self) -> Result<rusqlite::Connection, rusqlite::Error> {
         let mut path = std::path::PathBuf::from(&self.db);
-        path.push("data.db3");
+        path.push(DB_FILE_NAME);
+
+        // The database holds the client secret and the tokens, so only its owner may read it.
+        // It is created before SQLite opens it, which would create it readable by others for a moment
+        create_private_file(&path);
+        let conn = rusqlite::Connection::open(path.as_path())?;
+        restrict_permissions(&path, 0o600);
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        Ok(conn)
+    }
+}
+
+/// Create the database folder if it doesn't exist yet, accessible by its owner only
+fn create_db_dir(db: &str) {
+    // Without a trailing separator, so a symbolic link in place of the folder isn't followed
+    let path = std::path::Path::new(db).components().collect::<std::path::PathBuf>();
+    if !path.exists() {
+        let mut builder = std::fs::DirBuilder::new();
+        builder.recursive(true);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::DirBuilderExt;
+            builder.mode(0o700);
+        }
+
+        // Opening the database fails as well, which is reported by whoever needs it
+        if let Err(e) = builder.create(&path) {
+            eprintln!("Warning: Failed to create the database folder at {}: {}", db, e);
+        }
+
+        return;
+    }
+
+    // Folders created by older versions of GSync may be accessible by others. A folder set with the state directory variable
+    // may be shared with other programs, e.g. the home folder, so only GSync's own folder is changed
+    if is_own_db_dir(&path) {
+        restrict_permissions(&path, 0o700);
+    }
+}
+
+/// Whether a state directory is GSync's own folder, rather than a folder set with the state directory variable which may hold other files
+pub fn is_own_db_dir(path: &std::path::Path) -> bool {
+    path.file_name().is_some_and(|name| name == DB_DIR_NAME)
+}
 
-        rusqlite::Connection::open(path.as_path())
+/// Create a file accessible by its owner only, if it doesn't exist yet. Failures are ignored, opening the file reports them
+#[cfg(unix)]
+pub fn create_private_file(path: &std::path::Path) {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    // Creating a new file never follows a symbolic link in its place
+    let _ = std::fs::OpenOptions::new().write(true).create_new(true).mode(0o600).open(path);
+}
+
+/// On Windows the database folder is in the user's profile, which is only accessible to the user
+#[cfg(not(unix))]
+pub fn create_private_file(_path: &std::path::Path) {}
+
+/// Read a secret, e.g. a passphrase or a token, from the first line of a file.
+/// Secrets aren't accepted on the command line, where every local user could see them
+///
+/// ## Errors
+/// - When reading the file fails
+/// - When the first line of the file is empty
+pub fn read_secret_file(path: &std::path::Path) -> crate::Result<String> {
+    let contents = crate::unwrap_other_err!(std::fs::read_to_string(path));
+    match contents.lines().next().map(|line| line.trim_end_matches('\r')) {
+        Some(secret) if !secret.is_empty() => Ok(secret.to_string()),
+        _ => Err((crate::Error::Other(format!("'{}' is empty", path.to_string_lossy())), line!(), file!()))
+    }
+}
+
+/// Write a new file accessible by its owner only, e.g. a copy of the database. Fails if the file exists, so a link in its place is never followed
+///
+/// ## Errors
+/// - When the file exists
+/// - When creating or writing the file fails
+pub fn write_private_file(path: &std::path::Path, contents: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    options.open(path)?.write_all(contents)
+}
+
+/// Remove the permissions of the group and others from a file or folder, if it has any.
+/// Symbolic links aren't followed, and files owned by another user are left alone. Failures are ignored
+#[cfg(unix)]
+fn restrict_permissions(path: &std::path::Path, mode: u32) {
+    use std::os::unix::fs::{MetadataExt, OpenOptionsExt, PermissionsExt};
+
+    let file = match std::fs::OpenOptions::new().read(true).custom_flags(libc::O_NOFOLLOW).open(path) {
+        Ok(file) => file,
+        Err(_) => return
+    };
+
+    // The permissions are changed through the opened file, so the path can't be swapped for a link in between
+    if let Ok(metadata) = file.metadata() {
+        if metadata.uid() == ::users::get_current_uid() && metadata.permissions().mode() & 0o077 != 0 {
+            let _ = file.set_permissions(std::fs::Permissions::from_mode(mode));
+        }
+    }
+}
+
+/// On Windows the database folder is in the user's profile, which is only accessible to the user
+#[cfg(not(unix))]
+fn restrict_permissions(_path: &std::path::Path, _mode: u32) {}
+
+/// Get the database path, the folder in the state directory environment variable if it is set
+fn get_db_path() -> String {
+    match std::env::var(STATE_DIR_VAR) {
+        Ok(dir) if !dir.is_empty() => dir,
+        _ => default_db_path()
     }
 }
 
 cfg_if! {
     if #[cfg(unix)] {
-        /// Get the database path
-        fn get_db_path() -> String {
-            DB_PATH.replace("%home%", &std::env::var("HOME").unwrap())
+        /// Get the default database path. Without `HOME`, e.g. when started by a service manager, the home folder is looked up from the user database
+        fn default_db_path() -> String {
+            let home = std::env::var("HOME").ok()
+                .or_else(|| {
+                    use ::users::os::unix::UserExt;
+                    ::users::get_user_by_uid(::users::get_current_uid()).map(|user| user.home_dir().to_string_lossy().to_string())
+                })
+                .unwrap_or_else(|| ".".to_string());
+
+            DB_PATH.replace("%home%", &home)
         }
     } else if #[cfg(windows)] {
-        /// Get the database path
-        fn get_db_path() -> String {
-            DB_PATH.replace("%appdata%", &std::env::var("appdata").unwrap())
+        /// Get the default database path. Without `APPDATA` the current folder is used
+        fn default_db_path() -> String {
+            DB_PATH.replace("%appdata%", &std::env::var("appdata").unwrap_or_else(|_| ".".to_string()))
         }
     } else {
-        /// Get the database path
-        #[allow(clippy::panic)]
-        fn get_db_path() -> String {
-            panic!("Unsupported platform!");
+        /// Get the default database path. On other platforms the database is kept in the current folder
+        fn default_db_path() -> String {
+            "./.gsync/".to_string()
         }
     }
 }
+
+#[cfg(all(test, unix))]
+mod test {
+    use crate::env::{create_db_dir, create_private_file, restrict_permissions, write_private_file};
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::{Path, PathBuf};
+
+    /// Create an empty temporary directory for one test, unique to this process so concurrent test runs don't share it
+    fn temp_dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("gsync-{}-test-{}", test, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn mode(path: &Path) -> u32 {
+        std::fs::metadata(path).unwrap().permissions().mode() & 0o777
+    }
+
+    #[test]
+    fn restricts_without_following_links() {
+        let dir = temp_dir("restrict-permissions");
+
+        let target = dir.join("target");
+        std::fs::write(&target, "target").unwrap();
+        std::fs::set_permissions(&target, std::fs::Permissions::from_mode(0o644)).unwrap();
+        std::os::unix::fs::symlink(&target, dir.join("data.db3")).unwrap();
+
+        // A link in place of the database is neither replaced nor followed
+        create_private_file(&dir.join("data.db3"));
+        restrict_permissions(&dir.join("data.db3"), 0o600);
+        assert_eq!(0o644, mode(&target));
+        assert_eq!("target", std::fs::read_to_string(&target).unwrap());
+
+        restrict_permissions(&target, 0o600);
+        let restricted = mode(&target);
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!(0o600, restricted);
+    }
+
+    #[test]
+    fn creates_private_files() {
+        let dir = temp_dir("private-file");
+
+        create_private_file(&dir.join("data.db3"));
+        let created = mode(&dir.join("data.db3"));
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!(0o600, created);
+    }
+
+    #[test]
+    fn writes_private_files() {
+        let dir = temp_dir("write-private-file");
+
+        write_private_file(&dir.join("data.db3.restored"), b"secret").unwrap();
+        let created = mode(&dir.join("data.db3.restored"));
+        let again = write_private_file(&dir.join("data.db3.restored"), b"other");
+        let contents = std::fs::read(dir.join("data.db3.restored")).unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert_eq!(0o600, created);
+        assert!(again.is_err());
+        assert_eq!(b"secret".to_vec(), contents);
+    }
+
+    #[test]
+    fn only_restricts_its_own_folder() {
+        let dir = temp_dir("db-dir");
+
+        let created = dir.join("created/.gsync");
+        create_db_dir(&format!("{}/", created.to_string_lossy()));
+
+        let shared = dir.join("shared");
+        std::fs::create_dir_all(&shared).unwrap();
+        std::fs::set_permissions(&shared, std::fs::Permissions::from_mode(0o755)).unwrap();
+        create_db_dir(&shared.to_string_lossy());
+
+        let own = dir.join("own/.gsync");
+        std::fs::create_dir_all(&own).unwrap();
+        std::fs::set_permissions(&own, std::fs::Permissions::from_mode(0o755)).unwrap();
+        create_db_dir(&own.to_string_lossy());
+
+        let modes = (mode(&created), mode(&shared), mode(&own));
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!((0o700, 0o755, 0o700), modes);
+    }
+}
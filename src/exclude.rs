@@ -0,0 +1,139 @@
+//! Module for managing the stored exclusion patterns, globally or per input
+
+use crate::config::{Configuration, InputConfiguration};
+use crate::env::Env;
+use crate::filter::InputFilter;
+use crate::files;
+use crate::sync::{absolute_path, normalize_path};
+use crate::{Result, Error};
+use std::path::{Path, PathBuf};
+
+/// Add an exclusion pattern, to the patterns of an input or to the global patterns
+///
+/// ## Params
+/// - `input` The normalized path of the input, None for the global patterns
+///
+/// ## Errors
+/// - When the pattern contains a `,`, which separates the patterns
+/// - When a database operation fails
+pub fn add(env: &Env, input: Option<&Path>, pattern: &str) -> Result<bool> {
+    if pattern.is_empty() || pattern.contains(',') {
+        return Err((Error::Other("A pattern can't be empty or contain a ','".to_string()), line!(), file!()));
+    }
+
+    modify(env, input, |patterns| {
+        if patterns.iter().any(|existing| existing == pattern) {
+            return false;
+        }
+
+        patterns.push(pattern.to_string());
+        true
+    })
+}
+
+/// Remove an exclusion pattern, from the patterns of an input or from the global patterns
+///
+/// ## Params
+/// - `input` The normalized path of the input, None for the global patterns
+///
+/// ## Errors
+/// - When a database operation fails
+pub fn remove(env: &Env, input: Option<&Path>, pattern: &str) -> Result<bool> {
+    modify(env, input, |patterns| {
+        let count = patterns.len();
+        patterns.retain(|existing| existing != pattern);
+        patterns.len() != count
+    })
+}
+
+/// Modify the patterns of an input or the global patterns, and store them if they changed
+///
+/// ## Errors
+/// - When a database operation fails
+fn modify<F>(env: &Env, input: Option<&Path>, f: F) -> Result<bool>
+where F: FnOnce(&mut Vec<String>) -> bool {
+    match input {
+        Some(input) => {
+            let mut config = InputConfiguration::get(env, &input.to_string_lossy())?;
+            let mut patterns = config.excludes();
+            if !f(&mut patterns) {
+                return Ok(false);
+            }
+
+            config.excludes = Some(patterns.join(",")).filter(|patterns| !patterns.is_empty());
+            config.write(env)?;
+        },
+        None => {
+            let mut config = Configuration::get_config(env)?;
+            let mut patterns = config.excludes();
+            if !f(&mut patterns) {
+                return Ok(false);
+            }
+
+            config.excludes = Some(patterns.join(",")).filter(|patterns| !patterns.is_empty());
+            config.write(env)?;
+        }
+    }
+
+    Ok(true)
+}
+
+/// Get the tracked files and folders a pattern excludes, e.g. to show what a new pattern removes from the backup
+///
+/// ## Params
+/// - `input` The normalized path of the input the pattern applies to, None if it applies to all inputs
+///
+/// ## Errors
+/// - When a database operation fails
+pub fn excluded_tracked(config: &Configuration, env: &Env, input: Option<&Path>, pattern: &str) -> Result<Vec<String>> {
+    let inputs = config.input_files.as_deref().unwrap_or_default()
+        .split(',')
+        .filter(|input| !input.is_empty())
+        .map(|input| normalize_path(input).unwrap_or_else(|_| absolute_path(input)))
+        .collect::<Vec<PathBuf>>();
+
+    let filter = InputFilter { excludes: vec![pattern.to_string()], ..InputFilter::default() };
+    let mut excluded = files::get_all(env)?.into_iter()
+        .map(|file| file.path)
+        .filter(|path| {
+            let path = Path::new(path);
+            // A file belongs to the innermost input containing it
+            let owner = match inputs.iter().filter(|input| path.starts_with(input)).max_by_key(|input| input.components().count()) {
+                Some(owner) => owner,
+                None => return false
+            };
+
+            if input.map(|input| input != owner.as_path()).unwrap_or(false) {
+                return false;
+            }
+
+            is_excluded(&filter, owner, path)
+        })
+        .collect::<Vec<_>>();
+
+    excluded.sort();
+    Ok(excluded)
+}
+
+/// Whether a path is excluded by the filter, either itself or because one of the folders it is in is excluded
+fn is_excluded(filter: &InputFilter, input: &Path, path: &Path) -> bool {
+    path.ancestors()
+        .take_while(|ancestor| *ancestor != input && ancestor.starts_with(input))
+        .any(|ancestor| filter.is_excluded(input, ancestor))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::exclude::is_excluded;
+    use crate::filter::InputFilter;
+    use std::path::Path;
+
+    #[test]
+    fn excluded_by_folder() {
+        let filter = InputFilter { excludes: vec!["node_modules".to_string()], ..InputFilter::default() };
+        let input = Path::new("/home/user/code");
+
+        assert!(is_excluded(&filter, input, Path::new("/home/user/code/app/node_modules/lib/index.js")));
+        assert!(!is_excluded(&filter, input, Path::new("/home/user/code/app/src/index.js")));
+    }
+}
@@ -0,0 +1,146 @@
+//! Module for keeping track of the files and folders GSync has synced to Google Drive
+
+use crate::env::Env;
+use rusqlite::named_params;
+use crate::{Result, unwrap_db_err};
+
+/// Struct describing a file or folder tracked by GSync
+#[derive(Debug, Clone)]
+pub struct TrackedFile {
+    /// The local path of the file
+    pub path:       String,
+
+    /// The ID of the file in Google Drive
+    pub id:         String,
+
+    /// The ID of the parent folder in Google Drive
+    pub parent_id:  String,
+
+    /// Whether the file is a folder
+    pub is_folder:  bool,
+
+    /// If the file is a hard link or a symbolic link of another tracked file, the local path of that file
+    pub link_target: Option<String>,
+
+    /// The size of the file in bytes. None for folders
+    pub size:       Option<i64>,
+
+    /// The MD5 checksum of the file's content. None for folders
+    pub md5:        Option<String>,
+
+    /// The epoch time in seconds at which the file was last synced
+    pub synced_at:  Option<i64>
+}
+
+/// Insert a tracked file into the database, replacing the existing record for the same path
+///
+/// ## Errors
+/// - When a database operation fails
+pub fn insert(env: &Env, file: &TrackedFile) -> Result<()> {
+    let conn = unwrap_db_err!(env.get_conn());
+    unwrap_db_err!(conn.execute("INSERT OR REPLACE INTO files (path, id, parent_id, is_folder, link_target, size, md5, synced_at) VALUES (:path, :id, :parent_id, :is_folder, :link_target, :size, :md5, :synced_at)", named_params! {
+        ":path":        &file.path,
+        ":id":          &file.id,
+        ":parent_id":   &file.parent_id,
+        ":is_folder":   file.is_folder,
+        ":link_target": &file.link_target,
+        ":size":        &file.size,
+        ":md5":         &file.md5,
+        ":synced_at":   &file.synced_at
+    }));
+
+    Ok(())
+}
+
+/// Get all tracked files
+///
+/// ## Errors
+/// - When a database operation fails
+pub fn get_all(env: &Env) -> Result<Vec<TrackedFile>> {
+    let conn = unwrap_db_err!(env.get_conn());
+    let mut stmt = unwrap_db_err!(conn.prepare("SELECT path, id, parent_id, is_folder, link_target, size, md5, synced_at FROM files"));
+    let mut result = unwrap_db_err!(stmt.query(named_params! {}));
+
+    let mut files = Vec::new();
+    while let Some(row) = unwrap_db_err!(result.next()) {
+        files.push(unwrap_db_err!(read_row(row)));
+    }
+
+    Ok(files)
+}
+
+/// Get a tracked file by its ID in Google Drive
+///
+/// ## Errors
+/// - When a database operation fails
+pub fn get_by_id(env: &Env, id: &str) -> Result<Option<TrackedFile>> {
+    let conn = unwrap_db_err!(env.get_conn());
+    let mut stmt = unwrap_db_err!(conn.prepare("SELECT path, id, parent_id, is_folder, link_target, size, md5, synced_at FROM files WHERE id = :id"));
+    let mut result = unwrap_db_err!(stmt.query(named_params! {
+        ":id": id
+    }));
+
+    match unwrap_db_err!(result.next()) {
+        Some(row) => Ok(Some(unwrap_db_err!(read_row(row)))),
+        None => Ok(None)
+    }
+}
+
+/// Get a tracked file by its local path
+///
+/// ## Errors
+/// - When a database operation fails
+pub fn get_by_path(env: &Env, path: &str) -> Result<Option<TrackedFile>> {
+    let conn = unwrap_db_err!(env.get_conn());
+    let mut stmt = unwrap_db_err!(conn.prepare("SELECT path, id, parent_id, is_folder, link_target, size, md5, synced_at FROM files WHERE path = :path"));
+    let mut result = unwrap_db_err!(stmt.query(named_params! {
+        ":path": path
+    }));
+
+    match unwrap_db_err!(result.next()) {
+        Some(row) => Ok(Some(unwrap_db_err!(read_row(row)))),
+        None => Ok(None)
+    }
+}
+
+/// Stop tracking a file
+///
+/// ## Errors
+/// - When a database operation fails
+pub fn delete(env: &Env, path: &str) -> Result<()> {
+    let conn = unwrap_db_err!(env.get_conn());
+    unwrap_db_err!(conn.execute("DELETE FROM files WHERE path = :path", named_params! {
+        ":path": path
+    }));
+
+    Ok(())
+}
+
+/// Stop tracking a file or folder, and everything in the folder
+///
+/// ## Errors
+/// - When a database operation fails
+pub fn delete_tree(env: &Env, path: &str) -> Result<usize> {
+    let conn = unwrap_db_err!(env.get_conn());
+    // LIKE is case insensitive and treats `_` and `%` as wildcards, so the prefix is compared instead
+    let deleted = unwrap_db_err!(conn.execute("DELETE FROM files WHERE path = :path OR substr(path, 1, length(:prefix)) = :prefix", named_params! {
+        ":path":    path,
+        ":prefix":  format!("{}{}", path.trim_end_matches(std::path::MAIN_SEPARATOR), std::path::MAIN_SEPARATOR)
+    }));
+
+    Ok(deleted)
+}
+
+/// Read a TrackedFile from a row selected from the files table
+fn read_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<TrackedFile> {
+    Ok(TrackedFile {
+        path:       row.get::<&str, String>("path")?,
+        id:         row.get::<&str, String>("id")?,
+        parent_id:  row.get::<&str, String>("parent_id")?,
+        is_folder:  row.get::<&str, bool>("is_folder")?,
+        link_target: row.get::<&str, Option<String>>("link_target")?,
+        size:       row.get::<&str, Option<i64>>("size")?,
+        md5:        row.get::<&str, Option<String>>("md5")?,
+        synced_at:  row.get::<&str, Option<i64>>("synced_at")?
+    })
+}
@@ -0,0 +1,220 @@
+//! Module for deciding which files of an input are synced, based on the configuration of that input
+
+use crate::config::InputConfiguration;
+use std::path::Path;
+
+/// Struct describing which files of an input are synced
+#[derive(Debug, Clone)]
+pub struct InputFilter {
+    /// Patterns of files and folders which are not synced. A pattern without a `/` matches file names anywhere in the input,
+    /// a pattern with a `/` matches paths relative to the input. `*` matches any number of characters except `/`, `?` a single one
+    pub excludes:   Vec<String>,
+
+    /// Whether files and folders listed in .gitignore files are not synced
+    pub gitignore:  bool,
+
+    /// If set, files larger than this many bytes are not synced
+    pub max_size:   Option<u64>,
+
+    /// If not empty, only files with one of these extensions are synced. Lowercase, without the leading `.`
+    pub include_ext: Vec<String>,
+
+    /// Files with one of these extensions are not synced. Lowercase, without the leading `.`
+    pub exclude_ext: Vec<String>
+}
+
+impl Default for InputFilter {
+    fn default() -> Self {
+        Self {
+            excludes:   Vec::new(),
+            gitignore:  true,
+            max_size:   None,
+            include_ext: Vec::new(),
+            exclude_ext: Vec::new()
+        }
+    }
+}
+
+impl InputFilter {
+    /// Create the filter of an input from its configuration
+    pub fn for_input(input: &InputConfiguration) -> Self {
+        Self {
+            excludes:   input.excludes(),
+            gitignore:  input.gitignore.unwrap_or(true),
+            max_size:   input.max_size.filter(|max_size| *max_size > 0).map(|max_size| max_size as u64),
+            include_ext: Vec::new(),
+            exclude_ext: Vec::new()
+        }
+    }
+
+    /// Whether a file or folder is excluded by one of the patterns
+    ///
+    /// ## Params
+    /// - `input` The input the path belongs to
+    /// - `path` The path of the file or folder
+    pub fn is_excluded(&self, input: &Path, path: &Path) -> bool {
+        is_excluded_by(&self.excludes, input, path)
+    }
+
+    /// Whether a file is too large to be synced
+    pub fn is_too_large(&self, size: u64) -> bool {
+        matches!(self.max_size, Some(max_size) if size > max_size)
+    }
+
+    /// Whether a file is not synced because of its extension. Extensions are compared ignoring case, and a file without an extension is only synced if no extensions are included
+    pub fn is_excluded_ext(&self, path: &Path) -> bool {
+        if self.include_ext.is_empty() && self.exclude_ext.is_empty() {
+            return false;
+        }
+
+        let extension = path.extension().map(|extension| extension.to_string_lossy().to_lowercase());
+        match extension {
+            Some(extension) if self.exclude_ext.contains(&extension) => true,
+            Some(extension) => !self.include_ext.is_empty() && !self.include_ext.contains(&extension),
+            None => !self.include_ext.is_empty()
+        }
+    }
+}
+
+/// Parse a comma separated list of file extensions, e.g. `jpg,.RAW`, into lowercase extensions without the leading `.`
+pub fn parse_extensions(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(|extension| extension.trim().trim_start_matches('.').to_lowercase())
+        .filter(|extension| !extension.is_empty())
+        .collect()
+}
+
+/// Whether a file or folder is excluded by one of the patterns. A pattern without a `/` matches file names anywhere in the root,
+/// a pattern with a `/` matches paths relative to the root
+///
+/// ## Params
+/// - `root` The folder the patterns apply to, e.g. an input
+/// - `path` The path of the file or folder
+pub fn is_excluded_by(patterns: &[String], root: &Path, path: &Path) -> bool {
+    if patterns.is_empty() {
+        return false;
+    }
+
+    let name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    // The path relative to the folder containing the root, so a pattern can start with the name of the root
+    let relative = match path.strip_prefix(root.parent().unwrap_or(root)) {
+        Ok(relative) => relative.to_string_lossy().replace('\\', "/"),
+        Err(_) => return false
+    };
+
+    patterns.iter().any(|pattern| {
+        let pattern = pattern.trim_matches('/');
+        if pattern.contains('/') {
+            // Relative to the root itself, or to the folder containing it
+            let in_root = relative.split_once('/').map(|(_, in_root)| in_root).unwrap_or_default();
+            glob_match(pattern, in_root) || glob_match(pattern, &relative)
+        } else {
+            glob_match(pattern, &name)
+        }
+    })
+}
+
+/// Parse exclusion patterns from the contents of a file in gitignore syntax, one pattern per line. Empty lines and comments starting with `#` are ignored,
+/// as are negated patterns starting with `!`, which are not supported. A trailing `/` is dropped, so the pattern matches files as well as folders
+pub fn parse_patterns(contents: &str) -> Vec<String> {
+    contents.lines()
+        .map(|line| line.trim_end())
+        .filter(|line| !line.is_empty() && !line.starts_with('#') && !line.starts_with('!'))
+        .map(|line| line.strip_prefix('\\').unwrap_or(line))
+        .map(|line| line.trim_end_matches('/'))
+        .filter(|line| !line.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Match text against a pattern, where `*` matches any number of characters except `/` and `?` matches a single character except `/`
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+
+    // The positions to resume from when a `*` has to match more characters
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            },
+            Some('?') if text[t] != '/' => {
+                p += 1;
+                t += 1;
+            },
+            Some(c) if *c == text[t] => {
+                p += 1;
+                t += 1;
+            },
+            _ => match backtrack {
+                Some((star, matched)) if text[matched] != '/' => {
+                    backtrack = Some((star, matched + 1));
+                    p = star + 1;
+                    t = matched + 1;
+                },
+                _ => return false
+            }
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod test {
+    use crate::filter::{glob_match, parse_extensions, parse_patterns, InputFilter};
+    use std::path::Path;
+
+    #[test]
+    fn glob_wildcards() {
+        assert!(glob_match("*.jpg", "holiday.jpg"));
+        assert!(!glob_match("*.jpg", "holiday.jpeg"));
+        assert!(glob_match("IMG_????.CR2", "IMG_0042.CR2"));
+        assert!(glob_match("target", "target"));
+        assert!(!glob_match("*.log", "logs/app.log"));
+        assert!(glob_match("logs/*.log", "logs/app.log"));
+    }
+
+    #[test]
+    fn excludes_by_name_and_path() {
+        let filter = InputFilter { excludes: vec!["node_modules".to_string(), "build/*.o".to_string()], ..InputFilter::default() };
+        let input = Path::new("/home/user/code");
+
+        assert!(filter.is_excluded(input, Path::new("/home/user/code/app/node_modules")));
+        assert!(filter.is_excluded(input, Path::new("/home/user/code/build/main.o")));
+        assert!(!filter.is_excluded(input, Path::new("/home/user/code/src/build/main.o")));
+        assert!(!filter.is_excluded(input, Path::new("/home/user/code/src/main.rs")));
+    }
+
+    #[test]
+    fn patterns_from_file() {
+        let patterns = parse_patterns("# Caches\nnode_modules/\n\n*.tmp  \n!keep.tmp\n\\#notes\nbuild/*.o\n");
+        assert_eq!(vec!["node_modules", "*.tmp", "#notes", "build/*.o"], patterns);
+    }
+
+    #[test]
+    fn max_size() {
+        let filter = InputFilter { max_size: Some(1024), ..InputFilter::default() };
+        assert!(!filter.is_too_large(1024));
+        assert!(filter.is_too_large(1025));
+        assert!(!InputFilter::default().is_too_large(u64::MAX));
+    }
+
+    #[test]
+    fn extensions() {
+        assert_eq!(vec!["jpg", "raw"], parse_extensions("jpg, .RAW,"));
+
+        let filter = InputFilter { include_ext: parse_extensions("jpg,raw"), exclude_ext: parse_extensions("tmp"), ..InputFilter::default() };
+        assert!(!filter.is_excluded_ext(Path::new("/photos/IMG_0042.JPG")));
+        assert!(filter.is_excluded_ext(Path::new("/photos/notes.txt")));
+        assert!(filter.is_excluded_ext(Path::new("/photos/README")));
+
+        let filter = InputFilter { exclude_ext: parse_extensions("log,tmp"), ..InputFilter::default() };
+        assert!(filter.is_excluded_ext(Path::new("/code/app.log")));
+        assert!(!filter.is_excluded_ext(Path::new("/code/README")));
+        assert!(!InputFilter::default().is_excluded_ext(Path::new("/code/app.log")));
+    }
+}
@@ -0,0 +1,128 @@
+//! Module for forgetting files and folders. GSync stops tracking and syncing them, while their copies in Google Drive are left in place
+
+use crate::config::{Configuration, InputConfiguration};
+use crate::env::Env;
+use crate::files;
+use crate::sync::{absolute_path, normalize_path};
+use crate::{Result, Error};
+use std::path::{Path, PathBuf};
+
+/// Struct describing what was forgotten
+#[derive(Debug)]
+pub struct Forgotten {
+    /// The number of files and folders which are no longer tracked
+    pub tracked:    usize,
+
+    /// Whether the path was an input, which was removed from the configuration
+    pub input:      bool,
+
+    /// The input the path was excluded from, so it isn't synced again by the input containing it
+    pub excluded_from: Option<PathBuf>
+}
+
+/// Forget a file or folder. It is no longer tracked and excluded from future syncs, but unlike removing it locally or from the inputs,
+/// its copy in Google Drive is not deleted. If the path is an input, it is removed from the configured inputs
+///
+/// ## Params
+/// - `path` The normalized path to forget
+///
+/// ## Errors
+/// - When the path is not within a configured input
+/// - When the path can't be expressed as exclusion pattern
+/// - When a database operation fails
+pub fn forget(config: &Configuration, env: &Env, path: &Path) -> Result<Forgotten> {
+    let configured = config.input_files.as_deref().unwrap_or_default()
+        .split(',')
+        .filter(|input| !input.is_empty())
+        .map(|input| (input, normalize_path(input).unwrap_or_else(|_| absolute_path(input))))
+        .collect::<Vec<_>>();
+
+    if !configured.iter().any(|(_, input)| path.starts_with(input)) {
+        return Err((Error::Other(format!("'{}' is not within a configured input", path.to_string_lossy())), line!(), file!()));
+    }
+
+    let is_input = configured.iter().any(|(_, input)| input.eq(path));
+
+    // A nested input is synced by the input containing it once it's removed, so it's excluded from that one as well
+    let container = configured.iter()
+        .map(|(_, input)| input)
+        .filter(|input| path.starts_with(input) && !input.as_path().eq(path))
+        .max_by_key(|input| input.components().count());
+
+    if let Some(container) = container {
+        let pattern = match exclude_pattern(container, path) {
+            Some(pattern) => pattern,
+            None => return Err((Error::Other(format!("'{}' can't be excluded from input '{}', its path contains a ',', '*' or '?'", path.to_string_lossy(), container.to_string_lossy())), line!(), file!()))
+        };
+
+        let mut input = InputConfiguration::get(env, &container.to_string_lossy())?;
+        let mut excludes = input.excludes();
+        if !excludes.contains(&pattern) {
+            excludes.push(pattern);
+            input.excludes = Some(excludes.join(","));
+            input.write(env)?;
+        }
+    }
+
+    if is_input {
+        let mut config = Configuration::get_config(env)?;
+        config.input_files = Some(configured.iter()
+            .filter(|(_, input)| !input.eq(path))
+            .map(|(raw, _)| *raw)
+            .collect::<Vec<_>>()
+            .join(","));
+        config.write(env)?;
+    }
+
+    let tracked = files::delete_tree(env, &path.to_string_lossy())?;
+
+    Ok(Forgotten {
+        tracked,
+        input:  is_input,
+        excluded_from: container.cloned()
+    })
+}
+
+/// The exclusion pattern matching exactly the provided path in an input. The pattern starts with the name of the input,
+/// so it contains a `/` and is matched against the path rather than against names anywhere in the input.
+/// None if the path contains a `,`, which separates the patterns, or a `*` or `?`, which would make the pattern match other files as well.
+/// Patterns have no way to escape these
+fn exclude_pattern(input: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(input.parent().unwrap_or(input)).ok()?
+        .to_string_lossy()
+        .replace('\\', "/");
+
+    if relative.contains(&[',', '*', '?'][..]) {
+        return None;
+    }
+
+    Some(relative)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::filter::InputFilter;
+    use crate::forget::exclude_pattern;
+    use std::path::Path;
+
+    #[test]
+    fn pattern_matches_only_the_path() {
+        let input = Path::new("/home/user/code");
+        let pattern = exclude_pattern(input, Path::new("/home/user/code/notes.txt")).unwrap();
+        assert_eq!("code/notes.txt", pattern);
+
+        let filter = InputFilter { excludes: vec![pattern], ..InputFilter::default() };
+        assert!(filter.is_excluded(input, Path::new("/home/user/code/notes.txt")));
+        assert!(!filter.is_excluded(input, Path::new("/home/user/code/docs/notes.txt")));
+
+        assert_eq!(None, exclude_pattern(input, Path::new("/home/user/code/a,b")));
+        assert_eq!(None, exclude_pattern(input, Path::new("/home/user/code/*.txt")));
+        assert_eq!(None, exclude_pattern(input, Path::new("/home/user/code/what?.txt")));
+
+        // Only * and ? are wildcards, brackets and braces match themselves
+        let pattern = exclude_pattern(input, Path::new("/home/user/code/[draft]{1}.txt")).unwrap();
+        let filter = InputFilter { excludes: vec![pattern], ..InputFilter::default() };
+        assert!(filter.is_excluded(input, Path::new("/home/user/code/[draft]{1}.txt")));
+        assert!(!filter.is_excluded(input, Path::new("/home/user/code/d1.txt")));
+    }
+}
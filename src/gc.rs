@@ -0,0 +1,64 @@
+//! Module for removing stale state from the database
+
+use crate::config::{Configuration, InputConfiguration};
+use crate::env::Env;
+use crate::{Result, unwrap_db_err};
+use crate::files;
+use crate::sync::{absolute_path, normalize_path};
+use rusqlite::named_params;
+use std::path::{Path, PathBuf};
+
+/// The default number of days runs are kept in the run history
+pub const DEFAULT_HISTORY_RETENTION: i64 = 90;
+
+/// Remove stale state from the database:
+/// - Configuration of inputs which are no longer configured
+/// - Tracked files which do not belong to any configured input
+/// - Runs in the run history older than the configured retention
+///
+/// Afterwards the database file is compacted
+///
+/// ## Errors
+/// - When a database operation fails
+pub fn gc(config: &Configuration, env: &Env) -> Result<()> {
+    let inputs = config.input_files.as_deref().unwrap_or_default()
+        .split(',')
+        .filter(|input| !input.is_empty())
+        // An input which can't be resolved, e.g. an unmounted drive, is still configured, so its state must be kept
+        .map(|input| normalize_path(input).unwrap_or_else(|_| absolute_path(input)))
+        .collect::<Vec<PathBuf>>();
+
+    let conn = unwrap_db_err!(env.get_conn());
+
+    let mut removed_inputs = 0usize;
+    for input in InputConfiguration::get_all(env)? {
+        if !inputs.iter().any(|configured| configured.eq(Path::new(&input.path))) {
+            removed_inputs += unwrap_db_err!(conn.execute("DELETE FROM inputs WHERE path = :path", named_params! {
+                ":path": &input.path
+            }));
+        }
+    }
+    println!("Info: Removed the configuration of {} inputs which are no longer configured", removed_inputs);
+
+    let mut removed_files = 0usize;
+    for file in files::get_all(env)? {
+        if !inputs.iter().any(|input| Path::new(&file.path).starts_with(input)) {
+            removed_files += unwrap_db_err!(conn.execute("DELETE FROM files WHERE path = :path", named_params! {
+                ":path": &file.path
+            }));
+        }
+    }
+    println!("Info: Removed {} tracked files which do not belong to a configured input", removed_files);
+
+    let retention = config.history_retention.unwrap_or(DEFAULT_HISTORY_RETENTION);
+    let cutoff = chrono::Utc::now().timestamp() - retention * 24 * 60 * 60;
+    let removed_runs = unwrap_db_err!(conn.execute("DELETE FROM runs WHERE started < :cutoff", named_params! {
+        ":cutoff": cutoff
+    }));
+    println!("Info: Removed {} runs older than {} days from the run history", removed_runs, retention);
+
+    println!("Info: Compacting the database");
+    unwrap_db_err!(conn.execute("VACUUM", named_params! {}));
+
+    Ok(())
+}
@@ -0,0 +1,305 @@
+//! Module for computing checksums of local files
+
+use crate::env::Env;
+use crate::{Result, unwrap_db_err, unwrap_other_err};
+use rusqlite::named_params;
+use sha2::digest::Digest;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::io::Read;
+use std::sync::{Mutex, PoisonError};
+use std::time::SystemTime;
+
+/// The algorithms GSync can compute checksums of local files with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HashAlgorithm {
+    /// BLAKE3, which is a lot faster than the others
+    Blake3,
+
+    /// MD5, the algorithm Google Drive reports checksums in
+    Md5,
+
+    /// SHA-256, for when a standardized algorithm is required
+    Sha256
+}
+
+/// The algorithm used when none is configured
+pub const DEFAULT_ALGORITHM: HashAlgorithm = HashAlgorithm::Blake3;
+
+/// The names of the algorithms, as accepted on the command line
+pub const ALGORITHM_NAMES: [&str; 3] = ["blake3", "md5", "sha256"];
+
+impl HashAlgorithm {
+    /// Parse an algorithm from its name
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "blake3" => Some(Self::Blake3),
+            "md5" => Some(Self::Md5),
+            "sha256" => Some(Self::Sha256),
+            _ => None
+        }
+    }
+
+    /// The configured algorithm, or the default if none is configured
+    pub fn resolve(configured: Option<&str>) -> Self {
+        configured.and_then(Self::parse).unwrap_or(DEFAULT_ALGORITHM)
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Blake3 => "blake3",
+            Self::Md5 => "md5",
+            Self::Sha256 => "sha256"
+        };
+
+        f.pad(name)
+    }
+}
+
+/// The state of computing a checksum with one of the algorithms
+enum Hasher {
+    /// BLAKE3
+    Blake3(Box<blake3::Hasher>),
+
+    /// MD5
+    Md5(md5::Context),
+
+    /// SHA-256
+    Sha256(sha2::Sha256)
+}
+
+impl Hasher {
+    /// Start computing a checksum
+    fn new(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Blake3 => Self::Blake3(Box::new(blake3::Hasher::new())),
+            HashAlgorithm::Md5 => Self::Md5(md5::Context::new()),
+            HashAlgorithm::Sha256 => Self::Sha256(sha2::Sha256::new())
+        }
+    }
+
+    /// Add data to the checksum
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Blake3(hasher) => { hasher.update(data); },
+            Self::Md5(context) => context.consume(data),
+            Self::Sha256(hasher) => hasher.update(data)
+        }
+    }
+
+    /// Finish the checksum, as lowercase hex String
+    fn finish(self) -> String {
+        match self {
+            Self::Blake3(hasher) => hasher.finalize().to_hex().to_string(),
+            Self::Md5(context) => format!("{:x}", context.compute()),
+            Self::Sha256(hasher) => format!("{:x}", hasher.finalize())
+        }
+    }
+}
+
+/// Compute checksums of a file with several algorithms, reading the file only once
+///
+/// ## Errors
+/// - When reading the file fails
+fn hash_with(path: &Path, algorithms: &[HashAlgorithm]) -> Result<Vec<String>> {
+    let file = unwrap_other_err!(std::fs::File::open(path));
+    hash_all(file, algorithms)
+}
+
+/// Compute the checksum of everything a reader reads with the provided algorithm, as lowercase hex String
+///
+/// ## Errors
+/// - When reading fails
+pub fn hash_reader<R: Read>(reader: R, algorithm: HashAlgorithm) -> Result<String> {
+    let mut checksums = hash_all(reader, &[algorithm])?;
+    Ok(checksums.remove(0))
+}
+
+/// Compute checksums of everything a reader reads with several algorithms
+///
+/// ## Errors
+/// - When reading fails
+fn hash_all<R: Read>(mut reader: R, algorithms: &[HashAlgorithm]) -> Result<Vec<String>> {
+    let mut hashers = algorithms.iter().map(|algorithm| Hasher::new(*algorithm)).collect::<Vec<_>>();
+    let mut buffer = vec![0u8; 64 * 1024];
+
+    loop {
+        let read = unwrap_other_err!(reader.read(&mut buffer));
+        if read == 0 {
+            break;
+        }
+
+        for hasher in hashers.iter_mut() {
+            hasher.update(&buffer[..read]);
+        }
+    }
+
+    Ok(hashers.into_iter().map(Hasher::finish).collect())
+}
+
+/// Compute the MD5 checksum of a file, as lowercase hex String. This is the same format Google Drive uses for `md5Checksum`
+///
+/// ## Errors
+/// - When reading the file fails
+pub fn md5_file(path: &Path) -> Result<String> {
+    hash_file(path, HashAlgorithm::Md5)
+}
+
+/// Compute the checksum of a file with the provided algorithm, as lowercase hex String
+///
+/// ## Errors
+/// - When reading the file fails
+pub fn hash_file(path: &Path, algorithm: HashAlgorithm) -> Result<String> {
+    let mut checksums = hash_with(path, &[algorithm])?;
+    Ok(checksums.remove(0))
+}
+
+/// Compute the MD5 checksum of a file, which Google Drive can verify, together with its checksum with the provided algorithm, reading the file only once
+///
+/// ## Errors
+/// - When reading the file fails
+pub fn checksums(path: &Path, algorithm: HashAlgorithm) -> Result<(String, String)> {
+    let mut checksums = hash_with(path, &[HashAlgorithm::Md5, algorithm])?;
+    let hash = checksums.remove(1);
+    Ok((checksums.remove(0), hash))
+}
+
+/// A cached checksum: the size and modification time of the file when it was hashed, and the checksum
+type CachedHash = (u64, SystemTime, String);
+
+/// Checksums of local files computed during a run, so the plan and the sync don't both read every file they compare.
+/// A checksum is only reused while the file has the size and modification time it had when it was hashed
+#[derive(Debug, Default)]
+pub struct HashCache {
+    /// The checksums by the path of the file and the algorithm
+    hashes: Mutex<HashMap<(PathBuf, HashAlgorithm), CachedHash>>
+}
+
+impl HashCache {
+    /// Compute the checksum of a file, or reuse the checksum computed earlier in the run, see [hash_file]
+    ///
+    /// ## Params
+    /// - `path` The path the checksum is cached by
+    /// - `source` Where the file is read, which differs from `path` when syncing from a filesystem snapshot
+    /// - `algorithm` The algorithm to compute the checksum with
+    ///
+    /// ## Errors
+    /// - When reading the file fails
+    pub fn hash_file(&self, path: &Path, source: &Path, algorithm: HashAlgorithm) -> Result<String> {
+        // The metadata is read before the content, so a file changing while it is hashed is hashed again next time
+        let meta = unwrap_other_err!(std::fs::metadata(source));
+        let version = (meta.len(), unwrap_other_err!(meta.modified()));
+        let key = (path.to_path_buf(), algorithm);
+        if let Some((size, modified, hash)) = self.hashes.lock().unwrap_or_else(PoisonError::into_inner).get(&key) {
+            if (*size, *modified) == version {
+                return Ok(hash.clone());
+            }
+        }
+
+        let hash = hash_file(source, algorithm)?;
+        self.hashes.lock().unwrap_or_else(PoisonError::into_inner).insert(key, (version.0, version.1, hash.clone()));
+        Ok(hash)
+    }
+}
+
+/// Store the checksum of a tracked file. The algorithm is stored with it, so checksums stay valid when another algorithm is configured later
+///
+/// ## Errors
+/// - When a database operation fails
+pub fn store(env: &Env, path: &str, algorithm: HashAlgorithm, hash: &str) -> Result<()> {
+    let conn = unwrap_db_err!(env.get_conn());
+    unwrap_db_err!(conn.execute("UPDATE files SET hash = :hash, hash_algorithm = :algorithm WHERE path = :path", named_params! {
+        ":hash":        hash,
+        ":algorithm":   algorithm.to_string(),
+        ":path":        path
+    }));
+
+    Ok(())
+}
+
+/// Get the stored checksum of a tracked file, together with the algorithm it was computed with.
+/// Only a checksum stored for the same file in Google Drive is returned
+///
+/// ## Errors
+/// - When a database operation fails
+pub fn stored(env: &Env, path: &str, id: &str) -> Result<Option<(HashAlgorithm, String)>> {
+    let conn = unwrap_db_err!(env.get_conn());
+    let mut stmt = unwrap_db_err!(conn.prepare("SELECT hash, hash_algorithm FROM files WHERE path = :path AND id = :id AND hash IS NOT NULL"));
+    let mut rows = unwrap_db_err!(stmt.query(named_params! {
+        ":path":    path,
+        ":id":      id
+    }));
+
+    let row = match unwrap_db_err!(rows.next()) {
+        Some(row) => row,
+        None => return Ok(None)
+    };
+
+    let hash = unwrap_db_err!(row.get::<&str, String>("hash"));
+    let algorithm = unwrap_db_err!(row.get::<&str, String>("hash_algorithm"));
+    Ok(HashAlgorithm::parse(&algorithm).map(|algorithm| (algorithm, hash)))
+}
+
+/// Get the stored checksums of all tracked files by their local path, together with the algorithm they were computed with
+///
+/// ## Errors
+/// - When a database operation fails
+pub fn stored_all(env: &Env) -> Result<HashMap<String, (HashAlgorithm, String)>> {
+    let conn = unwrap_db_err!(env.get_conn());
+    let mut stmt = unwrap_db_err!(conn.prepare("SELECT path, hash, hash_algorithm FROM files WHERE hash IS NOT NULL"));
+    let mut rows = unwrap_db_err!(stmt.query(named_params! {}));
+
+    let mut hashes = HashMap::new();
+    while let Some(row) = unwrap_db_err!(rows.next()) {
+        let path = unwrap_db_err!(row.get::<&str, String>("path"));
+        let hash = unwrap_db_err!(row.get::<&str, String>("hash"));
+        let algorithm = unwrap_db_err!(row.get::<&str, String>("hash_algorithm"));
+        if let Some(algorithm) = HashAlgorithm::parse(&algorithm) {
+            hashes.insert(path, (algorithm, hash));
+        }
+    }
+
+    Ok(hashes)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::hash::{checksums, hash_file, HashAlgorithm, HashCache};
+
+    #[test]
+    fn algorithms() {
+        let path = std::env::temp_dir().join(format!("gsync-test-hash-{}", std::process::id()));
+        std::fs::write(&path, b"abc").unwrap();
+
+        let (md5, blake3) = checksums(&path, HashAlgorithm::Blake3).unwrap();
+        let sha256 = hash_file(&path, HashAlgorithm::Sha256).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!("900150983cd24fb0d6963f7d28e17f72", md5);
+        assert_eq!("6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85", blake3);
+        assert_eq!("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad", sha256);
+    }
+
+    #[test]
+    fn cache() {
+        let path = std::env::temp_dir().join(format!("gsync-test-hash-cache-{}", std::process::id()));
+        let cache = HashCache::default();
+        std::fs::write(&path, b"abc").unwrap();
+        let md5 = cache.hash_file(&path, &path, HashAlgorithm::Md5).unwrap();
+
+        // A cached checksum is reused for the same file, a file which changed is hashed again
+        let key = (path.clone(), HashAlgorithm::Md5);
+        cache.hashes.lock().unwrap().get_mut(&key).unwrap().2 = "cached".to_string();
+        assert_eq!("cached", cache.hash_file(&path, &path, HashAlgorithm::Md5).unwrap());
+
+        std::fs::write(&path, b"abcd").unwrap();
+        let changed = cache.hash_file(&path, &path, HashAlgorithm::Md5).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!("900150983cd24fb0d6963f7d28e17f72", md5);
+        assert_eq!("e2fc714c4727ee9395f324cd2e7f331f", changed);
+    }
+}
@@ -0,0 +1,154 @@
+//! Module for applying Drive labels to files created by GSync
+//!
+//! Labels are configured as a comma separated list of entries. An entry is either a label ID, to apply the label without any field values,
+//! or `LABEL_ID:FIELD=VALUE` to apply the label and set one of its fields. The field may be given by its ID or its name,
+//! for selection fields the value may be the ID or the name of a choice. Dates are formatted as YYYY-MM-DD
+
+use crate::env::Env;
+use crate::{Result, Error};
+use crate::api::labels::{self, Field};
+use crate::api::drive::{LabelModification, FieldModification, LabelDate};
+use chrono::Datelike;
+
+/// Struct describing a single entry of the label configuration
+#[derive(Debug, PartialEq)]
+struct LabelEntry<'a> {
+    /// The ID of the label
+    label_id:   &'a str,
+
+    /// The field and the value to set it to, if any
+    field:      Option<(&'a str, &'a str)>
+}
+
+/// Resolve the label configuration into the modifications to apply to files.
+/// The labels are looked up with the Drive Labels API, to determine the types of their fields
+///
+/// ## Errors
+/// - When the label configuration is malformed
+/// - When a label or field does not exist, or a value is not valid for its field
+/// - When the Drive Labels API returns an error
+pub fn resolve(env: &Env, config: &str) -> Result<Vec<LabelModification>> {
+    let mut modifications: Vec<(LabelModification, Vec<Field>)> = Vec::new();
+
+    for entry in parse(config)? {
+        if !modifications.iter().any(|(modification, _)| modification.label_id.eq(entry.label_id)) {
+            let label = match labels::get_label(env, entry.label_id) {
+                Err((Error::GoogleError(e), _, _)) if e.code == 403 => return Err((Error::Other(format!("GSync may not read label '{}', log in again with `gsync login` to allow it: {}", entry.label_id, e.message)), line!(), file!())),
+                result => result?
+            };
+            println!("Info: Files created by GSync will be labeled '{}'", &label.properties.title);
+            modifications.push((LabelModification { label_id: label.id, field_modifications: Vec::new() }, label.fields));
+        }
+
+        let (field_name, value) = match entry.field {
+            Some(field) => field,
+            None => continue
+        };
+
+        // Unwrap is safe because the label was inserted above
+        let (modification, fields) = modifications.iter_mut().find(|(modification, _)| modification.label_id.eq(entry.label_id)).unwrap();
+        let field = match fields.iter().find(|field| field.id.eq(field_name) || field.properties.display_name.eq(field_name)) {
+            Some(field) => field,
+            None => return Err((Error::Other(format!("Label '{}' has no field '{}'", entry.label_id, field_name)), line!(), file!()))
+        };
+
+        modification.field_modifications.push(field_modification(field, value)?);
+    }
+
+    Ok(modifications.into_iter().map(|(modification, _)| modification).collect())
+}
+
+/// Parse the label configuration
+///
+/// ## Errors
+/// - When an entry is malformed
+fn parse(config: &str) -> Result<Vec<LabelEntry<'_>>> {
+    let mut entries = Vec::new();
+    for entry in config.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+        let parsed = match entry.split_once(':') {
+            None => LabelEntry { label_id: entry, field: None },
+            Some((label_id, field)) => match field.split_once('=') {
+                Some((field, value)) if !label_id.is_empty() && !field.is_empty() => LabelEntry { label_id, field: Some((field, value)) },
+                _ => return Err((Error::Other(format!("Label entry '{}' is not formatted as LABEL_ID:FIELD=VALUE", entry)), line!(), file!()))
+            }
+        };
+
+        entries.push(parsed);
+    }
+
+    Ok(entries)
+}
+
+/// Create the modification setting a field to a value, based on the type of the field
+///
+/// ## Errors
+/// - When the value is not valid for the field
+fn field_modification(field: &Field, value: &str) -> Result<FieldModification> {
+    let mut modification = FieldModification {
+        field_id: field.id.clone(),
+        ..FieldModification::default()
+    };
+
+    if let Some(options) = &field.selection_options {
+        match options.choices.iter().find(|choice| choice.id.eq(value) || choice.properties.display_name.eq(value)) {
+            Some(choice) => modification.set_selection_values.push(choice.id.clone()),
+            None => return Err((Error::Other(format!("'{}' is not a choice of field '{}'", value, &field.properties.display_name)), line!(), file!()))
+        }
+    } else if field.integer_options.is_some() {
+        match value.parse::<i64>() {
+            Ok(integer) => modification.set_integer_values.push(integer.to_string()),
+            Err(_) => return Err((Error::Other(format!("'{}' is not a valid integer for field '{}'", value, &field.properties.display_name)), line!(), file!()))
+        }
+    } else if field.date_options.is_some() {
+        match chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+            Ok(date) => modification.set_date_values.push(LabelDate { year: date.year(), month: date.month(), day: date.day() }),
+            Err(_) => return Err((Error::Other(format!("'{}' is not a valid date (YYYY-MM-DD) for field '{}'", value, &field.properties.display_name)), line!(), file!()))
+        }
+    } else if field.user_options.is_some() {
+        modification.set_user_values.push(value.to_string());
+    } else if let Some(options) = &field.text_options {
+        let length = value.chars().count();
+        if options.min_length.map(|min| length < min).unwrap_or(false) || options.max_length.map(|max| length > max).unwrap_or(false) {
+            return Err((Error::Other(format!("'{}' is not between {} and {} characters long, as field '{}' requires", value, options.min_length.unwrap_or(0), options.max_length.map(|max| max.to_string()).unwrap_or_else(|| "any".to_string()), &field.properties.display_name)), line!(), file!()));
+        }
+
+        modification.set_text_values.push(value.to_string());
+    } else {
+        return Err((Error::Other(format!("Field '{}' has a type GSync can't set", &field.properties.display_name)), line!(), file!()));
+    }
+
+    Ok(modification)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::labels::{field_modification, parse, LabelEntry};
+    use crate::api::labels::Field;
+
+    #[test]
+    fn parse_label_without_field() {
+        assert_eq!(parse("abc").unwrap(), vec![LabelEntry { label_id: "abc", field: None }]);
+    }
+
+    #[test]
+    fn parse_label_with_fields() {
+        assert_eq!(parse("abc:Retention=7 years, abc:Owner=it@example.com").unwrap(), vec![
+            LabelEntry { label_id: "abc", field: Some(("Retention", "7 years")) },
+            LabelEntry { label_id: "abc", field: Some(("Owner", "it@example.com")) }
+        ]);
+    }
+
+    #[test]
+    fn parse_malformed() {
+        assert!(parse("abc:Retention").is_err());
+        assert!(parse(":Retention=1").is_err());
+    }
+
+    #[test]
+    fn text_length() {
+        let field: Field = serde_json::from_str(r#"{"id": "f1", "properties": {"displayName": "Code"}, "textOptions": {"minLength": 2, "maxLength": 4}}"#).unwrap();
+        assert_eq!(vec!["abc".to_string()], field_modification(&field, "abc").unwrap().set_text_values);
+        assert!(field_modification(&field, "a").is_err());
+        assert!(field_modification(&field, "abcde").is_err());
+    }
+}
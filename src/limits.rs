@@ -0,0 +1,108 @@
+//! Module for the limits Google imposes on shared drives. A shared drive holds at most 400,000 files and folders, and folders in it can be nested
+//! at most 100 levels deep. Once a limit is reached Google rejects new files, so GSync warns while there is still room to act
+
+use crate::config::RemoteRoot;
+use crate::Error;
+
+/// The maximum number of files and folders in a shared drive, including the trash
+pub const MAX_ITEMS: i64 = 400_000;
+
+/// The maximum number of levels folders in a shared drive can be nested
+pub const MAX_DEPTH: usize = 100;
+
+/// The percentage of a limit from which GSync warns
+const WARN_PERCENTAGE: i64 = 90;
+
+/// The reasons Google gives for rejecting a file because a limit was reached, with an explanation for the user
+const LIMIT_REASONS: [(&str, &str); 3] = [
+    ("teamDriveFileLimitExceeded", "The shared drive holds the maximum of 400,000 files and folders. Split the inputs across several shared drives, or pack small files into archives before syncing them"),
+    ("teamDriveHierarchyTooDeep", "Folders in a shared drive can be nested at most 100 levels deep. Exclude the deepest folders, or sync the input into a remote root closer to the top of the drive"),
+    ("numChildrenInNonRootLimitExceeded", "A folder in Google Drive holds at most 500,000 files and folders. Split the folder up, or pack its files into archives before syncing them")
+];
+
+/// Struct describing how much of a shared drive's limits the synced files take up
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct DriveUsage {
+    /// The number of synced files and folders in the drive
+    pub items:  i64,
+
+    /// The deepest nesting of synced folders in the drive, counted from the root of the drive
+    pub depth:  usize
+}
+
+impl DriveUsage {
+    /// Add an input synced to the drive
+    ///
+    /// ## Params
+    /// - `remote_root` The folder the input is synced into
+    /// - `items` The number of files and folders in the input
+    /// - `depth` The deepest nesting of folders in the input, including the input's own folder
+    pub fn add(&mut self, remote_root: &RemoteRoot, items: i64, depth: usize) {
+        self.items += items;
+        self.depth = self.depth.max(remote_root_depth(remote_root) + depth);
+    }
+
+    /// Get warnings for the limits the synced files come close to. Only files synced by GSync are counted,
+    /// other files in the drive take up room as well
+    ///
+    /// ## Params
+    /// - `drive_id` The ID of the shared drive
+    pub fn warnings(&self, drive_id: &str) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if self.items * 100 >= MAX_ITEMS * WARN_PERCENTAGE {
+            warnings.push(format!("The shared drive '{}' will hold {} synced files and folders, close to the limit of {}. Once it is reached Google rejects new files. Consider splitting the inputs across several shared drives with `gsync input --drive`, or packing small files into archives before syncing them", drive_id, self.items, MAX_ITEMS));
+        }
+
+        if self.depth as i64 * 100 >= MAX_DEPTH as i64 * WARN_PERCENTAGE {
+            warnings.push(format!("Folders in the shared drive '{}' will be nested {} levels deep, close to the limit of {}. Consider excluding the deepest folders, or syncing into a remote root closer to the top of the drive", drive_id, self.depth, MAX_DEPTH));
+        }
+
+        warnings
+    }
+}
+
+/// The number of folders between the root of a drive and the files synced into a remote root. Folders given by their ID are assumed to be at the top
+fn remote_root_depth(remote_root: &RemoteRoot) -> usize {
+    match remote_root {
+        RemoteRoot::Path(path) => path.split('/').filter(|part| !part.is_empty()).count(),
+        RemoteRoot::Default | RemoteRoot::Id(_) => 1
+    }
+}
+
+/// Get an explanation if an error means Google rejected a file because a limit was reached
+pub fn exceeded(error: &Error) -> Option<&'static str> {
+    let e = match error {
+        Error::GoogleError(e) => e,
+        _ => return None
+    };
+
+    LIMIT_REASONS.iter()
+        .find(|(reason, _)| e.errors.iter().any(|data| data.reason.eq(reason)))
+        .map(|(_, explanation)| *explanation)
+}
+
+/// Replace an error by a clearer one if it means Google rejected a file because a limit was reached
+pub fn explain(error: (Error, u32, &'static str)) -> (Error, u32, &'static str) {
+    match exceeded(&error.0) {
+        Some(explanation) => (Error::Other(format!("{}: {:?}", explanation, error.0)), error.1, error.2),
+        None => error
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::config::RemoteRoot;
+    use crate::limits::DriveUsage;
+
+    #[test]
+    fn warns_close_to_limits() {
+        let mut usage = DriveUsage::default();
+        usage.add(&RemoteRoot::Default, 1000, 5);
+        assert_eq!(6, usage.depth);
+        assert!(usage.warnings("drive").is_empty());
+
+        usage.add(&RemoteRoot::Path("Backups/laptop".to_string()), 359_000, 88);
+        assert_eq!(90, usage.depth);
+        assert_eq!(2, usage.warnings("drive").len());
+    }
+}
@@ -0,0 +1,91 @@
+//! Module for deciding how symbolic links in the inputs are synced
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// How GSync syncs symbolic links
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymlinkPolicy {
+    /// Sync the file or folder a link points to as if it was in the place of the link
+    Follow,
+
+    /// Sync the link as a shortcut in Drive to the synced copy of its target, which is restored as a link again
+    Shortcut,
+
+    /// Don't sync links at all
+    Skip
+}
+
+/// The policy used when none is configured
+pub const DEFAULT_POLICY: SymlinkPolicy = SymlinkPolicy::Follow;
+
+/// The names of the policies, as accepted on the command line
+pub const POLICY_NAMES: [&str; 3] = ["follow", "shortcut", "skip"];
+
+impl SymlinkPolicy {
+    /// Parse a policy from its name
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "follow" => Some(Self::Follow),
+            "shortcut" => Some(Self::Shortcut),
+            "skip" => Some(Self::Skip),
+            _ => None
+        }
+    }
+
+    /// The policy of an input: its own if it has one, the configured one otherwise, and the default if neither is set
+    ///
+    /// ## Params
+    /// - `input` The policy of the input, if set
+    /// - `global` The configured policy, if set
+    pub fn resolve(input: Option<&str>, global: Option<&str>) -> Self {
+        input.and_then(Self::parse)
+            .or_else(|| global.and_then(Self::parse))
+            .unwrap_or(DEFAULT_POLICY)
+    }
+}
+
+impl fmt::Display for SymlinkPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Follow => "follow",
+            Self::Shortcut => "shortcut",
+            Self::Skip => "skip"
+        };
+
+        f.pad(name)
+    }
+}
+
+/// The path a link points to. A relative target is relative to the folder the link is in
+///
+/// ## Params
+/// - `link` The path of the link
+/// - `target` The target of the link, as read from the link
+pub fn target_path(link: &Path, target: &Path) -> PathBuf {
+    match link.parent() {
+        Some(parent) => parent.join(target),
+        None => target.to_path_buf()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::links::{target_path, SymlinkPolicy};
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn parse_policy() {
+        assert_eq!(SymlinkPolicy::Shortcut, SymlinkPolicy::resolve(None, Some("shortcut")));
+        assert_eq!(SymlinkPolicy::Skip, SymlinkPolicy::resolve(None, Some("skip")));
+        assert_eq!(SymlinkPolicy::Follow, SymlinkPolicy::resolve(None, Some("unknown")));
+        assert_eq!(SymlinkPolicy::Follow, SymlinkPolicy::resolve(None, None));
+        assert_eq!(SymlinkPolicy::Skip, SymlinkPolicy::resolve(Some("skip"), Some("shortcut")));
+    }
+
+    #[test]
+    fn relative_targets() {
+        assert_eq!(PathBuf::from("/in/docs/../a.txt"), target_path(Path::new("/in/docs/link"), Path::new("../a.txt")));
+        assert_eq!(PathBuf::from("/etc/hosts"), target_path(Path::new("/in/link"), Path::new("/etc/hosts")));
+    }
+}
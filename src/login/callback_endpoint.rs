@@ -1,6 +1,7 @@
 //! Actix web endpoint for authorization callback
 
 use actix_web::{get, web, HttpResponse, HttpRequest};
+use actix_web::dev::HttpResponseBuilder;
 use crate::login::ActixData;
 use serde::Deserialize;
 
@@ -21,28 +22,83 @@ struct Query {
 pub async fn authorization(data: web::Data<ActixData>, req: HttpRequest) -> HttpResponse {
     let query: Query = match serde_qs::from_str(req.query_string()) {
         Ok(q) => q,
-        Err(e) => return HttpResponse::BadRequest().body(e.to_string())
+        Err(e) => return failure_page(&format!("The request from Google could not be read: {}", e))
     };
 
+    if data.state.ne(&query.state) {
+        // This request did not originate from the login we started, so we ignore it and keep waiting
+        eprintln!("Error: Received a login callback with a state parameter which does not match.");
+        return failure_page("The state parameter does not match. Please use the URL printed by GSync to log in.");
+    }
+
     if let Some(e) = query.error {
-        return HttpResponse::BadRequest().body(e);
+        let _ = data.tx.send(Err(e.clone()));
+        return failure_page(&format!("Google returned an error: {}", e));
     }
 
     let code = match query.code {
         Some(code) => code,
-        None => unreachable!()
+        None => return failure_page("Google did not return an authorization code.")
     };
 
-    if data.state.ne(&query.state) {
-        eprintln!("State does noet match!");
-        std::process::exit(1);
-    }
-
-    match &data.tx.send(code) {
-        Ok(_) => HttpResponse::Ok().body("You can now close this tab."),
+    match &data.tx.send(Ok(code)) {
+        Ok(_) => page(HttpResponse::Ok(), "Login successful", "You are now logged in to GSync. You can close this tab.", true),
         Err(e) => {
-            eprintln!("Error: Failed to send received code over channel: {:?}", e);
-            HttpResponse::InternalServerError().finish()
+            eprintln!("Error: Failed to send received code over channel: {}", crate::redact::redact(format!("{:?}", e)));
+            failure_page("GSync is no longer waiting for this login. Please try again.")
         }
     }
-}
\ No newline at end of file
+}
+
+/// Create a page telling the user the login failed
+fn failure_page(message: &str) -> HttpResponse {
+    page(HttpResponse::BadRequest(), "Login failed", &format!("{} You can close this tab and return to GSync.", message), false)
+}
+
+/// Create an HTML page to show the user after the login
+///
+/// ## Params
+/// - `builder` The response builder with the status code to use
+/// - `title` The title of the page
+/// - `message` The message to show to the user, this is escaped
+/// - `auto_close` Whether the page should attempt to close itself
+fn page(mut builder: HttpResponseBuilder, title: &str, message: &str, auto_close: bool) -> HttpResponse {
+    let script = if auto_close {
+        "<script>setTimeout(function() { window.close(); }, 3000);</script>"
+    } else {
+        ""
+    };
+
+    builder.content_type("text/html; charset=utf-8").body(format!(r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>GSync - {title}</title>
+<style>body {{ font-family: sans-serif; max-width: 40em; margin: 4em auto; text-align: center; }}</style>
+</head>
+<body>
+<h1>{title}</h1>
+<p>{message}</p>
+{script}
+</body>
+</html>"#, title = escape_html(title), message = escape_html(message), script = script))
+}
+
+/// Escape a string for use in HTML
+pub(crate) fn escape_html(input: &str) -> String {
+    input.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+#[cfg(test)]
+mod test {
+    use crate::login::callback_endpoint::escape_html;
+
+    #[test]
+    fn escape_html_special_characters() {
+        assert_eq!(escape_html("<b>\"Tom\" & 'Jerry'</b>"), "&lt;b&gt;&quot;Tom&quot; &amp; &#39;Jerry&#39;&lt;/b&gt;");
+    }
+}
@@ -17,9 +17,9 @@ pub fn save_to_database(login_data: &LoginData, env: &Env) -> Result<()> {
     }
 
     let expiry_time = chrono::Utc::now().timestamp() + login_data.expires_in;
-    unwrap_db_err!(if login_data.refresh_token.is_some() {
+    unwrap_db_err!(if let Some(refresh_token) = &login_data.refresh_token {
             conn.execute("INSERT INTO user (refresh_token, access_token, expiry) VALUES (:refresh_token, :access_token, :expiry)", named_params! {
-                ":refresh_token": &login_data.refresh_token.as_ref().unwrap(),
+                ":refresh_token": refresh_token,
                 ":access_token": &login_data.access_token,
                 ":expiry": expiry_time
             })
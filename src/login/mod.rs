@@ -1,16 +1,26 @@
 //! Module with everything related to the OAuth2 login flow
 
 mod port;
-mod callback_endpoint;
+pub(crate) mod callback_endpoint;
 pub mod db;
 
 use crate::env::Env;
 use actix_web::{HttpServer, App};
 use rand::Rng;
-use std::sync::mpsc::{Sender, channel};
+use std::sync::mpsc::{Sender, RecvTimeoutError, channel};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use std::io::Write;
 use crate::api::oauth::LoginData;
 
-use crate::{Result, unwrap_other_err};
+use crate::{Result, Error, unwrap_other_err};
+
+/// The default time the user gets to complete the login in the browser
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// How often to check if the login was cancelled or timed out while waiting for the callback
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
 
 /// Struct describing the data to be passed to Actix endpoints
 #[derive(Clone, Debug)]
@@ -18,12 +28,123 @@ pub struct ActixData {
     /// The state parameter. Refer to the Google OAuth2 docs for why this is used
     state:          String,
 
-    /// THe channel on which the endpoint can send the received code
-    tx:             Sender<String>
+    /// THe channel on which the endpoint can send the received code, or the error returned by Google
+    tx:             Sender<std::result::Result<String, String>>
+}
+
+/// The result of a single login attempt
+enum LoginAttempt {
+    /// The user completed the login
+    Success(LoginData),
+
+    /// The user did not complete the login within the timeout
+    TimedOut,
+
+    /// The user pressed Ctrl-C
+    Cancelled
+}
+
+/// Perform the OAuth2 login flow. If the user does not complete the login within the timeout, they are asked whether they want to try again
+///
+/// ## Params
+/// - `env` Env instance
+/// - `timeout` The time the user gets to complete the login in the browser
+///
+/// ## Errors
+/// - When the login is cancelled with Ctrl-C
+/// - When the login timed out and the user does not want to try again
+/// - When exchanging the code for tokens fails
+pub fn perform_oauth2_login(env: &Env, timeout: Duration) -> Result<LoginData> {
+    let cancelled = Arc::new(AtomicBool::new(false));
+    // Listens for Ctrl-C until the login is over
+    let _listener = CtrlCListener::start(cancelled.clone())?;
+
+    loop {
+        match attempt_login(env, timeout, &cancelled)? {
+            LoginAttempt::Success(login_data) => return Ok(login_data),
+            LoginAttempt::Cancelled => return Err((Error::Other("Login cancelled".to_string()), line!(), file!())),
+            LoginAttempt::TimedOut => {
+                let retry = prompt_retry(timeout)?;
+                if cancelled.load(Ordering::SeqCst) {
+                    return Err((Error::Other("Login cancelled".to_string()), line!(), file!()));
+                }
+
+                if !retry {
+                    return Err((Error::Other("Login timed out".to_string()), line!(), file!()));
+                }
+            }
+        }
+    }
+}
+
+/// Listens for Ctrl-C on a separate thread, setting the cancelled flag when it is pressed. Listening stops when this is dropped
+struct CtrlCListener {
+    /// Tells the listening thread to stop
+    stop:   Option<tokio::sync::oneshot::Sender<()>>,
+
+    /// The listening thread
+    thread: Option<std::thread::JoinHandle<()>>
+}
+
+impl CtrlCListener {
+    /// Start listening for Ctrl-C
+    ///
+    /// ## Errors
+    /// - When the runtime to listen on cannot be created
+    fn start(cancelled: Arc<AtomicBool>) -> Result<Self> {
+        let runtime = unwrap_other_err!(tokio::runtime::Builder::new_current_thread().enable_all().build());
+        let (stop, mut stopped) = tokio::sync::oneshot::channel::<()>();
+
+        let thread = std::thread::spawn(move || runtime.block_on(async move {
+            loop {
+                tokio::select! {
+                    _ = &mut stopped => break,
+                    result = tokio::signal::ctrl_c() => {
+                        if let Err(e) = result {
+                            eprintln!("Warning: Unable to listen for Ctrl-C, the login cannot be cancelled: {}", e);
+                            break;
+                        }
+
+                        // A second Ctrl-C, e.g. while exchanging tokens, exits immediately
+                        if cancelled.swap(true, Ordering::SeqCst) {
+                            std::process::exit(130);
+                        }
+                    }
+                }
+            }
+        }));
+
+        Ok(Self { stop: Some(stop), thread: Some(thread) })
+    }
+}
+
+impl Drop for CtrlCListener {
+    fn drop(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Ask the user whether they want to try logging in again
+///
+/// ## Errors
+/// - When reading from stdin fails
+fn prompt_retry(timeout: Duration) -> Result<bool> {
+    print!("Warning: The login was not completed within {} seconds. Try again? [y/N] ", timeout.as_secs());
+    unwrap_other_err!(std::io::stdout().flush());
+
+    let mut answer = String::new();
+    unwrap_other_err!(std::io::stdin().read_line(&mut answer));
+    Ok(answer.trim().eq_ignore_ascii_case("y") || answer.trim().eq_ignore_ascii_case("yes"))
 }
 
-/// Perform the OAuth2 login flow
-pub fn perform_oauth2_login(env: &Env) -> Result<LoginData> {
+/// Perform a single attempt of the OAuth2 login flow. The callback server is always stopped before returning
+fn attempt_login(env: &Env, timeout: Duration, cancelled: &AtomicBool) -> Result<LoginAttempt> {
     //Generate a code_verifier and code_challenge
     let (code_verifier, code_challenge) = generate_code();
     //Generate a state parameter
@@ -46,46 +167,79 @@ pub fn perform_oauth2_login(env: &Env) -> Result<LoginData> {
     //This channel will be used to receive the Serve instance from Actix
     let (tx_srv, rx_srv) = channel();
 
-    //Start the actix web server and wait for it to return us the Server instance
+    //Start the actix web server and wait for it to return us the Server instance, or the error if it failed to start
     std::thread::spawn(move || {
-        match start_actix(actix_data, port, tx_srv) {
-            Ok(_) => {},
-            Err(e) => {
-                eprintln!("Error: Failed to start Actix Web Server: {:?}", e);
-                std::process::exit(1);
-            }
+        if let Err((e, _, _)) = start_actix(actix_data, port, &tx_srv) {
+            let _ = tx_srv.send(Err(format!("{:?}", e)));
         }
     });
-    let server = unwrap_other_err!(rx_srv.recv());
+    let server = match unwrap_other_err!(rx_srv.recv()) {
+        Ok(server) => server,
+        Err(e) => return Err((Error::Other(format!("Failed to start the callback server: {}", e)), line!(), file!()))
+    };
 
-    let auth_uri = crate::api::oauth::create_authentication_uri(&env, &code_challenge, &state, &format!("http://localhost:{}", port));
+    let auth_uri = crate::api::oauth::create_authentication_uri(env, &code_challenge, &state, &format!("http://127.0.0.1:{}", port));
 
     println!("Info: Please open the following URL:");
     println!("\n{}\n", auth_uri);
 
-    //Wait for the code from the HTTP endpoint
-    let code = unwrap_other_err!(rx_code.recv());
+    //Wait for the code from the HTTP endpoint, until the user cancels or the timeout passes
+    let deadline = Instant::now() + timeout;
+    let code = loop {
+        if cancelled.load(Ordering::SeqCst) {
+            println!("Info: Login cancelled. Stopping the callback server.");
+            stop_actix(&server);
+            return Ok(LoginAttempt::Cancelled);
+        }
+
+        if Instant::now() >= deadline {
+            stop_actix(&server);
+            return Ok(LoginAttempt::TimedOut);
+        }
+
+        match rx_code.recv_timeout(POLL_INTERVAL) {
+            Ok(Ok(code)) => break code,
+            Ok(Err(e)) => {
+                stop_actix(&server);
+                return Err((Error::Other(format!("Google returned an error: {}", e)), line!(), file!()));
+            },
+            Err(RecvTimeoutError::Timeout) => {},
+            Err(e) => {
+                stop_actix(&server);
+                return Err((Error::Other(e.to_string()), line!(), file!()));
+            }
+        }
+    };
 
     println!("Info: Code received. Exchanging for tokens.");
 
     //Stop the Actix web server, we dont need it anymore
-    actix_web::rt::System::new("").block_on(server.stop(true));
+    stop_actix(&server);
 
-    crate::api::oauth::exchange_access_token(&env, &code, &code_verifier, &format!("http://localhost:{}", port))
+    let login_data = crate::api::oauth::exchange_access_token(env, &code, &code_verifier, &format!("http://127.0.0.1:{}", port))?;
+    Ok(LoginAttempt::Success(login_data))
+}
+
+/// Stop the Actix Web Server, freeing up the port it listens on
+fn stop_actix(server: &actix_server::Server) {
+    actix_web::rt::System::new("").block_on(server.stop(true));
 }
 
 /// Start the Actix Web Server.
 /// This is a blocking method call
 /// An instance of Actix's Server will be send over the provided channel so it can be stopped later
-fn start_actix(data: ActixData, port: u16, tx: Sender<actix_server::Server>) -> Result<()> {
+///
+/// ## Errors
+/// - When binding to the port fails. Nothing is sent over the channel in this case
+fn start_actix(data: ActixData, port: u16, tx: &Sender<std::result::Result<actix_server::Server, String>>) -> Result<()> {
     let mut sys = actix_web::rt::System::new("GSync");
     let actix = unwrap_other_err!(HttpServer::new(move || {
         App::new()
             .data(data.clone())
             .service(callback_endpoint::authorization)
-    }).bind(format!("0.0.0.0:{}", port))).run();
+    }).bind(format!("127.0.0.1:{}", port))).run();
 
-    let _ = tx.send(actix.clone());
+    let _ = tx.send(Ok(actix.clone()));
     let _ = sys.block_on(actix);
 
     Ok(())
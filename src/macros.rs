@@ -50,11 +50,12 @@ macro_rules! handle_err {
         match $expression {
             Ok(t) => t,
             Err((e, line, file)) => {
+                // Errors can contain tokens, e.g. in the URL of a failed request
                 match e {
-                    $crate::Error::DatabaseError(e) => eprintln!("Error: An error occurred while processing or handling database data: {:?} (line {} in {})", e, line, file),
-                    $crate::Error::RequestError(e) => eprintln!("Error: An error occurred while sending a HTTP request: {:?} (line {} in {})", e, line, file),
-                    $crate::Error::GoogleError(e) => eprintln!("Error: The Google API returned an error: {:?} (line {} in {})", e, line, file),
-                    $crate::Error::Other(e) => eprintln!("Error: An error occurred: {:?} (line {} in {})", e, line, file)
+                    $crate::Error::DatabaseError(e) => eprintln!("Error: An error occurred while processing or handling database data: {} (line {} in {})", $crate::redact::redact(format!("{:?}", e)), line, file),
+                    $crate::Error::RequestError(e) => eprintln!("Error: An error occurred while sending a HTTP request: {} (line {} in {})", $crate::redact::redact(format!("{:?}", e)), line, file),
+                    $crate::Error::GoogleError(e) => eprintln!("Error: The Google API returned an error: {} (line {} in {})", $crate::redact::redact(format!("{:?}", e)), line, file),
+                    $crate::Error::Other(e) => eprintln!("Error: An error occurred: {} (line {} in {})", $crate::redact::redact(format!("{:?}", e)), line, file)
                 }
 
                 eprintln!("This is a fatal error. Exiting!");
@@ -99,21 +100,19 @@ macro_rules! handle_err {
 ///     let response: GoogleResponse<Foo> = some_request();
 ///
 ///     // `foo` is of type Foo
-///     let foo = if response.error.is_some() {
-///         return Err(format!("{:?}", foo.error));
-///     } else {
-///         response.data.unwrap()
-///     }
+///     let foo = match response.error {
+///         Some(error) => return Err(format!("{:?}", error)),
+///         None => response.data.unwrap()
+///     };
 ///
 ///     Ok(foo.bar)
 /// }
 #[macro_export]
 macro_rules! unwrap_google_err {
     ($expression:expr) => {
-        if $expression.error.is_some() {
-            return Err(($crate::Error::GoogleError($expression.error.unwrap()), std::line!(), std::file!()));
-        } else {
-            $expression.data.unwrap()
+        match $expression.error {
+            Some(error) => return Err(($crate::Error::GoogleError(error), std::line!(), std::file!())),
+            None => $expression.data.unwrap()
         }
     }
 }
\ No newline at end of file
@@ -11,13 +11,162 @@
 //! 1. Create a project on [Google Deveopers](https://console.developers.google.com)
 //! 2. Configure the OAuth2 consent screen and create OAuth2 credentials
 //! 3. Enable the Google Drive API
-//! 4. If you are planning to use a Team Drive/Shared Drive, run `gsync drives` to get the ID of the drive you want to sync to
+//! 4. If you are planning to use a Team Drive/Shared Drive, run `gsync drives` to get the ID of the drive you want to sync to. It also shows whether you can write to each drive. With many shared drives, `gsync drives --filter <NAME>` only lists those whose name contains `NAME`
 //! 5. Configure GSync: `gsync config -i <GOOGLE APP ID> -s <GOOGLE APP SECRET> -f <INPUT FILES> -d <ID OF SHARED DRIVE>`. The `-d` parameter is optional
 //! 6. Login: `gsync login`
 //! 7. Sync away! `gsync sync`
 //!
 //! To update your configuration later, run `gsync config` again, you don't have to re-provide all options if you don't want to change them
 //!
+//! When you configure inputs, GSync checks that each of them exists, can be read and isn't inside its own `~/.gsync` folder, and refuses to save the configuration otherwise, so a typo doesn't go unnoticed until you need the backup. To configure an input which isn't there yet, e.g. on a drive which is not mounted, add `--skip-input-check`
+//!
+//! To sync an input to a different drive than the others, run `gsync input <INPUT> -d <ID OF SHARED DRIVE>`. Use `root` as ID to sync the input to My Drive
+//!
+//! Inputs may be nested, e.g. `/home/me` and `/home/me/projects`. The files of the inner input are then only synced with the inner input, into its own folder and with its own policies, and not a second time as part of the outer one. GSync warns about nested inputs at the start of every sync
+//!
+//! Files which are written to while GSync reads them, e.g. a mail spool or a database, may be backed up half-written. To back up such an input as it was at a single point in time, run `gsync input <INPUT> --fs-snapshot btrfs`, `zfs` or `lvm`. Every sync then takes a snapshot of the filesystem the input is on, syncs the input from the snapshot and removes the snapshot again afterwards. For btrfs the input must be a subvolume, for LVM the snapshot takes up to 10% of the size of the logical volume. Taking snapshots generally requires running GSync as root
+//!
+//! On Windows, files which other programs keep open, e.g. Outlook's mail files, can't be read while GSync syncs them. `gsync input <INPUT> --fs-snapshot vss` syncs the input from a Volume Shadow Copy of its drive instead, which is removed again after the sync. Creating a shadow copy requires running GSync as administrator
+//!
+//! Inputs on Windows may be given with a drive letter in either case, e.g. `c:\Users\me`, or as a network share, e.g. `\\server\share`. GSync works with them in their extended-length form, e.g. `\\?\C:\Users\me`, so files deeper than the usual 260 character limit, like those in a `node_modules` folder, are synced as well
+//!
+//! A shared drive holds at most 400,000 files and folders, and its folders can be nested at most 100 levels deep. Before syncing, GSync warns when the synced files come within 10% of either limit, so you can split the inputs across drives in time. If Google rejects a file because a limit was reached anyway, the sync stops with an explanation instead of failing on every remaining file
+//!
+//! GSync uploads multiple files at once, tuning the number of concurrent uploads to your connection automatically. To cap it, run `gsync config --max-jobs <JOBS>`, or `gsync sync -j <JOBS>` for a single run
+//!
+//! When Google rate limits a request or is temporarily unavailable, GSync retries it with an exponential backoff, waiting as long as Google asks for if it does, up to 15 minutes. When Google asks to wait longer, e.g. because a quota is exhausted, the request fails right away with the time to try again after. Requests are retried up to 5 times, which can be changed with `gsync config --max-retries <RETRIES>`; `0` disables retrying. Uploads are retried the same way, reading the file again for every attempt
+//!
+//! To keep an overnight backup from saturating your connection, limit the upload bandwidth with `gsync config --bwlimit 2M`. The limit is shared by all concurrent uploads. For a single run, use `gsync sync --bwlimit <RATE>`, where `0` disables the limit
+//!
+//! Google Workspace admins can sync into a user's Drive without logging in as that user, using a service account with domain-wide delegation: `gsync config --service-account <KEY FILE> --impersonate <USER EMAIL>`
+//!
+//! To run GSync as a Docker or Kubernetes backup job, e.g. as a sidecar, use `gsync container`. It needs no seeded home folder: every configuration field can be set as an environment variable, `GSYNC_` followed by the field in upper case, e.g. `GSYNC_INPUT_FILES=/data` or `GSYNC_IMPERSONATE=backup@example.com`, and the others can be read from a mounted TOML file with `--config-file <FILE>` or `GSYNC_CONFIG_FILE`, e.g. `input_files = ["/data", "/etc"]`. Keep the state on a volume with `GSYNC_STATE_DIR` or `gsync --state-dir <DIR>`. Nothing ever waits for input: it syncs once and exits with 0 on success, 2 if some files failed to sync, 3 if the configuration is invalid, 4 if there are no credentials and 5 if the sync failed. The result is written as a line of JSON, to stderr if something went wrong. Authenticate with a service account, or mount a state directory in which `gsync login` was run
+//!
+//! To check who has access to your backups, run `gsync perms`. Access can be revoked with `gsync perms --revoke <PERMISSION ID>`, or `gsync perms --revoke-all`
+//!
+//! To hand your backups over to another account, run `gsync transfer-ownership --to <EMAIL>`. Add `--pending` when the new owner is outside of your Google Workspace domain, they will then have to accept the ownership
+//!
+//! Google Workspace users can have GSync apply Drive labels to the files it creates, e.g. for retention policies: `gsync config --labels <LABEL ID>:<FIELD>=<VALUE>`. Separate multiple labels or fields with commas. GSync only asks for permission to read labels once they are configured, so log in again with `gsync login` after configuring them
+//!
+//! After every sync, GSync prints a report of what it did. To keep a record of your backups' health in Drive itself, e.g. for machines nobody logs into, run `gsync config --upload-reports true`. The reports are then uploaded to the `_reports` folder in the GSync folder
+//!
+//! To get a listing of everything GSync has backed up, e.g. for auditing, run `gsync export-manifest --format csv` or `gsync export-manifest --format json`. Use `-o <FILE>` to write it to a file
+//!
+//! GSync keeps its state in a local database. To remove stale state, e.g. of inputs you no longer sync, run `gsync gc`. Runs are kept in the run history for 90 days, this can be changed with `gsync config --history-retention <DAYS>`. To see what GSync is tracking, run `gsync db stats`
+//!
+//! Before making any changes, GSync shows what the sync will do, e.g. `12 new files, 3 updated, 2 deletions, 1.4 GiB to upload`. Files and folders which were removed locally are moved to the trash in Drive, where they can be recovered for 30 days. This deletion policy can be changed with `gsync config --delete-policy <POLICY>`: `mirror` deletes them permanently, so Drive mirrors your inputs, and `archive` never deletes anything, it marks the copies in Drive stale with the private `gsync_stale` property and stops tracking them. `gsync sync --purge` deletes permanently for a single run. To review this plan before anything is changed, run `gsync sync --confirm`
+//!
+//! To protect your backup against e.g. an unmounted external drive, GSync refuses to sync when more than half of the tracked files would be deleted. This limit can be changed with `gsync config --delete-limit <LIMIT>`, either as a number of files or as a percentage like `10%`. To proceed anyway, run `gsync sync --allow-mass-delete`
+//!
+//! A single odd file won't take down an unattended backup: files and folders which can't be read or fail to upload are skipped and listed in the report at the end of the sync, files and folders which can't be read under `Skipped (local I/O)`, and an input which can't be found is skipped entirely. Skipped files are never deleted from Drive. To abort on the first such problem instead, run `gsync sync --strict`
+//!
+//! A file or folder whose name isn't valid UTF-8 is synced with the invalid bytes replaced by `U+FFFD` (`�`), and is restored under that name. Only when the replaced name is the same as another name in its folder is it skipped and listed in the report
+//!
+//! To see what the next sync would do without transferring anything, run `gsync status`. Add `--check` to exit with code 1 when anything is out of sync, e.g. to monitor that a machine's backup is current
+//!
+//! Wrapper tools can get the plan as JSON, with the action, path, size and reason of every change, by running `gsync sync --dry-run --json` or `gsync status --json`
+//!
+//! To sync only some files, e.g. the ones a script or `git` knows have changed, pass a list of paths with `--files-from <FILE>`, one per line, or `--files-from -` to read them from stdin: `git diff --name-only | gsync sync --files-from -`. Only the listed files and folders are synced, into the same place in Drive as a full sync would put them, and unchanged files are still skipped. Listed folders are synced without their contents, paths outside of the inputs are skipped, and nothing is deleted from Drive
+//!
+//! To keep Drive up to date while you work, run `gsync watch`. It syncs the inputs once, then watches them and syncs files as they are saved, using the same records as `gsync sync`, so nothing is uploaded twice. Changes are collected until the inputs have been quiet for 2 seconds, or as long as `--debounce <SECONDS>` says, so a burst of writes results in a single upload. Removing, renaming or creating a folder results in a full sync, which also deletes what was removed from Drive
+//!
+//! Running `gsync config` again only changes the values you pass. To clear a value, e.g. to sync to My Drive again after configuring a shared drive, run `gsync config --unset drive_id`. Its default is then used again. `gsync config -h` lists the fields
+//!
+//! To sync into an existing folder instead of a folder named `GSync`, e.g. a folder a team shared with you, run `gsync config --target-folder <FOLDER ID>`. GSync checks that you can add files to the folder before every sync. All inputs are then synced into that folder, per-input drives are not used
+//!
+//! Rather not copy IDs from URLs? `gsync config --pick-folder` lists My Drive and your shared drives, and lets you navigate through their folders and select the one to sync into. The chosen folder and its drive are written into the configuration. GSync needs to be logged in for this, so run `gsync login` first
+//!
+//! To sync a single input into a folder of its own, run `gsync input <INPUT> --remote-root <PATH>`, e.g. `--remote-root Backups/Docs`. The path is relative to the root of the drive, missing folders are created. To sync it into an existing folder, e.g. one shared with you, run `gsync input <INPUT> --remote-root-id <FOLDER ID>`
+//!
+//! The remote root can contain placeholders, which are expanded every time GSync syncs: `{hostname}` is the name of the machine, `{date}` the date the sync started as `YYYY-MM-DD`, `{profile}` the user GSync runs as and `{input}` the name of the input. For example, `--remote-root 'Backups/{hostname}/{date}'` gives every machine its own folder, with a fresh copy of the input every day
+//!
+//! Already have a copy of an input in Drive, e.g. uploaded by hand or by another tool? `gsync adopt <INPUT> <FOLDER ID>` lets GSync take it over without uploading everything again. Files in the folder matching the local files by name, size and checksum are tracked as if GSync had synced them, and the input is synced into the folder the adopted folder is in from then on. Files which differ or are missing are listed, and are synced on the next sync
+//!
+//! Inputs can also have their own policies, since a folder of photos and a folder of code rarely need the same ones. `gsync input <INPUT> --excludes '*.tmp,build/*.o'` skips files and folders matching one of the patterns; a pattern without a `/` matches names anywhere in the input, a pattern with a `/` matches paths relative to the input. `--gitignore false` stops respecting `.gitignore` files in the input, and `--max-size 500M` skips files larger than that
+//!
+//! To skip large files, e.g. VM images, in every input, run `gsync config --max-size 2G`, or `gsync sync --max-size <SIZE>` for a single run. A maximum size set for an input or folder takes precedence. Files which are skipped because of their size are listed by `gsync status` and in the report of the sync
+//!
+//! `.gitignore` files are respected the way git does: globs and `**` match at any depth, a pattern ending in `/` only matches folders, a pattern with a `/` is relative to the folder of the `.gitignore` file, and `!pattern` re-includes what an earlier pattern ignores. A `.gitignore` file applies to its folder and everything in it, and the innermost file matching a path decides. At the root of a git repository the patterns in `.git/info/exclude` are respected as well
+//!
+//! To keep files out of the backup which git doesn't ignore, e.g. `node_modules` in a project that commits it, drop a `.gsyncignore` file in any synced folder. It has the same syntax as `.gitignore` and applies to its folder and everything in it, also when `.gitignore` files are not respected. Within a folder the patterns of `.gsyncignore` take precedence over those of `.gitignore`, so `!pattern` in a `.gsyncignore` file backs up a file git ignores. Across folders the innermost matching file decides, as with `.gitignore`
+//!
+//! Patterns can also be managed with `gsync exclude add <PATTERN>`, which excludes what matches from all inputs, or from a single input with `--input <INPUT>`. The tracked files and folders the new pattern excludes are listed right away, since their copies are deleted from Drive on the next sync. `gsync exclude remove <PATTERN>` syncs them again, and `gsync exclude list` shows the patterns globally and per input
+//!
+//! Policies can also live next to the data they govern. A `.gsync.toml` file in any synced folder overrides the policies of its input for that folder and everything in it, e.g. `excludes = ["target", "docs/*.pdf"]` to skip more files and folders, with patterns containing a `/` relative to that folder, `max-size = "100M"` to change the maximum file size, where 0 lifts it, and `gitignore = false` to stop respecting `.gitignore` files. Other keys are ignored with a warning. A folder whose `.gsync.toml` can't be read is skipped, so it isn't synced with the wrong policies
+//!
+//! By default a file is synced again when its modification time is newer than its copy in Drive. Tools that restore old timestamps, e.g. `rsync -t` or `git checkout`, can hide changes that way. `gsync config --compare size-mtime` also syncs files whose size changed, and `--compare hash` compares MD5 checksums with the ones Drive reports, which is slower since every file is read. `gsync input <INPUT> --compare hash` sets the policy for one input, `--compare default` makes it follow the global policy again
+//!
+//! Modification times within 2 seconds of each other are considered the same, since FAT and exFAT drives store them in steps of 2 seconds. For a network filesystem whose clock is off, raise this with `gsync config --mtime-tolerance <SECONDS>`. When a file's modification time moves backwards, e.g. because an older version was restored, its size and checksum are compared with its copy in Drive instead, so the change isn't missed
+//!
+//! GSync stores a checksum of every file it syncs. It uses BLAKE3 by default, which is fast enough to not slow down syncing. `gsync config --hash md5` uses the algorithm Google Drive reports, `--hash sha256` a standardized one for when compliance requires it. The algorithm is stored with every checksum, so checksums stored before changing it stay valid and are compared using the algorithm they were computed with
+//!
+//! Uploads are given the MIME type Google Drive expects for their extension. If that guess is wrong, or a file type is unknown, configure the type yourself, e.g. `gsync config --mime-types md=text/markdown,log=text/plain`. To edit backed up office files in Google Drive, run `gsync config --convert true`: Word, Excel and PowerPoint files (and their OpenDocument counterparts) are then uploaded as Google Docs, Sheets and Slides. Converted files are restored as exports, choose the format with `gsync restore --export-format`
+//!
+//! Google Drive has no notion of holes in a file, so a sparse file, e.g. a VM image, is uploaded with its holes as zeroes, and `gsync restore` turns blocks of zeroes back into holes. On Linux, `gsync config --pack-sparse true` uploads sparse files with at least 1 MiB of holes packed instead, which saves the upload and the storage of the holes. A packed file starts with a header: the text `GSYNC-SPARSE-V1`, followed by the length of the file, the number of ranges holding data, and the offset and length of every range, as little endian 64 bit integers. Only the data of those ranges follows. This means the copy in Drive is no longer your file as is: previewing or downloading it from the Drive website, or with any other application, gives you the packed content. GSync unpacks it again when restoring, serving or mounting your backup
+//!
+//! Symbolic links are followed by default, so what a link points to is synced as if it was in the place of the link. To keep the structure of your links instead, run `gsync config --symlinks shortcut`: a link is then synced as a shortcut in Drive to the synced copy of its target, and `gsync restore` recreates it as a link pointing where it did before. Links whose target is not synced, e.g. because it is outside of your inputs, are skipped and listed in the report
+//!
+//! `gsync config --symlinks skip` doesn't sync links at all. `gsync input <INPUT> --symlinks <POLICY>` sets the policy for one input, `--symlinks default` makes it follow the global policy again, and `gsync sync --links <POLICY>` uses a policy for a single run. When following links, a link to a folder it is in, which would make the sync loop forever, is skipped with a warning
+//!
+//! Sockets, named pipes, devices and broken links can't be synced. They are skipped, and listed under `Skipped` in the report of the sync
+//!
+//! To stop syncing a file or folder without deleting it from Drive, run `gsync forget <PATH>`. GSync stops tracking it and excludes it from its input, or removes it from the inputs if it is one, while its copy in Drive stays where it is. Removing something from your inputs or excludes yourself deletes its copy from Drive on the next sync instead
+//!
+//! Large curated exclude lists can live in a file of their own, e.g. one shared across machines. `gsync sync --exclude-from <FILE>` skips the files and folders matching the patterns in it, one per line in gitignore syntax, in addition to the excludes of each input. Comments and empty lines are ignored, negated patterns are not supported. Repeat the option to use several files
+//!
+//! To back up only some kinds of files from a large mixed folder, filter by extension. `gsync sync --include-ext jpg,raw` only syncs files with one of those extensions, and `--exclude-ext log,tmp` skips files with one of those. Extensions are compared ignoring case. Like excluded files, files filtered out this way are removed from Drive following the deletion policy if they were synced before
+//!
+//! On a metered connection or in a tight maintenance window, give the sync a budget. `gsync sync --max-transfer 5G` stops uploading once 5 GiB has been uploaded, `--max-duration 2h` stops starting new work after two hours, finishing the transfers in progress. The work the run didn't get to is journaled in the database and listed in the run report, and the next run picks up where it left off. Deleting files removed locally is deferred as well
+//!
+//! GSync never syncs its own database, nor its `.gsync` folder, even when they are inside one of your inputs. If the state directory is set to a folder you sync, e.g. your home folder, only the database and the files SQLite keeps next to it are left out
+//!
+//! Losing your machine shouldn't also lose what GSync knows about your backup. Write a passphrase to a file and run `gsync config --state-passphrase-file <FILE>`, and GSync backs up its own database once a day after a sync, encrypted with the passphrase, to a hidden folder in your Google Drive only GSync can access. On a new machine, configure GSync with the same client ID and secret, run `gsync login`, and then `gsync state restore --passphrase-file <FILE>`, or set the `GSYNC_STATE_PASSPHRASE` environment variable. Passphrases aren't accepted on the command line, where other users could see them in the process list. `gsync state backup` makes a backup right away. If you logged in before this was added, run `gsync login` again so GSync may access the hidden folder. The configured passphrase is stored in plain text in GSync's database on this machine, set the `GSYNC_STATE_PASSPHRASE` environment variable instead to keep it out of there
+//!
+//! To see the shape of what is stored in Drive, run `gsync tree`. Use `--depth <DEPTH>` to show more or fewer folder levels
+//!
+//! To look at a synced file in the Drive web interface, run `gsync open <PATH>`. Add `--print` to only print the URL
+//!
+//! To browse your backup without restoring it, GSync can mount it read-only with `gsync mount <MOUNTPOINT>`. Files are downloaded when they are read. This requires FUSE, and GSync built with the `fuse` feature: `cargo install gsync --features fuse`
+//!
+//! Running a large initial backup interactively? `gsync sync --tui` follows the sync in a full-screen terminal UI, with the progress of scanning the inputs, a progress bar per file being uploaded, a pane with warnings and errors, and the overall throughput. Press Ctrl+C to abort the sync, and any key to exit once it is done, after which the run report is printed. The TUI is optional, build GSync with the `tui` feature to use it: `cargo install gsync --features tui`
+//!
+//! Want to know how long a big sync will take without the full TUI? `gsync sync --progress` shows a progress bar over the whole sync, with the number of files handled, the bytes uploaded, the speed and the ETA, and a bar per file being uploaded. The bars are drawn on stderr, so redirect the log to keep them readable: `gsync sync --progress > sync.log`
+//!
+//! On machines where FUSE isn't available, run `gsync serve` and open http://127.0.0.1:8080 to browse your backup and download individual files
+//!
+//! To keep your backup up-to-date without a scheduler, run `gsync daemon`. It syncs every hour, this can be changed with `--interval <MINUTES>`. Add `--dashboard <PORT>` to see the last runs, the progress of the current sync and recent errors in your browser
+//!
+//! To trigger backups and collect their results from scripts or home automation, add `--api <PORT>` and a token with `--api-token-file <FILE>` or `GSYNC_API_TOKEN` to `gsync daemon`. Send the token as `Authorization: Bearer <TOKEN>` to `POST /sync` to start a sync, `GET /status` for the progress of the current sync and the schedule, and `GET /runs?limit=<N>` for the last runs. The API only listens on 127.0.0.1 unless `--api-bind` is set, e.g. to `0.0.0.0`
+//!
+//! On shared servers, a single daemon running as root can back up several users with `gsync daemon --user alice --user bob`. Every user runs `gsync config` and `gsync login` themselves, so their configuration, state database and Drive credentials stay in their own home folder. The sync, the scrub and reading the run history for each user all run in a child process with that user's privileges, so only files they can read are backed up and the daemon never opens their state database itself
+//!
+//! By default the users are synced one after another. `--parallel <COUNT>` syncs up to that many users at the same time, while users given with `--heavy <NAME>` are never synced at the same time as each other. `--jitter <MINUTES>` waits a random time before every scheduled sync, and before the sync of every user, so a fleet of machines, or many users on one machine, don't all start syncing at the same moment. How many uploads run at the same time for each user is set by their own `gsync config --max-jobs`
+//!
+//! GSync stores your client secret and Google tokens in its database in `~/.gsync`, which only your user can read. They are masked in everything GSync prints, in `gsync show` and in run reports
+//!
+//! Every change GSync makes in Google Drive is recorded in an audit log in its database, with the time, the run that made it, the path, the file ID and the result. Run `gsync audit` to see the most recent operations. To also append them to a file, run `gsync config --audit-log <FILE>`
+//!
+//! Changes in the GSync folders in Google Drive which GSync didn't make itself, e.g. in the web UI, on another machine or by another app, are shown by `gsync audit --remote`, with who made them. Use `--since DAYS` to look further back than a week. `gsync status --remote-activity` shows the changes made since the last sync, which is worth checking before resolving a conflict. This uses the Drive Activity API, so if you logged in with an older version of GSync, run `gsync login` again
+//!
+//! Synced with the wrong excludes? `gsync undo` reverts what the most recent sync changed in Google Drive: files it created are moved to the trash, files it updated are rolled back to their prior revision and files it trashed are restored. Only the revisions the sync uploaded are deleted, a file changed in Drive since, e.g. by a collaborator, is reported as a conflict and left alone. Files it deleted permanently can't be brought back. If some changes can't be undone, e.g. because Drive is unreachable, running `gsync undo` again retries them. Use `--dry-run` to see what would be undone first
+//!
+//! Besides mirroring your files, GSync can keep point-in-time backups with `gsync snapshot`. The first snapshot is a full one, uploading every file into a folder under `_snapshots`. The snapshots after it are incremental: they only upload the files which changed, and reference unchanged files from earlier snapshots in the chain. After 7 incremental snapshots the next one is a full snapshot again, this can be changed with `gsync config --snapshot-full-every <COUNT>`, and `--full` forces one. To restore a snapshot in full, run `gsync restore --snapshot <ID> / <TARGET>`
+//!
+//! Run `gsync snapshots` to list the snapshots with their ID, the chain they belong to, their size, how much was uploaded for them and whether they were verified. `gsync snapshots --verify <ID>` checks that every file of a snapshot, including the files it references from earlier snapshots, is still in Drive unchanged. A snapshot in which files failed to upload is listed as incomplete, and `gsync snapshot` exits with an error
+//!
+//! In daemon mode GSync also scrubs the files it synced: after every sync it verifies a batch of them, checking that each is still in Google Drive, not in the trash, and has the checksum it was uploaded with. The batch is sized so every file is verified once within 30 days, which can be changed with `gsync config --scrub-window <DAYS>`, or disabled with 0. Files which fail verification are logged as warnings and shown by `gsync status`, the dashboard and `GET /status`. `gsync scrub` verifies every synced file right away. `gsync history` lists the last syncs, with `--json` for scripts
+//!
+//! Scrubbing compares Drive with what was uploaded. To compare Drive with what is on your disk, run `gsync verify [PATH]`: it fetches the MD5 checksum Drive reports for every synced file, or those within PATH, and compares it with the checksum of the local file. Each mismatch is reported with what changed: the local file since the last sync, the copy in Drive, or both. Google documents have no checksum in Drive and are not verified. The exit code is 1 if any file failed verification
+//!
+//! A scrub trusts the checksums Google reports. For evidence that the backup can actually be restored, run `gsync drill`: it restores a random sample of the synced files to a temporary folder, verifies their checksums against the ones recorded when they were synced, removes them again, and exits with code 1 if any file failed. Use `--sample <COUNT>` to restore more than 10 files. `gsync daemon --drill <DAYS>` runs a drill on a schedule, reporting failures on the dashboard
+//!
+//! For the long term, `gsync stats` shows per month how many runs there were and how many of them failed, how much was uploaded, how many files failed to sync, and how the size of the synced data grew. These statistics are kept separately from the run history, so they are not removed by `gsync gc`. With `--projection`, it also estimates when your Drive storage will be full if your data keeps growing like it did the last six months
+//!
+//! ## Restoring
+//! To get a file or folder back, run `gsync restore <REMOTE PATH> --to <DIR>`, e.g. `gsync restore Documents/taxes/2023.pdf --to ~/restored`. The remote path is relative to the GSync folder in your Drive, and the directory defaults to the current one. Only the requested file, or the requested folder and its contents, is downloaded. Files GSync synced are looked up in its database, other paths by listing the folders in Drive
+//!
+//! Files can also change in Drive itself, e.g. when you edit a document in the web UI. At the start of every sync GSync asks Drive what changed since the previous sync. A synced file deleted or moved in Drive is uploaded again to its original place. A synced file edited in Drive is not overwritten: it is skipped and listed in the report until you restore it, or sync with `--overwrite-remote` to replace the edit with your local version
+//!
 //! ## Licence
 //! GSync is dual licenced under the MIT and Apache-2.0 licence, at your discretion
 
@@ -36,16 +185,69 @@
 
 #![allow(clippy::multiple_crate_versions)]
 
+mod activity;
+mod adopt;
 mod api;
+mod audit;
+mod bars;
+mod budget;
+mod changes;
+mod compare;
+mod concurrency;
 mod env;
+mod exclude;
 mod config;
+mod container;
+mod daemon;
+mod database;
+mod drill;
+mod files;
+mod filter;
+mod forget;
+mod gc;
+mod hash;
+mod labels;
+mod limits;
+mod links;
 mod login;
+mod manifest;
 mod macros;
+mod metadata;
+mod mime;
+#[cfg(all(unix, feature = "fuse"))]
+mod mount;
+mod open;
+mod overrides;
+mod picker;
+mod plan;
+mod progress;
+mod quota;
+mod redact;
+mod report;
+mod restore;
+mod scrub;
+mod serve;
+mod shadow;
+mod size;
+mod snapshot;
+mod sparse;
+mod state;
+mod stats;
 mod sync;
+mod template;
+mod throttle;
+mod transfer;
+mod tree;
+#[cfg(all(unix, feature = "tui"))]
+mod tui;
+mod undelete;
+mod undo;
+mod verify;
+mod watch;
 
 use clap::Arg;
 use crate::env::Env;
-use crate::config::Configuration;
+use crate::config::{Configuration, InputConfiguration, RemoteRoot};
 use crate::api::GoogleError;
 
 /// Type alias for Result
@@ -75,6 +277,12 @@ fn main() {
         .version(VERSION)
         .author("Tobias de Bruijn <t.debruijn@array21.dev>")
         .about("Sync folders and files to Google Drive while respecting gitignore files")
+        .arg(Arg::with_name("state-dir")
+            .long("state-dir")
+            .value_name("DIR")
+            .help("Keep GSync's state, its configuration, login and the synced files, in this folder instead of the default one, e.g. a volume mounted into a container. The GSYNC_STATE_DIR environment variable does the same")
+            .takes_value(true)
+            .required(false))
         .subcommand(clap::SubCommand::with_name("config")
             .about("Configure GSync. Not all options have to be supplied, if you don't want to overwrite them. If this is the first time you're running the config command, you must provide all options.")
             .arg(Arg::with_name("client-id")
@@ -98,185 +306,2657 @@ fn main() {
                 .help("The files you want to sync, comma seperated String")
                 .takes_value(true)
                 .required(false))
+            .arg(Arg::with_name("skip-input-check")
+                .long("skip-input-check")
+                .help("Configure the inputs provided with --files even if they don't exist or can't be read, e.g. for a drive which is not mounted yet. Problems are reported as warnings instead")
+                .requires("files")
+                .required(false))
             .arg(Arg::with_name("drive_id")
                 .short("d")
                 .long("drive")
                 .value_name("ID")
                 .help("The ID of the Team Drive to use, if you are not using a Team Drive leave this empty.")
                 .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("quota-threshold")
+                .long("quota-threshold")
+                .value_name("PERCENT")
+                .help("The percentage of the Drive storage quota that may be in use after a sync. Defaults to 100")
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("quota-action")
+                .long("quota-action")
+                .value_name("ACTION")
+                .help("What to do when a sync would exceed the quota threshold. Defaults to abort")
+                .possible_values(&["warn", "abort"])
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("xattrs")
+                .long("xattrs")
+                .value_name("BOOL")
+                .help("Whether extended attributes (e.g. SELinux labels, macOS Finder tags) should be stored in Drive and restored. Defaults to false")
+                .possible_values(&["true", "false"])
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("service-account")
+                .long("service-account")
+                .value_name("KEY_FILE")
+                .help("Path to the JSON key of a service account with domain-wide delegation. Requires --impersonate. Logging in is not necessary when using a service account")
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("impersonate")
+                .long("impersonate")
+                .value_name("EMAIL")
+                .help("The Google Workspace user to impersonate with the service account, e.g. user@domain.com")
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("labels")
+                .long("labels")
+                .value_name("LABELS")
+                .help("Drive labels to apply to files created by GSync, separated by commas. Either a label ID, or LABEL_ID:FIELD=VALUE to also set a field of the label")
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("upload-reports")
+                .long("upload-reports")
+                .value_name("BOOL")
+                .help("Whether the report of every sync should be uploaded to the '_reports' folder in the GSync folder in Drive. Defaults to false")
+                .possible_values(&["true", "false"])
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("history-retention")
+                .long("history-retention")
+                .value_name("DAYS")
+                .help("The number of days runs are kept in the run history, older runs are removed by `gsync gc`. Defaults to 90")
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("target-folder")
+                .long("target-folder")
+                .value_name("ID")
+                .help("The ID of an existing folder in Google Drive to sync into, e.g. a folder shared with you by your team. By default GSync syncs into a folder named 'GSync'")
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("pick-folder")
+                .long("pick-folder")
+                .help("Pick the folder to sync into interactively, by navigating through My Drive and your shared drives. Sets the target folder and the drive. Requires being logged in")
+                .conflicts_with_all(&["target-folder", "drive_id"])
+                .required(false))
+            .arg(Arg::with_name("delete-limit")
+                .long("delete-limit")
+                .value_name("LIMIT")
+                .help("The maximum number of tracked files a sync may delete from Drive, either a number or a percentage like 10%. Syncs exceeding it are refused unless --allow-mass-delete is given. Defaults to 50%")
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("delete-policy")
+                .long("delete-policy")
+                .value_name("POLICY")
+                .help("What happens in Drive to files and folders removed locally. 'trash' moves them to the trash, 'mirror' deletes them permanently and 'archive' keeps them, marked stale. Defaults to trash")
+                .possible_values(&["mirror", "archive", "trash"])
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("audit-log")
+                .long("audit-log")
+                .value_name("FILE")
+                .help("Also append every operation GSync performs in Google Drive to this file. Operations are always recorded in the database, see `gsync audit`")
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("snapshot-full-every")
+                .long("snapshot-full-every")
+                .value_name("COUNT")
+                .help("The number of incremental snapshots taken by `gsync snapshot` before the next full snapshot. Defaults to 7")
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("scrub-window")
+                .long("scrub-window")
+                .value_name("DAYS")
+                .help("The number of days within which `gsync daemon` verifies every synced file once, by comparing its checksum in Drive with the checksum it was uploaded with. 0 disables this. Defaults to 30")
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("compare")
+                .long("compare")
+                .value_name("POLICY")
+                .help("How GSync decides whether a file changed: 'mtime' compares the modification time, which is fast, 'size-mtime' also compares the size, and 'hash' compares checksums of the content, which is accurate but reads every file. Inputs can override it with `gsync input --compare`. Defaults to mtime")
+                .possible_values(&crate::compare::POLICY_NAMES)
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("mtime-tolerance")
+                .long("mtime-tolerance")
+                .value_name("SECONDS")
+                .help("The number of seconds modification times may differ while still being considered the same, for filesystems with coarse timestamps like FAT, or network filesystems with a skewed clock. Defaults to 2")
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("symlinks")
+                .long("symlinks")
+                .alias("links")
+                .value_name("POLICY")
+                .help("How GSync syncs symbolic links: 'follow' syncs what a link points to as if it was in the place of the link, 'shortcut' syncs the link as a shortcut to the synced copy of its target, which `gsync restore` recreates as a link, and 'skip' doesn't sync links. Inputs can override it with `gsync input --symlinks`. Defaults to follow")
+                .possible_values(&crate::links::POLICY_NAMES)
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("hash")
+                .long("hash")
+                .value_name("ALGORITHM")
+                .help("The algorithm GSync stores checksums of synced files with. 'blake3' is the fastest, 'md5' is what Google Drive reports and 'sha256' is there for when a standardized algorithm is required. Checksums stored with another algorithm stay valid. Defaults to blake3")
+                .possible_values(&crate::hash::ALGORITHM_NAMES)
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("state-passphrase-file")
+                .long("state-passphrase-file")
+                .value_name("FILE")
+                .help("Back up GSync's own state to a hidden folder in Google Drive once a day, encrypted with the passphrase on the first line of this file, so it can be restored on another machine with `gsync state restore`. The passphrase is stored in plain text in GSync's database. The GSYNC_STATE_PASSPHRASE environment variable takes precedence")
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("mime-types")
+                .long("mime-types")
+                .value_name("TYPES")
+                .help("Comma separated MIME types per file extension, used instead of the MIME type guessed from the extension, e.g. 'md=text/markdown,log=text/plain'")
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("convert")
+                .long("convert")
+                .value_name("BOOL")
+                .help("Whether uploaded office files (e.g. .docx, .xlsx, .pptx, .odt) should be converted to Google Docs, Sheets and Slides, so they can be edited in Google Drive. Defaults to false")
+                .possible_values(&["true", "false"])
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("pack-sparse")
+                .long("pack-sparse")
+                .value_name("BOOL")
+                .help("Whether sparse files with at least 1 MiB of holes are uploaded packed, without their holes. Packed files can only be read back with GSync. Defaults to false")
+                .possible_values(&["true", "false"])
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("unset")
+                .long("unset")
+                .value_name("FIELD")
+                .help("Clear a field of the configuration, so its default is used again, e.g. `--unset drive_id` to sync to My Drive again. Can be given multiple times")
+                .possible_values(&crate::config::FIELD_NAMES)
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .required(false))
+            .arg(Arg::with_name("max-jobs")
+                .long("max-jobs")
+                .value_name("JOBS")
+                .help("The maximum number of concurrent uploads. GSync tunes the number of concurrent uploads automatically up to this maximum. Defaults to 8")
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("max-retries")
+                .long("max-retries")
+                .value_name("RETRIES")
+                .help("The number of times a request to Google Drive is retried when Google rate limits it or is temporarily unavailable, with an exponential backoff. 0 disables retrying. Defaults to 5")
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("bwlimit")
+                .long("bwlimit")
+                .value_name("RATE")
+                .help("The maximum upload bandwidth per second, shared by all uploads, e.g. 2M. 0 disables the limit")
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("max-size")
+                .long("max-size")
+                .value_name("SIZE")
+                .help("Skip files larger than this size, e.g. 2G, and list them in the report. An input or folder can set its own maximum size. 0 disables the limit")
+                .takes_value(true)
                 .required(false)))
         .subcommand(clap::SubCommand::with_name("show")
             .about("Show the current GSync configuration"))
         .subcommand(clap::SubCommand::with_name("login")
-            .about("Login to Google"))
+            .about("Login to Google")
+            .arg(Arg::with_name("timeout")
+                .long("timeout")
+                .value_name("SECONDS")
+                .help("The time you get to complete the login in your browser. Defaults to 300")
+                .takes_value(true)
+                .required(false)))
         .subcommand(clap::SubCommand::with_name("sync")
-            .about("Start syncing the configured folders to Google Drive"))
+            .about("Start syncing the configured folders to Google Drive")
+            .arg(Arg::with_name("confirm")
+                .long("confirm")
+                .help("Show the plan of the sync and wait for approval before making any changes")
+                .takes_value(false)
+                .required(false))
+            .arg(Arg::with_name("strict")
+                .long("strict")
+                .help("Abort the sync on any anomaly, e.g. a file or folder which can't be read or fails to upload. By default these are skipped and listed in the report at the end of the sync")
+                .takes_value(false)
+                .required(false))
+            .arg(Arg::with_name("allow-mass-delete")
+                .long("allow-mass-delete")
+                .help("Proceed even if the sync would delete more tracked files than the configured deletion limit")
+                .takes_value(false)
+                .required(false))
+            .arg(Arg::with_name("dry-run")
+                .long("dry-run")
+                .help("Only show what the sync would do, without transferring anything")
+                .takes_value(false)
+                .required(false))
+            .arg(Arg::with_name("json")
+                .long("json")
+                .help("With --dry-run, write the plan as JSON to stdout")
+                .requires("dry-run")
+                .takes_value(false)
+                .required(false))
+            .arg(Arg::with_name("files-from")
+                .long("files-from")
+                .value_name("FILE")
+                .help("Only sync the files and folders listed in FILE, one path per line, instead of traversing the inputs. Use - to read the list from stdin, e.g. `git diff --name-only | gsync sync --files-from -`. Listed folders are synced without their contents, nothing is deleted from Drive")
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("exclude-from")
+                .long("exclude-from")
+                .value_name("FILE")
+                .help("Don't sync the files and folders matching the patterns in FILE, one per line in gitignore syntax, in addition to the excludes of each input. Can be given multiple times")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .required(false))
+            .arg(Arg::with_name("links")
+                .long("links")
+                .value_name("POLICY")
+                .help("How symbolic links are synced in this run, instead of the configured policy: 'follow', 'shortcut' or 'skip'")
+                .possible_values(&crate::links::POLICY_NAMES)
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("include-ext")
+                .long("include-ext")
+                .value_name("EXTENSIONS")
+                .help("Only sync files with one of these comma separated extensions in this run, e.g. jpg,raw. Folders are still traversed")
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("exclude-ext")
+                .long("exclude-ext")
+                .value_name("EXTENSIONS")
+                .help("Don't sync files with one of these comma separated extensions in this run, e.g. log,tmp")
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("purge")
+                .long("purge")
+                .help("Permanently delete the files and folders which were removed locally from Drive. By default they are moved to the trash in Drive, where they can be recovered for 30 days")
+                .takes_value(false)
+                .required(false))
+            .arg(Arg::with_name("overwrite-remote")
+                .long("overwrite-remote")
+                .help("Update files which were edited in Drive since the last sync with the local version. By default they are skipped and listed in the report")
+                .takes_value(false)
+                .required(false))
+            .arg(Arg::with_name("jobs")
+                .short("j")
+                .long("jobs")
+                .value_name("JOBS")
+                .help("The maximum number of files uploaded at the same time in this run, instead of the configured maximum. Folders are still created in order")
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("bwlimit")
+                .long("bwlimit")
+                .value_name("RATE")
+                .help("The maximum upload bandwidth per second in this run, e.g. 2M, instead of the configured limit. 0 disables the limit")
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("max-size")
+                .long("max-size")
+                .value_name("SIZE")
+                .help("Skip files larger than this size in this run, e.g. 2G, instead of the configured maximum sizes. 0 syncs files of any size")
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("max-transfer")
+                .long("max-transfer")
+                .value_name("SIZE")
+                .help("Stop uploading once this much has been uploaded in this run, e.g. 5G on a metered connection. The files which don't fit are synced in the next run")
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("max-duration")
+                .long("max-duration")
+                .value_name("TIME")
+                .help("Stop starting new work once the run has taken this long, e.g. 2h or 1h30m for a nightly maintenance window. Transfers in progress are finished, the remaining files are synced in the next run")
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("progress")
+                .long("progress")
+                .help("Show a progress bar over the whole sync and one per file being uploaded, with the bytes transferred, speed and ETA. The bars are drawn on stderr, redirect the log to keep them readable, e.g. `gsync sync --progress > sync.log`")
+                .conflicts_with_all(&["tui", "dry-run"])
+                .takes_value(false)
+                .required(false))
+            .arg(Arg::with_name("tui")
+                .long("tui")
+                .help("Follow the sync in a full-screen terminal UI, showing the scan progress, the transfers in progress, errors and the throughput. Requires GSync to be built with `--features tui`")
+                .conflicts_with_all(&["confirm", "dry-run"])
+                .takes_value(false)
+                .required(false)))
+        .subcommand(clap::SubCommand::with_name("container")
+            .about("Sync once and exit, for running GSync as a Docker or Kubernetes job. The configuration is read from GSYNC_<FIELD> environment variables, e.g. GSYNC_INPUT_FILES, and an optional TOML configuration file. Nothing waits for input; errors are written to stderr as JSON and the exit code tells the outcome: 0 success, 2 completed with failures, 3 configuration error, 4 authentication error, 5 sync failed")
+            .arg(Arg::with_name("config-file")
+                .long("config-file")
+                .value_name("FILE")
+                .help("A TOML file with the configuration fields as keys, e.g. a mounted secret. Environment variables take precedence over it. Defaults to the file in GSYNC_CONFIG_FILE, if set")
+                .takes_value(true)
+                .required(false)))
+        .subcommand(clap::SubCommand::with_name("watch")
+            .about("Sync the configured folders once, then keep watching them and sync changed files as they are saved")
+            .arg(Arg::with_name("debounce")
+                .long("debounce")
+                .value_name("SECONDS")
+                .help("How long the folders must be quiet before the changes are synced, so a burst of writes results in a single sync. Defaults to 2")
+                .takes_value(true)
+                .required(false)))
+        .subcommand(clap::SubCommand::with_name("status")
+            .about("Show which files are out of sync with Google Drive, without transferring anything")
+            .arg(Arg::with_name("check")
+                .long("check")
+                .help("Exit with code 1 if any file is out of sync, e.g. for use in CI or monitoring")
+                .takes_value(false)
+                .required(false))
+            .arg(Arg::with_name("json")
+                .long("json")
+                .help("Write the plan as JSON to stdout, with the action, path, size and reason of every change")
+                .takes_value(false)
+                .required(false))
+            .arg(Arg::with_name("remote-activity")
+                .long("remote-activity")
+                .help("Also show the changes made in Google Drive since the last sync which GSync didn't make, and who made them. Requires being logged in")
+                .takes_value(false)
+                .required(false)))
         .subcommand(clap::SubCommand::with_name("drives")
-            .about("Get a list of all shared drives and their IDs."))
-        .get_matches();
-
-    let empty_env = Env::empty();
-
-    // Scoping this seperately because we want to drop conn when we're done, since we can only ever have 1 conn.
-    {
-        //Check if there are tables
-        let conn = empty_env.get_conn().expect("Failed to create database connection. ");
-        conn.execute("CREATE TABLE IF NOT EXISTS user (id TEXT PRIMARY KEY, refresh_token TEXT, access_token TEXT, expiry INTEGER)", rusqlite::named_params! {}).expect("Failed to create table 'users'");
-        conn.execute("CREATE TABLE IF NOT EXISTS config (client_id TEXT, client_secret TEXT, input_files TEXT, drive_id TEXT)", rusqlite::named_params! {}).expect("Failed to create table 'config'");
-    }
-
-    // 'config' subcommand
-    if let Some(matches) = matches.subcommand_matches("config") {
-        let new_config = Configuration {
-            client_id:      option_str_string(matches.value_of("client-id")),
-            client_secret:  option_str_string(matches.value_of("client-secret")),
-            input_files:    option_str_string(matches.value_of("files")),
-            drive_id:       option_str_string(matches.value_of("drive_id"))
-        };
-
-        let current_config = handle_err!(Configuration::get_config(&empty_env));
-        let config = Configuration::merge(new_config, current_config);
-        match config.is_complete() {
-            (true, _) => {},
-            (false, str) => {
-                eprintln!("Error: Configuration is incomplete; {}", str);
-                std::process::exit(1);
-            }
-        }
-
-        handle_err!(config.write(&empty_env));
-
-        println!("Configuration updated!");
-        std::process::exit(0);
-    }
-
-    // 'show' subcommand
-    if matches.subcommand_matches("show").is_some() {
-        let config = handle_err!(Configuration::get_config(&empty_env));
-
-        if config.is_empty() {
-            println!("GSync is unconfigured. Run 'gsync config -h` for more information on how to configure GSync'");
-            std::process::exit(0);
-        }
-
-        println!("Current GSync configuration:");
-        println!("Client ID: {}", option_unwrap_text(config.client_id));
-        println!("Client Secret: {}", option_unwrap_text(config.client_secret));
-        println!("Input Files: {}", option_unwrap_text(config.input_files));
-        println!("Drive ID: {}", option_unwrap_text(config.drive_id));
-        std::process::exit(0);
-    }
-
-    // 'login' subcommand
-    if matches.subcommand_matches("login").is_some() {
-        let config = handle_err!(Configuration::get_config(&empty_env));
-
-        if config.is_empty() {
-            println!("GSync is unconfigured. Run 'gsync config -h` for more information on how to configure GSync'");
-            std::process::exit(0);
-        }
-
-        match config.is_complete() {
-            (true, _) => {},
-            (false, str) => {
-                eprintln!("Error: Configuration is incomplete; {}", str);
-                std::process::exit(1);
-            }
-        }
-
-        // Safe to call unwrap because we've verified that the config is complete
-        let env = Env::new(config.client_id.as_ref().unwrap(), config.client_secret.as_ref().unwrap(), config.drive_id.as_ref(), String::new());
-        let login_data = handle_err!(crate::login::perform_oauth2_login(&env));
-
-        println!("Info: Inserting tokens into database.");
-        handle_err!(crate::login::db::save_to_database(&login_data, &env));
-        println!("Info: Login successful!");
-        std::process::exit(0);
-    }
-
-    // 'sync' subcommand
-    if matches.subcommand_matches("sync").is_some() {
-        let config = handle_err!(Configuration::get_config(&empty_env));
-
-        if config.is_empty() {
-            println!("GSync is unconfigured. Run 'gsync config -h` for more information on how to configure GSync'");
-            std::process::exit(0);
-        }
-
-        match config.is_complete() {
-            (true, _) => {},
-            (false, str) => {
-                eprintln!("Error: Configuration is incomplete; {}", str);
-                std::process::exit(1);
-            }
-        }
-
-        if !handle_err!(is_logged_in(&empty_env)) {
-            eprintln!("Error: GSync isn't logged in with Google. Have you run `gsync login` yet?");
-            std::process::exit(1);
-        }
-
-        // Safe to call unwrap because we verified the config is complete above
-        let mut env = Env::new(config.client_id.as_ref().unwrap(), config.client_secret.as_ref().unwrap(), config.drive_id.as_ref(), String::new());
-
-        println!("Info: Querying Drive for root folder");
-        let list = handle_err!(crate::api::drive::list_files(&env, Some("name = 'GSync' and mimeType = 'application/vnd.google-apps.folder' and trashed = false"), config.drive_id.as_deref()));
-
-        let root_folder_id = if list.is_empty() {
-            println!("Info: Root folder doesn't exist. Creating one now.");
-            match &env.drive_id {
-                Some(drive_id) => handle_err!(crate::api::drive::create_folder(&env, "GSync", drive_id)),
-                None => handle_err!(crate::api::drive::create_folder(&env, "GSync", "root"))
-            }
-        } else {
-            println!("Info: Root folder exists.");
-            list.get(0).unwrap().id.clone()
-        };
-
-        env.root_folder = root_folder_id;
+            .about("Get a list of all shared drives, their IDs and what you may do in them.")
+            .arg(Arg::with_name("filter")
+                .long("filter")
+                .value_name("NAME")
+                .help("Only list the shared drives whose name contains NAME, ignoring case")
+                .takes_value(true)
+                .required(false)))
+        .subcommand(clap::SubCommand::with_name("restore")
+            .about("Restore a file or folder from Google Drive. Only the requested file, or the folder and everything in it, is downloaded.")
+            .arg(Arg::with_name("remote-path")
+                .value_name("REMOTE_PATH")
+                .help("The path of the file or folder to restore, relative to the GSync folder in Drive. E.g. 'Documents/taxes/2023.pdf'")
+                .required(true)
+                .index(1))
+            .arg(Arg::with_name("target")
+                .value_name("TARGET")
+                .help("The local directory to restore into. Defaults to the current directory")
+                .index(2))
+            .arg(Arg::with_name("to")
+                .long("to")
+                .value_name("DIR")
+                .help("The local directory to restore into, instead of TARGET")
+                .conflicts_with("target")
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("as-of")
+                .long("as-of")
+                .value_name("TIMESTAMP")
+                .help("Restore files as they were at this point in time, using Drive's version history. Either an RFC 3339 timestamp or a date (YYYY-MM-DD)")
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("export-format")
+                .long("export-format")
+                .value_name("FORMAT")
+                .help("The format to export Google documents (Docs, Sheets, Slides, Drawings) to")
+                .possible_values(&["office", "opendocument", "pdf"])
+                .default_value("office")
+                .takes_value(true))
+            .arg(Arg::with_name("skip-shortcuts")
+                .long("skip-shortcuts")
+                .help("Skip Drive shortcuts instead of restoring the file or folder they point to")
+                .required(false))
+            .arg(Arg::with_name("snapshot")
+                .long("snapshot")
+                .value_name("ID")
+                .help("Restore from this snapshot, taken with `gsync snapshot`. REMOTE_PATH is then the path within the snapshot, use '/' to restore everything")
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("ownership")
+                .long("ownership")
+                .help("Restore the owner and group of files. This usually requires root privileges")
+                .required(false))
+            .arg(Arg::with_name("drive_id")
+                .short("d")
+                .long("drive")
+                .value_name("ID")
+                .help("The ID of the Team Drive to restore from, if not the configured drive. Use 'root' for My Drive")
+                .takes_value(true)
+                .required(false)))
+        .subcommand(clap::SubCommand::with_name("undelete")
+            .about("List the files GSync has in the Google Drive trash, or restore them from the trash. Without any IDs, the trashed files are listed.")
+            .arg(Arg::with_name("ids")
+                .value_name("ID")
+                .help("The IDs of the trashed files to restore")
+                .multiple(true)
+                .required(false)
+                .index(1))
+            .arg(Arg::with_name("all")
+                .long("all")
+                .help("Restore all trashed files")
+                .required(false))
+            .arg(Arg::with_name("drive_id")
+                .short("d")
+                .long("drive")
+                .value_name("ID")
+                .help("The ID of the Team Drive to undelete from, if not the configured drive. Use 'root' for My Drive")
+                .takes_value(true)
+                .required(false)))
+        .subcommand(clap::SubCommand::with_name("perms")
+            .about("List who has access to your backups, and revoke that access. Without --revoke or --revoke-all, the permissions are listed.")
+            .arg(Arg::with_name("remote-path")
+                .value_name("REMOTE_PATH")
+                .help("The file or folder to inspect, relative to the GSync folder in Drive. Defaults to the GSync folder itself")
+                .required(false)
+                .index(1))
+            .arg(Arg::with_name("revoke")
+                .long("revoke")
+                .value_name("PERMISSION_ID")
+                .help("The ID of a permission to revoke")
+                .takes_value(true)
+                .multiple(true)
+                .required(false))
+            .arg(Arg::with_name("revoke-all")
+                .long("revoke-all")
+                .help("Revoke all permissions, except the owner's")
+                .conflicts_with("revoke")
+                .required(false))
+            .arg(Arg::with_name("drive_id")
+                .short("d")
+                .long("drive")
+                .value_name("ID")
+                .help("The ID of the Team Drive to inspect, if not the configured drive. Use 'root' for My Drive")
+                .takes_value(true)
+                .required(false)))
+        .subcommand(clap::SubCommand::with_name("transfer-ownership")
+            .about("Transfer the ownership of the GSync folder and everything in it to another user. You keep write access.")
+            .arg(Arg::with_name("to")
+                .long("to")
+                .value_name("EMAIL")
+                .help("The email address of the new owner")
+                .takes_value(true)
+                .required(true))
+            .arg(Arg::with_name("pending")
+                .long("pending")
+                .help("Ask the new owner to accept the ownership, rather than transferring it right away. Required when the new owner is not in your Google Workspace domain")
+                .required(false))
+            .arg(Arg::with_name("yes")
+                .short("y")
+                .long("yes")
+                .help("Don't ask for confirmation")
+                .required(false)))
+        .subcommand(clap::SubCommand::with_name("export-manifest")
+            .about("Export a listing of everything GSync has backed up: the local path, ID in Drive, size, MD5 checksum and the time it was last synced")
+            .arg(Arg::with_name("format")
+                .long("format")
+                .value_name("FORMAT")
+                .help("The format of the manifest")
+                .possible_values(&["csv", "json"])
+                .default_value("csv")
+                .takes_value(true))
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .value_name("FILE")
+                .help("The file to write the manifest to. Defaults to stdout")
+                .takes_value(true)
+                .required(false)))
+        .subcommand(clap::SubCommand::with_name("adopt")
+            .about("Take over a folder in Google Drive which already holds the content of an input, e.g. uploaded by hand or by another tool. Files matching by name, size and checksum are tracked without uploading them again, and the input is synced into the parent of the folder from then on")
+            .arg(Arg::with_name("path")
+                .help("The input the folder holds the content of")
+                .takes_value(true)
+                .required(true))
+            .arg(Arg::with_name("folder")
+                .help("The ID of the folder in Google Drive")
+                .takes_value(true)
+                .required(true)))
+        .subcommand(clap::SubCommand::with_name("exclude")
+            .about("Manage the patterns of files and folders which are not synced, globally or per input")
+            .subcommand(clap::SubCommand::with_name("add")
+                .about("Stop syncing the files and folders matching a pattern. The tracked files it excludes are listed, they are deleted from Google Drive on the next sync")
+                .arg(Arg::with_name("pattern")
+                    .help("The pattern. A pattern without a '/' matches names anywhere in an input, a pattern with a '/' matches paths relative to the input. '*' matches any number of characters except '/', '?' a single one")
+                    .takes_value(true)
+                    .required(true))
+                .arg(Arg::with_name("input")
+                    .long("input")
+                    .value_name("INPUT")
+                    .help("The input the pattern applies to. Without it, the pattern applies to all inputs")
+                    .takes_value(true)
+                    .required(false)))
+            .subcommand(clap::SubCommand::with_name("remove")
+                .about("Sync the files and folders matching a pattern again")
+                .arg(Arg::with_name("pattern")
+                    .help("The pattern. A pattern without a '/' matches names anywhere in an input, a pattern with a '/' matches paths relative to the input. '*' matches any number of characters except '/', '?' a single one")
+                    .takes_value(true)
+                    .required(true))
+                .arg(Arg::with_name("input")
+                    .long("input")
+                    .value_name("INPUT")
+                    .help("The input the pattern applies to. Without it, the pattern applies to all inputs")
+                    .takes_value(true)
+                    .required(false)))
+            .subcommand(clap::SubCommand::with_name("list")
+                .about("List the patterns, globally and per input")))
+        .subcommand(clap::SubCommand::with_name("forget")
+            .about("Stop tracking and syncing a file or folder, leaving its copy in Google Drive in place. The path is excluded from its input, or removed from the inputs if it is one")
+            .arg(Arg::with_name("path")
+                .help("The file or folder to forget. It doesn't have to exist locally anymore")
+                .takes_value(true)
+                .required(true)))
+        .subcommand(clap::SubCommand::with_name("gc")
+            .about("Remove stale state from the database: inputs which are no longer configured, the files tracked for them, and old runs in the run history"))
+        .subcommand(clap::SubCommand::with_name("db")
+            .about("Inspect the GSync database")
+            .subcommand(clap::SubCommand::with_name("stats")
+                .about("Show what GSync is tracking: the number of rows per table, tracked files and bytes, statistics of the last run and the size of the database")))
+        .subcommand(clap::SubCommand::with_name("state")
+            .about("Back up or restore GSync's own state, kept encrypted in a hidden folder in Google Drive")
+            .subcommand(clap::SubCommand::with_name("backup")
+                .about("Back up the state now"))
+            .subcommand(clap::SubCommand::with_name("restore")
+                .about("Replace the state with the backup in Google Drive, e.g. on a new machine. GSync must be configured and logged in. The current state is kept next to it")
+                .arg(Arg::with_name("passphrase-file")
+                    .long("passphrase-file")
+                    .value_name("FILE")
+                    .help("A file with the passphrase the backup was encrypted with on its first line. Defaults to the GSYNC_STATE_PASSPHRASE environment variable, then the configured passphrase")
+                    .takes_value(true)
+                    .required(false))))
+        .subcommand(clap::SubCommand::with_name("tree")
+            .about("Show the folder hierarchy GSync has synced to Google Drive, with the size and number of files per folder")
+            .arg(Arg::with_name("depth")
+                .long("depth")
+                .value_name("DEPTH")
+                .help("The number of folder levels to show. Defaults to 2")
+                .takes_value(true)
+                .required(false)))
+        .subcommand(clap::SubCommand::with_name("open")
+            .about("Open a synced file or folder in the Google Drive web interface")
+            .arg(Arg::with_name("path")
+                .value_name("PATH")
+                .help("The local path of the file or folder")
+                .required(true)
+                .index(1))
+            .arg(Arg::with_name("print")
+                .short("p")
+                .long("print")
+                .help("Only print the URL, instead of opening it in the browser")
+                .takes_value(false)
+                .required(false)))
+        .subcommand(clap::SubCommand::with_name("mount")
+            .about("Mount the GSync folder in Google Drive read-only, to browse and copy out files without a full restore. Requires GSync to be built with the 'fuse' feature")
+            .arg(Arg::with_name("mountpoint")
+                .value_name("MOUNTPOINT")
+                .help("The empty directory to mount the GSync folder at")
+                .required(true)
+                .index(1))
+            .arg(Arg::with_name("drive_id")
+                .short("d")
+                .long("drive")
+                .value_name("ID")
+                .help("The ID of the Team Drive to mount the GSync folder of. Use 'root' for My Drive. Defaults to the configured drive")
+                .takes_value(true)
+                .required(false)))
+        .subcommand(clap::SubCommand::with_name("serve")
+            .about("Browse the GSync folder in Google Drive with a web browser, through a local HTTP server. Files are downloaded from Drive when requested")
+            .arg(Arg::with_name("port")
+                .short("p")
+                .long("port")
+                .value_name("PORT")
+                .help("The port to listen on. Defaults to 8080")
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("bind")
+                .long("bind")
+                .value_name("ADDRESS")
+                .help("The address to listen on. Anyone who can reach this address can download your backup. Defaults to 127.0.0.1")
+                .default_value("127.0.0.1")
+                .takes_value(true))
+            .arg(Arg::with_name("drive_id")
+                .short("d")
+                .long("drive")
+                .value_name("ID")
+                .help("The ID of the Team Drive to serve the GSync folder of. Use 'root' for My Drive. Defaults to the configured drive")
+                .takes_value(true)
+                .required(false)))
+        .subcommand(clap::SubCommand::with_name("daemon")
+            .about("Keep running, syncing the configured folders to Google Drive every interval")
+            .arg(Arg::with_name("interval")
+                .long("interval")
+                .value_name("MINUTES")
+                .help("The time between two syncs. Defaults to 60")
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("dashboard")
+                .long("dashboard")
+                .value_name("PORT")
+                .help("Serve a status page on this port, showing the last runs, the schedule, the progress of the current sync and recent errors")
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("dashboard-bind")
+                .long("dashboard-bind")
+                .value_name("ADDRESS")
+                .help("The address to serve the status page on. Defaults to 127.0.0.1")
+                .default_value("127.0.0.1")
+                .takes_value(true))
+            .arg(Arg::with_name("api")
+                .long("api")
+                .value_name("PORT")
+                .help("Serve an HTTP API on this port to trigger syncs (POST /sync) and query their results (GET /status, GET /runs). Requires a token, set with --api-token-file or the GSYNC_API_TOKEN environment variable")
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("api-bind")
+                .long("api-bind")
+                .value_name("ADDRESS")
+                .help("The address to serve the HTTP API on. Use 0.0.0.0 to allow other hosts. Defaults to 127.0.0.1")
+                .default_value("127.0.0.1")
+                .takes_value(true))
+            .arg(Arg::with_name("api-token-file")
+                .long("api-token-file")
+                .value_name("FILE")
+                .help("A file holding the token clients of the HTTP API must send as 'Authorization: Bearer <TOKEN>' on its first line")
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("user")
+                .long("user")
+                .value_name("NAME")
+                .help("Sync for this user instead of for the user running the daemon. Can be given multiple times. Every user configures GSync and logs in themselves; their files are read with their own privileges. Requires running as root. Unix only")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .required(false))
+            .arg(Arg::with_name("jitter")
+                .long("jitter")
+                .value_name("MINUTES")
+                .help("Wait a random time of at most this many minutes before every scheduled sync, and before the sync of every user, so machines and users don't all sync at the same moment. Defaults to 0")
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("parallel")
+                .long("parallel")
+                .value_name("COUNT")
+                .help("The maximum number of users synced at the same time. Defaults to 1")
+                .takes_value(true)
+                .requires("user")
+                .required(false))
+            .arg(Arg::with_name("heavy")
+                .long("heavy")
+                .value_name("NAME")
+                .help("Never sync this user at the same time as another user given with --heavy, e.g. users with a lot of data. Can be given multiple times")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .requires("user")
+                .required(false))
+            .arg(Arg::with_name("drill")
+                .long("drill")
+                .value_name("DAYS")
+                .help("Run a restore drill every this many days, restoring and verifying a sample of the synced files like `gsync drill`. Not supported together with --user")
+                .conflicts_with("user")
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("drill-sample")
+                .long("drill-sample")
+                .value_name("COUNT")
+                .help("The number of files restored per drill. Defaults to 10")
+                .requires("drill")
+                .takes_value(true)
+                .required(false)))
+        .subcommand(clap::SubCommand::with_name("audit")
+            .about("Show the most recent operations GSync performed in Google Drive, with the run that performed them")
+            .arg(Arg::with_name("limit")
+                .short("n")
+                .long("limit")
+                .value_name("COUNT")
+                .help("The number of operations to show. Defaults to 50")
+                .default_value("50")
+                .takes_value(true))
+            .arg(Arg::with_name("remote")
+                .long("remote")
+                .help("Instead, show the changes made in the GSync folders in Google Drive which GSync didn't make, e.g. in the web UI, on another machine or by another app, and who made them")
+                .required(false))
+            .arg(Arg::with_name("since")
+                .long("since")
+                .value_name("DAYS")
+                .help("With --remote, show the changes of the last DAYS days. Defaults to 7")
+                .default_value("7")
+                .requires("remote")
+                .takes_value(true)))
+        .subcommand(clap::SubCommand::with_name("undo")
+            .about("Revert the changes the most recent sync made in Google Drive: files it created are moved to the trash, files it updated are rolled back to their prior revision and files it trashed are restored")
+            .arg(Arg::with_name("dry-run")
+                .long("dry-run")
+                .help("Only show what would be undone")
+                .required(false))
+            .arg(Arg::with_name("yes")
+                .short("y")
+                .long("yes")
+                .help("Don't ask for confirmation")
+                .required(false)))
+        .subcommand(clap::SubCommand::with_name("snapshot")
+            .about("Take a snapshot of the configured folders. Every few snapshots a full snapshot uploads every file, the incremental snapshots in between only upload the files which changed")
+            .arg(Arg::with_name("full")
+                .long("full")
+                .help("Take a full snapshot, even if the current chain isn't complete yet")
+                .required(false)))
+        .subcommand(clap::SubCommand::with_name("snapshots")
+            .about("List the snapshots taken with `gsync snapshot`, with the chain they belong to, their size and whether they were verified")
+            .arg(Arg::with_name("verify")
+                .long("verify")
+                .value_name("ID")
+                .help("Verify that every file of this snapshot still exists in Drive with the content it was uploaded with")
+                .takes_value(true)
+                .required(false)))
+        .subcommand(clap::SubCommand::with_name("scrub")
+            .about("Verify that synced files are still in Google Drive with the content they were uploaded with. Files verified longest ago are verified first")
+            .arg(Arg::with_name("count")
+                .long("count")
+                .short("n")
+                .value_name("COUNT")
+                .help("The maximum number of files to verify. Defaults to all synced files")
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("every")
+                .long("every")
+                .value_name("SECONDS")
+                .help("Only verify as many files as are due when scrubbing this often, so every synced file is verified once within the scrub window. Verifies nothing when scrubbing is disabled")
+                .takes_value(true)
+                .required(false)
+                .conflicts_with("count"))
+            .arg(Arg::with_name("json")
+                .long("json")
+                .help("Write every file which failed its last verification, including in earlier scrubs, as JSON to stdout. Exits with code 0 unless the scrub itself fails")
+                .takes_value(false)
+                .required(false)))
+        .subcommand(clap::SubCommand::with_name("history")
+            .about("Show the most recent syncs from the run history, newest first")
+            .arg(Arg::with_name("limit")
+                .long("limit")
+                .short("n")
+                .value_name("COUNT")
+                .help("The maximum number of syncs to show. Defaults to 10")
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("json")
+                .long("json")
+                .help("Write the syncs as JSON to stdout")
+                .takes_value(false)
+                .required(false)))
+        .subcommand(clap::SubCommand::with_name("verify")
+            .about("Compare the MD5 checksum Google Drive reports for every synced file with the checksum of the local file, to find files which are corrupted in Drive or out of sync")
+            .arg(Arg::with_name("path")
+                .value_name("PATH")
+                .help("Only verify the synced files within this local file or folder")
+                .required(false)
+                .index(1)))
+        .subcommand(clap::SubCommand::with_name("drill")
+            .about("Restore a random sample of the synced files from Google Drive to a temporary folder, verify their checksums and remove them again, as evidence the backup can be restored")
+            .arg(Arg::with_name("sample")
+                .long("sample")
+                .short("n")
+                .value_name("COUNT")
+                .help("The number of files to restore. Defaults to 10")
+                .takes_value(true)
+                .required(false)))
+        .subcommand(clap::SubCommand::with_name("stats")
+            .about("Show statistics per month: the runs, how much was uploaded, failures and the growth of the synced data")
+            .arg(Arg::with_name("projection")
+                .long("projection")
+                .help("Also project when the Drive storage quota will be used up if the synced data keeps growing at the rate of the last months")
+                .takes_value(false)
+                .required(false)))
+        .subcommand(clap::SubCommand::with_name("input")
+            .about("Configure a single input, overriding the global configuration. Without a path, the configuration of all inputs is shown.")
+            .arg(Arg::with_name("path")
+                .value_name("PATH")
+                .help("The input to configure, this must be one of the configured input files")
+                .required(false)
+                .index(1))
+            .arg(Arg::with_name("drive_id")
+                .short("d")
+                .long("drive")
+                .value_name("ID")
+                .help("The ID of the Team Drive to sync this input to. Use 'root' to sync this input to My Drive, regardless of the global configuration")
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("remote-root")
+                .long("remote-root")
+                .value_name("PATH")
+                .help("The folder to sync this input into instead of the 'GSync' folder, relative to the root of the drive, e.g. Backups/Docs. Missing folders are created. The placeholders {hostname}, {date}, {profile} and {input} are expanded when syncing, e.g. Backups/{hostname}/{date}")
+                .conflicts_with("remote-root-id")
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("remote-root-id")
+                .long("remote-root-id")
+                .value_name("ID")
+                .help("The ID of an existing folder to sync this input into instead of the 'GSync' folder, e.g. a folder shared with you")
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("excludes")
+                .long("excludes")
+                .value_name("PATTERNS")
+                .help("Comma separated patterns of files and folders in this input which are not synced, replacing the current ones. A pattern without a '/' matches names anywhere in the input, e.g. '*.tmp', a pattern with a '/' matches paths relative to the input, e.g. 'build/*.o'. Use '' to remove them")
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("gitignore")
+                .long("gitignore")
+                .value_name("true|false")
+                .help("Whether .gitignore files in this input are respected. Defaults to true")
+                .possible_values(&["true", "false"])
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("max-size")
+                .long("max-size")
+                .value_name("SIZE")
+                .help("Don't sync files in this input larger than this, e.g. 500M or 2G. Use 0 to remove the limit")
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("compare")
+                .long("compare")
+                .value_name("POLICY")
+                .help("How GSync decides whether a file in this input changed, overriding `gsync config --compare`: 'mtime', 'size-mtime' or 'hash'. Use 'default' to use the configured policy again")
+                .possible_values(&["mtime", "size-mtime", "hash", "default"])
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("symlinks")
+                .long("symlinks")
+                .value_name("POLICY")
+                .help("How GSync syncs symbolic links in this input, overriding `gsync config --symlinks`: 'follow', 'shortcut' or 'skip'. Use 'default' to use the configured policy again")
+                .possible_values(&["follow", "shortcut", "skip", "default"])
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("fs-snapshot")
+                .long("fs-snapshot")
+                .value_name("KIND")
+                .help("Sync this input from a snapshot of its filesystem, taken at the start of every sync and removed afterwards, so files being written to are backed up consistently: 'btrfs' if the input is a btrfs subvolume, 'zfs' or 'lvm'. On Windows, 'vss' uses a Volume Shadow Copy, which also allows reading files locked by other programs. Creating snapshots generally requires root or administrator rights. Use 'none' to sync the live files again")
+                .possible_values(&["btrfs", "zfs", "lvm", "vss", "none"])
+                .takes_value(true)
+                .required(false)))
+        .get_matches();
+
+    // The state directory must be known before the database is opened
+    if let Some(dir) = matches.value_of("state-dir") {
+        std::env::set_var(crate::env::STATE_DIR_VAR, dir);
+    }
+
+    let empty_env = Env::empty();
+
+    handle_err!(migrate(&empty_env));
+
+    // 'config' subcommand
+    if let Some(matches) = matches.subcommand_matches("config") {
+        let quota_threshold = match matches.value_of("quota-threshold") {
+            Some(threshold) => match threshold.parse::<i64>() {
+                Ok(threshold) if threshold > 0 => Some(threshold),
+                _ => {
+                    eprintln!("Error: Quota threshold '{}' is not a valid percentage", threshold);
+                    std::process::exit(1);
+                }
+            },
+            None => None
+        };
+
+        let max_jobs = match matches.value_of("max-jobs") {
+            Some(jobs) => match jobs.parse::<i64>() {
+                Ok(jobs) if jobs > 0 => Some(jobs),
+                _ => {
+                    eprintln!("Error: Maximum number of concurrent uploads '{}' is not a valid number", jobs);
+                    std::process::exit(1);
+                }
+            },
+            None => None
+        };
+
+        if let Some(rate) = matches.value_of("bwlimit") {
+            if crate::size::parse_size(rate).is_none() {
+                eprintln!("Error: Bandwidth limit '{}' is not a valid size", rate);
+                std::process::exit(1);
+            }
+        }
+
+        let max_size = match matches.value_of("max-size") {
+            Some(size) => match crate::size::parse_size(size) {
+                Some(bytes) => Some(bytes as i64),
+                None => {
+                    eprintln!("Error: Maximum file size '{}' is not a valid size", size);
+                    std::process::exit(1);
+                }
+            },
+            None => None
+        };
+
+        let max_retries = match matches.value_of("max-retries") {
+            Some(retries) => match retries.parse::<u32>() {
+                Ok(retries) => Some(retries as i64),
+                Err(_) => {
+                    eprintln!("Error: Maximum number of retries '{}' is not a valid number", retries);
+                    std::process::exit(1);
+                }
+            },
+            None => None
+        };
+
+        let history_retention = match matches.value_of("history-retention") {
+            Some(days) => match days.parse::<i64>() {
+                Ok(days) if days > 0 => Some(days),
+                _ => {
+                    eprintln!("Error: History retention '{}' is not a valid number of days", days);
+                    std::process::exit(1);
+                }
+            },
+            None => None
+        };
+
+        let snapshot_full_every = match matches.value_of("snapshot-full-every") {
+            Some(count) => match count.parse::<i64>() {
+                Ok(count) if count >= 0 => Some(count),
+                _ => {
+                    eprintln!("Error: '{}' is not a valid number of incremental snapshots", count);
+                    std::process::exit(1);
+                }
+            },
+            None => None
+        };
+
+        let scrub_window = match matches.value_of("scrub-window") {
+            Some(days) => match days.parse::<i64>() {
+                Ok(days) if days >= 0 => Some(days),
+                _ => {
+                    eprintln!("Error: Scrub window '{}' is not a valid number of days", days);
+                    std::process::exit(1);
+                }
+            },
+            None => None
+        };
+
+        let mtime_tolerance = match matches.value_of("mtime-tolerance") {
+            Some(seconds) => match seconds.parse::<i64>() {
+                Ok(seconds) if seconds >= 0 => Some(seconds),
+                _ => {
+                    eprintln!("Error: Modification time tolerance '{}' is not a valid number of seconds", seconds);
+                    std::process::exit(1);
+                }
+            },
+            None => None
+        };
+
+        let delete_limit = match matches.value_of("delete-limit") {
+            Some(limit) => match crate::plan::DeleteLimit::parse(limit) {
+                Some(_) => Some(limit.to_string()),
+                None => {
+                    eprintln!("Error: Deletion limit '{}' is not a valid number or percentage", limit);
+                    std::process::exit(1);
+                }
+            },
+            None => None
+        };
+
+        if let Some(mime_types) = matches.value_of("mime-types") {
+            if let Err(e) = crate::mime::parse_overrides(mime_types) {
+                eprintln!("Error: Invalid MIME types: {}", e);
+                std::process::exit(1);
+            }
+        }
+
+        let new_config = Configuration {
+            client_id:      option_str_string(matches.value_of("client-id")),
+            client_secret:  option_str_string(matches.value_of("client-secret")),
+            input_files:    option_str_string(matches.value_of("files")),
+            drive_id:       option_str_string(matches.value_of("drive_id")),
+            quota_threshold,
+            quota_action:   option_str_string(matches.value_of("quota-action")),
+            preserve_xattrs: option_str_bool(matches.value_of("xattrs")),
+            max_jobs,
+            service_account_key: option_str_string(matches.value_of("service-account")),
+            impersonate: option_str_string(matches.value_of("impersonate")),
+            labels: option_str_string(matches.value_of("labels")),
+            upload_reports: option_str_bool(matches.value_of("upload-reports")),
+            history_retention,
+            delete_limit,
+            target_folder: option_str_string(matches.value_of("target-folder")),
+            audit_log: option_str_string(matches.value_of("audit-log")),
+            snapshot_full_every,
+            scrub_window,
+            compare: option_str_string(matches.value_of("compare")),
+            symlinks: option_str_string(matches.value_of("symlinks")),
+            mtime_tolerance,
+            hash_algorithm: option_str_string(matches.value_of("hash")),
+            state_passphrase: matches.value_of_os("state-passphrase-file").map(|path| handle_err!(crate::env::read_secret_file(std::path::Path::new(path)))),
+            excludes: None,
+            mime_types: option_str_string(matches.value_of("mime-types")),
+            convert: option_str_bool(matches.value_of("convert")),
+            delete_policy: matches.value_of("delete-policy").map(String::from),
+            max_retries,
+            bwlimit: option_str_string(matches.value_of("bwlimit")),
+            max_size,
+            pack_sparse: option_str_bool(matches.value_of("pack-sparse"))
+        };
+
+        let current_config = handle_err!(Configuration::get_config(&empty_env));
+        let mut config = Configuration::merge(new_config, current_config);
+        for field in matches.values_of("unset").into_iter().flatten() {
+            config.unset(field);
+        }
+
+        match config.is_complete() {
+            (true, _) => {},
+            (false, str) => {
+                eprintln!("Error: Configuration is incomplete; {}", str);
+                std::process::exit(1);
+            }
+        }
+
+        if matches.is_present("files") {
+            let problems = config.input_problems(std::path::Path::new(&empty_env.db));
+            for problem in problems.iter() {
+                if matches.is_present("skip-input-check") {
+                    println!("Warning: {}", problem);
+                } else {
+                    eprintln!("Error: {}", problem);
+                }
+            }
+
+            if !problems.is_empty() && !matches.is_present("skip-input-check") {
+                eprintln!("Error: The configuration was not updated. Use --skip-input-check to configure these inputs anyway, e.g. for a drive which is not mounted yet");
+                std::process::exit(1);
+            }
+        }
+
+        if matches.is_present("pick-folder") {
+            if !config.uses_service_account() && !handle_err!(is_logged_in(&empty_env)) {
+                eprintln!("Error: GSync isn't logged in with Google. Run `gsync login` first, then `gsync config --pick-folder`");
+                std::process::exit(1);
+            }
+
+            let env = Env::from_config(&config, config.drive_id.as_ref());
+            match handle_err!(crate::picker::pick(&env)) {
+                Some(folder) => {
+                    println!("Syncing into '{}'", folder.path);
+                    config.drive_id = folder.drive_id;
+                    config.target_folder = Some(folder.folder_id);
+                },
+                None => {
+                    println!("No folder was picked, the configuration is unchanged");
+                    std::process::exit(0);
+                }
+            }
+        }
+
+        handle_err!(config.write(&empty_env));
+
+        println!("Configuration updated!");
+        if matches.is_present("labels") && !config.uses_service_account() {
+            println!("Info: Run `gsync login` again so GSync may read the configured labels");
+        }
+        std::process::exit(0);
+    }
+
+    // 'show' subcommand
+    if matches.subcommand_matches("show").is_some() {
+        let config = load_config_or_exit(&empty_env);
+
+        println!("Current GSync configuration:");
+        println!("Client ID: {}", option_unwrap_text(config.client_id));
+        println!("Client Secret: {}", option_unwrap_text(config.client_secret.map(crate::redact::mask)));
+        println!("Input Files: {}", option_unwrap_text(config.input_files));
+        println!("Drive ID: {}", option_unwrap_text(config.drive_id));
+        println!("Quota threshold: {}", option_unwrap_text(config.quota_threshold.map(|threshold| format!("{}%", threshold))));
+        println!("Quota action: {}", option_unwrap_text(config.quota_action));
+        println!("Preserve extended attributes: {}", option_unwrap_text(config.preserve_xattrs.map(|preserve| preserve.to_string())));
+        println!("Maximum concurrent uploads: {}", option_unwrap_text(config.max_jobs.map(|jobs| jobs.to_string())));
+        println!("Service account key: {}", option_unwrap_text(config.service_account_key));
+        println!("Impersonated user: {}", option_unwrap_text(config.impersonate));
+        println!("Labels: {}", option_unwrap_text(config.labels));
+        println!("Upload run reports: {}", option_unwrap_text(config.upload_reports.map(|upload| upload.to_string())));
+        println!("Run history retention: {}", option_unwrap_text(config.history_retention.map(|days| format!("{} days", days))));
+        println!("Deletion limit: {}", option_unwrap_text(config.delete_limit));
+        println!("Target folder: {}", option_unwrap_text(config.target_folder));
+        println!("Audit log file: {}", option_unwrap_text(config.audit_log));
+        println!("Incremental snapshots per full snapshot: {}", option_unwrap_text(config.snapshot_full_every.map(|every| every.to_string())));
+        println!("Scrub window: {}", option_unwrap_text(config.scrub_window.map(|days| format!("{} days", days))));
+        println!("Compare: {}", option_unwrap_text(config.compare));
+        println!("Symlinks: {}", option_unwrap_text(config.symlinks));
+        println!("Modification time tolerance: {}", option_unwrap_text(config.mtime_tolerance.map(|seconds| format!("{} seconds", seconds))));
+        println!("Hash algorithm: {}", option_unwrap_text(config.hash_algorithm));
+        println!("State backup passphrase: {}", if config.state_passphrase.is_some() { "set" } else { "not set" });
+        println!("Excludes: {}", option_unwrap_text(config.excludes));
+        println!("MIME types: {}", option_unwrap_text(config.mime_types));
+        println!("Convert to Google formats: {}", option_unwrap_text(config.convert.map(|c| c.to_string())));
+        println!("Deletion policy: {}", option_unwrap_text(config.delete_policy));
+        println!("Maximum retries: {}", option_unwrap_text(config.max_retries.map(|retries| retries.to_string())));
+        println!("Upload bandwidth limit: {}", option_unwrap_text(config.bwlimit.map(|limit| format!("{}/s", limit))));
+        println!("Maximum file size: {}", option_unwrap_text(config.max_size.map(|max_size| crate::size::format_size(max_size as u64))));
+        println!("Pack sparse files: {}", option_unwrap_text(config.pack_sparse.map(|p| p.to_string())));
+
+        for input in handle_err!(InputConfiguration::get_all(&empty_env)) {
+            print_input(input);
+        }
+
+        std::process::exit(0);
+    }
+
+    // 'login' subcommand
+    if let Some(matches) = matches.subcommand_matches("login") {
+        let timeout = match matches.value_of("timeout") {
+            Some(timeout) => match timeout.parse::<u64>() {
+                Ok(timeout) if timeout > 0 => std::time::Duration::from_secs(timeout),
+                _ => {
+                    eprintln!("Error: Timeout '{}' is not a valid number of seconds", timeout);
+                    std::process::exit(1);
+                }
+            },
+            None => crate::login::DEFAULT_TIMEOUT
+        };
+
+        let config = load_complete_config_or_exit(&empty_env);
+
+        if config.uses_service_account() {
+            println!("Info: GSync is configured to impersonate '{}' with a service account. Logging in is not necessary.", config.impersonate.as_ref().unwrap());
+            std::process::exit(0);
+        }
+
+        // Safe to call unwrap because we've verified that the config is complete, and we're not using a service account
+        let mut env = Env::new(config.client_id.as_ref().unwrap(), config.client_secret.as_ref().unwrap(), config.drive_id.as_ref(), String::new());
+        env.labels = config.labels.is_some();
+        let login_data = handle_err!(crate::login::perform_oauth2_login(&env, timeout));
+
+        println!("Info: Inserting tokens into database.");
+        handle_err!(crate::login::db::save_to_database(&login_data, &env));
+        println!("Info: Login successful!");
+        std::process::exit(0);
+    }
+
+    // 'sync' subcommand
+    if let Some(matches) = matches.subcommand_matches("sync") {
+        let config = load_complete_config_or_exit(&empty_env);
+
+        let files_from = match matches.value_of_os("files-from") {
+            Some(file) => {
+                let list = handle_err!(crate::sync::read_list(std::path::Path::new(file)));
+                Some(list.iter().map(crate::sync::absolute_path).collect::<Vec<_>>())
+            },
+            None => None
+        };
+
+        let mut excludes = Vec::new();
+        for file in matches.values_of_os("exclude-from").into_iter().flatten() {
+            match std::fs::read_to_string(file) {
+                Ok(contents) => excludes.append(&mut crate::filter::parse_patterns(&contents)),
+                Err(e) => {
+                    eprintln!("Error: Failed to read the exclusion patterns in '{}': {}", file.to_string_lossy(), e);
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        let bwlimit = match matches.value_of("bwlimit") {
+            Some(rate) => match crate::size::parse_size(rate) {
+                Some(bytes) => Some(bytes),
+                None => {
+                    eprintln!("Error: Bandwidth limit '{}' is not a valid size", rate);
+                    std::process::exit(1);
+                }
+            },
+            None => None
+        };
+
+        let max_size = match matches.value_of("max-size") {
+            Some(size) => match crate::size::parse_size(size) {
+                Some(bytes) => Some(bytes),
+                None => {
+                    eprintln!("Error: Maximum file size '{}' is not a valid size", size);
+                    std::process::exit(1);
+                }
+            },
+            None => None
+        };
+
+        let max_transfer = match matches.value_of("max-transfer") {
+            Some(size) => match crate::size::parse_size(size) {
+                Some(bytes) => Some(bytes),
+                None => {
+                    eprintln!("Error: Transfer budget '{}' is not a valid size", size);
+                    std::process::exit(1);
+                }
+            },
+            None => None
+        };
+
+        let max_duration = match matches.value_of("max-duration") {
+            Some(duration) => match crate::budget::parse_duration(duration) {
+                Some(duration) => Some(duration),
+                None => {
+                    eprintln!("Error: Time budget '{}' is not a valid duration", duration);
+                    std::process::exit(1);
+                }
+            },
+            None => None
+        };
+
+        let jobs = match matches.value_of("jobs") {
+            Some(jobs) => match jobs.parse::<usize>() {
+                Ok(jobs) if jobs > 0 => Some(jobs),
+                _ => {
+                    eprintln!("Error: '{}' is not a valid number of jobs", jobs);
+                    std::process::exit(1);
+                }
+            },
+            None => None
+        };
+
+        let options = crate::sync::SyncOptions {
+            confirm: matches.is_present("confirm"),
+            allow_mass_delete: matches.is_present("allow-mass-delete"),
+            strict: matches.is_present("strict"),
+            files_from,
+            excludes,
+            symlinks: matches.value_of("links").and_then(crate::links::SymlinkPolicy::parse),
+            include_ext: matches.value_of("include-ext").map(crate::filter::parse_extensions).unwrap_or_default(),
+            exclude_ext: matches.value_of("exclude-ext").map(crate::filter::parse_extensions).unwrap_or_default(),
+            max_transfer,
+            max_size,
+            max_duration,
+            jobs,
+            progress: matches.is_present("progress"),
+            overwrite_remote: matches.is_present("overwrite-remote"),
+            purge: matches.is_present("purge")
+        };
+
+        // A dry run is planned from the local database, so no login is needed
+        if matches.is_present("dry-run") {
+            let env = Env::from_config(&config, config.drive_id.as_ref());
+            let json = matches.is_present("json");
+            let plan = handle_err!(crate::sync::plan(&config, &env, &options, !json));
+
+            if json {
+                println!("{}", handle_err!(plan.to_json()));
+            } else {
+                print!("\n{}", plan.listing());
+                println!("{}", plan.summary());
+            }
+
+            std::process::exit(0);
+        }
+
+        if !config.uses_service_account() && !handle_err!(is_logged_in(&empty_env)) {
+            eprintln!("Error: GSync isn't logged in with Google. Have you run `gsync login` yet?");
+            std::process::exit(1);
+        }
+
+        let mut env = Env::from_config(&config, config.drive_id.as_ref());
+        if let Some(bytes) = bwlimit {
+            env.bwlimit = crate::throttle::limiter(bytes);
+        }
+
+        if matches.is_present("tui") {
+            #[cfg(not(all(unix, feature = "tui")))]
+            {
+                eprintln!("Error: This build of GSync does not support the TUI. Rebuild GSync with `--features tui`");
+                std::process::exit(1);
+            }
+
+            #[cfg(all(unix, feature = "tui"))]
+            {
+                handle_err!(crate::tui::sync(config, env, options));
+                std::process::exit(0);
+            }
+        }
+
+        handle_err!(crate::sync::sync(&config, &env, &options));
+        std::process::exit(0);
+    }
+
+    // 'container' subcommand
+    if let Some(matches) = matches.subcommand_matches("container") {
+        use crate::container::Outcome;
+
+        let file = matches.value_of("config-file").map(std::path::PathBuf::from)
+            .or_else(|| std::env::var_os(crate::container::CONFIG_FILE_VAR).map(std::path::PathBuf::from));
+        let config = match crate::container::load_config(&empty_env, file.as_deref()) {
+            Ok(config) => config,
+            Err(e) => crate::container::exit(Outcome::ConfigurationError, Some(&e), None)
+        };
+
+        if let (false, reason) = config.is_complete() {
+            crate::container::exit(Outcome::ConfigurationError, Some(&format!("Configuration is incomplete; {}", reason)), None);
+        }
+
+        // Logging in needs a browser, so in a container GSync authenticates with a service account, or with a login in the mounted state directory
+        if !config.uses_service_account() && !is_logged_in(&empty_env).unwrap_or(false) {
+            crate::container::exit(Outcome::AuthenticationError, Some("GSync isn't logged in with Google. Configure a service account with GSYNC_SERVICE_ACCOUNT_KEY and GSYNC_IMPERSONATE, or mount a state directory in which `gsync login` was run"), None);
+        }
+
+        let env = Env::from_config(&config, config.drive_id.as_ref());
+        let report = crate::report::RunReport::new();
+        let result = crate::sync::sync_with_report(&config, &env, &crate::sync::SyncOptions::default(), &report);
+        let run = report.snapshot();
+        match result {
+            Err((e, _, _)) => crate::container::exit(Outcome::SyncFailed, Some(&format!("{:?}", e)), Some(run)),
+            Ok(_) if run.failures > 0 => crate::container::exit(Outcome::CompletedWithFailures, None, Some(run)),
+            Ok(_) => crate::container::exit(Outcome::Success, None, Some(run))
+        }
+    }
+
+    // 'watch' subcommand
+    if let Some(matches) = matches.subcommand_matches("watch") {
+        let config = load_logged_in_config_or_exit(&empty_env);
+
+        let debounce = match matches.value_of("debounce") {
+            Some(seconds) => match seconds.parse::<u64>() {
+                Ok(seconds) if seconds > 0 => std::time::Duration::from_secs(seconds),
+                _ => {
+                    eprintln!("Error: Debounce time '{}' is not a valid number of seconds", seconds);
+                    std::process::exit(1);
+                }
+            },
+            None => crate::watch::DEFAULT_DEBOUNCE
+        };
+
+        let env = Env::from_config(&config, config.drive_id.as_ref());
+        handle_err!(crate::watch::watch(&config, &env, debounce));
+        std::process::exit(0);
+    }
+
+    // 'status' subcommand
+    if let Some(matches) = matches.subcommand_matches("status") {
+        let config = load_complete_config_or_exit(&empty_env);
+
+        // The plan is made from the local database, so no login is needed
+        let env = Env::from_config(&config, config.drive_id.as_ref());
+        let json = matches.is_present("json");
+        let plan = handle_err!(crate::sync::plan(&config, &env, &crate::sync::SyncOptions::default(), !json));
+
+        if json {
+            println!("{}", handle_err!(plan.to_json()));
+        } else {
+            print!("\n{}", plan.listing());
+            println!("{}", plan.summary());
+
+            if plan.is_empty() {
+                println!("Everything is in sync.");
+            }
+        }
+
+        // Files which failed verification are out of sync as well, but can't be found from the local database alone
+        let findings = handle_err!(crate::scrub::findings(&env));
+        if !json {
+            if !findings.is_empty() {
+                println!("\n{} file(s) failed verification in Google Drive:", findings.len());
+                for finding in &findings {
+                    println!("  {} {} (found {})", finding.path, finding.problem, chrono::NaiveDateTime::from_timestamp(finding.time, 0).format("%Y-%m-%d %H:%M:%S UTC"));
+                }
+            }
+
+            match handle_err!(crate::scrub::oldest_verification(&env)) {
+                Some(oldest) => println!("Every synced file was verified since {}", chrono::NaiveDateTime::from_timestamp(oldest, 0).format("%Y-%m-%d %H:%M:%S UTC")),
+                None => println!("Not every synced file was verified yet. Run `gsync scrub` or `gsync daemon` to verify them")
+            }
+        }
+
+        if matches.is_present("remote-activity") && !json {
+            if !config.uses_service_account() && !handle_err!(is_logged_in(&empty_env)) {
+                eprintln!("Error: GSync isn't logged in with Google. Have you run `gsync login` yet?");
+                std::process::exit(1);
+            }
+
+            match handle_err!(crate::report::last_run(&env)) {
+                Some(run) => {
+                    let changes = handle_err!(crate::activity::remote_changes(&env, run.started));
+                    if changes.is_empty() {
+                        println!("Nothing was changed in Google Drive outside of GSync since the last sync");
+                    } else {
+                        println!("\n{} change(s) were made in Google Drive outside of GSync since the last sync:", changes.len());
+                        for change in &changes {
+                            println!("  {} {} by {} ({})", change.path, change.action, change.actor, chrono::NaiveDateTime::from_timestamp(change.time, 0).format("%Y-%m-%d %H:%M:%S UTC"));
+                        }
+                    }
+                },
+                None => println!("GSync hasn't synced yet, so there is no remote activity to show")
+            }
+        }
+
+        if plan.is_empty() && findings.is_empty() {
+            std::process::exit(0);
+        }
+
+        std::process::exit(if matches.is_present("check") { 1 } else { 0 });
+    }
+
+    if let Some(matches) = matches.subcommand_matches("drives") {
+        let config = load_logged_in_config_or_exit(&empty_env);
+
+        let env = Env::from_config(&config, config.drive_id.as_ref());
+        let shared_drives = handle_err!(crate::api::drive::blocking::get_shared_drives(&env));
+        let filter = matches.value_of("filter");
+        let shared_drives: Vec<_> = shared_drives.into_iter()
+            .filter(|drive| filter.map(|filter| drive.matches(filter)).unwrap_or(true))
+            .collect();
+
+        if shared_drives.is_empty() {
+            println!("No shared drives found");
+        }
+
+        for drive in shared_drives {
+            let capabilities = drive.capabilities.names();
+            println!("Shared drive '{}' with identifier '{}' ({})", &drive.name, &drive.id, if drive.is_writable() { "writable" } else { "read-only" });
+            println!("    Capabilities: {}", if capabilities.is_empty() { "none".to_string() } else { capabilities.join(", ") });
+        }
+
+        std::process::exit(0);
+    }
+
+    // 'restore' subcommand
+    if let Some(matches) = matches.subcommand_matches("restore") {
+        let config = load_logged_in_config_or_exit(&empty_env);
+
+        // Snapshots keep their own catalog, so neither the drive nor the root folder is needed
+        if let Some(snapshot) = matches.value_of("snapshot") {
+            let snapshot = match snapshot.parse::<i64>() {
+                Ok(snapshot) => snapshot,
+                Err(_) => {
+                    eprintln!("Error: '{}' is not a valid snapshot ID", snapshot);
+                    std::process::exit(1);
+                }
+            };
+
+            let env = Env::from_config(&config, config.drive_id.as_ref());
+            let target = std::path::PathBuf::from(matches.value_of_os("to").or_else(|| matches.value_of_os("target")).unwrap_or_else(|| std::ffi::OsStr::new(".")));
+            // Safe to unwrap because clap enforces the presence of this argument
+            let restored = handle_err!(crate::snapshot::restore(&env, snapshot, matches.value_of("remote-path").unwrap(), &target));
+            println!("Info: Restored {} files from snapshot {}", restored, snapshot);
+            std::process::exit(0);
+        }
+
+        let drive_id = match matches.value_of("drive_id") {
+            Some("root") => None,
+            Some(drive_id) => Some(drive_id.to_string()),
+            None => config.drive_id.clone()
+        };
+
+        let mut env = Env::from_config(&config, drive_id.as_ref());
+
+        println!("Info: Querying Drive for root folder");
+        env.root_folder = match handle_err!(crate::sync::find_root_folder(&env)) {
+            Some(id) => id,
+            None => {
+                eprintln!("Error: The GSync root folder does not exist in Drive. There is nothing to restore.");
+                std::process::exit(1);
+            }
+        };
+
+        // Safe to unwrap because clap enforces the presence of this argument
+        let remote_path = matches.value_of("remote-path").unwrap();
+        let target = std::path::PathBuf::from(matches.value_of_os("to").or_else(|| matches.value_of_os("target")).unwrap_or_else(|| std::ffi::OsStr::new(".")));
+        let as_of = matches.value_of("as-of").map(|as_of| handle_err!(crate::restore::parse_timestamp(as_of)));
+
+        // Safe to unwrap because clap only allows the possible values, and provides a default
+        let export_format = crate::restore::ExportFormat::parse(matches.value_of("export-format").unwrap()).unwrap();
+        let options = crate::restore::RestoreOptions {
+            as_of,
+            export_format,
+            skip_shortcuts: matches.is_present("skip-shortcuts"),
+            restore_ownership: matches.is_present("ownership")
+        };
+
+        handle_err!(crate::restore::restore(&env, remote_path, &target, &options));
+        println!("Info: Restore complete!");
+        std::process::exit(0);
+    }
+
+    // 'undelete' subcommand
+    if let Some(matches) = matches.subcommand_matches("undelete") {
+        let config = load_logged_in_config_or_exit(&empty_env);
+
+        let drive_id = match matches.value_of("drive_id") {
+            Some("root") => None,
+            Some(drive_id) => Some(drive_id.to_string()),
+            None => config.drive_id.clone()
+        };
+
+        let mut env = Env::from_config(&config, drive_id.as_ref());
+
+        println!("Info: Querying Drive for root folder");
+        env.root_folder = match handle_err!(crate::sync::find_root_folder(&env)) {
+            Some(id) => id,
+            None => {
+                eprintln!("Error: The GSync root folder does not exist in Drive. There is nothing to undelete.");
+                std::process::exit(1);
+            }
+        };
+
+        println!("Info: Querying Drive for trashed files");
+        let trashed = handle_err!(crate::undelete::list_trashed(&env));
+        let ids = matches.values_of("ids").map(|ids| ids.collect::<Vec<_>>()).unwrap_or_default();
+
+        if ids.is_empty() && !matches.is_present("all") {
+            if trashed.is_empty() {
+                println!("There are no trashed files.");
+            }
+
+            for trashed_file in &trashed {
+                println!("Trashed file '{}' with identifier '{}'", &trashed_file.remote_path, &trashed_file.file.id);
+            }
+
+            std::process::exit(0);
+        }
+
+        for id in &ids {
+            if !trashed.iter().any(|trashed_file| trashed_file.file.id.eq(id)) {
+                eprintln!("Error: There is no trashed file with identifier '{}'", id);
+                std::process::exit(1);
+            }
+        }
+
+        for trashed_file in trashed {
+            if !matches.is_present("all") && !ids.contains(&trashed_file.file.id.as_str()) {
+                continue;
+            }
+
+            println!("Info: Restoring '{}' from the trash", &trashed_file.remote_path);
+            handle_err!(crate::undelete::undelete(&env, &trashed_file.file));
+        }
+
+        println!("Info: Undelete complete!");
+        std::process::exit(0);
+    }
+
+    // 'perms' subcommand
+    if let Some(matches) = matches.subcommand_matches("perms") {
+        let config = load_logged_in_config_or_exit(&empty_env);
+
+        let drive_id = match matches.value_of("drive_id") {
+            Some("root") => None,
+            Some(drive_id) => Some(drive_id.to_string()),
+            None => config.drive_id.clone()
+        };
+
+        let mut env = Env::from_config(&config, drive_id.as_ref());
+
+        println!("Info: Querying Drive for root folder");
+        env.root_folder = match handle_err!(crate::sync::find_root_folder(&env)) {
+            Some(id) => id,
+            None => {
+                eprintln!("Error: The GSync root folder does not exist in Drive. There is nothing to inspect.");
+                std::process::exit(1);
+            }
+        };
+
+        let remote_path = matches.value_of("remote-path").unwrap_or("");
+        let file = handle_err!(crate::restore::resolve_remote_path(&env, remote_path));
+        let permissions = handle_err!(crate::api::drive::blocking::list_permissions(&env, &file.id));
+
+        let revoke = matches.values_of("revoke").map(|ids| ids.collect::<Vec<_>>()).unwrap_or_default();
+        if revoke.is_empty() && !matches.is_present("revoke-all") {
+            for permission in &permissions {
+                let inherited = if permission.is_inherited() { " (inherited)" } else { "" };
+                println!("Permission '{}': {} is {}{}", &permission.id, permission.grantee(), &permission.role, inherited);
+            }
+
+            if permissions.iter().all(|permission| permission.role.eq("owner")) {
+                println!("'{}' is not shared with anyone.", &file.name);
+            }
+
+            std::process::exit(0);
+        }
+
+        for id in &revoke {
+            if !permissions.iter().any(|permission| permission.id.eq(id)) {
+                eprintln!("Error: There is no permission with identifier '{}' on '{}'", id, &file.name);
+                std::process::exit(1);
+            }
+        }
+
+        for permission in permissions {
+            if !matches.is_present("revoke-all") && !revoke.contains(&permission.id.as_str()) {
+                continue;
+            }
+
+            if permission.role.eq("owner") {
+                if !matches.is_present("revoke-all") {
+                    println!("Warning: The owner's permission cannot be revoked. Skipping.");
+                }
+                continue;
+            }
+
+            if permission.is_inherited() {
+                println!("Warning: The permission of {} is inherited from a parent folder, revoke it there instead. Skipping.", permission.grantee());
+                continue;
+            }
+
+            println!("Info: Revoking access of {}", permission.grantee());
+            handle_err!(crate::api::drive::blocking::delete_permission(&env, &file.id, &permission.id));
+        }
+
+        println!("Info: Revoking permissions complete!");
+        std::process::exit(0);
+    }
+
+    // 'transfer-ownership' subcommand
+    if let Some(matches) = matches.subcommand_matches("transfer-ownership") {
+        let config = load_logged_in_config_or_exit(&empty_env);
+
+        // Safe to unwrap because the argument is required
+        let email = matches.value_of("to").unwrap();
+        let mut env = Env::from_config(&config, config.drive_id.as_ref());
+
+        println!("Info: Querying Drive for root folder");
+        env.root_folder = match handle_err!(crate::sync::find_root_folder(&env)) {
+            Some(id) => id,
+            None => {
+                eprintln!("Error: The GSync root folder does not exist in Drive. There is nothing to transfer.");
+                std::process::exit(1);
+            }
+        };
+
+        if !matches.is_present("yes") {
+            print!("Transfer the ownership of the GSync folder and everything in it to '{}'? This cannot be undone by you. [y/N] ", email);
+            let _ = std::io::Write::flush(&mut std::io::stdout());
+
+            let mut answer = String::new();
+            if std::io::stdin().read_line(&mut answer).is_err() || !answer.trim().eq_ignore_ascii_case("y") {
+                println!("Info: Aborted.");
+                std::process::exit(0);
+            }
+        }
+
+        handle_err!(crate::transfer::transfer_ownership(&env, email, matches.is_present("pending")));
+        std::process::exit(0);
+    }
+
+    // 'export-manifest' subcommand
+    if let Some(matches) = matches.subcommand_matches("export-manifest") {
+        // Safe to unwrap because the argument has a default value and its possible values are checked by clap
+        let format = crate::manifest::ManifestFormat::parse(matches.value_of("format").unwrap()).unwrap();
+
+        match matches.value_of_os("output") {
+            Some(output) => {
+                let file = match std::fs::File::create(output) {
+                    Ok(file) => file,
+                    Err(e) => {
+                        eprintln!("Error: Failed to create '{}': {:?}", output.to_string_lossy(), e);
+                        std::process::exit(1);
+                    }
+                };
+
+                handle_err!(crate::manifest::export(&empty_env, format, std::io::BufWriter::new(file)));
+                println!("Info: Manifest written to '{}'", output.to_string_lossy());
+            },
+            None => handle_err!(crate::manifest::export(&empty_env, format, std::io::stdout()))
+        }
+
+        std::process::exit(0);
+    }
+
+    // 'adopt' subcommand
+    if let Some(matches) = matches.subcommand_matches("adopt") {
+        let config = load_logged_in_config_or_exit(&empty_env);
+
+        // Safe to unwrap because clap enforces the presence of these arguments
+        let path = std::path::Path::new(matches.value_of_os("path").unwrap());
+        let folder = matches.value_of("folder").unwrap();
+        let path = match crate::sync::normalize_path(path) {
+            Ok(path) => path,
+            Err(e) => {
+                eprintln!("Error: Input '{}' is not a valid path: {:?}", path.to_string_lossy(), e);
+                std::process::exit(1);
+            }
+        };
+
+        let is_configured = config.input_files.as_deref().unwrap_or_default()
+            .split(',')
+            .filter_map(|input| crate::sync::normalize_path(input).ok())
+            .any(|input| input.eq(&path));
+
+        if !is_configured {
+            eprintln!("Error: '{}' is not a configured input. Add it with `gsync config -f` first.", path.to_string_lossy());
+            std::process::exit(1);
+        }
+
+        let env = Env::from_config(&config, config.drive_id.as_ref());
+        let adopted = handle_err!(crate::adopt::adopt(&config, &env, &path, folder));
+        println!("Info: Adopted {} folders and {} files, totalling {}", adopted.folders, adopted.files, crate::size::format_size(adopted.bytes));
+
+        if !adopted.differs.is_empty() {
+            println!("Differ from their copy in Drive, compared again on the next sync ({}):", adopted.differs.len());
+            for path in &adopted.differs {
+                println!("  {}", path.to_string_lossy());
+            }
+        }
+
+        if !adopted.missing.is_empty() {
+            println!("Missing in Drive, uploaded on the next sync ({}):", adopted.missing.len());
+            for path in &adopted.missing {
+                println!("  {}", path.to_string_lossy());
+            }
+        }
+
+        std::process::exit(0);
+    }
+
+    // 'exclude' subcommand
+    if let Some(matches) = matches.subcommand_matches("exclude") {
+        let config = load_config_or_exit(&empty_env);
+
+        if matches.subcommand_matches("list").is_some() {
+            println!("All inputs: {}", option_unwrap_text(config.excludes));
+            for input in handle_err!(InputConfiguration::get_all(&empty_env)) {
+                println!("Input '{}': {}", input.path, option_unwrap_text(input.excludes));
+            }
+
+            std::process::exit(0);
+        }
+
+        let (adding, matches) = match matches.subcommand() {
+            ("add", Some(matches)) => (true, matches),
+            ("remove", Some(matches)) => (false, matches),
+            _ => {
+                eprintln!("Error: Missing subcommand. Run `gsync exclude -h` for the available subcommands");
+                std::process::exit(1);
+            }
+        };
+
+        // Safe to unwrap because clap enforces the presence of this argument
+        let pattern = matches.value_of("pattern").unwrap().trim();
+        let input = match matches.value_of_os("input") {
+            Some(input) => match crate::sync::normalize_path(input) {
+                Ok(path) => Some(path),
+                Err(e) => {
+                    eprintln!("Error: Input '{}' is not a valid path: {:?}", input.to_string_lossy(), e);
+                    std::process::exit(1);
+                }
+            },
+            None => None
+        };
+
+        if let Some(input) = &input {
+            let is_configured = config.input_files.as_deref().unwrap_or_default()
+                .split(',')
+                .filter_map(|configured| crate::sync::normalize_path(configured).ok())
+                .any(|configured| configured.eq(input));
+
+            if !is_configured {
+                eprintln!("Error: '{}' is not a configured input. Add it with `gsync config -f` first.", input.to_string_lossy());
+                std::process::exit(1);
+            }
+        }
+
+        if !adding {
+            if handle_err!(crate::exclude::remove(&empty_env, input.as_deref(), pattern)) {
+                println!("Info: Removed pattern '{}'. What it excluded is synced again on the next sync", pattern);
+            } else {
+                println!("Info: Pattern '{}' was not configured", pattern);
+            }
+
+            std::process::exit(0);
+        }
+
+        if !handle_err!(crate::exclude::add(&empty_env, input.as_deref(), pattern)) {
+            println!("Info: Pattern '{}' is already configured", pattern);
+            std::process::exit(0);
+        }
+
+        println!("Info: Added pattern '{}'", pattern);
+        let excluded = handle_err!(crate::exclude::excluded_tracked(&config, &empty_env, input.as_deref(), pattern));
+        if !excluded.is_empty() {
+            println!("Tracked files and folders it excludes, deleted from Google Drive on the next sync ({}):", excluded.len());
+            for path in &excluded {
+                println!("  {}", path);
+            }
+
+            println!("Use `gsync forget <PATH>` instead to keep their copies in Google Drive");
+        }
+
+        std::process::exit(0);
+    }
+
+    // 'forget' subcommand
+    if let Some(matches) = matches.subcommand_matches("forget") {
+        let config = load_config_or_exit(&empty_env);
+
+        // Safe to unwrap because clap enforces the presence of this argument
+        let path = matches.value_of_os("path").unwrap();
+        // A path which was already removed locally can't be normalized, so it's only made absolute
+        let path = match crate::sync::normalize_path(path) {
+            Ok(path) => path,
+            Err(_) => match std::env::current_dir() {
+                Ok(dir) => dir.join(path),
+                Err(e) => {
+                    eprintln!("Error: '{}' is not a valid path: {:?}", path.to_string_lossy(), e);
+                    std::process::exit(1);
+                }
+            }
+        };
+
+        let forgotten = handle_err!(crate::forget::forget(&config, &empty_env, &path));
+        if forgotten.input {
+            println!("Info: Removed '{}' from the inputs", path.to_string_lossy());
+        }
+
+        if let Some(input) = forgotten.excluded_from {
+            println!("Info: Excluded '{}' from input '{}'", path.to_string_lossy(), input.to_string_lossy());
+        }
+
+        println!("Info: Stopped tracking {} files and folders. Their copies in Google Drive are left in place", forgotten.tracked);
+        std::process::exit(0);
+    }
+
+    // 'gc' subcommand
+    if matches.subcommand_matches("gc").is_some() {
+        let config = load_config_or_exit(&empty_env);
+
+        handle_err!(crate::gc::gc(&config, &empty_env));
+        println!("Info: Garbage collection complete!");
+        std::process::exit(0);
+    }
+
+    // 'db' subcommand
+    if let Some(matches) = matches.subcommand_matches("db") {
+        if matches.subcommand_matches("stats").is_some() {
+            handle_err!(crate::database::print_stats(&empty_env));
+            std::process::exit(0);
+        }
+
+        eprintln!("Error: Missing subcommand. Run `gsync db -h` for the available subcommands");
+        std::process::exit(1);
+    }
+
+    // 'state' subcommand
+    if let Some(matches) = matches.subcommand_matches("state") {
+        if matches.subcommand_name().is_none() {
+            eprintln!("Error: Missing subcommand. Run `gsync state -h` for the available subcommands");
+            std::process::exit(1);
+        }
+
+        let config = load_logged_in_config_or_exit(&empty_env);
+
+        let env = Env::from_config(&config, config.drive_id.as_ref());
+        let passphrase = matches.subcommand_matches("restore")
+            .and_then(|matches| matches.value_of_os("passphrase-file"))
+            .map(|path| handle_err!(crate::env::read_secret_file(std::path::Path::new(path))))
+            .or_else(|| crate::state::passphrase(&config));
+        let passphrase = match passphrase {
+            Some(passphrase) => passphrase,
+            None => {
+                eprintln!("Error: No passphrase. Configure one with `gsync config --state-passphrase-file`, or set {}", crate::state::PASSPHRASE_VAR);
+                std::process::exit(1);
+            }
+        };
+
+        if matches.subcommand_matches("backup").is_some() {
+            handle_err!(crate::state::backup(&env, &passphrase));
+            println!("Backed up the GSync state to Google Drive");
+            std::process::exit(0);
+        }
+
+        let restored = handle_err!(crate::state::restore(&env, &passphrase));
+        println!("Restored the GSync state backed up at {}{}", restored.made, restored.host.map(|host| format!(" on '{}'", host)).unwrap_or_default());
+        if let Some(previous) = restored.previous {
+            println!("The previous state was moved to '{}'", previous.to_string_lossy());
+        }
+
+        std::process::exit(0);
+    }
+
+    // 'tree' subcommand
+    if let Some(matches) = matches.subcommand_matches("tree") {
+        let config = load_logged_in_config_or_exit(&empty_env);
+
+        let depth = match matches.value_of("depth") {
+            Some(depth) => match depth.parse::<usize>() {
+                Ok(depth) => depth,
+                Err(_) => {
+                    eprintln!("Error: Depth '{}' is not a valid number", depth);
+                    std::process::exit(1);
+                }
+            },
+            None => crate::tree::DEFAULT_DEPTH
+        };
 
-        handle_err!(crate::sync::sync(&config, &env));
+        let env = Env::from_config(&config, config.drive_id.as_ref());
+        print!("{}", handle_err!(crate::tree::tree(&env, depth)));
         std::process::exit(0);
     }
 
-    if matches.subcommand_matches("drives").is_some() {
-        let config = handle_err!(Configuration::get_config(&empty_env));
+    // 'open' subcommand
+    if let Some(matches) = matches.subcommand_matches("open") {
+        // Safe to unwrap because clap enforces the presence of this argument
+        let path = matches.value_of_os("path").unwrap();
+        let path = match crate::sync::normalize_path(path) {
+            Ok(path) => path.to_string_lossy().to_string(),
+            Err(e) => {
+                eprintln!("Error: '{}' is not a valid path: {:?}", path.to_string_lossy(), e);
+                std::process::exit(1);
+            }
+        };
+
+        let file = match handle_err!(crate::files::get_by_path(&empty_env, &path)) {
+            Some(file) => file,
+            None => {
+                eprintln!("Error: '{}' has not been synced by GSync", &path);
+                std::process::exit(1);
+            }
+        };
 
-        if config.is_empty() {
-            println!("GSync is unconfigured. Run 'gsync config -h` for more information on how to configure GSync'");
+        let url = crate::open::web_url(&file);
+        if matches.is_present("print") {
+            println!("{}", url);
             std::process::exit(0);
         }
 
-        match config.is_complete() {
-            (true, _) => {},
-            (false, str) => {
-                eprintln!("Error: Configuration is incomplete; {}", str);
+        if let Err((e, _, _)) = crate::open::open_in_browser(&url) {
+            println!("Warning: Failed to open the browser: {:?}", e);
+            println!("{}", url);
+        }
+
+        std::process::exit(0);
+    }
+
+    // 'mount' subcommand
+    if let Some(matches) = matches.subcommand_matches("mount") {
+        #[cfg(not(all(unix, feature = "fuse")))]
+        {
+            let _ = matches;
+            eprintln!("Error: This build of GSync does not support mounting. Rebuild GSync with `--features fuse` on a system with FUSE");
+            std::process::exit(1);
+        }
+
+        #[cfg(all(unix, feature = "fuse"))]
+        {
+            let config = load_logged_in_config_or_exit(&empty_env);
+
+            let drive_id = match matches.value_of("drive_id") {
+                Some("root") => None,
+                Some(drive_id) => Some(drive_id.to_string()),
+                None => config.drive_id.clone()
+            };
+
+            let mut env = Env::from_config(&config, drive_id.as_ref());
+
+            println!("Info: Querying Drive for root folder");
+            env.root_folder = match handle_err!(crate::sync::find_root_folder(&env)) {
+                Some(id) => id,
+                None => {
+                    eprintln!("Error: The GSync root folder does not exist in Drive. There is nothing to mount.");
+                    std::process::exit(1);
+                }
+            };
+
+            // Safe to unwrap because clap enforces the presence of this argument
+            handle_err!(crate::mount::mount(&env, matches.value_of_os("mountpoint").unwrap()));
+            std::process::exit(0);
+        }
+    }
+
+    // 'serve' subcommand
+    if let Some(matches) = matches.subcommand_matches("serve") {
+        let config = load_logged_in_config_or_exit(&empty_env);
+
+        let port = match matches.value_of("port") {
+            Some(port) => match port.parse::<u16>() {
+                Ok(port) => port,
+                Err(_) => {
+                    eprintln!("Error: Port '{}' is not a valid port", port);
+                    std::process::exit(1);
+                }
+            },
+            None => crate::serve::DEFAULT_PORT
+        };
+
+        let drive_id = match matches.value_of("drive_id") {
+            Some("root") => None,
+            Some(drive_id) => Some(drive_id.to_string()),
+            None => config.drive_id.clone()
+        };
+
+        let mut env = Env::from_config(&config, drive_id.as_ref());
+
+        println!("Info: Querying Drive for root folder");
+        env.root_folder = match handle_err!(crate::sync::find_root_folder(&env)) {
+            Some(id) => id,
+            None => {
+                eprintln!("Error: The GSync root folder does not exist in Drive. There is nothing to serve.");
+                std::process::exit(1);
+            }
+        };
+
+        // Safe to unwrap because the argument has a default value
+        handle_err!(crate::serve::serve(&env, matches.value_of("bind").unwrap(), port));
+        std::process::exit(0);
+    }
+
+    // 'daemon' subcommand
+    if let Some(matches) = matches.subcommand_matches("daemon") {
+        let users = match matches.values_of("user") {
+            Some(names) => names.map(|name| handle_err!(crate::daemon::users::ServiceUser::lookup(name))).collect::<Vec<_>>(),
+            None => Vec::new()
+        };
+
+        // In the multi-user mode every user has their own configuration, which is checked when syncing for them
+        if users.is_empty() {
+            load_logged_in_config_or_exit(&empty_env);
+        }
+
+        let interval = match matches.value_of("interval") {
+            Some(interval) => match interval.parse::<u64>() {
+                Ok(minutes) if minutes > 0 => std::time::Duration::from_secs(minutes * 60),
+                _ => {
+                    eprintln!("Error: Interval '{}' is not a valid number of minutes", interval);
+                    std::process::exit(1);
+                }
+            },
+            None => crate::daemon::DEFAULT_INTERVAL
+        };
+
+        let dashboard = match matches.value_of("dashboard") {
+            Some(port) => match port.parse::<u16>() {
+                // Safe to unwrap because the argument has a default value
+                Ok(port) => Some((matches.value_of("dashboard-bind").unwrap().to_string(), port)),
+                Err(_) => {
+                    eprintln!("Error: Port '{}' is not a valid port", port);
+                    std::process::exit(1);
+                }
+            },
+            None => None
+        };
+
+        let api = match matches.value_of("api") {
+            Some(port) => {
+                let port = match port.parse::<u16>() {
+                    Ok(port) => port,
+                    Err(_) => {
+                        eprintln!("Error: Port '{}' is not a valid port", port);
+                        std::process::exit(1);
+                    }
+                };
+
+                let token = match matches.value_of_os("api-token-file") {
+                    Some(path) => Some(handle_err!(crate::env::read_secret_file(std::path::Path::new(path)))),
+                    None => std::env::var("GSYNC_API_TOKEN").ok()
+                };
+
+                let token = match token {
+                    Some(token) if !token.is_empty() => token,
+                    _ => {
+                        eprintln!("Error: The HTTP API requires a token. Set one with --api-token-file or the GSYNC_API_TOKEN environment variable");
+                        std::process::exit(1);
+                    }
+                };
+
+                Some(crate::daemon::ApiOptions {
+                    // Safe to unwrap because the argument has a default value
+                    bind: matches.value_of("api-bind").unwrap().to_string(),
+                    port,
+                    token
+                })
+            },
+            None => None
+        };
+
+        let jitter = match matches.value_of("jitter") {
+            Some(jitter) => match jitter.parse::<u64>() {
+                Ok(minutes) => std::time::Duration::from_secs(minutes * 60),
+                Err(_) => {
+                    eprintln!("Error: Jitter '{}' is not a valid number of minutes", jitter);
+                    std::process::exit(1);
+                }
+            },
+            None => std::time::Duration::from_secs(0)
+        };
+
+        let parallel = match matches.value_of("parallel") {
+            Some(parallel) => match parallel.parse::<usize>() {
+                Ok(parallel) if parallel > 0 => parallel,
+                _ => {
+                    eprintln!("Error: '{}' is not a valid number of users", parallel);
+                    std::process::exit(1);
+                }
+            },
+            None => crate::daemon::schedule::DEFAULT_PARALLEL
+        };
+
+        let heavy = matches.values_of("heavy").map(|names| names.map(String::from).collect::<Vec<_>>()).unwrap_or_default();
+        if let Some(name) = heavy.iter().find(|name| !users.iter().any(|user| &user.name == *name)) {
+            eprintln!("Error: '{}' is given with --heavy, but not with --user", name);
+            std::process::exit(1);
+        }
+
+        let drill = match matches.value_of("drill") {
+            Some(days) => match days.parse::<u64>() {
+                Ok(days) if days > 0 => Some(std::time::Duration::from_secs(days * 24 * 60 * 60)),
+                _ => {
+                    eprintln!("Error: Drill interval '{}' is not a valid number of days", days);
+                    std::process::exit(1);
+                }
+            },
+            None => None
+        };
+
+        let drill_sample = match matches.value_of("drill-sample") {
+            Some(sample) => match sample.parse::<usize>() {
+                Ok(sample) if sample > 0 => sample,
+                _ => {
+                    eprintln!("Error: '{}' is not a valid number of files", sample);
+                    std::process::exit(1);
+                }
+            },
+            None => crate::drill::DEFAULT_SAMPLE
+        };
+
+        let options = crate::daemon::DaemonOptions { interval, dashboard, api, users, jitter, parallel, heavy, drill, drill_sample };
+        handle_err!(crate::daemon::run(&empty_env, &options));
+        std::process::exit(0);
+    }
+
+    // 'audit' subcommand
+    if let Some(matches) = matches.subcommand_matches("audit") {
+        if matches.is_present("remote") {
+            let config = load_logged_in_config_or_exit(&empty_env);
+
+            // Safe to unwrap because the argument has a default value
+            let days = match matches.value_of("since").unwrap().parse::<u32>() {
+                Ok(days) => days,
+                Err(_) => {
+                    eprintln!("Error: '{}' is not a valid number of days", matches.value_of("since").unwrap());
+                    std::process::exit(1);
+                }
+            };
+
+            let env = Env::from_config(&config, config.drive_id.as_ref());
+            let since = chrono::Utc::now().timestamp() - i64::from(days) * 24 * 60 * 60;
+            let changes = handle_err!(crate::activity::remote_changes(&env, since));
+            if changes.is_empty() {
+                println!("Nothing was changed in Google Drive outside of GSync in the last {} day(s)", days);
+            } else {
+                println!("Time\tAction\tBy\tPath\tFile ID");
+                for change in changes.iter().rev() {
+                    println!("{}", crate::activity::format_line(change));
+                }
+            }
+
+            std::process::exit(0);
+        }
+
+        // Safe to unwrap because the argument has a default value
+        let limit = match matches.value_of("limit").unwrap().parse::<u32>() {
+            Ok(limit) => limit,
+            Err(_) => {
+                eprintln!("Error: Limit '{}' is not a valid number", matches.value_of("limit").unwrap());
                 std::process::exit(1);
             }
+        };
+
+        let operations = handle_err!(crate::audit::recent_operations(&empty_env, limit));
+        if operations.is_empty() {
+            println!("No operations have been recorded yet");
+        } else {
+            println!("Time\tRun\tOperation\tPath\tFile ID\tResult");
+            for operation in operations.iter().rev() {
+                println!("{}", crate::audit::format_line(operation));
+            }
+        }
+
+        std::process::exit(0);
+    }
+
+    // 'undo' subcommand
+    if let Some(matches) = matches.subcommand_matches("undo") {
+        let config = load_complete_config_or_exit(&empty_env);
+
+        let plan = match handle_err!(crate::undo::plan(&empty_env)) {
+            Some(plan) => plan,
+            None => {
+                println!("No sync has changed anything in Google Drive yet. There is nothing to undo.");
+                std::process::exit(0);
+            }
+        };
+
+        println!("Undoing the sync started at {}:", plan.run_started());
+        for action in &plan.actions {
+            println!("  {}", action);
         }
 
-        if !handle_err!(is_logged_in(&empty_env)) {
+        if matches.is_present("dry-run") {
+            std::process::exit(0);
+        }
+
+        if !config.uses_service_account() && !handle_err!(is_logged_in(&empty_env)) {
             eprintln!("Error: GSync isn't logged in with Google. Have you run `gsync login` yet?");
             std::process::exit(1);
         }
 
-        let env = Env::new(config.client_id.as_ref().unwrap(), config.client_secret.as_ref().unwrap(), config.drive_id.as_ref(), String::new());
-        let shared_drives = handle_err!(crate::api::drive::get_shared_drives(&env));
-        for drive in shared_drives {
-            println!("Shared drive '{}' with identifier '{}'", &drive.name, &drive.id);
+        if !matches.is_present("yes") {
+            print!("Undo these changes? [y/N] ");
+            let _ = std::io::Write::flush(&mut std::io::stdout());
+
+            let mut answer = String::new();
+            if std::io::stdin().read_line(&mut answer).is_err() || !answer.trim().eq_ignore_ascii_case("y") {
+                println!("Info: Aborted.");
+                std::process::exit(0);
+            }
+        }
+
+        let env = Env::from_config(&config, config.drive_id.as_ref());
+        let failures = handle_err!(crate::undo::undo(&env, &plan));
+        if failures > 0 {
+            eprintln!("Error: {} change(s) could not be undone. Run 'gsync undo' again to retry them", failures);
+            std::process::exit(1);
+        }
+
+        std::process::exit(0);
+    }
+
+    // 'snapshot' subcommand
+    if let Some(matches) = matches.subcommand_matches("snapshot") {
+        let config = load_logged_in_config_or_exit(&empty_env);
+
+        let mut env = Env::from_config(&config, config.drive_id.as_ref());
+        env.root_folder = handle_err!(crate::sync::get_or_create_root_folder(&env));
+
+        handle_err!(crate::snapshot::create(&config, &env, matches.is_present("full")));
+        std::process::exit(0);
+    }
+
+    // 'snapshots' subcommand
+    if let Some(matches) = matches.subcommand_matches("snapshots") {
+        if let Some(id) = matches.value_of("verify") {
+            let id = match id.parse::<i64>() {
+                Ok(id) => id,
+                Err(_) => {
+                    eprintln!("Error: '{}' is not a valid snapshot ID", id);
+                    std::process::exit(1);
+                }
+            };
+
+            let config = handle_err!(Configuration::get_config(&empty_env));
+            if !config.uses_service_account() && !handle_err!(is_logged_in(&empty_env)) {
+                eprintln!("Error: GSync isn't logged in with Google. Have you run `gsync login` yet?");
+                std::process::exit(1);
+            }
+
+            let env = Env::from_config(&config, config.drive_id.as_ref());
+            let damaged = handle_err!(crate::snapshot::verify(&env, id));
+            if !damaged.is_empty() {
+                eprintln!("Error: {} file(s) of snapshot {} are missing or damaged", damaged.len(), id);
+                std::process::exit(1);
+            }
+
+            println!("Snapshot {} is intact", id);
+            std::process::exit(0);
+        }
+
+        let snapshots = handle_err!(crate::snapshot::list(&empty_env));
+        if snapshots.is_empty() {
+            println!("No snapshots have been taken yet. Run `gsync snapshot` to take one");
+            std::process::exit(0);
+        }
+
+        println!("{:>5}  {:<23}  {:<11}  {:>5}  {:>5}  {:>8}  {:>10}  {:>10}  Verification", "ID", "Taken", "Kind", "Base", "Chain", "Files", "Size", "Uploaded");
+        for snapshot in snapshots {
+            println!("{:>5}  {:<23}  {:<11}  {:>5}  {:>5}  {:>8}  {:>10}  {:>10}  {}",
+                snapshot.id,
+                snapshot.created_at(),
+                snapshot.kind,
+                snapshot.based_on(),
+                snapshot.chain,
+                snapshot.files,
+                crate::size::format_size(snapshot.bytes as u64),
+                crate::size::format_size(snapshot.uploaded as u64),
+                snapshot.verification());
+        }
+
+        std::process::exit(0);
+    }
+
+    // 'scrub' subcommand
+    if let Some(matches) = matches.subcommand_matches("scrub") {
+        // Nothing was synced yet, so there is nothing to verify, e.g. for a user of the multi-user daemon who hasn't configured GSync
+        if matches.is_present("json") && handle_err!(Configuration::get_config(&empty_env)).is_empty() {
+            println!("[]");
+            std::process::exit(0);
+        }
+
+        let config = load_logged_in_config_or_exit(&empty_env);
+
+        let env = Env::from_config(&config, config.drive_id.as_ref());
+        let json = matches.is_present("json");
+        let count = match (matches.value_of("count"), matches.value_of("every")) {
+            (Some(count), _) => match count.parse::<usize>() {
+                Ok(count) => Some(count),
+                Err(_) => {
+                    eprintln!("Error: '{}' is not a valid number of files", count);
+                    std::process::exit(1);
+                }
+            },
+            (None, Some(every)) => match every.parse::<u64>() {
+                Ok(every) => handle_err!(crate::scrub::due(&env, config.scrub_window, std::time::Duration::from_secs(every))),
+                Err(_) => {
+                    eprintln!("Error: '{}' is not a valid number of seconds", every);
+                    std::process::exit(1);
+                }
+            },
+            (None, None) => Some(handle_err!(crate::scrub::count(&env)))
+        };
+
+        // Scrubbing is disabled, so nothing was verified and nothing is reported
+        let count = match count {
+            Some(count) => count,
+            None => {
+                if json {
+                    println!("[]");
+                }
+
+                std::process::exit(0);
+            }
+        };
+
+        let result = handle_err!(crate::scrub::scrub(&env, count));
+        for finding in &result.findings {
+            eprintln!("Warning: '{}' {} in Google Drive", finding.path, finding.problem);
+        }
+
+        // With --json stdout only holds the findings, e.g. for the daemon reading them from a child process
+        if json {
+            eprintln!("Verified {} files, {} failed verification", result.checked, result.findings.len());
+            let findings = handle_err!(crate::scrub::findings(&env));
+            println!("{}", serde_json::to_string(&findings).unwrap_or_default());
+            std::process::exit(0);
+        }
+
+        println!("Verified {} files, {} failed verification", result.checked, result.findings.len());
+        std::process::exit(if result.findings.is_empty() { 0 } else { 1 });
+    }
+
+    // 'history' subcommand
+    if let Some(matches) = matches.subcommand_matches("history") {
+        let limit = match matches.value_of("limit") {
+            Some(limit) => match limit.parse::<u32>() {
+                Ok(limit) => limit,
+                Err(_) => {
+                    eprintln!("Error: '{}' is not a valid number of syncs", limit);
+                    std::process::exit(1);
+                }
+            },
+            None => 10
+        };
+
+        let runs = handle_err!(crate::report::recent_runs(&empty_env, limit));
+        if matches.is_present("json") {
+            println!("{}", serde_json::to_string(&runs).unwrap_or_default());
+            std::process::exit(0);
+        }
+
+        if runs.is_empty() {
+            println!("GSync hasn't synced yet");
+        }
+
+        for run in &runs {
+            println!("{}  {} s  {}  {} new, {} changed, {} up-to-date, {} uploaded, {} failure(s)",
+                chrono::NaiveDateTime::from_timestamp(run.started, 0).format("%Y-%m-%d %H:%M:%S UTC"),
+                run.finished - run.started,
+                if run.success { "Success" } else { "Failed" },
+                run.uploaded,
+                run.updated,
+                run.unchanged,
+                crate::size::format_size(run.bytes as u64),
+                run.failures);
+        }
+
+        std::process::exit(0);
+    }
+
+    // 'verify' subcommand
+    if let Some(matches) = matches.subcommand_matches("verify") {
+        let config = load_logged_in_config_or_exit(&empty_env);
+
+        let env = Env::from_config(&config, config.drive_id.as_ref());
+        let within = matches.value_of_os("path").map(crate::sync::absolute_path);
+        let result = handle_err!(crate::verify::verify(&env, within.as_deref()));
+        for mismatch in &result.mismatches {
+            eprintln!("Warning: '{}' {}", mismatch.path, mismatch.problem);
+        }
+
+        println!("Verified {} files, {} failed verification, {} could not be verified because Drive has no checksum for them", result.checked, result.mismatches.len(), result.skipped);
+        std::process::exit(if result.mismatches.is_empty() { 0 } else { 1 });
+    }
+
+    // 'drill' subcommand
+    if let Some(matches) = matches.subcommand_matches("drill") {
+        let config = load_logged_in_config_or_exit(&empty_env);
+
+        let env = Env::from_config(&config, config.drive_id.as_ref());
+        let sample = match matches.value_of("sample") {
+            Some(sample) => match sample.parse::<usize>() {
+                Ok(sample) if sample > 0 => sample,
+                _ => {
+                    eprintln!("Error: '{}' is not a valid number of files", sample);
+                    std::process::exit(1);
+                }
+            },
+            None => crate::drill::DEFAULT_SAMPLE
+        };
+
+        let result = handle_err!(crate::drill::drill(&env, sample));
+        for (path, problem) in &result.failures {
+            eprintln!("Warning: '{}' {}", path, problem);
+        }
+
+        println!("Restored and verified {} files ({}), {} failed, {} skipped", result.checked, crate::size::format_size(result.bytes), result.failures.len(), result.skipped);
+        std::process::exit(if result.failures.is_empty() { 0 } else { 1 });
+    }
+
+    // 'stats' subcommand
+    if let Some(matches) = matches.subcommand_matches("stats") {
+        let months = handle_err!(crate::stats::monthly(&empty_env));
+        if months.is_empty() {
+            println!("No runs have been recorded yet. Run `gsync sync` first");
+            std::process::exit(0);
+        }
+
+        println!("{:<8}  {:>5}  {:>7}  {:>8}  {:>10}  {:>8}  {:>10}  {:>10}", "Month", "Runs", "Failed", "Files", "Uploaded", "Failures", "Tracked", "Growth");
+        for (month, (_, growth)) in months.iter().zip(crate::stats::growth(&months)) {
+            println!("{:<8}  {:>5}  {:>6.1}%  {:>8}  {:>10}  {:>8}  {:>10}  {:>10}",
+                month.month,
+                month.runs,
+                month.failed_run_rate(),
+                month.uploaded + month.updated,
+                crate::size::format_size(month.bytes as u64),
+                month.failures,
+                month.tracked_bytes.map(|bytes| crate::size::format_size(bytes as u64)).unwrap_or_else(|| "-".to_string()),
+                growth.map(|growth| format!("{}{}", if growth < 0 { "-" } else { "+" }, crate::size::format_size(growth.unsigned_abs()))).unwrap_or_else(|| "-".to_string()));
+        }
+
+        if !matches.is_present("projection") {
+            std::process::exit(0);
+        }
+
+        let config = load_logged_in_config_or_exit(&empty_env);
+
+        let env = Env::from_config(&config, config.drive_id.as_ref());
+        let quota = handle_err!(crate::api::drive::blocking::get_storage_quota(&env));
+        let limit = match quota.limit() {
+            Some(limit) => limit,
+            None => {
+                println!("\nThe storage of this account is unlimited");
+                std::process::exit(0);
+            }
+        };
+
+        let free = limit.saturating_sub(quota.usage());
+        println!("\nStorage used: {} of {}, {} free", crate::size::format_size(quota.usage()), crate::size::format_size(limit), crate::size::format_size(free));
+
+        let rate = match crate::stats::growth_rate(&months) {
+            Some(rate) => rate,
+            None => {
+                println!("Not enough history to project the growth yet, it takes two months with runs");
+                std::process::exit(0);
+            }
+        };
+
+        match crate::stats::months_until_full(free, rate) {
+            Some(months_left) => {
+                // Months are 30.44 days on average
+                let full_at = chrono::Utc::now().checked_add_signed(chrono::Duration::days((months_left * 30.44).min(365.0 * 1000.0) as i64));
+                println!("At the current growth of {} per month, the storage is full in {:.1} months, around {}",
+                    crate::size::format_size(rate as u64),
+                    months_left,
+                    full_at.map(|full_at| full_at.format("%Y-%m-%d").to_string()).unwrap_or_else(|| "the far future".to_string()));
+            },
+            None => println!("The synced data is not growing, so the storage won't run out at the current rate")
+        }
+
+        std::process::exit(0);
+    }
+
+    // 'input' subcommand
+    if let Some(matches) = matches.subcommand_matches("input") {
+        let config = load_config_or_exit(&empty_env);
+
+        let path = match matches.value_of_os("path") {
+            Some(path) => path,
+            None => {
+                for input in handle_err!(InputConfiguration::get_all(&empty_env)) {
+                    print_input(input);
+                }
+
+                std::process::exit(0);
+            }
+        };
+
+        let path = match crate::sync::normalize_path(path) {
+            Ok(path) => path.to_string_lossy().to_string(),
+            Err(e) => {
+                eprintln!("Error: Input '{}' is not a valid path: {:?}", path.to_string_lossy(), e);
+                std::process::exit(1);
+            }
+        };
+
+        let is_configured = config.input_files.as_deref().unwrap_or_default()
+            .split(',')
+            .filter_map(|input| crate::sync::normalize_path(input).ok())
+            .any(|input| input.to_string_lossy() == path);
+
+        if !is_configured {
+            eprintln!("Error: '{}' is not a configured input. Add it with `gsync config -f` first.", &path);
+            std::process::exit(1);
+        }
+
+        let mut input = handle_err!(InputConfiguration::get(&empty_env, &path));
+        if let Some(drive_id) = matches.value_of("drive_id") {
+            input.drive_id = Some(drive_id.to_string());
+        }
+
+        if let Some(remote_root) = matches.value_of("remote-root") {
+            if let Err(placeholder) = crate::template::validate(remote_root) {
+                eprintln!("Error: '{}' is not a known placeholder. Use one of {}", placeholder, crate::template::PLACEHOLDERS.iter().map(|name| format!("{{{}}}", name)).collect::<Vec<_>>().join(", "));
+                std::process::exit(1);
+            }
+
+            input.remote_root = Some(remote_root.trim_matches('/').to_string());
+            input.remote_root_id = None;
+        }
+
+        if let Some(remote_root_id) = matches.value_of("remote-root-id") {
+            input.remote_root_id = Some(remote_root_id.to_string());
+            input.remote_root = None;
+        }
+
+        if let Some(excludes) = matches.value_of("excludes") {
+            input.excludes = Some(excludes.to_string()).filter(|excludes| !excludes.trim().is_empty());
+        }
+
+        if let Some(gitignore) = matches.value_of("gitignore") {
+            input.gitignore = Some(gitignore.eq("true"));
+        }
+
+        if let Some(max_size) = matches.value_of("max-size") {
+            input.max_size = match crate::size::parse_size(max_size) {
+                Some(0) => None,
+                Some(max_size) => Some(max_size as i64),
+                None => {
+                    eprintln!("Error: '{}' is not a valid size", max_size);
+                    std::process::exit(1);
+                }
+            };
+        }
+
+        if let Some(compare) = matches.value_of("compare") {
+            input.compare = Some(compare.to_string()).filter(|compare| compare.ne("default"));
+        }
+
+        if let Some(symlinks) = matches.value_of("symlinks") {
+            input.symlinks = Some(symlinks.to_string()).filter(|symlinks| symlinks.ne("default"));
+        }
+
+        if let Some(kind) = matches.value_of("fs-snapshot") {
+            input.fs_snapshot = Some(kind.to_string()).filter(|kind| kind.ne("none"));
         }
 
+        handle_err!(input.write(&empty_env));
+        print_input(input);
         std::process::exit(0);
     }
 
     println!("No command specified. Run 'gsync -h' for available commands.");
 }
 
+/// Get the configuration, exiting if GSync is unconfigured
+fn load_config_or_exit(env: &Env) -> Configuration {
+    let config = handle_err!(Configuration::get_config(env));
+
+    if config.is_empty() {
+        println!("GSync is unconfigured. Run 'gsync config -h` for more information on how to configure GSync'");
+        std::process::exit(0);
+    }
+
+    config
+}
+
+/// Get the configuration, exiting if GSync is unconfigured or its configuration is incomplete
+fn load_complete_config_or_exit(env: &Env) -> Configuration {
+    let config = load_config_or_exit(env);
+
+    if let (false, str) = config.is_complete() {
+        eprintln!("Error: Configuration is incomplete; {}", str);
+        std::process::exit(1);
+    }
+
+    config
+}
+
+/// Get the configuration, exiting if it is incomplete or if GSync is neither logged in nor using a service account
+fn load_logged_in_config_or_exit(env: &Env) -> Configuration {
+    let config = load_complete_config_or_exit(env);
+
+    if !config.uses_service_account() && !handle_err!(is_logged_in(env)) {
+        eprintln!("Error: GSync isn't logged in with Google. Have you run `gsync login` yet?");
+        std::process::exit(1);
+    }
+
+    config
+}
+
+/// Print the configuration of an input
+fn print_input(input: InputConfiguration) {
+    let remote_root = match input.remote_root() {
+        RemoteRoot::Default => "Default".to_string(),
+        RemoteRoot::Path(path) => path,
+        RemoteRoot::Id(id) => format!("Folder ID {}", id)
+    };
+
+    println!("Input '{}': Drive ID: {}, Remote root: {}, Excludes: {}, Respect .gitignore: {}, Max size: {}, Compare: {}, Symlinks: {}, Filesystem snapshot: {}",
+        &input.path,
+        option_unwrap_text(input.drive_id),
+        remote_root,
+        option_unwrap_text(input.excludes),
+        input.gitignore.unwrap_or(true),
+        option_unwrap_text(input.max_size.map(|max_size| crate::size::format_size(max_size as u64))),
+        option_unwrap_text(input.compare),
+        option_unwrap_text(input.symlinks),
+        option_unwrap_text(input.fs_snapshot));
+}
+
 /// Convert a Option<&str> to an Option<String>
 fn option_str_string(i: Option<&str>) -> Option<String> {
     i.map(|i| i.to_string())
 }
 
+/// Convert a Option<&str> to an Option<bool>, where only `true` is considered true
+fn option_str_bool(i: Option<&str>) -> Option<bool> {
+    i.map(|i| i.eq("true"))
+}
+
 /// Unwrap an Option<String> to a String. If the input is None, you'll get back the literal `None`
 fn option_unwrap_text(i: Option<String>) -> String {
     match i {
@@ -285,6 +2965,100 @@ fn option_unwrap_text(i: Option<String>) -> String {
     }
 }
 
+/// Create the tables in the database if they don't exist yet, and add the columns added since they were created
+///
+/// # Errors
+/// - When a database operation fails
+fn migrate(env: &Env) -> Result<()> {
+    let conn = unwrap_db_err!(env.get_conn());
+    unwrap_db_err!(conn.execute("CREATE TABLE IF NOT EXISTS user (id TEXT PRIMARY KEY, refresh_token TEXT, access_token TEXT, expiry INTEGER)", rusqlite::named_params! {}));
+    unwrap_db_err!(conn.execute("CREATE TABLE IF NOT EXISTS config (client_id TEXT, client_secret TEXT, input_files TEXT, drive_id TEXT)", rusqlite::named_params! {}));
+    unwrap_db_err!(add_column_if_missing(&conn, "config", "quota_threshold", "INTEGER"));
+    unwrap_db_err!(add_column_if_missing(&conn, "config", "quota_action", "TEXT"));
+    unwrap_db_err!(add_column_if_missing(&conn, "config", "preserve_xattrs", "INTEGER"));
+    unwrap_db_err!(add_column_if_missing(&conn, "config", "max_jobs", "INTEGER"));
+    unwrap_db_err!(add_column_if_missing(&conn, "config", "service_account_key", "TEXT"));
+    unwrap_db_err!(add_column_if_missing(&conn, "config", "impersonate", "TEXT"));
+    unwrap_db_err!(add_column_if_missing(&conn, "config", "labels", "TEXT"));
+    unwrap_db_err!(add_column_if_missing(&conn, "config", "upload_reports", "INTEGER"));
+    unwrap_db_err!(add_column_if_missing(&conn, "config", "history_retention", "INTEGER"));
+    unwrap_db_err!(add_column_if_missing(&conn, "config", "delete_limit", "TEXT"));
+    unwrap_db_err!(add_column_if_missing(&conn, "config", "target_folder", "TEXT"));
+    unwrap_db_err!(add_column_if_missing(&conn, "config", "audit_log", "TEXT"));
+    unwrap_db_err!(add_column_if_missing(&conn, "config", "snapshot_full_every", "INTEGER"));
+    unwrap_db_err!(add_column_if_missing(&conn, "config", "scrub_window", "INTEGER"));
+    unwrap_db_err!(add_column_if_missing(&conn, "config", "compare", "TEXT"));
+    unwrap_db_err!(add_column_if_missing(&conn, "config", "symlinks", "TEXT"));
+    unwrap_db_err!(add_column_if_missing(&conn, "config", "mtime_tolerance", "INTEGER"));
+    unwrap_db_err!(add_column_if_missing(&conn, "config", "hash_algorithm", "TEXT"));
+    unwrap_db_err!(add_column_if_missing(&conn, "config", "state_passphrase", "TEXT"));
+    unwrap_db_err!(add_column_if_missing(&conn, "config", "excludes", "TEXT"));
+    unwrap_db_err!(add_column_if_missing(&conn, "config", "mime_types", "TEXT"));
+    unwrap_db_err!(add_column_if_missing(&conn, "config", "convert", "INTEGER"));
+    unwrap_db_err!(add_column_if_missing(&conn, "config", "delete_policy", "TEXT"));
+    unwrap_db_err!(add_column_if_missing(&conn, "config", "max_retries", "INTEGER"));
+    unwrap_db_err!(add_column_if_missing(&conn, "config", "bwlimit", "TEXT"));
+    unwrap_db_err!(add_column_if_missing(&conn, "config", "max_size", "INTEGER"));
+    unwrap_db_err!(add_column_if_missing(&conn, "config", "pack_sparse", "INTEGER"));
+    unwrap_db_err!(conn.execute("CREATE TABLE IF NOT EXISTS inputs (path TEXT PRIMARY KEY, drive_id TEXT)", rusqlite::named_params! {}));
+    unwrap_db_err!(add_column_if_missing(&conn, "inputs", "remote_root", "TEXT"));
+    unwrap_db_err!(add_column_if_missing(&conn, "inputs", "remote_root_id", "TEXT"));
+    unwrap_db_err!(add_column_if_missing(&conn, "inputs", "excludes", "TEXT"));
+    unwrap_db_err!(add_column_if_missing(&conn, "inputs", "gitignore", "INTEGER"));
+    unwrap_db_err!(add_column_if_missing(&conn, "inputs", "max_size", "INTEGER"));
+    unwrap_db_err!(add_column_if_missing(&conn, "inputs", "compare", "TEXT"));
+    unwrap_db_err!(add_column_if_missing(&conn, "inputs", "symlinks", "TEXT"));
+    unwrap_db_err!(add_column_if_missing(&conn, "inputs", "fs_snapshot", "TEXT"));
+    unwrap_db_err!(conn.execute("CREATE TABLE IF NOT EXISTS files (path TEXT PRIMARY KEY, id TEXT NOT NULL, parent_id TEXT NOT NULL, is_folder INTEGER NOT NULL)", rusqlite::named_params! {}));
+    unwrap_db_err!(add_column_if_missing(&conn, "files", "link_target", "TEXT"));
+    unwrap_db_err!(add_column_if_missing(&conn, "files", "size", "INTEGER"));
+    unwrap_db_err!(add_column_if_missing(&conn, "files", "md5", "TEXT"));
+    unwrap_db_err!(add_column_if_missing(&conn, "files", "synced_at", "INTEGER"));
+    unwrap_db_err!(add_column_if_missing(&conn, "files", "scrubbed", "INTEGER"));
+    unwrap_db_err!(add_column_if_missing(&conn, "files", "hash", "TEXT"));
+    unwrap_db_err!(add_column_if_missing(&conn, "files", "hash_algorithm", "TEXT"));
+    unwrap_db_err!(conn.execute("CREATE TABLE IF NOT EXISTS scrub_findings (path TEXT PRIMARY KEY, time INTEGER NOT NULL, file_id TEXT NOT NULL, problem TEXT NOT NULL)", rusqlite::named_params! {}));
+    unwrap_db_err!(conn.execute("CREATE TABLE IF NOT EXISTS operations (id INTEGER PRIMARY KEY AUTOINCREMENT, time INTEGER NOT NULL, run INTEGER, operation TEXT NOT NULL, path TEXT, file_id TEXT, result TEXT NOT NULL)", rusqlite::named_params! {}));
+    unwrap_db_err!(add_column_if_missing(&conn, "operations", "revision_id", "TEXT"));
+    // The audit log is append-only
+    unwrap_db_err!(conn.execute("CREATE TRIGGER IF NOT EXISTS operations_no_update BEFORE UPDATE ON operations BEGIN SELECT RAISE(ABORT, 'the audit log is append-only'); END", rusqlite::named_params! {}));
+    unwrap_db_err!(conn.execute("CREATE TRIGGER IF NOT EXISTS operations_no_delete BEFORE DELETE ON operations BEGIN SELECT RAISE(ABORT, 'the audit log is append-only'); END", rusqlite::named_params! {}));
+    unwrap_db_err!(conn.execute("CREATE TABLE IF NOT EXISTS undone_runs (run INTEGER PRIMARY KEY, undone INTEGER NOT NULL)", rusqlite::named_params! {}));
+    unwrap_db_err!(conn.execute("CREATE TABLE IF NOT EXISTS snapshots (id INTEGER PRIMARY KEY AUTOINCREMENT, created INTEGER NOT NULL, kind TEXT NOT NULL, base INTEGER, chain INTEGER, folder_id TEXT NOT NULL, files INTEGER NOT NULL, bytes INTEGER NOT NULL, uploaded INTEGER NOT NULL, verified INTEGER)", rusqlite::named_params! {}));
+    unwrap_db_err!(add_column_if_missing(&conn, "snapshots", "verify_failures", "INTEGER"));
+    unwrap_db_err!(add_column_if_missing(&conn, "snapshots", "failures", "INTEGER"));
+    unwrap_db_err!(conn.execute("CREATE TABLE IF NOT EXISTS snapshot_files (snapshot INTEGER NOT NULL, path TEXT NOT NULL, file_id TEXT NOT NULL, size INTEGER NOT NULL, md5 TEXT NOT NULL, modified INTEGER, PRIMARY KEY (snapshot, path))", rusqlite::named_params! {}));
+    unwrap_db_err!(conn.execute("CREATE TABLE IF NOT EXISTS deferred (path TEXT PRIMARY KEY, deferred_at INTEGER NOT NULL)", rusqlite::named_params! {}));
+    unwrap_db_err!(conn.execute("CREATE TABLE IF NOT EXISTS change_tokens (drive TEXT PRIMARY KEY, token TEXT NOT NULL)", rusqlite::named_params! {}));
+    unwrap_db_err!(conn.execute("CREATE TABLE IF NOT EXISTS remote_edits (id TEXT PRIMARY KEY, path TEXT NOT NULL, detected INTEGER NOT NULL)", rusqlite::named_params! {}));
+    unwrap_db_err!(conn.execute("CREATE TABLE IF NOT EXISTS drills (time INTEGER NOT NULL, checked INTEGER NOT NULL, failed INTEGER NOT NULL, bytes INTEGER NOT NULL)", rusqlite::named_params! {}));
+    unwrap_db_err!(conn.execute("CREATE TABLE IF NOT EXISTS state_backups (time INTEGER NOT NULL, file_id TEXT NOT NULL)", rusqlite::named_params! {}));
+    unwrap_db_err!(conn.execute("CREATE TABLE IF NOT EXISTS runs (started INTEGER NOT NULL, finished INTEGER NOT NULL, uploaded INTEGER NOT NULL, updated INTEGER NOT NULL, unchanged INTEGER NOT NULL, bytes INTEGER NOT NULL, failures INTEGER NOT NULL, success INTEGER NOT NULL)", rusqlite::named_params! {}));
+    unwrap_db_err!(conn.execute("CREATE TABLE IF NOT EXISTS monthly_stats (month TEXT PRIMARY KEY, runs INTEGER NOT NULL, failed_runs INTEGER NOT NULL, uploaded INTEGER NOT NULL, updated INTEGER NOT NULL, bytes INTEGER NOT NULL, failures INTEGER NOT NULL, tracked_bytes INTEGER)", rusqlite::named_params! {}));
+    // Runs from before the statistics were kept are aggregated once, the size of the tracked data at the time is unknown
+    unwrap_db_err!(conn.execute("INSERT INTO monthly_stats (month, runs, failed_runs, uploaded, updated, bytes, failures) SELECT strftime('%Y-%m', started, 'unixepoch'), COUNT(*), SUM(success = 0), SUM(uploaded), SUM(updated), SUM(bytes), SUM(failures) FROM runs WHERE NOT EXISTS (SELECT 1 FROM monthly_stats) GROUP BY 1", rusqlite::named_params! {}));
+
+    Ok(())
+}
+
+/// Add a column to a table if the table does not have a column with that name yet
+///
+/// # Errors
+/// - When a database operation fails
+fn add_column_if_missing(conn: &rusqlite::Connection, table: &str, column: &str, column_type: &str) -> rusqlite::Result<()> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let mut result = stmt.query(rusqlite::named_params! {})?;
+
+    while let Some(row) = result.next()? {
+        if row.get::<&str, String>("name")?.eq(column) {
+            return Ok(());
+        }
+    }
+
+    conn.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, column_type), rusqlite::named_params! {})?;
+    Ok(())
+}
+
 /// Check if a user is logged in
 ///
 /// # Errors
@@ -0,0 +1,119 @@
+//! Module for exporting an inventory of everything GSync has backed up
+
+use crate::env::Env;
+use crate::{Result, unwrap_other_err};
+use crate::files::{self, TrackedFile};
+use serde::Serialize;
+use std::io::Write;
+
+/// The formats the manifest can be exported in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ManifestFormat {
+    /// Comma separated values, with a header row
+    Csv,
+    /// A JSON array of objects
+    Json
+}
+
+impl ManifestFormat {
+    /// Parse a format from its name as used on the command line
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "csv" => Some(Self::Csv),
+            "json" => Some(Self::Json),
+            _ => None
+        }
+    }
+}
+
+/// Struct describing a single entry in the manifest
+#[derive(Serialize, Debug)]
+struct ManifestEntry<'a> {
+    /// The local path
+    path:       &'a str,
+
+    /// The ID in Google Drive
+    id:         &'a str,
+
+    /// Either `file`, `folder` or `link`
+    #[serde(rename = "type")]
+    file_type:  &'static str,
+
+    /// The size in bytes, if known
+    size:       Option<i64>,
+
+    /// The MD5 checksum of the content, if known
+    md5:        Option<&'a str>,
+
+    /// The time at which the file was last synced, as RFC 3339 timestamp
+    synced_at:  Option<String>
+}
+
+impl<'a> ManifestEntry<'a> {
+    /// Create an entry from a tracked file
+    fn new(file: &'a TrackedFile) -> Self {
+        Self {
+            path:       &file.path,
+            id:         &file.id,
+            file_type:  if file.is_folder { "folder" } else if file.link_target.is_some() { "link" } else { "file" },
+            size:       file.size,
+            md5:        file.md5.as_deref(),
+            synced_at:  file.synced_at.map(|synced_at| chrono::NaiveDateTime::from_timestamp(synced_at, 0).format("%Y-%m-%dT%H:%M:%SZ").to_string())
+        }
+    }
+}
+
+/// Write the manifest of all tracked files, sorted by path
+///
+/// ## Errors
+/// - When a database operation fails
+/// - When writing the manifest fails
+pub fn export<W: Write>(env: &Env, format: ManifestFormat, mut out: W) -> Result<()> {
+    let mut tracked = files::get_all(env)?;
+    tracked.sort_by(|a, b| a.path.cmp(&b.path));
+    let entries = tracked.iter().map(ManifestEntry::new).collect::<Vec<_>>();
+
+    match format {
+        ManifestFormat::Json => unwrap_other_err!(serde_json::to_writer_pretty(&mut out, &entries)),
+        ManifestFormat::Csv => {
+            unwrap_other_err!(writeln!(out, "path,id,type,size,md5,synced_at"));
+            for entry in &entries {
+                unwrap_other_err!(writeln!(out, "{},{},{},{},{},{}",
+                    csv_field(entry.path),
+                    csv_field(entry.id),
+                    entry.file_type,
+                    entry.size.map(|size| size.to_string()).unwrap_or_default(),
+                    entry.md5.unwrap_or_default(),
+                    entry.synced_at.as_deref().unwrap_or_default()));
+            }
+        }
+    }
+
+    unwrap_other_err!(out.flush());
+    Ok(())
+}
+
+/// Quote a CSV field if it contains a separator, quote or newline
+fn csv_field(value: &str) -> String {
+    if value.contains(',') || value.contains('"') || value.contains('\n') || value.contains('\r') {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::manifest::csv_field;
+
+    #[test]
+    fn csv_field_plain() {
+        assert_eq!(csv_field("/home/user/file.txt"), "/home/user/file.txt");
+    }
+
+    #[test]
+    fn csv_field_quoted() {
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+}
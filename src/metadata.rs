@@ -0,0 +1,226 @@
+//! Module for preserving file metadata, such as permissions, ownership and timestamps.
+//! The metadata is stored in the `appProperties` of the file in Google Drive
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::SystemTime;
+use cfg_if::cfg_if;
+
+/// Key for the permission bits, in octal
+#[cfg(unix)]
+const MODE: &str = "gsync_mode";
+/// Key for the numeric user ID of the owner
+#[cfg(unix)]
+const UID: &str = "gsync_uid";
+/// Key for the numeric group ID of the owner
+#[cfg(unix)]
+const GID: &str = "gsync_gid";
+/// Key for the user name of the owner
+#[cfg(unix)]
+const USER: &str = "gsync_user";
+/// Key for the group name of the owner
+#[cfg(unix)]
+const GROUP: &str = "gsync_group";
+/// Key for the modification time, in seconds since the UNIX epoch
+const MTIME: &str = "gsync_mtime";
+/// Key for the access time, in seconds since the UNIX epoch
+const ATIME: &str = "gsync_atime";
+/// Key for the ID of the file a shortcut is a hard link of. Only present on shortcuts representing hard links
+pub const HARD_LINK_TARGET: &str = "gsync_hardlink_target";
+/// Key for the target of a symbolic link, as read from the link. Only present on shortcuts representing symbolic links
+pub const SYMLINK_TARGET: &str = "gsync_symlink_target";
+/// Key for the moment a file or folder was removed locally while the deletion policy is archive, in seconds since the UNIX epoch.
+/// Only present on files and folders which are kept in Drive as stale
+pub const STALE: &str = "gsync_stale";
+/// Key for the allocated size of sparse files in bytes. Only present for sparse files
+const SPARSE_ALLOCATED: &str = "gsync_sparse_allocated";
+/// Key marking a file whose content was uploaded packed, see [crate::sparse]. Only present on files uploaded packed
+pub const PACKED: &str = "gsync_packed";
+/// Prefix for the keys of extended attributes, followed by the name of the attribute. The values are base64 encoded
+#[cfg(unix)]
+const XATTR_PREFIX: &str = "gsync_xattr:";
+/// The maximum length of the key and value of a single property combined, in bytes, as imposed by Google Drive
+#[cfg(unix)]
+const MAX_PROPERTY_LENGTH: usize = 124;
+
+/// The modification time a file had when it was synced, as epoch seconds, if it was recorded
+pub fn recorded_mtime(properties: &HashMap<String, String>) -> Option<i64> {
+    properties.get(MTIME).and_then(|mtime| mtime.parse::<i64>().ok())
+}
+
+/// Collect the metadata of a local file. Metadata which can not be read is left out
+///
+/// ## Params
+/// - `path` The path of the file
+/// - `include_xattrs` Whether extended attributes should be collected. This includes macOS Finder metadata and SELinux labels
+pub fn collect(path: &Path, include_xattrs: bool) -> HashMap<String, String> {
+    let mut properties = HashMap::new();
+    let meta = match std::fs::metadata(path) {
+        Ok(meta) => meta,
+        Err(_) => return properties
+    };
+
+    if let Some(mtime) = meta.modified().ok().and_then(to_epoch) {
+        properties.insert(MTIME.to_string(), mtime.to_string());
+    }
+
+    if let Some(atime) = meta.accessed().ok().and_then(to_epoch) {
+        properties.insert(ATIME.to_string(), atime.to_string());
+    }
+
+    cfg_if! {
+        if #[cfg(unix)] {
+            use std::os::unix::fs::MetadataExt;
+
+            properties.insert(MODE.to_string(), format!("{:o}", meta.mode() & 0o7777));
+            properties.insert(UID.to_string(), meta.uid().to_string());
+            properties.insert(GID.to_string(), meta.gid().to_string());
+
+            // A file is sparse if less blocks are allocated than needed for its length. Blocks are always 512 bytes here
+            let allocated = meta.blocks() * 512;
+            if allocated < meta.len() {
+                properties.insert(SPARSE_ALLOCATED.to_string(), allocated.to_string());
+            }
+
+            if let Some(user) = users::get_user_by_uid(meta.uid()) {
+                properties.insert(USER.to_string(), user.name().to_string_lossy().to_string());
+            }
+
+            if let Some(group) = users::get_group_by_gid(meta.gid()) {
+                properties.insert(GROUP.to_string(), group.name().to_string_lossy().to_string());
+            }
+
+            if include_xattrs {
+                collect_xattrs(path, &mut properties);
+            }
+        } else {
+            if include_xattrs {
+                println!("Warning: Extended attributes are not supported on this platform, skipping '{}'", path.to_str().unwrap_or_default());
+            }
+        }
+    }
+
+    properties
+}
+
+/// Collect the extended attributes of a file into the provided properties.
+/// Attributes which do not fit in a single Drive property are skipped with a warning
+#[cfg(unix)]
+fn collect_xattrs(path: &Path, properties: &mut HashMap<String, String>) {
+    let names = match xattr::list(path) {
+        Ok(names) => names,
+        Err(e) => {
+            println!("Warning: Failed to read extended attributes of '{}': {}", path.to_str().unwrap_or_default(), e);
+            return;
+        }
+    };
+
+    for name in names {
+        let name = name.to_string_lossy().to_string();
+        let value = match xattr::get(path, &name) {
+            Ok(Some(value)) => base64::encode(&value),
+            Ok(None) => continue,
+            Err(e) => {
+                println!("Warning: Failed to read extended attribute '{}' of '{}': {}", &name, path.to_str().unwrap_or_default(), e);
+                continue;
+            }
+        };
+
+        let key = format!("{}{}", XATTR_PREFIX, &name);
+        if key.len() + value.len() > MAX_PROPERTY_LENGTH {
+            println!("Warning: Extended attribute '{}' of '{}' is too large to be stored in Drive, skipping", &name, path.to_str().unwrap_or_default());
+            continue;
+        }
+
+        properties.insert(key, value);
+    }
+}
+
+/// Apply the extended attributes stored in the properties to a file. This is best-effort, failures are reported as warnings
+#[cfg(unix)]
+fn apply_xattrs(path: &Path, properties: &HashMap<String, String>) {
+    for (key, value) in properties {
+        let name = match key.strip_prefix(XATTR_PREFIX) {
+            Some(name) => name,
+            None => continue
+        };
+
+        let value = match base64::decode(value) {
+            Ok(value) => value,
+            Err(_) => continue
+        };
+
+        if let Err(e) = xattr::set(path, name, &value) {
+            println!("Warning: Failed to restore extended attribute '{}' of '{}': {}", name, path.to_str().unwrap_or_default(), e);
+        }
+    }
+}
+
+/// Apply previously collected metadata to a local file. This is best-effort, failures are reported as warnings
+///
+/// ## Params
+/// - `path` The path of the file
+/// - `properties` The metadata collected by [collect]
+/// - `restore_ownership` Whether the owner and group should be restored. This usually requires root privileges
+pub fn apply(path: &Path, properties: &HashMap<String, String>, restore_ownership: bool) {
+    let path_str = path.to_str().unwrap_or_default();
+
+    cfg_if! {
+        if #[cfg(unix)] {
+            use std::os::unix::fs::PermissionsExt;
+
+            // Ownership has to be restored first, changing the owner clears the setuid and setgid bits
+            if restore_ownership {
+                let uid = properties.get(USER)
+                    .and_then(users::get_user_by_name)
+                    .map(|user| user.uid())
+                    .or_else(|| properties.get(UID).and_then(|uid| uid.parse().ok()));
+                let gid = properties.get(GROUP)
+                    .and_then(users::get_group_by_name)
+                    .map(|group| group.gid())
+                    .or_else(|| properties.get(GID).and_then(|gid| gid.parse().ok()));
+
+                if let Err(e) = std::os::unix::fs::chown(path, uid, gid) {
+                    println!("Warning: Failed to restore ownership of '{}': {}", path_str, e);
+                }
+            }
+
+            apply_xattrs(path, properties);
+
+            if let Some(mode) = properties.get(MODE).and_then(|mode| u32::from_str_radix(mode, 8).ok()) {
+                if let Err(e) = std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode)) {
+                    println!("Warning: Failed to restore permissions of '{}': {}", path_str, e);
+                }
+            }
+        } else {
+            if restore_ownership {
+                println!("Warning: Restoring ownership is not supported on this platform, skipping '{}'", path_str);
+            }
+        }
+    }
+
+    let mtime = properties.get(MTIME).and_then(|mtime| mtime.parse::<i64>().ok());
+    let atime = properties.get(ATIME).and_then(|atime| atime.parse::<i64>().ok());
+    if let Some(mtime) = mtime {
+        let mtime = filetime::FileTime::from_unix_time(mtime, 0);
+        let atime = atime.map(|atime| filetime::FileTime::from_unix_time(atime, 0)).unwrap_or(mtime);
+        if let Err(e) = filetime::set_file_times(path, atime, mtime) {
+            println!("Warning: Failed to restore timestamps of '{}': {}", path_str, e);
+        }
+    }
+}
+
+/// Check if the file the properties were collected from was a sparse file
+pub fn is_sparse(properties: &HashMap<String, String>) -> bool {
+    properties.contains_key(SPARSE_ALLOCATED)
+}
+
+/// Check if the content of a file was uploaded packed, in which case its size and MD5 checksum in Drive are those of the packed content
+pub fn is_packed(properties: &HashMap<String, String>) -> bool {
+    properties.contains_key(PACKED)
+}
+
+/// Convert a SystemTime to seconds since the UNIX epoch
+fn to_epoch(time: SystemTime) -> Option<u64> {
+    time.duration_since(SystemTime::UNIX_EPOCH).ok().map(|duration| duration.as_secs())
+}
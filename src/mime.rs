@@ -0,0 +1,84 @@
+//! Module for deciding the MIME type files are uploaded with, and whether Google Drive converts them to a Google document
+
+use std::collections::HashMap;
+use std::path::Path;
+
+/// The MIME type used when the type of a file can't be guessed
+const DEFAULT_MIME_TYPE: &str = "application/octet-stream";
+
+/// Parse configured MIME types per extension, e.g. `md=text/markdown,log=text/plain`. Extensions are case insensitive and may start with a `.`
+///
+/// ## Errors
+/// - When an entry is not an extension and a MIME type separated by `=`
+pub fn parse_overrides(overrides: &str) -> std::result::Result<HashMap<String, String>, String> {
+    let mut parsed = HashMap::new();
+    for entry in overrides.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+        let (extension, mime_type) = match entry.split_once('=') {
+            Some((extension, mime_type)) => (extension.trim().trim_start_matches('.').to_ascii_lowercase(), mime_type.trim()),
+            None => return Err(format!("'{}' is not of the form EXTENSION=MIME_TYPE", entry))
+        };
+
+        if extension.is_empty() || !mime_type.contains('/') {
+            return Err(format!("'{}' is not of the form EXTENSION=MIME_TYPE", entry));
+        }
+
+        parsed.insert(extension, mime_type.to_string());
+    }
+
+    Ok(parsed)
+}
+
+/// The MIME type of a file: the configured type for its extension, or the type guessed from its extension otherwise
+pub fn mime_type(path: &Path, overrides: &HashMap<String, String>) -> String {
+    let configured = path.extension()
+        .map(|extension| extension.to_string_lossy().to_ascii_lowercase())
+        .and_then(|extension| overrides.get(&extension));
+
+    match configured {
+        Some(mime_type) => mime_type.clone(),
+        None => match mime_guess::from_path(path).first() {
+            Some(guess) => guess.essence_str().to_string(),
+            None => DEFAULT_MIME_TYPE.to_string()
+        }
+    }
+}
+
+/// The type of Google document a file of this MIME type can be converted to, if any
+pub fn google_type(mime_type: &str) -> Option<&'static str> {
+    match mime_type {
+        "application/msword"
+            | "application/vnd.openxmlformats-officedocument.wordprocessingml.document"
+            | "application/vnd.oasis.opendocument.text"
+            | "application/rtf" => Some("application/vnd.google-apps.document"),
+        "application/vnd.ms-excel"
+            | "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"
+            | "application/vnd.oasis.opendocument.spreadsheet" => Some("application/vnd.google-apps.spreadsheet"),
+        "application/vnd.ms-powerpoint"
+            | "application/vnd.openxmlformats-officedocument.presentationml.presentation"
+            | "application/vnd.oasis.opendocument.presentation" => Some("application/vnd.google-apps.presentation"),
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::mime::{google_type, mime_type, parse_overrides};
+    use std::path::Path;
+
+    #[test]
+    fn overrides() {
+        let overrides = parse_overrides("md=text/markdown, .LOG = text/plain").unwrap();
+        assert_eq!("text/markdown", mime_type(Path::new("/notes/README.md"), &overrides));
+        assert_eq!("text/plain", mime_type(Path::new("/var/app.log"), &overrides));
+        assert_eq!("image/png", mime_type(Path::new("/photo.png"), &overrides));
+
+        assert!(parse_overrides("md").is_err());
+        assert!(parse_overrides("md=markdown").is_err());
+    }
+
+    #[test]
+    fn conversions() {
+        assert_eq!(Some("application/vnd.google-apps.spreadsheet"), google_type("application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"));
+        assert_eq!(None, google_type("application/pdf"));
+    }
+}
@@ -0,0 +1,272 @@
+//! Module for mounting the GSync folder in Google Drive as a read-only FUSE filesystem.
+//! Folders are listed when they are first accessed, file contents are downloaded on demand
+
+use crate::env::Env;
+use crate::{Result, unwrap_other_err};
+use crate::api::drive;
+use crate::metadata;
+use crate::sparse::{self, Layout, Segment};
+use fuser::{Filesystem, FileAttr, FileType, MountOption, ReplyAttr, ReplyData, ReplyDirectory, ReplyEntry, Request};
+use std::ffi::OsStr;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How long the kernel may cache attributes and lookups. The mount is read-only, so this can be long
+const TTL: Duration = Duration::from_secs(60);
+
+/// The inode of the root folder
+const ROOT_INODE: u64 = 1;
+
+/// Struct describing a file or folder in the mounted tree
+#[derive(Debug)]
+struct Node {
+    /// The ID of the file in Google Drive
+    id:         String,
+
+    /// The inode of the parent folder
+    parent:     u64,
+
+    /// The name of the file
+    name:       String,
+
+    /// Whether the file is a folder
+    is_folder:  bool,
+
+    /// The size of the file's content in bytes
+    size:       u64,
+
+    /// Where the data of a sparse file uploaded packed is in its content. None for other files
+    layout:     Option<Layout>,
+
+    /// The time the file was last modified
+    modified:   SystemTime,
+
+    /// The inodes of the children of a folder. None until the folder is listed
+    children:   Option<Vec<u64>>
+}
+
+/// The read-only filesystem. Inodes are indices into `nodes`, offset by one
+struct GSyncFs {
+    /// Env instance
+    env:    Env,
+
+    /// All files and folders discovered so far
+    nodes:  Vec<Node>
+}
+
+/// Mount the GSync folder read-only at the provided mountpoint. This blocks until the filesystem is unmounted
+///
+/// ## Errors
+/// - When the Google API returns an error
+/// - When mounting fails, e.g. because FUSE is not available
+pub fn mount<P: AsRef<Path>>(env: &Env, mountpoint: P) -> Result<()> {
+    let root = drive::blocking::get_file(env, &env.root_folder)?;
+    let fs = GSyncFs {
+        env:    env.clone(),
+        nodes:  vec![Node {
+            id:         root.id,
+            parent:     ROOT_INODE,
+            name:       root.name,
+            is_folder:  true,
+            size:       0,
+            layout:     None,
+            modified:   parse_time(&root.modified_time),
+            children:   None
+        }]
+    };
+
+    println!("Info: Mounting '{}' read-only. Unmount it to stop", mountpoint.as_ref().to_string_lossy());
+    unwrap_other_err!(fuser::mount2(fs, mountpoint, &[MountOption::RO, MountOption::FSName("gsync".to_string())]));
+    Ok(())
+}
+
+/// Read the header of a file which may have been uploaded packed. Returns None if it was not
+///
+/// ## Errors
+/// - When the Google API returns an error
+fn read_layout(env: &Env, id: &str, size: u64) -> Result<Option<Layout>> {
+    let prefix = drive::blocking::download_range(env, id, 0, size.min(sparse::PREFIX_LEN))?;
+    let header_len = match sparse::header_len(&prefix) {
+        Some(header_len) if header_len <= size => header_len,
+        _ => return Ok(None)
+    };
+
+    let header = drive::blocking::download_range(env, id, 0, header_len)?;
+    Ok(Layout::parse(&mut header.as_slice())?.filter(|layout| layout.packed_len() == size))
+}
+
+/// Read part of a file uploaded packed, downloading only the data and filling the holes with zeroes
+///
+/// ## Errors
+/// - When the Google API returns an error
+fn read_packed(env: &Env, id: &str, layout: &Layout, offset: u64, length: u64) -> Result<Vec<u8>> {
+    let mut data = Vec::with_capacity(length as usize);
+    for segment in layout.segments(offset, length) {
+        match segment {
+            Segment::Data { packed_offset, length } => data.extend(drive::blocking::download_range(env, id, packed_offset, length)?),
+            Segment::Hole(length) => data.resize(data.len() + length as usize, 0)
+        }
+    }
+
+    Ok(data)
+}
+
+/// Parse an RFC 3339 time returned by Google, falling back to the epoch
+fn parse_time(time: &str) -> SystemTime {
+    chrono::DateTime::parse_from_rfc3339(time)
+        .map(|time| UNIX_EPOCH + Duration::from_secs(time.timestamp().max(0) as u64))
+        .unwrap_or(UNIX_EPOCH)
+}
+
+impl GSyncFs {
+    /// Get a node by its inode
+    fn node(&self, ino: u64) -> Option<&Node> {
+        self.nodes.get((ino - 1) as usize)
+    }
+
+    /// Get the children of a folder, listing it in Google Drive if it was not listed before
+    ///
+    /// ## Errors
+    /// - When the Google API returns an error
+    fn children(&mut self, ino: u64) -> Result<Vec<u64>> {
+        let (id, children) = match self.node(ino) {
+            Some(node) => (node.id.clone(), node.children.clone()),
+            None => return Ok(Vec::new())
+        };
+
+        if let Some(children) = children {
+            return Ok(children);
+        }
+
+        let files = drive::blocking::list_files(&self.env, Some(&format!("'{}' in parents and trashed = false", id)), self.env.drive_id.as_deref())?;
+        let mut children = Vec::new();
+        for file in files {
+            // The size of a file uploaded packed is the size of the file it was packed from
+            let layout = if !file.is_folder() && metadata::is_packed(&file.app_properties) {
+                read_layout(&self.env, &file.id, file.size())?
+            } else {
+                None
+            };
+
+            self.nodes.push(Node {
+                is_folder:  file.is_folder(),
+                size:       layout.as_ref().map(|layout| layout.len).unwrap_or_else(|| file.size()),
+                layout,
+                modified:   parse_time(&file.modified_time),
+                id:         file.id,
+                parent:     ino,
+                name:       file.name,
+                children:   None
+            });
+
+            children.push(self.nodes.len() as u64);
+        }
+
+        self.nodes[(ino - 1) as usize].children = Some(children.clone());
+        Ok(children)
+    }
+
+    /// Get the attributes of a node
+    fn attr(&self, ino: u64) -> Option<FileAttr> {
+        let node = self.node(ino)?;
+        Some(FileAttr {
+            ino,
+            size:       node.size,
+            blocks:     (node.size + 511) / 512,
+            atime:      node.modified,
+            mtime:      node.modified,
+            ctime:      node.modified,
+            crtime:     node.modified,
+            kind:       if node.is_folder { FileType::Directory } else { FileType::RegularFile },
+            perm:       if node.is_folder { 0o555 } else { 0o444 },
+            nlink:      1,
+            uid:        unsafe { libc::getuid() },
+            gid:        unsafe { libc::getgid() },
+            rdev:       0,
+            blksize:    512,
+            flags:      0
+        })
+    }
+}
+
+impl Filesystem for GSyncFs {
+    fn lookup(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        let children = match self.children(parent) {
+            Ok(children) => children,
+            Err((e, _, _)) => {
+                eprintln!("Error: Failed to list folder: {}", crate::redact::redact(format!("{:?}", e)));
+                reply.error(libc::EIO);
+                return;
+            }
+        };
+
+        let found = children.into_iter().find(|ino| self.node(*ino).map(|node| OsStr::new(&node.name) == name).unwrap_or(false));
+        match found.and_then(|ino| self.attr(ino)) {
+            Some(attr) => reply.entry(&TTL, &attr, 0),
+            None => reply.error(libc::ENOENT)
+        }
+    }
+
+    fn getattr(&mut self, _req: &Request<'_>, ino: u64, reply: ReplyAttr) {
+        match self.attr(ino) {
+            Some(attr) => reply.attr(&TTL, &attr),
+            None => reply.error(libc::ENOENT)
+        }
+    }
+
+    fn read(&mut self, _req: &Request<'_>, ino: u64, _fh: u64, offset: i64, size: u32, _flags: i32, _lock: Option<u64>, reply: ReplyData) {
+        let node = match self.node(ino) {
+            Some(node) if !node.is_folder => node,
+            Some(_) => return reply.error(libc::EISDIR),
+            None => return reply.error(libc::ENOENT)
+        };
+
+        let offset = offset.max(0) as u64;
+        if offset >= node.size {
+            return reply.data(&[]);
+        }
+
+        let length = (size as u64).min(node.size - offset);
+        let data = match &node.layout {
+            Some(layout) => read_packed(&self.env, &node.id, layout, offset, length),
+            None => drive::blocking::download_range(&self.env, &node.id, offset, length)
+        };
+
+        match data {
+            Ok(data) => reply.data(&data),
+            Err((e, _, _)) => {
+                eprintln!("Error: Failed to download '{}': {}", &node.name, crate::redact::redact(format!("{:?}", e)));
+                reply.error(libc::EIO);
+            }
+        }
+    }
+
+    fn readdir(&mut self, _req: &Request<'_>, ino: u64, _fh: u64, offset: i64, mut reply: ReplyDirectory) {
+        let children = match self.children(ino) {
+            Ok(children) => children,
+            Err((e, _, _)) => {
+                eprintln!("Error: Failed to list folder: {}", crate::redact::redact(format!("{:?}", e)));
+                reply.error(libc::EIO);
+                return;
+            }
+        };
+
+        // Safe to unwrap, children were listed so the node exists
+        let parent = self.node(ino).unwrap().parent;
+        let mut entries = vec![(ino, FileType::Directory, ".".to_string()), (parent, FileType::Directory, "..".to_string())];
+        for child in children {
+            if let Some(node) = self.node(child) {
+                entries.push((child, if node.is_folder { FileType::Directory } else { FileType::RegularFile }, node.name.clone()));
+            }
+        }
+
+        for (i, (ino, kind, name)) in entries.into_iter().enumerate().skip(offset as usize) {
+            // The offset passed to add is the offset of the next entry
+            if reply.add(ino, (i + 1) as i64, kind, name) {
+                break;
+            }
+        }
+
+        reply.ok();
+    }
+}
@@ -0,0 +1,68 @@
+//! Module for opening synced files in the Google Drive web interface
+
+use crate::files::TrackedFile;
+use crate::{Result, unwrap_other_err};
+use cfg_if::cfg_if;
+use std::process::Command;
+
+/// Get the URL of a tracked file in the Google Drive web interface
+pub fn web_url(file: &TrackedFile) -> String {
+    if file.is_folder {
+        format!("https://drive.google.com/drive/folders/{}", file.id)
+    } else {
+        format!("https://drive.google.com/file/d/{}/view", file.id)
+    }
+}
+
+/// Open a URL in the default browser
+///
+/// ## Errors
+/// - When the browser could not be started
+pub fn open_in_browser(url: &str) -> Result<()> {
+    let status = unwrap_other_err!(browser_command(url).status());
+    if !status.success() {
+        return Err((crate::Error::Other(format!("Opening the browser exited with {}", status)), line!(), file!()));
+    }
+
+    Ok(())
+}
+
+cfg_if! {
+    if #[cfg(target_os = "macos")] {
+        /// Get the command which opens a URL in the default browser
+        fn browser_command(url: &str) -> Command {
+            let mut command = Command::new("open");
+            command.arg(url);
+            command
+        }
+    } else if #[cfg(windows)] {
+        /// Get the command which opens a URL in the default browser
+        fn browser_command(url: &str) -> Command {
+            let mut command = Command::new("cmd");
+            command.args(&["/C", "start", "", url]);
+            command
+        }
+    } else {
+        /// Get the command which opens a URL in the default browser
+        fn browser_command(url: &str) -> Command {
+            let mut command = Command::new("xdg-open");
+            command.arg(url);
+            command
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::open::web_url;
+    use crate::files::TrackedFile;
+
+    #[test]
+    fn web_url_file_and_folder() {
+        let mut file = TrackedFile { path: "/home/notes.txt".to_string(), id: "abc".to_string(), parent_id: "root".to_string(), is_folder: false, link_target: None, size: None, md5: None, synced_at: None };
+        assert_eq!(web_url(&file), "https://drive.google.com/file/d/abc/view");
+
+        file.is_folder = true;
+        assert_eq!(web_url(&file), "https://drive.google.com/drive/folders/abc");
+    }
+}
@@ -0,0 +1,100 @@
+//! Module for the `.gsync.toml` files which override the policies of an input for the folder they are in, and everything in it.
+//! This keeps a policy next to the data it governs, e.g. a project folder which excludes its own build output
+
+use crate::size::parse_size;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// The name of the file holding the overrides of a folder
+pub const FILE_NAME: &str = ".gsync.toml";
+
+/// The policies a `.gsync.toml` file overrides for the folder it is in
+#[derive(Debug, Default, PartialEq)]
+pub struct DirectoryOverrides {
+    /// Patterns of files and folders which are not synced, in addition to those of the input. Patterns with a `/` are relative to the folder
+    pub excludes:   Vec<String>,
+
+    /// Files larger than this many bytes are not synced. 0 lifts the maximum size of the input
+    pub max_size:   Option<u64>,
+
+    /// Whether .gitignore files are respected
+    pub gitignore:  Option<bool>,
+
+    /// The keys in the file GSync doesn't support, which are ignored
+    pub unsupported: Vec<String>
+}
+
+/// The contents of a `.gsync.toml` file, as written
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct RawOverrides {
+    /// Patterns of files and folders which are not synced
+    #[serde(default)]
+    excludes:   Vec<String>,
+
+    /// The maximum size of a file, in bytes or with a unit like `500M`
+    max_size:   Option<toml::Value>,
+
+    /// Whether .gitignore files are respected
+    gitignore:  Option<bool>,
+
+    /// Any other keys
+    #[serde(flatten)]
+    other:      BTreeMap<String, toml::Value>
+}
+
+impl DirectoryOverrides {
+    /// Parse the contents of a `.gsync.toml` file
+    ///
+    /// ## Errors
+    /// - When the contents are not valid TOML, or a value has the wrong type
+    pub fn parse(contents: &str) -> std::result::Result<Self, String> {
+        let raw: RawOverrides = toml::from_str(contents).map_err(|e| e.to_string())?;
+
+        let max_size = match raw.max_size {
+            Some(toml::Value::Integer(bytes)) if bytes >= 0 => Some(bytes as u64),
+            Some(toml::Value::String(size)) => match parse_size(&size) {
+                Some(bytes) => Some(bytes),
+                None => return Err(format!("'{}' is not a valid size", size))
+            },
+            Some(_) => return Err("max-size must be a number of bytes or a size like '500M'".to_string()),
+            None => None
+        };
+
+        Ok(Self {
+            excludes:   raw.excludes.into_iter().map(|pattern| pattern.trim().to_string()).filter(|pattern| !pattern.is_empty()).collect(),
+            max_size,
+            gitignore:  raw.gitignore,
+            unsupported: raw.other.into_keys().collect()
+        })
+    }
+
+    /// Read the `.gsync.toml` file in a folder
+    ///
+    /// ## Errors
+    /// - When the file can't be read or parsed
+    pub fn read(dir: &Path) -> std::result::Result<Self, String> {
+        let contents = std::fs::read_to_string(dir.join(FILE_NAME)).map_err(|e| e.to_string())?;
+        Self::parse(&contents)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::overrides::DirectoryOverrides;
+
+    #[test]
+    fn parse_overrides() {
+        let overrides = DirectoryOverrides::parse("excludes = [\"target\", \"docs/*.pdf\"]\nmax-size = \"10M\"\ngitignore = false\ncompression = true\n").unwrap();
+        assert_eq!(vec!["target", "docs/*.pdf"], overrides.excludes);
+        assert_eq!(Some(10 * 1024 * 1024), overrides.max_size);
+        assert_eq!(Some(false), overrides.gitignore);
+        assert_eq!(vec!["compression"], overrides.unsupported);
+
+        assert_eq!(Some(2048), DirectoryOverrides::parse("max-size = 2048").unwrap().max_size);
+        assert_eq!(DirectoryOverrides::default(), DirectoryOverrides::parse("").unwrap());
+        assert!(DirectoryOverrides::parse("max-size = \"big\"").is_err());
+        assert!(DirectoryOverrides::parse("excludes = 1").is_err());
+    }
+}
@@ -0,0 +1,169 @@
+//! Module for picking the folder to sync into interactively. The user navigates through My Drive and the shared drives they have access to,
+//! which is far less error-prone than copying the ID of a folder from its URL
+
+use crate::env::Env;
+use crate::api::drive;
+use crate::{Result, unwrap_other_err};
+use std::io::Write;
+
+/// Struct describing a folder picked by the user
+#[derive(Debug)]
+pub struct PickedFolder {
+    /// The shared drive the folder is in, where None is My Drive
+    pub drive_id:   Option<String>,
+
+    /// The ID of the folder
+    pub folder_id:  String,
+
+    /// The path of the folder, starting with the name of the drive, for showing to the user
+    pub path:       String
+}
+
+/// Enum describing what the user chose at a prompt of the picker
+#[derive(Debug, PartialEq, Eq)]
+enum Choice {
+    /// Open the entry with this index
+    Open(usize),
+
+    /// Select the current folder
+    Select,
+
+    /// Go to the parent folder
+    Up,
+
+    /// Stop without picking a folder
+    Quit
+}
+
+/// Let the user pick a folder in Google Drive. First the user picks My Drive or a shared drive, then navigates through its folders until selecting one
+///
+/// ## Errors
+/// - When the Google API returns an error
+/// - When reading the user's input fails
+pub fn pick(env: &Env) -> Result<Option<PickedFolder>> {
+    let drives = drive::blocking::get_shared_drives(env)?;
+
+    loop {
+        println!("\nDrives:");
+        println!("  1) My Drive");
+        for (index, shared_drive) in drives.iter().enumerate() {
+            println!("  {}) {}", index + 2, shared_drive.name);
+        }
+
+        let (drive_id, root_id, name) = match parse_choice(&prompt("Number to open a drive, 'q' to cancel: ")?, drives.len() + 1) {
+            Some(Choice::Open(0)) => (None, "root".to_string(), "My Drive".to_string()),
+            Some(Choice::Open(index)) => {
+                let shared_drive = &drives[index - 1];
+                (Some(shared_drive.id.clone()), shared_drive.id.clone(), shared_drive.name.clone())
+            },
+            Some(Choice::Quit) => return Ok(None),
+            _ => {
+                println!("Invalid choice");
+                continue;
+            }
+        };
+
+        // None means the user went up from the root of the drive
+        if let Some(folder) = navigate(env, drive_id, root_id, name)? {
+            return Ok(folder);
+        }
+    }
+}
+
+/// Let the user navigate through the folders of a drive.
+/// Returns None if the user went up from the root of the drive, Some(None) if the user cancelled, and the picked folder otherwise
+///
+/// ## Errors
+/// - When the Google API returns an error
+/// - When reading the user's input fails
+fn navigate(env: &Env, drive_id: Option<String>, root_id: String, name: String) -> Result<Option<Option<PickedFolder>>> {
+    // The folders from the root of the drive to the current folder, as (ID, name)
+    let mut stack = vec![(root_id, name)];
+
+    loop {
+        let path = stack.iter().map(|(_, name)| name.as_str()).collect::<Vec<_>>().join("/");
+        // The stack is never empty, going up from the root of the drive returns
+        let current = stack[stack.len() - 1].0.clone();
+
+        let q = format!("'{}' in parents and mimeType = 'application/vnd.google-apps.folder' and trashed = false", current);
+        let mut folders = drive::blocking::list_files(env, Some(&q), drive_id.as_deref())?;
+        folders.sort_by_key(|folder| folder.name.to_lowercase());
+
+        println!("\n{}", path);
+        if folders.is_empty() {
+            println!("  (no folders)");
+        }
+
+        for (index, folder) in folders.iter().enumerate() {
+            println!("  {}) {}", index + 1, folder.name);
+        }
+
+        match parse_choice(&prompt("Number to open a folder, '.' to select this folder, '..' to go up, 'q' to cancel: ")?, folders.len()) {
+            Some(Choice::Open(index)) => stack.push((folders[index].id.clone(), folders[index].name.clone())),
+            Some(Choice::Select) => {
+                // 'root' is an alias of the root of My Drive, the configuration stores its real ID
+                let folder_id = if current.eq("root") { drive::blocking::get_file(env, &current)?.id } else { current };
+                return Ok(Some(Some(PickedFolder {
+                    drive_id,
+                    folder_id,
+                    path
+                })));
+            },
+            Some(Choice::Up) => {
+                stack.pop();
+                if stack.is_empty() {
+                    return Ok(None);
+                }
+            },
+            Some(Choice::Quit) => return Ok(Some(None)),
+            None => println!("Invalid choice")
+        }
+    }
+}
+
+/// Show a prompt and read a line of input from the user
+///
+/// ## Errors
+/// - When reading from stdin or writing to stdout fails
+fn prompt(text: &str) -> Result<String> {
+    print!("{}", text);
+    unwrap_other_err!(std::io::stdout().flush());
+
+    let mut answer = String::new();
+    unwrap_other_err!(std::io::stdin().read_line(&mut answer));
+    Ok(answer.trim().to_string())
+}
+
+/// Parse the user's answer to a prompt. Entries are numbered from 1, the returned index starts at 0
+///
+/// ## Params
+/// - `answer` The user's answer
+/// - `count` The number of entries the user could open
+fn parse_choice(answer: &str, count: usize) -> Option<Choice> {
+    match answer {
+        "." => Some(Choice::Select),
+        ".." => Some(Choice::Up),
+        "q" | "Q" => Some(Choice::Quit),
+        _ => match answer.parse::<usize>() {
+            Ok(number) if (1..=count).contains(&number) => Some(Choice::Open(number - 1)),
+            _ => None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::picker::{parse_choice, Choice};
+
+    #[test]
+    fn parses_choices() {
+        assert_eq!(Some(Choice::Open(0)), parse_choice("1", 3));
+        assert_eq!(Some(Choice::Open(2)), parse_choice("3", 3));
+        assert_eq!(None, parse_choice("4", 3));
+        assert_eq!(None, parse_choice("0", 3));
+        assert_eq!(Some(Choice::Select), parse_choice(".", 0));
+        assert_eq!(Some(Choice::Up), parse_choice("..", 0));
+        assert_eq!(Some(Choice::Quit), parse_choice("q", 0));
+        assert_eq!(None, parse_choice("folder", 3));
+    }
+}
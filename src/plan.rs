@@ -0,0 +1,442 @@
+//! Module for planning a sync before executing it, so its scope can be shown up front
+
+use crate::compare::{self, ComparePolicy, SyncedCopy};
+use crate::files::TrackedFile;
+use crate::hash::{HashAlgorithm, HashCache};
+use crate::size::format_size;
+use crate::{Result, unwrap_other_err};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// The deletion limit used when none is configured
+pub const DEFAULT_DELETE_LIMIT: DeleteLimit = DeleteLimit::Percent(50);
+
+/// The maximum number of tracked files a sync may delete from Drive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeleteLimit {
+    /// An absolute number of files
+    Count(u64),
+
+    /// A percentage of the tracked files within the inputs
+    Percent(u64)
+}
+
+impl DeleteLimit {
+    /// Parse a deletion limit, either a number like `100` or a percentage like `10%`
+    pub fn parse(limit: &str) -> Option<Self> {
+        match limit.trim().strip_suffix('%') {
+            Some(percent) => percent.trim().parse::<u64>().ok().filter(|percent| *percent <= 100).map(Self::Percent),
+            None => limit.trim().parse::<u64>().ok().map(Self::Count)
+        }
+    }
+}
+
+impl std::fmt::Display for DeleteLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Count(count) => write!(f, "{} files", count),
+            Self::Percent(percent) => write!(f, "{}% of the tracked files", percent)
+        }
+    }
+}
+
+/// The deletion policy used when none is configured
+pub const DEFAULT_DELETE_POLICY: DeletePolicy = DeletePolicy::Trash;
+
+/// What happens in Drive to the synced copy of a file or folder which was removed locally
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeletePolicy {
+    /// Delete it permanently, so Drive mirrors the inputs
+    Mirror,
+
+    /// Never delete it, only mark it stale and stop tracking it
+    Archive,
+
+    /// Move it to the trash in Drive, from where it can be recovered for 30 days
+    Trash
+}
+
+impl DeletePolicy {
+    /// Parse a deletion policy from its name: `mirror`, `archive` or `trash`
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "mirror" => Some(Self::Mirror),
+            "archive" => Some(Self::Archive),
+            "trash" => Some(Self::Trash),
+            _ => None
+        }
+    }
+}
+
+/// A local file or folder found while traversing the inputs
+#[derive(Debug, Clone)]
+pub struct LocalEntry {
+    /// The local path
+    pub path:       PathBuf,
+
+    /// Whether the entry is a folder
+    pub is_folder:  bool,
+
+    /// The size of the file in bytes. 0 for folders
+    pub size:       u64,
+
+    /// The modification time of the file, as epoch seconds
+    pub modified:   Option<i64>,
+
+    /// How to decide whether the file changed since it was synced
+    pub compare:    ComparePolicy
+}
+
+/// The kind of change a sync will make in Google Drive
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    /// The file is not in Drive yet and will be uploaded
+    Upload,
+
+    /// The file has changed since it was last synced and will be updated
+    Update,
+
+    /// The file no longer exists locally and will be deleted from Drive
+    Delete
+}
+
+impl std::fmt::Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Upload => f.pad("upload"),
+            Self::Update => f.pad("update"),
+            Self::Delete => f.pad("delete")
+        }
+    }
+}
+
+/// A file which is not synced because it is larger than the maximum size
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Oversized {
+    /// The local path of the file
+    pub path:       String,
+
+    /// The size of the file in bytes
+    pub size:       u64,
+
+    /// The maximum size which applies to the file, in bytes
+    pub max_size:   u64
+}
+
+/// A single change a sync will make
+#[derive(Serialize, Debug, Clone)]
+pub struct PlannedAction {
+    /// The kind of change
+    pub action:     Action,
+
+    /// The local path of the file or folder
+    pub path:       String,
+
+    /// Whether the path is a folder
+    #[serde(rename = "folder")]
+    pub is_folder:  bool,
+
+    /// The number of bytes which will be uploaded
+    pub size:       u64,
+
+    /// Why the change is made
+    pub reason:     &'static str,
+
+    /// The ID in Google Drive of the file to delete. None for uploads and updates
+    #[serde(skip)]
+    pub id:         Option<String>
+}
+
+/// The plan of a sync as it is written as JSON
+#[derive(Serialize, Debug)]
+struct JsonPlan<'a> {
+    /// The number of new files which will be uploaded
+    new_files:      usize,
+
+    /// The number of files which will be updated
+    updated_files:  usize,
+
+    /// The number of files and folders which will be deleted
+    deletions:      usize,
+
+    /// The number of files which are up-to-date
+    unchanged:      u64,
+
+    /// The total number of bytes which will be uploaded
+    upload_bytes:   u64,
+
+    /// The changes
+    actions:        &'a [PlannedAction],
+
+    /// The files which are skipped because they are larger than the maximum size
+    oversized:      &'a [Oversized]
+}
+
+/// The changes a sync will make. The plan is estimated from the local files and the files table, so no requests to Google are made
+#[derive(Debug, Clone, Default)]
+pub struct Plan {
+    /// The changes, in traversal order followed by the deletions
+    pub actions:    Vec<PlannedAction>,
+
+    /// The number of files which are up-to-date
+    pub unchanged:  u64,
+
+    /// The number of tracked files within the inputs, i.e. the files which could be deleted
+    pub tracked:    u64,
+
+    /// The files which are skipped because they are larger than the maximum size. They are not changes, so they don't make the plan non-empty
+    pub oversized:  Vec<Oversized>
+}
+
+impl Plan {
+    /// The number of new files which will be uploaded
+    pub fn new_files(&self) -> usize {
+        self.actions.iter().filter(|action| action.action == Action::Upload && !action.is_folder).count()
+    }
+
+    /// The number of files which will be updated
+    pub fn updated_files(&self) -> usize {
+        self.actions.iter().filter(|action| action.action == Action::Update).count()
+    }
+
+    /// The files and folders which will be deleted from Drive
+    pub fn deletions(&self) -> impl Iterator<Item = &PlannedAction> {
+        self.actions.iter().filter(|action| action.action == Action::Delete)
+    }
+
+    /// Keep the files within the provided paths in Drive, by removing their deletions from the plan
+    pub fn keep_within(&mut self, paths: &[PathBuf]) {
+        self.actions.retain(|action| action.action != Action::Delete || !is_within_inputs(Path::new(&action.path), paths));
+    }
+
+    /// The number of files, not counting folders, which will be deleted from Drive
+    pub fn deleted_files(&self) -> u64 {
+        self.deletions().filter(|action| !action.is_folder).count() as u64
+    }
+
+    /// Whether the plan deletes more files than the limit allows
+    pub fn exceeds_delete_limit(&self, limit: DeleteLimit) -> bool {
+        let deleted = self.deleted_files();
+        match limit {
+            DeleteLimit::Count(count) => deleted > count,
+            DeleteLimit::Percent(percent) => deleted * 100 > self.tracked * percent
+        }
+    }
+
+    /// The total number of bytes which will be uploaded
+    pub fn upload_bytes(&self) -> u64 {
+        self.actions.iter().map(|action| action.size).sum()
+    }
+
+    /// Whether the sync would not change anything
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+
+    /// A listing of all changes, one per line, e.g. `upload  /home/user/notes.txt (1.5 KiB) - not in Drive yet`
+    pub fn listing(&self) -> String {
+        let mut text = String::new();
+        for action in self.actions.iter() {
+            let path = if action.is_folder { format!("{}/", action.path) } else { action.path.clone() };
+            match action.action {
+                Action::Delete => text.push_str(&format!("{:<7} {} - {}\n", action.action, path, action.reason)),
+                _ => text.push_str(&format!("{:<7} {} ({}) - {}\n", action.action, path, format_size(action.size), action.reason))
+            }
+        }
+
+        for file in self.oversized.iter() {
+            text.push_str(&format!("{:<7} {} ({}) - larger than the maximum size of {}\n", "skip", file.path, format_size(file.size), format_size(file.max_size)));
+        }
+
+        text
+    }
+
+    /// The plan as pretty printed JSON, for wrapper tools
+    ///
+    /// ## Errors
+    /// - When serializing fails
+    pub fn to_json(&self) -> Result<String> {
+        let json = JsonPlan {
+            new_files:      self.new_files(),
+            updated_files:  self.updated_files(),
+            deletions:      self.deletions().count(),
+            unchanged:      self.unchanged,
+            upload_bytes:   self.upload_bytes(),
+            actions:        &self.actions,
+            oversized:      &self.oversized
+        };
+
+        Ok(unwrap_other_err!(serde_json::to_string_pretty(&json)))
+    }
+
+    /// A one line summary of the plan, e.g. `3 new files, 1 updated, 2 deletions, 1.5 GiB to upload`
+    pub fn summary(&self) -> String {
+        let summary = format!("{} new files, {} updated, {} deletions, {} to upload", self.new_files(), self.updated_files(), self.deletions().count(), format_size(self.upload_bytes()));
+        match self.oversized.len() {
+            0 => summary,
+            count => format!("{}, {} too large to sync", summary, count)
+        }
+    }
+}
+
+/// Build the plan for a sync
+///
+/// ## Params
+/// - `local` The files and folders found while traversing the inputs
+/// - `inputs` The configured inputs. Only tracked files within an input are considered for deletion
+/// - `tracked` The files currently in the files table
+/// - `hashes` The checksums stored for the tracked files by their path, see [crate::hash::stored_all]
+/// - `cache` Keeps the checksums of the files the plan compares, so the sync doesn't hash them again
+/// - `tolerance` The number of seconds modification times may differ while still being considered the same
+pub fn build(local: &[LocalEntry], inputs: &[PathBuf], tracked: &[TrackedFile], hashes: &HashMap<String, (HashAlgorithm, String)>, cache: &HashCache, tolerance: i64) -> Plan {
+    let mut plan = Plan::default();
+    let local_paths = local.iter().map(|entry| entry.path.to_string_lossy().to_string()).collect::<HashSet<String>>();
+    let tracked_by_path = tracked.iter().map(|file| (file.path.as_str(), file)).collect::<HashMap<&str, &TrackedFile>>();
+
+    for entry in local {
+        let path = entry.path.to_string_lossy();
+        let action = match tracked_by_path.get(path.as_ref()) {
+            None => Some((Action::Upload, "not in Drive yet")),
+            Some(_) if entry.is_folder => None,
+            Some(file) => match (SyncedCopy::tracked(file, hashes.get(&file.path).cloned()), entry.modified) {
+                (Some(copy), Some(modified)) => {
+                    // The same check as the sync itself makes. A file which can't be compared is planned as updated, the sync will report the failure
+                    match compare::changed(entry.compare, tolerance, modified, entry.size, &copy, |algorithm| cache.hash_file(&entry.path, &entry.path, algorithm)) {
+                        Ok(false) => None,
+                        _ => Some((Action::Update, "modified since the last sync"))
+                    }
+                },
+                _ => Some((Action::Update, "time of the last sync is unknown"))
+            }
+        };
+
+        match action {
+            Some((action, reason)) => plan.actions.push(PlannedAction {
+                action,
+                path:       path.to_string(),
+                is_folder:  entry.is_folder,
+                size:       entry.size,
+                reason,
+                id:         None
+            }),
+            None if !entry.is_folder => plan.unchanged += 1,
+            None => {}
+        }
+    }
+
+    for file in tracked {
+        if !is_within_inputs(Path::new(&file.path), inputs) {
+            continue;
+        }
+
+        if !file.is_folder {
+            plan.tracked += 1;
+        }
+
+        if local_paths.contains(file.path.as_str()) {
+            continue;
+        }
+
+        plan.actions.push(PlannedAction {
+            action:     Action::Delete,
+            path:       file.path.clone(),
+            is_folder:  file.is_folder,
+            size:       0,
+            reason:     if Path::new(&file.path).exists() { "excluded from the sync" } else { "removed locally" },
+            id:         Some(file.id.clone())
+        });
+    }
+
+    plan
+}
+
+/// Check if a path is one of the inputs, or within one of them
+fn is_within_inputs(path: &Path, inputs: &[PathBuf]) -> bool {
+    inputs.iter().any(|input| path.starts_with(input))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::plan::{build, Action, DeleteLimit, LocalEntry};
+    use crate::files::TrackedFile;
+    use crate::compare::ComparePolicy;
+    use crate::hash::{HashAlgorithm, HashCache};
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    fn local(path: &str, modified: i64) -> LocalEntry {
+        LocalEntry { path: PathBuf::from(path), is_folder: false, size: 10, modified: Some(modified), compare: ComparePolicy::Mtime }
+    }
+
+    fn tracked(path: &str, synced_at: i64) -> TrackedFile {
+        TrackedFile { path: path.to_string(), id: format!("id-{}", path), parent_id: "root".to_string(), is_folder: false, link_target: None, size: Some(10), md5: None, synced_at: Some(synced_at) }
+    }
+
+    #[test]
+    fn classifies_new_updated_and_unchanged() {
+        let plan = build(&[local("/in/a", 100), local("/in/b", 200), local("/in/c", 100)], &[PathBuf::from("/in")], &[tracked("/in/b", 150), tracked("/in/c", 150)], &HashMap::new(), &HashCache::default(), 0);
+        assert_eq!(plan.new_files(), 1);
+        assert_eq!(plan.updated_files(), 1);
+        assert_eq!(plan.unchanged, 1);
+        assert_eq!(plan.upload_bytes(), 20);
+    }
+
+    #[test]
+    fn deletes_only_within_inputs() {
+        let plan = build(&[], &[PathBuf::from("/in")], &[tracked("/in/gone", 100), tracked("/elsewhere/file", 100)], &HashMap::new(), &HashCache::default(), 0);
+        let deletions = plan.deletions().collect::<Vec<_>>();
+        assert_eq!(deletions.len(), 1);
+        assert_eq!(deletions[0].action, Action::Delete);
+        assert_eq!(deletions[0].id.as_deref(), Some("id-/in/gone"));
+        assert_eq!(deletions[0].reason, "removed locally");
+    }
+
+    #[test]
+    fn keeps_skipped_paths() {
+        let mut plan = build(&[], &[PathBuf::from("/in")], &[tracked("/in/unreadable/file", 100), tracked("/in/gone", 100)], &HashMap::new(), &HashCache::default(), 0);
+        plan.keep_within(&[PathBuf::from("/in/unreadable")]);
+
+        let deletions = plan.deletions().collect::<Vec<_>>();
+        assert_eq!(deletions.len(), 1);
+        assert_eq!(deletions[0].path, "/in/gone");
+    }
+
+    #[test]
+    fn parse_delete_limit() {
+        assert_eq!(DeleteLimit::parse("100"), Some(DeleteLimit::Count(100)));
+        assert_eq!(DeleteLimit::parse("10%"), Some(DeleteLimit::Percent(10)));
+        assert_eq!(DeleteLimit::parse("150%"), None);
+        assert_eq!(DeleteLimit::parse("many"), None);
+    }
+
+    #[test]
+    fn mass_delete_exceeds_limit() {
+        let tracked = (0..10).map(|i| tracked(&format!("/in/{}", i), 100)).collect::<Vec<_>>();
+        let plan = build(&[local("/in/0", 100), local("/in/1", 100)], &[PathBuf::from("/in")], &tracked, &HashMap::new(), &HashCache::default(), 0);
+        assert!(plan.exceeds_delete_limit(DeleteLimit::Percent(50)));
+        assert!(!plan.exceeds_delete_limit(DeleteLimit::Count(8)));
+    }
+
+    #[test]
+    fn compares_stored_checksums() {
+        let dir = std::env::temp_dir().join(format!("gsync-plan-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("file");
+        std::fs::write(&path, b"abc").unwrap();
+
+        let path = path.to_string_lossy().to_string();
+        let entry = LocalEntry { compare: ComparePolicy::Hash, ..local(&path, 200) };
+        let same = vec![(path.clone(), (HashAlgorithm::Md5, "900150983cd24fb0d6963f7d28e17f72".to_string()))].into_iter().collect::<HashMap<_, _>>();
+        let other = vec![(path.clone(), (HashAlgorithm::Md5, "0".repeat(32)))].into_iter().collect::<HashMap<_, _>>();
+
+        let inputs = [dir.clone()];
+        let touched = build(std::slice::from_ref(&entry), &inputs, &[tracked(&path, 150)], &same, &HashCache::default(), 0);
+        let changed = build(&[entry], &inputs, &[tracked(&path, 150)], &other, &HashCache::default(), 0);
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert_eq!(touched.unchanged, 1);
+        assert_eq!(changed.updated_files(), 1);
+    }
+}
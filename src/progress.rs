@@ -0,0 +1,127 @@
+//! Module for following the transfers in progress. Uploads are registered here while their content is being read,
+//! so their progress can be shown while a sync runs, e.g. in the TUI
+
+use lazy_static::lazy_static;
+use std::io::Read;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+lazy_static! {
+    /// The transfers in progress, by a unique ID
+    static ref ACTIVE: Mutex<Vec<(u64, Arc<TransferState>)>> = Mutex::new(Vec::new());
+}
+
+/// The ID given to the next registered transfer
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Struct describing the state of a transfer in progress
+#[derive(Debug)]
+struct TransferState {
+    /// The local path of the file being transferred
+    path:   String,
+
+    /// The size of the file in bytes
+    total:  u64,
+
+    /// The number of bytes transferred so far
+    done:   AtomicU64
+}
+
+/// Struct describing the progress of a transfer, at the moment it was requested
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transfer {
+    /// The unique ID of the transfer
+    pub id:     u64,
+
+    /// The local path of the file being transferred
+    pub path:   String,
+
+    /// The size of the file in bytes
+    pub total:  u64,
+
+    /// The number of bytes transferred so far
+    pub done:   u64
+}
+
+/// A reader which registers the transfer of its content, and updates its progress as it is read.
+/// The transfer is unregistered when the reader is dropped
+#[derive(Debug)]
+pub struct TrackedReader<R> {
+    /// The reader of the content being transferred
+    inner:  R,
+
+    /// The ID of the transfer
+    id:     u64,
+
+    /// The state of the transfer
+    state:  Arc<TransferState>
+}
+
+/// Register the transfer of a file, its progress is updated as the returned reader is read
+///
+/// ## Params
+/// - `path` The local path of the file
+/// - `total` The size of the file in bytes
+/// - `inner` The reader of the file's content
+pub fn track<R: Read>(path: &str, total: u64, inner: R) -> TrackedReader<R> {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let state = Arc::new(TransferState {
+        path:   path.to_string(),
+        total,
+        done:   AtomicU64::new(0)
+    });
+
+    ACTIVE.lock().unwrap().push((id, state.clone()));
+    TrackedReader {
+        inner,
+        id,
+        state
+    }
+}
+
+/// Get the transfers in progress, oldest first
+pub fn active() -> Vec<Transfer> {
+    ACTIVE.lock().unwrap().iter()
+        .map(|(id, state)| Transfer {
+            id:     *id,
+            path:   state.path.clone(),
+            total:  state.total,
+            done:   state.done.load(Ordering::Relaxed)
+        })
+        .collect()
+}
+
+impl<R: Read> Read for TrackedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.state.done.fetch_add(read as u64, Ordering::Relaxed);
+        Ok(read)
+    }
+}
+
+impl<R> Drop for TrackedReader<R> {
+    fn drop(&mut self) {
+        ACTIVE.lock().unwrap().retain(|(id, _)| *id != self.id);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::progress::{active, track};
+    use std::io::Read;
+
+    #[test]
+    fn tracks_reads() {
+        let path = "/tmp/gsync-progress-test";
+        let mut reader = track(path, 4, &b"test"[..]);
+        let mut buf = [0u8; 2];
+        reader.read_exact(&mut buf).unwrap();
+
+        let transfer = active().into_iter().find(|transfer| transfer.path.eq(path)).unwrap();
+        assert_eq!((2, 4), (transfer.done, transfer.total));
+
+        drop(reader);
+        assert!(active().iter().all(|transfer| transfer.path.ne(path)));
+    }
+}
@@ -0,0 +1,45 @@
+//! Module for checking the Google Drive storage quota before syncing
+
+use crate::config::Configuration;
+use crate::env::Env;
+use crate::{Result, Error};
+use crate::api::drive;
+use crate::size::format_size;
+
+/// The default percentage of the storage quota that may be in use after a sync
+const DEFAULT_THRESHOLD: i64 = 100;
+
+/// Check if uploading the planned amount of bytes would exceed the configured percentage of the storage quota.
+/// Depending on the configured quota action, a warning is printed or an error is returned
+///
+/// ## Errors
+/// - When the Google API returns an error
+/// - When reqwest returns an error
+/// - When the planned upload would exceed the threshold, and the quota action is `abort`
+pub fn check(config: &Configuration, env: &Env, planned_bytes: u64) -> Result<()> {
+    let quota = drive::blocking::get_storage_quota(env)?;
+    let limit = match quota.limit() {
+        Some(limit) => limit,
+        // The account has unlimited storage
+        None => return Ok(())
+    };
+
+    let threshold = config.quota_threshold.unwrap_or(DEFAULT_THRESHOLD);
+    let allowed = (limit as f64 * (threshold as f64 / 100.0)) as u64;
+    let usage_after = quota.usage() + planned_bytes;
+
+    println!("Info: Planning to upload {} of new and changed files. Storage used: {} of {}", format_size(planned_bytes), format_size(quota.usage()), format_size(limit));
+
+    if usage_after <= allowed {
+        return Ok(());
+    }
+
+    let message = format!("Uploading {} would bring the storage usage to {} of {}, which exceeds the threshold of {}%", format_size(planned_bytes), format_size(usage_after), format_size(limit), threshold);
+    match config.quota_action.as_deref() {
+        Some("warn") => {
+            println!("Warning: {}", message);
+            Ok(())
+        },
+        _ => Err((Error::Other(message), line!(), file!()))
+    }
+}
@@ -0,0 +1,160 @@
+//! Module for keeping secrets out of the output of GSync.
+//! Client secrets, access tokens, refresh tokens and authorization codes are masked in everything that is printed, logged or stored in reports
+
+use lazy_static::lazy_static;
+use std::sync::Mutex;
+
+/// What a secret is replaced with
+const MASK: &str = "[REDACTED]";
+
+/// Prefixes of secrets issued by Google: access tokens, refresh tokens, client secrets and authorization codes
+const SECRET_PREFIXES: &[&str] = &["ya29.", "1//", "GOCSPX-", "4/0A"];
+
+/// Names of fields and query parameters whose values are secret
+const SECRET_KEYS: &[&str] = &["access_token", "refresh_token", "client_secret", "code", "code_verifier", "assertion", "private_key", "Bearer"];
+
+/// Secrets shorter than this are not registered, masking them would mangle unrelated text
+const MIN_SECRET_LEN: usize = 8;
+
+lazy_static! {
+    /// Secrets known to this process, e.g. the configured client secret and the current access token
+    static ref SECRETS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+}
+
+/// Register a secret, so it is masked wherever it appears in output passed through [redact]
+pub fn register<S: AsRef<str>>(secret: S) {
+    let secret = secret.as_ref();
+    if secret.len() < MIN_SECRET_LEN {
+        return;
+    }
+
+    let mut secrets = SECRETS.lock().unwrap();
+    if !secrets.iter().any(|known| known == secret) {
+        secrets.push(secret.to_string());
+    }
+}
+
+/// Mask all secrets in a piece of text: registered secrets, values of secret fields and query parameters, and anything that looks like a secret issued by Google
+pub fn redact<S: AsRef<str>>(text: S) -> String {
+    let mut text = text.as_ref().to_string();
+    for secret in SECRETS.lock().unwrap().iter() {
+        text = text.replace(secret.as_str(), MASK);
+    }
+
+    for key in SECRET_KEYS {
+        text = mask_values_of(&text, key);
+    }
+
+    for prefix in SECRET_PREFIXES {
+        text = mask_prefixed(&text, prefix);
+    }
+
+    text
+}
+
+/// Mask a secret for display, keeping only its first characters so the user can recognize it
+pub fn mask<S: AsRef<str>>(secret: S) -> String {
+    let start = secret.as_ref().chars().take(4).collect::<String>();
+    format!("{}****", start)
+}
+
+/// Whether a character can be part of a secret
+fn is_secret_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "._-/~+%=".contains(c)
+}
+
+/// Mask the values following `key`, as in `key=value`, `"key": "value"` and `key value`
+fn mask_values_of(text: &str, key: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(index) = rest.find(key) {
+        let (before, after) = rest.split_at(index + key.len());
+        result.push_str(before);
+
+        // Only match keys of fields and query parameters, e.g. not 'code' in 'encode' or 'Status code: 404'. Bearer tokens follow a space
+        let previous = before[..index].chars().last();
+        let starts_word = if key == "Bearer" {
+            previous.is_none_or(|c| !c.is_ascii_alphanumeric())
+        } else {
+            previous.is_none_or(|c| "\"?&".contains(c))
+        };
+
+        // Skip the separator between key and value, including escaped quotes in debug output
+        let separator_len = after.find(|c: char| !"\"\\: =".contains(c)).unwrap_or(after.len());
+        let (separator, value) = after.split_at(separator_len);
+        let value_len = value.find(|c: char| !is_secret_char(c)).unwrap_or(value.len());
+
+        if !starts_word || separator.is_empty() || value_len == 0 {
+            rest = after;
+            continue;
+        }
+
+        result.push_str(separator);
+        result.push_str(MASK);
+        rest = &value[value_len..];
+    }
+
+    result.push_str(rest);
+    result
+}
+
+/// Mask everything which starts with `prefix`, keeping the prefix so it is clear what kind of secret was masked
+fn mask_prefixed(text: &str, prefix: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(index) = rest.find(prefix) {
+        let (before, after) = rest.split_at(index + prefix.len());
+        result.push_str(before);
+
+        let value_len = after.find(|c: char| !is_secret_char(c)).unwrap_or(after.len());
+        let starts_word = before[..index].chars().last().is_none_or(|c| !is_secret_char(c));
+        if starts_word && value_len > 0 {
+            result.push_str(MASK);
+        } else {
+            result.push_str(&after[..value_len]);
+        }
+
+        rest = &after[value_len..];
+    }
+
+    result.push_str(rest);
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use crate::redact::{redact, mask};
+
+    #[test]
+    fn redact_query_parameters() {
+        assert_eq!("https://oauth2.googleapis.com/token?code=[REDACTED]&client_secret=[REDACTED]&grant_type=authorization_code", redact("https://oauth2.googleapis.com/token?code=abc123&client_secret=def456&grant_type=authorization_code"));
+    }
+
+    #[test]
+    fn redact_json_fields() {
+        assert_eq!(r#"{"access_token": "[REDACTED]", "expires_in": 3599}"#, redact(r#"{"access_token": "abc.def", "expires_in": 3599}"#));
+    }
+
+    #[test]
+    fn redact_bearer() {
+        assert_eq!("Authorization: Bearer [REDACTED]", redact("Authorization: Bearer abcdef"));
+    }
+
+    #[test]
+    fn redact_google_prefixes() {
+        assert_eq!("token ya29.[REDACTED] expired", redact("token ya29.a0AfH6SMB expired"));
+    }
+
+    #[test]
+    fn redact_keeps_other_text() {
+        assert_eq!("Failed to encode the file 'barcode.txt'", redact("Failed to encode the file 'barcode.txt'"));
+        assert_eq!("Status code: 404, exit code 1", redact("Status code: 404, exit code 1"));
+    }
+
+    #[test]
+    fn mask_keeps_start() {
+        assert_eq!("GOCS****", mask("GOCSPX-abcdef"));
+    }
+}
@@ -0,0 +1,430 @@
+//! Module for the report of a sync run, which is printed at the end of every run, stored in the run history and optionally uploaded to Drive
+
+use crate::env::Env;
+use crate::{Result, unwrap_db_err, unwrap_other_err};
+use crate::api::drive;
+use crate::size::format_size;
+use chrono::{DateTime, Utc};
+use rusqlite::named_params;
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// The name of the folder under the GSync root folder in which reports are uploaded
+const REPORTS_FOLDER: &str = "_reports";
+
+/// Struct describing the report of a sync run. Counters may be updated concurrently
+#[derive(Debug)]
+pub struct RunReport {
+    /// The moment the run started
+    started:    DateTime<Utc>,
+
+    /// The number of new files uploaded
+    uploaded:   AtomicU64,
+
+    /// The number of changed files uploaded
+    updated:    AtomicU64,
+
+    /// The number of files which were up-to-date
+    unchanged:  AtomicU64,
+
+    /// The number of folders created
+    folders:    AtomicU64,
+
+    /// The number of hard links synced as shortcuts
+    links:      AtomicU64,
+
+    /// The number of files and folders deleted from Drive because they were removed locally
+    deleted:    AtomicU64,
+
+    /// The total number of bytes uploaded
+    bytes:      AtomicU64,
+
+    /// The number of files the plan of the run expects to sync, including the up-to-date files
+    planned_files: AtomicU64,
+
+    /// The number of bytes the plan of the run expects to upload
+    planned_bytes: AtomicU64,
+
+    /// Files which were skipped, with the reason why
+    skipped:    Mutex<Vec<(String, String)>>,
+
+    /// Local files and folders which were skipped because they couldn't be read, with the reason why
+    skipped_local: Mutex<Vec<(String, String)>>,
+
+    /// Files which failed to sync, with the error
+    failures:   Mutex<Vec<(String, String)>>,
+
+    /// Files and folders which were not synced because the budget of the run was used up
+    deferred:   Mutex<Vec<String>>
+}
+
+/// Struct describing a run in the run history
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RunRecord {
+    /// The epoch time in seconds at which the run started
+    pub started:    i64,
+
+    /// The epoch time in seconds at which the run finished
+    pub finished:   i64,
+
+    /// The number of new files uploaded
+    pub uploaded:   i64,
+
+    /// The number of changed files uploaded
+    pub updated:    i64,
+
+    /// The number of files which were up-to-date
+    pub unchanged:  i64,
+
+    /// The total number of bytes uploaded
+    pub bytes:      i64,
+
+    /// The number of files which failed to sync
+    pub failures:   i64,
+
+    /// Whether the run completed
+    pub success:    bool
+}
+
+/// Struct describing the progress of a running sync
+#[derive(Debug, Serialize)]
+pub struct Progress {
+    /// The epoch time in seconds at which the run started
+    pub started:    i64,
+
+    /// The number of new files uploaded so far
+    pub uploaded:   u64,
+
+    /// The number of changed files uploaded so far
+    pub updated:    u64,
+
+    /// The number of files which were up-to-date so far
+    pub unchanged:  u64,
+
+    /// The number of files and folders deleted from Drive so far
+    pub deleted:    u64,
+
+    /// The number of bytes uploaded so far
+    pub bytes:      u64,
+
+    /// The number of files which failed to sync so far
+    pub failures:   usize,
+
+    /// The number of files which were skipped or deferred so far
+    pub skipped:    usize,
+
+    /// The number of files the plan of the run expects to sync, including the up-to-date files
+    pub planned_files: u64,
+
+    /// The number of bytes the plan of the run expects to upload
+    pub planned_bytes: u64
+}
+
+impl Progress {
+    /// The number of files which were handled so far, whether they were synced, up-to-date, skipped or failed
+    pub fn processed(&self) -> u64 {
+        self.uploaded + self.updated + self.unchanged + self.failures as u64 + self.skipped as u64
+    }
+}
+
+impl RunReport {
+    /// Start a new report
+    pub fn new() -> Self {
+        Self {
+            started:    Utc::now(),
+            uploaded:   AtomicU64::new(0),
+            updated:    AtomicU64::new(0),
+            unchanged:  AtomicU64::new(0),
+            folders:    AtomicU64::new(0),
+            links:      AtomicU64::new(0),
+            deleted:    AtomicU64::new(0),
+            bytes:      AtomicU64::new(0),
+            planned_files: AtomicU64::new(0),
+            planned_bytes: AtomicU64::new(0),
+            skipped:    Mutex::new(Vec::new()),
+            skipped_local: Mutex::new(Vec::new()),
+            failures:   Mutex::new(Vec::new()),
+            deferred:   Mutex::new(Vec::new())
+        }
+    }
+
+    /// Record that a new file was uploaded
+    pub fn add_uploaded(&self, bytes: u64) {
+        self.uploaded.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Record that a changed file was uploaded
+    pub fn add_updated(&self, bytes: u64) {
+        self.updated.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Record that a file was up-to-date
+    pub fn add_unchanged(&self) {
+        self.unchanged.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record that a folder was created
+    pub fn add_folder(&self) {
+        self.folders.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record that a hard link was synced as a shortcut
+    pub fn add_link(&self) {
+        self.links.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record that a file or folder was deleted from Drive
+    pub fn add_deleted(&self) {
+        self.deleted.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record that a file was skipped
+    pub fn add_skipped<P: AsRef<Path>>(&self, path: P, reason: &str) {
+        self.skipped.lock().unwrap_or_else(PoisonError::into_inner).push((path.as_ref().to_string_lossy().to_string(), crate::redact::redact(reason)));
+    }
+
+    /// Record that a local file or folder was skipped because it couldn't be read, e.g. because of its permissions
+    pub fn add_skipped_local<P: AsRef<Path>>(&self, path: P, reason: &str) {
+        self.skipped_local.lock().unwrap_or_else(PoisonError::into_inner).push((path.as_ref().to_string_lossy().to_string(), crate::redact::redact(reason)));
+    }
+
+    /// Record that a file failed to sync
+    pub fn add_failure<P: AsRef<Path>>(&self, path: P, error: &str) {
+        self.failures.lock().unwrap_or_else(PoisonError::into_inner).push((path.as_ref().to_string_lossy().to_string(), crate::redact::redact(error)));
+    }
+
+    /// Record that a file or folder was not synced because the budget of the run was used up
+    pub fn add_deferred<P: AsRef<Path>>(&self, path: P) {
+        self.deferred.lock().unwrap_or_else(PoisonError::into_inner).push(path.as_ref().to_string_lossy().to_string());
+    }
+
+    /// The files and folders which were not synced because the budget of the run was used up
+    pub fn deferred(&self) -> Vec<String> {
+        self.deferred.lock().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    /// Record the totals the plan of the run expects, against which the progress of the run is shown
+    ///
+    /// ## Params
+    /// - `files` The number of files to sync, including the up-to-date files
+    /// - `bytes` The number of bytes to upload
+    pub fn set_planned(&self, files: u64, bytes: u64) {
+        self.planned_files.store(files, Ordering::Relaxed);
+        self.planned_bytes.store(bytes, Ordering::Relaxed);
+    }
+
+    /// The moment the run started
+    pub fn started(&self) -> DateTime<Utc> {
+        self.started
+    }
+
+    /// The counters of this report so far
+    pub fn snapshot(&self) -> Progress {
+        Progress {
+            started:    self.started.timestamp(),
+            uploaded:   self.uploaded.load(Ordering::Relaxed),
+            updated:    self.updated.load(Ordering::Relaxed),
+            unchanged:  self.unchanged.load(Ordering::Relaxed),
+            deleted:    self.deleted.load(Ordering::Relaxed),
+            bytes:      self.bytes.load(Ordering::Relaxed),
+            failures:   self.failures.lock().unwrap_or_else(PoisonError::into_inner).len(),
+            skipped:    self.skipped.lock().unwrap_or_else(PoisonError::into_inner).len() + self.deferred.lock().unwrap_or_else(PoisonError::into_inner).len(),
+            planned_files: self.planned_files.load(Ordering::Relaxed),
+            planned_bytes: self.planned_bytes.load(Ordering::Relaxed)
+        }
+    }
+
+    /// A one line summary of the progress so far, e.g. for showing the progress of a running sync
+    pub fn progress(&self) -> String {
+        format!("{} new, {} changed, {} up-to-date, {} deleted, {} uploaded, {} failures",
+            self.uploaded.load(Ordering::Relaxed),
+            self.updated.load(Ordering::Relaxed),
+            self.unchanged.load(Ordering::Relaxed),
+            self.deleted.load(Ordering::Relaxed),
+            format_size(self.bytes.load(Ordering::Relaxed)),
+            self.failures.lock().unwrap_or_else(PoisonError::into_inner).len())
+    }
+
+    /// Render the report as text
+    ///
+    /// ## Params
+    /// - `error` The error which aborted the run, if any
+    pub fn render(&self, error: Option<&str>) -> String {
+        let finished = Utc::now();
+        let mut text = String::new();
+        text.push_str(&format!("GSync run report{}\n", hostname().map(|hostname| format!(" for {}", hostname)).unwrap_or_default()));
+        text.push_str(&format!("Started:  {}\n", self.started.to_rfc3339()));
+        text.push_str(&format!("Finished: {} ({} seconds)\n", finished.to_rfc3339(), (finished - self.started).num_seconds()));
+        text.push_str(&format!("Result:   {}\n\n", match error {
+            Some(_) => "FAILED",
+            None if !self.failures.lock().unwrap_or_else(PoisonError::into_inner).is_empty() => "COMPLETED WITH FAILURES",
+            None => "SUCCESS"
+        }));
+
+        text.push_str(&format!("New files uploaded:     {}\n", self.uploaded.load(Ordering::Relaxed)));
+        text.push_str(&format!("Changed files uploaded: {}\n", self.updated.load(Ordering::Relaxed)));
+        text.push_str(&format!("Up-to-date files:       {}\n", self.unchanged.load(Ordering::Relaxed)));
+        text.push_str(&format!("Folders created:        {}\n", self.folders.load(Ordering::Relaxed)));
+        text.push_str(&format!("Hard links:             {}\n", self.links.load(Ordering::Relaxed)));
+        text.push_str(&format!("Deleted:                {}\n", self.deleted.load(Ordering::Relaxed)));
+        text.push_str(&format!("Uploaded:               {}\n", format_size(self.bytes.load(Ordering::Relaxed))));
+
+        let skipped = self.skipped.lock().unwrap_or_else(PoisonError::into_inner);
+        if !skipped.is_empty() {
+            text.push_str(&format!("\nSkipped ({}):\n", skipped.len()));
+            for (path, reason) in skipped.iter() {
+                text.push_str(&format!("- {}: {}\n", path, reason));
+            }
+        }
+
+        let skipped_local = self.skipped_local.lock().unwrap_or_else(PoisonError::into_inner);
+        if !skipped_local.is_empty() {
+            text.push_str(&format!("\nSkipped (local I/O) ({}):\n", skipped_local.len()));
+            for (path, reason) in skipped_local.iter() {
+                text.push_str(&format!("- {}: {}\n", path, reason));
+            }
+        }
+
+        let failures = self.failures.lock().unwrap_or_else(PoisonError::into_inner);
+        if !failures.is_empty() {
+            text.push_str(&format!("\nFailures ({}):\n", failures.len()));
+            for (path, error) in failures.iter() {
+                text.push_str(&format!("- {}: {}\n", path, error));
+            }
+        }
+
+        let deferred = self.deferred.lock().unwrap_or_else(PoisonError::into_inner);
+        if !deferred.is_empty() {
+            text.push_str(&format!("\nDeferred to the next run, the budget was used up ({}):\n", deferred.len()));
+            for path in deferred.iter() {
+                text.push_str(&format!("- {}\n", path));
+            }
+        }
+
+        if let Some(error) = error {
+            text.push_str(&format!("\nThe run was aborted: {}\n", error));
+        }
+
+        text
+    }
+
+    /// Store the run in the run history
+    ///
+    /// ## Params
+    /// - `success` Whether the run completed
+    ///
+    /// ## Errors
+    /// - When a database operation fails
+    pub fn save(&self, env: &Env, success: bool) -> Result<()> {
+        let run = RunRecord {
+            started:    self.started.timestamp(),
+            finished:   Utc::now().timestamp(),
+            uploaded:   self.uploaded.load(Ordering::Relaxed) as i64,
+            updated:    self.updated.load(Ordering::Relaxed) as i64,
+            unchanged:  self.unchanged.load(Ordering::Relaxed) as i64,
+            bytes:      self.bytes.load(Ordering::Relaxed) as i64,
+            failures:   self.failures.lock().unwrap_or_else(PoisonError::into_inner).len() as i64,
+            success
+        };
+
+        let conn = unwrap_db_err!(env.get_conn());
+        unwrap_db_err!(conn.execute("INSERT INTO runs (started, finished, uploaded, updated, unchanged, bytes, failures, success) VALUES (:started, :finished, :uploaded, :updated, :unchanged, :bytes, :failures, :success)", named_params! {
+            ":started": run.started,
+            ":finished": run.finished,
+            ":uploaded": run.uploaded,
+            ":updated": run.updated,
+            ":unchanged": run.unchanged,
+            ":bytes": run.bytes,
+            ":failures": run.failures,
+            ":success": run.success
+        }));
+
+        crate::stats::record(env, &run)
+    }
+
+    /// Upload the rendered report as a timestamped file to the reports folder under the GSync root folder
+    ///
+    /// ## Params
+    /// - `env` Env instance, with the root folder set
+    /// - `text` The rendered report
+    ///
+    /// ## Errors
+    /// - When writing the report to a temporary file fails
+    /// - When a Drive API call fails
+    pub fn upload(&self, env: &Env, text: &str) -> Result<()> {
+        let query = format!("name = '{}' and mimeType = 'application/vnd.google-apps.folder' and trashed = false and '{}' in parents", REPORTS_FOLDER, &env.root_folder);
+        let folder_id = match drive::blocking::list_files(env, Some(&query), env.drive_id.as_deref())?.into_iter().next() {
+            Some(folder) => folder.id,
+            None => drive::blocking::create_folder(env, REPORTS_FOLDER, &env.root_folder, &HashMap::new())?
+        };
+
+        let name = format!("{}{}.txt", self.started.format("%Y-%m-%dT%H-%M-%SZ"), hostname().map(|hostname| format!("-{}", hostname)).unwrap_or_default());
+
+        // The file name in Drive is taken from the local file, so the report is written to a temporary directory under its final name
+        let dir = std::env::temp_dir().join(format!("gsync-report-{}", std::process::id()));
+        unwrap_other_err!(std::fs::create_dir_all(&dir));
+        let path = dir.join(&name);
+        unwrap_other_err!(std::fs::write(&path, text));
+
+        let result = drive::blocking::upload_file(env, &path, &folder_id, &HashMap::new());
+        let _ = std::fs::remove_dir_all(&dir);
+        result?;
+
+        println!("Info: Uploaded the run report to '{}/{}'", REPORTS_FOLDER, name);
+        Ok(())
+    }
+}
+
+/// Get the most recent run from the run history
+///
+/// ## Errors
+/// - When a database operation fails
+pub fn last_run(env: &Env) -> Result<Option<RunRecord>> {
+    Ok(recent_runs(env, 1)?.into_iter().next())
+}
+
+/// Get the most recent runs from the run history, newest first
+///
+/// ## Params
+/// - `limit` The maximum number of runs to return
+///
+/// ## Errors
+/// - When a database operation fails
+pub fn recent_runs(env: &Env, limit: u32) -> Result<Vec<RunRecord>> {
+    let conn = unwrap_db_err!(env.get_conn());
+    let mut stmt = unwrap_db_err!(conn.prepare("SELECT started, finished, uploaded, updated, unchanged, bytes, failures, success FROM runs ORDER BY started DESC LIMIT :limit"));
+    let mut result = unwrap_db_err!(stmt.query(named_params! {
+        ":limit": limit
+    }));
+
+    let mut runs = Vec::new();
+    while let Some(row) = unwrap_db_err!(result.next()) {
+        runs.push(RunRecord {
+            started:    unwrap_db_err!(row.get::<&str, i64>("started")),
+            finished:   unwrap_db_err!(row.get::<&str, i64>("finished")),
+            uploaded:   unwrap_db_err!(row.get::<&str, i64>("uploaded")),
+            updated:    unwrap_db_err!(row.get::<&str, i64>("updated")),
+            unchanged:  unwrap_db_err!(row.get::<&str, i64>("unchanged")),
+            bytes:      unwrap_db_err!(row.get::<&str, i64>("bytes")),
+            failures:   unwrap_db_err!(row.get::<&str, i64>("failures")),
+            success:    unwrap_db_err!(row.get::<&str, bool>("success"))
+        });
+    }
+
+    Ok(runs)
+}
+
+/// Get the name of this machine, if it is known
+pub fn hostname() -> Option<String> {
+    std::env::var("HOSTNAME")
+        .or_else(|_| std::env::var("COMPUTERNAME"))
+        .or_else(|_| std::fs::read_to_string("/etc/hostname"))
+        .ok()
+        .map(|hostname| hostname.trim().to_string())
+        .filter(|hostname| !hostname.is_empty())
+}
@@ -0,0 +1,723 @@
+//! Module related to restoring files from Google Drive
+
+use crate::env::Env;
+use crate::{Result, Error, unwrap_other_err};
+use crate::api::drive;
+use crate::api::drive::{File, Revision, ShortcutDetails};
+use crate::size::format_size;
+use crate::files::{self, TrackedFile};
+use crate::metadata;
+use std::path::{Component, Path, PathBuf};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+
+/// The margin of free space required on top of the size of a restore, as a percentage of the size of the restore
+const FREE_SPACE_MARGIN_PERCENT: u64 = 5;
+
+/// Struct describing the options for a restore
+#[derive(Debug, Clone)]
+pub struct RestoreOptions {
+    /// If set, the UNIX timestamp (seconds) to restore to. For every file the newest revision not newer than this timestamp is restored
+    pub as_of:          Option<i64>,
+
+    /// The format Google-native documents are exported to
+    pub export_format:  ExportFormat,
+
+    /// Skip shortcuts instead of restoring the file they point to
+    pub skip_shortcuts: bool,
+
+    /// Restore the owner and group of files, this usually requires root privileges
+    pub restore_ownership: bool
+}
+
+/// Enum describing the formats Google-native documents (Docs, Sheets, Slides, Drawings) can be exported to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    /// Microsoft Office formats (docx, xlsx, pptx). Drawings are exported as png
+    Office,
+
+    /// OpenDocument formats (odt, ods, odp). Drawings are exported as svg
+    OpenDocument,
+
+    /// PDF for all documents
+    Pdf
+}
+
+impl ExportFormat {
+    /// Parse an export format from its name: `office`, `opendocument` or `pdf`
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "office" => Some(Self::Office),
+            "opendocument" => Some(Self::OpenDocument),
+            "pdf" => Some(Self::Pdf),
+            _ => None
+        }
+    }
+
+    /// Get the MIME type and file extension to export a Google-native document with the provided MIME type to.
+    /// Returns None if the document can not be exported
+    fn export_type(self, mime_type: &str) -> Option<(&'static str, &'static str)> {
+        match (mime_type, self) {
+            ("application/vnd.google-apps.document", Self::Office) => Some(("application/vnd.openxmlformats-officedocument.wordprocessingml.document", "docx")),
+            ("application/vnd.google-apps.document", Self::OpenDocument) => Some(("application/vnd.oasis.opendocument.text", "odt")),
+            ("application/vnd.google-apps.spreadsheet", Self::Office) => Some(("application/vnd.openxmlformats-officedocument.spreadsheetml.sheet", "xlsx")),
+            ("application/vnd.google-apps.spreadsheet", Self::OpenDocument) => Some(("application/x-vnd.oasis.opendocument.spreadsheet", "ods")),
+            ("application/vnd.google-apps.presentation", Self::Office) => Some(("application/vnd.openxmlformats-officedocument.presentationml.presentation", "pptx")),
+            ("application/vnd.google-apps.presentation", Self::OpenDocument) => Some(("application/vnd.oasis.opendocument.presentation", "odp")),
+            ("application/vnd.google-apps.drawing", Self::Office) => Some(("image/png", "png")),
+            ("application/vnd.google-apps.drawing", Self::OpenDocument) => Some(("image/svg+xml", "svg")),
+            ("application/vnd.google-apps.document", Self::Pdf)
+                | ("application/vnd.google-apps.spreadsheet", Self::Pdf)
+                | ("application/vnd.google-apps.presentation", Self::Pdf)
+                | ("application/vnd.google-apps.drawing", Self::Pdf) => Some(("application/pdf", "pdf")),
+            _ => None
+        }
+    }
+}
+
+/// Restore a file or folder from Google Drive to the local filesystem
+///
+/// ## Params
+/// - `env` Env instance, the root folder should be set
+/// - `remote_path` The path of the file or folder to restore, relative to the GSync root folder. An empty path restores everything
+/// - `target` The local directory to restore into
+/// - `options` The options for this restore
+///
+/// ## Errors
+/// - When the remote path does not exist
+/// - When a Drive API call fails
+/// - When an IO operation fails
+pub fn restore(env: &Env, remote_path: &str, target: &Path, options: &RestoreOptions) -> Result<()> {
+    let file = resolve_remote_path(env, remote_path)?;
+
+    if !target.exists() {
+        unwrap_other_err!(fs::create_dir_all(target));
+    }
+
+    println!("Info: Planning restore");
+    let mut steps = Vec::new();
+    plan_file(env, &file, target, options, &mut HashSet::new(), &mut steps)?;
+
+    let size = steps.iter().map(|step| step.size).sum::<u64>();
+    println!("Info: Restoring {} files and folders, {} in total", steps.len(), format_size(size));
+    check_free_space(target, size)?;
+
+    // The IDs of the restored files, and the path they were restored to
+    let mut restored: HashMap<String, PathBuf> = HashMap::new();
+    for step in &steps {
+        match &step.action {
+            RestoreAction::CreateDirectory => {
+                execute_step(env, step)?;
+                continue;
+            },
+            // Links are restored after all other files, so the target of a hard link is guaranteed to be restored already
+            RestoreAction::HardLink(_) | RestoreAction::Symlink(_) => continue,
+            _ => execute_step(env, step)?
+        }
+
+        metadata::apply(&step.path, &step.file.app_properties, options.restore_ownership);
+        restored.insert(step.file.id.clone(), step.path.clone());
+    }
+
+    for step in &steps {
+        match &step.action {
+            RestoreAction::HardLink(target_id) => restore_hard_link(env, step, target_id, &restored, options)?,
+            RestoreAction::Symlink(target) => restore_symlink(step, target)?,
+            _ => {}
+        }
+    }
+
+    // Directory metadata is applied last, in reverse order, because restoring their contents changes their modification time
+    for step in steps.iter().rev() {
+        if let RestoreAction::CreateDirectory = step.action {
+            metadata::apply(&step.path, &step.file.app_properties, options.restore_ownership);
+        }
+    }
+
+    Ok(())
+}
+
+/// Parse a timestamp provided by the user to a UNIX timestamp (seconds).
+/// Accepted are RFC 3339 timestamps (`2021-07-01T12:00:00+02:00`) and dates (`2021-07-01`), the latter are interpreted as midnight UTC
+///
+/// ## Errors
+/// - When the input is not a valid timestamp
+pub fn parse_timestamp(input: &str) -> Result<i64> {
+    if let Ok(datetime) = chrono::DateTime::parse_from_rfc3339(input) {
+        return Ok(datetime.timestamp());
+    }
+
+    let date = unwrap_other_err!(chrono::NaiveDate::parse_from_str(input, "%Y-%m-%d"));
+    Ok(date.and_hms(0, 0, 0).timestamp())
+}
+
+/// Resolve a path relative to the GSync root folder to a file in Google Drive. Files GSync synced are looked up in the files table,
+/// other paths, or tracked files which no longer exist in Drive, are resolved by listing the folders along the path
+///
+/// ## Errors
+/// - When the remote path does not exist
+/// - When a Drive API call fails
+/// - When a database operation fails
+pub fn resolve_remote_path(env: &Env, remote_path: &str) -> Result<File> {
+    let parts = split_remote_path(remote_path);
+    if !parts.is_empty() {
+        let tracked = files::get_all(env)?;
+        if let Some(id) = find_tracked(&tracked, &env.root_folder, &parts) {
+            // The files table may be outdated, e.g. when the file was removed in the web UI
+            if let Ok(file) = drive::blocking::get_file(env, id) {
+                if !file.trashed {
+                    return resolve_shortcut(env, file);
+                }
+            }
+        }
+    }
+
+    let mut current = File {
+        id:             env.root_folder.clone(),
+        name:           "GSync".to_string(),
+        modified_time:  String::new(),
+        mime_type:      "application/vnd.google-apps.folder".to_string(),
+        parents:        Vec::new(),
+        explicitly_trashed: false,
+        trashed:        false,
+        shortcut_details:   None,
+        size:           None,
+        md5_checksum:   None,
+        head_revision_id: None,
+        app_properties: HashMap::new(),
+        owned_by_me:    false,
+        capabilities:   None,
+        drive_id:       None
+    };
+
+    for part in parts {
+        if !current.is_folder() {
+            return Err((Error::Other(format!("Remote path '{}' does not exist: '{}' is not a folder", remote_path, &current.name)), line!(), file!()));
+        }
+
+        let query = format!("name = '{}' and '{}' in parents and trashed = false", drive::escape_query_value(part), &current.id);
+        let mut list = drive::blocking::list_files(env, Some(&query), env.drive_id.as_deref())?;
+        if list.is_empty() {
+            return Err((Error::Other(format!("Remote path '{}' does not exist", remote_path)), line!(), file!()));
+        }
+
+        current = resolve_shortcut(env, list.remove(0))?;
+    }
+
+    Ok(current)
+}
+
+/// Find the ID of the tracked file at a path relative to the GSync root folder. The path of a tracked file in Drive is made up of the names
+/// of the folders it is in, up to the root folder. Returns None if no tracked file is at the path
+///
+/// ## Params
+/// - `tracked` All tracked files
+/// - `root_folder` The ID of the GSync root folder
+/// - `parts` The components of the path
+fn find_tracked<'a>(tracked: &'a [TrackedFile], root_folder: &str, parts: &[&str]) -> Option<&'a str> {
+    let by_id = tracked.iter().map(|file| (file.id.as_str(), file)).collect::<HashMap<_, _>>();
+    let name = |file: &TrackedFile| Path::new(&file.path).file_name().map(|name| name.to_string_lossy().to_string());
+
+    tracked.iter()
+        .filter(|file| name(file).as_deref() == parts.last().copied())
+        .find(|file| {
+            // Walk up the parents, comparing their names with the path from the end
+            let mut current = *file;
+            for (depth, part) in parts.iter().enumerate().rev() {
+                if name(current).as_deref() != Some(*part) {
+                    return false;
+                }
+
+                if depth == 0 {
+                    return current.parent_id == root_folder;
+                }
+
+                current = match by_id.get(current.parent_id.as_str()) {
+                    Some(parent) => *parent,
+                    None => return false
+                };
+            }
+
+            false
+        })
+        .map(|file| file.id.as_str())
+}
+
+/// If the file is a shortcut, get the file it points to, under the name of the shortcut. Otherwise the file is returned as-is
+///
+/// ## Errors
+/// - When a Drive API call fails
+fn resolve_shortcut(env: &Env, file: File) -> Result<File> {
+    match &file.shortcut_details {
+        Some(details) if file.is_shortcut() => {
+            let mut target = drive::blocking::get_file(env, &details.target_id)?;
+            target.name = file.name;
+            Ok(target)
+        },
+        _ => Ok(file)
+    }
+}
+
+/// Whether a name of a file in Drive can be used as a local file name: a single component, not `.`, `..` or a path
+fn is_plain_name(name: &str) -> bool {
+    let mut components = Path::new(name).components();
+    matches!((components.next(), components.next()), (Some(Component::Normal(component)), None) if component == name)
+}
+
+/// Enum describing what to do with a single file during a restore
+#[derive(Debug)]
+enum RestoreAction {
+    /// Create the directory
+    CreateDirectory,
+
+    /// Download the current content of the file
+    Download,
+
+    /// Download the content of the file at the provided revision
+    DownloadRevision(Revision),
+
+    /// Export the Google-native document to the provided MIME type
+    Export(&'static str),
+
+    /// Create a hard link to the file with the provided ID
+    HardLink(String),
+
+    /// Create a symbolic link with the provided target
+    Symlink(String)
+}
+
+/// Struct describing a single step of a restore
+#[derive(Debug)]
+struct RestoreStep {
+    /// The file in Google Drive
+    file:   File,
+
+    /// The local path to restore the file to
+    path:   PathBuf,
+
+    /// What to do with the file
+    action: RestoreAction,
+
+    /// The amount of bytes that will be downloaded, if known
+    size:   u64
+}
+
+/// Plan the restore of a file into the target directory. If the file is a folder, its contents are planned recursively.
+/// `planning` holds the IDs of the folders being planned, the folder the file is in and its ancestors.
+/// A shortcut to one of them is skipped, as the folder would contain itself and be planned forever
+///
+/// ## Errors
+/// - When a Drive API call fails
+fn plan_file(env: &Env, file: &File, target: &Path, options: &RestoreOptions, planning: &mut HashSet<String>, steps: &mut Vec<RestoreStep>) -> Result<()> {
+    // Names come from Drive, a name like '../x' would otherwise be restored outside of the target
+    if !is_plain_name(&file.name) {
+        println!("Warning: Skipping '{}' (ID: {}), its name is not a valid file name", &file.name, &file.id);
+        return Ok(());
+    }
+
+    let path = target.join(&file.name);
+
+    if file.is_shortcut() {
+        if let Some(link_target) = file.app_properties.get(metadata::SYMLINK_TARGET) {
+            steps.push(RestoreStep { file: file.clone(), path, action: RestoreAction::Symlink(link_target.clone()), size: 0 });
+            return Ok(());
+        }
+
+        if let Some(target_id) = file.app_properties.get(metadata::HARD_LINK_TARGET) {
+            steps.push(RestoreStep { file: file.clone(), path, action: RestoreAction::HardLink(target_id.clone()), size: 0 });
+            return Ok(());
+        }
+
+        if options.skip_shortcuts {
+            println!("Warning: Skipping shortcut '{}'", path.to_str().unwrap_or(&file.name));
+            return Ok(());
+        }
+
+        // Known from the listing, so such a shortcut doesn't have to be fetched first
+        if file.shortcut_details.as_ref().is_some_and(ShortcutDetails::points_to_shortcut) {
+            println!("Warning: Skipping shortcut '{}', it points to another shortcut", path.to_str().unwrap_or(&file.name));
+            return Ok(());
+        }
+
+        if file.shortcut_details.as_ref().is_some_and(|details| planning.contains(&details.target_id)) {
+            println!("Warning: Skipping shortcut '{}', it points to a folder it is in", path.to_str().unwrap_or(&file.name));
+            return Ok(());
+        }
+
+        println!("Info: Resolving shortcut '{}'", path.to_str().unwrap_or(&file.name));
+        let resolved = resolve_shortcut(env, file.clone())?;
+        if resolved.is_shortcut() {
+            println!("Warning: Skipping shortcut '{}', it points to another shortcut", path.to_str().unwrap_or(&file.name));
+            return Ok(());
+        }
+
+        return plan_file(env, &resolved, target, options, planning, steps);
+    }
+
+    if file.is_folder() {
+        // Reached through a shortcut to a folder it is in
+        if !planning.insert(file.id.clone()) {
+            println!("Warning: Skipping folder '{}', it is inside itself", path.to_str().unwrap_or(&file.name));
+            return Ok(());
+        }
+
+        steps.push(RestoreStep { file: file.clone(), path: path.clone(), action: RestoreAction::CreateDirectory, size: 0 });
+
+        let children = drive::blocking::list_files(env, Some(&format!("'{}' in parents and trashed = false", &file.id)), env.drive_id.as_deref())?;
+        for child in children {
+            plan_file(env, &child, &path, options, planning, steps)?;
+        }
+
+        planning.remove(&file.id);
+    } else if file.mime_type.starts_with("application/vnd.google-apps.") {
+        let (mime_type, extension) = match options.export_format.export_type(&file.mime_type) {
+            Some(export_type) => export_type,
+            None => {
+                println!("Warning: Skipping file '{}', Google documents of type '{}' can not be exported", path.to_str().unwrap_or(&file.name), &file.mime_type);
+                return Ok(());
+            }
+        };
+
+        if options.as_of.is_some() {
+            println!("Warning: Version history is not supported for Google documents, restoring the current version of '{}'", path.to_str().unwrap_or(&file.name));
+        }
+
+        // The size of an export is not known up front. Files converted on upload already carry the extension
+        let name = if file.name.to_ascii_lowercase().ends_with(&format!(".{}", extension)) {
+            file.name.clone()
+        } else {
+            format!("{}.{}", &file.name, extension)
+        };
+        let path = target.join(name);
+        steps.push(RestoreStep { file: file.clone(), path, action: RestoreAction::Export(mime_type), size: 0 });
+    } else {
+        match options.as_of {
+            Some(as_of) => {
+                let revision = match find_revision(env, file, as_of)? {
+                    Some(revision) => revision,
+                    None => {
+                        println!("Info: Skipping file '{}', it did not exist at the requested time", path.to_str().unwrap_or(&file.name));
+                        return Ok(());
+                    }
+                };
+
+                let size = revision.size();
+                steps.push(RestoreStep { file: file.clone(), path, action: RestoreAction::DownloadRevision(revision), size });
+            },
+            None => steps.push(RestoreStep { file: file.clone(), path, action: RestoreAction::Download, size: file.size() })
+        }
+    }
+
+    Ok(())
+}
+
+/// Execute a single step of a restore.
+/// Files are downloaded to a temporary file next to the destination first, and are only moved into place once their checksum is verified
+///
+/// ## Errors
+/// - When a Drive API call fails
+/// - When an IO operation fails
+/// - When the checksum of a downloaded file does not match the checksum reported by Drive
+fn execute_step(env: &Env, step: &RestoreStep) -> Result<()> {
+    let path_str = step.path.to_str().unwrap_or(&step.file.name);
+
+    if let RestoreAction::CreateDirectory = step.action {
+        println!("Info: Restoring directory '{}'", path_str);
+        if !step.path.exists() {
+            unwrap_other_err!(fs::create_dir_all(&step.path));
+        }
+
+        return Ok(());
+    }
+
+    let temp_path = temp_path(&step.path);
+    let result = download_step(env, step, &temp_path);
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+        return result;
+    }
+
+    unwrap_other_err!(fs::rename(&temp_path, &step.path));
+    Ok(())
+}
+
+/// Restore a hard link. If the target of the link was restored, a hard link to it is created.
+/// Otherwise the content of the target is restored as a regular file
+///
+/// ## Errors
+/// - When a Drive API call fails
+/// - When an IO operation fails
+fn restore_hard_link(env: &Env, step: &RestoreStep, target_id: &str, restored: &HashMap<String, PathBuf>, options: &RestoreOptions) -> Result<()> {
+    let path_str = step.path.to_str().unwrap_or(&step.file.name);
+
+    match restored.get(target_id) {
+        Some(target_path) => {
+            println!("Info: Restoring hard link '{}' to '{}'", path_str, target_path.to_str().unwrap_or_default());
+            if step.path.exists() {
+                unwrap_other_err!(fs::remove_file(&step.path));
+            }
+
+            unwrap_other_err!(fs::hard_link(target_path, &step.path));
+        },
+        None => {
+            println!("Info: The target of hard link '{}' was not restored, restoring it as a regular file", path_str);
+            let mut target = drive::blocking::get_file(env, target_id)?;
+            target.name = step.file.name.clone();
+
+            let size = target.size();
+            let target_step = RestoreStep { file: target, path: step.path.clone(), action: RestoreAction::Download, size };
+            execute_step(env, &target_step)?;
+            metadata::apply(&target_step.path, &target_step.file.app_properties, options.restore_ownership);
+        }
+    }
+
+    Ok(())
+}
+
+/// Restore a symbolic link with the target it had when it was synced. Symbolic links are only restored on unix
+///
+/// ## Errors
+/// - When an IO operation fails
+fn restore_symlink(step: &RestoreStep, target: &str) -> Result<()> {
+    let path_str = step.path.to_str().unwrap_or(&step.file.name);
+
+    #[cfg(unix)]
+    {
+        println!("Info: Restoring symbolic link '{}' to '{}'", path_str, target);
+        if step.path.symlink_metadata().is_ok() {
+            unwrap_other_err!(fs::remove_file(&step.path));
+        }
+
+        unwrap_other_err!(std::os::unix::fs::symlink(target, &step.path));
+    }
+
+    #[cfg(not(unix))]
+    println!("Warning: Skipping symbolic link '{}' to '{}', symbolic links are only restored on unix", path_str, target);
+
+    Ok(())
+}
+
+/// Download the content of a restore step to the provided temporary path, and verify its checksum if Drive provides one
+///
+/// ## Errors
+/// - When a Drive API call fails
+/// - When an IO operation fails
+/// - When the checksum of the downloaded file does not match the checksum reported by Drive
+fn download_step(env: &Env, step: &RestoreStep, temp_path: &Path) -> Result<()> {
+    let path_str = step.path.to_str().unwrap_or(&step.file.name);
+
+    let expected_checksum = match &step.action {
+        RestoreAction::CreateDirectory | RestoreAction::HardLink(_) | RestoreAction::Symlink(_) => return Ok(()),
+        RestoreAction::Download => {
+            println!("Info: Restoring file '{}'", path_str);
+            drive::blocking::download_file(env, &step.file.id, temp_path)?;
+            step.file.md5_checksum.as_ref()
+        },
+        RestoreAction::DownloadRevision(revision) => {
+            println!("Info: Restoring file '{}' as of {}", path_str, &revision.modified_time);
+            drive::blocking::download_revision(env, &step.file.id, &revision.id, temp_path)?;
+            revision.md5_checksum.as_ref()
+        },
+        RestoreAction::Export(mime_type) => {
+            println!("Info: Exporting file '{}'", path_str);
+            drive::blocking::export_file(env, &step.file.id, mime_type, temp_path)?;
+            None
+        }
+    };
+
+    if let Some(expected_checksum) = expected_checksum {
+        let checksum = crate::hash::md5_file(temp_path)?;
+        if checksum.ne(expected_checksum) {
+            return Err((Error::Other(format!("Checksum mismatch for '{}': expected '{}', got '{}'", path_str, expected_checksum, checksum)), line!(), file!()));
+        }
+    }
+
+    // Files uploaded packed are unpacked. Other sparse files were uploaded with their holes as zeroes
+    if metadata::is_packed(&step.file.app_properties) && crate::sparse::unpack(temp_path)? {
+        println!("Info: Recreated sparse file '{}'", path_str);
+    } else if metadata::is_sparse(&step.file.app_properties) {
+        println!("Info: Recreating sparse file '{}'", path_str);
+        crate::sparse::sparsify(temp_path)?;
+    }
+
+    Ok(())
+}
+
+/// Get the path of the temporary file used while downloading to the provided path
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    path.with_file_name(format!(".{}.gsync-tmp", name))
+}
+
+/// Check if the filesystem containing the target directory has enough free space to store the provided amount of bytes, plus a margin
+///
+/// ## Errors
+/// - When the free space can not be determined
+/// - When there is not enough free space
+fn check_free_space(target: &Path, bytes: u64) -> Result<()> {
+    let available = unwrap_other_err!(fs2::available_space(target));
+    let required = bytes + (bytes * FREE_SPACE_MARGIN_PERCENT).div_ceil(100);
+
+    if available < required {
+        return Err((Error::Other(format!("Not enough free space in '{}': the restore needs {} (including a {}% margin), but only {} is available", target.to_string_lossy(), format_size(required), FREE_SPACE_MARGIN_PERCENT, format_size(available))), line!(), file!()));
+    }
+
+    Ok(())
+}
+
+/// Find the newest revision of a file which is not newer than the provided UNIX timestamp
+///
+/// ## Errors
+/// - When a Drive API call fails
+/// - When Drive returns an invalid timestamp
+fn find_revision(env: &Env, file: &File, as_of: i64) -> Result<Option<Revision>> {
+    let mut newest: Option<(i64, Revision)> = None;
+    for revision in drive::blocking::list_revisions(env, &file.id)? {
+        let modified_time = unwrap_other_err!(chrono::DateTime::parse_from_rfc3339(&revision.modified_time)).timestamp();
+        if modified_time > as_of {
+            continue;
+        }
+
+        let is_newer = match &newest {
+            Some((newest_time, _)) => modified_time > *newest_time,
+            None => true
+        };
+
+        if is_newer {
+            newest = Some((modified_time, revision));
+        }
+    }
+
+    Ok(newest.map(|(_, revision)| revision))
+}
+
+/// Split a remote path into its components, ignoring empty components
+fn split_remote_path(remote_path: &str) -> Vec<&str> {
+    remote_path.split('/')
+        .filter(|part| !part.is_empty() && part.ne(&"."))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::restore::{find_tracked, is_plain_name, split_remote_path, parse_timestamp, plan_file, ExportFormat, RestoreOptions};
+    use crate::api::drive::{File, ShortcutDetails};
+    use crate::files::TrackedFile;
+    use crate::env::Env;
+    use std::collections::{HashMap, HashSet};
+    use std::path::Path;
+
+    fn tracked(path: &str, id: &str, parent_id: &str) -> TrackedFile {
+        TrackedFile {
+            path:       path.to_string(),
+            id:         id.to_string(),
+            parent_id:  parent_id.to_string(),
+            is_folder:  false,
+            link_target: None,
+            size:       None,
+            md5:        None,
+            synced_at:  None
+        }
+    }
+
+    fn folder_shortcut(id: &str, name: &str, target_id: &str) -> File {
+        File {
+            id:             id.to_string(),
+            name:           name.to_string(),
+            modified_time:  String::new(),
+            mime_type:      "application/vnd.google-apps.shortcut".to_string(),
+            parents:        Vec::new(),
+            explicitly_trashed: false,
+            trashed:        false,
+            shortcut_details:   Some(ShortcutDetails {
+                target_id:          target_id.to_string(),
+                target_mime_type:   Some("application/vnd.google-apps.folder".to_string())
+            }),
+            size:           None,
+            md5_checksum:   None,
+            head_revision_id: None,
+            app_properties: HashMap::new(),
+            owned_by_me:    true,
+            capabilities:   None,
+            drive_id:       None
+        }
+    }
+
+    #[test]
+    fn self_referencing_shortcut() {
+        let options = RestoreOptions { as_of: None, export_format: ExportFormat::Office, skip_shortcuts: false, restore_ownership: false };
+        let mut planning = HashSet::from(["root".to_string(), "docs".to_string()]);
+        let mut steps = Vec::new();
+
+        // 'docs/again' and 'docs/up' point to 'docs' and the GSync root folder, both are being planned
+        plan_file(&Env::empty(), &folder_shortcut("again", "again", "docs"), Path::new("restore/docs"), &options, &mut planning, &mut steps).unwrap();
+        plan_file(&Env::empty(), &folder_shortcut("up", "up", "root"), Path::new("restore/docs"), &options, &mut planning, &mut steps).unwrap();
+        assert!(steps.is_empty());
+        assert_eq!(2, planning.len());
+    }
+
+    #[test]
+    fn find_tracked_by_remote_path() {
+        let files = vec![
+            tracked("/home/me/Documents", "docs", "root"),
+            tracked("/home/me/Documents/taxes", "taxes", "docs"),
+            tracked("/home/me/Documents/taxes/2023.pdf", "2023", "taxes"),
+            tracked("/srv/backup/taxes/2023.pdf", "other", "elsewhere")
+        ];
+
+        assert_eq!(Some("2023"), find_tracked(&files, "root", &["Documents", "taxes", "2023.pdf"]));
+        assert_eq!(Some("docs"), find_tracked(&files, "root", &["Documents"]));
+        assert_eq!(None, find_tracked(&files, "root", &["taxes", "2023.pdf"]));
+        assert_eq!(None, find_tracked(&files, "root", &["Documents", "2023.pdf"]));
+    }
+
+    #[test]
+    fn plain_names() {
+        assert!(is_plain_name("2023.pdf"));
+        assert!(is_plain_name("..hidden"));
+        assert!(!is_plain_name("../x"));
+        assert!(!is_plain_name(".."));
+        assert!(!is_plain_name("."));
+        assert!(!is_plain_name("/etc/passwd"));
+        assert!(!is_plain_name("taxes/2023.pdf"));
+        assert!(!is_plain_name("taxes/"));
+        assert!(!is_plain_name(""));
+    }
+
+    #[test]
+    fn split_remote_path_nested() {
+        assert_eq!(vec!["Documents", "taxes", "2023.pdf"], split_remote_path("Documents/taxes/2023.pdf"))
+    }
+
+    #[test]
+    fn split_remote_path_slashes() {
+        assert_eq!(vec!["Documents", "taxes"], split_remote_path("/Documents//taxes/"))
+    }
+
+    #[test]
+    fn split_remote_path_empty() {
+        assert!(split_remote_path("").is_empty())
+    }
+
+    #[test]
+    fn parse_timestamp_rfc3339() {
+        assert_eq!(1625140800, parse_timestamp("2021-07-01T12:00:00+00:00").unwrap())
+    }
+
+    #[test]
+    fn export_type_document() {
+        assert_eq!(Some(("application/pdf", "pdf")), ExportFormat::Pdf.export_type("application/vnd.google-apps.document"));
+        assert_eq!("docx", ExportFormat::Office.export_type("application/vnd.google-apps.document").unwrap().1);
+        assert_eq!("ods", ExportFormat::OpenDocument.export_type("application/vnd.google-apps.spreadsheet").unwrap().1);
+    }
+
+    #[test]
+    fn export_type_unsupported() {
+        assert_eq!(None, ExportFormat::Pdf.export_type("application/vnd.google-apps.form"));
+    }
+
+    #[test]
+    fn parse_timestamp_date() {
+        assert_eq!(1625097600, parse_timestamp("2021-07-01").unwrap())
+    }
+}
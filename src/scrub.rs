@@ -0,0 +1,218 @@
+//! Module for scrubbing: verifying that the files GSync uploaded are still in Google Drive with the content they were uploaded with.
+//! Every scrub verifies a batch of the tracked files, the ones verified longest ago first, so repeated scrubs rotate through all of them
+
+use crate::env::Env;
+use crate::api::drive;
+use crate::{Result, Error, unwrap_db_err};
+use chrono::Utc;
+use rusqlite::named_params;
+use serde::{Serialize, Deserialize};
+use std::time::Duration;
+
+/// The default number of days within which every tracked file is verified once by the daemon
+pub const DEFAULT_WINDOW_DAYS: i64 = 30;
+
+/// Struct describing a tracked file which failed verification
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Finding {
+    /// The epoch time in seconds at which the problem was found
+    pub time:       i64,
+
+    /// The local path of the file
+    pub path:       String,
+
+    /// The ID of the file in Google Drive
+    pub file_id:    String,
+
+    /// What is wrong with the file
+    pub problem:    String
+}
+
+/// The result of a single scrub
+#[derive(Debug, Default)]
+pub struct ScrubResult {
+    /// The number of files verified
+    pub checked:    usize,
+
+    /// The files which failed verification
+    pub findings:   Vec<Finding>
+}
+
+/// The number of files to verify per scrub, so that every tracked file is verified once within the window
+///
+/// ## Params
+/// - `total` The number of tracked files
+/// - `window` The time within which every file should be verified once
+/// - `interval` The time between two scrubs
+pub fn batch_size(total: usize, window: Duration, interval: Duration) -> usize {
+    if total == 0 {
+        return 0;
+    }
+
+    let scrubs = (window.as_secs() / interval.as_secs().max(1)).max(1) as usize;
+    total.div_ceil(scrubs)
+}
+
+/// The number of files to verify when scrubbing every `interval`, so every tracked file is verified once within the scrub window.
+/// Returns None when scrubbing is turned off, i.e. the window is 0 days or less
+///
+/// ## Params
+/// - `window_days` The configured scrub window in days, [DEFAULT_WINDOW_DAYS] when not set
+/// - `interval` The time between two scrubs
+///
+/// ## Errors
+/// - When a database operation fails
+pub fn due(env: &Env, window_days: Option<i64>, interval: Duration) -> Result<Option<usize>> {
+    let window = window_days.unwrap_or(DEFAULT_WINDOW_DAYS);
+    if window <= 0 {
+        return Ok(None);
+    }
+
+    Ok(Some(batch_size(count(env)?, Duration::from_secs(window as u64 * 24 * 60 * 60), interval)))
+}
+
+/// The number of tracked files which can be scrubbed
+///
+/// ## Errors
+/// - When a database operation fails
+pub fn count(env: &Env) -> Result<usize> {
+    let conn = unwrap_db_err!(env.get_conn());
+    let count = unwrap_db_err!(conn.query_row("SELECT COUNT(*) FROM files WHERE is_folder = 0 AND md5 IS NOT NULL AND link_target IS NULL", named_params! {}, |row| row.get::<usize, i64>(0)));
+    Ok(count as usize)
+}
+
+/// Verify up to `limit` tracked files, the ones verified longest ago first. A file which was never verified counts as verified when it was synced.
+/// A file fails verification if it is missing from Drive, is in the trash, or its checksum differs from the checksum recorded when it was uploaded.
+/// Findings are stored until the file passes verification again
+///
+/// ## Errors
+/// - When a Drive API call fails for another reason than the file not existing
+/// - When a database operation fails
+pub fn scrub(env: &Env, limit: usize) -> Result<ScrubResult> {
+    let candidates = {
+        let conn = unwrap_db_err!(env.get_conn());
+        let mut stmt = unwrap_db_err!(conn.prepare("SELECT path, id, md5 FROM files WHERE is_folder = 0 AND md5 IS NOT NULL AND link_target IS NULL ORDER BY COALESCE(scrubbed, synced_at, 0) ASC LIMIT :limit"));
+        let mut result = unwrap_db_err!(stmt.query(named_params! {
+            ":limit": limit as i64
+        }));
+
+        let mut candidates = Vec::new();
+        while let Some(row) = unwrap_db_err!(result.next()) {
+            candidates.push((
+                unwrap_db_err!(row.get::<&str, String>("path")),
+                unwrap_db_err!(row.get::<&str, String>("id")),
+                unwrap_db_err!(row.get::<&str, String>("md5"))
+            ));
+        }
+
+        candidates
+    };
+
+    let mut scrub_result = ScrubResult::default();
+    for (path, file_id, md5) in candidates {
+        let problem = match drive::blocking::get_file(env, &file_id) {
+            Ok(remote) if remote.trashed => Some("is in the trash".to_string()),
+            Ok(remote) => match remote.md5_checksum {
+                Some(checksum) if checksum == md5 => None,
+                Some(checksum) => Some(format!("has checksum {} instead of {}", checksum, md5)),
+                None => Some("has no checksum".to_string())
+            },
+            Err((Error::GoogleError(e), _, _)) if e.code == 404 => Some("is missing".to_string()),
+            Err(e) => return Err(e)
+        };
+
+        let now = Utc::now().timestamp();
+        let conn = unwrap_db_err!(env.get_conn());
+        unwrap_db_err!(conn.execute("UPDATE files SET scrubbed = :scrubbed WHERE path = :path", named_params! {
+            ":scrubbed": now,
+            ":path": &path
+        }));
+
+        match problem {
+            Some(problem) => {
+                let finding = Finding { time: now, path, file_id, problem };
+                unwrap_db_err!(conn.execute("INSERT OR REPLACE INTO scrub_findings (path, time, file_id, problem) VALUES (:path, :time, :file_id, :problem)", named_params! {
+                    ":path": &finding.path,
+                    ":time": finding.time,
+                    ":file_id": &finding.file_id,
+                    ":problem": &finding.problem
+                }));
+
+                scrub_result.findings.push(finding);
+            },
+            None => {
+                unwrap_db_err!(conn.execute("DELETE FROM scrub_findings WHERE path = :path", named_params! {
+                    ":path": &path
+                }));
+            }
+        }
+
+        scrub_result.checked += 1;
+    }
+
+    Ok(scrub_result)
+}
+
+/// Get the files which failed their last verification, newest first
+///
+/// ## Errors
+/// - When a database operation fails
+pub fn findings(env: &Env) -> Result<Vec<Finding>> {
+    let conn = unwrap_db_err!(env.get_conn());
+    let mut stmt = unwrap_db_err!(conn.prepare("SELECT path, time, file_id, problem FROM scrub_findings ORDER BY time DESC"));
+    let mut result = unwrap_db_err!(stmt.query(named_params! {}));
+
+    let mut findings = Vec::new();
+    while let Some(row) = unwrap_db_err!(result.next()) {
+        findings.push(Finding {
+            time:       unwrap_db_err!(row.get::<&str, i64>("time")),
+            path:       unwrap_db_err!(row.get::<&str, String>("path")),
+            file_id:    unwrap_db_err!(row.get::<&str, String>("file_id")),
+            problem:    unwrap_db_err!(row.get::<&str, String>("problem"))
+        });
+    }
+
+    Ok(findings)
+}
+
+/// The epoch time in seconds of the oldest verification of a tracked file, or None if not every tracked file was verified yet.
+/// Every file was verified at least once since this time
+///
+/// ## Errors
+/// - When a database operation fails
+pub fn oldest_verification(env: &Env) -> Result<Option<i64>> {
+    let conn = unwrap_db_err!(env.get_conn());
+    let (unverified, oldest) = unwrap_db_err!(conn.query_row("SELECT COALESCE(SUM(scrubbed IS NULL), 0), MIN(scrubbed) FROM files WHERE is_folder = 0 AND md5 IS NOT NULL AND link_target IS NULL", named_params! {}, |row| {
+        Ok((row.get::<usize, i64>(0)?, row.get::<usize, Option<i64>>(1)?))
+    }));
+
+    Ok(if unverified > 0 { None } else { oldest })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::env::Env;
+    use crate::scrub::{batch_size, due};
+    use std::time::Duration;
+
+    #[test]
+    fn batch_covers_all_files_within_window() {
+        let hour = Duration::from_secs(60 * 60);
+        let day = Duration::from_secs(24 * 60 * 60);
+
+        assert_eq!(batch_size(0, day, hour), 0);
+        assert_eq!(batch_size(48, day, hour), 2);
+        assert_eq!(batch_size(49, day, hour), 3);
+        assert_eq!(batch_size(5, day, hour), 1);
+        // An interval longer than the window verifies everything every time
+        assert_eq!(batch_size(100, hour, day), 100);
+    }
+
+    #[test]
+    fn disabled_window_verifies_nothing() {
+        let hour = Duration::from_secs(60 * 60);
+
+        assert_eq!(due(&Env::empty(), Some(0), hour).unwrap(), None);
+        assert_eq!(due(&Env::empty(), Some(-1), hour).unwrap(), None);
+    }
+}
@@ -0,0 +1,197 @@
+//! Module for browsing the GSync folder in Google Drive with a web browser, through a local HTTP server.
+//! Folders are listed and files are streamed from Drive on request
+
+use crate::env::Env;
+use crate::{Result, unwrap_other_err};
+use crate::api::drive::{self, File};
+use crate::login::callback_endpoint::escape_html;
+use crate::metadata;
+use actix_web::{get, web, App, HttpResponse, HttpServer};
+use actix_web::web::Bytes;
+use futures::SinkExt;
+use std::io::{Cursor, Read};
+
+/// The default port the server listens on
+pub const DEFAULT_PORT: u16 = 8080;
+
+/// The size of the chunks files are streamed to the browser in
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Serve the GSync folder on the provided address. This blocks until the server is stopped
+///
+/// ## Errors
+/// - When binding to the address fails
+pub fn serve(env: &Env, bind: &str, port: u16) -> Result<()> {
+    let env = env.clone();
+    let mut sys = actix_web::rt::System::new("GSync");
+    let server = unwrap_other_err!(HttpServer::new(move || {
+        App::new()
+            .data(env.clone())
+            .service(browse)
+    }).bind(format!("{}:{}", bind, port))).run();
+
+    println!("Info: Serving the GSync folder on http://{}:{}. Press Ctrl-C to stop", bind, port);
+    unwrap_other_err!(sys.block_on(server));
+    Ok(())
+}
+
+/// Show the listing of a folder, or download a file
+#[get("/{path:.*}")]
+async fn browse(env: web::Data<Env>, path: web::Path<String>) -> HttpResponse {
+    let path = path.into_inner();
+
+    let resolve_env = env.get_ref().clone();
+    let resolve_path = path.clone();
+    let file = match web::block(move || crate::restore::resolve_remote_path(&resolve_env, &resolve_path)).await {
+        Ok(file) => file,
+        Err(e) => return HttpResponse::NotFound().content_type("text/plain; charset=utf-8").body(crate::redact::redact(format!("{:?}", e)))
+    };
+
+    if file.is_folder() {
+        let list_env = env.get_ref().clone();
+        let id = file.id.clone();
+        match web::block(move || drive::blocking::list_files(&list_env, Some(&format!("'{}' in parents and trashed = false", id)), list_env.drive_id.as_deref())).await {
+            Ok(children) => HttpResponse::Ok().content_type("text/html; charset=utf-8").body(listing_page(&path, children)),
+            Err(e) => HttpResponse::BadGateway().content_type("text/plain; charset=utf-8").body(crate::redact::redact(format!("{:?}", e)))
+        }
+    } else {
+        stream_file(env.get_ref().clone(), file)
+    }
+}
+
+/// Stream the content of a file from Drive to the browser. The download runs on its own thread, sending chunks as they arrive.
+/// Files uploaded packed are unpacked while they are streamed
+fn stream_file(env: Env, file: File) -> HttpResponse {
+    let (mut tx, rx) = futures::channel::mpsc::channel::<std::result::Result<Bytes, std::io::Error>>(4);
+    let id = file.id.clone();
+    let packed = metadata::is_packed(&file.app_properties).then(|| file.size());
+
+    std::thread::spawn(move || {
+        let response = match crate::api::block_on(drive::download_stream(&env, &id)) {
+            Ok(response) => response,
+            Err((e, _, _)) => {
+                let _ = futures::executor::block_on(tx.send(Err(std::io::Error::other(crate::redact::redact(format!("{:?}", e))))));
+                return;
+            }
+        };
+
+        let body = ResponseReader { response, chunk: Cursor::new(Vec::new()) };
+        let mut reader: Box<dyn Read + Send> = if let Some(packed_len) = packed {
+            match crate::sparse::unpacking(body, packed_len) {
+                Ok(reader) => reader,
+                Err((e, _, _)) => {
+                    let _ = futures::executor::block_on(tx.send(Err(std::io::Error::other(crate::redact::redact(format!("{:?}", e))))));
+                    return;
+                }
+            }
+        } else {
+            Box::new(body)
+        };
+
+        let mut buffer = vec![0u8; CHUNK_SIZE];
+        loop {
+            let chunk = match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(read) => Ok(Bytes::copy_from_slice(&buffer[..read])),
+                Err(e) => Err(e)
+            };
+
+            let failed = chunk.is_err();
+            // The browser went away when sending fails
+            if futures::executor::block_on(tx.send(chunk)).is_err() || failed {
+                break;
+            }
+        }
+    });
+
+    HttpResponse::Ok()
+        .content_type(file.mime_type.as_str())
+        .header("Content-Disposition", format!("attachment; filename=\"{}\"", file.name.replace('"', "")))
+        .streaming(rx)
+}
+
+/// Reads the body of a download, waiting for each chunk as it arrives
+struct ResponseReader {
+    /// The response to the download
+    response:   reqwest::Response,
+
+    /// The part of the last chunk which has not been read yet
+    chunk:      Cursor<Vec<u8>>
+}
+
+impl Read for ResponseReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            let read = self.chunk.read(buf)?;
+            if read > 0 || buf.is_empty() {
+                return Ok(read);
+            }
+
+            match crate::api::block_on(self.response.chunk()) {
+                Ok(Some(chunk)) => self.chunk = Cursor::new(chunk.to_vec()),
+                Ok(None) => return Ok(0),
+                Err(e) => return Err(std::io::Error::other(e.to_string()))
+            }
+        }
+    }
+}
+
+/// Create the HTML listing of a folder
+fn listing_page(path: &str, mut children: Vec<File>) -> String {
+    children.sort_by(|a, b| b.is_folder().cmp(&a.is_folder()).then_with(|| a.name.cmp(&b.name)));
+    let base = path.trim_matches('/');
+
+    let mut rows = String::new();
+    if !base.is_empty() {
+        let parent = base.rsplit_once('/').map(|(parent, _)| parent).unwrap_or("");
+        rows.push_str(&format!("<tr><td><a href=\"/{}\">..</a></td><td></td></tr>\n", encode_path(parent)));
+    }
+
+    for child in children.iter() {
+        let child_path = if base.is_empty() { child.name.clone() } else { format!("{}/{}", base, child.name) };
+        let (name, size) = if child.is_folder() {
+            (format!("{}/", child.name), String::new())
+        } else {
+            (child.name.clone(), crate::size::format_size(child.size()))
+        };
+
+        rows.push_str(&format!("<tr><td><a href=\"/{}\">{}</a></td><td>{}</td></tr>\n", encode_path(&child_path), escape_html(&name), size));
+    }
+
+    format!(r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>GSync - /{title}</title>
+<style>body {{ font-family: sans-serif; max-width: 60em; margin: 2em auto; }} td {{ padding: 0.2em 1em; }}</style>
+</head>
+<body>
+<h1>GSync/{title}</h1>
+<table>
+{rows}</table>
+</body>
+</html>"#, title = escape_html(base), rows = rows)
+}
+
+/// Percent-encode a path for use in a URL, leaving the `/` separators intact
+fn encode_path(path: &str) -> String {
+    let mut encoded = String::new();
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte))
+        }
+    }
+
+    encoded
+}
+
+#[cfg(test)]
+mod test {
+    use crate::serve::encode_path;
+
+    #[test]
+    fn encode_path_special_characters() {
+        assert_eq!(encode_path("Documents/taxes 2023/café.pdf"), "Documents/taxes%202023/caf%C3%A9.pdf");
+    }
+}
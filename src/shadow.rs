@@ -0,0 +1,211 @@
+//! Module for syncing inputs from a snapshot of the filesystem they are on. Files which are written to during the sync,
+//! e.g. mail spools or databases, are then backed up as they were at a single point in time. On Windows this also allows
+//! reading files which are kept open and locked by other programs
+
+use crate::{Result, unwrap_other_err};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The kind of filesystem snapshot an input is synced from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShadowKind {
+    /// A read-only btrfs snapshot. The input must be a btrfs subvolume
+    Btrfs,
+
+    /// A ZFS snapshot of the dataset the input is in
+    Zfs,
+
+    /// An LVM snapshot of the logical volume the input is on, mounted read-only for the duration of the sync
+    Lvm,
+
+    /// A Volume Shadow Copy of the Windows volume the input is on
+    Vss
+}
+
+impl ShadowKind {
+    /// Parse a kind from its name
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "btrfs" => Some(Self::Btrfs),
+            "zfs" => Some(Self::Zfs),
+            "lvm" => Some(Self::Lvm),
+            "vss" => Some(Self::Vss),
+            _ => None
+        }
+    }
+}
+
+/// A filesystem snapshot of an input. The snapshot is removed again when this is dropped
+#[derive(Debug)]
+pub struct Shadow {
+    /// The input
+    pub input:  PathBuf,
+
+    /// Where the input is found in the snapshot
+    pub path:   PathBuf,
+
+    /// The commands which remove the snapshot, in the reverse order in which they are run
+    cleanup:    Vec<Vec<String>>
+}
+
+impl Shadow {
+    /// Snapshot the filesystem an input is on. Creating a snapshot generally requires root
+    ///
+    /// ## Params
+    /// - `kind` The kind of snapshot
+    /// - `input` The input, as a canonical path
+    ///
+    /// ## Errors
+    /// - When a command creating the snapshot fails, whatever was created so far is removed again
+    pub fn create(kind: ShadowKind, input: &Path) -> Result<Self> {
+        let mut shadow = Self { input: input.to_path_buf(), path: input.to_path_buf(), cleanup: Vec::new() };
+        let name = format!("gsync-{}", chrono::Utc::now().timestamp());
+        let input_str = input.to_string_lossy().to_string();
+
+        match kind {
+            ShadowKind::Btrfs => {
+                // A snapshot must be on the same filesystem, so it is created next to the input
+                let file_name = input.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+                let path = input.with_file_name(format!(".{}.{}", file_name, name));
+                let path_str = path.to_string_lossy().to_string();
+
+                run(&["btrfs", "subvolume", "snapshot", "-r", &input_str, &path_str])?;
+                shadow.cleanup.push(vec!["btrfs".to_string(), "subvolume".to_string(), "delete".to_string(), path_str]);
+                shadow.path = path;
+            },
+            ShadowKind::Zfs => {
+                let listing = run(&["zfs", "list", "-H", "-o", "name,mountpoint", &input_str])?;
+                let (dataset, mountpoint) = match listing.trim().split_once('\t') {
+                    Some(dataset) => dataset,
+                    None => return Err((crate::Error::Other(format!("Failed to find the ZFS dataset of '{}'", input_str)), line!(), file!()))
+                };
+
+                let snapshot = format!("{}@{}", dataset, name);
+                run(&["zfs", "snapshot", &snapshot])?;
+                shadow.cleanup.push(vec!["zfs".to_string(), "destroy".to_string(), snapshot]);
+                shadow.path = within(Path::new(mountpoint), &Path::new(mountpoint).join(".zfs/snapshot").join(&name), input);
+            },
+            ShadowKind::Lvm => {
+                let mount = run(&["findmnt", "-n", "-o", "SOURCE,TARGET,FSTYPE", "--target", &input_str])?;
+                let (device, mountpoint, fs_type) = match mount.split_whitespace().collect::<Vec<_>>()[..] {
+                    [device, mountpoint, fs_type] => (device.to_string(), mountpoint.to_string(), fs_type.to_string()),
+                    _ => return Err((crate::Error::Other(format!("Failed to find the device '{}' is on", input_str)), line!(), file!()))
+                };
+
+                let volume_group = run(&["lvs", "--noheadings", "-o", "vg_name", &device])?.trim().to_string();
+                run(&["lvcreate", "--snapshot", "--extents", "10%ORIGIN", "--name", &name, &device])?;
+                shadow.cleanup.push(vec!["lvremove".to_string(), "-f".to_string(), format!("{}/{}", volume_group, name)]);
+
+                let mount_dir = std::env::temp_dir().join(&name);
+                let mount_dir_str = mount_dir.to_string_lossy().to_string();
+                unwrap_other_err!(std::fs::create_dir_all(&mount_dir));
+                shadow.cleanup.push(vec!["rmdir".to_string(), mount_dir_str.clone()]);
+
+                // XFS refuses to mount a snapshot next to its origin, since both have the same UUID
+                let options = if fs_type.eq("xfs") { "ro,nouuid" } else { "ro" };
+                run(&["mount", "-o", options, &format!("/dev/{}/{}", volume_group, name), &mount_dir_str])?;
+                shadow.cleanup.push(vec!["umount".to_string(), mount_dir_str]);
+                shadow.path = within(Path::new(&mountpoint), &mount_dir, input);
+            },
+            ShadowKind::Vss => {
+                if !cfg!(windows) {
+                    return Err((crate::Error::Other("Volume Shadow Copies are only available on Windows".to_string()), line!(), file!()));
+                }
+
+                // The drive letter and root, e.g. `\\?\C:\`
+                let volume = input.components().take(2).collect::<PathBuf>();
+                let volume_str = volume.to_string_lossy().trim_start_matches(r"\\?\").to_string();
+                let script = format!("$result = (Get-WmiObject -List Win32_ShadowCopy).Create('{}', 'ClientAccessible'); \
+                    if ($result.ReturnValue -ne 0) {{ Write-Error \"Creating the shadow copy failed with code $($result.ReturnValue)\"; exit 1 }}; \
+                    $copy = Get-WmiObject Win32_ShadowCopy | Where-Object {{ $_.ID -eq $result.ShadowID }}; \
+                    Write-Output $copy.ID; Write-Output $copy.DeviceObject", volume_str);
+
+                let output = run(&["powershell", "-NoProfile", "-NonInteractive", "-Command", &script])?;
+                let (id, device) = match parse_shadow_copy(&output) {
+                    Some(copy) => copy,
+                    None => return Err((crate::Error::Other(format!("Failed to create a shadow copy of '{}'", volume_str)), line!(), file!()))
+                };
+
+                shadow.cleanup.push(vec!["vssadmin".to_string(), "delete".to_string(), "shadows".to_string(), format!("/shadow={}", id), "/quiet".to_string()]);
+                shadow.path = within(&volume, Path::new(&format!("{}\\", device)), input);
+            }
+        }
+
+        println!("Info: Syncing input '{}' from snapshot '{}'", input_str, shadow.path.to_string_lossy());
+        Ok(shadow)
+    }
+}
+
+impl Drop for Shadow {
+    fn drop(&mut self) {
+        for command in self.cleanup.iter().rev() {
+            let command = command.iter().map(String::as_str).collect::<Vec<_>>();
+            if let Err((e, _, _)) = run(&command) {
+                println!("Warning: Failed to remove the snapshot of input '{}', run `{}` to remove it: {}", self.input.to_string_lossy(), command.join(" "), crate::redact::redact(format!("{:?}", e)));
+            }
+        }
+    }
+}
+
+/// Where a path on a filesystem is found in a snapshot of that filesystem
+///
+/// ## Params
+/// - `mountpoint` Where the filesystem is mounted
+/// - `snapshot` Where the snapshot is mounted
+/// - `path` The path on the filesystem
+fn within(mountpoint: &Path, snapshot: &Path, path: &Path) -> PathBuf {
+    match path.strip_prefix(mountpoint) {
+        Ok(relative) => snapshot.join(relative),
+        Err(_) => snapshot.to_path_buf()
+    }
+}
+
+/// Parse the ID and device path of a shadow copy, as printed on separate lines when it is created
+fn parse_shadow_copy(output: &str) -> Option<(String, String)> {
+    let mut lines = output.lines().map(str::trim).filter(|line| !line.is_empty());
+    let id = lines.next()?;
+    let device = lines.next()?;
+    Some((id.to_string(), device.to_string()))
+}
+
+/// Run a command, returning what it printed
+///
+/// ## Errors
+/// - When the command can't be started or fails
+fn run(command: &[&str]) -> Result<String> {
+    let output = match Command::new(command[0]).args(&command[1..]).output() {
+        Ok(output) => output,
+        Err(e) => return Err((crate::Error::Other(format!("Failed to run '{}': {}", command[0], e)), line!(), file!()))
+    };
+
+    if !output.status.success() {
+        return Err((crate::Error::Other(format!("'{}' failed: {}", command.join(" "), String::from_utf8_lossy(&output.stderr).trim())), line!(), file!()));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::shadow::{parse_shadow_copy, within, ShadowKind};
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn path_within_snapshot() {
+        assert_eq!(PathBuf::from("/tank/home/.zfs/snapshot/gsync-1/me/mail"), within(Path::new("/tank/home"), Path::new("/tank/home/.zfs/snapshot/gsync-1"), Path::new("/tank/home/me/mail")));
+        assert_eq!(PathBuf::from("/tmp/gsync-1"), within(Path::new("/srv"), Path::new("/tmp/gsync-1"), Path::new("/srv")));
+    }
+
+    #[test]
+    fn parse_kind() {
+        assert_eq!(Some(ShadowKind::Lvm), ShadowKind::parse("lvm"));
+        assert_eq!(None, ShadowKind::parse("ntfs"));
+    }
+
+    #[test]
+    fn shadow_copy_output() {
+        let output = "{5C4A8B2E-9F0B-4D0A-9C52-6A4C7E1C2F11}\r\n\\\\?\\GLOBALROOT\\Device\\HarddiskVolumeShadowCopy3\r\n";
+        assert_eq!(Some(("{5C4A8B2E-9F0B-4D0A-9C52-6A4C7E1C2F11}".to_string(), r"\\?\GLOBALROOT\Device\HarddiskVolumeShadowCopy3".to_string())), parse_shadow_copy(output));
+        assert_eq!(None, parse_shadow_copy("\r\n"));
+    }
+}
@@ -0,0 +1,63 @@
+//! Module for formatting and parsing sizes
+
+/// Format a size in bytes as a human readable String, e.g. `1.5 GiB`
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[unit])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+/// Parse a human readable size, e.g. `500M` or `2GiB`, into bytes. Units are binary, a number without a unit is in bytes
+pub fn parse_size(size: &str) -> Option<u64> {
+    let size = size.trim();
+    let split = size.find(|c: char| !c.is_ascii_digit()).unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+    let number = number.parse::<u64>().ok()?;
+
+    let multiplier: u64 = match unit.trim().to_ascii_uppercase().trim_end_matches("IB").trim_end_matches('B') {
+        "" => 1,
+        "K" => 1024,
+        "M" => 1024 * 1024,
+        "G" => 1024 * 1024 * 1024,
+        "T" => 1024 * 1024 * 1024 * 1024,
+        _ => return None
+    };
+
+    number.checked_mul(multiplier)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::size::{format_size, parse_size};
+
+    #[test]
+    fn format_size_bytes() {
+        assert_eq!("512 B", format_size(512))
+    }
+
+    #[test]
+    fn format_size_gibibytes() {
+        assert_eq!("1.5 GiB", format_size(1024 * 1024 * 1536))
+    }
+
+    #[test]
+    fn parse_size_units() {
+        assert_eq!(Some(512), parse_size("512"));
+        assert_eq!(Some(500 * 1024 * 1024), parse_size("500M"));
+        assert_eq!(Some(2 * 1024 * 1024 * 1024), parse_size("2GiB"));
+        assert_eq!(Some(1024), parse_size("1 kb"));
+        assert_eq!(None, parse_size("big"));
+        assert_eq!(None, parse_size("5X"));
+    }
+}
@@ -0,0 +1,509 @@
+//! Module for snapshot backups: periodic full snapshots, each followed by a chain of incremental snapshots.
+//! A full snapshot uploads every file. An incremental snapshot only uploads files which changed since the previous snapshot,
+//! unchanged files are referenced from the snapshot they were uploaded in. The catalog of snapshots and the files in them is kept in the database,
+//! so any snapshot in a chain can be restored in full
+
+use crate::config::Configuration;
+use crate::env::Env;
+use crate::{Result, Error, unwrap_db_err, unwrap_other_err};
+use crate::api::drive;
+use crate::size::format_size;
+use chrono::{NaiveDateTime, Utc};
+use rusqlite::{named_params, OptionalExtension};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// The number of incremental snapshots after which the next snapshot is a full one, if none is configured
+pub const DEFAULT_FULL_EVERY: i64 = 7;
+
+/// The name of the folder under the GSync root folder in which snapshots are uploaded
+const SNAPSHOTS_FOLDER: &str = "_snapshots";
+
+/// The kind of a snapshot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotKind {
+    /// Every file is uploaded
+    Full,
+
+    /// Only files changed since the previous snapshot are uploaded
+    Incremental
+}
+
+impl SnapshotKind {
+    /// Parse a kind as stored in the database
+    fn parse(kind: &str) -> Option<Self> {
+        match kind {
+            "full" => Some(Self::Full),
+            "incremental" => Some(Self::Incremental),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for SnapshotKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Full => f.pad("full"),
+            Self::Incremental => f.pad("incremental")
+        }
+    }
+}
+
+/// Struct describing a snapshot in the catalog
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    /// The ID of the snapshot
+    pub id:         i64,
+
+    /// The epoch time in seconds at which the snapshot was taken
+    pub created:    i64,
+
+    /// Whether the snapshot is full or incremental
+    pub kind:       SnapshotKind,
+
+    /// The snapshot this snapshot is based on. None for full snapshots
+    pub base:       Option<i64>,
+
+    /// The full snapshot at the start of the chain this snapshot belongs to
+    pub chain:      i64,
+
+    /// The number of files in the snapshot, including referenced files
+    pub files:      i64,
+
+    /// The total size in bytes of the files in the snapshot, including referenced files
+    pub bytes:      i64,
+
+    /// The number of bytes uploaded for this snapshot
+    pub uploaded:   i64,
+
+    /// The number of files which failed to upload. A snapshot with failures is incomplete, these files are missing from it
+    pub failures:   i64,
+
+    /// The epoch time in seconds at which the snapshot was last verified, if it was
+    pub verified:   Option<i64>,
+
+    /// The number of files which were missing or damaged when the snapshot was last verified
+    pub verify_failures: Option<i64>
+}
+
+impl Snapshot {
+    /// The snapshot this snapshot is based on, formatted for display. `-` for full snapshots
+    pub fn based_on(&self) -> String {
+        self.base.map(|base| base.to_string()).unwrap_or_else(|| "-".to_string())
+    }
+
+    /// When the snapshot was taken, formatted for display
+    pub fn created_at(&self) -> String {
+        NaiveDateTime::from_timestamp(self.created, 0).format("%Y-%m-%d %H:%M:%S UTC").to_string()
+    }
+
+    /// The result of the last verification, formatted for display. An incomplete snapshot is shown as such instead
+    pub fn verification(&self) -> String {
+        if self.failures > 0 {
+            return format!("incomplete, {} not uploaded", self.failures);
+        }
+
+        match (self.verified, self.verify_failures) {
+            (Some(verified), Some(0)) | (Some(verified), None) => format!("verified {}", NaiveDateTime::from_timestamp(verified, 0).format("%Y-%m-%d")),
+            (Some(verified), Some(failures)) => format!("{} damaged {}", failures, NaiveDateTime::from_timestamp(verified, 0).format("%Y-%m-%d")),
+            (None, _) => "not verified".to_string()
+        }
+    }
+}
+
+/// Struct describing a file in a snapshot
+#[derive(Debug, Clone)]
+pub struct SnapshotFile {
+    /// The path of the file within the snapshot
+    pub path:       String,
+
+    /// The ID of the file in Drive. For unchanged files in an incremental snapshot this is the file uploaded by an earlier snapshot
+    pub file_id:    String,
+
+    /// The size of the file in bytes
+    pub size:       i64,
+
+    /// The MD5 checksum of the file
+    pub md5:        String,
+
+    /// The modification time of the local file, as epoch seconds
+    pub modified:   Option<i64>
+}
+
+/// Take a snapshot of the configured inputs. The snapshot is full if requested, if there is no previous snapshot,
+/// or if the chain of the previous snapshot already has the configured number of incremental snapshots.
+/// Files which fail to upload are reported and skipped, the snapshot is then cataloged as incomplete and an error is returned
+///
+/// ## Params
+/// - `config` The configuration
+/// - `env` Env instance, with the root folder set
+/// - `force_full` Take a full snapshot, regardless of the chain
+///
+/// ## Errors
+/// - When traversing the inputs fails
+/// - When a Drive API call fails
+/// - When a database operation fails
+/// - When any file failed to upload
+pub fn create(config: &Configuration, env: &Env, force_full: bool) -> Result<Snapshot> {
+    let previous = latest(env)?;
+    let full_every = config.snapshot_full_every.unwrap_or(DEFAULT_FULL_EVERY);
+    let (kind, base, chain) = match &previous {
+        Some(previous) if !force_full && chain_length(env, previous.chain)? <= full_every => (SnapshotKind::Incremental, Some(previous.id), Some(previous.chain)),
+        _ => (SnapshotKind::Full, None, None)
+    };
+
+    let previous_files = match base {
+        Some(base) => files_of(env, base)?.into_iter().map(|file| (file.path.clone(), file)).collect::<HashMap<_, _>>(),
+        None => HashMap::new()
+    };
+
+    let created = Utc::now().timestamp();
+    let folder_id = create_snapshot_folder(env, created, kind)?;
+    let id = insert(env, created, kind, base, chain, &folder_id)?;
+    println!("Info: Taking {} snapshot {}", kind, id);
+
+    let entries = crate::sync::local_entries(config, env, true)?;
+
+    // Folders in the snapshot folder, by their path within the snapshot
+    let mut folders: HashMap<PathBuf, String> = HashMap::new();
+    folders.insert(PathBuf::new(), folder_id.clone());
+
+    let (mut files, mut bytes, mut uploaded, mut failures) = (0i64, 0i64, 0i64, 0i64);
+    for (input, entry) in entries {
+        if entry.is_folder {
+            continue;
+        }
+
+        let path = snapshot_path(&input, &entry.path);
+        let key = path.to_string_lossy().to_string();
+
+        let file = match previous_files.get(&key) {
+            Some(previous) if previous.size == entry.size as i64 && previous.modified.is_some() && previous.modified == entry.modified => SnapshotFile {
+                modified: entry.modified,
+                ..previous.clone()
+            },
+            _ => {
+                let parent = match path.parent() {
+                    Some(parent) => get_or_create_folder(env, parent, &mut folders)?,
+                    None => folder_id.clone()
+                };
+
+                let file = match drive::blocking::upload_file(env, &entry.path, &parent, &HashMap::new()) {
+                    Ok(file) => file,
+                    Err((e, _, _)) => {
+                        eprintln!("Error: Failed to upload '{}' to the snapshot: {}", entry.path.display(), crate::redact::redact(format!("{:?}", e)));
+                        failures += 1;
+                        continue;
+                    }
+                };
+
+                // The checksum Google reports is the one of the content in Drive, even if the local file changed meanwhile.
+                // Files converted to Google documents have none
+                uploaded += entry.size as i64;
+                SnapshotFile {
+                    path:       key,
+                    file_id:    file.id,
+                    size:       entry.size as i64,
+                    md5:        file.md5_checksum.unwrap_or_default(),
+                    modified:   entry.modified
+                }
+            }
+        };
+
+        files += 1;
+        bytes += file.size;
+        insert_file(env, id, &file)?;
+    }
+
+    let conn = unwrap_db_err!(env.get_conn());
+    unwrap_db_err!(conn.execute("UPDATE snapshots SET files = :files, bytes = :bytes, uploaded = :uploaded, failures = :failures WHERE id = :id", named_params! {
+        ":files": files,
+        ":bytes": bytes,
+        ":uploaded": uploaded,
+        ":failures": failures,
+        ":id": id
+    }));
+
+    println!("Info: Snapshot {} contains {} files ({}), {} uploaded", id, files, format_size(bytes as u64), format_size(uploaded as u64));
+    if failures > 0 {
+        return Err((Error::Other(format!("Snapshot {} is incomplete, {} file(s) failed to upload. The next snapshot uploads them again", id, failures)), line!(), file!()));
+    }
+
+    match get(env, id)? {
+        Some(snapshot) => Ok(snapshot),
+        None => Err((Error::Other(format!("Snapshot {} disappeared from the catalog", id)), line!(), file!()))
+    }
+}
+
+/// Restore the files of a snapshot below a path within the snapshot into a local directory. Returns the number of files restored.
+/// Files referenced from earlier snapshots in the chain are restored as well, so the snapshot is restored in full
+///
+/// ## Params
+/// - `env` Env instance
+/// - `id` The ID of the snapshot
+/// - `prefix` Only files whose path within the snapshot starts with this path are restored. Empty or `/` to restore everything
+/// - `target` The local directory to restore into
+///
+/// ## Errors
+/// - When the snapshot does not exist
+/// - When a Drive API call fails
+/// - When creating local directories fails
+/// - When a database operation fails
+pub fn restore(env: &Env, id: i64, prefix: &str, target: &Path) -> Result<usize> {
+    if get(env, id)?.is_none() {
+        return Err((Error::Other(format!("Snapshot {} does not exist", id)), line!(), file!()));
+    }
+
+    let prefix = Path::new(prefix.trim_start_matches('/'));
+    let mut restored = 0;
+    for file in files_of(env, id)? {
+        if !Path::new(&file.path).starts_with(prefix) {
+            continue;
+        }
+
+        let path = target.join(&file.path);
+        if let Some(parent) = path.parent() {
+            unwrap_other_err!(std::fs::create_dir_all(parent));
+        }
+
+        println!("Info: Restoring '{}'", file.path);
+        drive::blocking::download_file(env, &file.file_id, &path)?;
+        crate::sparse::unpack(&path)?;
+        restored += 1;
+    }
+
+    Ok(restored)
+}
+
+/// Verify that every file of a snapshot, including the files referenced from earlier snapshots, still exists in Drive with the content it was uploaded with.
+/// The result is stored in the catalog. Returns the paths of the files which are missing or damaged
+///
+/// ## Errors
+/// - When the snapshot does not exist
+/// - When a Drive API call fails for another reason than the file not existing
+/// - When a database operation fails
+pub fn verify(env: &Env, id: i64) -> Result<Vec<String>> {
+    if get(env, id)?.is_none() {
+        return Err((Error::Other(format!("Snapshot {} does not exist", id)), line!(), file!()));
+    }
+
+    let mut damaged = Vec::new();
+    for file in files_of(env, id)? {
+        let remote = match drive::blocking::get_file(env, &file.file_id) {
+            Ok(remote) => Some(remote),
+            Err((Error::GoogleError(e), _, _)) if e.code == 404 => None,
+            Err(e) => return Err(e)
+        };
+
+        match remote {
+            // Files converted to Google documents have no checksum, they are only checked to exist
+            Some(remote) if !remote.trashed && (file.md5.is_empty() || remote.md5_checksum.as_deref() == Some(file.md5.as_str())) => {},
+            _ => {
+                println!("Warning: '{}' is missing or damaged in Drive", file.path);
+                damaged.push(file.path);
+            }
+        }
+    }
+
+    let conn = unwrap_db_err!(env.get_conn());
+    unwrap_db_err!(conn.execute("UPDATE snapshots SET verified = :verified, verify_failures = :failures WHERE id = :id", named_params! {
+        ":verified": Utc::now().timestamp(),
+        ":failures": damaged.len() as i64,
+        ":id": id
+    }));
+
+    Ok(damaged)
+}
+
+/// The path of a local file within a snapshot: its path relative to the parent of the input it belongs to, so every input gets its own folder
+fn snapshot_path(input: &Path, path: &Path) -> PathBuf {
+    let base = input.parent().unwrap_or(input);
+    path.strip_prefix(base).map(Path::to_path_buf).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Create the folder for a new snapshot in the snapshots folder, creating the snapshots folder if needed
+///
+/// ## Errors
+/// - When a Drive API call fails
+fn create_snapshot_folder(env: &Env, created: i64, kind: SnapshotKind) -> Result<String> {
+    let query = format!("name = '{}' and mimeType = 'application/vnd.google-apps.folder' and trashed = false and '{}' in parents", SNAPSHOTS_FOLDER, &env.root_folder);
+    let snapshots_folder = match drive::blocking::list_files(env, Some(&query), env.drive_id.as_deref())?.into_iter().next() {
+        Some(folder) => folder.id,
+        None => drive::blocking::create_folder(env, SNAPSHOTS_FOLDER, &env.root_folder, &HashMap::new())?
+    };
+
+    let name = format!("{}-{}", NaiveDateTime::from_timestamp(created, 0).format("%Y-%m-%dT%H-%M-%SZ"), kind);
+    drive::blocking::create_folder(env, &name, &snapshots_folder, &HashMap::new())
+}
+
+/// Get the ID of a folder within the snapshot folder, creating it and its parents if needed
+///
+/// ## Errors
+/// - When a Drive API call fails
+fn get_or_create_folder(env: &Env, path: &Path, folders: &mut HashMap<PathBuf, String>) -> Result<String> {
+    if let Some(id) = folders.get(path) {
+        return Ok(id.clone());
+    }
+
+    // The root of the snapshot is always in the map, so every path has a parent here
+    let parent = get_or_create_folder(env, path.parent().unwrap_or_else(|| Path::new("")), folders)?;
+    let name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    let id = drive::blocking::create_folder(env, &name, &parent, &HashMap::new())?;
+    folders.insert(path.to_path_buf(), id.clone());
+    Ok(id)
+}
+
+/// Insert a new snapshot into the catalog, returning its ID. A full snapshot starts its own chain
+///
+/// ## Errors
+/// - When a database operation fails
+fn insert(env: &Env, created: i64, kind: SnapshotKind, base: Option<i64>, chain: Option<i64>, folder_id: &str) -> Result<i64> {
+    let conn = unwrap_db_err!(env.get_conn());
+    unwrap_db_err!(conn.execute("INSERT INTO snapshots (created, kind, base, chain, folder_id, files, bytes, uploaded) VALUES (:created, :kind, :base, :chain, :folder_id, 0, 0, 0)", named_params! {
+        ":created": created,
+        ":kind": kind.to_string(),
+        ":base": base,
+        ":chain": chain,
+        ":folder_id": folder_id
+    }));
+
+    let id = conn.last_insert_rowid();
+    if chain.is_none() {
+        unwrap_db_err!(conn.execute("UPDATE snapshots SET chain = id WHERE id = :id", named_params! {
+            ":id": id
+        }));
+    }
+
+    Ok(id)
+}
+
+/// Insert a file of a snapshot into the catalog
+///
+/// ## Errors
+/// - When a database operation fails
+fn insert_file(env: &Env, snapshot: i64, file: &SnapshotFile) -> Result<()> {
+    let conn = unwrap_db_err!(env.get_conn());
+    unwrap_db_err!(conn.execute("INSERT OR REPLACE INTO snapshot_files (snapshot, path, file_id, size, md5, modified) VALUES (:snapshot, :path, :file_id, :size, :md5, :modified)", named_params! {
+        ":snapshot": snapshot,
+        ":path": &file.path,
+        ":file_id": &file.file_id,
+        ":size": file.size,
+        ":md5": &file.md5,
+        ":modified": file.modified
+    }));
+
+    Ok(())
+}
+
+/// The number of snapshots in a chain, including the full snapshot it starts with
+///
+/// ## Errors
+/// - When a database operation fails
+fn chain_length(env: &Env, chain: i64) -> Result<i64> {
+    let conn = unwrap_db_err!(env.get_conn());
+    let length = unwrap_db_err!(conn.query_row("SELECT COUNT(*) AS length FROM snapshots WHERE chain = :chain", named_params! {
+        ":chain": chain
+    }, |row| row.get::<&str, i64>("length")));
+
+    Ok(length)
+}
+
+/// Get all snapshots in the catalog, oldest first
+///
+/// ## Errors
+/// - When a database operation fails
+pub fn list(env: &Env) -> Result<Vec<Snapshot>> {
+    let conn = unwrap_db_err!(env.get_conn());
+    let mut stmt = unwrap_db_err!(conn.prepare("SELECT * FROM snapshots ORDER BY id ASC"));
+    let mut result = unwrap_db_err!(stmt.query(named_params! {}));
+
+    let mut snapshots = Vec::new();
+    while let Some(row) = unwrap_db_err!(result.next()) {
+        snapshots.push(read_row(row)?);
+    }
+
+    Ok(snapshots)
+}
+
+/// Get a snapshot from the catalog
+///
+/// ## Errors
+/// - When a database operation fails
+pub fn get(env: &Env, id: i64) -> Result<Option<Snapshot>> {
+    let conn = unwrap_db_err!(env.get_conn());
+    let snapshot = unwrap_db_err!(conn.query_row("SELECT * FROM snapshots WHERE id = :id", named_params! {
+        ":id": id
+    }, |row| Ok(read_row(row))).optional());
+
+    snapshot.transpose()
+}
+
+/// Get the most recent snapshot
+///
+/// ## Errors
+/// - When a database operation fails
+fn latest(env: &Env) -> Result<Option<Snapshot>> {
+    Ok(list(env)?.pop())
+}
+
+/// Get the files in a snapshot, including the files referenced from earlier snapshots
+///
+/// ## Errors
+/// - When a database operation fails
+pub fn files_of(env: &Env, snapshot: i64) -> Result<Vec<SnapshotFile>> {
+    let conn = unwrap_db_err!(env.get_conn());
+    let mut stmt = unwrap_db_err!(conn.prepare("SELECT path, file_id, size, md5, modified FROM snapshot_files WHERE snapshot = :snapshot ORDER BY path ASC"));
+    let mut result = unwrap_db_err!(stmt.query(named_params! {
+        ":snapshot": snapshot
+    }));
+
+    let mut files = Vec::new();
+    while let Some(row) = unwrap_db_err!(result.next()) {
+        files.push(SnapshotFile {
+            path:       unwrap_db_err!(row.get::<&str, String>("path")),
+            file_id:    unwrap_db_err!(row.get::<&str, String>("file_id")),
+            size:       unwrap_db_err!(row.get::<&str, i64>("size")),
+            md5:        unwrap_db_err!(row.get::<&str, String>("md5")),
+            modified:   unwrap_db_err!(row.get::<&str, Option<i64>>("modified"))
+        });
+    }
+
+    Ok(files)
+}
+
+/// Read a Snapshot from a row selected from the snapshots table
+///
+/// ## Errors
+/// - When a column can't be read
+/// - When the kind of the snapshot is unknown
+fn read_row(row: &rusqlite::Row<'_>) -> Result<Snapshot> {
+    let kind = unwrap_db_err!(row.get::<&str, String>("kind"));
+    Ok(Snapshot {
+        id:         unwrap_db_err!(row.get::<&str, i64>("id")),
+        created:    unwrap_db_err!(row.get::<&str, i64>("created")),
+        kind:       match SnapshotKind::parse(&kind) {
+            Some(kind) => kind,
+            None => return Err((Error::Other(format!("Unknown snapshot kind '{}'", kind)), line!(), file!()))
+        },
+        base:       unwrap_db_err!(row.get::<&str, Option<i64>>("base")),
+        chain:      unwrap_db_err!(row.get::<&str, i64>("chain")),
+        files:      unwrap_db_err!(row.get::<&str, i64>("files")),
+        bytes:      unwrap_db_err!(row.get::<&str, i64>("bytes")),
+        uploaded:   unwrap_db_err!(row.get::<&str, i64>("uploaded")),
+        failures:   unwrap_db_err!(row.get::<&str, Option<i64>>("failures")).unwrap_or(0),
+        verified:   unwrap_db_err!(row.get::<&str, Option<i64>>("verified")),
+        verify_failures: unwrap_db_err!(row.get::<&str, Option<i64>>("verify_failures"))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::snapshot::snapshot_path;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn snapshot_path_keeps_input_name() {
+        assert_eq!(PathBuf::from("docs/taxes/2023.pdf"), snapshot_path(Path::new("/home/user/docs"), Path::new("/home/user/docs/taxes/2023.pdf")));
+    }
+}
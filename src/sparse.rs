@@ -0,0 +1,590 @@
+//! Module for uploading and recreating sparse files.
+//! Google Drive has no notion of holes in a file, so sparse files are uploaded with their holes as zeroes, and when restoring, blocks consisting of only zeroes are turned back into holes.
+//! With packing enabled, sparse files with enough of their length in holes are uploaded packed instead: a header listing the ranges which hold data, followed by only that data.
+//! The header is the magic `GSYNC-SPARSE-V1\n`, the length of the file and the number of ranges, then the offset and length of every range, all as little endian 64 bit integers.
+//! Packed content is unpacked again when GSync downloads it, other applications see only the packed content
+
+use crate::{Result, Error, unwrap_other_err};
+use std::collections::VecDeque;
+use std::convert::TryInto;
+use std::path::Path;
+use std::fs;
+use std::io::{Cursor, Read, Write, Seek, SeekFrom};
+
+/// The size of the blocks which are checked for zeroes
+const BLOCK_SIZE: usize = 4096;
+
+/// The start of packed content
+const MAGIC: &[u8; 16] = b"GSYNC-SPARSE-V1\n";
+
+/// The length of the part of the header before the ranges: the magic, the length of the file and the number of ranges
+pub const PREFIX_LEN: u64 = 32;
+
+/// The number of bytes a file must have in holes for it to be uploaded packed
+const MIN_HOLES: u64 = 1024 * 1024;
+
+/// The maximum number of ranges accepted in a header, so a damaged header can't make GSync allocate huge amounts of memory
+const MAX_RANGES: u64 = 1 << 24;
+
+/// A range of a sparse file which holds data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DataRange {
+    /// The offset of the range in the file
+    pub offset: u64,
+
+    /// The length of the range in bytes
+    pub length: u64
+}
+
+/// Which parts of a sparse file hold data
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Layout {
+    /// The length of the file in bytes, including the holes
+    pub len:    u64,
+
+    /// The ranges holding data, in order
+    pub ranges: Vec<DataRange>
+}
+
+/// Where a part of a sparse file is found in its packed content
+#[cfg(any(test, all(unix, feature = "fuse")))]
+#[derive(Debug, PartialEq, Eq)]
+pub enum Segment {
+    /// Data, stored at an offset in the packed content
+    Data { packed_offset: u64, length: u64 },
+
+    /// A hole of the provided length, read as zeroes
+    Hole(u64)
+}
+
+impl Layout {
+    /// The length of the header of the packed content
+    pub fn header_len(&self) -> u64 {
+        PREFIX_LEN + 16 * self.ranges.len() as u64
+    }
+
+    /// The length of the packed content, header included
+    pub fn packed_len(&self) -> u64 {
+        self.header_len() + self.ranges.iter().map(|range| range.length).sum::<u64>()
+    }
+
+    /// Encode the header of the packed content
+    fn header(&self) -> Vec<u8> {
+        let mut header = Vec::with_capacity(self.header_len() as usize);
+        header.extend_from_slice(MAGIC);
+        header.extend_from_slice(&self.len.to_le_bytes());
+        header.extend_from_slice(&(self.ranges.len() as u64).to_le_bytes());
+        for range in self.ranges.iter() {
+            header.extend_from_slice(&range.offset.to_le_bytes());
+            header.extend_from_slice(&range.length.to_le_bytes());
+        }
+
+        header
+    }
+
+    /// Read the header of packed content. Returns None if the content is not packed, or if the header is damaged
+    ///
+    /// ## Errors
+    /// - When reading fails
+    pub fn parse<R: Read>(reader: &mut R) -> Result<Option<Self>> {
+        let mut prefix = [0u8; PREFIX_LEN as usize];
+        if !read_full(reader, &mut prefix)? || prefix_ranges(&prefix).is_none() {
+            return Ok(None);
+        }
+
+        // Unwraps are safe because the slices are 8 bytes long
+        let len = u64::from_le_bytes(prefix[16..24].try_into().unwrap());
+        let count = u64::from_le_bytes(prefix[24..32].try_into().unwrap());
+
+        let mut ranges = Vec::with_capacity(count.min(1024) as usize);
+        let mut end = 0;
+        for _ in 0..count {
+            let mut range = [0u8; 16];
+            if !read_full(reader, &mut range)? {
+                return Ok(None);
+            }
+
+            let offset = u64::from_le_bytes(range[..8].try_into().unwrap());
+            let length = u64::from_le_bytes(range[8..].try_into().unwrap());
+
+            // Ranges must be in order, must not overlap and must lie within the file
+            match offset.checked_add(length) {
+                Some(range_end) if offset >= end && range_end <= len => end = range_end,
+                _ => return Ok(None)
+            }
+
+            ranges.push(DataRange { offset, length });
+        }
+
+        Ok(Some(Self { len, ranges }))
+    }
+
+    /// Find where the bytes from `offset` up to `offset + length` are in the packed content.
+    /// The segments are in order, and stop at the end of the file
+    #[cfg(any(test, all(unix, feature = "fuse")))]
+    pub fn segments(&self, offset: u64, length: u64) -> Vec<Segment> {
+        let end = offset.saturating_add(length).min(self.len);
+        let mut segments = Vec::new();
+        let mut position = offset;
+        let mut packed_offset = self.header_len();
+
+        for range in self.ranges.iter() {
+            let range_end = range.offset + range.length;
+            if range_end <= position {
+                packed_offset += range.length;
+                continue;
+            }
+
+            if range.offset >= end {
+                break;
+            }
+
+            if range.offset > position {
+                segments.push(Segment::Hole(range.offset - position));
+                position = range.offset;
+            }
+
+            let data_end = range_end.min(end);
+            segments.push(Segment::Data { packed_offset: packed_offset + (position - range.offset), length: data_end - position });
+            position = data_end;
+            packed_offset += range.length;
+        }
+
+        if position < end {
+            segments.push(Segment::Hole(end - position));
+        }
+
+        segments
+    }
+}
+
+/// Get the number of ranges from the start of a header. Returns None if the bytes are not the start of packed content
+fn prefix_ranges(prefix: &[u8]) -> Option<u64> {
+    if prefix.len() < PREFIX_LEN as usize || &prefix[..MAGIC.len()] != MAGIC {
+        return None;
+    }
+
+    // Unwrap is safe because the slice is 8 bytes long
+    let count = u64::from_le_bytes(prefix[24..32].try_into().unwrap());
+    Some(count).filter(|count| *count <= MAX_RANGES)
+}
+
+/// Get the length of the header from its first [PREFIX_LEN] bytes. Returns None if the bytes are not the start of packed content
+pub fn header_len(prefix: &[u8]) -> Option<u64> {
+    prefix_ranges(prefix).map(|count| PREFIX_LEN + 16 * count)
+}
+
+/// Fill the buffer from a reader. Returns false if the reader ended first
+///
+/// ## Errors
+/// - When reading fails
+fn read_full<R: Read>(reader: &mut R, buffer: &mut [u8]) -> Result<bool> {
+    match reader.read_exact(buffer) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err((Error::Other(e.to_string()), line!(), file!()))
+    }
+}
+
+/// Find the ranges of a file which hold data. Returns None if the file should not be uploaded packed,
+/// because it has too little of its length in holes or because the platform can't tell where the holes are
+#[cfg(target_os = "linux")]
+pub fn layout(file: &fs::File) -> Option<Layout> {
+    use std::os::unix::io::AsRawFd;
+
+    let len = file.metadata().ok()?.len();
+    let fd = file.as_raw_fd();
+    let mut ranges = Vec::new();
+    let mut offset = 0u64;
+
+    while offset < len {
+        // Safe because the file descriptor stays open while the file is borrowed
+        let data = unsafe { libc::lseek(fd, offset as libc::off_t, libc::SEEK_DATA) };
+        if data < 0 {
+            // ENXIO means there is no data after the offset, only a trailing hole
+            if std::io::Error::last_os_error().raw_os_error() == Some(libc::ENXIO) {
+                break;
+            }
+
+            return None;
+        }
+
+        let hole = unsafe { libc::lseek(fd, data, libc::SEEK_HOLE) };
+        if hole < 0 {
+            return None;
+        }
+
+        let hole = (hole as u64).min(len);
+        if hole <= data as u64 {
+            break;
+        }
+
+        ranges.push(DataRange { offset: data as u64, length: hole - data as u64 });
+        offset = hole;
+    }
+
+    let mut file = file;
+    file.seek(SeekFrom::Start(0)).ok()?;
+
+    let data = ranges.iter().map(|range| range.length).sum::<u64>();
+    if len - data < MIN_HOLES {
+        return None;
+    }
+
+    Some(Layout { len, ranges })
+}
+
+/// Find the ranges of a file which hold data. The platform can't tell where the holes are, so this always returns None
+#[cfg(not(target_os = "linux"))]
+pub fn layout(_file: &fs::File) -> Option<Layout> {
+    None
+}
+
+/// Reads the packed content of a sparse file: the header, followed by the data of every range
+pub struct PackedReader {
+    /// The sparse file
+    file:       fs::File,
+
+    /// The header, read before the data
+    header:     Cursor<Vec<u8>>,
+
+    /// The ranges which have not been read yet
+    ranges:     VecDeque<DataRange>,
+
+    /// The number of bytes left in the range currently read
+    remaining:  u64
+}
+
+impl PackedReader {
+    /// Read a file packed according to its layout
+    pub fn new(file: fs::File, layout: &Layout) -> Self {
+        Self {
+            file,
+            header:     Cursor::new(layout.header()),
+            ranges:     layout.ranges.iter().copied().collect(),
+            remaining:  0
+        }
+    }
+}
+
+impl Read for PackedReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let read = self.header.read(buf)?;
+        if read > 0 {
+            return Ok(read);
+        }
+
+        while self.remaining == 0 {
+            match self.ranges.pop_front() {
+                Some(range) => {
+                    self.file.seek(SeekFrom::Start(range.offset))?;
+                    self.remaining = range.length;
+                },
+                None => return Ok(0)
+            }
+        }
+
+        let max = (buf.len() as u64).min(self.remaining) as usize;
+        let read = self.file.read(&mut buf[..max])?;
+        if read == 0 {
+            // The packed length was announced before the content was read, so a file shrinking in the meantime can't be sent
+            return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "the file was truncated while it was read"));
+        }
+
+        self.remaining -= read as u64;
+        Ok(read)
+    }
+}
+
+/// Reads the sparse file packed content was packed from, with the holes as zeroes
+struct UnpackedReader<R: Read> {
+    /// Reads the packed content, following the header
+    reader:     R,
+
+    /// The ranges which have not been read completely yet
+    ranges:     VecDeque<DataRange>,
+
+    /// The offset in the sparse file
+    position:   u64,
+
+    /// The length of the sparse file
+    len:        u64
+}
+
+impl<R: Read> Read for UnpackedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.ranges.front().map(|range| range.length == 0).unwrap_or(false) {
+            self.ranges.pop_front();
+        }
+
+        if buf.is_empty() || self.position >= self.len {
+            return Ok(0);
+        }
+
+        let range = match self.ranges.front() {
+            Some(range) if self.position >= range.offset => *range,
+            next => {
+                let hole_end = next.map(|range| range.offset).unwrap_or(self.len);
+                let zeroes = (buf.len() as u64).min(hole_end - self.position) as usize;
+                buf[..zeroes].iter_mut().for_each(|byte| *byte = 0);
+                self.position += zeroes as u64;
+                return Ok(zeroes);
+            }
+        };
+
+        let range_end = range.offset + range.length;
+        let max = (buf.len() as u64).min(range_end - self.position) as usize;
+        let read = self.reader.read(&mut buf[..max])?;
+        if read == 0 {
+            return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "the packed content of the sparse file is damaged"));
+        }
+
+        self.position += read as u64;
+        if self.position == range_end {
+            self.ranges.pop_front();
+        }
+
+        Ok(read)
+    }
+}
+
+/// Read packed content, unpacking it while it is read
+///
+/// ## Params
+/// - `reader` Reads the packed content
+/// - `packed_len` The length of the packed content, as reported by Google Drive
+///
+/// ## Errors
+/// - When reading the header fails
+/// - When the content is not packed, or its header does not match its length
+pub fn unpacking<R: Read + Send + 'static>(mut reader: R, packed_len: u64) -> Result<Box<dyn Read + Send>> {
+    let mut prefix = Vec::new();
+    unwrap_other_err!(Read::by_ref(&mut reader).take(PREFIX_LEN).read_to_end(&mut prefix));
+
+    if let Some(header_len) = header_len(&prefix) {
+        unwrap_other_err!(Read::by_ref(&mut reader).take(header_len - PREFIX_LEN).read_to_end(&mut prefix));
+        if let Some(layout) = Layout::parse(&mut prefix.as_slice())?.filter(|layout| layout.packed_len() == packed_len) {
+            return Ok(Box::new(UnpackedReader { reader, ranges: layout.ranges.into_iter().collect(), position: 0, len: layout.len }));
+        }
+    }
+
+    Err((Error::Other("The packed content of the sparse file is damaged".to_string()), line!(), file!()))
+}
+
+/// Compute the MD5 checksum of a file as it is uploaded. This is the checksum Google Drive reports for it
+///
+/// ## Params
+/// - `path` The path of the file
+/// - `packed` Whether the file was uploaded packed, in which case the checksum is of the packed content
+///
+/// ## Errors
+/// - When reading the file fails
+pub fn md5_as_uploaded(path: &Path, packed: bool) -> Result<String> {
+    let file = unwrap_other_err!(fs::File::open(path));
+    match layout(&file).filter(|_| packed) {
+        Some(layout) => crate::hash::hash_reader(PackedReader::new(file, &layout), crate::hash::HashAlgorithm::Md5),
+        None => crate::hash::md5_file(path)
+    }
+}
+
+/// Turn downloaded packed content back into the sparse file it was packed from. Returns false, leaving the file alone, if the content is not packed
+///
+/// ## Errors
+/// - When an IO operation fails
+/// - When the packed content is damaged
+pub fn unpack(path: &Path) -> Result<bool> {
+    let mut source = unwrap_other_err!(fs::File::open(path));
+    let packed_len = unwrap_other_err!(source.metadata()).len();
+    let layout = match Layout::parse(&mut source)? {
+        Some(layout) if layout.packed_len() == packed_len => layout,
+        _ => return Ok(false)
+    };
+
+    let sparse_path = path.with_extension("gsync-sparse");
+    let result = write_unpacked(&mut source, &layout, &sparse_path);
+    if result.is_err() {
+        let _ = fs::remove_file(&sparse_path);
+        return result.map(|_| false);
+    }
+
+    unwrap_other_err!(fs::rename(&sparse_path, path));
+    Ok(true)
+}
+
+/// Write the ranges following the header of packed content to their offsets in a new file, leaving holes in between
+///
+/// ## Errors
+/// - When an IO operation fails
+/// - When the packed content ends early
+fn write_unpacked(source: &mut fs::File, layout: &Layout, target: &Path) -> Result<()> {
+    let mut target = unwrap_other_err!(fs::File::create(target));
+    for range in layout.ranges.iter() {
+        unwrap_other_err!(target.seek(SeekFrom::Start(range.offset)));
+        let copied = unwrap_other_err!(std::io::copy(&mut Read::by_ref(source).take(range.length), &mut target));
+        if copied != range.length {
+            return Err((Error::Other("The packed content of the sparse file is damaged".to_string()), line!(), file!()));
+        }
+    }
+
+    // Trailing holes are not written by seeking alone
+    unwrap_other_err!(target.set_len(layout.len));
+    Ok(())
+}
+
+/// Rewrite a file so that blocks consisting of only zeroes become holes
+///
+/// ## Errors
+/// - When an IO operation fails
+pub fn sparsify(path: &Path) -> Result<()> {
+    let sparse_path = path.with_extension("gsync-sparse");
+
+    let result = copy_sparse(path, &sparse_path);
+    if result.is_err() {
+        let _ = fs::remove_file(&sparse_path);
+        return result;
+    }
+
+    unwrap_other_err!(fs::rename(&sparse_path, path));
+    Ok(())
+}
+
+/// Copy a file, seeking over blocks consisting of only zeroes instead of writing them
+///
+/// ## Errors
+/// - When an IO operation fails
+fn copy_sparse(source: &Path, target: &Path) -> Result<()> {
+    let mut source = unwrap_other_err!(fs::File::open(source));
+    let mut target = unwrap_other_err!(fs::File::create(target));
+    let mut buffer = vec![0u8; BLOCK_SIZE];
+    let mut length = 0u64;
+
+    loop {
+        let read = unwrap_other_err!(source.read(&mut buffer));
+        if read == 0 {
+            break;
+        }
+
+        if buffer[..read].iter().all(|byte| *byte == 0) {
+            unwrap_other_err!(target.seek(SeekFrom::Current(read as i64)));
+        } else {
+            unwrap_other_err!(target.write_all(&buffer[..read]));
+        }
+
+        length += read as u64;
+    }
+
+    // Trailing holes are not written by seeking alone
+    unwrap_other_err!(target.set_len(length));
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::sparse::{layout, sparsify, unpack, unpacking, DataRange, Layout, PackedReader, Segment};
+    use std::io::Read;
+    use std::path::PathBuf;
+
+    /// Create an empty temporary directory for one test
+    fn temp_dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("gsync-sparse-{}-{}", test, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn sparsify_preserves_content() {
+        let dir = temp_dir("sparsify");
+        let path = dir.join("file");
+        let mut content = vec![0u8; 3 * 4096];
+        content[5000] = 1;
+        std::fs::write(&path, &content).unwrap();
+
+        sparsify(&path).unwrap();
+
+        assert_eq!(content, std::fs::read(&path).unwrap());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unpack_restores_packed_content() {
+        let dir = temp_dir("unpack");
+        let path = dir.join("file");
+        let mut content = vec![0u8; 10_000];
+        content[..3].copy_from_slice(b"abc");
+        content[8000..8002].copy_from_slice(b"de");
+        std::fs::write(&path, &content).unwrap();
+
+        let layout = Layout { len: 10_000, ranges: vec![DataRange { offset: 0, length: 3 }, DataRange { offset: 8000, length: 2 }] };
+        let mut packed = Vec::new();
+        PackedReader::new(std::fs::File::open(&path).unwrap(), &layout).read_to_end(&mut packed).unwrap();
+        assert_eq!(layout.packed_len(), packed.len() as u64);
+        assert_eq!(Some(layout.clone()), Layout::parse(&mut packed.as_slice()).unwrap());
+
+        let download = dir.join("download");
+        std::fs::write(&download, &packed).unwrap();
+        assert!(unpack(&download).unwrap());
+        assert_eq!(content, std::fs::read(&download).unwrap());
+
+        let mut streamed = Vec::new();
+        unpacking(std::io::Cursor::new(packed.clone()), layout.packed_len()).unwrap().read_to_end(&mut streamed).unwrap();
+        assert_eq!(content, streamed);
+
+        // A truncated download is not streamed as a shorter file
+        let truncated = packed[..packed.len() - 1].to_vec();
+        assert!(unpacking(std::io::Cursor::new(truncated.clone()), truncated.len() as u64).is_err());
+        let mut streamed = Vec::new();
+        assert!(unpacking(std::io::Cursor::new(truncated), layout.packed_len()).unwrap().read_to_end(&mut streamed).is_err());
+
+        // Content which is not packed is left alone
+        assert!(!unpack(&download).unwrap());
+        assert_eq!(content, std::fs::read(&download).unwrap());
+        assert!(unpacking(std::io::Cursor::new(content.clone()), content.len() as u64).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn layout_skips_holes() {
+        use std::io::{Seek, SeekFrom, Write};
+        use std::os::unix::fs::MetadataExt;
+
+        let dir = temp_dir("layout");
+        let path = dir.join("file");
+        let mut file = std::fs::File::create(&path).unwrap();
+        file.write_all(b"a").unwrap();
+        file.seek(SeekFrom::Start(3 * 1024 * 1024)).unwrap();
+        file.write_all(b"b").unwrap();
+        drop(file);
+
+        // Not every filesystem creates holes
+        let metadata = std::fs::metadata(&path).unwrap();
+        if metadata.blocks() * 512 < metadata.len() {
+            let file = std::fs::File::open(&path).unwrap();
+            let layout = layout(&file).unwrap();
+            assert_eq!(metadata.len(), layout.len);
+            assert!(layout.packed_len() < 1024 * 1024);
+
+            let mut unpacked = Vec::new();
+            unpacking(PackedReader::new(file, &layout), layout.packed_len()).unwrap().read_to_end(&mut unpacked).unwrap();
+            assert_eq!(std::fs::read(&path).unwrap(), unpacked);
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn segments_cover_holes_and_data() {
+        let layout = Layout { len: 100, ranges: vec![DataRange { offset: 10, length: 20 }, DataRange { offset: 50, length: 10 }] };
+        let header = layout.header_len();
+
+        assert_eq!(vec![Segment::Hole(10), Segment::Data { packed_offset: header, length: 20 }, Segment::Hole(20), Segment::Data { packed_offset: header + 20, length: 5 }], layout.segments(0, 55));
+        assert_eq!(vec![Segment::Data { packed_offset: header + 25, length: 5 }, Segment::Hole(40)], layout.segments(55, 100));
+        assert_eq!(vec![Segment::Data { packed_offset: header + 5, length: 2 }], layout.segments(15, 2));
+        assert!(layout.segments(100, 10).is_empty());
+    }
+}
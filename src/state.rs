@@ -0,0 +1,264 @@
+//! Module for backing up GSync's own state to the application data folder in Google Drive, and restoring it on another machine.
+//! The backup is encrypted with a passphrase, since the database holds the client secret and the tokens
+
+use crate::env::Env;
+use crate::api::drive;
+use crate::config::Configuration;
+use crate::report::hostname;
+use crate::{Result, Error, unwrap_db_err, unwrap_other_err};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use chacha20poly1305::aead::{Aead, NewAead};
+use rand::RngCore;
+use rusqlite::named_params;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::fs;
+
+/// The name of the backup in the application data folder
+pub const BACKUP_NAME: &str = "gsync-state.db3.enc";
+
+/// The environment variable holding the passphrase the backup is encrypted with. Takes precedence over the configured passphrase
+pub const PASSPHRASE_VAR: &str = "GSYNC_STATE_PASSPHRASE";
+
+/// The app property holding the name of the machine the backup was made on
+const HOST_PROPERTY: &str = "gsync_state_host";
+
+/// The minimum number of seconds between two backups made after a sync
+const BACKUP_INTERVAL: i64 = 24 * 60 * 60;
+
+/// The start of every backup, including the version of its format
+const MAGIC: &[u8] = b"GSYNCST1";
+
+/// The length of the salt the key is derived with, in bytes
+const SALT_LEN: usize = 16;
+
+/// The length of the nonce, in bytes
+const NONCE_LEN: usize = 12;
+
+/// The number of PBKDF2 rounds used to derive the key from the passphrase
+const ROUNDS: u32 = 100_000;
+
+/// Struct describing a restored backup
+pub struct Restored {
+    /// The time the backup was made, as RFC 3339 String
+    pub made:       String,
+
+    /// The name of the machine the backup was made on, if known
+    pub host:       Option<String>,
+
+    /// Where the database which was replaced was moved to, if there was one
+    pub previous:   Option<PathBuf>
+}
+
+/// The passphrase to encrypt the backup with: the one in the environment variable if set, the configured one otherwise
+pub fn passphrase(config: &Configuration) -> Option<String> {
+    std::env::var(PASSPHRASE_VAR).ok()
+        .filter(|passphrase| !passphrase.is_empty())
+        .or_else(|| config.state_passphrase.clone())
+}
+
+/// Back up the state if a passphrase is configured and the last backup is older than a day
+///
+/// ## Errors
+/// - When making the backup fails
+pub fn backup_if_due(config: &Configuration, env: &Env) -> Result<bool> {
+    let passphrase = match passphrase(config) {
+        Some(passphrase) => passphrase,
+        None => return Ok(false)
+    };
+
+    if let Some(last) = last_backup(env)? {
+        if chrono::Utc::now().timestamp() - last < BACKUP_INTERVAL {
+            return Ok(false);
+        }
+    }
+
+    backup(env, &passphrase)?;
+    Ok(true)
+}
+
+/// Get the time of the last backup made from this machine, as epoch seconds
+///
+/// ## Errors
+/// - When a database operation fails
+pub fn last_backup(env: &Env) -> Result<Option<i64>> {
+    let conn = unwrap_db_err!(env.get_conn());
+    let last = unwrap_db_err!(conn.query_row("SELECT MAX(time) FROM state_backups", named_params! {}, |row| row.get::<usize, Option<i64>>(0)));
+    Ok(last)
+}
+
+/// Encrypt a copy of the database and upload it to the application data folder, replacing the previous backup
+///
+/// ## Errors
+/// - When copying the database fails
+/// - When encrypting fails
+/// - When the Google API returns an error
+pub fn backup(env: &Env, passphrase: &str) -> Result<()> {
+    // The copy holds the tokens unencrypted, so it is made in the database folder, which only its owner can access
+    let dir = PathBuf::from(&env.db).join(format!("state-backup-{}", std::process::id()));
+    unwrap_other_err!(fs::create_dir_all(&dir));
+    let result = backup_in(env, passphrase, &dir);
+    let _ = fs::remove_dir_all(&dir);
+    let id = result?;
+
+    let conn = unwrap_db_err!(env.get_conn());
+    unwrap_db_err!(conn.execute("INSERT INTO state_backups (time, file_id) VALUES (:time, :file_id)", named_params! {
+        ":time":    chrono::Utc::now().timestamp(),
+        ":file_id": &id
+    }));
+
+    Ok(())
+}
+
+/// Make the backup in the provided directory and upload it. Returns the ID of the backup in Drive
+///
+/// ## Errors
+/// - When copying the database fails
+/// - When encrypting fails
+/// - When the Google API returns an error
+fn backup_in(env: &Env, passphrase: &str, dir: &Path) -> Result<String> {
+    let copy = dir.join("data.db3");
+    {
+        // Unlike copying the file, this gives a consistent copy while the database is in use.
+        // SQLite writes into the empty file created first, so the copy is never readable by others
+        crate::env::create_private_file(&copy);
+        let conn = unwrap_db_err!(env.get_conn());
+        unwrap_db_err!(conn.execute("VACUUM INTO :path", named_params! {
+            ":path": copy.to_string_lossy().to_string()
+        }));
+    }
+
+    let encrypted = encrypt(&unwrap_other_err!(fs::read(&copy)), passphrase)?;
+    let path = dir.join(BACKUP_NAME);
+    unwrap_other_err!(crate::env::write_private_file(&path, &encrypted));
+
+    let mut properties = HashMap::new();
+    if let Some(hostname) = hostname() {
+        properties.insert(HOST_PROPERTY.to_string(), hostname);
+    }
+
+    match drive::blocking::list_app_data(env, BACKUP_NAME)?.into_iter().next() {
+        Some(existing) => {
+            drive::blocking::update_file(env, &path, &existing.id, &properties)?;
+            Ok(existing.id)
+        },
+        None => drive::blocking::upload_app_data(env, &path, &properties)
+    }
+}
+
+/// Download the backup from the application data folder and replace the database with it. The current database is kept next to it
+///
+/// ## Errors
+/// - When there is no backup
+/// - When the Google API returns an error
+/// - When the passphrase is wrong or the backup is damaged
+/// - When replacing the database fails
+pub fn restore(env: &Env, passphrase: &str) -> Result<Restored> {
+    let backup = match drive::blocking::list_app_data(env, BACKUP_NAME)?.into_iter().next() {
+        Some(backup) => backup,
+        None => return Err((Error::Other("There is no backup of the GSync state in Google Drive".to_string()), line!(), file!()))
+    };
+
+    let dir = PathBuf::from(&env.db);
+    let download = dir.join(format!("{}.download", BACKUP_NAME));
+    drive::blocking::download_file(env, &backup.id, &download)?;
+    let encrypted = fs::read(&download);
+    let _ = fs::remove_file(&download);
+    let database = decrypt(&unwrap_other_err!(encrypted), passphrase)?;
+
+    // The restored database holds the client secret and the tokens. A file left behind by an earlier restore is replaced
+    let restored = dir.join("data.db3.restored");
+    let _ = fs::remove_file(&restored);
+    unwrap_other_err!(crate::env::write_private_file(&restored, &database));
+
+    // The current database is only replaced by one GSync can read
+    let check = rusqlite::Connection::open(&restored)
+        .and_then(|conn| conn.query_row("SELECT COUNT(*) FROM files", named_params! {}, |row| row.get::<usize, i64>(0)));
+    if let Err(e) = check {
+        let _ = fs::remove_file(&restored);
+        return Err((Error::DatabaseError(e), line!(), file!()));
+    }
+
+    let current = dir.join("data.db3");
+    let previous = if current.exists() {
+        let previous = dir.join(format!("data.db3.{}.bak", chrono::Utc::now().timestamp()));
+        unwrap_other_err!(fs::rename(&current, &previous));
+        Some(previous)
+    } else {
+        None
+    };
+
+    unwrap_other_err!(fs::rename(&restored, &current));
+
+    Ok(Restored {
+        made:   backup.modified_time,
+        host:   backup.app_properties.get(HOST_PROPERTY).cloned(),
+        previous
+    })
+}
+
+/// Derive the key from the passphrase
+fn derive_key(passphrase: &str, salt: &[u8]) -> Key {
+    let mut key = Key::default();
+    pbkdf2::pbkdf2::<hmac::Hmac<sha2::Sha256>>(passphrase.as_bytes(), salt, ROUNDS, &mut key);
+    key
+}
+
+/// Encrypt data with a key derived from the passphrase. The salt and nonce are stored in front of the encrypted data
+///
+/// ## Errors
+/// - When encrypting fails
+pub fn encrypt(data: &[u8], passphrase: &str) -> Result<Vec<u8>> {
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut salt);
+    rand::thread_rng().fill_bytes(&mut nonce);
+
+    let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, &salt));
+    let encrypted = match cipher.encrypt(Nonce::from_slice(&nonce), data) {
+        Ok(encrypted) => encrypted,
+        Err(_) => return Err((Error::Other("Failed to encrypt the state".to_string()), line!(), file!()))
+    };
+
+    let mut output = Vec::with_capacity(MAGIC.len() + SALT_LEN + NONCE_LEN + encrypted.len());
+    output.extend_from_slice(MAGIC);
+    output.extend_from_slice(&salt);
+    output.extend_from_slice(&nonce);
+    output.extend_from_slice(&encrypted);
+    Ok(output)
+}
+
+/// Decrypt data encrypted with [encrypt]
+///
+/// ## Errors
+/// - When the data isn't a backup made by GSync
+/// - When the passphrase is wrong or the data is damaged
+pub fn decrypt(data: &[u8], passphrase: &str) -> Result<Vec<u8>> {
+    if data.len() < MAGIC.len() + SALT_LEN + NONCE_LEN || !data.starts_with(MAGIC) {
+        return Err((Error::Other("The backup was not made by this version of GSync".to_string()), line!(), file!()));
+    }
+
+    let (salt, rest) = data[MAGIC.len()..].split_at(SALT_LEN);
+    let (nonce, encrypted) = rest.split_at(NONCE_LEN);
+
+    let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, salt));
+    match cipher.decrypt(Nonce::from_slice(nonce), encrypted) {
+        Ok(data) => Ok(data),
+        Err(_) => Err((Error::Other("Failed to decrypt the backup. Is the passphrase correct?".to_string()), line!(), file!()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::state::{decrypt, encrypt};
+
+    #[test]
+    fn round_trip() {
+        let encrypted = encrypt(b"state", "passphrase").unwrap();
+        assert_ne!(b"state".to_vec(), encrypted);
+        assert_eq!(b"state".to_vec(), decrypt(&encrypted, "passphrase").unwrap());
+
+        assert!(decrypt(&encrypted, "wrong").is_err());
+        assert!(decrypt(b"state", "passphrase").is_err());
+    }
+}
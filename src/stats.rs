@@ -0,0 +1,168 @@
+//! Module for long-term statistics: the run history aggregated per month, and a projection of when the storage quota runs out.
+//! The aggregates are kept in their own table, so they outlive the run history, which is pruned by `gsync gc`
+
+use crate::env::Env;
+use crate::report::RunRecord;
+use crate::{Result, unwrap_db_err};
+use chrono::NaiveDateTime;
+use rusqlite::named_params;
+
+/// The number of most recent months the growth rate is averaged over
+const GROWTH_MONTHS: usize = 6;
+
+/// Struct describing the statistics of a single month
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MonthlyStats {
+    /// The month, as `YYYY-MM`
+    pub month:          String,
+
+    /// The number of runs
+    pub runs:           i64,
+
+    /// The number of runs which did not complete
+    pub failed_runs:    i64,
+
+    /// The number of new files uploaded
+    pub uploaded:       i64,
+
+    /// The number of changed files uploaded
+    pub updated:        i64,
+
+    /// The number of bytes uploaded
+    pub bytes:          i64,
+
+    /// The number of files which failed to sync
+    pub failures:       i64,
+
+    /// The total size in bytes of the tracked files after the last run of the month. None for months aggregated from the run history
+    pub tracked_bytes:  Option<i64>
+}
+
+impl MonthlyStats {
+    /// The percentage of runs which did not complete
+    pub fn failed_run_rate(&self) -> f64 {
+        if self.runs == 0 {
+            return 0.0;
+        }
+
+        self.failed_runs as f64 / self.runs as f64 * 100.0
+    }
+}
+
+/// Add a finished run to the statistics of the month it started in
+///
+/// ## Errors
+/// - When a database operation fails
+pub fn record(env: &Env, run: &RunRecord) -> Result<()> {
+    let conn = unwrap_db_err!(env.get_conn());
+    let month = NaiveDateTime::from_timestamp(run.started, 0).format("%Y-%m").to_string();
+    let tracked_bytes = unwrap_db_err!(conn.query_row("SELECT COALESCE(SUM(size), 0) FROM files WHERE is_folder = 0", named_params! {}, |row| row.get::<usize, i64>(0)));
+
+    unwrap_db_err!(conn.execute("INSERT OR IGNORE INTO monthly_stats (month, runs, failed_runs, uploaded, updated, bytes, failures) VALUES (:month, 0, 0, 0, 0, 0, 0)", named_params! {
+        ":month": &month
+    }));
+
+    unwrap_db_err!(conn.execute("UPDATE monthly_stats SET runs = runs + 1, failed_runs = failed_runs + :failed, uploaded = uploaded + :uploaded, updated = updated + :updated, bytes = bytes + :bytes, failures = failures + :failures, tracked_bytes = :tracked_bytes WHERE month = :month", named_params! {
+        ":failed": !run.success,
+        ":uploaded": run.uploaded,
+        ":updated": run.updated,
+        ":bytes": run.bytes,
+        ":failures": run.failures,
+        ":tracked_bytes": tracked_bytes,
+        ":month": &month
+    }));
+
+    Ok(())
+}
+
+/// Get the statistics of every month with at least one run, oldest first
+///
+/// ## Errors
+/// - When a database operation fails
+pub fn monthly(env: &Env) -> Result<Vec<MonthlyStats>> {
+    let conn = unwrap_db_err!(env.get_conn());
+    let mut stmt = unwrap_db_err!(conn.prepare("SELECT month, runs, failed_runs, uploaded, updated, bytes, failures, tracked_bytes FROM monthly_stats ORDER BY month ASC"));
+    let mut result = unwrap_db_err!(stmt.query(named_params! {}));
+
+    let mut months = Vec::new();
+    while let Some(row) = unwrap_db_err!(result.next()) {
+        months.push(MonthlyStats {
+            month:          unwrap_db_err!(row.get::<&str, String>("month")),
+            runs:           unwrap_db_err!(row.get::<&str, i64>("runs")),
+            failed_runs:    unwrap_db_err!(row.get::<&str, i64>("failed_runs")),
+            uploaded:       unwrap_db_err!(row.get::<&str, i64>("uploaded")),
+            updated:        unwrap_db_err!(row.get::<&str, i64>("updated")),
+            bytes:          unwrap_db_err!(row.get::<&str, i64>("bytes")),
+            failures:       unwrap_db_err!(row.get::<&str, i64>("failures")),
+            tracked_bytes:  unwrap_db_err!(row.get::<&str, Option<i64>>("tracked_bytes"))
+        });
+    }
+
+    Ok(months)
+}
+
+/// The growth of the tracked data per month, in bytes. Each month is compared to the month before it
+pub fn growth(months: &[MonthlyStats]) -> Vec<(String, Option<i64>)> {
+    let mut previous: Option<i64> = None;
+    months.iter().map(|month| {
+        let growth = match (previous, month.tracked_bytes) {
+            (Some(previous), Some(tracked)) => Some(tracked - previous),
+            _ => None
+        };
+
+        previous = month.tracked_bytes;
+        (month.month.clone(), growth)
+    }).collect()
+}
+
+/// The average growth of the tracked data per month over the most recent months, in bytes. None if there is not enough history
+pub fn growth_rate(months: &[MonthlyStats]) -> Option<f64> {
+    let growths = growth(months).into_iter()
+        .filter_map(|(_, growth)| growth)
+        .collect::<Vec<_>>();
+
+    let recent = &growths[growths.len().saturating_sub(GROWTH_MONTHS)..];
+    if recent.is_empty() {
+        return None;
+    }
+
+    Some(recent.iter().sum::<i64>() as f64 / recent.len() as f64)
+}
+
+/// The number of months until the free storage is used up, at the provided growth rate. None if the data isn't growing
+pub fn months_until_full(free: u64, growth_rate: f64) -> Option<f64> {
+    if growth_rate <= 0.0 {
+        return None;
+    }
+
+    Some(free as f64 / growth_rate)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::stats::{growth_rate, months_until_full, MonthlyStats};
+
+    /// Statistics of a month with the provided size of the tracked data
+    fn month(month: &str, tracked_bytes: Option<i64>) -> MonthlyStats {
+        MonthlyStats { month: month.to_string(), runs: 1, failed_runs: 0, uploaded: 0, updated: 0, bytes: 0, failures: 0, tracked_bytes }
+    }
+
+    #[test]
+    fn averages_growth() {
+        let months = vec![month("2024-01", Some(100)), month("2024-02", Some(300)), month("2024-03", Some(400))];
+        assert_eq!(growth_rate(&months), Some(150.0));
+    }
+
+    #[test]
+    fn needs_two_months_of_history() {
+        assert_eq!(growth_rate(&[month("2024-01", Some(100))]), None);
+        assert_eq!(growth_rate(&[month("2024-01", None), month("2024-02", Some(100))]), None);
+    }
+
+    #[test]
+    fn projects_exhaustion() {
+        assert_eq!(months_until_full(1000, 100.0), Some(10.0));
+        assert_eq!(months_until_full(1000, 0.0), None);
+        assert_eq!(months_until_full(1000, -50.0), None);
+    }
+}
@@ -1,285 +1,2637 @@
 //! Module related to syncing files
 
-use crate::config::Configuration;
+use crate::config::{Configuration, InputConfiguration, RemoteRoot};
+use crate::filter::{self, InputFilter};
+use crate::template::{self, TemplateValues};
 use crate::env::Env;
 use crate::Result;
 use std::path::{Path, PathBuf};
+use std::ffi::{OsStr, OsString};
 use std::fs;
 use crate::unwrap_other_err;
 use crate::api::drive;
-use std::time::SystemTime;
+use crate::files::{self, TrackedFile};
+use crate::metadata;
+use crate::concurrency::{self, AdaptiveLimiter, Outcome};
+use crate::report::RunReport;
+use crate::plan::{self, Action, DeleteLimit, DeletePolicy, LocalEntry, Oversized, Plan};
+use crate::limits::{self, DriveUsage};
+use crate::compare::{self, ComparePolicy, SyncedCopy};
+use crate::hash::{self, HashAlgorithm, HashCache};
+use crate::links::{self, SymlinkPolicy};
+use crate::shadow::{Shadow, ShadowKind};
+use crate::overrides::{self, DirectoryOverrides};
+use crate::budget::{self, Budget};
+use std::time::{SystemTime, Duration, Instant};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::future::Future;
+use futures::future::LocalBoxFuture;
+use futures::stream::{self, TryStreamExt};
+use tokio::sync::Semaphore;
+use cfg_if::cfg_if;
+use ignore::Match;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
 
-/// Sync the configured input files to google drive
-pub fn sync(config: &Configuration, env: &Env) -> Result<()> {
-    // Unwrap is safe because the caller verifiers the configuration
-    let input = config.input_files.as_ref().unwrap();
-    let input_parts = input.split(',').map(|f| normalize_path(f).unwrap()).collect::<Vec<PathBuf>>();
+/// The name of the file in which a folder lists the files and folders GSync doesn't sync, in gitignore syntax
+const IGNORE_FILE_NAME: &str = ".gsyncignore";
+
+/// The number of files synced at once beyond the limit on concurrent uploads. They are compared and hashed while the other files upload
+const PREPARING_FILES: usize = 2;
+
+/// Options for a sync, set on the command line
+#[derive(Debug, Clone, Default)]
+pub struct SyncOptions {
+    /// Ask the user for approval after showing the plan, before making any changes
+    pub confirm:    bool,
+
+    /// Proceed even if the plan deletes more files than the configured deletion limit
+    pub allow_mass_delete: bool,
+
+    /// Abort on any anomaly, e.g. an unreadable file or folder, instead of skipping it and reporting it at the end of the sync
+    pub strict:     bool,
+
+    /// If set, only these files and folders are synced instead of traversing the inputs. Nothing is deleted from Drive
+    pub files_from: Option<Vec<PathBuf>>,
+
+    /// Patterns of files and folders which are not synced in this run, in addition to the excludes of each input
+    pub excludes:   Vec<String>,
+
+    /// How symbolic links are synced in this run, instead of the configured policy
+    pub symlinks:   Option<SymlinkPolicy>,
+
+    /// If not empty, only files with one of these extensions are synced in this run
+    pub include_ext: Vec<String>,
+
+    /// Files with one of these extensions are not synced in this run
+    pub exclude_ext: Vec<String>,
+
+    /// The maximum number of bytes uploaded in this run. Files which don't fit are deferred to the next run
+    pub max_transfer: Option<u64>,
+
+    /// The maximum size of a file in this run, instead of the configured maximum sizes. 0 means files of any size are synced
+    pub max_size:   Option<u64>,
+
+    /// How long this run may take, counted from its start. Once it is up, the remaining work is deferred to the next run
+    pub max_duration: Option<Duration>,
+
+    /// The maximum number of concurrent uploads in this run, instead of the configured maximum
+    pub jobs:       Option<usize>,
+
+    /// Show progress bars with the transfer speed and ETA while syncing
+    pub progress:   bool,
+
+    /// Update files which were edited in Drive since the last sync with the local version, instead of skipping them
+    pub overwrite_remote: bool,
+
+    /// Permanently delete the files and folders which were removed locally from Drive, instead of moving them to the trash
+    pub purge:      bool
+}
+
+/// A path skipped during the sync because of an anomaly, with the reason
+type Skipped = (PathBuf, String);
+
+/// Struct describing a traversed input, and where it is synced to
+#[derive(Debug)]
+struct TraversedInput {
+    /// The drive the input is synced to, where None is My Drive
+    drive_id:       Option<String>,
+
+    /// The folder the input is synced into
+    remote_root:    RemoteRoot,
+
+    /// How to decide whether a file in the input changed
+    compare:        ComparePolicy,
+
+    /// The children of the input
+    children:       Vec<Child>,
+
+    /// The files in the input which are larger than the maximum size
+    oversized:      Vec<Oversized>,
+
+    /// The special files in the input, e.g. sockets and broken links, with the reason why they are not synced
+    special:        Vec<Skipped>
+}
+
+/// The files in a folder in Google Drive, by their name
+type FolderListing = HashMap<String, Vec<drive::File>>;
+
+/// Struct describing a symbolic link waiting to be synced as a shortcut
+#[derive(Debug)]
+struct PendingSymlink {
+    /// The link
+    link:       Symlink,
+
+    /// The Env of the input the link is in
+    env:        Env,
+
+    /// The ID of the folder in Google Drive the link is synced into
+    parent_id:  String,
+
+    /// The files in Google Drive with the same name as the link
+    existing:   Vec<drive::File>
+}
+
+/// Struct describing the state kept for the duration of a sync, shared between the files and folders synced concurrently
+#[derive(Debug)]
+struct SyncState<'a> {
+    /// Files with multiple hard links which have been synced, by their (device, inode).
+    /// The value is the local path and the ID in Google Drive of the first link that was synced
+    hard_links: Mutex<HashMap<(u64, u64), (PathBuf, String)>>,
+
+    /// The IDs of the folders in Google Drive by their local path, together with the ID of their parent folder.
+    /// Populated from the files table and from folders found or created during the sync, so each folder is only looked up in Drive once
+    folder_ids: Mutex<HashMap<PathBuf, (String, String)>>,
+
+    /// The IDs in Google Drive of all files and folders tracked at the start of the sync
+    tracked_ids: HashSet<String>,
+
+    /// The IDs in Google Drive of the tracked files which were edited in Drive since they were last synced
+    remote_edits: HashSet<String>,
+
+    /// Whether files edited in Drive are overwritten with the local version
+    overwrite_remote: bool,
+
+    /// Limits the number of concurrent uploads
+    limiter:    AdaptiveLimiter,
+
+    /// Bounds the number of files synced at once across all folders, see [PREPARING_FILES]
+    files:      Semaphore,
+
+    /// Serializes the database writes of the synced files, so the record of a file and its checksum are written together
+    /// and the blocking threads don't contend for the lock on the database
+    db_writes:  Arc<Mutex<()>>,
+
+    /// The labels to apply to files and folders created during the sync
+    labels:     Vec<drive::LabelModification>,
+
+    /// Whether a file which fails to sync aborts the sync
+    strict:     bool,
+
+    /// How to decide whether a file changed, per input
+    compare:    Vec<(PathBuf, ComparePolicy)>,
+
+    /// The number of seconds modification times may differ while still being considered the same
+    tolerance:  i64,
+
+    /// The algorithm checksums of synced files are stored with
+    algorithm:  HashAlgorithm,
+
+    /// The checksums computed by the plan and during the sync
+    hash_cache: Arc<HashCache>,
+
+    /// The inputs which are synced from a filesystem snapshot, with where the input is found in the snapshot
+    sources:    Vec<(PathBuf, PathBuf)>,
+
+    /// The symbolic links found during the sync, which are synced as shortcuts once everything else is synced
+    symlinks:   Mutex<Vec<PendingSymlink>>,
+
+    /// The budget of this run
+    budget:     Budget,
+
+    /// The report of this run
+    report:     &'a RunReport
+}
+
+impl SyncState<'_> {
+    /// How to decide whether a file changed, following the policy of the innermost input it is in
+    fn compare_policy(&self, path: &Path) -> ComparePolicy {
+        self.compare.iter()
+            .filter(|(input, _)| path.starts_with(input))
+            .max_by_key(|(input, _)| input.components().count())
+            .map(|(_, policy)| *policy)
+            .unwrap_or(compare::DEFAULT_POLICY)
+    }
+
+    /// Where the content of a path is read from: the filesystem snapshot of the innermost input it is in, or the path itself
+    fn source(&self, path: &Path) -> PathBuf {
+        let input = self.compare.iter()
+            .map(|(input, _)| input)
+            .filter(|input| path.starts_with(input))
+            .max_by_key(|input| input.components().count());
+
+        match input.and_then(|input| self.sources.iter().find(|(shadowed, _)| shadowed == input)) {
+            Some((input, source)) => rebase(path, input, source),
+            None => path.to_path_buf()
+        }
+    }
+}
+
+/// Sync the configured input files to google drive.
+/// At the end of the sync, successful or not, a report is printed and stored in the run history. If configured, the report is uploaded to Drive as well
+///
+/// # Errors
+/// - When the sync fails
+pub fn sync(config: &Configuration, env: &Env, options: &SyncOptions) -> Result<()> {
+    sync_with_report(config, env, options, &RunReport::new())
+}
+
+/// Sync the configured input files to google drive, recording the progress in the provided report so it can be followed while the sync runs.
+/// At the end of the sync, successful or not, a report is printed and stored in the run history. If configured, the report is uploaded to Drive as well,
+/// and the state of GSync is backed up
+///
+/// # Errors
+/// - When the sync fails
+pub fn sync_with_report(config: &Configuration, env: &Env, options: &SyncOptions, report: &RunReport) -> Result<()> {
+    // Remote operations are recorded in the audit log together with the run that performed them
+    let mut env = env.clone();
+    env.run = Some(report.started().timestamp());
+    let env = &env;
+
+    let result = if options.progress {
+        crate::bars::show(report, || sync_inputs(config, env, options, report))
+    } else {
+        sync_inputs(config, env, options, report)
+    };
+
+    let error = result.as_ref().err().map(|(e, line, file)| format!("{} (line {} in {})", crate::redact::redact(format!("{:?}", e)), line, file));
+    let text = report.render(error.as_deref());
+    println!("\n{}", text);
+
+    if let Err((e, _, _)) = report.save(env, result.is_ok()) {
+        println!("Warning: Failed to store the run in the run history: {}", crate::redact::redact(format!("{:?}", e)));
+    }
+
+    if config.upload_reports.unwrap_or(false) {
+        if let Err((e, _, _)) = upload_report(env, report, &text) {
+            println!("Warning: Failed to upload the run report: {}", crate::redact::redact(format!("{:?}", e)));
+        }
+    }
+
+    if let Err((e, _, _)) = crate::state::backup_if_due(config, env) {
+        println!("Warning: Failed to back up the GSync state: {}", crate::redact::redact(format!("{:?}", e)));
+    }
+
+    result
+}
+
+/// Upload the report of a run to the GSync root folder in the configured drive
+///
+/// # Errors
+/// - When the Google API returns an error
+fn upload_report(env: &Env, report: &RunReport, text: &str) -> Result<()> {
+    let mut env = env.clone();
+    env.root_folder = get_or_create_root_folder(&env)?;
+    report.upload(&env, text)
+}
+
+/// Traverse and sync all configured inputs. Before any changes are made, the plan of the sync is shown.
+/// After all files are synced, files which were removed locally are deleted from Drive
+///
+/// # Errors
+/// - When traversing an input fails
+/// - When the plan deletes more files than the deletion limit, and mass deletes are not allowed
+/// - When the storage quota would be exceeded and the quota action is `abort`
+/// - When the Google API returns an error
+/// - When a database operation fails
+/// - When reading the user's confirmation fails
+/// - When creating a filesystem snapshot of an input fails
+fn sync_inputs(config: &Configuration, env: &Env, options: &SyncOptions, report: &RunReport) -> Result<()> {
+    // The time budget includes the traversal, so the run fits in its window however long traversing takes
+    let budget = Budget::new(options.max_transfer, options.max_duration);
+    // The snapshots are removed again when they are dropped at the end of the sync, whether it succeeds or not
+    let shadows = create_shadows(config, env)?;
+    let (input_parts, children, skipped) = traverse_inputs(config, env, options, &shadows, true)?;
+    for (path, reason) in skipped.iter() {
+        report.add_skipped_local(path, reason);
+    }
+
+    for (path, reason) in children.iter().flat_map(|input| input.special.iter()) {
+        report.add_skipped(path, reason);
+    }
+
+    // Changes made in Drive are applied to the files table first, so the plan accounts for files deleted in Drive
+    let mut drive_ids = children.iter().map(|input| input.drive_id.clone()).collect::<Vec<_>>();
+    drive_ids.sort();
+    drive_ids.dedup();
+    for drive_id in drive_ids.iter() {
+        if let Err((e, _, _)) = crate::changes::check(env, drive_id.as_deref()) {
+            println!("Warning: Failed to check Drive for changes made outside of GSync: {}", crate::redact::redact(format!("{:?}", e)));
+        }
+    }
+
+    let tracked = files::get_all(env)?;
+    let tolerance = config.mtime_tolerance.unwrap_or(compare::DEFAULT_TOLERANCE);
+    // The checksums the plan computes are kept, so the sync doesn't read the files it compares again
+    let hash_cache = Arc::new(HashCache::default());
+    let plan = build_plan(&input_parts, &children, &skipped, &tracked, &hash::stored_all(env)?, &hash_cache, options.files_from.is_some(), tolerance);
+    println!("Info: Plan: {}", plan.summary());
+    for file in plan.oversized.iter() {
+        report.add_skipped(&file.path, &format!("larger than the maximum size of {}", crate::size::format_size(file.max_size)));
+    }
+
+    let previous = budget::deferred(env)?;
+    if !previous.is_empty() {
+        println!("Info: The previous run used up its budget before syncing {} files and folders, picking up where it left off", previous.len());
+    }
+
+    let policy = match options.purge {
+        true => DeletePolicy::Mirror,
+        false => config.delete_policy.as_deref().and_then(DeletePolicy::parse).unwrap_or(plan::DEFAULT_DELETE_POLICY)
+    };
+
+    // Archiving never deletes anything, so there is nothing to protect against
+    let delete_limit = config.delete_limit.as_deref().and_then(DeleteLimit::parse).unwrap_or(plan::DEFAULT_DELETE_LIMIT);
+    if policy != DeletePolicy::Archive && plan.exceeds_delete_limit(delete_limit) {
+        let message = format!("The sync would delete {} of {} tracked files from Drive, which exceeds the deletion limit of {}. Check that all inputs are mounted and your exclusions are correct", plan.deleted_files(), plan.tracked, delete_limit);
+        if !options.allow_mass_delete {
+            return Err((crate::Error::Other(format!("{}. Run with --allow-mass-delete to proceed anyway", message)), line!(), file!()));
+        }
+
+        println!("Warning: {}. Proceeding because of --allow-mass-delete", message);
+    }
+
+    let tracked_ids = tracked.iter().map(|file| file.id.clone()).collect::<HashSet<String>>();
+    let folder_ids = tracked.iter()
+        .filter(|file| file.is_folder)
+        .map(|file| (PathBuf::from(&file.path), (file.parent_id.clone(), file.id.clone())))
+        .collect::<HashMap<_, _>>();
+
+    // Only inputs synced to My Drive count towards the user's storage quota. Both new and changed files are uploaded
+    let upload_sizes = plan.actions.iter()
+        .filter(|action| action.action != Action::Delete && !action.is_folder)
+        .map(|action| (action.path.as_str(), action.size))
+        .collect::<HashMap<&str, u64>>();
+    let mut planned_bytes = 0u64;
+    let mut syncs_to_my_drive = false;
+    for input in children.iter() {
+        if input.drive_id.is_none() {
+            syncs_to_my_drive = true;
+            for child in input.children.iter() {
+                planned_bytes += child.upload_size(&upload_sizes);
+            }
+        }
+    }
+
+    if syncs_to_my_drive {
+        println!("Info: Checking storage quota");
+        crate::quota::check(config, env, planned_bytes)?;
+    }
+
+    // Shared drives don't have a storage quota, but limit the number of files and how deep folders are nested
+    let mut drive_usage: HashMap<&str, DriveUsage> = HashMap::new();
+    for input in children.iter() {
+        if let Some(drive_id) = &input.drive_id {
+            let usage = drive_usage.entry(drive_id.as_str()).or_default();
+            for child in input.children.iter() {
+                usage.add(&input.remote_root, child.count_items(), child.depth());
+            }
+        }
+    }
+
+    for (drive_id, usage) in drive_usage.iter() {
+        for warning in usage.warnings(drive_id) {
+            println!("Warning: {}", warning);
+        }
+    }
+
+    if options.confirm && !plan.is_empty() && !confirm()? {
+        println!("Info: Sync cancelled. No changes were made.");
+        return Ok(());
+    }
+
+    report.set_planned((plan.new_files() + plan.updated_files()) as u64 + plan.unchanged, plan.upload_bytes());
+    println!("Info: Beginning sync now.");
+
+    // The drive and ID of the root folders per drive and remote root, where None is My Drive
+    let mut root_folders: HashMap<(Option<String>, RemoteRoot), (Option<String>, String)> = HashMap::new();
+    let labels = match &config.labels {
+        Some(labels) => crate::labels::resolve(env, labels)?,
+        None => Vec::new()
+    };
+
+    let max_jobs = options.jobs.or_else(|| config.max_jobs.map(|jobs| jobs as usize)).unwrap_or(concurrency::DEFAULT_MAX_JOBS);
+    let state = SyncState {
+        hard_links: Mutex::new(HashMap::new()),
+        folder_ids: Mutex::new(folder_ids),
+        tracked_ids,
+        remote_edits: crate::changes::edited(env)?,
+        overwrite_remote: options.overwrite_remote,
+        limiter:    AdaptiveLimiter::new(max_jobs),
+        files:      Semaphore::new(max_jobs.max(1) + PREPARING_FILES),
+        db_writes:  Arc::new(Mutex::new(())),
+        labels,
+        strict:     options.strict,
+        compare:    input_parts.iter().cloned().zip(children.iter().map(|input| input.compare)).collect(),
+        tolerance,
+        algorithm:  HashAlgorithm::resolve(config.hash_algorithm.as_deref()),
+        hash_cache,
+        sources:    shadows.iter().map(|shadow| (shadow.input.clone(), shadow.path.clone())).collect(),
+        symlinks:   Mutex::new(Vec::new()),
+        budget,
+        report
+    };
+    for input in children {
+        let mut input_env = env.clone();
+        input_env.drive_id = input.drive_id.clone();
+
+        let key = (input.drive_id, input.remote_root);
+        let (drive_id, root_folder) = match root_folders.get(&key) {
+            Some(root_folder) => root_folder.clone(),
+            None => {
+                let root_folder = resolve_remote_root(&input_env, &key.1)?;
+                root_folders.insert(key, root_folder.clone());
+                root_folder
+            }
+        };
+
+        input_env.drive_id = drive_id;
+        input_env.root_folder = root_folder;
+        crate::api::block_on(sync_children(input.children, config, &input_env, None, &state)).map_err(limits::explain)?;
+    }
+
+    sync_symlinks(&state)?;
+
+    // Once the budget is used up the run stops, including deleting, which the next run picks up since the plan is made again
+    if state.budget.is_exhausted() {
+        println!("Info: The budget of this run is used up, deferring {} files and folders and the deletions to the next run", report.deferred().len());
+    } else {
+        remote_delete_removed(env, &plan, policy, report)?;
+    }
+
+    budget::journal(env, &report.deferred())
+
+}
+
+/// Traverse and plan a sync of all configured inputs, without making any changes.
+/// The plan is estimated from the local files and the files table, no requests to Google are made
+///
+/// ## Params
+/// - `options` The options of the sync to plan. Anomalies are always skipped
+/// - `log` Whether progress of the traversal should be printed. Disabled when the plan is written as JSON to stdout
+///
+/// # Errors
+/// - When traversing an input fails
+/// - When a database operation fails
+pub fn plan(config: &Configuration, env: &Env, options: &SyncOptions, log: bool) -> Result<Plan> {
+    let options = SyncOptions {
+        strict: false,
+        ..options.clone()
+    };
+
+    let (input_parts, children, skipped) = traverse_inputs(config, env, &options, &[], log)?;
+    let tracked = files::get_all(env)?;
+    Ok(build_plan(&input_parts, &children, &skipped, &tracked, &hash::stored_all(env)?, &HashCache::default(), options.files_from.is_some(), config.mtime_tolerance.unwrap_or(compare::DEFAULT_TOLERANCE)))
+}
+
+/// Get all local files and folders within the configured inputs, each together with the input it belongs to
+///
+/// # Errors
+/// - When traversing an input fails
+/// - When a database operation fails
+pub fn local_entries(config: &Configuration, env: &Env, log: bool) -> Result<Vec<(PathBuf, LocalEntry)>> {
+    let (input_parts, children, _) = traverse_inputs(config, env, &SyncOptions::default(), &[], log)?;
+
+    let mut entries = Vec::new();
+    for (input, traversed) in input_parts.into_iter().zip(children.iter()) {
+        let mut local = Vec::new();
+        for child in traversed.children.iter() {
+            child.local_entries(&mut local, traversed.compare);
+        }
+
+        entries.extend(local.into_iter().map(|entry| (input.clone(), entry)));
+    }
+
+    Ok(entries)
+}
+
+/// Traverse all configured inputs. Returns the inputs, per input where it is synced to together with its children, and the paths skipped because of an anomaly.
+/// An input which can't be found is skipped as well, its files are left alone in Drive. If the options list the files to sync, only those are collected
+///
+/// ## Params
+/// - `options` The options of the sync. If `strict` is set, an anomaly aborts the traversal instead of skipping the path
+/// - `shadows` The filesystem snapshots the inputs are read from, if any
+/// - `log` Whether progress of the traversal should be printed
+///
+/// # Errors
+/// - When traversing an input fails
+/// - When there is an anomaly and `strict` is set
+/// - When a database operation fails
+fn traverse_inputs(config: &Configuration, env: &Env, options: &SyncOptions, shadows: &[Shadow], log: bool) -> Result<(Vec<PathBuf>, Vec<TraversedInput>, Vec<Skipped>)> {
+    let started = chrono::Local::today().naive_local();
+    let strict = options.strict;
+    let mut skipped = Vec::new();
+
+    let mut input_parts = Vec::new();
+    for input in config.input_files.as_deref().unwrap_or_default().split(',').filter(|input| !input.is_empty()) {
+        let reason = match normalize_path(input) {
+            Ok(path) if path.to_str().is_some() => {
+                input_parts.push(path);
+                continue;
+            },
+            Ok(_) => "its path is not valid UTF-8".to_string(),
+            Err(e) => format!("it can't be found: {}", e)
+        };
+
+        if strict {
+            return Err((crate::Error::Other(format!("Input '{}' {}. Aborting because of --strict", input, reason)), line!(), file!()));
+        }
+
+        println!("Warning: Skipping input '{}': {}", input, reason);
+        skipped.push((PathBuf::from(input), reason));
+    }
+
+    // Inputs are canonicalized, so an input configured twice, e.g. once through a symlink, is only synced once
+    let mut seen = HashSet::new();
+    input_parts.retain(|input| seen.insert(input.clone()));
+
+    for (outer, inner) in overlapping_inputs(&input_parts) {
+        println!("Warning: Input '{}' is inside input '{}'. Its files are only synced with '{}'", inner.to_string_lossy(), outer.to_string_lossy(), inner.to_string_lossy());
+    }
+
+    if let Some(files_from) = &options.files_from {
+        for path in files_from.iter().filter(|path| !input_parts.iter().any(|input| path.starts_with(input))) {
+            let reason = "it is not within any of the inputs".to_string();
+            if strict {
+                return Err((crate::Error::Other(format!("'{}' {}. Aborting because of --strict", path.to_string_lossy(), reason)), line!(), file!()));
+            }
+
+            println!("Warning: Skipping '{}': {}", path.to_string_lossy(), reason);
+            skipped.push((path.clone(), reason));
+        }
+    }
 
     let mut children = Vec::new();
-    for input in input_parts {
-        let name = input.clone();
-        let name = name.to_str().unwrap();
-        println!("Info: Traversing file tree for input '{}'", name);
-        let mut ichildren = traverse(input, &mut Vec::new())?;
+    for input in input_parts.clone() {
+        // Checked to be valid UTF-8 above
+        let name = input.to_string_lossy().to_string();
+        if log {
+            println!("Info: Traversing file tree for input '{}'", name);
+        }
+
+        let input_config = InputConfiguration::get(env, &name)?;
+        let mut filter = InputFilter::for_input(&input_config);
+        // The maximum size of the input, even if it is 0, takes precedence over the configured maximum size
+        filter.max_size = match options.max_size {
+            Some(max_size) => Some(max_size).filter(|max_size| *max_size > 0),
+            None if input_config.max_size.is_none() => config.max_size.filter(|max_size| *max_size > 0).map(|max_size| max_size as u64),
+            None => filter.max_size
+        };
+        filter.excludes.extend(config.excludes());
+        filter.excludes.extend(options.excludes.iter().cloned());
+        filter.include_ext = options.include_ext.clone();
+        filter.exclude_ext = options.exclude_ext.clone();
+        // Nested inputs are synced on their own, so they are not synced a second time as part of this one
+        let mut exclusions = state_paths(env);
+        exclusions.extend(overlapping_inputs(&input_parts).into_iter()
+            .filter(|(outer, _)| **outer == input)
+            .map(|(_, inner)| inner.to_path_buf()));
+        // A btrfs snapshot is created next to its input, which may be inside another input
+        exclusions.extend(shadows.iter().map(|shadow| shadow.path.clone()));
+
+        let mut traversal = Traversal {
+            input:      &input,
+            shadow:     shadows.iter().find(|shadow| shadow.input == input).map(|shadow| shadow.path.as_path()),
+            filter:     &filter,
+            overrides:  Vec::new(),
+            exclusions,
+            gitignores: Vec::new(),
+            strict,
+            // A policy for the run takes precedence over the policy of the input
+            symlinks:   options.symlinks.unwrap_or_else(|| SymlinkPolicy::resolve(input_config.symlinks.as_deref(), config.symlinks.as_deref())),
+            log,
+            ancestors:  Vec::new(),
+            skipped:    Vec::new(),
+            oversized:  Vec::new(),
+            special:    Vec::new()
+        };
+
+        let ichildren = match &options.files_from {
+            Some(files_from) => {
+                let listed = files_from.iter().filter(|path| path.starts_with(&input)).cloned().collect::<Vec<_>>();
+                select(input.parent().unwrap_or(&input), &listed, &mut traversal)?
+            },
+            None => traverse(input.clone(), &mut traversal)?
+        };
+        skipped.append(&mut traversal.skipped);
+        let oversized = std::mem::take(&mut traversal.oversized);
+        let special = std::mem::take(&mut traversal.special);
+
+        let mut child_count = 0i64;
+        for child in ichildren.iter() {
+            child_count += child.count_all_children();
+        }
+
+        if log {
+            println!("Info: Found {} child nodes for input '{}'.", child_count, name);
+        }
+
+        // The target folder lives in the configured drive, so per-input drives don't apply
+        let drive_id = match &env.target_folder {
+            Some(_) => env.drive_id.clone(),
+            None => input_config.target_drive(env.drive_id.as_ref())
+        };
+
+        // Placeholders are expanded with the date the traversal started, so a sync running past midnight stays in the same folder
+        let remote_root = match input_config.remote_root() {
+            RemoteRoot::Path(path) => RemoteRoot::Path(template::expand(&path, &TemplateValues::new(&input, started))),
+            remote_root => remote_root
+        };
+
+        children.push(TraversedInput {
+            drive_id,
+            remote_root,
+            compare:        ComparePolicy::resolve(input_config.compare.as_deref(), config.compare.as_deref()),
+            children:       ichildren,
+            oversized,
+            special
+        });
+    }
+
+    if log {
+        println!("Info: All directories traversed.");
+    }
+
+    Ok((input_parts, children, skipped))
+}
+
+/// Get the paths of GSync's own state, which are never synced even if they are within an input: the database with the files SQLite keeps next to it,
+/// GSync's own state directory, and the audit log. The database holds the client secret and the tokens unencrypted, and syncing it while it is written to
+/// would upload a corrupt copy and dirty every run. A state directory set with the state directory variable may be an input or be inside one, e.g. the home folder,
+/// so it isn't excluded as a whole. Match paths against these with [is_within]
+pub fn state_paths(env: &Env) -> Vec<PathBuf> {
+    // Traversed paths are built from canonicalized inputs, so the state paths must be canonicalized to match. The database files may not exist yet, so their folder is
+    let dir = PathBuf::from(&env.db);
+    let dir = fs::canonicalize(&dir).unwrap_or(dir);
+    let mut paths = ["", "-journal", "-wal", "-shm"].iter()
+        .map(|suffix| dir.join(format!("{}{}", crate::env::DB_FILE_NAME, suffix)))
+        .collect::<Vec<_>>();
+    if crate::env::is_own_db_dir(&dir) {
+        paths.push(dir);
+    }
+
+    if let Some(audit_log) = &env.audit_log {
+        let audit_log = PathBuf::from(audit_log);
+        paths.push(fs::canonicalize(&audit_log).unwrap_or(audit_log));
+    }
+
+    paths
+}
+
+/// Whether a path is one of the provided paths, or inside one of them
+pub fn is_within(path: &Path, paths: &[PathBuf]) -> bool {
+    paths.iter().any(|other| path.starts_with(other))
+}
+
+/// Move a path from one folder to another, e.g. from an input to the filesystem snapshot of it. A path outside of `from` is returned as-is
+fn rebase(path: &Path, from: &Path, to: &Path) -> PathBuf {
+    match path.strip_prefix(from) {
+        Ok(relative) => to.join(relative),
+        Err(_) => path.to_path_buf()
+    }
+}
+
+/// Create the filesystem snapshots of the inputs which are configured to be synced from one
+///
+/// # Errors
+/// - When a database operation fails
+/// - When creating a snapshot fails
+fn create_shadows(config: &Configuration, env: &Env) -> Result<Vec<Shadow>> {
+    let mut shadows = Vec::new();
+    for input in config.input_files.as_deref().unwrap_or_default().split(',').filter(|input| !input.is_empty()) {
+        // Inputs which can't be found are reported by the traversal
+        let input = match normalize_path(input) {
+            Ok(input) => input,
+            Err(_) => continue
+        };
+
+        let kind = InputConfiguration::get(env, &input.to_string_lossy())?.fs_snapshot.as_deref().and_then(ShadowKind::parse);
+        if let Some(kind) = kind {
+            shadows.push(Shadow::create(kind, &input)?);
+        }
+    }
+
+    Ok(shadows)
+}
+
+/// Find the inputs which are inside another input. Returns pairs of the outer and the inner input.
+/// The files of an inner input are synced with the inner input only, so each file has a single destination
+fn overlapping_inputs(inputs: &[PathBuf]) -> Vec<(&Path, &Path)> {
+    let mut overlapping = Vec::new();
+    for inner in inputs {
+        // Only the closest enclosing input excludes the inner input, the inputs in between take care of the rest
+        let outer = inputs.iter()
+            .filter(|outer| *outer != inner && inner.starts_with(outer))
+            .max_by_key(|outer| outer.components().count());
+
+        if let Some(outer) = outer {
+            overlapping.push((outer.as_path(), inner.as_path()));
+        }
+    }
+
+    overlapping
+}
+
+/// Build the plan of a sync from the traversed inputs and the tracked files.
+/// Tracked files within skipped paths are not deleted, they may only have been skipped because of a temporary problem
+#[allow(clippy::too_many_arguments)]
+fn build_plan(inputs: &[PathBuf], children: &[TraversedInput], skipped: &[Skipped], tracked: &[TrackedFile], hashes: &HashMap<String, (HashAlgorithm, String)>, cache: &HashCache, explicit: bool, tolerance: i64) -> Plan {
+    let mut local = Vec::new();
+    for input in children.iter() {
+        for child in input.children.iter() {
+            child.local_entries(&mut local, input.compare);
+        }
+    }
+
+    let mut plan = plan::build(&local, inputs, tracked, hashes, cache, tolerance);
+    plan.oversized = children.iter().flat_map(|input| input.oversized.iter().cloned()).collect();
+    plan.keep_within(&skipped.iter().map(|(path, _)| path.clone()).collect::<Vec<_>>());
+
+    // Files which are not listed aren't gone, they just weren't traversed
+    if explicit {
+        plan.keep_within(inputs);
+    }
+
+    plan
+}
+
+/// Ask the user whether they want to continue with the sync
+///
+/// # Errors
+/// - When reading from stdin fails
+fn confirm() -> Result<bool> {
+    use std::io::Write;
+
+    print!("Continue with the sync? [y/N] ");
+    unwrap_other_err!(std::io::stdout().flush());
+
+    let mut answer = String::new();
+    unwrap_other_err!(std::io::stdin().read_line(&mut answer));
+    Ok(answer.trim().eq_ignore_ascii_case("y") || answer.trim().eq_ignore_ascii_case("yes"))
+}
+
+/// Delete the files and folders the plan marked for deletion from Drive, following the deletion policy, and stop tracking them.
+/// Files within a folder which is deleted are deleted along with the folder, so they are not deleted separately
+///
+/// # Errors
+/// - When the Google API returns an error
+/// - When a database operation fails
+fn remote_delete_removed(env: &Env, plan: &Plan, policy: DeletePolicy, report: &RunReport) -> Result<()> {
+    let mut deletions = plan.deletions().collect::<Vec<_>>();
+    deletions.sort_by(|a, b| a.path.cmp(&b.path));
+
+    // Everything in a removed folder goes along with it, so only the outermost files and folders are removed in Drive
+    let mut removals = Vec::new();
+    let mut removed_folders: Vec<&Path> = Vec::new();
+    for deletion in &deletions {
+        let path = Path::new(&deletion.path);
+        if !removed_folders.iter().any(|folder| path.starts_with(folder)) {
+            removals.push(*deletion);
+            if deletion.is_folder {
+                removed_folders.push(path);
+            }
+        }
+    }
+
+    for deletion in &removals {
+        match policy {
+            DeletePolicy::Trash => println!("Info: Moving '{}' to the trash in Drive, it was removed locally", deletion.path),
+            DeletePolicy::Mirror => println!("Info: Deleting '{}' from Drive, it was removed locally", deletion.path),
+            DeletePolicy::Archive => println!("Info: Keeping '{}' in Drive as stale, it was removed locally", deletion.path)
+        }
+    }
+
+    // Unwrap is safe because the plan sets the ID for every deletion
+    let ids = removals.iter().map(|deletion| deletion.id.as_deref().unwrap()).collect::<Vec<_>>();
+    let results = match policy {
+        DeletePolicy::Trash => drive::blocking::trash_files(env, &ids)?,
+        DeletePolicy::Mirror => drive::blocking::delete_files(env, &ids)?,
+        DeletePolicy::Archive => drive::blocking::mark_stale_files(env, &ids)?
+    };
+
+    let mut removed: Vec<&Path> = Vec::new();
+    let mut error = None;
+    for (deletion, result) in removals.iter().zip(results) {
+        match result {
+            Ok(_) => {},
+            // The file was already removed from Drive
+            Err((crate::Error::GoogleError(e), _, _)) if e.code == 404 => {},
+            Err(e) => {
+                error.get_or_insert(e);
+                continue;
+            }
+        }
+
+        if policy != DeletePolicy::Archive {
+            report.add_deleted();
+        }
+
+        removed.push(Path::new(&deletion.path));
+    }
+
+    // Files which failed to be removed stay tracked, so they are tried again on the next sync
+    for deletion in deletions {
+        if removed.iter().any(|path| Path::new(&deletion.path).starts_with(path)) {
+            files::delete(env, &deletion.path)?;
+        }
+    }
+
+    match error {
+        Some(e) => Err(e),
+        None => Ok(())
+    }
+}
+
+/// Find the ID of the GSync root folder in Google Drive, if it exists. If a target folder is configured, that folder is the root folder
+///
+/// # Errors
+/// - When the Google API returns an error
+/// - When reqwest returns an error
+pub fn find_root_folder(env: &Env) -> Result<Option<String>> {
+    if let Some(target_folder) = &env.target_folder {
+        return Ok(Some(target_folder.clone()));
+    }
+
+    let list = drive::blocking::list_files(env, Some("name = 'GSync' and mimeType = 'application/vnd.google-apps.folder' and trashed = false"), env.drive_id.as_deref())?;
+    Ok(list.into_iter().next().map(|folder| folder.id))
+}
+
+/// Get the ID of the GSync root folder in Google Drive, creating it if it does not exist.
+/// If a target folder is configured, it is verified that the folder exists and can be written to instead
+///
+/// # Errors
+/// - When the Google API returns an error
+/// - When reqwest returns an error
+/// - When the target folder is not a folder, or the user can't add files to it
+pub fn get_or_create_root_folder(env: &Env) -> Result<String> {
+    if let Some(target_folder) = &env.target_folder {
+        return Ok(get_writable_folder(env, target_folder)?.id);
+    }
+
+    println!("Info: Querying Drive for root folder");
+    let root_folder_id = match find_root_folder(env)? {
+        Some(id) => {
+            println!("Info: Root folder exists.");
+            id
+        },
+        None => {
+            println!("Info: Root folder doesn't exist. Creating one now.");
+            match &env.drive_id {
+                Some(drive_id) => drive::blocking::create_folder(env, "GSync", drive_id, &HashMap::new())?,
+                None => drive::blocking::create_folder(env, "GSync", "root", &HashMap::new())?
+            }
+        }
+    };
+
+    Ok(root_folder_id)
+}
+
+/// Get an existing folder, verifying that the user can add files to it
+///
+/// # Errors
+/// - When the Google API returns an error
+/// - When the file is not a folder, or the user can't add files to it
+fn get_writable_folder(env: &Env, id: &str) -> Result<drive::File> {
+    println!("Info: Checking access to folder '{}'", id);
+    let folder = drive::blocking::get_file(env, id)?;
+    if !folder.is_folder() {
+        return Err((crate::Error::Other(format!("'{}' ({}) is not a folder", folder.name, id)), line!(), file!()));
+    }
+
+    if !folder.capabilities.as_ref().map(|capabilities| capabilities.can_add_children).unwrap_or(false) {
+        return Err((crate::Error::Other(format!("You don't have permission to add files to the folder '{}' ({})", folder.name, id)), line!(), file!()));
+    }
+
+    Ok(folder)
+}
+
+/// Resolve the folder an input is synced into. Returns the drive the folder is in, where None is My Drive, and the ID of the folder
+///
+/// # Errors
+/// - When the Google API returns an error
+/// - When an existing folder can't be written to
+fn resolve_remote_root(env: &Env, remote_root: &RemoteRoot) -> Result<(Option<String>, String)> {
+    match remote_root {
+        RemoteRoot::Default => Ok((env.drive_id.clone(), get_or_create_root_folder(env)?)),
+        RemoteRoot::Path(path) => Ok((env.drive_id.clone(), get_or_create_folder_path(env, path)?)),
+        RemoteRoot::Id(id) => {
+            let folder = get_writable_folder(env, id)?;
+            Ok((folder.drive_id, folder.id))
+        }
+    }
+}
+
+/// Get the ID of the folder at a path relative to the root of the drive, creating the folders on the path which don't exist
+///
+/// # Errors
+/// - When the Google API returns an error
+fn get_or_create_folder_path(env: &Env, path: &str) -> Result<String> {
+    let mut parent_id = env.drive_id.clone().unwrap_or_else(|| "root".to_string());
+    for part in path.split('/').filter(|part| !part.is_empty()) {
+        let query = format!("name = '{}' and mimeType = 'application/vnd.google-apps.folder' and trashed = false and '{}' in parents", drive::escape_query_value(part), &parent_id);
+        parent_id = match drive::blocking::list_files(env, Some(&query), env.drive_id.as_deref())?.into_iter().next() {
+            Some(folder) => folder.id,
+            None => {
+                println!("Info: Creating folder '{}'", part);
+                drive::blocking::create_folder(env, part, &parent_id, &HashMap::new())?
+            }
+        };
+    }
+
+    Ok(parent_id)
+}
+
+/// Sync a list of Child's with Google Drive. The files are synced while the directories are synced recursively, so listing and creating folders overlaps with the uploads.
+/// The parent folder is listed once, after which the children are matched with the listing by their name
+///
+/// # Errors
+/// - When the Google API returns an error
+/// - When a database operation fails
+fn sync_children<'a>(children: Vec<Child>, config: &'a Configuration, env: &'a Env, parent_folder_id: Option<&'a str>, state: &'a SyncState<'_>) -> LocalBoxFuture<'a, Result<()>> {
+    // Boxed, since folders are synced recursively
+    Box::pin(async move {
+        let mut files = Vec::new();
+        let mut directories = Vec::new();
+        let mut symlinks = Vec::new();
+        for child in children {
+            match child {
+                Child::File(path) => files.push(path),
+                Child::Directory(dir) => directories.push(dir),
+                Child::Symlink(link) => symlinks.push(link)
+            }
+        }
+
+        let listing = list_folder(env, parent_folder_id.unwrap_or(&env.root_folder)).await?;
+
+        // The root folder holds the top level folders of all inputs, and the reports folder, so orphans are only detected below it
+        if parent_folder_id.is_some() {
+            let local_names = files.iter().map(|path| path.file_name().unwrap().to_string_lossy().to_string())
+                .chain(directories.iter().map(|dir| dir.name.clone()))
+                .chain(symlinks.iter().map(|link| link.name.clone()))
+                .collect::<HashSet<String>>();
+
+            for (name, remote_files) in listing.iter() {
+                if local_names.contains(name.as_str()) {
+                    continue;
+                }
+
+                for file in remote_files.iter().filter(|file| !state.tracked_ids.contains(&file.id)) {
+                    println!("Info: '{}' exists in Drive, but not locally and is not tracked by GSync (ID: {})", name, file.id);
+                }
+            }
+        }
+
+        // A link can point at anything in the inputs, so links are synced once everything else is
+        for link in symlinks {
+            let existing = listing.get(&link.name).cloned().unwrap_or_default();
+            state.symlinks.lock().unwrap_or_else(PoisonError::into_inner).push(PendingSymlink {
+                link,
+                env:        env.clone(),
+                parent_id:  parent_folder_id.unwrap_or(&env.root_folder).to_string(),
+                existing
+            });
+        }
+
+        futures::try_join!(
+            sync_files(files, config, env, parent_folder_id, &listing, state),
+            sync_directories(directories, config, env, parent_folder_id, &listing, state)
+        )?;
+
+        Ok(())
+    })
+}
+
+/// List the files and folders in a folder in Google Drive
+///
+/// # Errors
+/// - When the Google API returns an error
+async fn list_folder(env: &Env, folder_id: &str) -> Result<FolderListing> {
+    println!("Info: Listing folder '{}' in Drive", folder_id);
+
+    let mut listing = FolderListing::new();
+    for file in drive::list_files(env, Some(&format!("'{}' in parents and trashed = false", folder_id)), env.drive_id.as_deref()).await? {
+        listing.entry(file.name.clone()).or_default().push(file);
+    }
+
+    Ok(listing)
+}
+
+/// Sync files in the same folder concurrently. The number of concurrent uploads is tuned by the limiter in the SyncState,
+/// while a few more files are compared and hashed so the connection doesn't sit idle while the local files are read
+///
+/// # Errors
+/// - When syncing any of the files fails, the first error is returned and no more files are synced
+async fn sync_files(files: Vec<PathBuf>, config: &Configuration, env: &Env, parent_folder_id: Option<&str>, listing: &FolderListing, state: &SyncState<'_>) -> Result<()> {
+    let workers = state.limiter.max() + PREPARING_FILES;
+    stream::iter(files.into_iter().map(Ok))
+        .try_for_each_concurrent(workers, |path| async move {
+            sync_file_limited(&path, config, env, parent_folder_id, listing, state).await
+        })
+        .await
+}
+
+/// Sync a file, the transfer itself waits for the limiter, see [transfer].
+/// Requests Google throttles, uploads included, are already retried with a backoff, see [crate::api::retry::send_with], so the file isn't retried again.
+/// Unless the sync is strict, a file which fails to sync is reported and skipped
+///
+/// # Errors
+/// - When syncing the file fails and the sync is strict
+/// - When a database operation or a request fails
+/// - When Google rejects the file because a limit of the drive was reached
+/// - When Google keeps throttling us after all retries and the sync is strict
+async fn sync_file_limited(path: &Path, config: &Configuration, env: &Env, parent_folder_id: Option<&str>, listing: &FolderListing, state: &SyncState<'_>) -> Result<()> {
+    // The semaphore is never closed, so acquiring only fails in theory
+    let _permit = state.files.acquire().await.ok();
+    match sync_file(path, config, env, parent_folder_id, listing, state).await {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            let limit_reached = limits::exceeded(&e.0).is_some();
+            let e = limits::explain(e);
+            state.report.add_failure(path, &format!("{:?}", e.0));
+
+            // A problem with the database or the connection, or a limit of the drive, affects every file, so it always aborts the sync
+            if state.strict || limit_reached || matches!(e.0, crate::Error::DatabaseError(_) | crate::Error::RequestError(_)) {
+                return Err(e);
+            }
+
+            println!("Warning: Failed to sync '{}', skipping it: {}", path.to_string_lossy(), crate::redact::redact(format!("{:?}", e.0)));
+            Ok(())
+        }
+    }
+}
+
+/// Sync the directories in the same folder one after another, see [sync_directory]
+///
+/// # Errors
+/// - When syncing any of the directories fails
+async fn sync_directories(directories: Vec<Directory>, config: &Configuration, env: &Env, parent_folder_id: Option<&str>, listing: &FolderListing, state: &SyncState<'_>) -> Result<()> {
+    for dir in directories {
+        sync_directory(dir, config, env, parent_folder_id, listing, state).await?;
+    }
+
+    Ok(())
+}
+
+/// Sync a directory and its children with Google Drive
+///
+/// # Errors
+/// - When the Google API returns an error
+/// - When a database operation fails
+async fn sync_directory(dir: Directory, config: &Configuration, env: &Env, parent_folder_id: Option<&str>, listing: &FolderListing, state: &SyncState<'_>) -> Result<()> {
+    if state.budget.is_exhausted() {
+        state.report.add_deferred(&dir.path);
+        return Ok(());
+    }
+
+    // A cached ID is only valid if the folder is still synced to the same parent, e.g. not when the input was moved to another drive,
+    // and if the folder is still in Drive. A folder trashed or deleted in Drive is looked up or created again
+    let cached_id = state.folder_ids.lock().unwrap_or_else(PoisonError::into_inner).get(&dir.path)
+        .filter(|(parent_id, _)| parent_id.eq(parent_folder_id.unwrap_or(&env.root_folder)))
+        .filter(|(_, id)| listing.get(&dir.name).into_iter().flatten().any(|file| file.is_folder() && file.id.eq(id)))
+        .map(|(_, id)| id.clone());
+
+    let folder_id = if let Some(id) = cached_id {
+        println!("Info: Directory '{}' is known", &dir.name);
+        id
+    } else {
+        let mut id = String::new();
+        for file in listing.get(&dir.name).into_iter().flatten().filter(|file| file.is_folder()) {
+            id = file.id.clone();
+        }
+
+        if id.is_empty() {
+            println!("Info: Creating directory '{}'", &dir.name);
+            let source = state.source(&dir.path);
+            id = match parent_folder_id {
+                Some(pfi) => drive::create_folder(env, &dir.name, pfi, &metadata::collect(&source, config.preserve_xattrs.unwrap_or(false))).await?,
+                None => drive::create_folder(env, &dir.name, &env.root_folder, &metadata::collect(&source, config.preserve_xattrs.unwrap_or(false))).await?
+            };
+
+            if !state.labels.is_empty() {
+                drive::modify_labels(env, &id, &state.labels).await?;
+            }
+
+            state.report.add_folder();
+        }
+
+        state.folder_ids.lock().unwrap_or_else(PoisonError::into_inner).insert(dir.path.clone(), (parent_folder_id.unwrap_or(&env.root_folder).to_string(), id.clone()));
+        id
+    };
+
+    insert_file(env, TrackedFile {
+        path:       dir.path.to_string_lossy().to_string(),
+        id:         folder_id.clone(),
+        parent_id:  parent_folder_id.unwrap_or(&env.root_folder).to_string(),
+        is_folder:  true,
+        link_target: None,
+        size:       None,
+        md5:        None,
+        synced_at:  Some(chrono::Utc::now().timestamp())
+    }).await?;
+
+    sync_children(dir.children, config, env, Some(&folder_id), state).await
+}
+
+/// Record a synced file or folder in the files table, on the blocking thread pool
+///
+/// # Errors
+/// - When a database operation fails
+async fn insert_file(env: &Env, file: TrackedFile) -> Result<()> {
+    let env = env.clone();
+    crate::api::unblock(move || files::insert(&env, &file)).await
+}
+
+/// Sync a file with Google Drive
+///
+/// # Errors
+/// - When the Google API returns an error
+/// - When a database operation fails
+/// - When reading the file's metadata fails
+async fn sync_file(path: &Path, config: &Configuration, env: &Env, parent_folder_id: Option<&str>, listing: &FolderListing, state: &SyncState<'_>) -> Result<()> {
+    if state.budget.is_exhausted() {
+        state.report.add_deferred(path);
+        return Ok(());
+    }
+
+    let file_name = path.file_name().unwrap().to_string_lossy();
+    let mut query_result = listing.get(file_name.as_ref()).into_iter().flatten().filter(|file| !file.is_folder()).cloned().collect::<Vec<_>>();
+
+    // The content is read from the filesystem snapshot of the input if there is one, the file is tracked by its own path
+    let source = state.source(path);
+    let hard_link_key = hard_link_key(&source);
+    let linked = hard_link_key.and_then(|key| state.hard_links.lock().unwrap_or_else(PoisonError::into_inner).get(&key).cloned());
+    if let Some((target_path, target_id)) = linked {
+        sync_hard_link(env, path, parent_folder_id.unwrap_or(&env.root_folder), &query_result, &target_path, &target_id).await?;
+        state.report.add_link();
+        return Ok(());
+    }
+
+    // A hard link synced before is a shortcut to the file it was linked to. The links may be synced in any order, so the shortcut is kept
+    // while that file is still linked. Once it was removed, the shortcut is replaced with the content, which would otherwise be trashed with it
+    if let Some(shortcut) = query_result.iter().find(|file| file.is_shortcut() && file.app_properties.contains_key(metadata::HARD_LINK_TARGET)).cloned() {
+        let target_id = shortcut.app_properties[metadata::HARD_LINK_TARGET].clone();
+        let target_path = tracked_path(env, &target_id).await?;
+        match target_path {
+            Some(target_path) if is_linked_to(&source, &state.source(&target_path)) => {
+                sync_hard_link(env, path, parent_folder_id.unwrap_or(&env.root_folder), &query_result, &target_path, &target_id).await?;
+                state.report.add_link();
+                return Ok(());
+            },
+            _ => {
+                println!("Info: '{}' is no longer a hard link of the file its shortcut points to, replacing the shortcut with the file", file_name);
+                drive::set_trashed(env, &shortcut.id, true).await?;
+                query_result.retain(|file| file.id != shortcut.id);
+            }
+        }
+    }
+
+    if query_result.iter().any(|file| file.is_shortcut()) {
+        println!("Warning: '{}' exists in Drive as a shortcut, it will not be overwritten. Skipping.", file_name);
+        state.report.add_skipped(path, "exists in Drive as a shortcut");
+        return Ok(());
+    }
+
+    // The ID, MD5 checksum and size of the content in Drive, as recorded in the files table, and the checksum of the local file if it was computed
+    let (id, md5, size, checksum) = match query_result.first() {
+        Some(file) => {
+            let (changed, size, stored_hash) = compare_file(env, path, &source, file, state.compare_policy(path), state.tolerance, &state.hash_cache).await?;
+            if changed {
+                let edited = state.remote_edits.contains(&file.id);
+                if edited && !state.overwrite_remote {
+                    println!("Warning: '{}' was edited in Drive since the last sync, it will not be overwritten. Skipping.", file_name);
+                    state.report.add_skipped(path, "edited in Drive since the last sync, restore it or sync with --overwrite-remote");
+                    return Ok(());
+                }
+
+                if !reserve(state, path, size) {
+                    return Ok(());
+                }
+
+                let checksum = checksum(&state.hash_cache, path, &source, state.algorithm).await?;
+                println!("Info: Updating file '{}'", file_name);
+                let uploaded = transfer(state, size, drive::update_file(env, &source, &file.id, &metadata::collect(&source, config.preserve_xattrs.unwrap_or(false)))).await?;
+                if edited {
+                    resolve_edit(env, &file.id).await?;
+                }
+
+                state.report.add_updated(size);
+                (file.id.clone(), uploaded.md5_checksum, size, Some((state.algorithm, checksum)))
+            } else {
+                // The edit in Drive is settled once the local file has the same content, e.g. after restoring it
+                if state.remote_edits.contains(&file.id) {
+                    let (env, source, file) = (env.clone(), source.clone(), file.clone());
+                    crate::api::unblock(move || {
+                        if file.md5_checksum.as_deref().is_some_and(|md5| crate::sparse::md5_as_uploaded(&source, metadata::is_packed(&file.app_properties)).is_ok_and(|local| local.eq_ignore_ascii_case(md5))) {
+                            crate::changes::resolve(&env, &file.id)?;
+                        }
+
+                        Ok(())
+                    }).await?;
+                }
+
+                println!("Info: File '{}' is up-to-date.", file_name);
+                state.report.add_unchanged();
+                (file.id.clone(), file.md5_checksum.clone(), size, stored_hash)
+            }
+        }
+        None => {
+            let size = unwrap_other_err!(source.metadata()).len();
+            if !reserve(state, path, size) {
+                return Ok(());
+            }
+
+            let checksum = checksum(&state.hash_cache, path, &source, state.algorithm).await?;
+            println!("Info: Uploading file '{}'", file_name);
+            let uploaded = transfer(state, size, drive::upload_file(env, &source, parent_folder_id.unwrap_or(&env.root_folder), &metadata::collect(&source, config.preserve_xattrs.unwrap_or(false)))).await?;
+
+            if !state.labels.is_empty() {
+                drive::modify_labels(env, &uploaded.id, &state.labels).await?;
+            }
+
+            state.report.add_uploaded(size);
+            (uploaded.id, uploaded.md5_checksum, size, Some((state.algorithm, checksum)))
+        }
+    };
+
+    // Another link to the same file may have been synced concurrently, in which case both are uploaded as regular files
+    if let Some(key) = hard_link_key {
+        state.hard_links.lock().unwrap_or_else(PoisonError::into_inner).insert(key, (path.to_path_buf(), id.clone()));
+    }
+
+    let tracked = TrackedFile {
+        path:       path.to_string_lossy().to_string(),
+        id,
+        parent_id:  parent_folder_id.unwrap_or(&env.root_folder).to_string(),
+        is_folder:  false,
+        link_target: None,
+        size:       Some(size as i64),
+        md5,
+        synced_at:  Some(chrono::Utc::now().timestamp())
+    };
+
+    let (env, db_writes) = (env.clone(), state.db_writes.clone());
+    crate::api::unblock(move || {
+        let _db_writes = db_writes.lock().unwrap_or_else(PoisonError::into_inner);
+        files::insert(&env, &tracked)?;
+
+        // Inserting replaces the row, so the checksum is stored again even if the file didn't change
+        if let Some((algorithm, checksum)) = checksum {
+            hash::store(&env, &tracked.path, algorithm, &checksum)?;
+        }
+
+        Ok(())
+    }).await
+}
+
+/// Compare a local file with its copy in Drive, on the blocking thread pool since the file may have to be hashed.
+/// A checksum the plan computed for the file is reused if the file didn't change since.
+/// Returns whether the file changed, its size, and the checksum stored for it when it was last synced
+///
+/// # Errors
+/// - When a database operation fails
+/// - When reading the file fails
+async fn compare_file(env: &Env, path: &Path, source: &Path, file: &drive::File, policy: ComparePolicy, tolerance: i64, cache: &Arc<HashCache>) -> Result<(bool, u64, Option<(HashAlgorithm, String)>)> {
+    let (env, path, source, file, cache) = (env.clone(), path.to_path_buf(), source.to_path_buf(), file.clone(), cache.clone());
+    crate::api::unblock(move || {
+        let mod_time_rfc_3339 = &file.modified_time;
+        let mod_time_epoch = unwrap_other_err!(chrono::DateTime::parse_from_rfc3339(mod_time_rfc_3339)).timestamp();
+        let copy = SyncedCopy {
+            modified:   mod_time_epoch,
+            mtime:      metadata::recorded_mtime(&file.app_properties),
+            // The size and MD5 checksum in Drive of a file uploaded packed are not those of the local file
+            size:       file.size.as_ref().and_then(|size| size.parse().ok()).filter(|_| !metadata::is_packed(&file.app_properties)),
+            md5:        file.md5_checksum.clone().filter(|_| !metadata::is_packed(&file.app_properties)),
+            hash:       hash::stored(&env, &path.to_string_lossy(), &file.id)?
+        };
+
+        let modified = get_modification_time(&source)? as i64;
+        let size = unwrap_other_err!(source.metadata()).len();
+        let changed = compare::changed(policy, tolerance, modified, size, &copy, |algorithm| cache.hash_file(&path, &source, algorithm))?;
+        Ok((changed, size, copy.hash))
+    }).await
+}
+
+/// Compute the checksum of a file with the configured algorithm on the blocking thread pool, see [HashCache::hash_file].
+/// It is computed before the upload, so a file changing while it is uploaded is seen as changed by the next sync.
+/// The MD5 checksum is taken from Google's response instead, so it is the checksum of the content in Drive
+///
+/// # Errors
+/// - When reading the file fails
+async fn checksum(cache: &Arc<HashCache>, path: &Path, source: &Path, algorithm: HashAlgorithm) -> Result<String> {
+    let (cache, path, source) = (cache.clone(), path.to_path_buf(), source.to_path_buf());
+    crate::api::unblock(move || cache.hash_file(&path, &source, algorithm)).await
+}
+
+/// Get the path of the file or folder with the provided ID in the files table, on the blocking thread pool
+///
+/// # Errors
+/// - When a database operation fails
+async fn tracked_path(env: &Env, id: &str) -> Result<Option<PathBuf>> {
+    let (env, id) = (env.clone(), id.to_string());
+    crate::api::unblock(move || Ok(files::get_by_id(&env, &id)?.map(|tracked| PathBuf::from(tracked.path)))).await
+}
+
+/// Settle an edit made in Drive, on the blocking thread pool, see [crate::changes::resolve]
+///
+/// # Errors
+/// - When a database operation fails
+async fn resolve_edit(env: &Env, id: &str) -> Result<()> {
+    let (env, id) = (env.clone(), id.to_string());
+    crate::api::unblock(move || crate::changes::resolve(&env, &id)).await
+}
+
+/// Transfer a file to Drive once the limiter allows it, and report the outcome back to the limiter.
+/// Only the transfer holds a permit, so comparing and hashing files doesn't count against the number of concurrent uploads
+async fn transfer<T, F: Future<Output = Result<T>>>(state: &SyncState<'_>, bytes: u64, upload: F) -> Result<T> {
+    let permit = state.limiter.acquire().await;
+    let start = Instant::now();
+    let result = upload.await;
+
+    permit.finish(match &result {
+        Ok(_) => Outcome::Success { bytes, elapsed: start.elapsed() },
+        Err(e) if concurrency::is_throttled(&e.0) => Outcome::Throttled,
+        Err(_) => Outcome::Failed
+    });
+
+    result
+}
+
+/// Reserve room in the budget of the run for uploading a file. If it doesn't fit, the file is deferred to the next run
+fn reserve(state: &SyncState<'_>, path: &Path, size: u64) -> bool {
+    if state.budget.reserve(size) {
+        return true;
+    }
+
+    if state.budget.exceeds(size) {
+        println!("Warning: '{}' is larger than the transfer budget of the run, it can't be uploaded with this budget", path.to_string_lossy());
+    }
+
+    state.report.add_deferred(path);
+    false
+}
+
+/// Sync a hard link of a file which was already synced during this run.
+/// Instead of uploading the content again, the link is represented by a shortcut to the already synced file
+///
+/// ## Params
+/// - `env` Env instance
+/// - `path` The path of the hard link
+/// - `parent_id` The ID of the parent folder in Google Drive
+/// - `existing` The files in Google Drive with the same name as the hard link
+/// - `target_path` The local path of the already synced file
+/// - `target_id` The ID of the already synced file in Google Drive
+///
+/// # Errors
+/// - When the Google API returns an error
+/// - When a database operation fails
+async fn sync_hard_link(env: &Env, path: &Path, parent_id: &str, existing: &[drive::File], target_path: &Path, target_id: &str) -> Result<()> {
+    let file_name = path.file_name().unwrap().to_string_lossy();
+
+    let id = match existing.first() {
+        Some(file) if file.app_properties.get(metadata::HARD_LINK_TARGET).map(|id| id.eq(target_id)).unwrap_or(false) => {
+            println!("Info: Hard link '{}' is up-to-date.", file_name);
+            file.id.clone()
+        },
+        Some(file) if file.is_shortcut() => {
+            println!("Warning: '{}' exists in Drive as a shortcut, it will not be overwritten. Skipping.", file_name);
+            return Ok(());
+        },
+        Some(_) => {
+            println!("Info: '{}' is a hard link of '{}', but was uploaded as a regular file before. Leaving it as-is.", file_name, target_path.to_string_lossy());
+            return Ok(());
+        },
+        None => {
+            println!("Info: Creating hard link '{}' to '{}'", file_name, target_path.to_string_lossy());
+            let mut app_properties = HashMap::new();
+            app_properties.insert(metadata::HARD_LINK_TARGET.to_string(), target_id.to_string());
+            drive::create_shortcut(env, &file_name, parent_id, target_id, &app_properties).await?
+        }
+    };
+
+    insert_file(env, TrackedFile {
+        path:       path.to_string_lossy().to_string(),
+        id,
+        parent_id:  parent_id.to_string(),
+        is_folder:  false,
+        link_target: Some(target_path.to_string_lossy().to_string()),
+        size:       None,
+        md5:        None,
+        synced_at:  Some(chrono::Utc::now().timestamp())
+    }).await
+}
+
+/// Sync the symbolic links found during the sync as shortcuts to the synced copies of their targets.
+/// A link whose target is not synced, e.g. because it is outside of the inputs, is skipped
+///
+/// # Errors
+/// - When the Google API returns an error
+/// - When a database operation fails
+fn sync_symlinks(state: &SyncState<'_>) -> Result<()> {
+    let pending = std::mem::take(&mut *state.symlinks.lock().unwrap_or_else(PoisonError::into_inner));
+    for PendingSymlink { link, env, parent_id, existing } in pending {
+        if state.budget.is_exhausted() {
+            state.report.add_deferred(&link.path);
+            continue;
+        }
+
+        let target = fs::canonicalize(links::target_path(&link.path, &link.target)).ok()
+            .map(|target| target.to_string_lossy().to_string());
+        let tracked = match &target {
+            Some(target) => files::get_by_path(&env, target)?,
+            None => None
+        };
+
+        match (target, tracked) {
+            (Some(target), Some(tracked)) => sync_symlink(&env, &link, &parent_id, &existing, &target, &tracked.id)?,
+            _ => {
+                println!("Warning: Skipping symbolic link '{}', its target '{}' is not synced", link.path.to_string_lossy(), link.target.to_string_lossy());
+                state.report.add_skipped(&link.path, "its target is not synced");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Sync a symbolic link as a shortcut to the synced copy of its target. The target as read from the link is stored with the shortcut, so the link can be restored
+///
+/// ## Params
+/// - `env` Env instance
+/// - `link` The symbolic link
+/// - `parent_id` The ID of the parent folder in Google Drive
+/// - `existing` The files in Google Drive with the same name as the link
+/// - `target_path` The local path of the target of the link
+/// - `target_id` The ID of the target in Google Drive
+///
+/// # Errors
+/// - When the Google API returns an error
+/// - When a database operation fails
+fn sync_symlink(env: &Env, link: &Symlink, parent_id: &str, existing: &[drive::File], target_path: &str, target_id: &str) -> Result<()> {
+    let link_target = link.target.to_string_lossy().to_string();
+    let mut app_properties = HashMap::new();
+    app_properties.insert(metadata::SYMLINK_TARGET.to_string(), link_target.clone());
+
+    let id = match existing.first() {
+        Some(file) if file.app_properties.get(metadata::SYMLINK_TARGET).map(|target| target.eq(&link_target)).unwrap_or(false) => {
+            println!("Info: Symbolic link '{}' is up-to-date.", &link.name);
+            file.id.clone()
+        },
+        Some(file) if file.app_properties.contains_key(metadata::SYMLINK_TARGET) => {
+            println!("Info: Symbolic link '{}' now points to '{}', replacing its shortcut", &link.name, &link_target);
+            drive::blocking::set_trashed(env, &file.id, true)?;
+            drive::blocking::create_shortcut(env, &link.name, parent_id, target_id, &app_properties)?
+        },
+        Some(file) if file.is_shortcut() => {
+            println!("Warning: '{}' exists in Drive as a shortcut, it will not be overwritten. Skipping.", &link.name);
+            return Ok(());
+        },
+        Some(_) => {
+            println!("Info: '{}' is a symbolic link, but was uploaded as a regular file before. Leaving it as-is.", link.path.to_string_lossy());
+            return Ok(());
+        },
+        None => {
+            println!("Info: Creating symbolic link '{}' to '{}'", &link.name, &link_target);
+            drive::blocking::create_shortcut(env, &link.name, parent_id, target_id, &app_properties)?
+        }
+    };
+
+    files::insert(env, &TrackedFile {
+        path:       link.path.to_string_lossy().to_string(),
+        id,
+        parent_id:  parent_id.to_string(),
+        is_folder:  false,
+        link_target: Some(target_path.to_string()),
+        size:       None,
+        md5:        None,
+        synced_at:  Some(chrono::Utc::now().timestamp())
+    })?;
+
+    Ok(())
+}
+
+cfg_if! {
+    if #[cfg(unix)] {
+        /// Get the (device, inode) of a file if it has more than one hard link
+        fn hard_link_key(path: &Path) -> Option<(u64, u64)> {
+            use std::os::unix::fs::MetadataExt;
+
+            let meta = path.metadata().ok()?;
+            if meta.nlink() > 1 {
+                Some((meta.dev(), meta.ino()))
+            } else {
+                None
+            }
+        }
+    } else {
+        /// Get the (device, inode) of a file if it has more than one hard link. Hard links are only detected on unix
+        fn hard_link_key(_path: &Path) -> Option<(u64, u64)> {
+            None
+        }
+    }
+}
+
+/// Whether two paths are hard links of the same file. False once either was removed, the other has no other links then
+fn is_linked_to(path: &Path, other: &Path) -> bool {
+    match (hard_link_key(path), hard_link_key(other)) {
+        (Some(key), Some(other_key)) => key == other_key,
+        _ => false
+    }
+}
+
+/// Get the modification time of a file
+///
+/// # Errors
+/// - When the underlying IO operation to fetch the modification time fails
+fn get_modification_time(path: &Path) -> Result<u64> {
+    let meta = unwrap_other_err!(path.metadata());
+    let meta_modified = unwrap_other_err!(meta.modified());
+    let as_epoch = unwrap_other_err!(meta_modified.duration_since(SystemTime::UNIX_EPOCH)).as_secs();
+
+    Ok(as_epoch)
+}
+
+/// Get the modification time of a symbolic link itself, rather than of its target, as epoch seconds
+fn get_link_modification_time(path: &Path) -> Option<i64> {
+    let modified = path.symlink_metadata().and_then(|meta| meta.modified()).ok()?;
+    modified.duration_since(SystemTime::UNIX_EPOCH).ok().map(|time| time.as_secs() as i64)
+}
+
+/// Struct describing a Directory
+#[derive(Debug)]
+pub struct Directory {
+    /// The name of the directory
+    name:       String,
+
+    /// The path to the directory
+    path:       PathBuf,
+
+    /// A vector of Child's that this directory is the parent of
+    children:   Vec<Child>
+}
+
+/// Struct describing a symbolic link which is synced as a shortcut
+#[derive(Debug)]
+pub struct Symlink {
+    /// The name of the link
+    name:       String,
+
+    /// The path to the link
+    path:       PathBuf,
+
+    /// The target of the link, as read from the link
+    target:     PathBuf
+}
+
+/// Enum describing a Child
+#[derive(Debug)]
+pub enum Child {
+    /// Directory
+    Directory(Directory),
+
+    /// File
+    File(PathBuf),
+
+    /// Symbolic link, synced as a shortcut
+    Symlink(Symlink)
+}
+
+impl Child {
+    /// Cound all Child elements to this Child
+    fn count_all_children(&self) -> i64 {
+        match self {
+            Self::File(_) | Self::Symlink(_) => 1,
+            Self::Directory(d) => {
+                let mut count = 0i64;
+                for child in d.children.iter() {
+                    count += child.count_all_children();
+                }
+
+                count
+            }
+        }
+    }
+
+    /// Count this Child and everything in it, files as well as folders
+    fn count_items(&self) -> i64 {
+        match self {
+            Self::File(_) | Self::Symlink(_) => 1,
+            Self::Directory(d) => 1 + d.children.iter().map(Self::count_items).sum::<i64>()
+        }
+    }
+
+    /// The deepest nesting of folders in this Child, including this Child itself if it is a folder
+    fn depth(&self) -> usize {
+        match self {
+            Self::File(_) | Self::Symlink(_) => 0,
+            Self::Directory(d) => 1 + d.children.iter().map(Self::depth).max().unwrap_or(0)
+        }
+    }
+
+    /// Collect this Child and everything in it as LocalEntry's, for planning the sync
+    ///
+    /// ## Params
+    /// - `compare` How to decide whether the files changed, the policy of the input
+    pub fn local_entries(&self, entries: &mut Vec<LocalEntry>, compare: ComparePolicy) {
+        match self {
+            Self::File(path) => {
+                let meta = path.metadata().ok();
+                entries.push(LocalEntry {
+                    path:       path.clone(),
+                    is_folder:  false,
+                    size:       meta.as_ref().map(|meta| meta.len()).unwrap_or(0),
+                    modified:   get_modification_time(path).ok().map(|time| time as i64),
+                    compare
+                });
+            },
+            Self::Symlink(link) => {
+                entries.push(LocalEntry {
+                    path:       link.path.clone(),
+                    is_folder:  false,
+                    size:       0,
+                    modified:   get_link_modification_time(&link.path),
+                    compare
+                });
+            },
+            Self::Directory(d) => {
+                entries.push(LocalEntry {
+                    path:       d.path.clone(),
+                    is_folder:  true,
+                    size:       0,
+                    modified:   None,
+                    compare
+                });
+
+                for child in d.children.iter() {
+                    child.local_entries(entries, compare);
+                }
+            }
+        }
+    }
+
+    /// Get the total size in bytes of all files in this Child which the plan uploads, as new files or as new content of changed files
+    fn upload_size(&self, upload_sizes: &HashMap<&str, u64>) -> u64 {
+        match self {
+            Self::File(path) => upload_sizes.get(path.to_string_lossy().as_ref()).copied().unwrap_or(0),
+            Self::Symlink(_) => 0,
+            Self::Directory(d) => d.children.iter().map(|child| child.upload_size(upload_sizes)).sum()
+        }
+    }
+}
+
+/// Struct describing the state of the traversal of a single input
+#[derive(Debug)]
+struct Traversal<'a> {
+    /// The input being traversed
+    input:      &'a Path,
+
+    /// Where the input is read from instead, if it is synced from a filesystem snapshot. Paths are always those of the input itself
+    shadow:     Option<&'a Path>,
+
+    /// Decides which files of the input are synced
+    filter:     &'a InputFilter,
+
+    /// The overrides from the `.gsync.toml` files of the folders being traversed, with the folder they apply to. Innermost last
+    overrides:  Vec<(PathBuf, DirectoryOverrides)>,
+
+    /// Paths which are not synced, nor is anything inside them, from GSync's own state and nested inputs
+    exclusions: Vec<PathBuf>,
+
+    /// The .gitignore and .gsyncignore files of the folders being traversed, innermost last
+    gitignores: Vec<Gitignore>,
+
+    /// Whether an anomaly aborts the traversal, instead of skipping the path
+    strict:     bool,
+
+    /// How symbolic links are synced
+    symlinks:   SymlinkPolicy,
+
+    /// Whether every traversed path is printed
+    log:        bool,
+
+    /// The folders being traversed, outermost first, by their identity on disk. A link to one of them would make the traversal loop forever
+    ancestors:  Vec<FolderId>,
+
+    /// The paths skipped because of an anomaly
+    skipped:    Vec<Skipped>,
+
+    /// The files skipped because they are larger than the maximum size
+    oversized:  Vec<Oversized>,
+
+    /// The files skipped because they are not regular files, e.g. sockets and broken links, with the reason why
+    special:    Vec<Skipped>
+}
+
+impl<'a> Traversal<'a> {
+    /// A traversal of an input which is not synced from a snapshot, follows links, skips anomalies and excludes nothing but what the filter does
+    #[cfg(test)]
+    fn for_test(input: &'a Path, filter: &'a InputFilter) -> Self {
+        Self {
+            input,
+            shadow:     None,
+            filter,
+            overrides:  Vec::new(),
+            exclusions: Vec::new(),
+            gitignores: Vec::new(),
+            strict:     false,
+            symlinks:   SymlinkPolicy::Follow,
+            log:        false,
+            ancestors:  Vec::new(),
+            skipped:    Vec::new(),
+            oversized:  Vec::new(),
+            special:    Vec::new()
+        }
+    }
+
+    /// Where a path in the input is read from
+    fn source(&self, path: &Path) -> PathBuf {
+        match self.shadow {
+            Some(shadow) => rebase(path, self.input, shadow),
+            None => path.to_path_buf()
+        }
+    }
+
+    /// Apply the `.gsync.toml` file of a folder, if it has one, to everything in the folder. Returns whether overrides were applied,
+    /// or None if the file can't be read. The folder is skipped then, rather than synced with the wrong policies
+    ///
+    /// # Errors
+    /// - When the file can't be read and the traversal is strict
+    fn enter(&mut self, dir: &Path) -> Result<Option<bool>> {
+        let source = self.source(dir);
+        if !source.join(overrides::FILE_NAME).is_file() {
+            return Ok(Some(false));
+        }
+
+        match DirectoryOverrides::read(&source) {
+            Ok(found) => {
+                for key in found.unsupported.iter() {
+                    println!("Warning: '{}' in '{}' is not supported, ignoring it", key, dir.join(overrides::FILE_NAME).to_string_lossy());
+                }
+
+                self.overrides.push((dir.to_path_buf(), found));
+                Ok(Some(true))
+            },
+            Err(e) => {
+                self.anomaly(dir, format!("its {} can't be read: {}", overrides::FILE_NAME, e))?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Whether a file or folder is excluded, by the input or by the overrides of a folder it is in
+    fn is_excluded(&self, path: &Path) -> bool {
+        self.filter.is_excluded(self.input, path)
+            || self.overrides.iter().any(|(dir, overrides)| filter::is_excluded_by(&overrides.excludes, dir, path))
+    }
+
+    /// Whether a file or folder is ignored by the .gitignore and .gsyncignore files of the folders it is in. Like git, the innermost folder which matches
+    /// the path decides, and within a file the last matching pattern, so a negated pattern can re-include what an earlier pattern ignores
+    fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        for gitignore in self.gitignores.iter().rev() {
+            match gitignore.matched(path, is_dir) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => {}
+            }
+        }
+
+        false
+    }
+
+    /// Whether .gitignore files are respected in the folder being traversed
+    fn gitignore(&self) -> bool {
+        self.overrides.iter().rev()
+            .find_map(|(_, overrides)| overrides.gitignore)
+            .unwrap_or(self.filter.gitignore)
+    }
+
+    /// Check whether a file is too large to be synced, the innermost folder overriding the maximum size decides.
+    /// If it is too large, it is recorded so the plan and the report can list it
+    fn skip_oversized(&mut self, path: &Path, size: u64) -> bool {
+        let max_size = match self.overrides.iter().rev().find_map(|(_, overrides)| overrides.max_size) {
+            Some(max_size) if max_size > 0 && size > max_size => max_size,
+            Some(_) => return false,
+            None if self.filter.is_too_large(size) => self.filter.max_size.unwrap_or_default(),
+            None => return false
+        };
 
-        let mut child_count = 0i64;
-        for child in ichildren.iter() {
-            child_count += child.count_all_children();
+        if self.log {
+            println!("Info: Skipping '{}', it is larger than the maximum size of {}", path.to_string_lossy(), crate::size::format_size(max_size));
         }
-        println!("Info: Found {} child nodes for input '{}'.", child_count, name);
 
-        children.append(&mut ichildren);
+        self.oversized.push(Oversized {
+            path: path.to_string_lossy().to_string(),
+            size,
+            max_size
+        });
+
+        true
     }
 
-    println!("Info: All directories traversed. Beginning sync now.");
+    /// Check whether a file is a special file, e.g. a socket, a FIFO or a device, which can't be synced. If it is, it is recorded so the report can list it
+    fn skip_special(&mut self, path: &Path, meta: &fs::Metadata) -> bool {
+        let kind = match special_kind(&meta.file_type()) {
+            Some(kind) => kind,
+            None => return false
+        };
 
-    for child in children {
-        sync_child(child, env, None)?;
+        if self.log {
+            println!("Info: Skipping '{}', it is {}", path.to_string_lossy(), kind);
+        }
+
+        self.special.push((path.to_path_buf(), format!("it is {}", kind)));
+        true
     }
 
-    Ok(())
+    /// Record a symbolic link whose target doesn't exist, so the report can list it
+    fn skip_broken_link(&mut self, path: &Path) {
+        if self.log {
+            println!("Info: Skipping '{}', it is a broken symbolic link", path.to_string_lossy());
+        }
+
+        self.special.push((path.to_path_buf(), "it is a broken symbolic link".to_string()));
+    }
+
+    /// Handle an anomaly, e.g. a folder which can't be read. When strict, the traversal is aborted, otherwise the path is skipped and reported
+    ///
+    /// # Errors
+    /// - When the traversal is strict
+    fn anomaly(&mut self, path: &Path, reason: String) -> Result<()> {
+        if self.strict {
+            return Err((crate::Error::Other(format!("'{}': {}. Aborting because of --strict", path.to_string_lossy(), reason)), line!(), file!()));
+        }
+
+        if self.log {
+            println!("Warning: Skipping '{}': {}", path.to_string_lossy(), reason);
+        }
+
+        self.skipped.push((path.to_path_buf(), reason));
+        Ok(())
+    }
 }
 
-/// Delete a file from Google Drive if it no longer exists locally
-fn delete_if_removed(path: &Path, parent_id: &str, env: &Env) -> Result<()> {
-    if !path.exists() {
-        let name = path.file_name().unwrap().to_str().unwrap();
-        let file_list  = drive::list_files(env, Some(&format!("name = '{}' and '{}' in parents", name, parent_id)), env.drive_id.as_deref())?;
-        for file in file_list {
-            drive::delete_file(env, &file.id)?;
+/// Traverse a path to map them to a Vec of Child, skipping what the filter of the input excludes. If `log` is set, every traversed path is printed
+///
+/// # Errors
+/// - When there is an anomaly, e.g. a folder which can't be read, and the traversal is strict
+fn traverse(p: PathBuf, traversal: &mut Traversal<'_>) -> Result<Vec<Child>> {
+    let mut top_children = Vec::new();
+
+    if traversal.log {
+        println!("Info: Traversing '{}'", p.to_string_lossy());
+    }
+
+    // Paths are stored in the database and compared as UTF-8, a name which isn't valid UTF-8 is synced with its invalid bytes replaced
+    let name = match p.file_name() {
+        Some(name) => utf8_name(&p, name, traversal.log),
+        None => {
+            traversal.anomaly(&p, "it has no name".to_string())?;
+            return Ok(top_children);
+        }
+    };
+
+    let source = traversal.source(&p);
+    if traversal.symlinks != SymlinkPolicy::Follow && is_symlink(&source) {
+        if traversal.symlinks == SymlinkPolicy::Shortcut {
+            top_children.extend(read_symlink(p, name, traversal)?);
+        } else if traversal.log {
+            println!("Info: Skipping '{}', it is a symbolic link", p.to_string_lossy());
         }
+
+        return Ok(top_children);
     }
 
-    Ok(())
-}
+    if source.is_dir() {
+        if name.eq(".git") {
+           return Ok(vec![]);
+        }
 
-/// Sync a child with Google Drive. This is a recursive function
-fn sync_child(child: Child, env: &Env, parent_folder_id: Option<&str>) -> Result<()> {
-    match child {
-        Child::Directory(dir) => {
+        // A followed link to a folder the traversal is already in would make it loop forever
+        let id = folder_id(&source);
+        if let Some(id) = &id {
+            if traversal.ancestors.contains(id) {
+                println!("Warning: Skipping '{}', it links to a folder it is in", p.to_string_lossy());
+                return Ok(top_children);
+            }
+        }
 
-            println!("Info: Querying Drive for directory '{}'", &dir.name);
-            let query_result = match parent_folder_id {
-                Some(parent_folder_id) => drive::list_files(env, Some(&format!("name = '{}' and mimeType = 'application/vnd.google-apps.folder' and trashed = false and '{}' in parents", &dir.name, parent_folder_id)), env.drive_id.as_deref())?,
-                None => drive::list_files(env, Some(&format!("name = '{}' and mimeType = 'application/vnd.google-apps.folder' and trashed = false and '{}' in parents", &dir.name, &env.root_folder)), env.drive_id.as_deref())?
-            };
+        let scoped = match traversal.enter(&p)? {
+            Some(scoped) => scoped,
+            None => return Ok(top_children)
+        };
 
-            let folder_id = {
-                let mut id = String::new();
-                for file in query_result {
-                    id = file.id;
+        let ignores = read_ignore_files(&p, traversal)?;
+        traversal.ancestors.extend(id.iter().cloned());
+
+        let entries = match fs::read_dir(&source) {
+            Ok(entries) => entries,
+            Err(e) => {
+                if scoped {
+                    traversal.overrides.pop();
                 }
 
-                if id.is_empty() {
-                    println!("Info: Creating directory '{}'", &dir.name);
-                    id = match parent_folder_id {
-                        Some(pfi) => drive::create_folder(env, &dir.name, pfi)?,
-                        None => drive::create_folder(env, &dir.name, &env.root_folder)?
-                    }
+                if ignores {
+                    traversal.gitignores.pop();
                 }
 
-                id
-            };
+                if id.is_some() {
+                    traversal.ancestors.pop();
+                }
 
-            match parent_folder_id {
-                Some(pfi) => delete_if_removed(&dir.path, pfi, env)?,
-                None => delete_if_removed(&dir.path, &env.root_folder, env)?
+                traversal.anomaly(&p, format!("it can't be read: {}", e))?;
+                return Ok(top_children);
             }
+        };
 
-            for child in dir.children {
-                sync_child(child, env, Some(&folder_id))?
+        let mut readable = Vec::new();
+        for entry in entries {
+            match entry {
+                Ok(entry) => readable.push(entry),
+                // The entry's path is unknown, so the folder is reported, which also keeps the entry in Drive
+                Err(e) => traversal.anomaly(&p, format!("an entry can't be read: {}", e))?
             }
-        },
-        Child::File(file_path) => {
-            let file_name = file_path.file_name().unwrap().to_str().unwrap();
-            println!("Info: Querying Drive for file '{}'", file_name);
+        }
 
-            let query_result = match parent_folder_id {
-                Some(parent_folder_id) => drive::list_files(env, Some(&format!("name = '{}' and trashed = false and '{}' in parents", file_name, parent_folder_id)), env.drive_id.as_deref())?,
-                None => drive::list_files(env, Some(&format!("name = '{}' and trashed = false and '{}' in parents", file_name, &env.root_folder)), env.drive_id.as_deref())?
-            };
+        let names = readable.iter().map(|entry| entry.file_name()).collect::<Vec<_>>();
+        let mut children = Vec::new();
+        for entry in readable {
+            // Built from the traversed path rather than the entry, which is in the filesystem snapshot if there is one
+            let path = p.join(entry.file_name());
+            if clashes(&entry.file_name(), &names) {
+                traversal.anomaly(&path, "its name is not valid UTF-8, and is the same as another name in its folder once its invalid bytes are replaced".to_string())?;
+                continue;
+            }
 
-            match query_result.get(0) {
-                Some(file) => {
-                    let mod_time_rfc_3339 = &file.modified_time;
-                    let mod_time_epoch = unwrap_other_err!(chrono::DateTime::parse_from_rfc3339(mod_time_rfc_3339)).timestamp();
-
-                    if file_changed(&file_path, mod_time_epoch)? {
-                        println!("Info: Updating file '{}'", file_name);
-                        drive::update_file(env, &file_path, &file.id)?;
-                    } else {
-                        println!("Info: File '{}' is up-to-date.", file_name);
-                    }
-                }
-                None => {
-                    println!("Info: Uploading file '{}'", file_name);
-                    match parent_folder_id {
-                        Some(pfi) => drive::upload_file(env, &file_path, pfi)?,
-                        None => drive::upload_file(env, &file_path, &env.root_folder)?
-                    };
-                }
+            if is_within(&path, &traversal.exclusions) { continue }
+            if traversal.is_excluded(&path) { continue }
+            if traversal.is_ignored(&path, entry.file_type().map(|kind| kind.is_dir()).unwrap_or(false)) { continue }
+
+            let mut ichild = traverse(path, traversal)?;
+            children.append(&mut ichild);
+        }
+
+        if scoped {
+            traversal.overrides.pop();
+        }
+
+        if ignores {
+            traversal.gitignores.pop();
+        }
+
+        if id.is_some() {
+            traversal.ancestors.pop();
+        }
+
+        top_children.push(Child::Directory(Directory { path: p.clone(), name, children }))
+    } else {
+        if traversal.filter.is_excluded_ext(&p) {
+            return Ok(top_children);
+        }
+
+        let size = match source.metadata() {
+            Ok(meta) if traversal.skip_special(&p, &meta) => return Ok(top_children),
+            Ok(meta) => meta.len(),
+            Err(_) if is_symlink(&source) => {
+                traversal.skip_broken_link(&p);
+                return Ok(top_children);
+            },
+            Err(e) => {
+                traversal.anomaly(&p, format!("its metadata can't be read: {}", e))?;
+                return Ok(top_children);
             }
+        };
+
+        if traversal.skip_oversized(&p, size) {
+            return Ok(top_children);
         }
+
+        top_children.push(Child::File(p));
     }
 
-    Ok(())
+    Ok(top_children)
 }
 
-/// Get the modification time of a file
+/// Collect exactly the listed files and folders within a folder as Child's, together with the folders leading up to them.
+/// A listed folder is synced without its contents, unless those are listed as well
 ///
 /// # Errors
-/// - When the underlying IO operation to fetch the modification time fails
-fn get_modification_time(path: &Path) -> Result<u64> {
-    let meta = unwrap_other_err!(path.metadata());
-    let meta_modified = unwrap_other_err!(meta.modified());
-    let as_epoch = unwrap_other_err!(meta_modified.duration_since(SystemTime::UNIX_EPOCH)).as_secs();
+/// - When a listed path can't be read and the traversal is strict
+fn select(dir: &Path, listed: &[PathBuf], traversal: &mut Traversal<'_>) -> Result<Vec<Child>> {
+    let mut names = listed.iter()
+        .filter_map(|path| path.strip_prefix(dir).ok()?.components().next())
+        .map(|component| component.as_os_str().to_os_string())
+        .collect::<Vec<_>>();
+    names.sort();
+    names.dedup();
 
-    Ok(as_epoch)
+    let mut children = Vec::new();
+    for name in names {
+        let path = dir.join(&name);
+        if is_within(&path, &traversal.exclusions) { continue }
+        if traversal.is_excluded(&path) { continue }
+
+        // Paths are stored in the database and compared as UTF-8
+        let name = utf8_name(&path, &name, traversal.log);
+
+        if traversal.symlinks != SymlinkPolicy::Follow && is_symlink(&traversal.source(&path)) {
+            if traversal.symlinks == SymlinkPolicy::Shortcut {
+                children.extend(read_symlink(path, name, traversal)?);
+            }
+
+            continue;
+        }
+
+        match traversal.source(&path).metadata() {
+            Ok(meta) if meta.is_dir() => {
+                let scoped = match traversal.enter(&path)? {
+                    Some(scoped) => scoped,
+                    None => continue
+                };
+
+                let below = listed.iter().filter(|listed| listed.starts_with(&path) && **listed != path).cloned().collect::<Vec<_>>();
+                let dir_children = select(&path, &below, traversal)?;
+                if scoped {
+                    traversal.overrides.pop();
+                }
+
+                children.push(Child::Directory(Directory { path, name, children: dir_children }));
+            },
+            Ok(_) if traversal.filter.is_excluded_ext(&path) => {},
+            Ok(meta) if traversal.skip_special(&path, &meta) => {},
+            Ok(meta) if traversal.skip_oversized(&path, meta.len()) => {},
+            Ok(_) => children.push(Child::File(path)),
+            Err(_) if is_symlink(&traversal.source(&path)) => traversal.skip_broken_link(&path),
+            Err(e) => traversal.anomaly(&path, format!("it can't be read: {}", e))?
+        }
+    }
+
+    Ok(children)
 }
 
-/// Check if a file has changed by their modification time
-///
-/// # Errors
-/// - When the underlying IO operation to fetch the modification time fails
-fn file_changed(path: &Path, stored_modification_time: i64) -> Result<bool> {
-    let modification_time = get_modification_time(path)?;
-    if modification_time > (stored_modification_time as u64) {
-        Ok(true)
-    } else {
-        Ok(false)
+/// The name of a file or folder as UTF-8. Bytes of a name which aren't valid UTF-8 are replaced with `U+FFFD`, which is how the name is stored and shown in Drive
+fn utf8_name(path: &Path, name: &OsStr, log: bool) -> String {
+    match name.to_str() {
+        Some(name) => name.to_string(),
+        None => {
+            let name = name.to_string_lossy().to_string();
+            if log {
+                println!("Warning: The name of '{}' is not valid UTF-8, it is synced as '{}'", path.to_string_lossy(), name);
+            }
+
+            name
+        }
     }
 }
 
-/// Struct describing a Directory
-#[derive(Debug)]
-pub struct Directory {
-    /// The name of the directory
-    name:       String,
+/// Whether a name which isn't valid UTF-8 becomes the same as another name in its folder once its invalid bytes are replaced.
+/// Both would be synced to the same path, so such a name is skipped
+fn clashes(name: &OsStr, names: &[OsString]) -> bool {
+    if name.to_str().is_some() {
+        return false;
+    }
 
-    /// The path to the directory
-    path:       PathBuf,
+    let lossy = name.to_string_lossy();
+    names.iter().any(|other| other.as_os_str() != name && other.to_string_lossy() == lossy)
+}
 
-    /// A vector of Child's that this directory is the parent of
-    children:   Vec<Child>
+/// Check whether a path is a symbolic link, without following it
+fn is_symlink(path: &Path) -> bool {
+    path.symlink_metadata().map(|meta| meta.file_type().is_symlink()).unwrap_or(false)
 }
 
-/// Enum describing a Child
-#[derive(Debug)]
-pub enum Child {
-    /// Directory
-    Directory(Directory),
+/// What kind of special file a file is, e.g. `a socket`. None for regular files and folders
+#[cfg(unix)]
+fn special_kind(file_type: &fs::FileType) -> Option<&'static str> {
+    use std::os::unix::fs::FileTypeExt;
 
-    /// File
-    File(PathBuf)
+    if file_type.is_file() || file_type.is_dir() {
+        None
+    } else if file_type.is_socket() {
+        Some("a socket")
+    } else if file_type.is_fifo() {
+        Some("a named pipe")
+    } else if file_type.is_block_device() || file_type.is_char_device() {
+        Some("a device")
+    } else {
+        Some("not a regular file")
+    }
 }
 
-impl Child {
-    /// Cound all Child elements to this Child
-    fn count_all_children(&self) -> i64 {
-        match self {
-            Self::File(_) => 1,
-            Self::Directory(d) => {
-                let mut count = 0i64;
-                for child in d.children.iter() {
-                    count += child.count_all_children();
-                }
+/// What kind of special file a file is. None for regular files and folders
+#[cfg(not(unix))]
+fn special_kind(file_type: &fs::FileType) -> Option<&'static str> {
+    if file_type.is_file() || file_type.is_dir() {
+        None
+    } else {
+        Some("not a regular file")
+    }
+}
 
-                count
-            }
+/// Identifies a folder on disk, whichever path it is reached through. On Unix, its device and inode
+#[cfg(unix)]
+type FolderId = (u64, u64);
+
+/// Identifies a folder on disk, whichever path it is reached through. Elsewhere, its canonical path
+#[cfg(not(unix))]
+type FolderId = PathBuf;
+
+/// Get the identity of a folder on disk, following links. None if it can't be read
+#[cfg(unix)]
+fn folder_id(path: &Path) -> Option<FolderId> {
+    use std::os::unix::fs::MetadataExt;
+
+    let meta = fs::metadata(path).ok()?;
+    Some((meta.dev(), meta.ino()))
+}
+
+/// Get the identity of a folder on disk, following links. None if it can't be read
+#[cfg(not(unix))]
+fn folder_id(path: &Path) -> Option<FolderId> {
+    fs::canonicalize(path).ok()
+}
+
+/// Read a symbolic link, to sync it as a shortcut. Returns None if the link can't be read
+///
+/// # Errors
+/// - When the link can't be read and the traversal is strict
+fn read_symlink(path: PathBuf, name: String, traversal: &mut Traversal<'_>) -> Result<Option<Child>> {
+    match fs::read_link(traversal.source(&path)) {
+        Ok(target) => Ok(Some(Child::Symlink(Symlink { name, path, target }))),
+        Err(e) => {
+            traversal.anomaly(&path, format!("it can't be read: {}", e))?;
+            Ok(None)
         }
     }
 }
 
-/// Traverse a path to map them to a Vec of Child
-pub fn traverse(p: PathBuf, exclusions: &mut Vec<PathBuf>) -> Result<Vec<Child>> {
-    let mut top_children = Vec::new();
+/// Read a list of paths, one per line, from a file or from stdin if the file is `-`. Empty lines are ignored
+///
+/// # Errors
+/// - When the file or stdin can't be read
+pub fn read_list(file: &Path) -> Result<Vec<String>> {
+    use std::io::Read;
 
-    println!("Info: Traversing '{}'", p.to_str().unwrap());
+    let mut contents = String::new();
+    if file == Path::new("-") {
+        unwrap_other_err!(std::io::stdin().read_to_string(&mut contents));
+    } else {
+        contents = unwrap_other_err!(fs::read_to_string(file));
+    }
 
-    if p.is_dir() {
-        if p.file_name().unwrap().eq(".git") {
-           return Ok(vec![]);
-        }
+    Ok(contents.lines()
+        .map(|line| line.trim_end_matches('\r'))
+        .filter(|line| !line.is_empty())
+        .map(String::from)
+        .collect())
+}
 
-        let mut potential_gitignore = PathBuf::from(&p);
-        potential_gitignore.push(".gitignore");
-        if potential_gitignore.exists() {
-            exclusions.append(&mut parse_gitignore(&potential_gitignore));
-        }
+/// Make a listed path absolute, relative to the current directory. Only the parent is resolved, so a listed symlink is not replaced by its target
+pub fn absolute_path<P: AsRef<Path>>(path: P) -> PathBuf {
+    let path = match std::env::current_dir() {
+        Ok(current) => current.join(path),
+        Err(_) => path.as_ref().to_path_buf()
+    };
 
-        let mut children = Vec::new();
-        for entry in unwrap_other_err!(fs::read_dir(&p)) {
-            let entry = unwrap_other_err!(entry);
+    match (path.parent().map(fs::canonicalize), path.file_name()) {
+        (Some(Ok(parent)), Some(name)) => parent.join(name),
+        _ => extended_path(&path)
+    }
+}
+
+/// Read the .gsyncignore file of a folder and, if .gitignore files are respected, its .gitignore file and the `.git/info/exclude` file
+/// if the folder is the root of a git repository. Their patterns apply to everything in the folder until the traversal leaves it.
+/// Returns whether any patterns were read
+///
+/// # Errors
+/// - When a file can't be read and the traversal is strict
+fn read_ignore_files(dir: &Path, traversal: &mut Traversal<'_>) -> Result<bool> {
+    // Later patterns take precedence. The patterns of .gitignore take precedence over those of .git/info/exclude like they do in git,
+    // and those of .gsyncignore over both, so it can re-include what git ignores with a negated pattern
+    let mut files = Vec::new();
+    if traversal.gitignore() {
+        files.push(dir.join(".git/info/exclude"));
+        files.push(dir.join(".gitignore"));
+    }
 
-            if exclusions.contains(&entry.path()) { continue }
+    files.push(dir.join(IGNORE_FILE_NAME));
 
-            let mut ichild = traverse(entry.path(), exclusions)?;
-            children.append(&mut ichild);
-        }
+    let mut contents = String::new();
+    for file in files.iter() {
+        let source = traversal.source(file);
+        if !source.is_file() { continue }
 
-        top_children.push(Child::Directory(Directory { path: p.clone(), name: p.file_name().unwrap().to_str().unwrap().to_string(), children }))
-    } else {
-        let file_name = p.file_name().unwrap().to_str().unwrap();
-        if file_name.eq(".gitignore") {
-            exclusions.append(&mut parse_gitignore(&p))
+        match fs::read_to_string(&source) {
+            Ok(read) => {
+                contents.push_str(&read);
+                contents.push('\n');
+            },
+            Err(e) => traversal.anomaly(file, format!("it can't be read: {}", e))?
         }
+    }
 
-        top_children.push(Child::File(p));
+    if contents.is_empty() {
+        return Ok(false);
     }
 
-    Ok(top_children)
+    traversal.gitignores.push(parse_gitignore(dir, &contents));
+    Ok(true)
 }
 
-/// Parse a gitignore file, returns a Vec<PathBuf> to be ignored
-fn parse_gitignore(p: &Path) -> Vec<PathBuf> {
-    let mut exclusions = Vec::new();
-
-    let contents = fs::read_to_string(&p).unwrap();
+/// Parse the contents of a gitignore file into a matcher for the paths within `dir`, the folder the file is in.
+/// Invalid patterns are skipped, as git does
+fn parse_gitignore(dir: &Path, contents: &str) -> Gitignore {
+    let mut builder = GitignoreBuilder::new(dir);
     for line in contents.lines() {
-        if line.is_empty() { continue }
-        if line.starts_with('#') { continue }
-
-        let mut line_fmt = line.to_string();
-        if line.starts_with('/') { line_fmt = line.replacen("/", "", 1)}
-        if line.ends_with('/') { line_fmt = line_fmt[..line_fmt.len() - 1].to_string()};
-        let pathbuf = PathBuf::from(p.parent().unwrap()).join(line_fmt);
-
-        exclusions.push(pathbuf);
+        let _ = builder.add_line(None, line.trim_end_matches('\r'));
     }
 
-    exclusions
+    builder.build().unwrap_or_else(|_| Gitignore::empty())
 }
 
 /// Normalize a path. Meaning a relative path will be turned into an absolute one.
-fn normalize_path(i: &str) -> anyhow::Result<PathBuf> {
+/// On Windows the path is in its extended-length form, e.g. `\\?\C:\Users` or `\\?\UNC\server\share`, which isn't limited to 260 characters
+pub fn normalize_path<P: AsRef<Path>>(i: P) -> anyhow::Result<PathBuf> {
     let npath = std::fs::canonicalize(i)?;
-    Ok(npath)
+    Ok(extended_path(&npath))
+}
+
+/// Turn an absolute path into its extended-length form, as returned by [normalize_path], so a path which can't be canonicalized is still stored and compared
+/// like one which can. Drive letters are made uppercase, and `.` and `..` are resolved lexically as Windows doesn't resolve them in such a path.
+/// Relative paths and paths which already are in that form are returned as-is
+#[cfg(windows)]
+fn extended_path(path: &Path) -> PathBuf {
+    use std::path::{Component, Prefix};
+
+    let mut components = path.components();
+    let mut extended = match components.next() {
+        Some(Component::Prefix(prefix)) if path.has_root() => match prefix.kind() {
+            Prefix::Disk(letter) => PathBuf::from(format!("\\\\?\\{}:\\", letter.to_ascii_uppercase() as char)),
+            Prefix::UNC(server, share) => {
+                let mut unc = OsString::from("\\\\?\\UNC\\");
+                unc.push(server);
+                unc.push("\\");
+                unc.push(share);
+                unc.push("\\");
+                PathBuf::from(unc)
+            },
+            _ => return path.to_path_buf()
+        },
+        _ => return path.to_path_buf()
+    };
+
+    for component in components {
+        match component {
+            Component::ParentDir => { extended.pop(); },
+            Component::Normal(name) => extended.push(name),
+            _ => {}
+        }
+    }
+
+    extended
+}
+
+/// Turn an absolute path into its extended-length form. Only Windows has such a form, elsewhere the path is returned as-is
+#[cfg(not(windows))]
+fn extended_path(path: &Path) -> PathBuf {
+    path.to_path_buf()
 }
 
 #[cfg(test)]
 mod test {
-    use crate::sync::{pwd, normalize_path};
+    use crate::sync::{is_linked_to, normalize_path, overlapping_inputs, parse_gitignore, rebase, select, state_paths, traverse, Child, Traversal};
+    use crate::compare::ComparePolicy;
+    use crate::env::Env;
+    use crate::filter::InputFilter;
+    use crate::links::SymlinkPolicy;
+    use std::path::{Path, PathBuf};
+
+    /// Create an empty temporary directory for one test, unique to this process so concurrent test runs don't share it
+    fn temp_dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("gsync-{}-test-{}", test, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// The paths of the files and folders found by a traversal, in the order they were found
+    fn paths_of(children: &[Child]) -> Vec<PathBuf> {
+        let mut entries = Vec::new();
+        for child in children.iter() {
+            child.local_entries(&mut entries, ComparePolicy::Mtime);
+        }
+
+        entries.into_iter().map(|entry| entry.path).collect()
+    }
+
+    #[test]
+    fn parse_gitignore_lines() {
+        let gitignore = parse_gitignore(Path::new("/repo"), "# build output\n/target/\n\n*.log\n!keep.log\ndocs/**/*.pdf\nnode_modules \n");
+        let ignored = |path: &str, is_dir: bool| gitignore.matched(Path::new(path), is_dir).is_ignore();
+
+        // Anchored and directory-only
+        assert!(ignored("/repo/target", true));
+        assert!(!ignored("/repo/target", false));
+        assert!(!ignored("/repo/src/target", true));
+
+        // Globs match at any depth, unless negated
+        assert!(ignored("/repo/app.log", false));
+        assert!(ignored("/repo/src/app.log", false));
+        assert!(!ignored("/repo/keep.log", false));
+
+        // ** matches any number of folders
+        assert!(ignored("/repo/docs/manual.pdf", false));
+        assert!(ignored("/repo/docs/a/b/manual.pdf", false));
+        assert!(!ignored("/repo/src/manual.pdf", false));
+
+        assert!(ignored("/repo/web/node_modules", true));
+        assert!(!ignored("/repo/main.rs", false));
+    }
+
+    #[test]
+    fn select_listed_paths() {
+        let dir = temp_dir("select");
+        let input = dir.join("input");
+        std::fs::create_dir_all(input.join("docs")).unwrap();
+        std::fs::write(input.join("docs/a.txt"), "a").unwrap();
+        std::fs::write(input.join("b.txt"), "b").unwrap();
+
+        let filter = InputFilter::default();
+        let mut traversal = Traversal::for_test(&input, &filter);
+
+        let paths = paths_of(&select(&dir, &[input.join("docs/a.txt"), input.join("missing.txt")], &mut traversal).unwrap());
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert_eq!(vec![input.clone(), input.join("docs"), input.join("docs/a.txt")], paths);
+        assert_eq!(vec![input.join("missing.txt")], traversal.skipped.into_iter().map(|(path, _)| path).collect::<Vec<_>>());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_cycles() {
+        let dir = temp_dir("symlink-cycle");
+        let input = dir.join("input");
+        std::fs::create_dir_all(input.join("docs")).unwrap();
+        std::fs::write(input.join("docs/a.txt"), "a").unwrap();
+        std::os::unix::fs::symlink(&input, input.join("docs/loop")).unwrap();
+        std::os::unix::fs::symlink(input.join("docs"), input.join("copy")).unwrap();
+
+        let filter = InputFilter::default();
+        let mut traversal = Traversal::for_test(&input, &filter);
+
+        let mut paths = paths_of(&traverse(input.clone(), &mut traversal).unwrap());
+        paths.sort();
+
+        // A link to a folder elsewhere is followed, a link back into the traversal is not
+        assert_eq!(vec![input.clone(), input.join("copy"), input.join("copy/a.txt"), input.join("docs"), input.join("docs/a.txt")], paths);
+        assert!(traversal.ancestors.is_empty());
+
+        traversal.symlinks = SymlinkPolicy::Skip;
+        let mut paths = paths_of(&traverse(input.clone(), &mut traversal).unwrap());
+        let _ = std::fs::remove_dir_all(&dir);
+        paths.sort();
+        assert_eq!(vec![input.clone(), input.join("docs"), input.join("docs/a.txt")], paths);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn link_target_deleted_remaining_link_gets_uploaded() {
+        let dir = temp_dir("hard-link-target");
+        std::fs::write(dir.join("a.txt"), "a").unwrap();
+        std::fs::hard_link(dir.join("a.txt"), dir.join("b.txt")).unwrap();
+        std::fs::write(dir.join("c.txt"), "a").unwrap();
+
+        // The shortcut of 'b.txt' to 'a.txt' is kept while they are linked, whichever of them is synced first
+        assert!(is_linked_to(&dir.join("b.txt"), &dir.join("a.txt")));
+        assert!(!is_linked_to(&dir.join("b.txt"), &dir.join("c.txt")));
+
+        // Once 'a.txt' is removed, 'b.txt' is uploaded in place of its shortcut
+        std::fs::remove_file(dir.join("a.txt")).unwrap();
+        let linked = is_linked_to(&dir.join("b.txt"), &dir.join("a.txt"));
+        let _ = std::fs::remove_dir_all(&dir);
+        assert!(!linked);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn special_files() {
+        let dir = temp_dir("special-files");
+        let input = dir.join("input");
+        std::fs::create_dir_all(&input).unwrap();
+        std::fs::write(input.join("a.txt"), "a").unwrap();
+        let _listener = std::os::unix::net::UnixListener::bind(input.join("app.sock")).unwrap();
+        std::os::unix::fs::symlink(input.join("missing.txt"), input.join("broken")).unwrap();
+
+        let filter = InputFilter::default();
+        let mut traversal = Traversal::for_test(&input, &filter);
+        traversal.strict = true;
+
+        // Special files are not anomalies, so they don't abort a strict traversal
+        let paths = paths_of(&traverse(input.clone(), &mut traversal).unwrap());
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!(vec![input.clone(), input.join("a.txt")], paths);
+
+        let mut special = traversal.special;
+        special.sort();
+        assert_eq!(vec![
+            (input.join("app.sock"), "it is a socket".to_string()),
+            (input.join("broken"), "it is a broken symbolic link".to_string())
+        ], special);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_names() {
+        use crate::sync::utf8_name;
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let dir = temp_dir("non-utf8");
+        let input = dir.join("input");
+        std::fs::create_dir_all(&input).unwrap();
+        let resume = input.join(OsStr::from_bytes(b"r\xe9sum\xe9.txt"));
+        let latin = input.join(OsStr::from_bytes(b"caf\xe9.txt"));
+        let invalid = input.join(OsStr::from_bytes(b"caf\xff.txt"));
+        for path in [&resume, &latin, &invalid] {
+            std::fs::write(path, "a").unwrap();
+        }
+
+        let filter = InputFilter::default();
+        let mut traversal = Traversal::for_test(&input, &filter);
+
+        let paths = paths_of(&traverse(input.clone(), &mut traversal).unwrap());
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!(vec![input.clone(), resume.clone()], paths);
+        assert_eq!("r\u{FFFD}sum\u{FFFD}.txt", utf8_name(&resume, resume.file_name().unwrap(), false));
+
+        // Both names become 'caf\u{FFFD}.txt', so neither is synced
+        let mut skipped = traversal.skipped.into_iter().map(|(path, _)| path).collect::<Vec<_>>();
+        skipped.sort();
+        let mut expected = vec![latin, invalid];
+        expected.sort();
+        assert_eq!(expected, skipped);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn extended_paths() {
+        use crate::sync::extended_path;
+
+        assert_eq!(PathBuf::from(r"\\?\C:\Users\projects"), extended_path(Path::new(r"c:\Users\me\..\projects\.")));
+        assert_eq!(PathBuf::from(r"\\?\UNC\server\share\backup"), extended_path(Path::new(r"\\server\share\backup")));
+
+        // Already extended, or relative to the current directory of a drive
+        assert_eq!(PathBuf::from(r"\\?\D:\data"), extended_path(Path::new(r"\\?\D:\data")));
+        assert_eq!(PathBuf::from(r"C:data"), extended_path(Path::new(r"C:data")));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn long_paths() {
+        let dir = temp_dir("long-paths");
+
+        // Lowercase drive letters resolve to the same input
+        let dir = dir.to_string_lossy().to_string();
+        let lowercase = format!("{}{}", dir[..1].to_ascii_lowercase(), &dir[1..]);
+        let input = normalize_path(&dir).unwrap().join("input");
+        assert_eq!(input, normalize_path(&lowercase).unwrap().join("input"));
+        assert!(input.to_string_lossy().starts_with(r"\\?\"));
+
+        // Deeper than the 260 characters a path is otherwise limited to, like a node_modules tree
+        let mut deep = input.clone();
+        for _ in 0..12 {
+            deep.push("node_modules-with-a-long-name");
+        }
+
+        std::fs::create_dir_all(&deep).unwrap();
+        std::fs::write(deep.join("index.js"), "module.exports = {}").unwrap();
+        assert!(deep.join("index.js").to_string_lossy().len() > 260);
+
+        let filter = InputFilter::default();
+        let mut traversal = Traversal::for_test(&input, &filter);
+        traversal.strict = true;
+
+        let paths = paths_of(&traverse(input.clone(), &mut traversal).unwrap());
+        let _ = std::fs::remove_dir_all(normalize_path(&dir).unwrap());
+        assert!(paths.contains(&deep.join("index.js")));
+    }
+
+    #[test]
+    fn directory_overrides() {
+        let dir = temp_dir("overrides");
+        let input = dir.join("input");
+        std::fs::create_dir_all(input.join("project/target")).unwrap();
+        std::fs::write(input.join("project/.gsync.toml"), "excludes = [\"target\"]\nmax-size = 1\n").unwrap();
+        std::fs::write(input.join("project/target/app"), "app").unwrap();
+        std::fs::write(input.join("project/main.rs"), "fn main() {}").unwrap();
+        std::fs::write(input.join("notes.txt"), "notes").unwrap();
+
+        let filter = InputFilter::default();
+        let mut traversal = Traversal::for_test(&input, &filter);
+
+        let mut paths = paths_of(&traverse(input.clone(), &mut traversal).unwrap());
+        let _ = std::fs::remove_dir_all(&dir);
+        paths.sort();
+
+        // The overrides only apply to the folder they are in
+        assert_eq!(vec![input.clone(), input.join("notes.txt"), input.join("project")], paths);
+        assert!(traversal.overrides.is_empty());
+
+        // Like a .gitignore file, the .gsync.toml file is synced itself, so its own maximum size applies to it as well
+        let mut oversized = traversal.oversized.iter().map(|file| file.path.clone()).collect::<Vec<_>>();
+        oversized.sort();
+        assert_eq!(vec![input.join("project/.gsync.toml").to_string_lossy().to_string(), input.join("project/main.rs").to_string_lossy().to_string()], oversized);
+    }
+
+    #[test]
+    fn nested_gitignores() {
+        let dir = temp_dir("gitignore");
+        let input = dir.join("input");
+        std::fs::create_dir_all(input.join("app/build")).unwrap();
+        std::fs::write(input.join(".gitignore"), "*.log\nbuild/\n").unwrap();
+        std::fs::write(input.join("app/.gitignore"), "!important.log\n").unwrap();
+        std::fs::write(input.join("app/build/out.o"), "out").unwrap();
+        std::fs::write(input.join("app/important.log"), "kept").unwrap();
+        std::fs::write(input.join("app/debug.log"), "debug").unwrap();
+        std::fs::write(input.join("important.log"), "ignored").unwrap();
+
+        let filter = InputFilter::default();
+        let mut traversal = Traversal::for_test(&input, &filter);
+
+        let mut paths = paths_of(&traverse(input.clone(), &mut traversal).unwrap());
+        let _ = std::fs::remove_dir_all(&dir);
+        paths.sort();
+
+        // The negation in app/.gitignore only re-includes the file within app
+        assert_eq!(vec![input.clone(), input.join(".gitignore"), input.join("app"), input.join("app/.gitignore"), input.join("app/important.log")], paths);
+        assert!(traversal.gitignores.is_empty());
+    }
+
+    #[test]
+    fn gsyncignore_precedence() {
+        let dir = temp_dir("gsyncignore");
+        let input = dir.join("input");
+        std::fs::create_dir_all(input.join("node_modules")).unwrap();
+        std::fs::write(input.join(".gitignore"), "*.log\n").unwrap();
+        std::fs::write(input.join(".gsyncignore"), "node_modules/\n!important.log\n").unwrap();
+        std::fs::write(input.join("node_modules/index.js"), "js").unwrap();
+        std::fs::write(input.join("important.log"), "kept").unwrap();
+        std::fs::write(input.join("debug.log"), "debug").unwrap();
+
+        let filter = InputFilter::default();
+        let mut traversal = Traversal::for_test(&input, &filter);
+
+        let mut paths = paths_of(&traverse(input.clone(), &mut traversal).unwrap());
+        let _ = std::fs::remove_dir_all(&dir);
+        paths.sort();
+
+        // .gsyncignore excludes what git doesn't ignore, and re-includes what it does
+        assert_eq!(vec![input.clone(), input.join(".gitignore"), input.join(".gsyncignore"), input.join("important.log")], paths);
+    }
+
+    #[test]
+    fn state_dir_is_input() {
+        let dir = temp_dir("state-dir");
+        let input = std::fs::canonicalize(&dir).unwrap();
+        for name in ["data.db3", "data.db3-journal", "data.db3-wal", "data.db3-shm", "notes.txt"] {
+            std::fs::write(input.join(name), "a").unwrap();
+        }
+
+        // Set with the state directory variable to a folder which is synced itself, e.g. the home folder
+        let mut env = Env::empty();
+        env.db = input.to_string_lossy().to_string();
+
+        let filter = InputFilter::default();
+        let mut traversal = Traversal::for_test(&input, &filter);
+        traversal.exclusions = state_paths(&env);
+
+        let paths = paths_of(&traverse(input.clone(), &mut traversal).unwrap());
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!(vec![input.clone(), input.join("notes.txt")], paths);
+    }
+
+    #[test]
+    fn nested_inputs() {
+        let inputs = vec![PathBuf::from("/home/me"), PathBuf::from("/home/me/projects/gsync"), PathBuf::from("/home/me/projects"), PathBuf::from("/home/meow")];
+        let overlapping = overlapping_inputs(&inputs);
+
+        assert_eq!(vec![(Path::new("/home/me/projects"), Path::new("/home/me/projects/gsync")), (Path::new("/home/me"), Path::new("/home/me/projects"))], overlapping);
+    }
+
+    #[test]
+    fn rebase_into_snapshot() {
+        assert_eq!(PathBuf::from("/srv/.mail.gsync-1/me/inbox"), rebase(Path::new("/srv/mail/me/inbox"), Path::new("/srv/mail"), Path::new("/srv/.mail.gsync-1")));
+        assert_eq!(PathBuf::from("/home/me/notes"), rebase(Path::new("/home/me/notes"), Path::new("/srv/mail"), Path::new("/srv/.mail.gsync-1")));
+    }
 
     #[test]
     fn normalize_path_relative_period() {
-        let pwd = pwd();
-        let p = "./example";
+        let pwd = std::fs::canonicalize(std::env::current_dir().unwrap()).unwrap();
+        let p = "./src";
 
-        assert_eq!(format!("{}{}", pwd, p), normalize_path(p))
+        assert_eq!(pwd.join("src"), normalize_path(p).unwrap())
     }
 
     #[test]
     fn normalize_path_relative_no_period() {
-        let pwd = pwd();
-        let p = "example";
+        let pwd = std::fs::canonicalize(std::env::current_dir().unwrap()).unwrap();
+        let p = "src";
 
-        assert_eq!(format!("{}/{}", pwd, p), normalize_path(p))
+        assert_eq!(pwd.join("src"), normalize_path(p).unwrap())
     }
 
     #[test]
     fn normalize_path_absolute() {
-        let p = "/tmp/example";
+        let p = std::fs::canonicalize(std::env::temp_dir()).unwrap();
 
-        assert_eq!(p, normalize_path(p))
+        assert_eq!(p, normalize_path(&p).unwrap())
     }
 }
\ No newline at end of file
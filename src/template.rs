@@ -0,0 +1,100 @@
+//! Module for expanding placeholders in remote paths, e.g. `Backups/{hostname}/{date}`, so inputs can be synced into dated or per-machine folders
+
+use chrono::NaiveDate;
+use std::path::Path;
+
+/// The placeholders which can be used in remote paths
+pub const PLACEHOLDERS: [&str; 4] = ["hostname", "date", "profile", "input"];
+
+/// Struct describing the values placeholders are expanded to
+#[derive(Debug, Clone)]
+pub struct TemplateValues {
+    /// `{hostname}`: The name of this machine
+    pub hostname:   String,
+
+    /// `{date}`: The date the sync started, as `YYYY-MM-DD`
+    pub date:       String,
+
+    /// `{profile}`: The name of the user GSync runs as, which is the profile synced for in the daemon's multi-user mode
+    pub profile:    String,
+
+    /// `{input}`: The name of the input folder or file
+    pub input:      String
+}
+
+impl TemplateValues {
+    /// The values for an input synced on the provided date on this machine
+    pub fn new(input: &Path, date: NaiveDate) -> Self {
+        Self {
+            hostname:   crate::report::hostname().unwrap_or_else(|| "unknown".to_string()),
+            date:       date.format("%Y-%m-%d").to_string(),
+            profile:    std::env::var("USER").or_else(|_| std::env::var("USERNAME")).unwrap_or_else(|_| "default".to_string()),
+            input:      input.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default()
+        }
+    }
+}
+
+/// Expand the placeholders in a remote path. A `/` in a value is replaced, so a value never adds folders to the path
+pub fn expand(template: &str, values: &TemplateValues) -> String {
+    let value = |value: &str| value.replace('/', "-");
+    template
+        .replace("{hostname}", &value(&values.hostname))
+        .replace("{date}", &value(&values.date))
+        .replace("{profile}", &value(&values.profile))
+        .replace("{input}", &value(&values.input))
+}
+
+/// Check that every placeholder in a remote path is known. Returns the first unknown placeholder
+pub fn validate(template: &str) -> std::result::Result<(), String> {
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => return Err(rest[start..].to_string())
+        };
+
+        let name = &rest[start + 1..end];
+        if !PLACEHOLDERS.contains(&name) {
+            return Err(rest[start..=end].to_string());
+        }
+
+        rest = &rest[end + 1..];
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::template::{expand, validate, TemplateValues};
+
+    /// Values for the tests
+    fn values() -> TemplateValues {
+        TemplateValues {
+            hostname:   "laptop".to_string(),
+            date:       "2024-03-01".to_string(),
+            profile:    "alice".to_string(),
+            input:      "Documents".to_string()
+        }
+    }
+
+    #[test]
+    fn expands_placeholders() {
+        assert_eq!(expand("Backups/{hostname}/{date}", &values()), "Backups/laptop/2024-03-01");
+        assert_eq!(expand("{profile}/{input}", &values()), "alice/Documents");
+        assert_eq!(expand("Backups/Docs", &values()), "Backups/Docs");
+    }
+
+    #[test]
+    fn values_do_not_add_folders() {
+        let values = TemplateValues { hostname: "a/b".to_string(), ..values() };
+        assert_eq!(expand("{hostname}", &values), "a-b");
+    }
+
+    #[test]
+    fn rejects_unknown_placeholders() {
+        assert!(validate("Backups/{hostname}/{date}").is_ok());
+        assert_eq!(validate("Backups/{host}"), Err("{host}".to_string()));
+        assert_eq!(validate("Backups/{date"), Err("{date".to_string()));
+    }
+}
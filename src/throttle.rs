@@ -0,0 +1,141 @@
+//! Module for limiting the upload bandwidth. All uploads of a run share a single limit, so it holds regardless of the number of concurrent uploads
+
+use std::io::Read;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The maximum number of bytes read at once from a throttled reader, so a single read can't use the limit of many seconds
+const MAX_CHUNK: usize = 64 * 1024;
+
+/// Limits the rate at which bytes are uploaded, with a token bucket which holds at most one second worth of bytes
+#[derive(Debug)]
+pub struct RateLimiter {
+    /// The maximum number of bytes per second
+    rate:   u64,
+
+    /// The state of the bucket
+    bucket: Mutex<Bucket>
+}
+
+/// Struct describing the state of the token bucket of a RateLimiter
+#[derive(Debug)]
+struct Bucket {
+    /// The number of bytes which may be sent right away. Negative if more was sent than allowed, which has to be waited off
+    available:  f64,
+
+    /// The moment the bucket was last refilled
+    refilled:   Instant
+}
+
+/// A reader which reads no faster than its RateLimiter allows. Without a limiter it reads at full speed
+#[derive(Debug)]
+pub struct ThrottledReader<R> {
+    /// The reader of the content being uploaded
+    inner:      R,
+
+    /// The limiter shared by all uploads, if the bandwidth is limited
+    limiter:    Option<Arc<RateLimiter>>
+}
+
+/// Create a limiter for the given bandwidth in bytes per second. A bandwidth of 0 means the bandwidth is not limited
+pub fn limiter(rate: u64) -> Option<Arc<RateLimiter>> {
+    if rate == 0 {
+        None
+    } else {
+        Some(Arc::new(RateLimiter::new(rate)))
+    }
+}
+
+/// Wrap a reader so it reads no faster than the limiter allows
+pub fn throttle<R: Read>(limiter: Option<&Arc<RateLimiter>>, inner: R) -> ThrottledReader<R> {
+    ThrottledReader {
+        inner,
+        limiter: limiter.cloned()
+    }
+}
+
+impl RateLimiter {
+    /// Create a new limiter, starting with a full bucket
+    ///
+    /// ## Params
+    /// - `rate` The maximum number of bytes per second
+    pub fn new(rate: u64) -> Self {
+        Self {
+            rate,
+            bucket: Mutex::new(Bucket {
+                available:  rate as f64,
+                refilled:   Instant::now()
+            })
+        }
+    }
+
+    /// Take bytes which were sent from the bucket, and wait for as long as more was sent than the limit allows
+    pub fn consume(&self, bytes: u64) {
+        let wait = {
+            let mut bucket = self.bucket.lock().unwrap();
+            let now = Instant::now();
+            let available = refill(bucket.available, now.duration_since(bucket.refilled), self.rate) - bytes as f64;
+
+            bucket.available = available;
+            bucket.refilled = now;
+            delay(available, self.rate)
+        };
+
+        if !wait.is_zero() {
+            std::thread::sleep(wait);
+        }
+    }
+}
+
+/// The number of bytes available after refilling the bucket for `elapsed` at `rate` bytes per second. The bucket holds at most one second worth of bytes
+fn refill(available: f64, elapsed: Duration, rate: u64) -> f64 {
+    (available + elapsed.as_secs_f64() * rate as f64).min(rate as f64)
+}
+
+/// How long to wait until the bucket is no longer in debt
+fn delay(available: f64, rate: u64) -> Duration {
+    if available >= 0.0 {
+        Duration::ZERO
+    } else {
+        Duration::from_secs_f64(-available / rate as f64)
+    }
+}
+
+impl<R: Read> Read for ThrottledReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let limiter = match &self.limiter {
+            Some(limiter) => limiter,
+            None => return self.inner.read(buf)
+        };
+
+        let len = buf.len().min(MAX_CHUNK);
+        let read = self.inner.read(&mut buf[..len])?;
+        limiter.consume(read as u64);
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::throttle::{delay, refill, limiter};
+    use std::time::Duration;
+
+    #[test]
+    fn refill_is_capped() {
+        assert_eq!(1500.0, refill(1000.0, Duration::from_millis(250), 2000));
+        assert_eq!(2000.0, refill(1000.0, Duration::from_secs(10), 2000));
+        assert_eq!(0.0, refill(-1000.0, Duration::from_millis(500), 2000));
+    }
+
+    #[test]
+    fn debt_is_waited_off() {
+        assert_eq!(Duration::ZERO, delay(10.0, 1000));
+        assert_eq!(Duration::from_millis(500), delay(-500.0, 1000));
+    }
+
+    #[test]
+    fn zero_is_unlimited() {
+        assert!(limiter(0).is_none());
+        assert!(limiter(1024).is_some());
+    }
+}
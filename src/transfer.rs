@@ -0,0 +1,54 @@
+//! Module for transferring the ownership of the GSync tree to another user
+
+use crate::env::Env;
+use crate::{Result, Error};
+use crate::api::drive;
+
+/// Transfer the ownership of the GSync root folder and everything in it to another user.
+/// Files which are not owned by the user, e.g. files shared with them, are skipped
+///
+/// ## Params
+/// - `env` Env instance
+/// - `email` The email address of the new owner
+/// - `pending` Ask the new owner to accept the ownership, rather than transferring it right away
+///
+/// ## Errors
+/// - When the GSync folder is in a shared drive, files in a shared drive are owned by the drive
+/// - When a Drive API call fails
+pub fn transfer_ownership(env: &Env, email: &str, pending: bool) -> Result<()> {
+    if env.drive_id.is_some() {
+        return Err((Error::Other("Files in a shared drive are owned by the drive, their ownership cannot be transferred".to_string()), line!(), file!()));
+    }
+
+    let root = drive::blocking::get_file(env, &env.root_folder)?;
+    let mut queue = vec![(root, String::from("GSync"))];
+    let mut transferred = 0u64;
+    let mut skipped = 0u64;
+
+    while let Some((file, path)) = queue.pop() {
+        if file.is_folder() {
+            let children = drive::blocking::list_files(env, Some(&format!("'{}' in parents and trashed = false", &file.id)), env.drive_id.as_deref())?;
+            for child in children {
+                let child_path = format!("{}/{}", &path, &child.name);
+                queue.push((child, child_path));
+            }
+        }
+
+        if !file.owned_by_me {
+            println!("Warning: '{}' is not owned by you. Skipping.", &path);
+            skipped += 1;
+            continue;
+        }
+
+        println!("Info: Transferring ownership of '{}'", &path);
+        drive::blocking::transfer_ownership(env, &file.id, email, pending)?;
+        transferred += 1;
+    }
+
+    println!("Info: Transferred ownership of {} files and folders to '{}', skipped {}.", transferred, email, skipped);
+    if pending {
+        println!("Info: '{}' has to accept the ownership of each file before the transfer is complete.", email);
+    }
+
+    Ok(())
+}
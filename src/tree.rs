@@ -0,0 +1,130 @@
+//! Module for rendering the remote GSync folder hierarchy as a tree
+
+use crate::env::Env;
+use crate::Result;
+use crate::api::drive;
+use crate::files::{self, TrackedFile};
+use crate::size::format_size;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+/// The default number of folder levels shown below the root folders
+pub const DEFAULT_DEPTH: usize = 2;
+
+/// The total size and number of files in a folder, including its subfolders
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Totals {
+    /// The total size in bytes
+    size:   u64,
+
+    /// The number of files
+    files:  u64
+}
+
+/// Render the remote folder hierarchy of everything GSync has synced as an indented tree.
+/// The hierarchy is built from the files table, the names of the root folders are queried from Drive
+///
+/// ## Params
+/// - `env` Env instance
+/// - `max_depth` The number of folder levels shown below the root folders
+///
+/// ## Errors
+/// - When a database operation fails
+/// - When the Google API returns an error
+pub fn tree(env: &Env, max_depth: usize) -> Result<String> {
+    let tracked = files::get_all(env)?;
+    let ids = tracked.iter().map(|file| file.id.as_str()).collect::<HashSet<&str>>();
+
+    // Root folders are the parents of tracked files which are not tracked themselves, e.g. the GSync folder or a remote root of an input
+    let mut roots = tracked.iter()
+        .map(|file| file.parent_id.as_str())
+        .filter(|parent_id| !ids.contains(parent_id))
+        .collect::<Vec<&str>>();
+    roots.sort_unstable();
+    roots.dedup();
+
+    let mut text = String::new();
+    for root in roots {
+        let name = drive::blocking::get_file(env, root)?.name;
+        text.push_str(&render(&tracked, root, &name, max_depth));
+    }
+
+    Ok(text)
+}
+
+/// Render the tree below a root folder
+///
+/// ## Params
+/// - `tracked` All tracked files
+/// - `root_id` The ID of the root folder in Drive
+/// - `root_name` The name of the root folder
+/// - `max_depth` The number of folder levels shown below the root folder
+fn render(tracked: &[TrackedFile], root_id: &str, root_name: &str, max_depth: usize) -> String {
+    let mut children: HashMap<&str, Vec<&TrackedFile>> = HashMap::new();
+    for file in tracked {
+        children.entry(file.parent_id.as_str()).or_default().push(file);
+    }
+
+    for folder_children in children.values_mut() {
+        folder_children.sort_by(|a, b| a.path.cmp(&b.path));
+    }
+
+    let mut text = String::new();
+    render_folder(&mut text, &children, root_id, root_name, 0, max_depth);
+    text
+}
+
+/// Render a folder and, if the maximum depth is not reached yet, its subfolders
+fn render_folder(text: &mut String, children: &HashMap<&str, Vec<&TrackedFile>>, id: &str, name: &str, depth: usize, max_depth: usize) {
+    let totals = totals(children, id);
+    text.push_str(&format!("{}{}/ ({}, {} files)\n", "  ".repeat(depth), name, format_size(totals.size), totals.files));
+
+    if depth >= max_depth {
+        return;
+    }
+
+    for folder in children.get(id).into_iter().flatten().filter(|file| file.is_folder) {
+        let name = Path::new(&folder.path).file_name().and_then(|name| name.to_str()).unwrap_or(&folder.path);
+        render_folder(text, children, &folder.id, name, depth + 1, max_depth);
+    }
+}
+
+/// Get the totals of a folder, including its subfolders
+fn totals(children: &HashMap<&str, Vec<&TrackedFile>>, id: &str) -> Totals {
+    let mut totals = Totals::default();
+    for file in children.get(id).into_iter().flatten() {
+        if file.is_folder {
+            let folder_totals = self::totals(children, &file.id);
+            totals.size += folder_totals.size;
+            totals.files += folder_totals.files;
+        } else {
+            totals.size += file.size.unwrap_or(0) as u64;
+            totals.files += 1;
+        }
+    }
+
+    totals
+}
+
+#[cfg(test)]
+mod test {
+    use crate::tree::render;
+    use crate::files::TrackedFile;
+
+    fn tracked(path: &str, id: &str, parent_id: &str, size: Option<i64>) -> TrackedFile {
+        TrackedFile { path: path.to_string(), id: id.to_string(), parent_id: parent_id.to_string(), is_folder: size.is_none(), link_target: None, size, md5: None, synced_at: None }
+    }
+
+    #[test]
+    fn renders_totals_up_to_depth() {
+        let tracked = vec![
+            tracked("/home/docs", "docs", "root", None),
+            tracked("/home/docs/a.txt", "a", "docs", Some(1024)),
+            tracked("/home/docs/old", "old", "docs", None),
+            tracked("/home/docs/old/b.txt", "b", "old", Some(1024)),
+        ];
+
+        assert_eq!(render(&tracked, "root", "GSync", 1), "GSync/ (2.0 KiB, 2 files)\n  docs/ (2.0 KiB, 2 files)\n");
+        assert_eq!(render(&tracked, "root", "GSync", 2), "GSync/ (2.0 KiB, 2 files)\n  docs/ (2.0 KiB, 2 files)\n    old/ (1.0 KiB, 1 files)\n");
+    }
+}
@@ -0,0 +1,295 @@
+//! Module for following a sync in a full-screen terminal UI, showing the scan progress, the transfers in progress, the errors and the overall throughput.
+//! Everything GSync prints while the TUI is shown is captured, and shown in the TUI instead
+
+use crate::config::Configuration;
+use crate::env::Env;
+use crate::report::RunReport;
+use crate::size::format_size;
+use crate::sync::SyncOptions;
+use crate::{Result, Error, unwrap_other_err};
+use crossterm::event::{self, Event, KeyCode, KeyModifiers};
+use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use ratatui::style::{Color, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Gauge, Paragraph};
+use ratatui::{Frame, Terminal};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::os::unix::io::FromRawFd;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How often the TUI is redrawn
+const TICK: Duration = Duration::from_millis(250);
+
+/// The number of captured lines kept for the log and error panes
+const MAX_LINES: usize = 500;
+
+/// Struct describing the output of GSync captured while the TUI is shown
+#[derive(Debug, Default)]
+struct Captured {
+    /// The most recent informational lines
+    log:    VecDeque<String>,
+
+    /// The most recent warnings and errors
+    errors: VecDeque<String>,
+
+    /// The most recent line about the traversal of the inputs
+    scan:   Option<String>
+}
+
+/// Struct describing the standard output and error of the process, while they are redirected into a pipe
+struct Redirect {
+    /// A duplicate of the original standard output
+    stdout: libc::c_int,
+
+    /// A duplicate of the original standard error
+    stderr: libc::c_int
+}
+
+impl Redirect {
+    /// Redirect standard output and error into a pipe, returning the redirect and the read end of the pipe
+    ///
+    /// ## Errors
+    /// - When the pipe can't be created or the file descriptors can't be duplicated
+    fn start() -> Result<(Self, File)> {
+        let mut fds: [libc::c_int; 2] = [0; 2];
+        // Safe because fds has room for both ends of the pipe, and the file descriptors are checked before use
+        unsafe {
+            if libc::pipe(fds.as_mut_ptr()) != 0 {
+                return Err((Error::Other(format!("Failed to create a pipe: {}", std::io::Error::last_os_error())), line!(), file!()));
+            }
+
+            let redirect = Self {
+                stdout: libc::dup(libc::STDOUT_FILENO),
+                stderr: libc::dup(libc::STDERR_FILENO)
+            };
+
+            if redirect.stdout < 0 || redirect.stderr < 0 || libc::dup2(fds[1], libc::STDOUT_FILENO) < 0 || libc::dup2(fds[1], libc::STDERR_FILENO) < 0 {
+                return Err((Error::Other(format!("Failed to redirect the output: {}", std::io::Error::last_os_error())), line!(), file!()));
+            }
+
+            libc::close(fds[1]);
+            Ok((redirect, File::from_raw_fd(fds[0])))
+        }
+    }
+
+    /// Open the original standard output, for drawing the TUI on
+    ///
+    /// ## Errors
+    /// - When the file descriptor can't be duplicated
+    fn terminal(&self) -> Result<File> {
+        // Safe because the duplicate is owned by the returned File only
+        unsafe {
+            let fd = libc::dup(self.stdout);
+            if fd < 0 {
+                return Err((Error::Other(format!("Failed to open the terminal: {}", std::io::Error::last_os_error())), line!(), file!()));
+            }
+
+            Ok(File::from_raw_fd(fd))
+        }
+    }
+}
+
+impl Drop for Redirect {
+    fn drop(&mut self) {
+        use std::io::Write;
+
+        let _ = std::io::stdout().flush();
+        // Safe because the duplicates are owned by the redirect. Restoring the output closes the write end of the pipe, which ends the capture
+        unsafe {
+            libc::dup2(self.stdout, libc::STDOUT_FILENO);
+            libc::dup2(self.stderr, libc::STDERR_FILENO);
+            libc::close(self.stdout);
+            libc::close(self.stderr);
+        }
+    }
+}
+
+/// Run a sync while showing its progress in the TUI. When the sync is done, the TUI waits for a key press and the run report is printed
+///
+/// ## Errors
+/// - When setting up the terminal fails
+/// - When the user aborts the sync
+/// - When the sync fails
+pub fn sync(config: Configuration, env: Env, options: SyncOptions) -> Result<()> {
+    let captured = Arc::new(Mutex::new(Captured::default()));
+    let report = Arc::new(RunReport::new());
+
+    let (redirect, pipe) = Redirect::start()?;
+    let capture = {
+        let captured = captured.clone();
+        std::thread::spawn(move || {
+            for line in BufReader::new(pipe).lines().map_while(std::result::Result::ok) {
+                captured.lock().unwrap().push(line);
+            }
+        })
+    };
+
+    let sync = {
+        let report = report.clone();
+        std::thread::spawn(move || crate::sync::sync_with_report(&config, &env, &options, &report))
+    };
+
+    let shown = show(&redirect, &captured, &report, &sync);
+    drop(redirect);
+    let _ = capture.join();
+    shown?;
+
+    let result = match sync.join() {
+        Ok(result) => result,
+        Err(_) => Err((Error::Other("The sync stopped unexpectedly".to_string()), line!(), file!()))
+    };
+
+    let error = result.as_ref().err().map(|(e, line, file)| format!("{} (line {} in {})", crate::redact::redact(format!("{:?}", e)), line, file));
+    println!("{}", report.render(error.as_deref()));
+    result
+}
+
+/// Show the TUI until the sync is done and the user pressed a key. Ctrl+C returns an error, which aborts the sync when GSync exits
+///
+/// ## Errors
+/// - When drawing on the terminal fails
+fn show(redirect: &Redirect, captured: &Mutex<Captured>, report: &RunReport, sync: &std::thread::JoinHandle<Result<()>>) -> Result<()> {
+    let mut terminal = unwrap_other_err!(Terminal::new(CrosstermBackend::new(redirect.terminal()?)));
+    unwrap_other_err!(terminal::enable_raw_mode());
+    unwrap_other_err!(crossterm::execute!(terminal.backend_mut(), EnterAlternateScreen));
+
+    let started = Instant::now();
+    let mut finished = None;
+    let result = loop {
+        if finished.is_none() && sync.is_finished() {
+            finished = Some(started.elapsed());
+        }
+
+        let elapsed = finished.unwrap_or_else(|| started.elapsed());
+        if let Err(e) = terminal.draw(|frame| draw(frame, &captured.lock().unwrap(), report, elapsed, finished.is_some())) {
+            break Err((Error::Other(format!("Failed to draw the TUI: {}", e)), line!(), file!()));
+        }
+
+        match event::poll(TICK) {
+            Ok(true) => {},
+            Ok(false) => continue,
+            Err(e) => break Err((Error::Other(format!("Failed to read from the terminal: {}", e)), line!(), file!()))
+        }
+
+        match event::read() {
+            Ok(Event::Key(key)) if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) => {
+                break Err((Error::Other("The sync was aborted".to_string()), line!(), file!()));
+            },
+            Ok(Event::Key(_)) if finished.is_some() => break Ok(()),
+            Ok(_) => {},
+            Err(e) => break Err((Error::Other(format!("Failed to read from the terminal: {}", e)), line!(), file!()))
+        }
+    };
+
+    let _ = terminal::disable_raw_mode();
+    let _ = crossterm::execute!(terminal.backend_mut(), LeaveAlternateScreen);
+    result
+}
+
+impl Captured {
+    /// Add a captured line to the pane it belongs in
+    fn push(&mut self, line: String) {
+        if line.trim().is_empty() {
+            return;
+        }
+
+        if line.starts_with("Info: Traversing") || line.starts_with("Info: Found") {
+            self.scan = Some(line.clone());
+        }
+
+        let pane = if is_error(&line) { &mut self.errors } else { &mut self.log };
+        pane.push_back(line);
+        if pane.len() > MAX_LINES {
+            pane.pop_front();
+        }
+    }
+}
+
+/// Check if a line printed by GSync is a warning or an error
+fn is_error(line: &str) -> bool {
+    line.starts_with("Warning:") || line.starts_with("Error:")
+}
+
+/// The average number of bytes transferred per second
+fn throughput(bytes: u64, elapsed: Duration) -> u64 {
+    match elapsed.as_secs_f64() {
+        secs if secs > 0.0 => (bytes as f64 / secs) as u64,
+        _ => 0
+    }
+}
+
+/// Draw the TUI
+fn draw(frame: &mut Frame<'_>, captured: &Captured, report: &RunReport, elapsed: Duration, finished: bool) {
+    let transfers = crate::progress::active();
+    let areas = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(5), Constraint::Min(5), Constraint::Length(8), Constraint::Length(8)])
+        .split(frame.size());
+
+    let progress = report.snapshot();
+    let in_flight = transfers.iter().map(|transfer| transfer.done).sum::<u64>();
+    let state = if finished { "Finished, press any key to exit" } else { "Syncing, press Ctrl+C to abort" };
+    let summary = vec![
+        Line::from(format!("{} ({}s)", state, elapsed.as_secs())),
+        Line::from(captured.scan.clone().unwrap_or_else(|| "Scanning the inputs".to_string())),
+        Line::from(format!("{} new, {} changed, {} up-to-date, {} deleted, {} failures. {} uploaded at {}/s",
+            progress.uploaded, progress.updated, progress.unchanged, progress.deleted, progress.failures,
+            format_size(progress.bytes + in_flight), format_size(throughput(progress.bytes + in_flight, elapsed))))
+    ];
+    frame.render_widget(Paragraph::new(summary).block(Block::default().borders(Borders::ALL).title("GSync")), areas[0]);
+
+    let block = Block::default().borders(Borders::ALL).title(format!("Transfers ({})", transfers.len()));
+    let inner = block.inner(areas[1]);
+    frame.render_widget(block, areas[1]);
+    for (row, transfer) in transfers.iter().take(inner.height as usize).enumerate() {
+        let ratio = if transfer.total == 0 { 1.0 } else { (transfer.done as f64 / transfer.total as f64).min(1.0) };
+        let gauge = Gauge::default()
+            .gauge_style(Style::default().fg(Color::Green))
+            .ratio(ratio)
+            .label(format!("{} ({} of {})", transfer.path, format_size(transfer.done), format_size(transfer.total)));
+        frame.render_widget(gauge, Rect { y: inner.y + row as u16, height: 1, ..inner });
+    }
+
+    frame.render_widget(pane("Errors", &captured.errors, areas[2]), areas[2]);
+    frame.render_widget(pane("Log", &captured.log, areas[3]), areas[3]);
+}
+
+/// A pane showing the most recent lines which fit in the area
+fn pane<'a>(title: &'a str, lines: &'a VecDeque<String>, area: Rect) -> Paragraph<'a> {
+    let fits = area.height.saturating_sub(2) as usize;
+    let lines = lines.iter()
+        .skip(lines.len().saturating_sub(fits))
+        .map(|line| Line::from(line.as_str()))
+        .collect::<Vec<_>>();
+
+    Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::tui::{throughput, Captured};
+    use std::time::Duration;
+
+    #[test]
+    fn sorts_lines_into_panes() {
+        let mut captured = Captured::default();
+        captured.push("Info: Traversing '/home'".to_string());
+        captured.push("Warning: Failed to sync '/home/a', skipping it".to_string());
+        captured.push(String::new());
+
+        assert_eq!(Some("Info: Traversing '/home'"), captured.scan.as_deref());
+        assert_eq!(1, captured.log.len());
+        assert_eq!(1, captured.errors.len());
+    }
+
+    #[test]
+    fn average_throughput() {
+        assert_eq!(512, throughput(1024, Duration::from_secs(2)));
+        assert_eq!(0, throughput(1024, Duration::from_secs(0)));
+    }
+}
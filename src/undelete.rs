@@ -0,0 +1,138 @@
+//! Module related to recovering files from the Google Drive trash
+
+use crate::env::Env;
+use crate::Result;
+use crate::api::drive;
+use crate::api::drive::File;
+use crate::files::{self, TrackedFile};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// Struct describing a trashed file which belongs to the GSync folder
+#[derive(Debug)]
+pub struct TrashedFile {
+    /// The trashed file
+    pub file:           File,
+
+    /// The path of the file relative to the GSync root folder
+    pub remote_path:    String
+}
+
+/// List all files in the trash which belong to the GSync root folder.
+/// Only explicitly trashed files are returned, files trashed as part of a trashed folder are restored together with that folder
+///
+/// ## Errors
+/// - When a Drive API call fails
+pub fn list_trashed(env: &Env) -> Result<Vec<TrashedFile>> {
+    let trashed = drive::blocking::list_files(env, Some("trashed = true"), env.drive_id.as_deref())?;
+
+    // Cache of folder ID to the folder's path relative to the root folder. None if the folder is not in the GSync root folder
+    let mut folder_paths: HashMap<String, Option<String>> = HashMap::new();
+    folder_paths.insert(env.root_folder.clone(), Some(String::new()));
+
+    let mut result = Vec::new();
+    for file in trashed {
+        if !file.explicitly_trashed {
+            continue;
+        }
+
+        let parent_path = match file.parents.first() {
+            Some(parent) => resolve_folder_path(env, parent, &mut folder_paths)?,
+            None => None
+        };
+
+        if let Some(parent_path) = parent_path {
+            let remote_path = join_remote_path(&parent_path, &file.name);
+            result.push(TrashedFile { file, remote_path });
+        }
+    }
+
+    Ok(result)
+}
+
+/// Restore a file from the trash and start tracking it again.
+/// If the file is a folder, everything in it is tracked again as well
+///
+/// ## Errors
+/// - When a Drive API call fails
+/// - When a database operation fails
+pub fn undelete(env: &Env, file: &File) -> Result<()> {
+    drive::blocking::set_trashed(env, &file.id, false)?;
+    retrack(env, file)
+}
+
+/// Start tracking a file in Drive again, under its parent folder. If the file is a folder, everything in it is tracked again as well.
+/// A file whose parent folder is not tracked is left to the next sync
+///
+/// ## Errors
+/// - When a Drive API call fails
+/// - When a database operation fails
+pub fn retrack(env: &Env, file: &File) -> Result<()> {
+    let parent_id = match file.parents.first() {
+        Some(parent_id) => parent_id,
+        None => return Ok(())
+    };
+
+    match files::get_by_id(env, parent_id)? {
+        Some(parent) => track(env, file, parent_id, PathBuf::from(&parent.path).join(&file.name))?,
+        None => println!("Info: The parent folder of '{}' is not tracked, it will be picked up by the next sync", &file.name)
+    }
+
+    Ok(())
+}
+
+/// Insert a file into the database, and if it is a folder, all files within it
+///
+/// ## Errors
+/// - When a Drive API call fails
+/// - When a database operation fails
+fn track(env: &Env, file: &File, parent_id: &str, path: PathBuf) -> Result<()> {
+    files::insert(env, &TrackedFile {
+        path:       path.to_string_lossy().to_string(),
+        id:         file.id.clone(),
+        parent_id:  parent_id.to_string(),
+        is_folder:  file.is_folder(),
+        link_target: None,
+        size:       if file.is_folder() { None } else { Some(file.size() as i64) },
+        md5:        file.md5_checksum.clone(),
+        synced_at:  Some(chrono::Utc::now().timestamp())
+    })?;
+
+    if file.is_folder() {
+        let children = drive::blocking::list_files(env, Some(&format!("'{}' in parents and trashed = false", &file.id)), env.drive_id.as_deref())?;
+        for child in children {
+            let child_path = path.join(&child.name);
+            track(env, &child, &file.id, child_path)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Resolve the path of a folder relative to the GSync root folder. Returns None if the folder is not inside the GSync root folder
+///
+/// ## Errors
+/// - When a Drive API call fails
+fn resolve_folder_path(env: &Env, id: &str, cache: &mut HashMap<String, Option<String>>) -> Result<Option<String>> {
+    if let Some(path) = cache.get(id) {
+        return Ok(path.clone());
+    }
+
+    let folder = drive::blocking::get_file(env, id)?;
+    let path = match folder.parents.first() {
+        Some(parent) => resolve_folder_path(env, parent, cache)?.map(|parent_path| join_remote_path(&parent_path, &folder.name)),
+        None => None
+    };
+
+    cache.insert(id.to_string(), path.clone());
+    Ok(path)
+}
+
+/// Join a remote path and a name
+fn join_remote_path(parent: &str, name: &str) -> String {
+    if parent.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{}", parent, name)
+    }
+}
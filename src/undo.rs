@@ -0,0 +1,309 @@
+//! Module for undoing the remote effects of the most recent sync run, using the audit log, the trash and the revisions of files
+
+use crate::env::Env;
+use crate::{Result, Error, unwrap_db_err};
+use crate::api::drive;
+use crate::audit::{self, OperationRecord};
+use crate::files;
+use chrono::{NaiveDateTime, Utc};
+use rusqlite::{named_params, OptionalExtension};
+use std::collections::HashSet;
+use std::fmt;
+
+/// Enum describing what is done to undo a remote operation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UndoAction {
+    /// Move a file or folder created by the run to the trash
+    Remove {
+        /// The ID of the file
+        id:     String,
+        /// The path or name of the file
+        path:   String
+    },
+
+    /// Delete the revisions uploaded by the run, rolling the file back to its prior revision
+    RollBack {
+        /// The ID of the file
+        id:     String,
+        /// The path or name of the file
+        path:   String,
+        /// The IDs of the revisions the run uploaded, oldest first
+        revisions: Vec<String>
+    },
+
+    /// Restore a file or folder the run moved to the trash
+    Untrash {
+        /// The ID of the file
+        id:     String,
+        /// The path or name of the file
+        path:   String
+    },
+
+    /// Mark a file or folder the run kept in Drive as stale as current again, and track it again
+    Unarchive {
+        /// The ID of the file
+        id:     String,
+        /// The path or name of the file
+        path:   String
+    },
+
+    /// An operation which can't be undone
+    Irreversible {
+        /// The path, name or ID of the file
+        path:   String,
+        /// Why the operation can't be undone
+        reason: &'static str
+    }
+}
+
+impl fmt::Display for UndoAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Remove { path, .. } => write!(f, "{:<9} {}", "remove", path),
+            Self::RollBack { path, .. } => write!(f, "{:<9} {}", "roll back", path),
+            Self::Untrash { path, .. } => write!(f, "{:<9} {}", "untrash", path),
+            Self::Unarchive { path, .. } => write!(f, "{:<9} {}", "unarchive", path),
+            Self::Irreversible { path, reason } => write!(f, "{:<9} {} ({})", "skip", path, reason)
+        }
+    }
+}
+
+/// Struct describing how the most recent run is undone
+#[derive(Debug)]
+pub struct UndoPlan {
+    /// The epoch time in seconds at which the run started
+    pub run:        i64,
+
+    /// What is done, in the order it is done
+    pub actions:    Vec<UndoAction>
+}
+
+impl UndoPlan {
+    /// When the run started, formatted for display
+    pub fn run_started(&self) -> String {
+        NaiveDateTime::from_timestamp(self.run, 0).format("%Y-%m-%d %H:%M:%S UTC").to_string()
+    }
+}
+
+/// Determine how the most recent run which changed something in Drive is undone. Returns None if no run has changed anything
+///
+/// ## Errors
+/// - When a database operation fails
+/// - When the most recent run was already undone
+pub fn plan(env: &Env) -> Result<Option<UndoPlan>> {
+    let run = match audit::last_run(env)? {
+        Some(run) => run,
+        None => return Ok(None)
+    };
+
+    let plan = UndoPlan { run, actions: build_actions(&audit::operations_of_run(env, run)?) };
+    if is_undone(env, run)? {
+        return Err((Error::Other(format!("The most recent run, started at {}, was already undone", plan.run_started())), line!(), file!()));
+    }
+
+    Ok(Some(plan))
+}
+
+/// Determine the actions undoing a run from its operations, newest operation first
+fn build_actions(operations: &[OperationRecord]) -> Vec<UndoAction> {
+    // Files created by the run are removed, undoing anything else done to them as well
+    let created = operations.iter()
+        .filter(|operation| operation.result == "ok" && operation.operation == "create")
+        .filter_map(|operation| operation.file_id.clone())
+        .collect::<HashSet<_>>();
+
+    let mut rolled_back = HashSet::new();
+    let mut actions = Vec::new();
+    for operation in operations.iter().rev().filter(|operation| operation.result == "ok") {
+        let id = match &operation.file_id {
+            Some(id) => id.clone(),
+            None => continue
+        };
+        let path = operation.path.clone().unwrap_or_else(|| id.clone());
+
+        let action = match operation.operation.as_str() {
+            "create" => UndoAction::Remove { id, path },
+            _ if created.contains(&id) => continue,
+            "update" if rolled_back.insert(id.clone()) => {
+                // Only the revisions the run uploaded are deleted. Older runs didn't record them
+                let revisions = operations.iter()
+                    .filter(|other| other.result == "ok" && other.operation == "update" && other.file_id.as_deref() == Some(id.as_str()))
+                    .map(|other| other.revision_id.clone())
+                    .collect::<Option<Vec<_>>>();
+
+                match revisions {
+                    Some(revisions) => UndoAction::RollBack { id, path, revisions },
+                    None => UndoAction::Irreversible { path, reason: "the revision it uploaded wasn't recorded" }
+                }
+            },
+            "update" => continue,
+            "trash" => UndoAction::Untrash { id, path },
+            "archive" => UndoAction::Unarchive { id, path },
+            "delete" => UndoAction::Irreversible { path, reason: "it was permanently deleted" },
+            "permission" => UndoAction::Irreversible { path, reason: "permission changes are not undone" },
+            "label" => UndoAction::Irreversible { path, reason: "label changes are not undone" },
+            _ => continue
+        };
+
+        actions.push(action);
+    }
+
+    actions
+}
+
+/// Undo a run. Actions which fail are reported and skipped. Returns the number of actions which failed.
+/// The run is only recorded as undone when every action succeeded, so undoing it again retries the actions which failed.
+/// Every action can be repeated, the ones which succeeded before change nothing the second time
+///
+/// ## Errors
+/// - When a database operation fails
+pub fn undo(env: &Env, plan: &UndoPlan) -> Result<usize> {
+    let mut failures = 0;
+    for action in &plan.actions {
+        let result = match action {
+            UndoAction::Remove { id, .. } => remove(env, id),
+            UndoAction::RollBack { id, revisions, .. } => roll_back(env, id, revisions),
+            UndoAction::Untrash { id, .. } => drive::blocking::get_file(env, id).and_then(|file| crate::undelete::undelete(env, &file)),
+            UndoAction::Unarchive { id, .. } => drive::blocking::unmark_stale(env, id)
+                .and_then(|_| drive::blocking::get_file(env, id))
+                .and_then(|file| crate::undelete::retrack(env, &file)),
+            UndoAction::Irreversible { .. } => continue
+        };
+
+        match result {
+            Ok(_) => println!("Info: Done: {}", action),
+            Err((e, _, _)) => {
+                eprintln!("Error: Failed to {}: {}", action, crate::redact::redact(format!("{:?}", e)));
+                failures += 1;
+            }
+        }
+    }
+
+    if failures > 0 {
+        return Ok(failures);
+    }
+
+    let conn = unwrap_db_err!(env.get_conn());
+    unwrap_db_err!(conn.execute("INSERT OR REPLACE INTO undone_runs (run, undone) VALUES (:run, :undone)", named_params! {
+        ":run": plan.run,
+        ":undone": Utc::now().timestamp()
+    }));
+
+    Ok(0)
+}
+
+/// Move a file created by the run to the trash and stop tracking it
+///
+/// ## Errors
+/// - When a Drive API call fails
+/// - When a database operation fails
+fn remove(env: &Env, id: &str) -> Result<()> {
+    drive::blocking::set_trashed(env, id, true)?;
+    if let Some(tracked) = files::get_by_id(env, id)? {
+        files::delete(env, &tracked.path)?;
+    }
+
+    Ok(())
+}
+
+/// Delete the revisions of a file the run uploaded, so its prior revision becomes the current one again.
+/// Revisions made by others after the run are never deleted: if the current revision isn't the last one the run uploaded, the file isn't rolled back
+///
+/// ## Params
+/// - `revisions` The IDs of the revisions the run uploaded, oldest first
+///
+/// ## Errors
+/// - When the file was changed in Drive after the run
+/// - When the file has no revision from before the run
+/// - When a Drive API call fails
+/// - When a database operation fails
+fn roll_back(env: &Env, id: &str, revisions: &[String]) -> Result<()> {
+    let (ours, prior): (Vec<_>, Vec<_>) = drive::blocking::list_revisions(env, id)?.into_iter()
+        .partition(|revision| revisions.contains(&revision.id));
+
+    // Rolled back before, when undoing the run failed for another file
+    if ours.is_empty() {
+        return Ok(());
+    }
+
+    let head = drive::blocking::get_file(env, id)?.head_revision_id;
+    if head.is_none() || head.as_ref() != revisions.last() {
+        return Err((Error::Other("The file was changed in Drive after the run, not rolling it back".to_string()), line!(), file!()));
+    }
+
+    // Revisions are listed oldest first
+    let prior = match prior.into_iter().last() {
+        Some(prior) => prior,
+        None => return Err((Error::Other("The file has no revision from before the run, Drive may have removed it".to_string()), line!(), file!()))
+    };
+
+    for revision in ours.iter().rev() {
+        drive::blocking::delete_revision(env, id, &revision.id)?;
+    }
+
+    // Track the content the file has in Drive again, so the next sync uploads the local file if it differs
+    if let Some(mut tracked) = files::get_by_id(env, id)? {
+        tracked.size = Some(prior.size() as i64);
+        tracked.md5 = prior.md5_checksum.clone();
+        files::insert(env, &tracked)?;
+    }
+
+    Ok(())
+}
+
+/// Check if a run was undone before
+///
+/// ## Errors
+/// - When a database operation fails
+fn is_undone(env: &Env, run: i64) -> Result<bool> {
+    let conn = unwrap_db_err!(env.get_conn());
+    let undone = unwrap_db_err!(conn.query_row("SELECT undone FROM undone_runs WHERE run = :run", named_params! {
+        ":run": run
+    }, |row| row.get::<&str, i64>("undone")).optional());
+
+    Ok(undone.is_some())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::undo::{build_actions, UndoAction};
+    use crate::audit::OperationRecord;
+
+    /// Create a successful operation on a file
+    fn operation(operation: &str, id: &str) -> OperationRecord {
+        OperationRecord {
+            time:       0,
+            run:        Some(0),
+            operation:  operation.to_string(),
+            path:       Some(format!("/{}", id)),
+            file_id:    Some(id.to_string()),
+            revision_id: Some(format!("{}-1", id)),
+            result:     "ok".to_string()
+        }
+    }
+
+    #[test]
+    fn build_actions_reverses_operations() {
+        let actions = build_actions(&[operation("create", "a"), operation("update", "b"), operation("trash", "c"), operation("delete", "d"), operation("archive", "e")]);
+        assert_eq!(vec![
+            UndoAction::Unarchive { id: "e".to_string(), path: "/e".to_string() },
+            UndoAction::Irreversible { path: "/d".to_string(), reason: "it was permanently deleted" },
+            UndoAction::Untrash { id: "c".to_string(), path: "/c".to_string() },
+            UndoAction::RollBack { id: "b".to_string(), path: "/b".to_string(), revisions: vec!["b-1".to_string()] },
+            UndoAction::Remove { id: "a".to_string(), path: "/a".to_string() }
+        ], actions);
+    }
+
+    #[test]
+    fn build_actions_removes_created_files_only() {
+        let actions = build_actions(&[operation("create", "a"), operation("update", "a"), operation("label", "a")]);
+        assert_eq!(vec![UndoAction::Remove { id: "a".to_string(), path: "/a".to_string() }], actions);
+    }
+
+    #[test]
+    fn build_actions_skips_failed_operations() {
+        let mut failed = operation("create", "a");
+        failed.result = "error".to_string();
+        assert!(build_actions(&[failed]).is_empty());
+    }
+}
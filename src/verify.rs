@@ -0,0 +1,166 @@
+//! Module for verifying the synced files against their local counterparts: the MD5 checksum Google Drive reports for every tracked file
+//! is compared with the checksum of the local file, to find files which are corrupted in Drive or out of sync
+
+use crate::env::Env;
+use crate::api::drive;
+use crate::files::{self, TrackedFile};
+use crate::{Result, Error};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+/// Enum describing why a tracked file failed verification
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Problem {
+    /// The file is not in Drive, or is in the trash
+    MissingRemote,
+
+    /// The local file no longer exists
+    MissingLocal,
+
+    /// The local file changed since it was last synced
+    ChangedLocally,
+
+    /// The file in Drive has other content than was synced, it is corrupted or was edited in Drive
+    ChangedRemotely,
+
+    /// The file differs from the copy in Drive, and both differ from what was synced
+    Differs
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::MissingRemote => "is missing from Google Drive",
+            Self::MissingLocal => "no longer exists locally",
+            Self::ChangedLocally => "changed locally since the last sync",
+            Self::ChangedRemotely => "has other content in Google Drive than was synced",
+            Self::Differs => "differs from its copy in Google Drive, and both changed since the last sync"
+        })
+    }
+}
+
+/// Struct describing a tracked file which failed verification
+#[derive(Debug, Clone)]
+pub struct Mismatch {
+    /// The local path of the file
+    pub path:       String,
+
+    /// What is wrong with the file
+    pub problem:    Problem
+}
+
+/// The result of a verification
+#[derive(Debug, Default)]
+pub struct VerifyResult {
+    /// The number of files verified
+    pub checked:    usize,
+
+    /// The number of files which could not be verified, because Drive has no checksum for them, e.g. converted Google documents
+    pub skipped:    usize,
+
+    /// The files which failed verification
+    pub mismatches: Vec<Mismatch>
+}
+
+/// Verify every tracked file within `within`, or all tracked files. The checksums in Drive are listed per folder,
+/// files which are not found in the listing of their folder are requested on their own
+///
+/// ## Errors
+/// - When a Drive API call fails for another reason than the file not existing
+/// - When a database operation fails
+pub fn verify(env: &Env, within: Option<&Path>) -> Result<VerifyResult> {
+    let tracked = files::get_all(env)?.into_iter()
+        .filter(|file| !file.is_folder && file.link_target.is_none())
+        .filter(|file| within.is_none_or(|within| Path::new(&file.path).starts_with(within)))
+        .collect::<Vec<_>>();
+
+    let mut by_parent: HashMap<&str, Vec<&TrackedFile>> = HashMap::new();
+    for file in tracked.iter() {
+        by_parent.entry(file.parent_id.as_str()).or_default().push(file);
+    }
+
+    let mut result = VerifyResult::default();
+    for (parent_id, files) in by_parent {
+        println!("Info: Verifying {} files in folder '{}'", files.len(), parent_id);
+        let listing = drive::blocking::list_files(env, Some(&format!("'{}' in parents and trashed = false", parent_id)), env.drive_id.as_deref())?;
+        let checksums = listing.into_iter().map(|remote| (remote.id, (remote.md5_checksum, crate::metadata::is_packed(&remote.app_properties)))).collect::<HashMap<_, _>>();
+
+        for file in files {
+            let remote = match checksums.get(&file.id) {
+                Some(remote) => Some(remote.clone()),
+                // Not in the folder it was synced into, it may be in another drive or in the trash
+                None => match drive::blocking::get_file(env, &file.id) {
+                    Ok(remote) if !remote.trashed => Some((remote.md5_checksum, crate::metadata::is_packed(&remote.app_properties))),
+                    Ok(_) => None,
+                    Err((Error::GoogleError(e), _, _)) if e.code == 404 => None,
+                    Err(e) => return Err(e)
+                }
+            };
+
+            let (remote, packed) = match remote {
+                Some((Some(md5), packed)) => (Some(md5), packed),
+                Some((None, _)) => {
+                    result.skipped += 1;
+                    continue;
+                },
+                None => (None, false)
+            };
+
+            let local = if Path::new(&file.path).is_file() {
+                Some(crate::sparse::md5_as_uploaded(Path::new(&file.path), packed)?)
+            } else {
+                None
+            };
+
+            if let Some(problem) = classify(file.md5.as_deref(), local.as_deref(), remote.as_deref()) {
+                result.mismatches.push(Mismatch { path: file.path.clone(), problem });
+            }
+
+            result.checked += 1;
+        }
+    }
+
+    result.mismatches.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(result)
+}
+
+/// Decide what is wrong with a file from its checksums, if anything
+///
+/// ## Params
+/// - `recorded` The checksum recorded when the file was last synced, if known
+/// - `local` The checksum of the local file, None if it doesn't exist
+/// - `remote` The checksum Drive reports, None if the file is missing from Drive
+fn classify(recorded: Option<&str>, local: Option<&str>, remote: Option<&str>) -> Option<Problem> {
+    let (local, remote) = match (local, remote) {
+        (_, None) => return Some(Problem::MissingRemote),
+        (None, _) => return Some(Problem::MissingLocal),
+        (Some(local), Some(remote)) => (local, remote)
+    };
+
+    if local.eq_ignore_ascii_case(remote) {
+        return None;
+    }
+
+    match recorded {
+        Some(recorded) if recorded.eq_ignore_ascii_case(remote) => Some(Problem::ChangedLocally),
+        Some(recorded) if recorded.eq_ignore_ascii_case(local) => Some(Problem::ChangedRemotely),
+        _ => Some(Problem::Differs)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::verify::{classify, Problem};
+
+    #[test]
+    fn classify_checksums() {
+        assert_eq!(None, classify(Some("a"), Some("a"), Some("A")));
+        assert_eq!(Some(Problem::ChangedLocally), classify(Some("a"), Some("b"), Some("a")));
+        assert_eq!(Some(Problem::ChangedRemotely), classify(Some("a"), Some("a"), Some("b")));
+        assert_eq!(Some(Problem::Differs), classify(Some("a"), Some("b"), Some("c")));
+        assert_eq!(Some(Problem::Differs), classify(None, Some("b"), Some("c")));
+        assert_eq!(Some(Problem::MissingRemote), classify(Some("a"), Some("a"), None));
+        assert_eq!(Some(Problem::MissingLocal), classify(Some("a"), None, Some("a")));
+    }
+}
@@ -0,0 +1,209 @@
+//! Module for watching the inputs for changes, and syncing changed files as they are saved instead of syncing the whole tree.
+//! Changes are batched until the inputs are quiet for a moment, so a burst of writes to the same files results in a single sync
+
+use crate::config::Configuration;
+use crate::env::Env;
+use crate::report::RunReport;
+use crate::sync::{self, SyncOptions};
+use crate::{Result, unwrap_other_err};
+use notify::{DebouncedEvent, RecursiveMode, Watcher};
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+/// The default time the inputs must be quiet before the changes are synced
+pub const DEFAULT_DEBOUNCE: Duration = Duration::from_secs(2);
+
+/// A change to a path in an input, as far as syncing it is concerned
+#[derive(Debug, PartialEq, Eq)]
+enum Change {
+    /// A file was created or written to, and can be synced on its own
+    File(PathBuf),
+
+    /// Something changed which a sync of single files can't handle, e.g. a removal, a rename or a new folder, so the inputs are synced in full
+    Tree,
+
+    /// Nothing which needs syncing changed, e.g. only a file's permissions
+    None
+}
+
+/// Struct describing the changes collected since the last sync
+#[derive(Debug, Default)]
+struct Batch {
+    /// The files which were created or written to
+    files:  BTreeSet<PathBuf>,
+
+    /// Whether the inputs must be synced in full
+    full:   bool
+}
+
+impl Batch {
+    /// Add a change to the batch
+    fn add(&mut self, change: Change) {
+        match change {
+            Change::File(path) => {
+                self.files.insert(path);
+            },
+            Change::Tree => self.full = true,
+            Change::None => {}
+        }
+    }
+
+    /// Whether there is anything to sync
+    fn is_empty(&self) -> bool {
+        self.files.is_empty() && !self.full
+    }
+}
+
+/// Sync the inputs once, after which they are watched for changes and the changed files are synced as they are saved.
+/// Files which were created or written to are synced on their own, like `gsync sync --files-from`, other changes result in a full sync.
+/// A sync which fails is reported, after which watching continues. This never returns unless watching the inputs fails
+///
+/// ## Params
+/// - `config` The configuration
+/// - `env` Env instance
+/// - `debounce` How long the inputs must be quiet before the collected changes are synced
+///
+/// ## Errors
+/// - When watching an input fails, e.g. because the system's limit of watches was reached
+pub fn watch(config: &Configuration, env: &Env, debounce: Duration) -> Result<()> {
+    let (tx, rx) = channel();
+    // The watcher debounces the events per path, the batch debounces the events across paths
+    let mut watcher = unwrap_other_err!(notify::watcher(tx, debounce));
+    let own = sync::state_paths(env);
+    for input in config.input_files.as_deref().unwrap_or_default().split(',').filter(|input| !input.is_empty()) {
+        // Inputs which can't be found are reported by the sync
+        if let Ok(path) = sync::normalize_path(input) {
+            unwrap_other_err!(watcher.watch(&path, RecursiveMode::Recursive));
+            println!("Info: Watching '{}'", path.to_string_lossy());
+        }
+    }
+
+    // Files changed while GSync wasn't watching are only picked up by a full sync
+    run(config, env, Batch { files: BTreeSet::new(), full: true });
+
+    loop {
+        let batch = collect(&rx, debounce, &own)?;
+        if !batch.is_empty() {
+            run(config, env, batch);
+        }
+    }
+}
+
+/// Wait for a change, and collect changes until the inputs are quiet for the debounce time.
+/// Changes to GSync's own state are dropped, they don't count as changes to the inputs
+///
+/// ## Params
+/// - `own` The paths of GSync's own state, see [sync::state_paths]
+///
+/// ## Errors
+/// - When the watcher stopped
+fn collect(rx: &Receiver<DebouncedEvent>, debounce: Duration, own: &[PathBuf]) -> Result<Batch> {
+    let mut batch = Batch::default();
+    loop {
+        let event = unwrap_other_err!(rx.recv());
+        if !is_own(&event, own) {
+            batch.add(classify(event));
+            break;
+        }
+    }
+
+    let mut quiet_until = Instant::now() + debounce;
+    loop {
+        match rx.recv_timeout(quiet_until.saturating_duration_since(Instant::now())) {
+            Ok(event) if is_own(&event, own) => {},
+            Ok(event) => {
+                batch.add(classify(event));
+                quiet_until = Instant::now() + debounce;
+            },
+            Err(RecvTimeoutError::Timeout) => return Ok(batch),
+            Err(e) => return Err((crate::Error::Other(format!("The watcher stopped: {}", e)), line!(), file!()))
+        }
+    }
+}
+
+/// Sync the changes in a batch, reporting a failed sync instead of stopping
+fn run(config: &Configuration, env: &Env, batch: Batch) {
+    let options = SyncOptions {
+        files_from: if batch.full { None } else { Some(batch.files.into_iter().collect()) },
+        ..SyncOptions::default()
+    };
+
+    if let Err((e, _, _)) = sync::sync_with_report(config, env, &options, &RunReport::new()) {
+        eprintln!("Error: Sync failed: {}", crate::redact::redact(format!("{:?}", e)));
+    }
+}
+
+/// Whether an event only concerns GSync's own state, e.g. the database which is written at the end of every sync.
+/// If an input contains the state directory, syncing such a change would write the database again, and trigger another sync forever
+fn is_own(event: &DebouncedEvent, own: &[PathBuf]) -> bool {
+    let paths = match event {
+        DebouncedEvent::Create(path) | DebouncedEvent::Write(path) | DebouncedEvent::Remove(path) | DebouncedEvent::Chmod(path)
+            | DebouncedEvent::NoticeWrite(path) | DebouncedEvent::NoticeRemove(path) => vec![path],
+        DebouncedEvent::Rename(from, to) => vec![from, to],
+        DebouncedEvent::Rescan | DebouncedEvent::Error(_, _) => return false
+    };
+
+    paths.iter().all(|path| sync::is_within(path, own))
+}
+
+/// Decide what an event means for syncing
+fn classify(event: DebouncedEvent) -> Change {
+    match event {
+        DebouncedEvent::Create(path) | DebouncedEvent::Write(path) if path.is_dir() => Change::Tree,
+        DebouncedEvent::Create(path) | DebouncedEvent::Write(path) => Change::File(path),
+        DebouncedEvent::Remove(_) | DebouncedEvent::Rename(_, _) | DebouncedEvent::Rescan => Change::Tree,
+        // A problem with the watcher may mean events were missed
+        DebouncedEvent::Error(e, path) => {
+            println!("Warning: Watching {} failed: {}", path.map(|path| format!("'{}'", path.to_string_lossy())).unwrap_or_else(|| "the inputs".to_string()), e);
+            Change::Tree
+        },
+        // Notices are followed by the debounced event, a change of permissions doesn't change the content
+        DebouncedEvent::NoticeWrite(_) | DebouncedEvent::NoticeRemove(_) | DebouncedEvent::Chmod(_) => Change::None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::watch::{classify, is_own, Batch, Change};
+    use notify::DebouncedEvent;
+    use std::path::PathBuf;
+
+    #[test]
+    fn classify_events() {
+        let file = PathBuf::from("/nonexistent/gsync/notes.txt");
+        assert_eq!(Change::File(file.clone()), classify(DebouncedEvent::Write(file.clone())));
+        assert_eq!(Change::Tree, classify(DebouncedEvent::Create(std::env::temp_dir())));
+        assert_eq!(Change::Tree, classify(DebouncedEvent::Remove(file.clone())));
+        assert_eq!(Change::None, classify(DebouncedEvent::Chmod(file.clone())));
+
+        let mut batch = Batch::default();
+        assert!(batch.is_empty());
+        batch.add(Change::File(file.clone()));
+        batch.add(Change::File(file));
+        assert_eq!(1, batch.files.len());
+        assert!(!batch.full);
+        batch.add(Change::Tree);
+        assert!(batch.full);
+    }
+
+    #[test]
+    fn own_events() {
+        let own = vec![PathBuf::from("/home/me/.gsync"), PathBuf::from("/var/log/gsync-audit.log")];
+        let db = PathBuf::from("/home/me/.gsync/data.db3");
+        let notes = PathBuf::from("/home/me/notes.txt");
+
+        assert!(is_own(&DebouncedEvent::Write(db.clone()), &own));
+        assert!(is_own(&DebouncedEvent::Create(PathBuf::from("/home/me/.gsync/data.db3-journal")), &own));
+        assert!(is_own(&DebouncedEvent::Write(PathBuf::from("/var/log/gsync-audit.log")), &own));
+        assert!(!is_own(&DebouncedEvent::Write(notes.clone()), &own));
+        assert!(!is_own(&DebouncedEvent::Rename(db, notes), &own));
+        assert!(!is_own(&DebouncedEvent::Rescan, &own));
+
+        // The state directory is the home folder, which is an input
+        let own = vec![PathBuf::from("/home/me/data.db3"), PathBuf::from("/home/me/data.db3-wal")];
+        assert!(is_own(&DebouncedEvent::Write(PathBuf::from("/home/me/data.db3-wal")), &own));
+        assert!(!is_own(&DebouncedEvent::Write(PathBuf::from("/home/me/notes.txt")), &own));
+    }
+}